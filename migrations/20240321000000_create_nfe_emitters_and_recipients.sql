@@ -0,0 +1,74 @@
+CREATE TABLE nfe_emitters (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    CNPJ VARCHAR2(14),
+    CPF VARCHAR2(11),
+    XNOME VARCHAR2(60) NOT NULL,
+    XFANT VARCHAR2(60),
+    XLGR VARCHAR2(60) NOT NULL,
+    NRO VARCHAR2(60) NOT NULL,
+    XCPL VARCHAR2(60),
+    XBAIRRO VARCHAR2(60) NOT NULL,
+    CMUN VARCHAR2(7) NOT NULL,
+    XMUN VARCHAR2(60) NOT NULL,
+    UF VARCHAR2(2) NOT NULL,
+    CEP VARCHAR2(8) NOT NULL,
+    CPAIS VARCHAR2(4),
+    XPAIS VARCHAR2(60),
+    FONE VARCHAR2(14),
+    IE VARCHAR2(14) NOT NULL,
+    IEST VARCHAR2(14),
+    IM VARCHAR2(15),
+    CNAE VARCHAR2(7),
+    CRT VARCHAR2(1) NOT NULL,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_emitters_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE,
+    CONSTRAINT ck_nfe_emitters_doc CHECK (
+        (CNPJ IS NOT NULL AND CPF IS NULL) OR (CNPJ IS NULL AND CPF IS NOT NULL)
+    )
+);
+
+CREATE TABLE nfe_recipients (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    CNPJ VARCHAR2(14),
+    CPF VARCHAR2(11),
+    IDESTRANGEIRO VARCHAR2(20),
+    XNOME VARCHAR2(60),
+    XLGR VARCHAR2(60),
+    NRO VARCHAR2(60),
+    XCPL VARCHAR2(60),
+    XBAIRRO VARCHAR2(60),
+    CMUN VARCHAR2(7),
+    XMUN VARCHAR2(60),
+    UF VARCHAR2(2),
+    CEP VARCHAR2(8),
+    CPAIS VARCHAR2(4),
+    XPAIS VARCHAR2(60),
+    FONE VARCHAR2(14),
+    INDIEDEST VARCHAR2(1) NOT NULL,
+    IE VARCHAR2(14),
+    ISUF VARCHAR2(9),
+    IM VARCHAR2(15),
+    EMAIL VARCHAR2(60),
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_recipients_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE OR REPLACE TRIGGER nfe_emitters_bur
+BEFORE UPDATE ON nfe_emitters
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
+
+CREATE OR REPLACE TRIGGER nfe_recipients_bur
+BEFORE UPDATE ON nfe_recipients
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
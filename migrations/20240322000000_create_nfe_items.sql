@@ -0,0 +1,40 @@
+CREATE TABLE nfe_items (
+    INTERNALKEY RAW(16) NOT NULL,
+    NITEM NUMBER(3) NOT NULL,
+    CPROD VARCHAR2(60) NOT NULL,
+    CEAN VARCHAR2(14),
+    XPROD VARCHAR2(120) NOT NULL,
+    NCM VARCHAR2(8) NOT NULL,
+    CEST VARCHAR2(7),
+    CFOP VARCHAR2(4) NOT NULL,
+    UCOM VARCHAR2(6) NOT NULL,
+    QCOM NUMBER(15,4) NOT NULL,
+    VUNCOM NUMBER(21,10) NOT NULL,
+    VPROD NUMBER(15,2) NOT NULL,
+    CEANTRIB VARCHAR2(14),
+    UTRIB VARCHAR2(6) NOT NULL,
+    QTRIB NUMBER(15,4) NOT NULL,
+    VUNTRIB NUMBER(21,10) NOT NULL,
+    VFRETE NUMBER(15,2),
+    VSEG NUMBER(15,2),
+    VDESC NUMBER(15,2),
+    VOUTRO NUMBER(15,2),
+    INDTOT VARCHAR2(1) NOT NULL,
+    -- imposto block serialized as JSON; the subgroup is derived from CST/CSOSN
+    IMPOSTO CLOB,
+    INFADPROD VARCHAR2(500),
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT pk_nfe_items PRIMARY KEY (INTERNALKEY, NITEM),
+    CONSTRAINT fk_nfe_items_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE,
+    CONSTRAINT ck_nfe_items_nitem CHECK (NITEM BETWEEN 1 AND 990)
+);
+
+CREATE OR REPLACE TRIGGER nfe_items_bur
+BEFORE UPDATE ON nfe_items
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
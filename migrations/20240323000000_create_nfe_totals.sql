@@ -0,0 +1,27 @@
+-- ICMSTot and ISSQNtot are derived from nfe_items on read; only the values that cannot
+-- be derived from the items are stored here.
+CREATE TABLE nfe_totals (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    DCOMPET DATE,
+    CREGTRIB VARCHAR2(1),
+    VRETPIS NUMBER(15,2),
+    VRETCOFINS NUMBER(15,2),
+    VRETCSLL NUMBER(15,2),
+    VBCIRRF NUMBER(15,2),
+    VIRRF NUMBER(15,2),
+    VBCRETPREV NUMBER(15,2),
+    VRETPREV NUMBER(15,2),
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_totals_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE,
+    CONSTRAINT ck_nfe_totals_cregtrib CHECK (CREGTRIB IN ('1', '2', '3', '4', '5', '6'))
+);
+
+CREATE OR REPLACE TRIGGER nfe_totals_bur
+BEFORE UPDATE ON nfe_totals
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
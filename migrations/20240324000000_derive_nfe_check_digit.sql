@@ -0,0 +1,5 @@
+-- cDV is derived from the access key and stays NULL until the emitter is known.
+ALTER TABLE nfe_identifications MODIFY (CDV NULL);
+
+-- Contingency justification (xJust), already read by the identification queries.
+ALTER TABLE nfe_identifications ADD (X_JUSTIFICATIVA VARCHAR2(256));
@@ -0,0 +1,24 @@
+-- Every answer of SEFAZ to a transmission (protNFe), newest last. Rows with CSTAT 105
+-- keep the receipt (NREC) of a batch that was still being processed.
+CREATE TABLE nfe_protocols (
+    ID RAW(16) PRIMARY KEY,
+    INTERNALKEY RAW(16) NOT NULL,
+    TPAMB VARCHAR2(1) NOT NULL,
+    VERAPLIC VARCHAR2(20),
+    CHNFE VARCHAR2(44) NOT NULL,
+    DHRECBTO TIMESTAMP WITH TIME ZONE,
+    NPROT VARCHAR2(15),
+    DIGVAL VARCHAR2(28),
+    CSTAT VARCHAR2(3) NOT NULL,
+    XMOTIVO VARCHAR2(255) NOT NULL,
+    NREC VARCHAR2(15),
+    -- Signed NFe as sent and protNFe as received, joined in nfeProc.
+    NFEXML CLOB,
+    PROTXML CLOB,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_protocols_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE INDEX ix_nfe_protocols_ide ON nfe_protocols (INTERNALKEY, CREATEDAT);
+CREATE INDEX ix_nfe_protocols_chnfe ON nfe_protocols (CHNFE);
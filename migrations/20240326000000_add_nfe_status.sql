@@ -0,0 +1,34 @@
+-- Lifecycle of each note. SIGNEDXML keeps the signed NFe between signing and
+-- transmission and is cleared when the note goes back to draft.
+ALTER TABLE nfe_identifications ADD (
+    STATUS VARCHAR2(12) DEFAULT 'draft' NOT NULL,
+    SIGNEDXML CLOB,
+    CONSTRAINT ck_nfe_identifications_status CHECK (STATUS IN (
+        'draft', 'validated', 'signed', 'transmitted',
+        'authorized', 'rejected', 'denied', 'cancelled'
+    ))
+);
+
+CREATE INDEX ix_nfe_identifications_status ON nfe_identifications (STATUS);
+
+UPDATE nfe_identifications i
+SET STATUS = 'authorized'
+WHERE EXISTS (
+    SELECT 1 FROM nfe_protocols p
+    WHERE p.INTERNALKEY = i.INTERNALKEY AND p.CSTAT IN ('100', '150')
+);
+
+-- Every transition, with the SEFAZ answer that caused it.
+CREATE TABLE nfe_status_history (
+    ID RAW(16) PRIMARY KEY,
+    INTERNALKEY RAW(16) NOT NULL,
+    FROMSTATUS VARCHAR2(12),
+    TOSTATUS VARCHAR2(12) NOT NULL,
+    CSTAT VARCHAR2(3),
+    XMOTIVO VARCHAR2(255),
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_status_history_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE INDEX ix_nfe_status_history_ide ON nfe_status_history (INTERNALKEY, CREATEDAT);
@@ -0,0 +1,23 @@
+-- Events sent for each note (cancellation, correction letter), registered or not.
+-- EVENTXML holds the procEventoNFe of registered events and the signed evento of
+-- rejected ones.
+CREATE TABLE nfe_events (
+    ID RAW(16) PRIMARY KEY,
+    INTERNALKEY RAW(16) NOT NULL,
+    TPEVENTO VARCHAR2(6) NOT NULL,
+    NSEQEVENTO NUMBER(2) NOT NULL,
+    CHNFE VARCHAR2(44) NOT NULL,
+    DHEVENTO TIMESTAMP WITH TIME ZONE NOT NULL,
+    -- xJust of a cancellation, xCorrecao of a correction letter.
+    DETAIL VARCHAR2(1000),
+    CSTAT VARCHAR2(3) NOT NULL,
+    XMOTIVO VARCHAR2(255) NOT NULL,
+    NPROT VARCHAR2(15),
+    DHREGEVENTO TIMESTAMP WITH TIME ZONE,
+    EVENTXML CLOB,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_events_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE INDEX ix_nfe_events_ide ON nfe_events (INTERNALKEY, CREATEDAT);
@@ -0,0 +1,29 @@
+-- Ranges of skipped numbers sent to SEFAZ for inutilização, homologated or not.
+-- INUTXML holds the ProcInutNFe of homologated ranges and the signed inutNFe of
+-- rejected ones.
+CREATE TABLE nfe_inutilizations (
+    ID RAW(16) PRIMARY KEY,
+    CUF VARCHAR2(2) NOT NULL,
+    TPAMB VARCHAR2(1) NOT NULL,
+    ANO VARCHAR2(2) NOT NULL,
+    CNPJ VARCHAR2(14),
+    CPF VARCHAR2(11),
+    MOD_ VARCHAR2(2) NOT NULL,
+    SERIE VARCHAR2(3) NOT NULL,
+    NNFINI NUMBER(9) NOT NULL,
+    NNFFIN NUMBER(9) NOT NULL,
+    XJUST VARCHAR2(255) NOT NULL,
+    CSTAT VARCHAR2(3) NOT NULL,
+    XMOTIVO VARCHAR2(255) NOT NULL,
+    NPROT VARCHAR2(15),
+    DHRECBTO TIMESTAMP WITH TIME ZONE,
+    INUTXML CLOB,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT ck_nfe_inutilizations_doc CHECK (
+        (CNPJ IS NOT NULL AND CPF IS NULL) OR (CNPJ IS NULL AND CPF IS NOT NULL)
+    ),
+    CONSTRAINT ck_nfe_inutilizations_range CHECK (NNFINI <= NNFFIN)
+);
+
+CREATE INDEX ix_nfe_inutilizations_serie
+    ON nfe_inutilizations (MOD_, SERIE, TPAMB, NNFINI, NNFFIN);
@@ -0,0 +1,42 @@
+-- Document of the emitter, copied from nfe_emitters (or given when the number is
+-- allocated before the emitter exists) so the numbering can be enforced by one index.
+ALTER TABLE nfe_identifications ADD (EMITDOC VARCHAR2(14));
+
+UPDATE nfe_identifications i
+SET EMITDOC = (
+    SELECT NVL(e.CNPJ, e.CPF) FROM nfe_emitters e WHERE e.INTERNALKEY = i.INTERNALKEY
+);
+
+-- One note per number in each serie of an emitter and environment. Duplicates already
+-- stored must be renumbered or deleted before this runs. Notes stored without an
+-- emitter document before this migration are left out of the index; the check below
+-- keeps new notes from skipping it.
+CREATE UNIQUE INDEX ux_nfe_identifications_nnf ON nfe_identifications (
+    CASE WHEN EMITDOC IS NOT NULL THEN EMITDOC END,
+    CASE WHEN EMITDOC IS NOT NULL THEN MOD_ END,
+    CASE WHEN EMITDOC IS NOT NULL THEN TO_NUMBER(SERIE) END,
+    CASE WHEN EMITDOC IS NOT NULL THEN TPAMB END,
+    CASE WHEN EMITDOC IS NOT NULL THEN TO_NUMBER(NNF) END
+);
+
+ALTER TABLE nfe_identifications ADD CONSTRAINT ck_nfe_identifications_emitdoc
+    CHECK (EMITDOC IS NOT NULL) ENABLE NOVALIDATE;
+
+-- Last number handed out in each serie. Numbers are allocated with one UPDATE in the
+-- transaction that inserts the note; its row lock is held until that transaction
+-- commits or rolls back, which serializes concurrent allocations.
+CREATE TABLE nfe_numbering (
+    EMITDOC VARCHAR2(14) NOT NULL,
+    MOD_ VARCHAR2(2) NOT NULL,
+    SERIE NUMBER(3) NOT NULL,
+    TPAMB VARCHAR2(1) NOT NULL,
+    LASTNNF NUMBER(9) DEFAULT 0 NOT NULL,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT pk_nfe_numbering PRIMARY KEY (EMITDOC, MOD_, SERIE, TPAMB)
+);
+
+INSERT INTO nfe_numbering (EMITDOC, MOD_, SERIE, TPAMB, LASTNNF)
+SELECT EMITDOC, MOD_, TO_NUMBER(SERIE), TPAMB, MAX(TO_NUMBER(NNF))
+FROM nfe_identifications
+WHERE EMITDOC IS NOT NULL
+GROUP BY EMITDOC, MOD_, TO_NUMBER(SERIE), TPAMB;
@@ -0,0 +1,45 @@
+-- Notes signed in offline contingency wait in the queue with status 'queued'.
+ALTER TABLE nfe_identifications DROP CONSTRAINT ck_nfe_identifications_status;
+ALTER TABLE nfe_identifications ADD CONSTRAINT ck_nfe_identifications_status CHECK (STATUS IN (
+    'draft', 'validated', 'signed', 'queued', 'transmitted',
+    'authorized', 'rejected', 'denied', 'cancelled'
+));
+
+-- Periods in which the notes of a state and model are issued in contingency. A period
+-- is active while DHEND is empty, and only one can be active per state and model.
+CREATE TABLE nfe_contingencies (
+    ID RAW(16) PRIMARY KEY,
+    CUF VARCHAR2(2) NOT NULL,
+    MOD_ VARCHAR2(2) NOT NULL,
+    TPEMIS VARCHAR2(1) NOT NULL,
+    DHCONT TIMESTAMP WITH TIME ZONE NOT NULL,
+    XJUST VARCHAR2(256) NOT NULL,
+    AUTOMATIC NUMBER(1) DEFAULT 0 NOT NULL,
+    DHEND TIMESTAMP WITH TIME ZONE,
+    CONSTRAINT ck_nfe_contingencies_automatic CHECK (AUTOMATIC IN (0, 1))
+);
+
+CREATE UNIQUE INDEX ux_nfe_contingencies_active ON nfe_contingencies (
+    CASE WHEN DHEND IS NULL THEN CUF END,
+    CASE WHEN DHEND IS NULL THEN MOD_ END
+);
+
+-- Notes issued in offline contingency, transmitted in QUEUEDAT order once the
+-- contingency of their state and model ends. Each attempt updates the entry; SENTAT
+-- is set when SEFAZ received the note.
+CREATE TABLE nfe_contingency_queue (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    CUF VARCHAR2(2) NOT NULL,
+    MOD_ VARCHAR2(2) NOT NULL,
+    TPEMIS VARCHAR2(1) NOT NULL,
+    QUEUEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    ATTEMPTS NUMBER(5) DEFAULT 0 NOT NULL,
+    LASTERROR VARCHAR2(1000),
+    SENTAT TIMESTAMP WITH TIME ZONE,
+    CSTAT VARCHAR2(3),
+    XMOTIVO VARCHAR2(255),
+    CONSTRAINT fk_nfe_contingency_queue_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE INDEX ix_nfe_contingency_queue_pending ON nfe_contingency_queue (SENTAT, QUEUEDAT);
@@ -0,0 +1,35 @@
+-- Position of each CNPJ in NFeDistribuicaoDFe, so a restart resumes after the last NSU
+-- read. NEXTQUERYAT holds the hour SEFAZ asks to wait once nothing new is left.
+CREATE TABLE nfe_distribution_nsu (
+    CNPJ VARCHAR2(14) PRIMARY KEY,
+    ULTNSU NUMBER(15) DEFAULT 0 NOT NULL,
+    MAXNSU NUMBER(15) DEFAULT 0 NOT NULL,
+    NEXTQUERYAT TIMESTAMP WITH TIME ZONE,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- Documents distributed to a CNPJ, one per NSU. Summaries (resNFe) keep their fields;
+-- full notes (procNFe) go through the XML import and keep its outcome.
+CREATE TABLE nfe_distribution_documents (
+    CNPJ VARCHAR2(14) NOT NULL,
+    NSU NUMBER(15) NOT NULL,
+    DOCSCHEMA VARCHAR2(60) NOT NULL,
+    CHNFE VARCHAR2(44),
+    EMITDOC VARCHAR2(14),
+    XNOME VARCHAR2(60),
+    DHEMI TIMESTAMP WITH TIME ZONE,
+    VNF NUMBER(15, 2),
+    CSITNFE VARCHAR2(1),
+    NPROT VARCHAR2(15),
+    INTERNALKEY RAW(16),
+    IMPORTSTATUS VARCHAR2(10),
+    REASON VARCHAR2(1000),
+    XML CLOB NOT NULL,
+    RECEIVEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    CONSTRAINT pk_nfe_distribution_documents PRIMARY KEY (CNPJ, NSU),
+    CONSTRAINT ck_nfe_distribution_import CHECK (
+        IMPORTSTATUS IN ('inserted', 'skipped', 'failed')
+    )
+);
+
+CREATE INDEX ix_nfe_distribution_documents_key ON nfe_distribution_documents (CHNFE);
@@ -0,0 +1,24 @@
+-- transp group of each note. Notes without a row are issued with modFrete 9 (no
+-- transport). The carrier, retTransp, vehicles and volumes with their seals are kept
+-- as JSON in TRANSP; MODFRETE and the carrier document are copied out for queries.
+CREATE TABLE nfe_transports (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    MODFRETE VARCHAR2(1) NOT NULL,
+    CARRIERDOC VARCHAR2(14),
+    TRANSP CLOB NOT NULL,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_transports_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE,
+    CONSTRAINT ck_nfe_transports_modfrete CHECK (MODFRETE IN ('0', '1', '2', '3', '4', '9'))
+);
+
+CREATE INDEX ix_nfe_transports_carrier ON nfe_transports (CARRIERDOC);
+
+CREATE OR REPLACE TRIGGER nfe_transports_bur
+BEFORE UPDATE ON nfe_transports
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
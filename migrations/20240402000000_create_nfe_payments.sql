@@ -0,0 +1,21 @@
+-- cobr and pag groups of each note, kept as JSON. Notes without PAG are issued with
+-- tPag 90 (no payment); VPAG and VTROCO are copied out for queries.
+CREATE TABLE nfe_payments (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    COBR CLOB,
+    PAG CLOB,
+    VPAG NUMBER(15,2),
+    VTROCO NUMBER(15,2),
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_payments_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE OR REPLACE TRIGGER nfe_payments_bur
+BEFORE UPDATE ON nfe_payments
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
@@ -0,0 +1,18 @@
+-- NFref groups of each note, in the order they are written. REFKEY copies the access
+-- key of refNFe, refNFeSig and refCTe so notes can be found by the document they
+-- reference; the whole group is kept as JSON in REFERENCE.
+CREATE TABLE nfe_references (
+    INTERNALKEY RAW(16) NOT NULL,
+    NREF NUMBER(3) NOT NULL,
+    KIND VARCHAR2(9) NOT NULL,
+    REFKEY VARCHAR2(44),
+    REFERENCE CLOB NOT NULL,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT pk_nfe_references PRIMARY KEY (INTERNALKEY, NREF),
+    CONSTRAINT fk_nfe_references_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE,
+    CONSTRAINT ck_nfe_references_kind
+        CHECK (KIND IN ('refNFe', 'refNFeSig', 'refNF', 'refNFP', 'refCTe', 'refECF'))
+);
+
+CREATE INDEX ix_nfe_references_refkey ON nfe_references (REFKEY);
@@ -0,0 +1,20 @@
+-- infAdic, infRespTec and autXML of each note, each kept as JSON. idCSRT and hashCSRT
+-- are not stored: they are derived from the configured CSRT when the note is loaded.
+CREATE TABLE nfe_additional_info (
+    INTERNALKEY RAW(16) PRIMARY KEY,
+    INFADIC CLOB,
+    INFRESPTEC CLOB,
+    AUTXML CLOB,
+    CREATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    UPDATEDAT TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_nfe_additional_info_ide FOREIGN KEY (INTERNALKEY)
+        REFERENCES nfe_identifications (INTERNALKEY) ON DELETE CASCADE
+);
+
+CREATE OR REPLACE TRIGGER nfe_additional_info_bur
+BEFORE UPDATE ON nfe_additional_info
+FOR EACH ROW
+BEGIN
+    :NEW.UPDATEDAT := CURRENT_TIMESTAMP;
+END;
+/
@@ -0,0 +1,3 @@
+-- rastro and the specific product group of prod (veicProd, med, arma, comb or nRECOPI),
+-- kept as JSON; NULL for ordinary products.
+ALTER TABLE nfe_items ADD (PRODSPECIFIC CLOB);
@@ -0,0 +1,7 @@
+-- One registered event per sequence of each event type of a note. Rejected events keep
+-- their sequence number and stay out of the index, so a sequence can be retried.
+CREATE UNIQUE INDEX ux_nfe_events_registered ON nfe_events (
+    CASE WHEN CSTAT IN ('135', '136', '155') THEN INTERNALKEY END,
+    CASE WHEN CSTAT IN ('135', '136', '155') THEN TPEVENTO END,
+    CASE WHEN CSTAT IN ('135', '136', '155') THEN NSEQEVENTO END
+);
@@ -0,0 +1,8 @@
+-- impostoDevol of the items of a return (finNFe 4): the returned share of the goods and
+-- the IPI returned with them, summed into ICMSTot.vIPIDevol.
+ALTER TABLE nfe_items ADD (
+    PDEVOL NUMBER(5,2),
+    VIPIDEVOL NUMBER(15,2),
+    CONSTRAINT ck_nfe_items_impostodevol
+        CHECK ((PDEVOL IS NULL AND VIPIDEVOL IS NULL) OR (PDEVOL IS NOT NULL AND VIPIDEVOL IS NOT NULL))
+);
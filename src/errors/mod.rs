@@ -1,3 +1,11 @@
 pub mod repository_error;
+pub mod schema_error;
+pub mod sefaz_error;
+pub mod signature_error;
+pub mod validation_error;
 
 pub use repository_error::RepositoryError;
+pub use schema_error::SchemaError;
+pub use sefaz_error::SefazError;
+pub use signature_error::SignatureError;
+pub use validation_error::ValidationError;
@@ -1,14 +1,19 @@
+use crate::errors::{SefazError, SignatureError, ValidationError};
 use oracle;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum RepositoryError {
     OracleError(oracle::Error),
-    #[allow(dead_code)]
     NotFound,
     CreationFailed,
     UpdateFailed,
     InvalidUuid(String),
+    InvalidData(String),
+    Conflict(String),
+    Validation(ValidationError),
+    Signature(SignatureError),
+    Sefaz(SefazError),
 }
 
 impl fmt::Display for RepositoryError {
@@ -19,6 +24,11 @@ impl fmt::Display for RepositoryError {
             RepositoryError::CreationFailed => write!(f, "Failed to create record"),
             RepositoryError::UpdateFailed => write!(f, "Failed to update record"),
             RepositoryError::InvalidUuid(msg) => write!(f, "Invalid UUID: {}", msg),
+            RepositoryError::InvalidData(msg) => write!(f, "Invalid stored data: {}", msg),
+            RepositoryError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            RepositoryError::Validation(e) => write!(f, "Validation failed: {}", e),
+            RepositoryError::Signature(e) => write!(f, "Signature failed: {}", e),
+            RepositoryError::Sefaz(e) => write!(f, "Transmission failed: {}", e),
         }
     }
 }
@@ -30,3 +40,34 @@ impl From<oracle::Error> for RepositoryError {
         RepositoryError::OracleError(err)
     }
 }
+
+impl From<ValidationError> for RepositoryError {
+    fn from(err: ValidationError) -> Self {
+        RepositoryError::Validation(err)
+    }
+}
+
+/// Documents that do not verify are a client error; certificate and OpenSSL failures
+/// are not.
+impl From<SignatureError> for RepositoryError {
+    fn from(err: SignatureError) -> Self {
+        match err {
+            SignatureError::Invalid(msg) => {
+                RepositoryError::Validation(ValidationError::new("Signature", msg))
+            }
+            other => RepositoryError::Signature(other),
+        }
+    }
+}
+
+/// A state without a configured endpoint is reported against `cUF`.
+impl From<SefazError> for RepositoryError {
+    fn from(err: SefazError) -> Self {
+        match err {
+            SefazError::NotConfigured(msg) => {
+                RepositoryError::Validation(ValidationError::new("cUF", msg))
+            }
+            other => RepositoryError::Sefaz(other),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use std::fmt;
+
+/// A bundled XSD that could not be read or compiled.
+#[derive(Debug, Clone)]
+pub struct SchemaError {
+    pub file: String,
+    pub message: String,
+}
+
+impl SchemaError {
+    pub fn new(file: &str, message: impl Into<String>) -> Self {
+        Self {
+            file: file.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file, self.message)
+    }
+}
+
+impl std::error::Error for SchemaError {}
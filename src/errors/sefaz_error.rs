@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SefazError {
+    /// No endpoint is configured for the state, service or environment.
+    NotConfigured(String),
+    /// The connection to SEFAZ could not be opened, so the request was not sent.
+    Unreachable(String),
+    /// The connection failed after the request was sent; SEFAZ may have received it.
+    Transport(String),
+    /// SEFAZ answered with a SOAP fault or an HTTP error status.
+    Fault(String),
+    /// The response could not be read as the expected layout.
+    InvalidResponse(String),
+    /// The batch itself was rejected, before any note was processed.
+    Rejected { c_stat: String, x_motivo: String },
+}
+
+impl fmt::Display for SefazError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SefazError::NotConfigured(msg) => write!(f, "SEFAZ not configured: {}", msg),
+            SefazError::Unreachable(msg) => write!(f, "SEFAZ unreachable: {}", msg),
+            SefazError::Transport(msg) => write!(f, "SEFAZ connection failed: {}", msg),
+            SefazError::Fault(msg) => write!(f, "SEFAZ fault: {}", msg),
+            SefazError::InvalidResponse(msg) => write!(f, "Invalid SEFAZ response: {}", msg),
+            SefazError::Rejected { c_stat, x_motivo } => {
+                write!(f, "SEFAZ rejected the request: {} - {}", c_stat, x_motivo)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SefazError {}
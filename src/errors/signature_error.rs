@@ -0,0 +1,29 @@
+use openssl::error::ErrorStack;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SignatureError {
+    /// The certificate could not be loaded or cannot be used to sign.
+    Certificate(String),
+    Crypto(ErrorStack),
+    /// The document or its signature does not verify.
+    Invalid(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Certificate(msg) => write!(f, "Certificate error: {}", msg),
+            SignatureError::Crypto(e) => write!(f, "Cryptographic error: {}", e),
+            SignatureError::Invalid(msg) => write!(f, "Invalid signature: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl From<ErrorStack> for SignatureError {
+    fn from(err: ErrorStack) -> Self {
+        SignatureError::Crypto(err)
+    }
+}
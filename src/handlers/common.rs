@@ -1,3 +1,5 @@
+use crate::errors::RepositoryError;
+use actix_web::HttpResponse;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -21,3 +23,36 @@ pub struct PaginationResponse<T> {
     pub page_size: u32,
     pub total_pages: u32,
 }
+
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorResponse {
+    pub error: String,
+    pub field: String,
+}
+
+/// Maps a repository error to the matching HTTP status, falling back to 500 with `message`.
+pub fn repository_error_response(e: &RepositoryError, message: &str) -> HttpResponse {
+    match e {
+        RepositoryError::NotFound => HttpResponse::NotFound().json(ErrorResponse {
+            error: e.to_string(),
+        }),
+        RepositoryError::InvalidUuid(_) => HttpResponse::BadRequest().json(ErrorResponse {
+            error: e.to_string(),
+        }),
+        RepositoryError::Conflict(msg) => {
+            HttpResponse::Conflict().json(ErrorResponse { error: msg.clone() })
+        }
+        RepositoryError::Validation(v) => {
+            HttpResponse::UnprocessableEntity().json(ValidationErrorResponse {
+                error: v.message.clone(),
+                field: v.field.clone(),
+            })
+        }
+        RepositoryError::Sefaz(e) => HttpResponse::BadGateway().json(ErrorResponse {
+            error: e.to_string(),
+        }),
+        _ => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    }
+}
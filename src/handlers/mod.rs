@@ -1,2 +1,20 @@
 pub mod common;
+pub mod nfe_access_key_handler;
+pub mod nfe_additional_handler;
+pub mod nfe_authorization_handler;
+pub mod nfe_contingency_handler;
+pub mod nfe_danfe_handler;
+pub mod nfe_distribution_handler;
+pub mod nfe_event_handler;
 pub mod nfe_identification_handler;
+pub mod nfe_import_handler;
+pub mod nfe_inutilization_handler;
+pub mod nfe_item_handler;
+pub mod nfe_numbering_handler;
+pub mod nfe_participant_handler;
+pub mod nfe_payment_handler;
+pub mod nfe_reference_handler;
+pub mod nfe_total_handler;
+pub mod nfe_transport_handler;
+pub mod nfe_validation_handler;
+pub mod nfe_xml_handler;
@@ -0,0 +1,36 @@
+use crate::handlers::common::{repository_error_response, ValidationErrorResponse};
+use crate::models::nfe_access_key::AccessKey;
+use crate::repositories::nfe_access_key_repository::NFeAccessKeyRepository;
+use actix_web::{get, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_access_key).service(parse_access_key);
+}
+
+#[get("/identifications/{id}/access-key")]
+pub async fn get_access_key(
+    repo: web::Data<Arc<NFeAccessKeyRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find(&id).await {
+        Ok(key) => HttpResponse::Ok().json(key),
+        Err(e) => {
+            error!("Failed to build access key: {}", e);
+            repository_error_response(&e, "Failed to build access key")
+        }
+    }
+}
+
+/// Validates a key and breaks it back into its parts.
+#[get("/access-keys/{key}")]
+pub async fn parse_access_key(key: web::Path<String>) -> impl Responder {
+    match AccessKey::parse(&key) {
+        Ok(key) => HttpResponse::Ok().json(key),
+        Err(e) => HttpResponse::UnprocessableEntity().json(ValidationErrorResponse {
+            error: e.message,
+            field: e.field,
+        }),
+    }
+}
@@ -0,0 +1,108 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_additional::{NFeAdditionalInfo, NFeAuthorizedDownload, NFeTechResponsible};
+use crate::repositories::nfe_additional_repository::NFeAdditionalRepository;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_additional_info)
+        .service(update_additional_info)
+        .service(get_tech_responsible)
+        .service(update_tech_responsible)
+        .service(get_authorized_downloads)
+        .service(replace_authorized_downloads);
+}
+
+#[get("/identifications/{id}/additional-info")]
+pub async fn get_additional_info(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_additional_info(&id).await {
+        Ok(inf_adic) => HttpResponse::Ok().json(inf_adic),
+        Err(e) => {
+            error!("Failed to get additional information: {}", e);
+            repository_error_response(&e, "Failed to get additional information")
+        }
+    }
+}
+
+#[put("/identifications/{id}/additional-info")]
+pub async fn update_additional_info(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+    inf_adic: web::Json<NFeAdditionalInfo>,
+) -> impl Responder {
+    match repo.update_additional_info(&id, &inf_adic).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update additional information: {}", e);
+            repository_error_response(&e, "Failed to update additional information")
+        }
+    }
+}
+
+/// The `infRespTec` group, or null when none was informed.
+#[get("/identifications/{id}/technical-responsible")]
+pub async fn get_tech_responsible(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_tech_responsible(&id).await {
+        Ok(responsible) => HttpResponse::Ok().json(responsible),
+        Err(e) => {
+            error!("Failed to get technical responsible: {}", e);
+            repository_error_response(&e, "Failed to get technical responsible")
+        }
+    }
+}
+
+/// Replaces the `infRespTec` group; null removes it.
+#[put("/identifications/{id}/technical-responsible")]
+pub async fn update_tech_responsible(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+    responsible: web::Json<Option<NFeTechResponsible>>,
+) -> impl Responder {
+    match repo
+        .update_tech_responsible(&id, responsible.as_ref())
+        .await
+    {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update technical responsible: {}", e);
+            repository_error_response(&e, "Failed to update technical responsible")
+        }
+    }
+}
+
+#[get("/identifications/{id}/authorized-downloads")]
+pub async fn get_authorized_downloads(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_authorized_downloads(&id).await {
+        Ok(authorized) => HttpResponse::Ok().json(authorized),
+        Err(e) => {
+            error!("Failed to get autXML: {}", e);
+            repository_error_response(&e, "Failed to get autXML")
+        }
+    }
+}
+
+/// Replaces the `autXML` list of the note; an empty list removes every person.
+#[put("/identifications/{id}/authorized-downloads")]
+pub async fn replace_authorized_downloads(
+    repo: web::Data<Arc<NFeAdditionalRepository>>,
+    id: web::Path<String>,
+    authorized: web::Json<Vec<NFeAuthorizedDownload>>,
+) -> impl Responder {
+    match repo.replace_authorized_downloads(&id, &authorized).await {
+        Ok(stored) => HttpResponse::Ok().json(stored),
+        Err(e) => {
+            error!("Failed to replace autXML: {}", e);
+            repository_error_response(&e, "Failed to replace autXML")
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::handlers::common::repository_error_response;
+use crate::repositories::nfe_authorization_repository::NFeAuthorizationRepository;
+use crate::repositories::nfe_contingency_repository::{NFeContingencyRepository, Transmission};
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(validate)
+        .service(sign)
+        .service(transmit)
+        .service(get_protocol)
+        .service(get_protocols)
+        .service(get_status_history);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransmitQuery {
+    /// Asks for a synchronous answer (`indSinc` = 1).
+    #[serde(default = "default_sync")]
+    pub sync: bool,
+}
+
+fn default_sync() -> bool {
+    true
+}
+
+/// Moves the note to validated when its XML matches the layout schema. Answers 422 with
+/// the report otherwise.
+#[post("/identifications/{id}/validate")]
+pub async fn validate(
+    repo: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.validate(&id).await {
+        Ok(report) if report.valid => HttpResponse::Ok().json(report),
+        Ok(report) => HttpResponse::UnprocessableEntity().json(report),
+        Err(e) => {
+            error!("Failed to validate NFe: {}", e);
+            repository_error_response(&e, "Failed to validate NFe")
+        }
+    }
+}
+
+#[post("/identifications/{id}/sign")]
+pub async fn sign(
+    repo: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.sign(&id).await {
+        Ok(xml) => HttpResponse::Ok()
+            .content_type("application/xml; charset=utf-8")
+            .body(xml),
+        Err(e) => {
+            error!("Failed to sign NFe: {}", e);
+            repository_error_response(&e, "Failed to sign NFe")
+        }
+    }
+}
+
+/// Answers 200 with the protocol, including rejections, or 202 while SEFAZ is still
+/// processing the batch; sending again then queries the receipt. Notes issued in
+/// offline contingency answer 202 with their entry in the queue.
+#[post("/identifications/{id}/transmit")]
+pub async fn transmit(
+    repo: web::Data<Arc<NFeContingencyRepository>>,
+    id: web::Path<String>,
+    query: web::Query<TransmitQuery>,
+) -> impl Responder {
+    match repo.transmit(&id, query.sync).await {
+        Ok(Transmission::Sent(protocol)) if protocol.is_pending() => {
+            HttpResponse::Accepted().json(protocol)
+        }
+        Ok(Transmission::Sent(protocol)) => HttpResponse::Ok().json(protocol),
+        Ok(Transmission::Queued(entry)) => HttpResponse::Accepted().json(entry),
+        Err(e) => {
+            error!("Failed to transmit NFe: {}", e);
+            repository_error_response(&e, "Failed to transmit NFe")
+        }
+    }
+}
+
+#[get("/identifications/{id}/protocol")]
+pub async fn get_protocol(
+    repo: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find(&id).await {
+        Ok(protocol) => HttpResponse::Ok().json(protocol),
+        Err(e) => {
+            error!("Failed to fetch NFe protocol: {}", e);
+            repository_error_response(&e, "Failed to fetch NFe protocol")
+        }
+    }
+}
+
+#[get("/identifications/{id}/protocols")]
+pub async fn get_protocols(
+    repo: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_all(&id).await {
+        Ok(protocols) => HttpResponse::Ok().json(protocols),
+        Err(e) => {
+            error!("Failed to fetch NFe protocols: {}", e);
+            repository_error_response(&e, "Failed to fetch NFe protocols")
+        }
+    }
+}
+
+#[get("/identifications/{id}/status-history")]
+pub async fn get_status_history(
+    repo: web::Data<Arc<NFeStatusRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_history(&id).await {
+        Ok(history) => HttpResponse::Ok().json(history),
+        Err(e) => {
+            error!("Failed to fetch NFe status history: {}", e);
+            repository_error_response(&e, "Failed to fetch NFe status history")
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_contingency::ActivateContingency;
+use crate::repositories::nfe_contingency_repository::NFeContingencyRepository;
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_contingencies)
+        .service(activate_contingency)
+        .service(deactivate_contingency)
+        .service(list_queue)
+        .service(transmit_queue);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueueQuery {
+    /// Only the notes SEFAZ has not received yet.
+    #[serde(default)]
+    pub pending: bool,
+}
+
+/// Every contingency period, newest first; active ones have no `dhEnd`.
+#[get("/contingencies")]
+pub async fn list_contingencies(repo: web::Data<Arc<NFeContingencyRepository>>) -> impl Responder {
+    match repo.find_all().await {
+        Ok(contingencies) => HttpResponse::Ok().json(contingencies),
+        Err(e) => {
+            error!("Failed to fetch contingencies: {}", e);
+            repository_error_response(&e, "Failed to fetch contingencies")
+        }
+    }
+}
+
+/// Answers 201 with the period; 409 when one is already active for the state and model.
+#[post("/contingencies")]
+pub async fn activate_contingency(
+    repo: web::Data<Arc<NFeContingencyRepository>>,
+    body: web::Json<ActivateContingency>,
+) -> impl Responder {
+    match repo.activate(&body).await {
+        Ok(contingency) => HttpResponse::Created().json(contingency),
+        Err(e) => {
+            error!("Failed to activate contingency: {}", e);
+            repository_error_response(&e, "Failed to activate contingency")
+        }
+    }
+}
+
+/// Ends the contingency and answers with the queued notes transmitted, in order.
+#[delete("/contingencies/{c_uf}/{mod_}")]
+pub async fn deactivate_contingency(
+    repo: web::Data<Arc<NFeContingencyRepository>>,
+    path: web::Path<(String, String)>,
+) -> impl Responder {
+    let (c_uf, mod_) = path.into_inner();
+    match repo.deactivate(&c_uf, &mod_).await {
+        Ok(attempted) => HttpResponse::Ok().json(attempted),
+        Err(e) => {
+            error!("Failed to deactivate contingency: {}", e);
+            repository_error_response(&e, "Failed to deactivate contingency")
+        }
+    }
+}
+
+#[get("/contingencies/queue")]
+pub async fn list_queue(
+    repo: web::Data<Arc<NFeContingencyRepository>>,
+    query: web::Query<QueueQuery>,
+) -> impl Responder {
+    match repo.find_queue(query.pending).await {
+        Ok(queue) => HttpResponse::Ok().json(queue),
+        Err(e) => {
+            error!("Failed to fetch contingency queue: {}", e);
+            repository_error_response(&e, "Failed to fetch contingency queue")
+        }
+    }
+}
+
+/// Retries the queued notes of states no longer in contingency, in order.
+#[post("/contingencies/queue/transmit")]
+pub async fn transmit_queue(repo: web::Data<Arc<NFeContingencyRepository>>) -> impl Responder {
+    match repo.drain().await {
+        Ok(attempted) => HttpResponse::Ok().json(attempted),
+        Err(e) => {
+            error!("Failed to transmit contingency queue: {}", e);
+            repository_error_response(&e, "Failed to transmit contingency queue")
+        }
+    }
+}
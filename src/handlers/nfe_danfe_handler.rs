@@ -0,0 +1,95 @@
+use crate::errors::RepositoryError;
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_document::NFeDocument;
+use crate::repositories::nfe_authorization_repository::NFeAuthorizationRepository;
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::services::danfe::nfce::{self, Line};
+use crate::services::danfe::{escpos, nfe};
+use actix_web::http::header;
+use actix_web::{get, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_danfe).service(get_danfe_escpos);
+}
+
+/// Lines of the DANFE NFC-e, with the QR code of the signed note.
+async fn receipt(
+    document: &NFeDocument,
+    authorizations: &NFeAuthorizationRepository,
+    internal_key: &str,
+) -> Result<Vec<Line>, RepositoryError> {
+    let authorization = authorizations.find_authorization(internal_key).await?;
+    let supplement = authorizations.find_nfce_supplement(internal_key).await?;
+    Ok(nfce::receipt(
+        document,
+        authorization.as_ref(),
+        &supplement,
+    )?)
+}
+
+/// Access key and PDF of the DANFE of the note.
+async fn render(
+    documents: &NFeDocumentRepository,
+    authorizations: &NFeAuthorizationRepository,
+    internal_key: &str,
+) -> Result<(String, Vec<u8>), RepositoryError> {
+    let document = documents.find(internal_key).await?;
+    let key = document.access_key()?.to_string();
+    let pdf = if document.identification.mod_ == "65" {
+        let lines = receipt(&document, authorizations, internal_key).await?;
+        nfce::render_pdf(&lines, &key)?
+    } else {
+        let authorization = authorizations.find_authorization(internal_key).await?;
+        nfe::render(&document, authorization.as_ref())?
+    };
+    Ok((key, pdf))
+}
+
+/// DANFE in the orientation of `tpImp`; marked "SEM VALOR FISCAL" in homologation and
+/// until the note is authorized, unless it was issued in offline contingency. The
+/// NFC-e gets its 80 mm receipt, available once the note is signed.
+#[get("/identifications/{id}/danfe.pdf")]
+pub async fn get_danfe(
+    documents: web::Data<Arc<NFeDocumentRepository>>,
+    authorizations: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match render(&documents, &authorizations, &id).await {
+        Ok((key, pdf)) => HttpResponse::Ok()
+            .content_type("application/pdf")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.pdf\"", key),
+            ))
+            .body(pdf),
+        Err(e) => {
+            error!("Failed to render DANFE: {}", e);
+            repository_error_response(&e, "Failed to render DANFE")
+        }
+    }
+}
+
+/// DANFE NFC-e as ESC/POS commands, to be sent as they are to a thermal printer.
+#[get("/identifications/{id}/danfe.escpos")]
+pub async fn get_danfe_escpos(
+    documents: web::Data<Arc<NFeDocumentRepository>>,
+    authorizations: web::Data<Arc<NFeAuthorizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let result = async {
+        let document = documents.find(&id).await?;
+        let lines = receipt(&document, &authorizations, &id).await?;
+        Ok::<_, RepositoryError>(escpos::render(&lines))
+    };
+    match result.await {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(bytes),
+        Err(e) => {
+            error!("Failed to render DANFE NFC-e: {}", e);
+            repository_error_response(&e, "Failed to render DANFE NFC-e")
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::handlers::common::repository_error_response;
+use crate::repositories::nfe_distribution_repository::NFeDistributionRepository;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(sync_distribution).service(list_distributed);
+}
+
+/// Reads the new documents of the CNPJ now instead of waiting for the periodic run;
+/// 409 while SEFAZ asks to wait before the next query.
+#[post("/distribution/{cnpj}/sync")]
+pub async fn sync_distribution(
+    repo: web::Data<Arc<NFeDistributionRepository>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match repo.sync(&path.into_inner()).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Failed to read distributed documents: {}", e);
+            repository_error_response(&e, "Failed to read distributed documents")
+        }
+    }
+}
+
+#[get("/distribution/{cnpj}/documents")]
+pub async fn list_distributed(
+    repo: web::Data<Arc<NFeDistributionRepository>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match repo.find_documents(&path.into_inner()).await {
+        Ok(documents) => HttpResponse::Ok().json(documents),
+        Err(e) => {
+            error!("Failed to fetch distributed documents: {}", e);
+            repository_error_response(&e, "Failed to fetch distributed documents")
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_event::{CancelNFe, CorrectNFe};
+use crate::repositories::nfe_event_repository::NFeEventRepository;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(cancel).service(correct).service(get_events);
+}
+
+/// Answers 200 with the event, including events rejected by SEFAZ; the note is only
+/// cancelled when `cStat` is 135, 136 or 155.
+#[post("/identifications/{id}/cancel")]
+pub async fn cancel(
+    repo: web::Data<Arc<NFeEventRepository>>,
+    id: web::Path<String>,
+    body: web::Json<CancelNFe>,
+) -> impl Responder {
+    match repo.cancel(&id, &body).await {
+        Ok(event) => HttpResponse::Ok().json(event),
+        Err(e) => {
+            error!("Failed to cancel NFe: {}", e);
+            repository_error_response(&e, "Failed to cancel NFe")
+        }
+    }
+}
+
+/// Registers a correction letter (CC-e); like cancellations, rejected events are
+/// answered with 200 and kept in the history.
+#[post("/identifications/{id}/corrections")]
+pub async fn correct(
+    repo: web::Data<Arc<NFeEventRepository>>,
+    id: web::Path<String>,
+    body: web::Json<CorrectNFe>,
+) -> impl Responder {
+    match repo.correct(&id, &body).await {
+        Ok(event) => HttpResponse::Ok().json(event),
+        Err(e) => {
+            error!("Failed to register correction letter: {}", e);
+            repository_error_response(&e, "Failed to register correction letter")
+        }
+    }
+}
+
+/// Every event sent for the note, oldest first.
+#[get("/identifications/{id}/events")]
+pub async fn get_events(
+    repo: web::Data<Arc<NFeEventRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_all(&id).await {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(e) => {
+            error!("Failed to fetch NFe events: {}", e);
+            repository_error_response(&e, "Failed to fetch NFe events")
+        }
+    }
+}
@@ -1,23 +1,22 @@
-use crate::handlers::common::{ErrorResponse, PaginationResponse};
+use crate::handlers::common::{repository_error_response, ErrorResponse, PaginationResponse};
 use crate::models::nfe_identification::{CreateNFeIdentification, NFeIdentification};
+use crate::models::nfe_status::NFeStatus;
 use crate::repositories::nfe_identification_repository::{
     NFeFilterParams, NFeIdentificationRepository,
 };
 use actix_web::web::{self, Query};
 use actix_web::{delete, get, post, put, HttpResponse, Responder};
 use serde::Deserialize;
+use std::str::FromStr;
 use std::sync::Arc;
 use tracing::{error, info, instrument};
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/api")
-            .service(list_identifications)
-            .service(get_identification)
-            .service(create_identification)
-            .service(update_identification)
-            .service(delete_identification),
-    );
+    cfg.service(list_identifications)
+        .service(get_identification)
+        .service(create_identification)
+        .service(update_identification)
+        .service(delete_identification);
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,6 +35,8 @@ pub struct ListQuery {
     pub dh_emi: Option<String>,
     #[serde(default)]
     pub search: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
 }
 
 fn default_page() -> u32 {
@@ -57,6 +58,11 @@ pub async fn list_identifications(
         query.page, query.page_size
     );
 
+    let status = match query.status.as_deref().map(NFeStatus::from_str).transpose() {
+        Ok(status) => status,
+        Err(e) => return repository_error_response(&e.into(), "Invalid status"),
+    };
+
     let params = NFeFilterParams {
         page: query.page,
         page_size: query.page_size,
@@ -64,6 +70,7 @@ pub async fn list_identifications(
         sort_order: query.n_nf.clone(),
         filter: query.tp_nf.clone(),
         search: query.search.clone(),
+        status,
     };
 
     match repo.find_all(params).await {
@@ -86,7 +93,7 @@ pub async fn list_identifications(
     }
 }
 
-#[get("/api/identifications/{id}")]
+#[get("/identifications/{id}")]
 pub async fn get_identification(
     repo: web::Data<Arc<NFeIdentificationRepository>>,
     id: web::Path<String>,
@@ -105,7 +112,7 @@ pub async fn get_identification(
     }
 }
 
-#[post("/api/identifications")]
+#[post("/identifications")]
 pub async fn create_identification(
     repo: web::Data<Arc<NFeIdentificationRepository>>,
     identification: web::Json<CreateNFeIdentification>,
@@ -114,14 +121,12 @@ pub async fn create_identification(
         Ok(created) => HttpResponse::Created().json(created),
         Err(e) => {
             error!("Failed to create identification: {}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to create identification".to_string(),
-            })
+            repository_error_response(&e, "Failed to create identification")
         }
     }
 }
 
-#[put("/api/identifications/{id}")]
+#[put("/identifications/{id}")]
 pub async fn update_identification(
     repo: web::Data<Arc<NFeIdentificationRepository>>,
     id: web::Path<String>,
@@ -131,14 +136,12 @@ pub async fn update_identification(
         Ok(updated) => HttpResponse::Ok().json(updated),
         Err(e) => {
             error!("Failed to update identification: {}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to update identification".to_string(),
-            })
+            repository_error_response(&e, "Failed to update identification")
         }
     }
 }
 
-#[delete("/api/identifications/{id}")]
+#[delete("/identifications/{id}")]
 pub async fn delete_identification(
     repo: web::Data<Arc<NFeIdentificationRepository>>,
     id: web::Path<String>,
@@ -147,9 +150,7 @@ pub async fn delete_identification(
         Ok(_) => HttpResponse::NoContent().finish(),
         Err(e) => {
             error!("Failed to delete identification: {}", e);
-            HttpResponse::InternalServerError().json(ErrorResponse {
-                error: "Failed to delete identification".to_string(),
-            })
+            repository_error_response(&e, "Failed to delete identification")
         }
     }
 }
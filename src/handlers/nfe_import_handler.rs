@@ -0,0 +1,36 @@
+use crate::handlers::common::repository_error_response;
+use crate::repositories::nfe_import_repository::NFeImportRepository;
+use actix_web::{post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+/// Request body limit for uploads, applied through `web::PayloadConfig`.
+pub const MAX_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(import_xml);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    /// Name used for the upload in the report.
+    pub file_name: Option<String>,
+}
+
+/// Accepts an `NFe`/`nfeProc` XML file or a ZIP of them as the raw request body.
+#[post("/imports/xml")]
+pub async fn import_xml(
+    repo: web::Data<Arc<NFeImportRepository>>,
+    params: web::Query<ImportParams>,
+    body: web::Bytes,
+) -> impl Responder {
+    let file_name = params.file_name.as_deref().unwrap_or("upload");
+    match repo.import(file_name, &body).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Failed to import XML: {}", e);
+            repository_error_response(&e, "Failed to import XML")
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_inutilization::CreateNFeInutilization;
+use crate::repositories::nfe_inutilization_repository::NFeInutilizationRepository;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_inutilization)
+        .service(list_inutilizations)
+        .service(get_inutilization);
+}
+
+/// Answers 201 with the stored request, including requests rejected by SEFAZ; the range
+/// is only voided when `cStat` is 102.
+#[post("/inutilizations")]
+pub async fn create_inutilization(
+    repo: web::Data<Arc<NFeInutilizationRepository>>,
+    body: web::Json<CreateNFeInutilization>,
+) -> impl Responder {
+    match repo.create(&body).await {
+        Ok(inutilization) => HttpResponse::Created().json(inutilization),
+        Err(e) => {
+            error!("Failed to request inutilização: {}", e);
+            repository_error_response(&e, "Failed to request inutilização")
+        }
+    }
+}
+
+/// Every inutilização requested, newest first.
+#[get("/inutilizations")]
+pub async fn list_inutilizations(
+    repo: web::Data<Arc<NFeInutilizationRepository>>,
+) -> impl Responder {
+    match repo.find_all().await {
+        Ok(inutilizations) => HttpResponse::Ok().json(inutilizations),
+        Err(e) => {
+            error!("Failed to fetch inutilizações: {}", e);
+            repository_error_response(&e, "Failed to fetch inutilizações")
+        }
+    }
+}
+
+#[get("/inutilizations/{id}")]
+pub async fn get_inutilization(
+    repo: web::Data<Arc<NFeInutilizationRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_by_id(&id).await {
+        Ok(inutilization) => HttpResponse::Ok().json(inutilization),
+        Err(e) => {
+            error!("Failed to fetch inutilização: {}", e);
+            repository_error_response(&e, "Failed to fetch inutilização")
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::handlers::common::{repository_error_response, ErrorResponse};
+use crate::models::nfe_item::{CreateNFeItem, ReorderNFeItems};
+use crate::repositories::nfe_item_repository::NFeItemRepository;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_items)
+        .service(reorder_items)
+        .service(get_item)
+        .service(create_item)
+        .service(delete_item);
+}
+
+#[get("/identifications/{id}/items")]
+pub async fn list_items(
+    repo: web::Data<Arc<NFeItemRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_all(&id).await {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(e) => {
+            error!("Failed to list items: {}", e);
+            repository_error_response(&e, "Failed to list items")
+        }
+    }
+}
+
+#[get("/identifications/{id}/items/{n_item}")]
+pub async fn get_item(
+    repo: web::Data<Arc<NFeItemRepository>>,
+    path: web::Path<(String, u32)>,
+) -> impl Responder {
+    let (id, n_item) = path.into_inner();
+    match repo.find_by_number(&id, n_item).await {
+        Ok(Some(item)) => HttpResponse::Ok().json(item),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Item not found".to_string(),
+        }),
+        Err(e) => {
+            error!("Failed to get item: {}", e);
+            repository_error_response(&e, "Failed to get item")
+        }
+    }
+}
+
+#[post("/identifications/{id}/items")]
+pub async fn create_item(
+    repo: web::Data<Arc<NFeItemRepository>>,
+    id: web::Path<String>,
+    item: web::Json<CreateNFeItem>,
+) -> impl Responder {
+    match repo.create(&id, &item).await {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => {
+            error!("Failed to add item: {}", e);
+            repository_error_response(&e, "Failed to add item")
+        }
+    }
+}
+
+#[put("/identifications/{id}/items/order")]
+pub async fn reorder_items(
+    repo: web::Data<Arc<NFeItemRepository>>,
+    id: web::Path<String>,
+    reorder: web::Json<ReorderNFeItems>,
+) -> impl Responder {
+    match repo.reorder(&id, &reorder).await {
+        Ok(items) => HttpResponse::Ok().json(items),
+        Err(e) => {
+            error!("Failed to reorder items: {}", e);
+            repository_error_response(&e, "Failed to reorder items")
+        }
+    }
+}
+
+#[delete("/identifications/{id}/items/{n_item}")]
+pub async fn delete_item(
+    repo: web::Data<Arc<NFeItemRepository>>,
+    path: web::Path<(String, u32)>,
+) -> impl Responder {
+    let (id, n_item) = path.into_inner();
+    match repo.delete(&id, n_item).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Failed to delete item: {}", e);
+            repository_error_response(&e, "Failed to delete item")
+        }
+    }
+}
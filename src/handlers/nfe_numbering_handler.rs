@@ -0,0 +1,26 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_numbering::NFeGapFilter;
+use crate::repositories::nfe_numbering_repository::NFeNumberingRepository;
+use actix_web::{get, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_gaps);
+}
+
+/// Numbers skipped in each serie, optionally narrowed by `emitDoc`, `mod` and `tpAmb`.
+/// Ranges already voided by a homologated inutilização are not reported.
+#[get("/numbering/gaps")]
+pub async fn get_gaps(
+    repo: web::Data<Arc<NFeNumberingRepository>>,
+    filter: web::Query<NFeGapFilter>,
+) -> impl Responder {
+    match repo.find_gaps(&filter).await {
+        Ok(gaps) => HttpResponse::Ok().json(gaps),
+        Err(e) => {
+            error!("Failed to build numbering gap report: {}", e);
+            repository_error_response(&e, "Failed to build numbering gap report")
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::handlers::common::{repository_error_response, ErrorResponse};
+use crate::models::nfe_emitter::CreateNFeEmitter;
+use crate::models::nfe_recipient::CreateNFeRecipient;
+use crate::repositories::nfe_participant_repository::NFeParticipantRepository;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_emitter)
+        .service(create_emitter)
+        .service(update_emitter)
+        .service(delete_emitter)
+        .service(get_recipient)
+        .service(create_recipient)
+        .service(update_recipient)
+        .service(delete_recipient);
+}
+
+#[get("/identifications/{id}/emitter")]
+pub async fn get_emitter(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_emitter(&id).await {
+        Ok(Some(emitter)) => HttpResponse::Ok().json(emitter),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Emitter not found".to_string(),
+        }),
+        Err(e) => {
+            error!("Failed to get emitter: {}", e);
+            repository_error_response(&e, "Failed to get emitter")
+        }
+    }
+}
+
+#[post("/identifications/{id}/emitter")]
+pub async fn create_emitter(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+    emitter: web::Json<CreateNFeEmitter>,
+) -> impl Responder {
+    match repo.create_emitter(&id, &emitter).await {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => {
+            error!("Failed to create emitter: {}", e);
+            repository_error_response(&e, "Failed to create emitter")
+        }
+    }
+}
+
+#[put("/identifications/{id}/emitter")]
+pub async fn update_emitter(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+    emitter: web::Json<CreateNFeEmitter>,
+) -> impl Responder {
+    match repo.update_emitter(&id, &emitter).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update emitter: {}", e);
+            repository_error_response(&e, "Failed to update emitter")
+        }
+    }
+}
+
+#[delete("/identifications/{id}/emitter")]
+pub async fn delete_emitter(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.delete_emitter(&id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Failed to delete emitter: {}", e);
+            repository_error_response(&e, "Failed to delete emitter")
+        }
+    }
+}
+
+#[get("/identifications/{id}/recipient")]
+pub async fn get_recipient(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_recipient(&id).await {
+        Ok(Some(recipient)) => HttpResponse::Ok().json(recipient),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "Recipient not found".to_string(),
+        }),
+        Err(e) => {
+            error!("Failed to get recipient: {}", e);
+            repository_error_response(&e, "Failed to get recipient")
+        }
+    }
+}
+
+#[post("/identifications/{id}/recipient")]
+pub async fn create_recipient(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+    recipient: web::Json<CreateNFeRecipient>,
+) -> impl Responder {
+    match repo.create_recipient(&id, &recipient).await {
+        Ok(created) => HttpResponse::Created().json(created),
+        Err(e) => {
+            error!("Failed to create recipient: {}", e);
+            repository_error_response(&e, "Failed to create recipient")
+        }
+    }
+}
+
+#[put("/identifications/{id}/recipient")]
+pub async fn update_recipient(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+    recipient: web::Json<CreateNFeRecipient>,
+) -> impl Responder {
+    match repo.update_recipient(&id, &recipient).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update recipient: {}", e);
+            repository_error_response(&e, "Failed to update recipient")
+        }
+    }
+}
+
+#[delete("/identifications/{id}/recipient")]
+pub async fn delete_recipient(
+    repo: web::Data<Arc<NFeParticipantRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.delete_recipient(&id).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => {
+            error!("Failed to delete recipient: {}", e);
+            repository_error_response(&e, "Failed to delete recipient")
+        }
+    }
+}
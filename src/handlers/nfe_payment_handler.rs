@@ -0,0 +1,73 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_payment::{NFeBilling, NFePayment};
+use crate::repositories::nfe_payment_repository::NFePaymentRepository;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_billing)
+        .service(update_billing)
+        .service(get_payment)
+        .service(update_payment);
+}
+
+/// The stored `cobr` group, empty when none was informed.
+#[get("/identifications/{id}/billing")]
+pub async fn get_billing(
+    repo: web::Data<Arc<NFePaymentRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_billing(&id).await {
+        Ok(billing) => HttpResponse::Ok().json(billing),
+        Err(e) => {
+            error!("Failed to get billing: {}", e);
+            repository_error_response(&e, "Failed to get billing")
+        }
+    }
+}
+
+#[put("/identifications/{id}/billing")]
+pub async fn update_billing(
+    repo: web::Data<Arc<NFePaymentRepository>>,
+    id: web::Path<String>,
+    billing: web::Json<NFeBilling>,
+) -> impl Responder {
+    match repo.update_billing(&id, &billing).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update billing: {}", e);
+            repository_error_response(&e, "Failed to update billing")
+        }
+    }
+}
+
+/// The stored `pag` group, or tPag 90 alone when none was informed.
+#[get("/identifications/{id}/payment")]
+pub async fn get_payment(
+    repo: web::Data<Arc<NFePaymentRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_payment(&id).await {
+        Ok(payment) => HttpResponse::Ok().json(payment),
+        Err(e) => {
+            error!("Failed to get payment: {}", e);
+            repository_error_response(&e, "Failed to get payment")
+        }
+    }
+}
+
+#[put("/identifications/{id}/payment")]
+pub async fn update_payment(
+    repo: web::Data<Arc<NFePaymentRepository>>,
+    id: web::Path<String>,
+    payment: web::Json<NFePayment>,
+) -> impl Responder {
+    match repo.update_payment(&id, &payment).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update payment: {}", e);
+            repository_error_response(&e, "Failed to update payment")
+        }
+    }
+}
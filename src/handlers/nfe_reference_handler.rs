@@ -0,0 +1,57 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_reference::NFeReference;
+use crate::repositories::nfe_reference_repository::NFeReferenceRepository;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_references)
+        .service(replace_references)
+        .service(get_referrers);
+}
+
+#[get("/identifications/{id}/references")]
+pub async fn get_references(
+    repo: web::Data<Arc<NFeReferenceRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find_all(&id).await {
+        Ok(references) => HttpResponse::Ok().json(references),
+        Err(e) => {
+            error!("Failed to get references: {}", e);
+            repository_error_response(&e, "Failed to get references")
+        }
+    }
+}
+
+/// Replaces the `NFref` list of the note; an empty list removes every reference.
+#[put("/identifications/{id}/references")]
+pub async fn replace_references(
+    repo: web::Data<Arc<NFeReferenceRepository>>,
+    id: web::Path<String>,
+    references: web::Json<Vec<NFeReference>>,
+) -> impl Responder {
+    match repo.replace(&id, &references).await {
+        Ok(stored) => HttpResponse::Ok().json(stored),
+        Err(e) => {
+            error!("Failed to replace references: {}", e);
+            repository_error_response(&e, "Failed to replace references")
+        }
+    }
+}
+
+/// Notes that reference the access key, such as the returns and complements of a note.
+#[get("/access-keys/{key}/referrers")]
+pub async fn get_referrers(
+    repo: web::Data<Arc<NFeReferenceRepository>>,
+    key: web::Path<String>,
+) -> impl Responder {
+    match repo.find_referrers(&key).await {
+        Ok(referrers) => HttpResponse::Ok().json(referrers),
+        Err(e) => {
+            error!("Failed to find referrers: {}", e);
+            repository_error_response(&e, "Failed to find referrers")
+        }
+    }
+}
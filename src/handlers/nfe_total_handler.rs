@@ -0,0 +1,39 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_total::UpdateNFeTotal;
+use crate::repositories::nfe_total_repository::NFeTotalRepository;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_total).service(update_total);
+}
+
+#[get("/identifications/{id}/total")]
+pub async fn get_total(
+    repo: web::Data<Arc<NFeTotalRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find(&id).await {
+        Ok(total) => HttpResponse::Ok().json(total),
+        Err(e) => {
+            error!("Failed to get totals: {}", e);
+            repository_error_response(&e, "Failed to get totals")
+        }
+    }
+}
+
+#[put("/identifications/{id}/total")]
+pub async fn update_total(
+    repo: web::Data<Arc<NFeTotalRepository>>,
+    id: web::Path<String>,
+    total: web::Json<UpdateNFeTotal>,
+) -> impl Responder {
+    match repo.update(&id, &total).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update totals: {}", e);
+            repository_error_response(&e, "Failed to update totals")
+        }
+    }
+}
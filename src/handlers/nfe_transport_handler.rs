@@ -0,0 +1,40 @@
+use crate::handlers::common::repository_error_response;
+use crate::models::nfe_transport::NFeTransport;
+use crate::repositories::nfe_transport_repository::NFeTransportRepository;
+use actix_web::{get, put, web, HttpResponse, Responder};
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_transport).service(update_transport);
+}
+
+/// The stored `transp` group, or modFrete 9 alone when none was informed.
+#[get("/identifications/{id}/transport")]
+pub async fn get_transport(
+    repo: web::Data<Arc<NFeTransportRepository>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    match repo.find(&id).await {
+        Ok(transport) => HttpResponse::Ok().json(transport),
+        Err(e) => {
+            error!("Failed to get transport: {}", e);
+            repository_error_response(&e, "Failed to get transport")
+        }
+    }
+}
+
+#[put("/identifications/{id}/transport")]
+pub async fn update_transport(
+    repo: web::Data<Arc<NFeTransportRepository>>,
+    id: web::Path<String>,
+    transport: web::Json<NFeTransport>,
+) -> impl Responder {
+    match repo.update(&id, &transport).await {
+        Ok(updated) => HttpResponse::Ok().json(updated),
+        Err(e) => {
+            error!("Failed to update transport: {}", e);
+            repository_error_response(&e, "Failed to update transport")
+        }
+    }
+}
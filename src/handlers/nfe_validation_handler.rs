@@ -0,0 +1,61 @@
+use crate::handlers::common::{repository_error_response, ValidationErrorResponse};
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::services::xml::nfe_serializer;
+use crate::services::xml::schema_validator::SchemaValidator;
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(validate_xml).service(validate_note);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidateParams {
+    /// Accept documents without `ds:Signature`.
+    #[serde(default)]
+    pub unsigned: bool,
+}
+
+#[post("/validate/xml")]
+pub async fn validate_xml(
+    validator: web::Data<Arc<SchemaValidator>>,
+    params: web::Query<ValidateParams>,
+    body: String,
+) -> impl Responder {
+    let result = if params.unsigned {
+        validator.validate_unsigned(&body)
+    } else {
+        validator.validate(&body)
+    };
+    match result {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::UnprocessableEntity().json(ValidationErrorResponse {
+            error: e.message,
+            field: e.field,
+        }),
+    }
+}
+
+/// Validates the XML generated for a stored note, before it is signed.
+#[get("/identifications/{id}/xml/validation")]
+pub async fn validate_note(
+    repo: web::Data<Arc<NFeDocumentRepository>>,
+    validator: web::Data<Arc<SchemaValidator>>,
+    id: web::Path<String>,
+) -> impl Responder {
+    let result = match repo.find(&id).await {
+        Ok(document) => nfe_serializer::serialize(&document)
+            .and_then(|xml| validator.validate_unsigned(&xml))
+            .map_err(Into::into),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Failed to validate NFe: {}", e);
+            repository_error_response(&e, "Failed to validate NFe")
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::handlers::common::repository_error_response;
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::services::signing_service::SigningService;
+use crate::services::xml::nfe_serializer;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::error;
+
+#[derive(Debug, Deserialize)]
+pub struct XmlQuery {
+    /// Signs `infNFe` with the configured A1 certificate.
+    #[serde(default)]
+    pub signed: bool,
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_xml);
+}
+
+#[get("/identifications/{id}/xml")]
+pub async fn get_xml(
+    repo: web::Data<Arc<NFeDocumentRepository>>,
+    signing: web::Data<Arc<SigningService>>,
+    id: web::Path<String>,
+    query: web::Query<XmlQuery>,
+) -> impl Responder {
+    let result = match repo.find(&id).await {
+        Ok(document) if query.signed => signing.sign_nfe(&document),
+        Ok(document) => nfe_serializer::serialize(&document).map_err(Into::into),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(xml) => HttpResponse::Ok()
+            .content_type("application/xml; charset=utf-8")
+            .body(format!("{}{}", nfe_serializer::XML_DECLARATION, xml)),
+        Err(e) => {
+            error!("Failed to serialize NFe: {}", e);
+            repository_error_response(&e, "Failed to serialize NFe")
+        }
+    }
+}
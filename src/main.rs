@@ -1,11 +1,12 @@
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use oracle::Connection;
+use oracle::pool::PoolBuilder;
 use redis::aio::ConnectionManager;
 use redis::Client;
 use std::env;
+use std::path::Path;
 use std::sync::Arc;
-use tracing::{error, info, Level};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
 mod errors;
@@ -14,7 +15,13 @@ mod models;
 mod repositories;
 mod services;
 
-use handlers::nfe_identification_handler;
+use handlers::{
+    nfe_access_key_handler, nfe_additional_handler, nfe_authorization_handler,
+    nfe_contingency_handler, nfe_danfe_handler, nfe_distribution_handler, nfe_event_handler,
+    nfe_identification_handler, nfe_import_handler, nfe_inutilization_handler, nfe_item_handler,
+    nfe_numbering_handler, nfe_participant_handler, nfe_payment_handler, nfe_reference_handler,
+    nfe_total_handler, nfe_transport_handler, nfe_validation_handler, nfe_xml_handler,
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -38,6 +45,7 @@ async fn main() -> std::io::Result<()> {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     let redis_url = env::var("REDIS_URL").expect("REDIS_URL must be set");
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    let schema_dir = env::var("NFE_SCHEMA_DIR").unwrap_or_else(|_| ".".to_string());
 
     // Parse the database URL into username, password, and connect string
     let parts: Vec<&str> = database_url.split("://").collect();
@@ -62,9 +70,27 @@ async fn main() -> std::io::Result<()> {
     let password = user_pass[1];
     let connect_string = credentials[1];
 
+    let pool_size: u32 = env::var("DATABASE_POOL_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(10);
+
     info!("Attempting to connect to Oracle database...");
-    let oracle_conn = match Connection::connect(username, password, connect_string) {
-        Ok(conn) => {
+    let oracle_pool = match PoolBuilder::new(username, password, connect_string)
+        .max_connections(pool_size)
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Failed to create the Oracle connection pool: {}", e);
+            panic!("Failed to create the Oracle connection pool: {}", e);
+        }
+    };
+    // Single statements share one session and commit as they run; writes spanning
+    // several statements take a pooled connection and commit as one transaction.
+    let oracle_conn = Arc::new(match oracle_pool.get() {
+        Ok(mut conn) => {
+            conn.set_autocommit(true);
             info!("Successfully connected to Oracle database");
             conn
         }
@@ -72,7 +98,7 @@ async fn main() -> std::io::Result<()> {
             error!("Failed to connect to Oracle database: {}", e);
             panic!("Failed to connect to Oracle database: {}", e);
         }
-    };
+    });
 
     // Connect to Redis
     let redis_client = Client::open(redis_url).expect("Failed to create Redis client");
@@ -82,21 +108,314 @@ async fn main() -> std::io::Result<()> {
 
     info!("Successfully connected to both Oracle and Redis");
 
-    // Create repository
+    let schema_validator = Arc::new(
+        services::xml::schema_validator::SchemaValidator::load(Path::new(&schema_dir))
+            .expect("Failed to load the NF-e schemas"),
+    );
+
+    // The A1 certificate signs the XML and authenticates the TLS connections to SEFAZ.
+    let certificate = env::var("NFE_CERT_PATH")
+        .ok()
+        .map(|path| std::fs::read(path).expect("Failed to read the A1 certificate"));
+    let certificate_password = env::var("NFE_CERT_PASSWORD").unwrap_or_default();
+    // The CSC issued by the state hashes the QR code of the NFC-e.
+    let csc = match (env::var("NFCE_CSC_ID"), env::var("NFCE_CSC")) {
+        (Ok(id), Ok(token)) => Some(
+            services::xml::nfce_supplement::Csc::new(&id, &token)
+                .expect("Invalid NFCE_CSC_ID or NFCE_CSC"),
+        ),
+        _ => {
+            warn!("NFCE_CSC_ID or NFCE_CSC is not set; NFC-e issuing is disabled");
+            None
+        }
+    };
+    // The CSRT issued to the software vendor hashes infRespTec in the states that require it.
+    let csrt = match (env::var("NFE_CSRT_ID"), env::var("NFE_CSRT")) {
+        (Ok(id), Ok(token)) => Some(
+            services::xml::csrt::Csrt::new(&id, &token).expect("Invalid NFE_CSRT_ID or NFE_CSRT"),
+        ),
+        _ => {
+            warn!("NFE_CSRT_ID or NFE_CSRT is not set; infRespTec is sent without hashCSRT");
+            None
+        }
+    };
+    let signing_service = Arc::new(
+        match &certificate {
+            Some(der) => {
+                services::signing_service::SigningService::from_pkcs12(der, &certificate_password)
+                    .expect("Failed to load the A1 certificate")
+            }
+            None => {
+                warn!("NFE_CERT_PATH is not set; XML signing is disabled");
+                services::signing_service::SigningService::new(None)
+            }
+        }
+        .with_csc(csc),
+    );
+
+    // SEFAZ_MOCK=true answers every state from an in-process mock SEFAZ.
+    let mock_sefaz = if env::var("SEFAZ_MOCK").is_ok_and(|v| v == "true") {
+        let tp_amb = env::var("SEFAZ_MOCK_TP_AMB").unwrap_or_else(|_| "2".to_string());
+        Some(
+            services::sefaz::mock::MockSefaz::start(&tp_amb)
+                .await
+                .expect("Failed to start the mock SEFAZ"),
+        )
+    } else {
+        None
+    };
+    let sefaz_config = match &mock_sefaz {
+        Some(mock) => services::sefaz::config::SefazConfig::single_server(
+            mock.url(),
+            &env::var("SEFAZ_MOCK_TP_AMB").unwrap_or_else(|_| "2".to_string()),
+        ),
+        None => {
+            let path =
+                env::var("SEFAZ_CONFIG").unwrap_or_else(|_| "sefaz_endpoints.json".to_string());
+            services::sefaz::config::SefazConfig::load(Path::new(&path)).unwrap_or_else(|e| {
+                warn!("{}; transmission to SEFAZ is disabled", e);
+                services::sefaz::config::SefazConfig::default()
+            })
+        }
+    };
+    let sefaz_client = Arc::new(
+        services::sefaz::soap::SoapClient::new(
+            sefaz_config,
+            certificate
+                .as_deref()
+                .map(|der| (der, certificate_password.as_str())),
+        )
+        .expect("Failed to create the SEFAZ client"),
+    );
+
+    // Create repositories
+    let status_repo = Arc::new(
+        repositories::nfe_status_repository::NFeStatusRepository::new(
+            Arc::clone(&oracle_conn),
+            oracle_pool.clone(),
+            redis_manager.clone(),
+        ),
+    );
     let nfe_repo = Arc::new(
         repositories::nfe_identification_repository::NFeIdentificationRepository::new(
-            oracle_conn,
+            Arc::clone(&oracle_conn),
+            oracle_pool.clone(),
+            redis_manager.clone(),
+            Arc::clone(&status_repo),
+        ),
+    );
+    let participant_repo = Arc::new(
+        repositories::nfe_participant_repository::NFeParticipantRepository::new(
+            Arc::clone(&oracle_conn),
+            redis_manager.clone(),
+            Arc::clone(&status_repo),
+        ),
+    );
+    let item_repo = Arc::new(repositories::nfe_item_repository::NFeItemRepository::new(
+        Arc::clone(&oracle_conn),
+        redis_manager.clone(),
+        Arc::clone(&status_repo),
+    ));
+    let access_key_repo = Arc::new(
+        repositories::nfe_access_key_repository::NFeAccessKeyRepository::new(Arc::clone(
+            &oracle_conn,
+        )),
+    );
+    let total_repo = Arc::new(repositories::nfe_total_repository::NFeTotalRepository::new(
+        Arc::clone(&oracle_conn),
+        Arc::clone(&item_repo),
+        Arc::clone(&status_repo),
+    ));
+    let transport_repo = Arc::new(
+        repositories::nfe_transport_repository::NFeTransportRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&status_repo),
+        ),
+    );
+    let reference_repo = Arc::new(
+        repositories::nfe_reference_repository::NFeReferenceRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&status_repo),
+        ),
+    );
+    let payment_repo = Arc::new(
+        repositories::nfe_payment_repository::NFePaymentRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&total_repo),
+            Arc::clone(&status_repo),
+        ),
+    );
+    let additional_repo = Arc::new(
+        repositories::nfe_additional_repository::NFeAdditionalRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&status_repo),
+        )
+        .with_csrt(csrt),
+    );
+    let document_repo = Arc::new(
+        repositories::nfe_document_repository::NFeDocumentRepository::new(
+            Arc::clone(&nfe_repo),
+            Arc::clone(&participant_repo),
+            Arc::clone(&reference_repo),
+            Arc::clone(&item_repo),
+            Arc::clone(&total_repo),
+            Arc::clone(&transport_repo),
+            Arc::clone(&payment_repo),
+            Arc::clone(&additional_repo),
+        ),
+    );
+    let import_repo = Arc::new(
+        repositories::nfe_import_repository::NFeImportRepository::new(
+            Arc::clone(&oracle_conn),
+            oracle_pool.clone(),
             redis_manager,
+            Arc::clone(&status_repo),
+        ),
+    );
+    let authorization_repo = Arc::new(
+        repositories::nfe_authorization_repository::NFeAuthorizationRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&document_repo),
+            Arc::clone(&status_repo),
+            Arc::clone(&schema_validator),
+            Arc::clone(&signing_service),
+            Arc::clone(&sefaz_client),
+        ),
+    );
+    let contingency_repo = Arc::new(
+        repositories::nfe_contingency_repository::NFeContingencyRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&document_repo),
+            Arc::clone(&nfe_repo),
+            Arc::clone(&authorization_repo),
+            Arc::clone(&status_repo),
+            Arc::clone(&sefaz_client),
+        ),
+    );
+
+    let event_repo = Arc::new(repositories::nfe_event_repository::NFeEventRepository::new(
+        Arc::clone(&oracle_conn),
+        oracle_pool.clone(),
+        Arc::clone(&document_repo),
+        Arc::clone(&authorization_repo),
+        Arc::clone(&status_repo),
+        Arc::clone(&signing_service),
+        Arc::clone(&sefaz_client),
+    ));
+    let numbering_repo = Arc::new(
+        repositories::nfe_numbering_repository::NFeNumberingRepository::new(Arc::clone(
+            &oracle_conn,
+        )),
+    );
+    let inutilization_repo = Arc::new(
+        repositories::nfe_inutilization_repository::NFeInutilizationRepository::new(
+            Arc::clone(&oracle_conn),
+            oracle_pool.clone(),
+            Arc::clone(&signing_service),
+            Arc::clone(&sefaz_client),
+        ),
+    );
+    let distribution_repo = Arc::new(
+        repositories::nfe_distribution_repository::NFeDistributionRepository::new(
+            Arc::clone(&oracle_conn),
+            Arc::clone(&import_repo),
+            Arc::clone(&sefaz_client),
         ),
     );
 
+    // Ends automatic contingencies once SEFAZ answers again and sends the queued notes.
+    let check_interval =
+        std::time::Duration::from_secs(sefaz_client.config().contingency_check_secs);
+    let resume_repo = Arc::clone(&contingency_repo);
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(check_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = resume_repo.resume().await {
+                warn!("Failed to resume transmissions after contingency: {}", e);
+            }
+        }
+    });
+
+    // Pulls the documents issued to our CNPJs from the Ambiente Nacional.
+    let dfe_cnpjs: Vec<String> = env::var("DFE_CNPJS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|cnpj| cnpj.trim().to_string())
+        .filter(|cnpj| !cnpj.is_empty())
+        .collect();
+    if dfe_cnpjs.is_empty() {
+        warn!("DFE_CNPJS is not set; documents issued to our CNPJs are not pulled");
+    } else {
+        let distribution_interval =
+            std::time::Duration::from_secs(sefaz_client.config().distribution_interval_secs);
+        let sync_repo = Arc::clone(&distribution_repo);
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(distribution_interval);
+            loop {
+                interval.tick().await;
+                for cnpj in &dfe_cnpjs {
+                    if let Err(e) = sync_repo.sync(cnpj).await {
+                        warn!("Failed to pull documents of CNPJ {}: {}", cnpj, e);
+                    }
+                }
+            }
+        });
+    }
+
     info!("Starting HTTP server on 0.0.0.0:{}", port);
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(Arc::clone(&nfe_repo)))
-            .configure(nfe_identification_handler::init_routes)
+            .app_data(web::Data::new(Arc::clone(&participant_repo)))
+            .app_data(web::Data::new(Arc::clone(&item_repo)))
+            .app_data(web::Data::new(Arc::clone(&total_repo)))
+            .app_data(web::Data::new(Arc::clone(&transport_repo)))
+            .app_data(web::Data::new(Arc::clone(&payment_repo)))
+            .app_data(web::Data::new(Arc::clone(&additional_repo)))
+            .app_data(web::Data::new(Arc::clone(&reference_repo)))
+            .app_data(web::Data::new(Arc::clone(&access_key_repo)))
+            .app_data(web::Data::new(Arc::clone(&document_repo)))
+            .app_data(web::Data::new(Arc::clone(&import_repo)))
+            .app_data(web::Data::new(Arc::clone(&authorization_repo)))
+            .app_data(web::Data::new(Arc::clone(&contingency_repo)))
+            .app_data(web::Data::new(Arc::clone(&distribution_repo)))
+            .app_data(web::Data::new(Arc::clone(&status_repo)))
+            .app_data(web::Data::new(Arc::clone(&event_repo)))
+            .app_data(web::Data::new(Arc::clone(&inutilization_repo)))
+            .app_data(web::Data::new(Arc::clone(&numbering_repo)))
+            .app_data(web::Data::new(Arc::clone(&schema_validator)))
+            .app_data(web::Data::new(Arc::clone(&signing_service)))
+            .app_data(web::PayloadConfig::new(nfe_import_handler::MAX_UPLOAD_SIZE))
+            .service(
+                web::scope("/api")
+                    .configure(nfe_identification_handler::init_routes)
+                    .configure(nfe_participant_handler::init_routes)
+                    .configure(nfe_item_handler::init_routes)
+                    .configure(nfe_total_handler::init_routes)
+                    .configure(nfe_transport_handler::init_routes)
+                    .configure(nfe_payment_handler::init_routes)
+                    .configure(nfe_additional_handler::init_routes)
+                    .configure(nfe_reference_handler::init_routes)
+                    .configure(nfe_access_key_handler::init_routes)
+                    .configure(nfe_xml_handler::init_routes)
+                    .configure(nfe_danfe_handler::init_routes)
+                    .configure(nfe_import_handler::init_routes)
+                    .configure(nfe_validation_handler::init_routes)
+                    .configure(nfe_authorization_handler::init_routes)
+                    .configure(nfe_contingency_handler::init_routes)
+                    .configure(nfe_distribution_handler::init_routes)
+                    .configure(nfe_event_handler::init_routes)
+                    .configure(nfe_inutilization_handler::init_routes)
+                    .configure(nfe_numbering_handler::init_routes),
+            )
     })
     .bind(format!("0.0.0.0:{}", port))?
     .run()
-    .await
+    .await;
+
+    if let Some(mock) = mock_sefaz {
+        mock.stop().await;
+    }
+    server
 }
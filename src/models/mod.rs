@@ -1 +1,23 @@
+pub mod nfe_access_key;
+pub mod nfe_additional;
+pub mod nfe_address;
+pub mod nfe_contingency;
+pub mod nfe_distribution;
+pub mod nfe_document;
+pub mod nfe_emitter;
+pub mod nfe_event;
 pub mod nfe_identification;
+pub mod nfe_import;
+pub mod nfe_inutilization;
+pub mod nfe_item;
+pub mod nfe_item_specific;
+pub mod nfe_item_tax;
+pub mod nfe_numbering;
+pub mod nfe_payment;
+pub mod nfe_protocol;
+pub mod nfe_recipient;
+pub mod nfe_reference;
+pub mod nfe_status;
+pub mod nfe_total;
+pub mod nfe_transport;
+pub mod validation;
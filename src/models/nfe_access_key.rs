@@ -0,0 +1,327 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_identification::uf_offset;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt;
+
+/// Length of `chNFe`.
+pub const ACCESS_KEY_LEN: usize = 44;
+
+/// IBGE codes accepted in `cUF`.
+pub const UF_CODES: [&str; 27] = [
+    "11", "12", "13", "14", "15", "16", "17", "21", "22", "23", "24", "25", "26", "27", "28", "29",
+    "31", "32", "33", "35", "41", "42", "43", "50", "51", "52", "53",
+];
+
+/// Fields of the access key before the check digit is appended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessKeyParts {
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    /// Year and month of `dhEmi`.
+    #[serde(rename = "AAMM")]
+    pub aamm: String,
+    /// Emitter CNPJ, or CPF left-padded with zeros to 14 digits.
+    #[serde(rename = "CNPJ")]
+    pub document: String,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: String,
+    #[serde(rename = "nNF")]
+    pub n_nf: String,
+    #[serde(rename = "tpEmis")]
+    pub tp_emis: String,
+    #[serde(rename = "cNF")]
+    pub c_nf: String,
+}
+
+/// 44-digit access key (`chNFe`):
+/// cUF(2) AAMM(4) CNPJ/CPF(14) mod(2) serie(3) nNF(9) tpEmis(1) cNF(8) cDV(1).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AccessKey {
+    #[serde(rename = "chNFe")]
+    pub key: String,
+    #[serde(flatten)]
+    pub parts: AccessKeyParts,
+    #[serde(rename = "cDV")]
+    pub c_dv: String,
+}
+
+pub fn is_digits(value: &str, min: usize, max: usize) -> bool {
+    (min..=max).contains(&value.len()) && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Checks the `ide` fields that make up the access key. `n_nf` is `None` while the
+/// number is still to be allocated.
+pub fn validate_key_fields(
+    c_uf: &str,
+    c_nf: &str,
+    mod_: &str,
+    serie: &str,
+    n_nf: Option<&str>,
+    tp_emis: &str,
+) -> Result<(), ValidationError> {
+    if !UF_CODES.contains(&c_uf) {
+        return Err(ValidationError::new("cUF", "must be an IBGE state code"));
+    }
+    if !is_digits(c_nf, 8, 8) {
+        return Err(ValidationError::new("cNF", "must have 8 digits"));
+    }
+    if !matches!(mod_, "55" | "65") {
+        return Err(ValidationError::new("mod", "must be 55 or 65"));
+    }
+    if !is_digits(serie, 1, 3) {
+        return Err(ValidationError::new(
+            "serie",
+            "must have between 1 and 3 digits",
+        ));
+    }
+    if let Some(n_nf) = n_nf {
+        if !is_digits(n_nf, 1, 9) || n_nf.chars().all(|c| c == '0') {
+            return Err(ValidationError::new(
+                "nNF",
+                "must be a number between 1 and 999999999",
+            ));
+        }
+    }
+    if !matches!(tp_emis, "1" | "2" | "3" | "4" | "5" | "6" | "7" | "9") {
+        return Err(ValidationError::new(
+            "tpEmis",
+            "must be 1, 2, 3, 4, 5, 6, 7 or 9",
+        ));
+    }
+    match n_nf {
+        Some(n_nf) => validate_c_nf(c_nf, n_nf),
+        None => Ok(()),
+    }
+}
+
+/// NT 2019.001: cNF must not repeat nNF, so the key cannot be guessed from the number.
+pub fn validate_c_nf(c_nf: &str, n_nf: &str) -> Result<(), ValidationError> {
+    if c_nf.parse::<u64>().ok() == n_nf.parse::<u64>().ok() {
+        return Err(ValidationError::new("cNF", "must differ from nNF"));
+    }
+    Ok(())
+}
+
+/// `AAMM` of the key: year and month of `dhEmi` in the local time of the issuing state.
+pub fn aamm(c_uf: &str, dh_emi: &DateTime<Utc>) -> String {
+    dh_emi
+        .with_timezone(&uf_offset(c_uf))
+        .format("%y%m")
+        .to_string()
+}
+
+/// Modulo 11 check digit over the first 43 digits, with weights 2 to 9 from the right.
+pub fn check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .zip((2..=9).cycle())
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    match sum % 11 {
+        0 | 1 => 0,
+        rest => 11 - rest,
+    }
+}
+
+impl AccessKey {
+    /// Builds the key, zero-padding serie, nNF and a CPF document and computing `cDV`.
+    pub fn new(parts: AccessKeyParts) -> Result<Self, ValidationError> {
+        validate_key_fields(
+            &parts.c_uf,
+            &parts.c_nf,
+            &parts.mod_,
+            &parts.serie,
+            Some(&parts.n_nf),
+            &parts.tp_emis,
+        )?;
+        let month = parts.aamm.get(2..).and_then(|m| m.parse::<u32>().ok());
+        if !is_digits(&parts.aamm, 4, 4) || !matches!(month, Some(1..=12)) {
+            return Err(ValidationError::new(
+                "AAMM",
+                "must be a valid year and month",
+            ));
+        }
+        if !is_digits(&parts.document, 11, 11) && !is_digits(&parts.document, 14, 14) {
+            return Err(ValidationError::new(
+                "CNPJ",
+                "emitter must have a 14-digit CNPJ or an 11-digit CPF",
+            ));
+        }
+
+        let parts = AccessKeyParts {
+            document: format!("{:0>14}", parts.document),
+            serie: format!("{:0>3}", parts.serie),
+            n_nf: format!("{:0>9}", parts.n_nf),
+            ..parts
+        };
+        let body = format!(
+            "{}{}{}{}{}{}{}{}",
+            parts.c_uf,
+            parts.aamm,
+            parts.document,
+            parts.mod_,
+            parts.serie,
+            parts.n_nf,
+            parts.tp_emis,
+            parts.c_nf
+        );
+        let c_dv = check_digit(&body).to_string();
+        Ok(Self {
+            key: format!("{}{}", body, c_dv),
+            parts,
+            c_dv,
+        })
+    }
+
+    /// Splits a 44-digit key back into its parts, rejecting a wrong check digit. Only
+    /// the format, `cUF` and `cDV` are checked: keys SEFAZ already authorized, of other
+    /// models or older than the issuing rules in `new`, must still parse.
+    pub fn parse(key: &str) -> Result<Self, ValidationError> {
+        if !is_digits(key, ACCESS_KEY_LEN, ACCESS_KEY_LEN) {
+            return Err(ValidationError::new("chNFe", "must have 44 digits"));
+        }
+        if !UF_CODES.contains(&&key[0..2]) {
+            return Err(ValidationError::new("cUF", "must be an IBGE state code"));
+        }
+        let expected = check_digit(&key[..43]).to_string();
+        if key[43..] != expected {
+            return Err(ValidationError::new(
+                "chNFe",
+                format!("invalid check digit, expected {}", expected),
+            ));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            parts: AccessKeyParts {
+                c_uf: key[0..2].to_string(),
+                aamm: key[2..6].to_string(),
+                document: key[6..20].to_string(),
+                mod_: key[20..22].to_string(),
+                serie: key[22..25].to_string(),
+                n_nf: key[25..34].to_string(),
+                tp_emis: key[34..35].to_string(),
+                c_nf: key[35..43].to_string(),
+            },
+            c_dv: expected,
+        })
+    }
+}
+
+impl fmt::Display for AccessKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts() -> AccessKeyParts {
+        AccessKeyParts {
+            c_uf: "35".to_string(),
+            aamm: "2403".to_string(),
+            document: "12345678000195".to_string(),
+            mod_: "55".to_string(),
+            serie: "1".to_string(),
+            n_nf: "123".to_string(),
+            tp_emis: "1".to_string(),
+            c_nf: "87654321".to_string(),
+        }
+    }
+
+    #[test]
+    fn computes_check_digit() {
+        assert_eq!(
+            check_digit("5206043300991100250655012000000780026730161"),
+            5
+        );
+        // Remainders 0 and 1 both yield 0.
+        assert_eq!(
+            check_digit("0000000000000000000000000000000000000000000"),
+            0
+        );
+    }
+
+    #[test]
+    fn builds_and_parses_key() {
+        let key = AccessKey::new(parts()).unwrap();
+        assert_eq!(key.key.len(), ACCESS_KEY_LEN);
+        assert!(key
+            .key
+            .starts_with("35240312345678000195550010000001231876543"));
+        assert_eq!(AccessKey::parse(&key.key).unwrap(), key);
+    }
+
+    #[test]
+    fn parses_authorized_keys_outside_the_issuing_rules() {
+        // cNF equal to nNF, as issued before NT 2019.001, and a model this API does not issue.
+        for body in [
+            concat!(
+                "35",
+                "1903",
+                "12345678000195",
+                "55",
+                "001",
+                "000000123",
+                "1",
+                "00000123"
+            ),
+            concat!(
+                "35",
+                "1903",
+                "12345678000195",
+                "57",
+                "001",
+                "000000123",
+                "1",
+                "87654321"
+            ),
+        ] {
+            let key = format!("{}{}", body, check_digit(body));
+            let parsed = AccessKey::parse(&key).unwrap();
+            assert_eq!(parsed.key, key);
+            assert_eq!(parsed.parts.n_nf, "000000123");
+            assert_eq!(parsed.c_dv, key[43..]);
+        }
+    }
+
+    #[test]
+    fn pads_cpf_emitters() {
+        let key = AccessKey::new(AccessKeyParts {
+            document: "12345678909".to_string(),
+            ..parts()
+        })
+        .unwrap();
+        assert_eq!(key.parts.document, "00012345678909");
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let key = AccessKey::new(parts()).unwrap().key;
+        let wrong_dv = format!(
+            "{}{}",
+            &key[..43],
+            (key[43..].parse::<u32>().unwrap() + 1) % 10
+        );
+        assert_eq!(AccessKey::parse(&wrong_dv).unwrap_err().field, "chNFe");
+        assert_eq!(AccessKey::parse("123").unwrap_err().field, "chNFe");
+
+        let err = AccessKey::new(AccessKeyParts {
+            c_nf: "00000123".to_string(),
+            ..parts()
+        })
+        .unwrap_err();
+        assert_eq!(err.field, "cNF");
+        let err = AccessKey::new(AccessKeyParts {
+            aamm: "2413".to_string(),
+            ..parts()
+        })
+        .unwrap_err();
+        assert_eq!(err.field, "AAMM");
+    }
+}
@@ -0,0 +1,221 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_address::validate_document;
+use crate::models::validation::{validate_optional_text, validate_text};
+use serde::{Deserialize, Serialize};
+
+/// `obsCont` and `obsFisco` groups of a note.
+pub const MAX_OBSERVATIONS: usize = 10;
+/// `procRef` groups of a note.
+pub const MAX_PROCESSES: usize = 100;
+/// `autXML` groups of a note.
+pub const MAX_AUTHORIZED: usize = 10;
+
+/// `infAdic`: free text for the tax authority and the taxpayer, tagged observations and
+/// the processes or acts the note refers to.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeAdditionalInfo {
+    #[serde(rename = "infAdFisco")]
+    pub inf_ad_fisco: Option<String>,
+    #[serde(rename = "infCpl")]
+    pub inf_cpl: Option<String>,
+    #[serde(rename = "obsCont", default)]
+    pub obs_cont: Vec<NFeObservation>,
+    #[serde(rename = "obsFisco", default)]
+    pub obs_fisco: Vec<NFeObservation>,
+    #[serde(rename = "procRef", default)]
+    pub proc_ref: Vec<NFeProcessReference>,
+}
+
+/// `obsCont` / `obsFisco`: `xTexto` under the name given in the `xCampo` attribute.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeObservation {
+    #[serde(rename = "xCampo")]
+    pub x_campo: String,
+    #[serde(rename = "xTexto")]
+    pub x_texto: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeProcessReference {
+    #[serde(rename = "nProc")]
+    pub n_proc: String,
+    /// 0 = SEFAZ, 1 = Federal Justice, 2 = State Justice, 3 = Secex/RFB, 4 = CONFAZ,
+    /// 9 = others.
+    #[serde(rename = "indProc")]
+    pub ind_proc: String,
+    /// Type of the concession act: 08 (Termo de Acordo), 10 (Regime Especial), 12
+    /// (Autorização específica), 14 (Ajuste SINIEF) or 15 (Convênio ICMS).
+    #[serde(rename = "tpAto")]
+    pub tp_ato: Option<String>,
+}
+
+/// `infRespTec`: the company responsible for the software that issued the note.
+/// `idCSRT` and `hashCSRT` are derived from the configured CSRT when the note is
+/// loaded, never taken from the client.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeTechResponsible {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: String,
+    #[serde(rename = "xContato")]
+    pub x_contato: String,
+    pub email: String,
+    pub fone: String,
+    #[serde(rename = "idCSRT", skip_deserializing)]
+    pub id_csrt: Option<String>,
+    #[serde(rename = "hashCSRT", skip_deserializing)]
+    pub hash_csrt: Option<String>,
+}
+
+/// `autXML`: a person authorized to download the XML of the note.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeAuthorizedDownload {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+}
+
+fn validate_observations(
+    group: &str,
+    observations: &[NFeObservation],
+) -> Result<(), ValidationError> {
+    if observations.len() > MAX_OBSERVATIONS {
+        return Err(ValidationError::new(
+            group,
+            format!("must have at most {} observations", MAX_OBSERVATIONS),
+        ));
+    }
+    for (i, observation) in observations.iter().enumerate() {
+        let group = format!("{}[{}]", group, i + 1);
+        validate_text(&format!("{}.xCampo", group), &observation.x_campo, 1, 20)?;
+        validate_text(&format!("{}.xTexto", group), &observation.x_texto, 1, 60)?;
+    }
+    Ok(())
+}
+
+impl NFeAdditionalInfo {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_optional_text("infAdic.infAdFisco", self.inf_ad_fisco.as_deref(), 1, 2000)?;
+        validate_optional_text("infAdic.infCpl", self.inf_cpl.as_deref(), 1, 5000)?;
+        validate_observations("infAdic.obsCont", &self.obs_cont)?;
+        validate_observations("infAdic.obsFisco", &self.obs_fisco)?;
+        if self.proc_ref.len() > MAX_PROCESSES {
+            return Err(ValidationError::new(
+                "infAdic.procRef",
+                format!("must have at most {} processes", MAX_PROCESSES),
+            ));
+        }
+        for (i, proc_ref) in self.proc_ref.iter().enumerate() {
+            let group = format!("infAdic.procRef[{}]", i + 1);
+            validate_text(&format!("{}.nProc", group), &proc_ref.n_proc, 1, 60)?;
+            if !matches!(
+                proc_ref.ind_proc.as_str(),
+                "0" | "1" | "2" | "3" | "4" | "9"
+            ) {
+                return Err(ValidationError::new(
+                    &format!("{}.indProc", group),
+                    "must be 0, 1, 2, 3, 4 or 9",
+                ));
+            }
+            if let Some(tp_ato) = &proc_ref.tp_ato {
+                if !matches!(tp_ato.as_str(), "08" | "10" | "12" | "14" | "15") {
+                    return Err(ValidationError::new(
+                        &format!("{}.tpAto", group),
+                        "must be 08, 10, 12, 14 or 15",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NFeTechResponsible {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_document("infRespTec", Some(&self.cnpj), None, 0)?;
+        validate_text("infRespTec.xContato", &self.x_contato, 2, 60)?;
+        if !(6..=60).contains(&self.email.len()) || !self.email.contains('@') {
+            return Err(ValidationError::new(
+                "infRespTec.email",
+                "must be an e-mail address of 6 to 60 characters",
+            ));
+        }
+        if !is_digits(&self.fone, 6, 14) {
+            return Err(ValidationError::new(
+                "infRespTec.fone",
+                "must have 6 to 14 digits",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks the `autXML` list: at most ten people, each with one document, none twice.
+pub fn validate_authorized_downloads(
+    authorized: &[NFeAuthorizedDownload],
+) -> Result<(), ValidationError> {
+    if authorized.len() > MAX_AUTHORIZED {
+        return Err(ValidationError::new(
+            "autXML",
+            format!("must have at most {} people", MAX_AUTHORIZED),
+        ));
+    }
+    for (i, person) in authorized.iter().enumerate() {
+        let group = format!("autXML[{}]", i + 1);
+        validate_document(&group, person.cnpj.as_deref(), person.cpf.as_deref(), 0)?;
+        if authorized[..i].contains(person) {
+            return Err(ValidationError::new(&group, "is already authorized"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_observations_and_authorized_people() {
+        let mut inf_adic = NFeAdditionalInfo {
+            inf_cpl: Some("Pedido 4521".to_string()),
+            obs_cont: vec![NFeObservation {
+                x_campo: "Vendedor".to_string(),
+                x_texto: "Maria".to_string(),
+            }],
+            proc_ref: vec![NFeProcessReference {
+                n_proc: "2024/0001".to_string(),
+                ind_proc: "0".to_string(),
+                tp_ato: Some("08".to_string()),
+            }],
+            ..Default::default()
+        };
+        assert!(inf_adic.validate().is_ok());
+        inf_adic.obs_cont[0].x_campo = "Um campo longo demais para o xCampo".to_string();
+        assert_eq!(
+            inf_adic.validate().unwrap_err().field,
+            "infAdic.obsCont[1].xCampo"
+        );
+
+        let person = |cnpj: &str| NFeAuthorizedDownload {
+            cnpj: Some(cnpj.to_string()),
+            cpf: None,
+        };
+        assert!(validate_authorized_downloads(&[person("11222333000181")]).is_ok());
+        assert_eq!(
+            validate_authorized_downloads(&[person("11222333000181"), person("11222333000181")])
+                .unwrap_err()
+                .field,
+            "autXML[2]"
+        );
+        let many = vec![person("11222333000181"); MAX_AUTHORIZED + 1];
+        assert_eq!(
+            validate_authorized_downloads(&many).unwrap_err().field,
+            "autXML"
+        );
+    }
+}
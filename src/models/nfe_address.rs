@@ -0,0 +1,173 @@
+use crate::errors::ValidationError;
+use serde::{Deserialize, Serialize};
+
+/// Acronyms of the states of the federation (TUfEmi). Groups that take addresses abroad
+/// also accept [`UF_ABROAD`].
+pub const UF_ACRONYMS: [&str; 27] = [
+    "AC", "AL", "AM", "AP", "BA", "CE", "DF", "ES", "GO", "MA", "MG", "MS", "MT", "PA", "PB", "PE",
+    "PI", "PR", "RJ", "RN", "RO", "RR", "RS", "SC", "SE", "SP", "TO",
+];
+
+/// `UF` of addresses abroad, accepted by `enderDest` (TUf) but not by `enderEmit` (TUfEmi).
+pub const UF_ABROAD: &str = "EX";
+
+/// `cMun` of addresses abroad.
+pub const C_MUN_ABROAD: &str = "9999999";
+
+/// Address shared by `enderEmit` (TEnderEmi) and `enderDest` (TEndereco).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeAddress {
+    #[serde(rename = "xLgr")]
+    pub x_lgr: String,
+    pub nro: String,
+    #[serde(rename = "xCpl")]
+    pub x_cpl: Option<String>,
+    #[serde(rename = "xBairro")]
+    pub x_bairro: String,
+    #[serde(rename = "cMun")]
+    pub c_mun: String,
+    #[serde(rename = "xMun")]
+    pub x_mun: String,
+    #[serde(rename = "UF")]
+    pub uf: String,
+    #[serde(rename = "CEP")]
+    pub cep: Option<String>,
+    #[serde(rename = "cPais")]
+    pub c_pais: Option<String>,
+    #[serde(rename = "xPais")]
+    pub x_pais: Option<String>,
+    pub fone: Option<String>,
+}
+
+impl NFeAddress {
+    /// Checks the address of `group`; `abroad` allows the `EX` state of TEndereco.
+    pub fn validate(&self, group: &str, abroad: bool) -> Result<(), ValidationError> {
+        if self.c_mun.chars().count() != 7 || !self.c_mun.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ValidationError::new(
+                &format!("{}.cMun", group),
+                "must be a 7-digit IBGE code",
+            ));
+        }
+        if self.uf == UF_ABROAD && abroad {
+            if self.c_mun != C_MUN_ABROAD {
+                return Err(ValidationError::new(
+                    &format!("{}.cMun", group),
+                    "must be 9999999 for addresses abroad",
+                ));
+            }
+        } else if !UF_ACRONYMS.contains(&self.uf.as_str()) {
+            return Err(ValidationError::new(
+                &format!("{}.UF", group),
+                if abroad {
+                    "must be a state acronym or EX"
+                } else {
+                    "must be a state acronym"
+                },
+            ));
+        }
+        if let Some(cep) = &self.cep {
+            if cep.chars().count() != 8 || !cep.chars().all(|c| c.is_ascii_digit()) {
+                return Err(ValidationError::new(
+                    &format!("{}.CEP", group),
+                    "must have 8 digits",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the CNPJ/CPF choice shared by `emit`, `dest` and other party groups.
+pub fn validate_document(
+    group: &str,
+    cnpj: Option<&str>,
+    cpf: Option<&str>,
+    others: usize,
+) -> Result<(), ValidationError> {
+    let informed = cnpj.is_some() as usize + cpf.is_some() as usize + others;
+    if informed != 1 {
+        return Err(ValidationError::new(
+            group,
+            "exactly one document (CNPJ, CPF or foreign id) must be informed",
+        ));
+    }
+    if let Some(cnpj) = cnpj {
+        if cnpj.len() != 14 || !cnpj.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ValidationError::new(
+                &format!("{}.CNPJ", group),
+                "must have 14 digits",
+            ));
+        }
+    }
+    if let Some(cpf) = cpf {
+        if cpf.len() != 11 || !cpf.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ValidationError::new(
+                &format!("{}.CPF", group),
+                "must have 11 digits",
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(uf: &str, c_mun: &str) -> NFeAddress {
+        NFeAddress {
+            x_lgr: "Rua das Flores".to_string(),
+            nro: "10".to_string(),
+            x_cpl: None,
+            x_bairro: "Centro".to_string(),
+            c_mun: c_mun.to_string(),
+            x_mun: "Município".to_string(),
+            uf: uf.to_string(),
+            cep: None,
+            c_pais: None,
+            x_pais: None,
+            fone: None,
+        }
+    }
+
+    #[test]
+    fn accepts_ex_only_for_addresses_abroad() {
+        assert!(address("RJ", "3304557").validate("dest", false).is_ok());
+        assert!(address("EX", "9999999").validate("dest", true).is_ok());
+        assert_eq!(
+            address("EX", "9999999")
+                .validate("emit", false)
+                .unwrap_err()
+                .field,
+            "emit.UF"
+        );
+        assert_eq!(
+            address("EX", "3304557")
+                .validate("dest", true)
+                .unwrap_err()
+                .field,
+            "dest.cMun"
+        );
+        assert_eq!(
+            address("ZZ", "3304557")
+                .validate("dest", true)
+                .unwrap_err()
+                .field,
+            "dest.UF"
+        );
+    }
+
+    #[test]
+    fn counts_codes_in_characters() {
+        assert_eq!(
+            address("SP", "３５５０３０８")
+                .validate("emit", false)
+                .unwrap_err()
+                .field,
+            "emit.cMun"
+        );
+        let mut cep = address("SP", "3550308");
+        cep.cep = Some("0131010".to_string());
+        assert_eq!(cep.validate("emit", false).unwrap_err().field, "emit.CEP");
+    }
+}
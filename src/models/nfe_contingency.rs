@@ -0,0 +1,213 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::UF_CODES;
+use crate::models::validation::validate_text;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `tpEmis` of notes issued while the authorizer is reachable.
+pub const NORMAL: &str = "1";
+/// States whose notes are authorized by SVC-RS in contingency; the others use SVC-AN.
+const SVC_RS_STATES: [&str; 8] = ["13", "21", "26", "29", "41", "50", "51", "52"];
+
+/// Virtual contingency SEFAZ of the state: `7` (SVC-RS) or `6` (SVC-AN).
+pub fn svc_mode(c_uf: &str) -> &'static str {
+    if SVC_RS_STATES.contains(&c_uf) {
+        "7"
+    } else {
+        "6"
+    }
+}
+
+/// Mode used when a contingency is switched on without one: SVC for the NF-e, which
+/// keeps notes authorized online, and offline contingency for the NFC-e, which has no
+/// SVC.
+pub fn default_mode(c_uf: &str, mod_: &str) -> &'static str {
+    if mod_ == "65" {
+        "9"
+    } else {
+        svc_mode(c_uf)
+    }
+}
+
+/// Whether notes of the mode are authorized online by a virtual contingency SEFAZ.
+/// The other modes are printed and queued until the authorizer of the state is back.
+pub fn is_svc(tp_emis: &str) -> bool {
+    matches!(tp_emis, "6" | "7")
+}
+
+/// Contingency modes of each model: FS-IA (2), FS-DA (5) and the SVC of the state for
+/// the NF-e, offline (9) for the NFC-e. EPEC (4) is not offered, since its notes are
+/// only valid once the EPEC event (110140) is registered, which is not sent.
+pub fn validate_mode(c_uf: &str, mod_: &str, tp_emis: &str) -> Result<(), ValidationError> {
+    let valid = match (mod_, tp_emis) {
+        ("55", "2" | "5") | ("65", "9") => true,
+        ("55", "6" | "7") => tp_emis == svc_mode(c_uf),
+        _ => false,
+    };
+    if !valid {
+        return Err(ValidationError::new(
+            "tpEmis",
+            format!(
+                "must be one of 2, 5 or {} for mod 55 in cUF {}, or 9 for mod 65",
+                svc_mode(c_uf),
+                c_uf
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// `dhCont` and `xJust` are required in contingency and refused otherwise.
+pub fn validate_contingency_fields(
+    tp_emis: &str,
+    dh_cont: Option<&DateTime<Utc>>,
+    x_just: Option<&str>,
+) -> Result<(), ValidationError> {
+    if tp_emis == NORMAL {
+        if dh_cont.is_some() || x_just.is_some() {
+            return Err(ValidationError::new(
+                "dhCont",
+                "dhCont and xJust are only informed in contingency",
+            ));
+        }
+        return Ok(());
+    }
+    if dh_cont.is_none() {
+        return Err(ValidationError::new(
+            "dhCont",
+            format!("is required with tpEmis {}", tp_emis),
+        ));
+    }
+    validate_text("xJust", x_just.unwrap_or_default(), 15, 256)
+}
+
+/// Request to switch the notes of a state and model to contingency.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActivateContingency {
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    /// Defaults to [`default_mode`].
+    #[serde(rename = "tpEmis", default)]
+    pub tp_emis: Option<String>,
+    #[serde(rename = "xJust")]
+    pub x_just: String,
+}
+
+impl ActivateContingency {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !UF_CODES.contains(&self.c_uf.as_str()) {
+            return Err(ValidationError::new("cUF", "must be an IBGE state code"));
+        }
+        if !matches!(self.mod_.as_str(), "55" | "65") {
+            return Err(ValidationError::new("mod", "must be 55 or 65"));
+        }
+        validate_mode(&self.c_uf, &self.mod_, self.tp_emis())?;
+        validate_text("xJust", &self.x_just, 15, 256)
+    }
+
+    pub fn tp_emis(&self) -> &str {
+        self.tp_emis
+            .as_deref()
+            .unwrap_or_else(|| default_mode(&self.c_uf, &self.mod_))
+    }
+}
+
+/// A period in which the notes of a state and model are issued in contingency. It is
+/// active while `dhEnd` is empty.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeContingency {
+    pub id: String,
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    #[serde(rename = "tpEmis")]
+    pub tp_emis: String,
+    #[serde(rename = "dhCont")]
+    pub dh_cont: DateTime<Utc>,
+    #[serde(rename = "xJust")]
+    pub x_just: String,
+    /// Switched on after transmission failures rather than by a user; it ends by itself
+    /// once the status service of the authorizer answers again.
+    pub automatic: bool,
+    #[serde(rename = "dhEnd")]
+    pub dh_end: Option<DateTime<Utc>>,
+}
+
+/// A note issued in offline contingency, kept in the transmission queue with the result
+/// of its latest attempt.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeQueuedNote {
+    pub internal_key: String,
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    #[serde(rename = "tpEmis")]
+    pub tp_emis: String,
+    pub queued_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// When SEFAZ received the note; empty while it is waiting.
+    pub sent_at: Option<DateTime<Utc>>,
+    #[serde(rename = "cStat")]
+    pub c_stat: Option<String>,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_contingency_mode_of_each_state() {
+        assert_eq!(default_mode("35", "55"), "6");
+        assert_eq!(default_mode("29", "55"), "7");
+        assert_eq!(default_mode("35", "65"), "9");
+        assert!(validate_mode("35", "55", "5").is_ok());
+        assert!(validate_mode("35", "55", "4").is_err());
+        assert!(validate_mode("29", "55", "7").is_ok());
+        assert!(validate_mode("35", "55", "7").is_err());
+        assert!(validate_mode("35", "55", "9").is_err());
+        assert!(validate_mode("35", "65", "6").is_err());
+        assert!(validate_mode("35", "55", "1").is_err());
+
+        let request = ActivateContingency {
+            c_uf: "41".to_string(),
+            mod_: "55".to_string(),
+            tp_emis: None,
+            x_just: "SEFAZ fora do ar desde as 10h".to_string(),
+        };
+        assert!(request.validate().is_ok());
+        assert_eq!(request.tp_emis(), "7");
+        let short = ActivateContingency {
+            x_just: "fora do ar".to_string(),
+            ..request
+        };
+        assert_eq!(short.validate().unwrap_err().field, "xJust");
+    }
+
+    #[test]
+    fn requires_dh_cont_and_x_just_only_in_contingency() {
+        let now = Utc::now();
+        let x_just = Some("SEFAZ fora do ar desde as 10h");
+        assert!(validate_contingency_fields("1", None, None).is_ok());
+        assert!(validate_contingency_fields("1", Some(&now), x_just).is_err());
+        assert!(validate_contingency_fields("6", Some(&now), x_just).is_ok());
+        assert_eq!(
+            validate_contingency_fields("9", None, x_just)
+                .unwrap_err()
+                .field,
+            "dhCont"
+        );
+        assert_eq!(
+            validate_contingency_fields("9", Some(&now), None)
+                .unwrap_err()
+                .field,
+            "xJust"
+        );
+    }
+}
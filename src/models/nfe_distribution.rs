@@ -0,0 +1,62 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_address::validate_document;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// CNPJ whose documents are read from NFeDistribuicaoDFe.
+pub fn validate_cnpj(cnpj: &str) -> Result<(), ValidationError> {
+    validate_document("distDFeInt", Some(cnpj), None, 0)
+}
+
+/// A document the Ambiente Nacional distributed to one of our CNPJs. Summaries carry
+/// the fields of `resNFe`; full notes carry the outcome of their import.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeDistributedDocument {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: String,
+    #[serde(rename = "NSU")]
+    pub nsu: u64,
+    /// Schema of the document, e.g. `resNFe_v1.01.xsd` or `procNFe_v4.00.xsd`.
+    pub schema: String,
+    #[serde(rename = "chNFe")]
+    pub ch_nfe: Option<String>,
+    /// CNPJ or CPF of the emitter.
+    pub emit_doc: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: Option<String>,
+    #[serde(rename = "dhEmi")]
+    pub dh_emi: Option<DateTime<Utc>>,
+    #[serde(rename = "vNF")]
+    pub v_nf: Option<Decimal>,
+    #[serde(rename = "cSitNFe")]
+    pub c_sit_nfe: Option<String>,
+    #[serde(rename = "nProt")]
+    pub n_prot: Option<String>,
+    /// The imported note, for `procNFe` documents.
+    pub internal_key: Option<String>,
+    pub import_status: Option<String>,
+    pub reason: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+/// Outcome of reading the new documents of a CNPJ.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DistributionReport {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: String,
+    #[serde(rename = "ultNSU")]
+    pub ult_nsu: u64,
+    #[serde(rename = "maxNSU")]
+    pub max_nsu: u64,
+    /// Documents stored in this run.
+    pub received: usize,
+    /// Full notes inserted through the XML import.
+    pub imported: usize,
+    #[serde(rename = "cStat")]
+    pub c_stat: String,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: String,
+    /// SEFAZ refuses queries of the CNPJ before this time.
+    pub next_query_at: Option<DateTime<Utc>>,
+}
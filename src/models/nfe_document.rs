@@ -0,0 +1,290 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::{aamm, AccessKey, AccessKeyParts};
+use crate::models::nfe_additional::{NFeAdditionalInfo, NFeAuthorizedDownload, NFeTechResponsible};
+use crate::models::nfe_emitter::NFeEmitter;
+use crate::models::nfe_identification::NFeIdentification;
+use crate::models::nfe_item::NFeItem;
+use crate::models::nfe_payment::{NFeBilling, NFePayment};
+use crate::models::nfe_recipient::NFeRecipient;
+use crate::models::nfe_reference::{validate_references, NFeReference, RETURN};
+use crate::models::nfe_total::NFeTotal;
+use crate::models::nfe_transport::NFeTransport;
+use serde::Serialize;
+
+/// A note with every stored group, as needed to build its XML.
+#[derive(Debug, Clone, Serialize)]
+pub struct NFeDocument {
+    pub identification: NFeIdentification,
+    pub emitter: Option<NFeEmitter>,
+    pub recipient: Option<NFeRecipient>,
+    /// `NFref` groups of `ide`.
+    pub references: Vec<NFeReference>,
+    pub items: Vec<NFeItem>,
+    pub total: NFeTotal,
+    pub transport: NFeTransport,
+    pub billing: NFeBilling,
+    pub payment: NFePayment,
+    /// `autXML` groups, written after `dest`.
+    pub authorized_downloads: Vec<NFeAuthorizedDownload>,
+    pub additional_info: NFeAdditionalInfo,
+    pub tech_responsible: Option<NFeTechResponsible>,
+}
+
+impl NFeDocument {
+    pub fn emitter(&self) -> Result<&NFeEmitter, ValidationError> {
+        self.emitter
+            .as_ref()
+            .ok_or_else(|| ValidationError::new("emit", "the note has no emitter"))
+    }
+
+    pub fn access_key(&self) -> Result<AccessKey, ValidationError> {
+        let ide = &self.identification;
+        let emitter = self.emitter()?;
+        AccessKey::new(AccessKeyParts {
+            c_uf: ide.c_uf.clone(),
+            aamm: aamm(&ide.c_uf, &ide.dh_emi),
+            document: emitter
+                .cnpj
+                .clone()
+                .or_else(|| emitter.cpf.clone())
+                .unwrap_or_default(),
+            mod_: ide.mod_.clone(),
+            serie: ide.serie.clone(),
+            n_nf: ide.n_nf.clone(),
+            tp_emis: ide.tp_emis.clone(),
+            c_nf: ide.c_nf.clone(),
+        })
+    }
+
+    /// Rules that relate the groups of the note. Each group is checked against the
+    /// others when it is written, but a later change to another group can break them,
+    /// so they are checked again before the note is validated.
+    pub fn validate_groups(&self) -> Result<(), ValidationError> {
+        let ide = &self.identification;
+        validate_references(&ide.fin_nfe, &self.references)?;
+        if ide.fin_nfe != RETURN {
+            if let Some(item) = self.items.iter().find(|item| item.imposto_devol.is_some()) {
+                return Err(ValidationError::new(
+                    &format!("det[{}].impostoDevol", item.n_item),
+                    "is only informed by returns (finNFe 4)",
+                ));
+            }
+        }
+        if self.transport != NFeTransport::default() {
+            self.transport.validate(&ide.mod_, &ide.id_dest)?;
+        }
+        self.billing.validate(&ide.mod_)?;
+        self.payment.validate(self.total.icms_tot.v_nf)
+    }
+}
+
+#[cfg(test)]
+impl NFeDocument {
+    /// A complete note from São Paulo with one taxed item, shared by the XML tests.
+    pub fn sample() -> Self {
+        use crate::models::nfe_address::NFeAddress;
+        use crate::models::nfe_item::NFeProduct;
+        use crate::models::nfe_item_tax::{NFeCofins, NFeIcms, NFeItemTaxes, NFePis};
+        use crate::services::tax::totals;
+        use chrono::{TimeZone, Utc};
+        use rust_decimal_macros::dec;
+
+        let now = Utc::now();
+        let dh_emi = Utc.with_ymd_and_hms(2024, 3, 20, 13, 30, 0).unwrap();
+        let address = NFeAddress {
+            x_lgr: "Avenida Paulista".to_string(),
+            nro: "1000".to_string(),
+            x_cpl: None,
+            x_bairro: "Bela Vista".to_string(),
+            c_mun: "3550308".to_string(),
+            x_mun: "Sao Paulo".to_string(),
+            uf: "SP".to_string(),
+            cep: Some("01310100".to_string()),
+            c_pais: Some("1058".to_string()),
+            x_pais: Some("Brasil".to_string()),
+            fone: None,
+        };
+        let items = vec![NFeItem {
+            internal_key: String::new(),
+            n_item: 1,
+            prod: NFeProduct {
+                c_prod: "001".to_string(),
+                c_ean: "SEM GTIN".to_string(),
+                x_prod: "Camiseta".to_string(),
+                ncm: "61091000".to_string(),
+                cest: None,
+                cfop: "5102".to_string(),
+                u_com: "UN".to_string(),
+                q_com: dec!(2),
+                v_un_com: dec!(50),
+                v_prod: dec!(100.00),
+                c_ean_trib: "SEM GTIN".to_string(),
+                u_trib: "UN".to_string(),
+                q_trib: dec!(2),
+                v_un_trib: dec!(50),
+                v_frete: None,
+                v_seg: None,
+                v_desc: None,
+                v_outro: None,
+                ind_tot: "1".to_string(),
+                specific: Default::default(),
+            },
+            imposto: NFeItemTaxes {
+                icms: Some(NFeIcms {
+                    orig: "0".to_string(),
+                    cst: Some("00".to_string()),
+                    mod_bc: Some("3".to_string()),
+                    v_bc: Some(dec!(100.00)),
+                    p_icms: Some(dec!(18)),
+                    v_icms: Some(dec!(18.00)),
+                    ..Default::default()
+                }),
+                pis: Some(NFePis {
+                    cst: "01".to_string(),
+                    v_bc: Some(dec!(100.00)),
+                    p_pis: Some(dec!(1.65)),
+                    v_pis: Some(dec!(1.65)),
+                    ..Default::default()
+                }),
+                cofins: Some(NFeCofins {
+                    cst: "01".to_string(),
+                    v_bc: Some(dec!(100.00)),
+                    p_cofins: Some(dec!(7.6)),
+                    v_cofins: Some(dec!(7.60)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            imposto_devol: None,
+            inf_ad_prod: None,
+            created_at: now,
+            updated_at: now,
+        }];
+        let (icms_tot, issqn_tot) = totals::compute(&items, dh_emi.date_naive(), None);
+
+        Self {
+            identification: NFeIdentification {
+                internal_key: String::new(),
+                c_uf: "35".to_string(),
+                c_nf: "87654321".to_string(),
+                nat_op: "Venda de mercadoria".to_string(),
+                mod_: "55".to_string(),
+                serie: "1".to_string(),
+                n_nf: "123".to_string(),
+                dh_emi,
+                dh_sai_ent: None,
+                dh_cont: None,
+                tp_nf: "1".to_string(),
+                id_dest: "1".to_string(),
+                c_mun_fg: "3550308".to_string(),
+                tp_imp: "1".to_string(),
+                tp_emis: "1".to_string(),
+                c_dv: String::new(),
+                tp_amb: "1".to_string(),
+                fin_nfe: "1".to_string(),
+                ind_final: "0".to_string(),
+                ind_pres: "1".to_string(),
+                proc_emi: "0".to_string(),
+                ver_proc: "1.0".to_string(),
+                x_justificativa: None,
+                status: Default::default(),
+                created_at: now,
+                updated_at: now,
+            },
+            emitter: Some(NFeEmitter {
+                internal_key: String::new(),
+                cnpj: Some("12345678000195".to_string()),
+                cpf: None,
+                x_nome: "Loja Exemplo Ltda".to_string(),
+                x_fant: None,
+                ender_emit: address.clone(),
+                ie: "111111111111".to_string(),
+                iest: None,
+                im: None,
+                cnae: None,
+                crt: "3".to_string(),
+                created_at: now,
+                updated_at: now,
+            }),
+            recipient: Some(NFeRecipient {
+                internal_key: String::new(),
+                cnpj: Some("98765432000198".to_string()),
+                cpf: None,
+                id_estrangeiro: None,
+                x_nome: Some("Cliente & Filhos".to_string()),
+                ender_dest: Some(address),
+                ind_ie_dest: "9".to_string(),
+                ie: None,
+                isuf: None,
+                im: None,
+                email: None,
+                created_at: now,
+                updated_at: now,
+            }),
+            references: Vec::new(),
+            items,
+            total: NFeTotal {
+                icms_tot,
+                issqn_tot,
+                ret_trib: None,
+            },
+            transport: NFeTransport::default(),
+            billing: NFeBilling::default(),
+            payment: NFePayment::default(),
+            authorized_downloads: Vec::new(),
+            additional_info: NFeAdditionalInfo::default(),
+            tech_responsible: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_payment::NFePaymentDetail;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn checks_the_payments_against_the_items() {
+        let mut document = NFeDocument::sample();
+        assert!(document.validate_groups().is_ok());
+
+        // Paid in full when pag was written, before the item price went up.
+        document.payment = NFePayment {
+            det_pag: vec![NFePaymentDetail {
+                t_pag: "01".to_string(),
+                v_pag: dec!(50.00),
+                ..NFePayment::default().det_pag[0].clone()
+            }],
+            v_troco: None,
+        };
+        assert_eq!(document.validate_groups().unwrap_err().field, "pag.detPag");
+        document.payment.det_pag[0].v_pag = document.total.icms_tot.v_nf;
+        assert!(document.validate_groups().is_ok());
+    }
+
+    #[test]
+    fn requires_references_of_returns() {
+        let mut document = NFeDocument::sample();
+        // finNFe changed to a return after the note was written without NFref.
+        document.identification.fin_nfe = "4".to_string();
+        assert_eq!(document.validate_groups().unwrap_err().field, "NFref");
+    }
+
+    #[test]
+    fn accepts_returned_ipi_only_in_returns() {
+        use crate::models::nfe_item::{NFeReturnedIpi, NFeReturnedTax};
+
+        let mut document = NFeDocument::sample();
+        document.items[0].imposto_devol = Some(NFeReturnedTax {
+            p_devol: dec!(100.00),
+            ipi: NFeReturnedIpi {
+                v_ipi_devol: dec!(5.00),
+            },
+        });
+        assert_eq!(
+            document.validate_groups().unwrap_err().field,
+            "det[1].impostoDevol"
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_address::{validate_document, NFeAddress};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `emit` group: the issuer of the note, one per identification.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeEmitter {
+    pub internal_key: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: String,
+    #[serde(rename = "xFant")]
+    pub x_fant: Option<String>,
+    #[serde(rename = "enderEmit")]
+    pub ender_emit: NFeAddress,
+    #[serde(rename = "IE")]
+    pub ie: String,
+    #[serde(rename = "IEST")]
+    pub iest: Option<String>,
+    #[serde(rename = "IM")]
+    pub im: Option<String>,
+    #[serde(rename = "CNAE")]
+    pub cnae: Option<String>,
+    #[serde(rename = "CRT")]
+    pub crt: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNFeEmitter {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: String,
+    #[serde(rename = "xFant")]
+    pub x_fant: Option<String>,
+    #[serde(rename = "enderEmit")]
+    pub ender_emit: NFeAddress,
+    #[serde(rename = "IE")]
+    pub ie: String,
+    #[serde(rename = "IEST")]
+    pub iest: Option<String>,
+    #[serde(rename = "IM")]
+    pub im: Option<String>,
+    #[serde(rename = "CNAE")]
+    pub cnae: Option<String>,
+    #[serde(rename = "CRT")]
+    pub crt: String,
+}
+
+impl CreateNFeEmitter {
+    pub fn document(&self) -> Option<&str> {
+        self.cnpj.as_deref().or(self.cpf.as_deref())
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_document("emit", self.cnpj.as_deref(), self.cpf.as_deref(), 0)?;
+        if !(2..=60).contains(&self.x_nome.chars().count()) {
+            return Err(ValidationError::new(
+                "emit.xNome",
+                "must have between 2 and 60 characters",
+            ));
+        }
+        if !matches!(self.crt.as_str(), "1" | "2" | "3" | "4") {
+            return Err(ValidationError::new("emit.CRT", "must be 1, 2, 3 or 4"));
+        }
+        if self.cnae.is_some() && self.im.is_none() {
+            return Err(ValidationError::new(
+                "emit.CNAE",
+                "may only be informed together with IM",
+            ));
+        }
+        self.ender_emit.validate("emit.enderEmit", false)?;
+        if self.ender_emit.cep.is_none() {
+            return Err(ValidationError::new("emit.enderEmit.CEP", "is required"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emitter() -> CreateNFeEmitter {
+        CreateNFeEmitter {
+            cnpj: Some("12345678000195".to_string()),
+            cpf: None,
+            x_nome: "Comércio de Roupas Ltda".to_string(),
+            x_fant: None,
+            ender_emit: NFeAddress {
+                x_lgr: "Avenida Paulista".to_string(),
+                nro: "1000".to_string(),
+                x_cpl: None,
+                x_bairro: "Bela Vista".to_string(),
+                c_mun: "3550308".to_string(),
+                x_mun: "São Paulo".to_string(),
+                uf: "SP".to_string(),
+                cep: Some("01310100".to_string()),
+                c_pais: Some("1058".to_string()),
+                x_pais: Some("Brasil".to_string()),
+                fone: None,
+            },
+            ie: "123456789012".to_string(),
+            iest: None,
+            im: None,
+            cnae: None,
+            crt: "3".to_string(),
+        }
+    }
+
+    #[test]
+    fn counts_the_name_in_characters() {
+        let mut emit = emitter();
+        emit.x_nome = "Ç".repeat(60);
+        assert!(emit.validate().is_ok());
+        emit.x_nome = "Ç".repeat(61);
+        assert_eq!(emit.validate().unwrap_err().field, "emit.xNome");
+        emit.x_nome = "É".to_string();
+        assert_eq!(emit.validate().unwrap_err().field, "emit.xNome");
+    }
+
+    #[test]
+    fn requires_a_state_of_the_federation() {
+        let mut emit = emitter();
+        for uf in ["XX", "sp", "EX", "SPA"] {
+            emit.ender_emit.uf = uf.to_string();
+            assert_eq!(
+                emit.validate().unwrap_err().field,
+                "emit.enderEmit.UF",
+                "{}",
+                uf
+            );
+        }
+        emit.ender_emit.uf = "EX".to_string();
+        emit.ender_emit.c_mun = "9999999".to_string();
+        assert_eq!(emit.validate().unwrap_err().field, "emit.enderEmit.UF");
+    }
+}
@@ -0,0 +1,98 @@
+use crate::errors::ValidationError;
+use crate::models::validation::validate_text;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `tpEvento` of the cancellation.
+pub const CANCELLATION: &str = "110111";
+/// `tpEvento` of the correction letter (CC-e).
+pub const CORRECTION: &str = "110110";
+/// Correction letters a note can have, numbered by `nSeqEvento`.
+pub const MAX_CORRECTIONS: u32 = 20;
+/// `xCondUso` of the CC-e, fixed by the layout.
+pub const CONDITIONS_OF_USE: &str = "A Carta de Correcao e disciplinada pelo paragrafo 1o-A do art. 7o do Convenio S/N, de 15 de dezembro de 1970 e pode ser utilizada para regularizacao de erro ocorrido na emissao de documento fiscal, desde que o erro nao esteja relacionado com: I - as variaveis que determinam o valor do imposto tais como: base de calculo, aliquota, diferenca de preco, quantidade, valor da operacao ou da prestacao; II - a correcao de dados cadastrais que implique mudanca do remetente ou do destinatario; III - a data de emissao ou de saida.";
+/// Registered and linked to the note, registered without the link, and cancellation
+/// registered after the deadline.
+pub const REGISTERED: [&str; 3] = ["135", "136", "155"];
+
+/// An event sent for a note and the answer of SEFAZ, registered or not.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeEvent {
+    pub internal_key: String,
+    #[serde(rename = "tpEvento")]
+    pub tp_evento: String,
+    #[serde(rename = "nSeqEvento")]
+    pub n_seq_evento: u32,
+    #[serde(rename = "chNFe")]
+    pub ch_nfe: String,
+    #[serde(rename = "dhEvento")]
+    pub dh_evento: DateTime<Utc>,
+    /// Justification or correction text of the event.
+    pub detail: Option<String>,
+    #[serde(rename = "cStat")]
+    pub c_stat: String,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: String,
+    #[serde(rename = "nProt")]
+    pub n_prot: Option<String>,
+    #[serde(rename = "dhRegEvento")]
+    pub dh_reg_evento: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl NFeEvent {
+    pub fn is_registered(&self) -> bool {
+        REGISTERED.contains(&self.c_stat.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelNFe {
+    #[serde(rename = "xJust")]
+    pub x_just: String,
+}
+
+impl CancelNFe {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_text("xJust", &self.x_just, 15, 255)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CorrectNFe {
+    #[serde(rename = "xCorrecao")]
+    pub x_correcao: String,
+}
+
+impl CorrectNFe {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_text("xCorrecao", &self.x_correcao, 15, 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_event_texts() {
+        let cancel = |x_just: &str| CancelNFe {
+            x_just: x_just.to_string(),
+        };
+        assert!(cancel("Pedido cancelado").validate().is_ok());
+        assert!(cancel(&"ç".repeat(255)).validate().is_ok());
+
+        let short = cancel("  Cancelado     ").validate().unwrap_err();
+        assert_eq!(short.field, "xJust");
+        assert!(cancel(&"a".repeat(256)).validate().is_err());
+
+        let correct = |x_correcao: &str| CorrectNFe {
+            x_correcao: x_correcao.to_string(),
+        };
+        assert!(correct(&"a".repeat(1000)).validate().is_ok());
+        assert_eq!(
+            correct(&"a".repeat(1001)).validate().unwrap_err().field,
+            "xCorrecao"
+        );
+    }
+}
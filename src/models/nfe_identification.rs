@@ -1,4 +1,9 @@
-use chrono::{DateTime, Utc};
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::validate_key_fields;
+use crate::models::nfe_address::validate_document;
+use crate::models::nfe_contingency::validate_contingency_fields;
+use crate::models::nfe_status::NFeStatus;
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -30,6 +35,7 @@ pub struct NFeIdentification {
     pub tp_imp: String,
     #[serde(rename = "tpEmis")]
     pub tp_emis: String,
+    /// Check digit of the access key, filled once the emitter is known.
     #[serde(rename = "cDV")]
     pub c_dv: String,
     #[serde(rename = "tpAmb")]
@@ -44,7 +50,12 @@ pub struct NFeIdentification {
     pub proc_emi: String,
     #[serde(rename = "verProc")]
     pub ver_proc: String,
+    /// Justification of the contingency, informed with `dhCont`.
+    #[serde(rename = "xJust", alias = "x_justificativa")]
     pub x_justificativa: Option<String>,
+    /// Changed only through the validate, sign and transmit endpoints.
+    #[serde(default)]
+    pub status: NFeStatus,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -59,8 +70,15 @@ pub struct CreateNFeIdentification {
     pub nat_op: String,
     pub mod_: String,
     pub serie: String,
-    #[serde(rename = "nNF")]
-    pub n_nf: String,
+    /// Allocated from the serie of the emitter when omitted.
+    #[serde(rename = "nNF", default)]
+    pub n_nf: Option<String>,
+    /// Emitter document, stored before the emitter so the unique numbering index covers
+    /// the note; required even when `nNF` is informed.
+    #[serde(rename = "emitCNPJ", default)]
+    pub emit_cnpj: Option<String>,
+    #[serde(rename = "emitCPF", default)]
+    pub emit_cpf: Option<String>,
     #[serde(rename = "dhEmi")]
     pub dh_emi: DateTime<Utc>,
     #[serde(rename = "dhSaiEnt")]
@@ -77,8 +95,6 @@ pub struct CreateNFeIdentification {
     pub tp_imp: String,
     #[serde(rename = "tpEmis")]
     pub tp_emis: String,
-    #[serde(rename = "cDV")]
-    pub c_dv: String,
     #[serde(rename = "tpAmb")]
     pub tp_amb: String,
     #[serde(rename = "finNFe")]
@@ -91,4 +107,191 @@ pub struct CreateNFeIdentification {
     pub proc_emi: String,
     #[serde(rename = "verProc")]
     pub ver_proc: String,
+    #[serde(rename = "xJust", default)]
+    pub x_justificativa: Option<String>,
+}
+
+/// UTC offset of a state (cUF). Brazil has had no daylight saving time since 2019; the
+/// islands at -02:00 and western Amazonas at -05:00 use their state's main offset.
+pub fn uf_offset(c_uf: &str) -> FixedOffset {
+    let hours = match c_uf {
+        "12" => -5,
+        "11" | "13" | "14" | "50" | "51" => -4,
+        _ => -3,
+    };
+    FixedOffset::east_opt(hours * 3600).expect("offset within one day")
+}
+
+/// Fields whose values depend on the model. The NFC-e is issued only to the final
+/// consumer in a sale inside the state, printed as a receipt and, in contingency,
+/// issued offline.
+fn validate_model_fields(
+    mod_: &str,
+    tp_nf: &str,
+    id_dest: &str,
+    tp_imp: &str,
+    tp_emis: &str,
+    ind_final: &str,
+    ind_pres: &str,
+) -> Result<(), ValidationError> {
+    if mod_ == "55" {
+        if !matches!(tp_imp, "0" | "1" | "2" | "3") {
+            return Err(ValidationError::new(
+                "tpImp",
+                "must be 0, 1, 2 or 3 for mod 55",
+            ));
+        }
+        if tp_emis == "9" {
+            return Err(ValidationError::new("tpEmis", "9 is only used by mod 65"));
+        }
+        return Ok(());
+    }
+    let rules = [
+        ("tpNF", tp_nf == "1", "must be 1 for mod 65"),
+        ("idDest", id_dest == "1", "must be 1 for mod 65"),
+        (
+            "tpImp",
+            matches!(tp_imp, "4" | "5"),
+            "must be 4 or 5 for mod 65",
+        ),
+        (
+            "tpEmis",
+            matches!(tp_emis, "1" | "9"),
+            "must be 1 or 9 for mod 65",
+        ),
+        ("indFinal", ind_final == "1", "must be 1 for mod 65"),
+        (
+            "indPres",
+            matches!(ind_pres, "1" | "4" | "5"),
+            "must be 1, 4 or 5 for mod 65",
+        ),
+    ];
+    match rules.iter().find(|(_, valid, _)| !valid) {
+        Some((field, _, message)) => Err(ValidationError::new(field, *message)),
+        None => Ok(()),
+    }
+}
+
+impl CreateNFeIdentification {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_key_fields(
+            &self.c_uf,
+            &self.c_nf,
+            &self.mod_,
+            &self.serie,
+            self.n_nf.as_deref(),
+            &self.tp_emis,
+        )?;
+        validate_model_fields(
+            &self.mod_,
+            &self.tp_nf,
+            &self.id_dest,
+            &self.tp_imp,
+            &self.tp_emis,
+            &self.ind_final,
+            &self.ind_pres,
+        )?;
+        validate_contingency_fields(
+            &self.tp_emis,
+            self.dh_cont.as_ref(),
+            self.x_justificativa.as_deref(),
+        )?;
+        match self.emit_doc() {
+            Some(_) => validate_document(
+                "emit",
+                self.emit_cnpj.as_deref(),
+                self.emit_cpf.as_deref(),
+                0,
+            ),
+            None => Err(ValidationError::new(
+                "emitCNPJ",
+                "is required (or emitCPF); nNF is unique per emitter document",
+            )),
+        }
+    }
+
+    pub fn emit_doc(&self) -> Option<&str> {
+        self.emit_cnpj.as_deref().or(self.emit_cpf.as_deref())
+    }
+}
+
+impl NFeIdentification {
+    /// A timestamp in the local time of the issuing state, as written in the XML.
+    pub fn local_time(&self, value: &DateTime<Utc>) -> DateTime<FixedOffset> {
+        value.with_timezone(&uf_offset(&self.c_uf))
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_key_fields(
+            &self.c_uf,
+            &self.c_nf,
+            &self.mod_,
+            &self.serie,
+            Some(&self.n_nf),
+            &self.tp_emis,
+        )?;
+        validate_model_fields(
+            &self.mod_,
+            &self.tp_nf,
+            &self.id_dest,
+            &self.tp_imp,
+            &self.tp_emis,
+            &self.ind_final,
+            &self.ind_pres,
+        )?;
+        validate_contingency_fields(
+            &self.tp_emis,
+            self.dh_cont.as_ref(),
+            self.x_justificativa.as_deref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_the_fields_of_each_model() {
+        assert!(validate_model_fields("55", "0", "2", "1", "1", "0", "9").is_ok());
+        assert!(validate_model_fields("55", "1", "1", "4", "1", "1", "1").is_err());
+        assert!(validate_model_fields("55", "1", "1", "1", "9", "1", "1").is_err());
+        assert!(validate_model_fields("65", "1", "1", "4", "9", "1", "1").is_ok());
+        let error = validate_model_fields("65", "1", "1", "4", "1", "0", "1").unwrap_err();
+        assert_eq!(error.field, "indFinal");
+        let error = validate_model_fields("65", "1", "1", "4", "1", "1", "2").unwrap_err();
+        assert_eq!(error.field, "indPres");
+        assert!(validate_model_fields("65", "0", "1", "4", "1", "1", "1").is_err());
+    }
+
+    #[test]
+    fn requires_the_emitter_document_even_with_n_nf() {
+        let mut identification: CreateNFeIdentification =
+            serde_json::from_value(serde_json::json!({
+                "cUF": "35",
+                "cNF": "12345678",
+                "natOp": "Venda",
+                "mod_": "55",
+                "serie": "1",
+                "nNF": "1",
+                "dhEmi": "2024-03-20T12:00:00Z",
+                "dhSaiEnt": null,
+                "dhCont": null,
+                "tpNF": "1",
+                "idDest": "1",
+                "cMunFG": "3550308",
+                "tpImp": "1",
+                "tpEmis": "1",
+                "tpAmb": "2",
+                "finNFe": "1",
+                "indFinal": "0",
+                "indPres": "1",
+                "procEmi": "0",
+                "verProc": "1.0"
+            }))
+            .unwrap();
+        assert_eq!(identification.validate().unwrap_err().field, "emitCNPJ");
+        identification.emit_cnpj = Some("11222333000181".to_string());
+        assert!(identification.validate().is_ok());
+    }
 }
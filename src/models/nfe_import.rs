@@ -0,0 +1,54 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Inserted,
+    Skipped,
+    Failed,
+}
+
+impl ImportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportStatus::Inserted => "inserted",
+            ImportStatus::Skipped => "skipped",
+            ImportStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Outcome of one file of an import.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedFile {
+    pub file: String,
+    pub status: ImportStatus,
+    #[serde(rename = "chNFe")]
+    pub access_key: Option<String>,
+    pub internal_key: Option<String>,
+    /// Authorization protocol, for `nfeProc` files.
+    #[serde(rename = "nProt")]
+    pub n_prot: Option<String>,
+    /// Subject of the certificate that signed the note, when it is signed.
+    pub signer: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub files: Vec<ImportedFile>,
+}
+
+impl ImportReport {
+    pub fn push(&mut self, file: ImportedFile) {
+        match file.status {
+            ImportStatus::Inserted => self.inserted += 1,
+            ImportStatus::Skipped => self.skipped += 1,
+            ImportStatus::Failed => self.failed += 1,
+        }
+        self.files.push(file);
+    }
+}
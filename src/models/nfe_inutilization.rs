@@ -0,0 +1,162 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::{is_digits, UF_CODES};
+use crate::models::nfe_address::validate_document;
+use crate::models::validation::validate_text;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Inutilização homologated; the numbers of the range can no longer be used.
+pub const HOMOLOGATED: &str = "102";
+/// Largest `nNF` of a serie.
+pub const MAX_N_NF: u32 = 999_999_999;
+
+/// Request to void a range of numbers skipped in a serie.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNFeInutilization {
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: String,
+    /// Year of the numbers, in two digits.
+    pub ano: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: String,
+    #[serde(rename = "nNFIni")]
+    pub n_nf_ini: u32,
+    #[serde(rename = "nNFFin")]
+    pub n_nf_fin: u32,
+    #[serde(rename = "xJust")]
+    pub x_just: String,
+}
+
+impl CreateNFeInutilization {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !UF_CODES.contains(&self.c_uf.as_str()) {
+            return Err(ValidationError::new("cUF", "must be an IBGE state code"));
+        }
+        if !matches!(self.tp_amb.as_str(), "1" | "2") {
+            return Err(ValidationError::new("tpAmb", "must be 1 or 2"));
+        }
+        if !is_digits(&self.ano, 2, 2) {
+            return Err(ValidationError::new("ano", "must have 2 digits"));
+        }
+        validate_document("inutNFe", self.cnpj.as_deref(), self.cpf.as_deref(), 0)?;
+        if !matches!(self.mod_.as_str(), "55" | "65") {
+            return Err(ValidationError::new("mod", "must be 55 or 65"));
+        }
+        if !is_digits(&self.serie, 1, 3) {
+            return Err(ValidationError::new(
+                "serie",
+                "must have between 1 and 3 digits",
+            ));
+        }
+        if self.n_nf_ini == 0 || self.n_nf_ini > MAX_N_NF {
+            return Err(ValidationError::new(
+                "nNFIni",
+                "must be a number between 1 and 999999999",
+            ));
+        }
+        if self.n_nf_fin < self.n_nf_ini || self.n_nf_fin > MAX_N_NF {
+            return Err(ValidationError::new(
+                "nNFFin",
+                "must be between nNFIni and 999999999",
+            ));
+        }
+        validate_text("xJust", &self.x_just, 15, 255)
+    }
+
+    /// The serie as sent to SEFAZ, without leading zeros.
+    pub fn serie_number(&self) -> u32 {
+        self.serie.parse().unwrap_or_default()
+    }
+}
+
+/// An inutilização sent to SEFAZ and its answer, homologated or not.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeInutilization {
+    pub id: String,
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: String,
+    pub ano: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: String,
+    #[serde(rename = "nNFIni")]
+    pub n_nf_ini: u32,
+    #[serde(rename = "nNFFin")]
+    pub n_nf_fin: u32,
+    #[serde(rename = "xJust")]
+    pub x_just: String,
+    #[serde(rename = "cStat")]
+    pub c_stat: String,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: String,
+    #[serde(rename = "nProt")]
+    pub n_prot: Option<String>,
+    #[serde(rename = "dhRecbto")]
+    pub dh_recbto: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CreateNFeInutilization {
+        CreateNFeInutilization {
+            c_uf: "35".to_string(),
+            tp_amb: "2".to_string(),
+            ano: "24".to_string(),
+            cnpj: Some("12345678000195".to_string()),
+            cpf: None,
+            mod_: "55".to_string(),
+            serie: "1".to_string(),
+            n_nf_ini: 10,
+            n_nf_fin: 12,
+            x_just: "Numeracao pulada por falha no sistema".to_string(),
+        }
+    }
+
+    #[test]
+    fn validates_the_range() {
+        assert!(request().validate().is_ok());
+        let single = CreateNFeInutilization {
+            n_nf_fin: 10,
+            ..request()
+        };
+        assert!(single.validate().is_ok());
+
+        let reversed = CreateNFeInutilization {
+            n_nf_ini: 12,
+            n_nf_fin: 10,
+            ..request()
+        };
+        assert_eq!(reversed.validate().unwrap_err().field, "nNFFin");
+        let zero = CreateNFeInutilization {
+            n_nf_ini: 0,
+            ..request()
+        };
+        assert_eq!(zero.validate().unwrap_err().field, "nNFIni");
+        let both = CreateNFeInutilization {
+            cpf: Some("12345678909".to_string()),
+            ..request()
+        };
+        assert_eq!(both.validate().unwrap_err().field, "inutNFe");
+        let year = CreateNFeInutilization {
+            ano: "2024".to_string(),
+            ..request()
+        };
+        assert_eq!(year.validate().unwrap_err().field, "ano");
+    }
+}
@@ -0,0 +1,210 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_item_specific::NFeSpecificProduct;
+use crate::models::nfe_item_tax::NFeItemTaxes;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of `det` occurrences allowed by the layout.
+pub const MAX_ITEMS: u32 = 990;
+
+/// `det` group: one line item of the note, numbered by `nItem` starting at 1.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeItem {
+    pub internal_key: String,
+    #[serde(rename = "nItem")]
+    pub n_item: u32,
+    pub prod: NFeProduct,
+    pub imposto: NFeItemTaxes,
+    #[serde(rename = "impostoDevol")]
+    pub imposto_devol: Option<NFeReturnedTax>,
+    #[serde(rename = "infAdProd")]
+    pub inf_ad_prod: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// `prod` group of a line item.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeProduct {
+    #[serde(rename = "cProd")]
+    pub c_prod: String,
+    #[serde(rename = "cEAN")]
+    pub c_ean: String,
+    #[serde(rename = "xProd")]
+    pub x_prod: String,
+    #[serde(rename = "NCM")]
+    pub ncm: String,
+    #[serde(rename = "CEST")]
+    pub cest: Option<String>,
+    #[serde(rename = "CFOP")]
+    pub cfop: String,
+    #[serde(rename = "uCom")]
+    pub u_com: String,
+    #[serde(rename = "qCom")]
+    pub q_com: Decimal,
+    #[serde(rename = "vUnCom")]
+    pub v_un_com: Decimal,
+    #[serde(rename = "vProd")]
+    pub v_prod: Decimal,
+    #[serde(rename = "cEANTrib")]
+    pub c_ean_trib: String,
+    #[serde(rename = "uTrib")]
+    pub u_trib: String,
+    #[serde(rename = "qTrib")]
+    pub q_trib: Decimal,
+    #[serde(rename = "vUnTrib")]
+    pub v_un_trib: Decimal,
+    #[serde(rename = "vFrete")]
+    pub v_frete: Option<Decimal>,
+    #[serde(rename = "vSeg")]
+    pub v_seg: Option<Decimal>,
+    #[serde(rename = "vDesc")]
+    pub v_desc: Option<Decimal>,
+    #[serde(rename = "vOutro")]
+    pub v_outro: Option<Decimal>,
+    #[serde(rename = "indTot")]
+    pub ind_tot: String,
+    /// `rastro` and the specific-product groups, at the end of `prod`.
+    #[serde(flatten)]
+    pub specific: NFeSpecificProduct,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNFeItem {
+    pub prod: NFeProduct,
+    #[serde(default)]
+    pub imposto: NFeItemTaxes,
+    #[serde(rename = "impostoDevol", default)]
+    pub imposto_devol: Option<NFeReturnedTax>,
+    #[serde(rename = "infAdProd")]
+    pub inf_ad_prod: Option<String>,
+}
+
+/// `impostoDevol` group of a return (`finNFe` 4): the share of the goods returned and
+/// the IPI returned with them, summed into `vIPIDevol`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeReturnedTax {
+    #[serde(rename = "pDevol")]
+    pub p_devol: Decimal,
+    #[serde(rename = "IPI")]
+    pub ipi: NFeReturnedIpi,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeReturnedIpi {
+    #[serde(rename = "vIPIDevol")]
+    pub v_ipi_devol: Decimal,
+}
+
+/// New order of the items, given as the current `nItem` values in their desired position.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderNFeItems {
+    pub order: Vec<u32>,
+}
+
+fn is_gtin(value: &str) -> bool {
+    value == "SEM GTIN"
+        || (value.chars().all(|c| c.is_ascii_digit())
+            && matches!(value.len(), 0 | 8 | 12 | 13 | 14))
+}
+
+fn is_digits(value: &str, len: usize) -> bool {
+    value.len() == len && value.chars().all(|c| c.is_ascii_digit())
+}
+
+impl NFeProduct {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.c_prod.is_empty() || self.c_prod.len() > 60 {
+            return Err(ValidationError::new(
+                "prod.cProd",
+                "must have between 1 and 60 characters",
+            ));
+        }
+        if self.x_prod.is_empty() || self.x_prod.len() > 120 {
+            return Err(ValidationError::new(
+                "prod.xProd",
+                "must have between 1 and 120 characters",
+            ));
+        }
+        if !is_gtin(&self.c_ean) {
+            return Err(ValidationError::new(
+                "prod.cEAN",
+                "must be a GTIN-8/12/13/14 or SEM GTIN",
+            ));
+        }
+        if !is_gtin(&self.c_ean_trib) {
+            return Err(ValidationError::new(
+                "prod.cEANTrib",
+                "must be a GTIN-8/12/13/14 or SEM GTIN",
+            ));
+        }
+        if !is_digits(&self.ncm, 2) && !is_digits(&self.ncm, 8) {
+            return Err(ValidationError::new("prod.NCM", "must have 2 or 8 digits"));
+        }
+        if let Some(cest) = &self.cest {
+            if !is_digits(cest, 7) {
+                return Err(ValidationError::new("prod.CEST", "must have 7 digits"));
+            }
+        }
+        if !is_digits(&self.cfop, 4) || !"123567".contains(&self.cfop[..1]) {
+            return Err(ValidationError::new(
+                "prod.CFOP",
+                "must have 4 digits starting with 1, 2, 3, 5, 6 or 7",
+            ));
+        }
+        if self.q_com <= Decimal::ZERO {
+            return Err(ValidationError::new("prod.qCom", "must be positive"));
+        }
+        if self.q_trib <= Decimal::ZERO {
+            return Err(ValidationError::new("prod.qTrib", "must be positive"));
+        }
+        // SEFAZ rejection 629: vProd must match qCom x vUnCom within one cent.
+        let expected = (self.q_com * self.v_un_com).round_dp(2);
+        if (self.v_prod - expected).abs() > Decimal::new(1, 2) {
+            return Err(ValidationError::new(
+                "prod.vProd",
+                format!("must equal qCom x vUnCom ({})", expected),
+            ));
+        }
+        if !matches!(self.ind_tot.as_str(), "0" | "1") {
+            return Err(ValidationError::new("prod.indTot", "must be 0 or 1"));
+        }
+        self.specific.validate()
+    }
+}
+
+impl CreateNFeItem {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.prod.validate()?;
+        if self.imposto.icms.is_some() && self.imposto.issqn.is_some() {
+            return Err(ValidationError::new(
+                "imposto",
+                "ICMS and ISSQN are mutually exclusive",
+            ));
+        }
+        if let Some(devol) = &self.imposto_devol {
+            if devol.p_devol < Decimal::ZERO || devol.p_devol > Decimal::ONE_HUNDRED {
+                return Err(ValidationError::new(
+                    "impostoDevol.pDevol",
+                    "must be between 0 and 100",
+                ));
+            }
+            if devol.ipi.v_ipi_devol < Decimal::ZERO {
+                return Err(ValidationError::new(
+                    "impostoDevol.IPI.vIPIDevol",
+                    "must not be negative",
+                ));
+            }
+        }
+        if let Some(inf_ad_prod) = &self.inf_ad_prod {
+            if inf_ad_prod.is_empty() || inf_ad_prod.len() > 500 {
+                return Err(ValidationError::new(
+                    "infAdProd",
+                    "must have between 1 and 500 characters",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
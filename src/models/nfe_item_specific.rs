@@ -0,0 +1,568 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::{is_digits, UF_CODES};
+use crate::models::nfe_address::UF_ACRONYMS;
+use crate::models::validation::{validate_optional_text, validate_text};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// `rastro` groups of an item.
+pub const MAX_BATCHES: usize = 500;
+/// `arma` groups of an item.
+pub const MAX_WEAPONS: usize = 500;
+/// `origComb` groups of a fuel.
+pub const MAX_FUEL_ORIGINS: usize = 30;
+/// `cProdANVISA` of a medicine exempt from registration.
+pub const ANVISA_EXEMPT: &str = "ISENTO";
+
+/// Traceability and specific-product groups of `prod`. `rastro` may accompany any
+/// product; `veicProd`, `med`, `arma`, `comb` and `nRECOPI` are an XSD choice, so at most
+/// one of them is informed.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeSpecificProduct {
+    #[serde(default)]
+    pub rastro: Vec<NFeBatch>,
+    #[serde(rename = "veicProd")]
+    pub veic_prod: Option<NFeVehicleProduct>,
+    pub med: Option<NFeMedicine>,
+    #[serde(default)]
+    pub arma: Vec<NFeWeapon>,
+    pub comb: Option<NFeFuel>,
+    /// RECOPI number of the sale of immune paper.
+    #[serde(rename = "nRECOPI")]
+    pub n_recopi: Option<String>,
+}
+
+/// `rastro`: a batch of the product, required by ANVISA for medicines.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeBatch {
+    #[serde(rename = "nLote")]
+    pub n_lote: String,
+    #[serde(rename = "qLote")]
+    pub q_lote: Decimal,
+    #[serde(rename = "dFab")]
+    pub d_fab: NaiveDate,
+    #[serde(rename = "dVal")]
+    pub d_val: NaiveDate,
+    /// Aggregation code of the batch.
+    #[serde(rename = "cAgreg")]
+    pub c_agreg: Option<String>,
+}
+
+/// `veicProd`: a new vehicle, as registered with DENATRAN.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeVehicleProduct {
+    /// 1 = dealer sale, 2 = direct billing, 3 = direct sale, 0 = others.
+    #[serde(rename = "tpOp")]
+    pub tp_op: String,
+    pub chassi: String,
+    #[serde(rename = "cCor")]
+    pub c_cor: String,
+    #[serde(rename = "xCor")]
+    pub x_cor: String,
+    pub pot: String,
+    pub cilin: String,
+    #[serde(rename = "pesoL")]
+    pub peso_l: String,
+    #[serde(rename = "pesoB")]
+    pub peso_b: String,
+    #[serde(rename = "nSerie")]
+    pub n_serie: String,
+    #[serde(rename = "tpComb")]
+    pub tp_comb: String,
+    #[serde(rename = "nMotor")]
+    pub n_motor: String,
+    #[serde(rename = "CMT")]
+    pub cmt: String,
+    pub dist: String,
+    #[serde(rename = "anoMod")]
+    pub ano_mod: String,
+    #[serde(rename = "anoFab")]
+    pub ano_fab: String,
+    #[serde(rename = "tpPint")]
+    pub tp_pint: String,
+    #[serde(rename = "tpVeic")]
+    pub tp_veic: String,
+    #[serde(rename = "espVeic")]
+    pub esp_veic: String,
+    /// R = restamped, N = normal.
+    #[serde(rename = "VIN")]
+    pub vin: String,
+    /// 1 = finished, 2 = unfinished, 3 = semi-finished.
+    #[serde(rename = "condVeic")]
+    pub cond_veic: String,
+    #[serde(rename = "cMod")]
+    pub c_mod: String,
+    #[serde(rename = "cCorDENATRAN")]
+    pub c_cor_denatran: String,
+    pub lota: String,
+    /// 0 = none, 1 = fiduciary alienation, 2 = lease, 3 = reservation of title,
+    /// 4 = pledge, 9 = others.
+    #[serde(rename = "tpRest")]
+    pub tp_rest: String,
+}
+
+/// `med`: a medicine or pharmaceutical raw material.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeMedicine {
+    /// ANVISA registration, or ISENTO with the reason in `xMotivoIsencao`.
+    #[serde(rename = "cProdANVISA")]
+    pub c_prod_anvisa: String,
+    #[serde(rename = "xMotivoIsencao")]
+    pub x_motivo_isencao: Option<String>,
+    /// Maximum consumer price.
+    #[serde(rename = "vPMC")]
+    pub v_pmc: Decimal,
+}
+
+/// `arma`: a firearm, one group per serial number.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeWeapon {
+    /// 0 = permitted use, 1 = restricted use.
+    #[serde(rename = "tpArma")]
+    pub tp_arma: String,
+    #[serde(rename = "nSerie")]
+    pub n_serie: String,
+    #[serde(rename = "nCano")]
+    pub n_cano: String,
+    pub descr: String,
+}
+
+/// `comb`: a fuel, identified by its ANP product code.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeFuel {
+    #[serde(rename = "cProdANP")]
+    pub c_prod_anp: String,
+    #[serde(rename = "descANP")]
+    pub desc_anp: String,
+    /// Share of LPG derived from petroleum, of national and of imported natural gas.
+    #[serde(rename = "pGLP")]
+    pub p_glp: Option<Decimal>,
+    #[serde(rename = "pGNn")]
+    pub p_gnn: Option<Decimal>,
+    #[serde(rename = "pGNi")]
+    pub p_gni: Option<Decimal>,
+    /// Value per kg of the LPG departure.
+    #[serde(rename = "vPart")]
+    pub v_part: Option<Decimal>,
+    /// Authorization code of the SEFAZ fuel control system (CODIF).
+    #[serde(rename = "CODIF")]
+    pub codif: Option<String>,
+    /// Quantity billed at ambient temperature.
+    #[serde(rename = "qTemp")]
+    pub q_temp: Option<Decimal>,
+    /// State of consumption.
+    #[serde(rename = "UFCons")]
+    pub uf_cons: String,
+    #[serde(rename = "CIDE")]
+    pub cide: Option<NFeCide>,
+    pub encerrante: Option<NFePumpReading>,
+    /// Share of biodiesel.
+    #[serde(rename = "pBio")]
+    pub p_bio: Option<Decimal>,
+    #[serde(rename = "origComb", default)]
+    pub orig_comb: Vec<NFeFuelOrigin>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeCide {
+    #[serde(rename = "qBCProd")]
+    pub q_bc_prod: Decimal,
+    #[serde(rename = "vAliqProd")]
+    pub v_aliq_prod: Decimal,
+    #[serde(rename = "vCIDE")]
+    pub v_cide: Decimal,
+}
+
+/// `encerrante`: pump totalizer readings of a retail fuel sale.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFePumpReading {
+    #[serde(rename = "nBico")]
+    pub n_bico: String,
+    #[serde(rename = "nBomba")]
+    pub n_bomba: Option<String>,
+    #[serde(rename = "nTanque")]
+    pub n_tanque: String,
+    #[serde(rename = "vEncIni")]
+    pub v_enc_ini: Decimal,
+    #[serde(rename = "vEncFin")]
+    pub v_enc_fin: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeFuelOrigin {
+    /// 0 = national, 1 = imported.
+    #[serde(rename = "indImport")]
+    pub ind_import: String,
+    #[serde(rename = "cUFOrig")]
+    pub c_uf_orig: String,
+    #[serde(rename = "pOrig")]
+    pub p_orig: Decimal,
+}
+
+fn validate_percentage(field: &str, value: Option<Decimal>) -> Result<(), ValidationError> {
+    match value {
+        Some(value) if value < Decimal::ZERO || value > Decimal::ONE_HUNDRED => Err(
+            ValidationError::new(field, "must be a percentage between 0 and 100"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn validate_positive(field: &str, value: Decimal) -> Result<(), ValidationError> {
+    if value <= Decimal::ZERO {
+        return Err(ValidationError::new(field, "must be positive"));
+    }
+    Ok(())
+}
+
+fn validate_code(field: &str, value: &str, codes: &[&str]) -> Result<(), ValidationError> {
+    if !codes.contains(&value) {
+        return Err(ValidationError::new(
+            field,
+            format!("must be one of {}", codes.join(", ")),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_digits(
+    field: &str,
+    value: &str,
+    min: usize,
+    max: usize,
+) -> Result<(), ValidationError> {
+    if !is_digits(value, min, max) {
+        let message = if min == max {
+            format!("must have {} digits", min)
+        } else {
+            format!("must have {} to {} digits", min, max)
+        };
+        return Err(ValidationError::new(field, message));
+    }
+    Ok(())
+}
+
+impl NFeSpecificProduct {
+    /// Groups of the XSD choice that are informed, by their tags.
+    fn informed(&self) -> Vec<&'static str> {
+        [
+            ("veicProd", self.veic_prod.is_some()),
+            ("med", self.med.is_some()),
+            ("arma", !self.arma.is_empty()),
+            ("comb", self.comb.is_some()),
+            ("nRECOPI", self.n_recopi.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, informed)| *informed)
+        .map(|(kind, _)| kind)
+        .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if self.rastro.len() > MAX_BATCHES {
+            return Err(ValidationError::new(
+                "prod.rastro",
+                format!("must have at most {} batches", MAX_BATCHES),
+            ));
+        }
+        for (i, batch) in self.rastro.iter().enumerate() {
+            batch.validate(&format!("prod.rastro[{}]", i + 1))?;
+        }
+
+        let informed = self.informed();
+        if informed.len() > 1 {
+            return Err(ValidationError::new(
+                "prod",
+                format!(
+                    "veicProd, med, arma, comb and nRECOPI are mutually exclusive, got {}",
+                    informed.join(" and ")
+                ),
+            ));
+        }
+
+        if let Some(veic_prod) = &self.veic_prod {
+            veic_prod.validate()?;
+        }
+        if let Some(med) = &self.med {
+            med.validate()?;
+        }
+        if self.arma.len() > MAX_WEAPONS {
+            return Err(ValidationError::new(
+                "prod.arma",
+                format!("must have at most {} weapons", MAX_WEAPONS),
+            ));
+        }
+        for (i, arma) in self.arma.iter().enumerate() {
+            arma.validate(&format!("prod.arma[{}]", i + 1))?;
+        }
+        if let Some(comb) = &self.comb {
+            comb.validate()?;
+        }
+        if let Some(n_recopi) = &self.n_recopi {
+            validate_digits("prod.nRECOPI", n_recopi, 20, 20)?;
+        }
+        Ok(())
+    }
+}
+
+impl NFeBatch {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        validate_text(&format!("{}.nLote", group), &self.n_lote, 1, 20)?;
+        validate_positive(&format!("{}.qLote", group), self.q_lote)?;
+        if self.d_val < self.d_fab {
+            return Err(ValidationError::new(
+                &format!("{}.dVal", group),
+                "must not be before dFab",
+            ));
+        }
+        validate_optional_text(&format!("{}.cAgreg", group), self.c_agreg.as_deref(), 1, 20)
+    }
+}
+
+impl NFeVehicleProduct {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_code("prod.veicProd.tpOp", &self.tp_op, &["0", "1", "2", "3"])?;
+        if self.chassi.len() != 17 || !self.chassi.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(ValidationError::new(
+                "prod.veicProd.chassi",
+                "must have 17 letters or digits",
+            ));
+        }
+        for (field, value, max) in [
+            ("cCor", &self.c_cor, 4),
+            ("xCor", &self.x_cor, 40),
+            ("pot", &self.pot, 4),
+            ("cilin", &self.cilin, 4),
+            ("pesoL", &self.peso_l, 9),
+            ("pesoB", &self.peso_b, 9),
+            ("nSerie", &self.n_serie, 9),
+            ("tpComb", &self.tp_comb, 2),
+            ("nMotor", &self.n_motor, 21),
+            ("CMT", &self.cmt, 9),
+            ("dist", &self.dist, 4),
+        ] {
+            validate_text(&format!("prod.veicProd.{}", field), value, 1, max)?;
+        }
+        validate_digits("prod.veicProd.anoMod", &self.ano_mod, 4, 4)?;
+        validate_digits("prod.veicProd.anoFab", &self.ano_fab, 4, 4)?;
+        validate_text("prod.veicProd.tpPint", &self.tp_pint, 1, 1)?;
+        validate_digits("prod.veicProd.tpVeic", &self.tp_veic, 1, 2)?;
+        validate_code(
+            "prod.veicProd.espVeic",
+            &self.esp_veic,
+            &["1", "2", "3", "4", "5", "6"],
+        )?;
+        validate_code("prod.veicProd.VIN", &self.vin, &["R", "N"])?;
+        validate_code("prod.veicProd.condVeic", &self.cond_veic, &["1", "2", "3"])?;
+        validate_digits("prod.veicProd.cMod", &self.c_mod, 1, 6)?;
+        let color = self.c_cor_denatran.parse::<u32>().unwrap_or_default();
+        if !is_digits(&self.c_cor_denatran, 2, 2) || !(1..=16).contains(&color) {
+            return Err(ValidationError::new(
+                "prod.veicProd.cCorDENATRAN",
+                "must be a DENATRAN color code between 01 and 16",
+            ));
+        }
+        validate_digits("prod.veicProd.lota", &self.lota, 1, 3)?;
+        validate_code(
+            "prod.veicProd.tpRest",
+            &self.tp_rest,
+            &["0", "1", "2", "3", "4", "9"],
+        )
+    }
+}
+
+impl NFeMedicine {
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.c_prod_anvisa == ANVISA_EXEMPT {
+            if self.x_motivo_isencao.is_none() {
+                return Err(ValidationError::new(
+                    "prod.med.xMotivoIsencao",
+                    "is required when cProdANVISA is ISENTO",
+                ));
+            }
+        } else if self.c_prod_anvisa.len() != 13
+            || !self
+                .c_prod_anvisa
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(ValidationError::new(
+                "prod.med.cProdANVISA",
+                "must be the 13-character ANVISA registration or ISENTO",
+            ));
+        }
+        validate_optional_text(
+            "prod.med.xMotivoIsencao",
+            self.x_motivo_isencao.as_deref(),
+            1,
+            255,
+        )?;
+        if self.v_pmc < Decimal::ZERO {
+            return Err(ValidationError::new(
+                "prod.med.vPMC",
+                "must not be negative",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl NFeWeapon {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        validate_code(&format!("{}.tpArma", group), &self.tp_arma, &["0", "1"])?;
+        validate_text(&format!("{}.nSerie", group), &self.n_serie, 1, 15)?;
+        validate_text(&format!("{}.nCano", group), &self.n_cano, 1, 15)?;
+        validate_text(&format!("{}.descr", group), &self.descr, 1, 256)
+    }
+}
+
+impl NFeFuel {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_digits("prod.comb.cProdANP", &self.c_prod_anp, 9, 9)?;
+        validate_text("prod.comb.descANP", &self.desc_anp, 2, 95)?;
+        validate_percentage("prod.comb.pGLP", self.p_glp)?;
+        validate_percentage("prod.comb.pGNn", self.p_gnn)?;
+        validate_percentage("prod.comb.pGNi", self.p_gni)?;
+        if let Some(codif) = &self.codif {
+            validate_digits("prod.comb.CODIF", codif, 1, 21)?;
+        }
+        if !UF_ACRONYMS.contains(&self.uf_cons.as_str()) {
+            return Err(ValidationError::new(
+                "prod.comb.UFCons",
+                "must be a state acronym",
+            ));
+        }
+        if let Some(encerrante) = &self.encerrante {
+            validate_digits("prod.comb.encerrante.nBico", &encerrante.n_bico, 1, 3)?;
+            if let Some(n_bomba) = &encerrante.n_bomba {
+                validate_digits("prod.comb.encerrante.nBomba", n_bomba, 1, 3)?;
+            }
+            validate_digits("prod.comb.encerrante.nTanque", &encerrante.n_tanque, 1, 3)?;
+            if encerrante.v_enc_fin < encerrante.v_enc_ini {
+                return Err(ValidationError::new(
+                    "prod.comb.encerrante.vEncFin",
+                    "must not be below vEncIni",
+                ));
+            }
+        }
+        validate_percentage("prod.comb.pBio", self.p_bio)?;
+        if self.orig_comb.len() > MAX_FUEL_ORIGINS {
+            return Err(ValidationError::new(
+                "prod.comb.origComb",
+                format!("must have at most {} origins", MAX_FUEL_ORIGINS),
+            ));
+        }
+        for (i, origin) in self.orig_comb.iter().enumerate() {
+            let group = format!("prod.comb.origComb[{}]", i + 1);
+            validate_code(
+                &format!("{}.indImport", group),
+                &origin.ind_import,
+                &["0", "1"],
+            )?;
+            if !UF_CODES.contains(&origin.c_uf_orig.as_str()) {
+                return Err(ValidationError::new(
+                    &format!("{}.cUFOrig", group),
+                    "must be an IBGE state code",
+                ));
+            }
+            validate_percentage(&format!("{}.pOrig", group), Some(origin.p_orig))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn batch() -> NFeBatch {
+        NFeBatch {
+            n_lote: "L2403".to_string(),
+            q_lote: dec!(10),
+            d_fab: NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(),
+            d_val: NaiveDate::from_ymd_opt(2026, 1, 10).unwrap(),
+            c_agreg: None,
+        }
+    }
+
+    #[test]
+    fn takes_at_most_one_specific_group() {
+        let med = NFeMedicine {
+            c_prod_anvisa: "1234567890123".to_string(),
+            x_motivo_isencao: None,
+            v_pmc: dec!(45.90),
+        };
+        let mut specific = NFeSpecificProduct {
+            rastro: vec![batch()],
+            med: Some(med),
+            ..Default::default()
+        };
+        assert!(specific.validate().is_ok());
+
+        specific.n_recopi = Some("20240320123456789012".to_string());
+        let error = specific.validate().unwrap_err();
+        assert_eq!(error.field, "prod");
+        assert!(error.message.contains("med and nRECOPI"));
+
+        specific.n_recopi = None;
+        specific.rastro[0].d_val = NaiveDate::from_ymd_opt(2023, 1, 10).unwrap();
+        assert_eq!(
+            specific.validate().unwrap_err().field,
+            "prod.rastro[1].dVal"
+        );
+    }
+
+    #[test]
+    fn checks_fuel_and_exempt_medicines() {
+        let mut comb = NFeFuel {
+            c_prod_anp: "320102001".to_string(),
+            desc_anp: "GASOLINA C COMUM".to_string(),
+            p_glp: None,
+            p_gnn: None,
+            p_gni: None,
+            v_part: None,
+            codif: None,
+            q_temp: None,
+            uf_cons: "SP".to_string(),
+            cide: None,
+            encerrante: Some(NFePumpReading {
+                n_bico: "1".to_string(),
+                n_bomba: None,
+                n_tanque: "2".to_string(),
+                v_enc_ini: dec!(1000.000),
+                v_enc_fin: dec!(1040.000),
+            }),
+            p_bio: None,
+            orig_comb: vec![NFeFuelOrigin {
+                ind_import: "0".to_string(),
+                c_uf_orig: "35".to_string(),
+                p_orig: dec!(100),
+            }],
+        };
+        let specific = |comb: &NFeFuel| NFeSpecificProduct {
+            comb: Some(comb.clone()),
+            ..Default::default()
+        };
+        assert!(specific(&comb).validate().is_ok());
+        comb.orig_comb[0].p_orig = dec!(120);
+        assert_eq!(
+            specific(&comb).validate().unwrap_err().field,
+            "prod.comb.origComb[1].pOrig"
+        );
+
+        let exempt = NFeSpecificProduct {
+            med: Some(NFeMedicine {
+                c_prod_anvisa: ANVISA_EXEMPT.to_string(),
+                x_motivo_isencao: None,
+                v_pmc: dec!(0),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            exempt.validate().unwrap_err().field,
+            "prod.med.xMotivoIsencao"
+        );
+    }
+}
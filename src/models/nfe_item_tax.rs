@@ -0,0 +1,285 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// `imposto` block of a line item.
+///
+/// Each tax is stored flat: the XSD subgroup (ICMS00, PISAliq, IPITrib...) is derived
+/// from the CST/CSOSN when the note is serialized, so only the fields that apply to
+/// that subgroup are expected to be filled.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeItemTaxes {
+    #[serde(rename = "vTotTrib")]
+    pub v_tot_trib: Option<Decimal>,
+    #[serde(rename = "ICMS")]
+    pub icms: Option<NFeIcms>,
+    #[serde(rename = "IPI")]
+    pub ipi: Option<NFeIpi>,
+    #[serde(rename = "II")]
+    pub ii: Option<NFeIi>,
+    #[serde(rename = "ISSQN")]
+    pub issqn: Option<NFeIssqn>,
+    #[serde(rename = "PIS")]
+    pub pis: Option<NFePis>,
+    #[serde(rename = "PISST")]
+    pub pis_st: Option<NFePisSt>,
+    #[serde(rename = "COFINS")]
+    pub cofins: Option<NFeCofins>,
+    #[serde(rename = "COFINSST")]
+    pub cofins_st: Option<NFeCofinsSt>,
+}
+
+/// Every ICMS subgroup (ICMS00..ICMS90, the monophasic fuel groups ICMS02/15/53/61,
+/// ICMSPart, ICMSST and ICMSSN101..ICMSSN900).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeIcms {
+    pub orig: String,
+    #[serde(rename = "CST")]
+    pub cst: Option<String>,
+    #[serde(rename = "CSOSN")]
+    pub csosn: Option<String>,
+    #[serde(rename = "modBC")]
+    pub mod_bc: Option<String>,
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pRedBC")]
+    pub p_red_bc: Option<Decimal>,
+    #[serde(rename = "pICMS")]
+    pub p_icms: Option<Decimal>,
+    #[serde(rename = "vICMSOp")]
+    pub v_icms_op: Option<Decimal>,
+    #[serde(rename = "pDif")]
+    pub p_dif: Option<Decimal>,
+    #[serde(rename = "vICMSDif")]
+    pub v_icms_dif: Option<Decimal>,
+    #[serde(rename = "vICMS")]
+    pub v_icms: Option<Decimal>,
+    #[serde(rename = "vBCFCP")]
+    pub v_bc_fcp: Option<Decimal>,
+    #[serde(rename = "pFCP")]
+    pub p_fcp: Option<Decimal>,
+    #[serde(rename = "vFCP")]
+    pub v_fcp: Option<Decimal>,
+    #[serde(rename = "modBCST")]
+    pub mod_bc_st: Option<String>,
+    #[serde(rename = "pMVAST")]
+    pub p_mva_st: Option<Decimal>,
+    #[serde(rename = "pRedBCST")]
+    pub p_red_bc_st: Option<Decimal>,
+    #[serde(rename = "vBCST")]
+    pub v_bc_st: Option<Decimal>,
+    #[serde(rename = "pICMSST")]
+    pub p_icms_st: Option<Decimal>,
+    #[serde(rename = "vICMSST")]
+    pub v_icms_st: Option<Decimal>,
+    #[serde(rename = "vBCFCPST")]
+    pub v_bc_fcp_st: Option<Decimal>,
+    #[serde(rename = "pFCPST")]
+    pub p_fcp_st: Option<Decimal>,
+    #[serde(rename = "vFCPST")]
+    pub v_fcp_st: Option<Decimal>,
+    #[serde(rename = "vICMSDeson")]
+    pub v_icms_deson: Option<Decimal>,
+    #[serde(rename = "motDesICMS")]
+    pub mot_des_icms: Option<String>,
+    #[serde(rename = "indDeduzDeson")]
+    pub ind_deduz_deson: Option<String>,
+    #[serde(rename = "vBCSTRet")]
+    pub v_bc_st_ret: Option<Decimal>,
+    #[serde(rename = "pST")]
+    pub p_st: Option<Decimal>,
+    #[serde(rename = "vICMSSubstituto")]
+    pub v_icms_substituto: Option<Decimal>,
+    #[serde(rename = "vICMSSTRet")]
+    pub v_icms_st_ret: Option<Decimal>,
+    #[serde(rename = "vBCFCPSTRet")]
+    pub v_bc_fcp_st_ret: Option<Decimal>,
+    #[serde(rename = "pFCPSTRet")]
+    pub p_fcp_st_ret: Option<Decimal>,
+    #[serde(rename = "vFCPSTRet")]
+    pub v_fcp_st_ret: Option<Decimal>,
+    #[serde(rename = "pBCOp")]
+    pub p_bc_op: Option<Decimal>,
+    #[serde(rename = "UFST")]
+    pub uf_st: Option<String>,
+    #[serde(rename = "vBCSTDest")]
+    pub v_bc_st_dest: Option<Decimal>,
+    #[serde(rename = "vICMSSTDest")]
+    pub v_icms_st_dest: Option<Decimal>,
+    #[serde(rename = "pCredSN")]
+    pub p_cred_sn: Option<Decimal>,
+    #[serde(rename = "vCredICMSSN")]
+    pub v_cred_icms_sn: Option<Decimal>,
+    #[serde(rename = "qBCMono")]
+    pub q_bc_mono: Option<Decimal>,
+    #[serde(rename = "adRemICMS")]
+    pub ad_rem_icms: Option<Decimal>,
+    #[serde(rename = "vICMSMonoOp")]
+    pub v_icms_mono_op: Option<Decimal>,
+    #[serde(rename = "vICMSMonoDif")]
+    pub v_icms_mono_dif: Option<Decimal>,
+    #[serde(rename = "vICMSMono")]
+    pub v_icms_mono: Option<Decimal>,
+    #[serde(rename = "qBCMonoReten")]
+    pub q_bc_mono_reten: Option<Decimal>,
+    #[serde(rename = "adRemICMSReten")]
+    pub ad_rem_icms_reten: Option<Decimal>,
+    #[serde(rename = "vICMSMonoReten")]
+    pub v_icms_mono_reten: Option<Decimal>,
+    #[serde(rename = "pRedAdRem")]
+    pub p_red_ad_rem: Option<Decimal>,
+    #[serde(rename = "motRedAdRem")]
+    pub mot_red_ad_rem: Option<String>,
+    #[serde(rename = "qBCMonoDif")]
+    pub q_bc_mono_dif: Option<Decimal>,
+    #[serde(rename = "adRemICMSDif")]
+    pub ad_rem_icms_dif: Option<Decimal>,
+    #[serde(rename = "qBCMonoRet")]
+    pub q_bc_mono_ret: Option<Decimal>,
+    #[serde(rename = "adRemICMSRet")]
+    pub ad_rem_icms_ret: Option<Decimal>,
+    #[serde(rename = "vICMSMonoRet")]
+    pub v_icms_mono_ret: Option<Decimal>,
+}
+
+/// `TIpi`: IPITrib when `vIPI` applies (CST 00, 49, 50, 99), IPINT otherwise.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeIpi {
+    #[serde(rename = "CNPJProd")]
+    pub cnpj_prod: Option<String>,
+    #[serde(rename = "cSelo")]
+    pub c_selo: Option<String>,
+    #[serde(rename = "qSelo")]
+    pub q_selo: Option<String>,
+    #[serde(rename = "cEnq")]
+    pub c_enq: String,
+    #[serde(rename = "CST")]
+    pub cst: String,
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pIPI")]
+    pub p_ipi: Option<Decimal>,
+    #[serde(rename = "qUnid")]
+    pub q_unid: Option<Decimal>,
+    #[serde(rename = "vUnid")]
+    pub v_unid: Option<Decimal>,
+    #[serde(rename = "vIPI")]
+    pub v_ipi: Option<Decimal>,
+}
+
+/// `II`: import tax.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeIi {
+    #[serde(rename = "vBC")]
+    pub v_bc: Decimal,
+    #[serde(rename = "vDespAdu")]
+    pub v_desp_adu: Decimal,
+    #[serde(rename = "vII")]
+    pub v_ii: Decimal,
+    #[serde(rename = "vIOF")]
+    pub v_iof: Decimal,
+}
+
+/// `ISSQN`: municipal service tax, mutually exclusive with ICMS.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeIssqn {
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "vAliq")]
+    pub v_aliq: Decimal,
+    #[serde(rename = "vISSQN")]
+    pub v_issqn: Option<Decimal>,
+    #[serde(rename = "cMunFG")]
+    pub c_mun_fg: String,
+    #[serde(rename = "cListServ")]
+    pub c_list_serv: String,
+    #[serde(rename = "vDeducao")]
+    pub v_deducao: Option<Decimal>,
+    #[serde(rename = "vOutro")]
+    pub v_outro: Option<Decimal>,
+    #[serde(rename = "vDescIncond")]
+    pub v_desc_incond: Option<Decimal>,
+    #[serde(rename = "vDescCond")]
+    pub v_desc_cond: Option<Decimal>,
+    #[serde(rename = "vISSRet")]
+    pub v_iss_ret: Option<Decimal>,
+    #[serde(rename = "indISS")]
+    pub ind_iss: String,
+    #[serde(rename = "cServico")]
+    pub c_servico: Option<String>,
+    #[serde(rename = "cMun")]
+    pub c_mun: Option<String>,
+    #[serde(rename = "cPais")]
+    pub c_pais: Option<String>,
+    #[serde(rename = "nProcesso")]
+    pub n_processo: Option<String>,
+    #[serde(rename = "indIncentivo")]
+    pub ind_incentivo: String,
+}
+
+/// `PIS`: PISAliq (CST 01, 02), PISQtde (03), PISNT (04-09) or PISOutr (49-99).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFePis {
+    #[serde(rename = "CST")]
+    pub cst: String,
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pPIS")]
+    pub p_pis: Option<Decimal>,
+    #[serde(rename = "qBCProd")]
+    pub q_bc_prod: Option<Decimal>,
+    #[serde(rename = "vAliqProd")]
+    pub v_aliq_prod: Option<Decimal>,
+    #[serde(rename = "vPIS")]
+    pub v_pis: Option<Decimal>,
+}
+
+/// `PISST`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFePisSt {
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pPIS")]
+    pub p_pis: Option<Decimal>,
+    #[serde(rename = "qBCProd")]
+    pub q_bc_prod: Option<Decimal>,
+    #[serde(rename = "vAliqProd")]
+    pub v_aliq_prod: Option<Decimal>,
+    #[serde(rename = "vPIS")]
+    pub v_pis: Option<Decimal>,
+    #[serde(rename = "indSomaPISST")]
+    pub ind_soma_pis_st: Option<String>,
+}
+
+/// `COFINS`: COFINSAliq (CST 01, 02), COFINSQtde (03), COFINSNT (04-09) or COFINSOutr (49-99).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeCofins {
+    #[serde(rename = "CST")]
+    pub cst: String,
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pCOFINS")]
+    pub p_cofins: Option<Decimal>,
+    #[serde(rename = "qBCProd")]
+    pub q_bc_prod: Option<Decimal>,
+    #[serde(rename = "vAliqProd")]
+    pub v_aliq_prod: Option<Decimal>,
+    #[serde(rename = "vCOFINS")]
+    pub v_cofins: Option<Decimal>,
+}
+
+/// `COFINSST`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NFeCofinsSt {
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "pCOFINS")]
+    pub p_cofins: Option<Decimal>,
+    #[serde(rename = "qBCProd")]
+    pub q_bc_prod: Option<Decimal>,
+    #[serde(rename = "vAliqProd")]
+    pub v_aliq_prod: Option<Decimal>,
+    #[serde(rename = "vCOFINS")]
+    pub v_cofins: Option<Decimal>,
+    #[serde(rename = "indSomaCOFINSST")]
+    pub ind_soma_cofins_st: Option<String>,
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+/// A numbering sequence: notes of one emitter, model, serie and environment share the
+/// `nNF` space.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NFeSerie {
+    /// CNPJ, or CPF, of the emitter.
+    #[serde(rename = "emitDoc")]
+    pub emit_doc: String,
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: u32,
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NFeNumberRange {
+    #[serde(rename = "nNFIni")]
+    pub n_nf_ini: u32,
+    #[serde(rename = "nNFFin")]
+    pub n_nf_fin: u32,
+}
+
+/// Numbers of a serie that no stored note uses and no homologated inutilização voids.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NFeSerieGaps {
+    #[serde(flatten)]
+    pub serie: NFeSerie,
+    /// Highest number allocated or used in the serie.
+    #[serde(rename = "lastNNF")]
+    pub last_n_nf: u32,
+    pub gaps: Vec<NFeNumberRange>,
+}
+
+/// Narrows the gap report; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct NFeGapFilter {
+    #[serde(rename = "emitDoc")]
+    pub emit_doc: Option<String>,
+    #[serde(rename = "mod")]
+    pub mod_: Option<String>,
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: Option<String>,
+}
+
+/// Ranges of `1..=last` covered neither by `used` nor by the `voided` ranges.
+pub fn find_gaps(last: u32, used: &[u32], voided: &[(u32, u32)]) -> Vec<NFeNumberRange> {
+    let mut covered: Vec<(u32, u32)> = used.iter().map(|n| (*n, *n)).collect();
+    covered.extend_from_slice(voided);
+    covered.sort_unstable();
+
+    let mut gaps = Vec::new();
+    let mut next = 1;
+    for (ini, fin) in covered {
+        if ini > last {
+            break;
+        }
+        if ini > next {
+            gaps.push(NFeNumberRange {
+                n_nf_ini: next,
+                n_nf_fin: ini - 1,
+            });
+        }
+        next = next.max(fin.saturating_add(1));
+    }
+    if next <= last {
+        gaps.push(NFeNumberRange {
+            n_nf_ini: next,
+            n_nf_fin: last,
+        });
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(n_nf_ini: u32, n_nf_fin: u32) -> NFeNumberRange {
+        NFeNumberRange { n_nf_ini, n_nf_fin }
+    }
+
+    #[test]
+    fn finds_unused_and_unvoided_numbers() {
+        assert!(find_gaps(3, &[1, 2, 3], &[]).is_empty());
+        assert!(find_gaps(0, &[], &[]).is_empty());
+        assert_eq!(find_gaps(3, &[], &[]), vec![range(1, 3)]);
+        assert_eq!(
+            find_gaps(12, &[9, 1, 4, 4], &[(6, 7)]),
+            vec![range(2, 3), range(5, 5), range(8, 8), range(10, 12)]
+        );
+        // Voided ranges may overlap the used numbers and go past the last one.
+        assert_eq!(
+            find_gaps(10, &[2, 3], &[(1, 3), (5, 20)]),
+            vec![range(4, 4)]
+        );
+    }
+}
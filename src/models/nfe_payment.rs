@@ -0,0 +1,417 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_address::UF_ACRONYMS;
+use crate::models::validation::validate_optional_text;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// `dup` groups of a note.
+pub const MAX_INSTALLMENTS: usize = 120;
+/// `detPag` groups of a note.
+pub const MAX_PAYMENTS: usize = 100;
+/// `tPag` of a note without payment, such as a return or an adjustment.
+pub const NO_PAYMENT: &str = "90";
+/// `tPag` of a payment method described in `xPag`.
+pub const OTHER_PAYMENT: &str = "99";
+
+/// `tPag` codes of the layout.
+const PAYMENT_METHODS: [&str; 19] = [
+    "01", "02", "03", "04", "05", "10", "11", "12", "13", "15", "16", "17", "18", "19", "20", "21",
+    "22", "90", "99",
+];
+/// Credit and debit cards, which require the `card` group.
+const CARDS: [&str; 2] = ["03", "04"];
+/// Methods that may inform the `card` group: the cards and instant payments (PIX).
+const CARD_GROUP: [&str; 3] = ["03", "04", "17"];
+
+/// `cobr` group: the invoice and its installments. Both are optional in the layout.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeBilling {
+    pub fat: Option<NFeInvoice>,
+    #[serde(default)]
+    pub dup: Vec<NFeInstallment>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeInvoice {
+    #[serde(rename = "nFat")]
+    pub n_fat: Option<String>,
+    #[serde(rename = "vOrig")]
+    pub v_orig: Option<Decimal>,
+    #[serde(rename = "vDesc")]
+    pub v_desc: Option<Decimal>,
+    #[serde(rename = "vLiq")]
+    pub v_liq: Option<Decimal>,
+}
+
+/// `dup`: one installment of the invoice.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeInstallment {
+    #[serde(rename = "nDup")]
+    pub n_dup: Option<String>,
+    #[serde(rename = "dVenc")]
+    pub d_venc: Option<NaiveDate>,
+    #[serde(rename = "vDup")]
+    pub v_dup: Decimal,
+}
+
+/// `pag` group. The sum of `vPag` covers vNF, and what exceeds it is the change
+/// (`vTroco`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFePayment {
+    #[serde(rename = "detPag")]
+    pub det_pag: Vec<NFePaymentDetail>,
+    #[serde(rename = "vTroco")]
+    pub v_troco: Option<Decimal>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFePaymentDetail {
+    /// 0 = cash, 1 = installments.
+    #[serde(rename = "indPag")]
+    pub ind_pag: Option<String>,
+    #[serde(rename = "tPag")]
+    pub t_pag: String,
+    /// Description of the method when tPag is 99.
+    #[serde(rename = "xPag")]
+    pub x_pag: Option<String>,
+    #[serde(rename = "vPag")]
+    pub v_pag: Decimal,
+    #[serde(rename = "dPag")]
+    pub d_pag: Option<NaiveDate>,
+    /// CNPJ and state of the establishment where the payment was made, when it is not
+    /// the emitter.
+    #[serde(rename = "CNPJPag")]
+    pub cnpj_pag: Option<String>,
+    #[serde(rename = "UFPag")]
+    pub uf_pag: Option<String>,
+    pub card: Option<NFeCard>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeCard {
+    /// 1 = integrated with the automation of the register (TEF), 2 = not integrated
+    /// (POS).
+    #[serde(rename = "tpIntegra")]
+    pub tp_integra: String,
+    /// CNPJ of the acquirer.
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    /// Card brand, `01` to `99`.
+    #[serde(rename = "tBand")]
+    pub t_band: Option<String>,
+    /// Authorization code of the transaction.
+    #[serde(rename = "cAut")]
+    pub c_aut: Option<String>,
+}
+
+impl Default for NFePayment {
+    fn default() -> Self {
+        Self {
+            det_pag: vec![NFePaymentDetail {
+                ind_pag: None,
+                t_pag: NO_PAYMENT.to_string(),
+                x_pag: None,
+                v_pag: Decimal::ZERO,
+                d_pag: None,
+                cnpj_pag: None,
+                uf_pag: None,
+                card: None,
+            }],
+            v_troco: None,
+        }
+    }
+}
+
+fn validate_amount(field: &str, amount: Option<Decimal>) -> Result<(), ValidationError> {
+    if amount.is_some_and(|amount| amount < Decimal::ZERO) {
+        return Err(ValidationError::new(field, "must not be negative"));
+    }
+    Ok(())
+}
+
+fn validate_cnpj(field: &str, cnpj: Option<&str>) -> Result<(), ValidationError> {
+    match cnpj {
+        Some(cnpj) if !is_digits(cnpj, 14, 14) => {
+            Err(ValidationError::new(field, "must have 14 digits"))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl NFeInvoice {
+    fn validate(&self) -> Result<(), ValidationError> {
+        validate_optional_text("cobr.fat.nFat", self.n_fat.as_deref(), 1, 60)?;
+        validate_amount("cobr.fat.vOrig", self.v_orig)?;
+        validate_amount("cobr.fat.vDesc", self.v_desc)?;
+        validate_amount("cobr.fat.vLiq", self.v_liq)?;
+        if let (Some(v_orig), Some(v_liq)) = (self.v_orig, self.v_liq) {
+            let expected = v_orig - self.v_desc.unwrap_or_default();
+            if v_liq != expected {
+                return Err(ValidationError::new(
+                    "cobr.fat.vLiq",
+                    format!("must be vOrig - vDesc ({})", expected),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NFeBilling {
+    pub fn is_empty(&self) -> bool {
+        self.fat.is_none() && self.dup.is_empty()
+    }
+
+    /// Checks the invoice and the installments, whose due dates must not go back.
+    /// The NFC-e takes no `cobr`.
+    pub fn validate(&self, mod_: &str) -> Result<(), ValidationError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        if mod_ == "65" {
+            return Err(ValidationError::new(
+                "cobr",
+                "must not be informed for mod 65",
+            ));
+        }
+        if let Some(fat) = &self.fat {
+            fat.validate()?;
+        }
+        if self.dup.len() > MAX_INSTALLMENTS {
+            return Err(ValidationError::new(
+                "cobr.dup",
+                format!("must have at most {} installments", MAX_INSTALLMENTS),
+            ));
+        }
+        let mut previous: Option<NaiveDate> = None;
+        for (i, dup) in self.dup.iter().enumerate() {
+            let group = format!("cobr.dup[{}]", i + 1);
+            validate_optional_text(&format!("{}.nDup", group), dup.n_dup.as_deref(), 1, 60)?;
+            if dup.v_dup < Decimal::ZERO {
+                return Err(ValidationError::new(
+                    &format!("{}.vDup", group),
+                    "must not be negative",
+                ));
+            }
+            if let Some(d_venc) = dup.d_venc {
+                if previous.is_some_and(|previous| d_venc < previous) {
+                    return Err(ValidationError::new(
+                        &format!("{}.dVenc", group),
+                        "due dates must be in ascending order",
+                    ));
+                }
+                previous = Some(d_venc);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NFeCard {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        if !matches!(self.tp_integra.as_str(), "1" | "2") {
+            return Err(ValidationError::new(
+                &format!("{}.tpIntegra", group),
+                "must be 1 or 2",
+            ));
+        }
+        validate_cnpj(&format!("{}.CNPJ", group), self.cnpj.as_deref())?;
+        if let Some(t_band) = &self.t_band {
+            if !is_digits(t_band, 2, 2) || t_band == "00" {
+                return Err(ValidationError::new(
+                    &format!("{}.tBand", group),
+                    "must be a brand code from 01 to 99",
+                ));
+            }
+        }
+        validate_optional_text(&format!("{}.cAut", group), self.c_aut.as_deref(), 1, 128)
+    }
+}
+
+impl NFePaymentDetail {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        if let Some(ind_pag) = &self.ind_pag {
+            if !matches!(ind_pag.as_str(), "0" | "1") {
+                return Err(ValidationError::new(
+                    &format!("{}.indPag", group),
+                    "must be 0 or 1",
+                ));
+            }
+        }
+        if !PAYMENT_METHODS.contains(&self.t_pag.as_str()) {
+            return Err(ValidationError::new(
+                &format!("{}.tPag", group),
+                "is not a payment method of the layout",
+            ));
+        }
+        match &self.x_pag {
+            None if self.t_pag == OTHER_PAYMENT => {
+                return Err(ValidationError::new(
+                    &format!("{}.xPag", group),
+                    "is required when tPag is 99",
+                ));
+            }
+            x_pag => validate_optional_text(&format!("{}.xPag", group), x_pag.as_deref(), 2, 60)?,
+        }
+        if self.v_pag < Decimal::ZERO {
+            return Err(ValidationError::new(
+                &format!("{}.vPag", group),
+                "must not be negative",
+            ));
+        }
+        if self.cnpj_pag.is_some() != self.uf_pag.is_some() {
+            return Err(ValidationError::new(
+                &format!("{}.CNPJPag", group),
+                "CNPJPag and UFPag are informed together",
+            ));
+        }
+        validate_cnpj(&format!("{}.CNPJPag", group), self.cnpj_pag.as_deref())?;
+        if let Some(uf_pag) = &self.uf_pag {
+            if !UF_ACRONYMS.contains(&uf_pag.as_str()) {
+                return Err(ValidationError::new(
+                    &format!("{}.UFPag", group),
+                    "must be a Brazilian state",
+                ));
+            }
+        }
+        match &self.card {
+            None if CARDS.contains(&self.t_pag.as_str()) => Err(ValidationError::new(
+                &format!("{}.card", group),
+                "is required for credit and debit cards",
+            )),
+            Some(_) if !CARD_GROUP.contains(&self.t_pag.as_str()) => Err(ValidationError::new(
+                &format!("{}.card", group),
+                "is informed only for cards and instant payments",
+            )),
+            Some(card) => card.validate(&format!("{}.card", group)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl NFePayment {
+    /// Whether the note declares it has no payment (tPag 90).
+    pub fn is_unpaid(&self) -> bool {
+        self.det_pag.iter().any(|det| det.t_pag == NO_PAYMENT)
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.det_pag.iter().map(|det| det.v_pag).sum()
+    }
+
+    /// Checks the payments against vNF: together they cover it, and `vTroco` is what
+    /// they exceed it by. A note without payment has a single zero tPag 90.
+    pub fn validate(&self, v_nf: Decimal) -> Result<(), ValidationError> {
+        if self.det_pag.is_empty() || self.det_pag.len() > MAX_PAYMENTS {
+            return Err(ValidationError::new(
+                "pag.detPag",
+                format!("must have 1 to {} payments", MAX_PAYMENTS),
+            ));
+        }
+        for (i, det) in self.det_pag.iter().enumerate() {
+            det.validate(&format!("pag.detPag[{}]", i + 1))?;
+        }
+        validate_amount("pag.vTroco", self.v_troco)?;
+        if self.is_unpaid() {
+            if self.det_pag.len() > 1 || self.total() != Decimal::ZERO || self.v_troco.is_some() {
+                return Err(ValidationError::new(
+                    "pag.detPag",
+                    "tPag 90 (no payment) is the only payment, with vPag 0",
+                ));
+            }
+            return Ok(());
+        }
+        let total = self.total();
+        if total < v_nf {
+            return Err(ValidationError::new(
+                "pag.detPag",
+                format!("the payments ({}) must cover vNF ({})", total, v_nf),
+            ));
+        }
+        let change = total - v_nf;
+        if self.v_troco.unwrap_or_default() != change {
+            return Err(ValidationError::new(
+                "pag.vTroco",
+                format!("must be the payments minus vNF ({})", change),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn payment(t_pag: &str, v_pag: Decimal) -> NFePaymentDetail {
+        NFePaymentDetail {
+            ind_pag: Some("0".to_string()),
+            t_pag: t_pag.to_string(),
+            v_pag,
+            ..NFePayment::default().det_pag[0].clone()
+        }
+    }
+
+    #[test]
+    fn payments_cover_the_note_with_change() {
+        assert!(NFePayment::default().validate(dec!(100.00)).is_ok());
+
+        let mut pag = NFePayment {
+            det_pag: vec![payment("01", dec!(50.00)), payment("17", dec!(60.00))],
+            v_troco: Some(dec!(10.00)),
+        };
+        assert!(pag.validate(dec!(100.00)).is_ok());
+        assert_eq!(pag.validate(dec!(120.00)).unwrap_err().field, "pag.detPag");
+        pag.v_troco = None;
+        assert_eq!(pag.validate(dec!(100.00)).unwrap_err().field, "pag.vTroco");
+
+        let mut card = payment("03", dec!(100.00));
+        let pag = NFePayment {
+            det_pag: vec![card.clone()],
+            v_troco: None,
+        };
+        assert_eq!(
+            pag.validate(dec!(100.00)).unwrap_err().field,
+            "pag.detPag[1].card"
+        );
+        card.card = Some(NFeCard {
+            tp_integra: "2".to_string(),
+            cnpj: Some("11222333000181".to_string()),
+            t_band: Some("01".to_string()),
+            c_aut: Some("A1B2C3".to_string()),
+        });
+        let pag = NFePayment {
+            det_pag: vec![card, payment("90", dec!(0))],
+            v_troco: None,
+        };
+        assert_eq!(pag.validate(dec!(100.00)).unwrap_err().field, "pag.detPag");
+    }
+
+    #[test]
+    fn installments_fall_due_in_order() {
+        let dup = |n_dup: &str, day: u32| NFeInstallment {
+            n_dup: Some(n_dup.to_string()),
+            d_venc: NaiveDate::from_ymd_opt(2024, 4, day),
+            v_dup: dec!(50.00),
+        };
+        let mut cobr = NFeBilling {
+            fat: Some(NFeInvoice {
+                n_fat: Some("123".to_string()),
+                v_orig: Some(dec!(110.00)),
+                v_desc: Some(dec!(10.00)),
+                v_liq: Some(dec!(100.00)),
+            }),
+            dup: vec![dup("001", 20), dup("002", 20), dup("003", 30)],
+        };
+        assert!(cobr.validate("55").is_ok());
+        assert_eq!(cobr.validate("65").unwrap_err().field, "cobr");
+        cobr.dup.swap(1, 2);
+        assert_eq!(cobr.validate("55").unwrap_err().field, "cobr.dup[3].dVenc");
+        cobr.dup.clear();
+        cobr.fat.as_mut().unwrap().v_liq = Some(dec!(110.00));
+        assert_eq!(cobr.validate("55").unwrap_err().field, "cobr.fat.vLiq");
+    }
+}
@@ -0,0 +1,60 @@
+use crate::models::nfe_status::NFeStatus;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Authorized, or authorized after the deadline.
+pub const AUTHORIZED: [&str; 2] = ["100", "150"];
+/// Use denied; the number is consumed and the note cannot be sent again.
+pub const DENIED: [&str; 4] = ["110", "301", "302", "303"];
+/// Duplicate of a note SEFAZ already received, under this key (204) or another (539);
+/// the protocol of the note is recovered by consulting its key.
+pub const DUPLICATE: [&str; 2] = ["204", "539"];
+/// Batch still in progress at SEFAZ; `nRec` is kept to query it again.
+pub const PENDING: &str = "105";
+
+/// Answer of SEFAZ to one transmission of a note (`protNFe/infProt`), kept as history.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeProtocol {
+    pub internal_key: String,
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: String,
+    #[serde(rename = "verAplic")]
+    pub ver_aplic: Option<String>,
+    #[serde(rename = "chNFe")]
+    pub ch_nfe: String,
+    #[serde(rename = "dhRecbto")]
+    pub dh_recbto: Option<DateTime<Utc>>,
+    #[serde(rename = "nProt")]
+    pub n_prot: Option<String>,
+    #[serde(rename = "digVal")]
+    pub dig_val: Option<String>,
+    #[serde(rename = "cStat")]
+    pub c_stat: String,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: String,
+    /// Receipt of the asynchronous batch, while it is pending.
+    #[serde(rename = "nRec")]
+    pub n_rec: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl NFeProtocol {
+    pub fn is_authorized(&self) -> bool {
+        AUTHORIZED.contains(&self.c_stat.as_str())
+    }
+
+    /// Status the note takes once SEFAZ has processed it.
+    pub fn status(&self) -> NFeStatus {
+        if self.is_authorized() {
+            NFeStatus::Authorized
+        } else if DENIED.contains(&self.c_stat.as_str()) {
+            NFeStatus::Denied
+        } else {
+            NFeStatus::Rejected
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.c_stat == PENDING && self.n_rec.is_some()
+    }
+}
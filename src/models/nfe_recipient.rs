@@ -0,0 +1,93 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_address::{validate_document, NFeAddress};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// `dest` group: the recipient of the note, at most one per identification.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeRecipient {
+    pub internal_key: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "idEstrangeiro")]
+    pub id_estrangeiro: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: Option<String>,
+    #[serde(rename = "enderDest")]
+    pub ender_dest: Option<NFeAddress>,
+    #[serde(rename = "indIEDest")]
+    pub ind_ie_dest: String,
+    #[serde(rename = "IE")]
+    pub ie: Option<String>,
+    #[serde(rename = "ISUF")]
+    pub isuf: Option<String>,
+    #[serde(rename = "IM")]
+    pub im: Option<String>,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNFeRecipient {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "idEstrangeiro")]
+    pub id_estrangeiro: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: Option<String>,
+    #[serde(rename = "enderDest")]
+    pub ender_dest: Option<NFeAddress>,
+    #[serde(rename = "indIEDest")]
+    pub ind_ie_dest: String,
+    #[serde(rename = "IE")]
+    pub ie: Option<String>,
+    #[serde(rename = "ISUF")]
+    pub isuf: Option<String>,
+    #[serde(rename = "IM")]
+    pub im: Option<String>,
+    pub email: Option<String>,
+}
+
+impl CreateNFeRecipient {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        validate_document(
+            "dest",
+            self.cnpj.as_deref(),
+            self.cpf.as_deref(),
+            self.id_estrangeiro.is_some() as usize,
+        )?;
+        match self.ind_ie_dest.as_str() {
+            "1" if self.ie.is_none() => {
+                return Err(ValidationError::new(
+                    "dest.IE",
+                    "is required when indIEDest is 1",
+                ))
+            }
+            "2" if self.ie.is_some() => {
+                return Err(ValidationError::new(
+                    "dest.IE",
+                    "must not be informed when indIEDest is 2",
+                ))
+            }
+            "1" | "2" | "9" => {}
+            _ => return Err(ValidationError::new("dest.indIEDest", "must be 1, 2 or 9")),
+        }
+        if let Some(email) = &self.email {
+            if email.is_empty() || email.chars().count() > 60 || !email.contains('@') {
+                return Err(ValidationError::new(
+                    "dest.email",
+                    "must be a valid address of up to 60 characters",
+                ));
+            }
+        }
+        if let Some(ender_dest) = &self.ender_dest {
+            ender_dest.validate("dest.enderDest", true)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::{check_digit, is_digits, UF_CODES};
+use crate::models::nfe_address::validate_document;
+use serde::{Deserialize, Serialize};
+
+/// `NFref` groups of a note.
+pub const MAX_REFERENCES: usize = 500;
+/// `finNFe` of a return of goods, which must reference the notes being returned.
+pub const RETURN: &str = "4";
+
+/// `NFref`: a document referenced by the note. Exactly one of the fields is informed.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeReference {
+    /// Access key of an NF-e or NFC-e.
+    #[serde(rename = "refNFe")]
+    pub ref_nfe: Option<String>,
+    /// Access key of an NF-e whose signature the reference carries, as required by
+    /// some states for notes issued by the recipient.
+    #[serde(rename = "refNFeSig")]
+    pub ref_nfe_sig: Option<String>,
+    /// Paper note of model 1, 1A or 2.
+    #[serde(rename = "refNF")]
+    pub ref_nf: Option<NFeRefNF>,
+    /// Note of a rural producer, model 4 or 1.
+    #[serde(rename = "refNFP")]
+    pub ref_nfp: Option<NFeRefNFP>,
+    /// Access key of a CT-e.
+    #[serde(rename = "refCTe")]
+    pub ref_cte: Option<String>,
+    /// Tax coupon printed by an ECF.
+    #[serde(rename = "refECF")]
+    pub ref_ecf: Option<NFeRefECF>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeRefNF {
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "AAMM")]
+    pub aamm: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: String,
+    /// `01` for models 1 and 1A, `02` for model 2.
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: String,
+    #[serde(rename = "nNF")]
+    pub n_nf: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeRefNFP {
+    #[serde(rename = "cUF")]
+    pub c_uf: String,
+    #[serde(rename = "AAMM")]
+    pub aamm: String,
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "IE")]
+    pub ie: String,
+    /// `04` for the producer note, `01` for a model 1 or 1A note.
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    pub serie: String,
+    #[serde(rename = "nNF")]
+    pub n_nf: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeRefECF {
+    /// `2B` (machine register), `2C` (PDV) or `2D` (ECF).
+    #[serde(rename = "mod")]
+    pub mod_: String,
+    /// Order number of the ECF at the establishment.
+    #[serde(rename = "nECF")]
+    pub n_ecf: String,
+    /// Counter of the operation (COO) of the coupon.
+    #[serde(rename = "nCOO")]
+    pub n_coo: String,
+}
+
+/// A note that references an access key, as listed by the reverse lookup.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeReferrer {
+    pub internal_key: String,
+    /// Access key of the referencing note, once its emitter is known.
+    pub access_key: Option<String>,
+    #[serde(rename = "finNFe")]
+    pub fin_nfe: String,
+    /// Group of the reference: `refNFe`, `refNFeSig` or `refCTe`.
+    pub kind: String,
+}
+
+/// Checks a referenced access key: 44 digits, a model among `models` and the check
+/// digit.
+fn validate_key(field: &str, key: &str, models: &[&str]) -> Result<(), ValidationError> {
+    if !is_digits(key, 44, 44) {
+        return Err(ValidationError::new(field, "must have 44 digits"));
+    }
+    if !models.contains(&&key[20..22]) {
+        return Err(ValidationError::new(
+            field,
+            format!("must be the key of a model {} document", models.join("/")),
+        ));
+    }
+    let expected = check_digit(&key[..43]).to_string();
+    if key[43..] != expected {
+        return Err(ValidationError::new(
+            field,
+            format!("invalid check digit, expected {}", expected),
+        ));
+    }
+    Ok(())
+}
+
+/// cUF, AAMM, serie and nNF of the paper notes.
+fn validate_paper_note(
+    group: &str,
+    c_uf: &str,
+    aamm: &str,
+    serie: &str,
+    n_nf: &str,
+) -> Result<(), ValidationError> {
+    if !UF_CODES.contains(&c_uf) {
+        return Err(ValidationError::new(
+            &format!("{}.cUF", group),
+            "must be an IBGE state code",
+        ));
+    }
+    let month = aamm.get(2..).and_then(|mm| mm.parse::<u32>().ok());
+    if !is_digits(aamm, 4, 4) || !month.is_some_and(|mm| (1..=12).contains(&mm)) {
+        return Err(ValidationError::new(
+            &format!("{}.AAMM", group),
+            "must be the year and month of issue (AAMM)",
+        ));
+    }
+    if !is_digits(serie, 1, 3) {
+        return Err(ValidationError::new(
+            &format!("{}.serie", group),
+            "must have between 1 and 3 digits",
+        ));
+    }
+    if !is_digits(n_nf, 1, 9) || n_nf.chars().all(|c| c == '0') {
+        return Err(ValidationError::new(
+            &format!("{}.nNF", group),
+            "must be a number between 1 and 999999999",
+        ));
+    }
+    Ok(())
+}
+
+impl NFeReference {
+    /// The single group informed, by its tag.
+    pub fn kind(&self) -> Option<&'static str> {
+        let kinds = [
+            ("refNFe", self.ref_nfe.is_some()),
+            ("refNFeSig", self.ref_nfe_sig.is_some()),
+            ("refNF", self.ref_nf.is_some()),
+            ("refNFP", self.ref_nfp.is_some()),
+            ("refCTe", self.ref_cte.is_some()),
+            ("refECF", self.ref_ecf.is_some()),
+        ];
+        let mut informed = kinds.iter().filter(|(_, informed)| *informed);
+        match (informed.next(), informed.next()) {
+            (Some((kind, _)), None) => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// The access key referenced, for the groups that carry one.
+    pub fn access_key(&self) -> Option<&str> {
+        self.ref_nfe
+            .as_deref()
+            .or(self.ref_nfe_sig.as_deref())
+            .or(self.ref_cte.as_deref())
+    }
+
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        let Some(kind) = self.kind() else {
+            return Err(ValidationError::new(
+                group,
+                "exactly one of refNFe, refNFeSig, refNF, refNFP, refCTe or refECF must be informed",
+            ));
+        };
+        let field = format!("{}.{}", group, kind);
+        if let Some(key) = self.ref_nfe.as_deref().or(self.ref_nfe_sig.as_deref()) {
+            validate_key(&field, key, &["55", "65"])?;
+        }
+        if let Some(key) = &self.ref_cte {
+            validate_key(&field, key, &["57", "67"])?;
+        }
+        if let Some(ref_nf) = &self.ref_nf {
+            validate_paper_note(
+                &field,
+                &ref_nf.c_uf,
+                &ref_nf.aamm,
+                &ref_nf.serie,
+                &ref_nf.n_nf,
+            )?;
+            validate_document(&field, Some(&ref_nf.cnpj), None, 0)?;
+            if !matches!(ref_nf.mod_.as_str(), "01" | "02") {
+                return Err(ValidationError::new(
+                    &format!("{}.mod", field),
+                    "must be 01 or 02",
+                ));
+            }
+        }
+        if let Some(ref_nfp) = &self.ref_nfp {
+            validate_paper_note(
+                &field,
+                &ref_nfp.c_uf,
+                &ref_nfp.aamm,
+                &ref_nfp.serie,
+                &ref_nfp.n_nf,
+            )?;
+            validate_document(&field, ref_nfp.cnpj.as_deref(), ref_nfp.cpf.as_deref(), 0)?;
+            if ref_nfp.ie != "ISENTO" && !is_digits(&ref_nfp.ie, 2, 14) {
+                return Err(ValidationError::new(
+                    &format!("{}.IE", field),
+                    "must have 2 to 14 digits or be ISENTO",
+                ));
+            }
+            if !matches!(ref_nfp.mod_.as_str(), "04" | "01") {
+                return Err(ValidationError::new(
+                    &format!("{}.mod", field),
+                    "must be 04 or 01",
+                ));
+            }
+        }
+        if let Some(ref_ecf) = &self.ref_ecf {
+            if !matches!(ref_ecf.mod_.as_str(), "2B" | "2C" | "2D") {
+                return Err(ValidationError::new(
+                    &format!("{}.mod", field),
+                    "must be 2B, 2C or 2D",
+                ));
+            }
+            if !is_digits(&ref_ecf.n_ecf, 3, 3) {
+                return Err(ValidationError::new(
+                    &format!("{}.nECF", field),
+                    "must have 3 digits",
+                ));
+            }
+            if !is_digits(&ref_ecf.n_coo, 6, 6) {
+                return Err(ValidationError::new(
+                    &format!("{}.nCOO", field),
+                    "must have 6 digits",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the references of a note with the given `finNFe`. A return must reference
+/// at least one document.
+pub fn validate_references(
+    fin_nfe: &str,
+    references: &[NFeReference],
+) -> Result<(), ValidationError> {
+    if fin_nfe == RETURN && references.is_empty() {
+        return Err(ValidationError::new(
+            "NFref",
+            "a return (finNFe 4) must reference at least one document",
+        ));
+    }
+    if references.len() > MAX_REFERENCES {
+        return Err(ValidationError::new(
+            "NFref",
+            format!("must have at most {} references", MAX_REFERENCES),
+        ));
+    }
+    for (i, reference) in references.iter().enumerate() {
+        reference.validate(&format!("NFref[{}]", i + 1))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "35240312345678000195550010000001231123456780";
+
+    #[test]
+    fn takes_exactly_one_document_per_reference() {
+        let key = format!("{}{}", &KEY[..43], check_digit(&KEY[..43]));
+        let nfe = NFeReference {
+            ref_nfe: Some(key.clone()),
+            ..Default::default()
+        };
+        assert_eq!(nfe.kind(), Some("refNFe"));
+        assert_eq!(nfe.access_key(), Some(key.as_str()));
+        assert!(validate_references("4", std::slice::from_ref(&nfe)).is_ok());
+        assert_eq!(validate_references("4", &[]).unwrap_err().field, "NFref");
+        assert!(validate_references("1", &[]).is_ok());
+
+        let both = NFeReference {
+            ref_cte: Some(key.clone()),
+            ..nfe.clone()
+        };
+        assert_eq!(both.kind(), None);
+        assert_eq!(
+            validate_references("1", &[both]).unwrap_err().field,
+            "NFref[1]"
+        );
+        // An NF-e key is not a CT-e key.
+        let cte = NFeReference {
+            ref_cte: Some(key),
+            ..Default::default()
+        };
+        assert_eq!(
+            validate_references("1", &[nfe, cte]).unwrap_err().field,
+            "NFref[2].refCTe"
+        );
+    }
+
+    #[test]
+    fn checks_paper_notes_and_coupons() {
+        let mut paper = NFeRefNF {
+            c_uf: "35".to_string(),
+            aamm: "2403".to_string(),
+            cnpj: "12345678000195".to_string(),
+            mod_: "01".to_string(),
+            serie: "1".to_string(),
+            n_nf: "4521".to_string(),
+        };
+        let reference = |ref_nf: &NFeRefNF| NFeReference {
+            ref_nf: Some(ref_nf.clone()),
+            ..Default::default()
+        };
+        assert!(validate_references("2", &[reference(&paper)]).is_ok());
+        paper.aamm = "2413".to_string();
+        assert_eq!(
+            validate_references("2", &[reference(&paper)])
+                .unwrap_err()
+                .field,
+            "NFref[1].refNF.AAMM"
+        );
+
+        let coupon = NFeReference {
+            ref_ecf: Some(NFeRefECF {
+                mod_: "2D".to_string(),
+                n_ecf: "001".to_string(),
+                n_coo: "12345".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            validate_references("1", &[coupon]).unwrap_err().field,
+            "NFref[1].refECF.nCOO"
+        );
+    }
+}
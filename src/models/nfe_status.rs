@@ -0,0 +1,148 @@
+use crate::errors::ValidationError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Lifecycle of a note. Only the transitions in [`NFeStatus::next`] are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NFeStatus {
+    #[default]
+    Draft,
+    /// The XML passed the layout schema.
+    Validated,
+    Signed,
+    /// Signed in offline contingency, waiting for the authorizer to be reachable.
+    Queued,
+    /// Received by SEFAZ, waiting for the protocol.
+    Transmitted,
+    Authorized,
+    Rejected,
+    Denied,
+    Cancelled,
+}
+
+impl NFeStatus {
+    pub const ALL: [NFeStatus; 9] = [
+        NFeStatus::Draft,
+        NFeStatus::Validated,
+        NFeStatus::Signed,
+        NFeStatus::Queued,
+        NFeStatus::Transmitted,
+        NFeStatus::Authorized,
+        NFeStatus::Rejected,
+        NFeStatus::Denied,
+        NFeStatus::Cancelled,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NFeStatus::Draft => "draft",
+            NFeStatus::Validated => "validated",
+            NFeStatus::Signed => "signed",
+            NFeStatus::Queued => "queued",
+            NFeStatus::Transmitted => "transmitted",
+            NFeStatus::Authorized => "authorized",
+            NFeStatus::Rejected => "rejected",
+            NFeStatus::Denied => "denied",
+            NFeStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// States reachable from this one. Editing a validated, signed or rejected note
+    /// sends it back to draft, and a transmission that could not reach SEFAZ hands the
+    /// note back to signed or queued.
+    pub fn next(&self) -> &'static [NFeStatus] {
+        match self {
+            NFeStatus::Draft => &[NFeStatus::Validated],
+            NFeStatus::Validated => &[NFeStatus::Signed, NFeStatus::Draft],
+            NFeStatus::Signed => &[NFeStatus::Transmitted, NFeStatus::Queued, NFeStatus::Draft],
+            NFeStatus::Queued => &[NFeStatus::Transmitted],
+            NFeStatus::Transmitted => &[
+                NFeStatus::Authorized,
+                NFeStatus::Rejected,
+                NFeStatus::Denied,
+                NFeStatus::Signed,
+                NFeStatus::Queued,
+            ],
+            NFeStatus::Rejected => &[NFeStatus::Draft, NFeStatus::Validated],
+            NFeStatus::Authorized => &[NFeStatus::Cancelled],
+            NFeStatus::Denied | NFeStatus::Cancelled => &[],
+        }
+    }
+
+    pub fn can_transition_to(&self, to: NFeStatus) -> bool {
+        self.next().contains(&to)
+    }
+
+    /// Whether the note's data can still change.
+    pub fn is_editable(&self) -> bool {
+        matches!(
+            self,
+            NFeStatus::Draft | NFeStatus::Validated | NFeStatus::Signed | NFeStatus::Rejected
+        )
+    }
+}
+
+impl fmt::Display for NFeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NFeStatus {
+    type Err = ValidationError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        NFeStatus::ALL
+            .into_iter()
+            .find(|status| status.as_str() == value)
+            .ok_or_else(|| ValidationError::new("status", format!("unknown status {}", value)))
+    }
+}
+
+/// One entry of the status history, with the SEFAZ answer that caused it, if any.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeStatusChange {
+    pub internal_key: String,
+    pub from: Option<NFeStatus>,
+    pub to: NFeStatus,
+    #[serde(rename = "cStat")]
+    pub c_stat: Option<String>,
+    #[serde(rename = "xMotivo")]
+    pub x_motivo: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_only_lifecycle_transitions() {
+        use NFeStatus::*;
+        let path = [Draft, Validated, Signed, Transmitted, Authorized, Cancelled];
+        for pair in path.windows(2) {
+            assert!(pair[0].can_transition_to(pair[1]), "{:?}", pair);
+        }
+        assert!(Transmitted.can_transition_to(Rejected));
+        assert!(Rejected.can_transition_to(Draft));
+        assert!(!Draft.can_transition_to(Signed));
+        assert!(!Authorized.can_transition_to(Draft));
+        assert!(!Denied.can_transition_to(Draft));
+        assert!(Cancelled.next().is_empty());
+
+        assert!(!Authorized.is_editable());
+        assert!(!Transmitted.is_editable());
+        assert!(Rejected.is_editable());
+        assert!(Signed.can_transition_to(Queued));
+        assert!(Queued.can_transition_to(Transmitted));
+        assert!(Transmitted.can_transition_to(Queued));
+        assert!(!Transmitted.can_transition_to(Draft));
+        assert!(!Queued.can_transition_to(Draft));
+        assert!(!Queued.is_editable());
+        assert_eq!("signed".parse::<NFeStatus>().unwrap(), Signed);
+        assert!("sent".parse::<NFeStatus>().is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::errors::ValidationError;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// `total` group. ICMSTot and ISSQNtot are always derived from the stored items; only
+/// `dCompet`, `cRegTrib` and `retTrib` are kept from what the client informs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NFeTotal {
+    #[serde(rename = "ICMSTot")]
+    pub icms_tot: NFeIcmsTot,
+    #[serde(rename = "ISSQNtot")]
+    pub issqn_tot: Option<NFeIssqnTot>,
+    #[serde(rename = "retTrib")]
+    pub ret_trib: Option<NFeRetTrib>,
+}
+
+/// Totals declared by the client. Declared ICMSTot/ISSQNtot values are checked against
+/// the ones derived from the items and rejected when they differ beyond the tolerance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateNFeTotal {
+    #[serde(rename = "ICMSTot")]
+    pub icms_tot: Option<NFeIcmsTot>,
+    #[serde(rename = "ISSQNtot")]
+    pub issqn_tot: Option<NFeIssqnTot>,
+    #[serde(rename = "retTrib")]
+    pub ret_trib: Option<NFeRetTrib>,
+}
+
+/// `ICMSTot`. The interstate totals (`vFCPUFDest`...) are not produced because the items
+/// do not carry ICMSUFDest; the monophasic fuel totals (`qBCMono`...) are present only
+/// when some item carries them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeIcmsTot {
+    #[serde(rename = "vBC")]
+    pub v_bc: Decimal,
+    #[serde(rename = "vICMS")]
+    pub v_icms: Decimal,
+    #[serde(rename = "vICMSDeson")]
+    pub v_icms_deson: Decimal,
+    #[serde(rename = "vFCP")]
+    pub v_fcp: Decimal,
+    #[serde(rename = "vBCST")]
+    pub v_bc_st: Decimal,
+    #[serde(rename = "vST")]
+    pub v_st: Decimal,
+    #[serde(rename = "vFCPST")]
+    pub v_fcp_st: Decimal,
+    #[serde(rename = "vFCPSTRet")]
+    pub v_fcp_st_ret: Decimal,
+    #[serde(rename = "qBCMono")]
+    pub q_bc_mono: Option<Decimal>,
+    #[serde(rename = "vICMSMono")]
+    pub v_icms_mono: Option<Decimal>,
+    #[serde(rename = "qBCMonoReten")]
+    pub q_bc_mono_reten: Option<Decimal>,
+    #[serde(rename = "vICMSMonoReten")]
+    pub v_icms_mono_reten: Option<Decimal>,
+    #[serde(rename = "qBCMonoRet")]
+    pub q_bc_mono_ret: Option<Decimal>,
+    #[serde(rename = "vICMSMonoRet")]
+    pub v_icms_mono_ret: Option<Decimal>,
+    #[serde(rename = "vProd")]
+    pub v_prod: Decimal,
+    #[serde(rename = "vFrete")]
+    pub v_frete: Decimal,
+    #[serde(rename = "vSeg")]
+    pub v_seg: Decimal,
+    #[serde(rename = "vDesc")]
+    pub v_desc: Decimal,
+    #[serde(rename = "vII")]
+    pub v_ii: Decimal,
+    #[serde(rename = "vIPI")]
+    pub v_ipi: Decimal,
+    #[serde(rename = "vIPIDevol")]
+    pub v_ipi_devol: Decimal,
+    #[serde(rename = "vPIS")]
+    pub v_pis: Decimal,
+    #[serde(rename = "vCOFINS")]
+    pub v_cofins: Decimal,
+    #[serde(rename = "vOutro")]
+    pub v_outro: Decimal,
+    #[serde(rename = "vNF")]
+    pub v_nf: Decimal,
+    #[serde(rename = "vTotTrib")]
+    pub v_tot_trib: Option<Decimal>,
+}
+
+/// `ISSQNtot`, present only when at least one item is subject to ISSQN.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeIssqnTot {
+    #[serde(rename = "vServ")]
+    pub v_serv: Option<Decimal>,
+    #[serde(rename = "vBC")]
+    pub v_bc: Option<Decimal>,
+    #[serde(rename = "vISS")]
+    pub v_iss: Option<Decimal>,
+    #[serde(rename = "vPIS")]
+    pub v_pis: Option<Decimal>,
+    #[serde(rename = "vCOFINS")]
+    pub v_cofins: Option<Decimal>,
+    #[serde(rename = "dCompet")]
+    pub d_compet: NaiveDate,
+    #[serde(rename = "vDeducao")]
+    pub v_deducao: Option<Decimal>,
+    #[serde(rename = "vOutro")]
+    pub v_outro: Option<Decimal>,
+    #[serde(rename = "vDescIncond")]
+    pub v_desc_incond: Option<Decimal>,
+    #[serde(rename = "vDescCond")]
+    pub v_desc_cond: Option<Decimal>,
+    #[serde(rename = "vISSRet")]
+    pub v_iss_ret: Option<Decimal>,
+    #[serde(rename = "cRegTrib")]
+    pub c_reg_trib: Option<String>,
+}
+
+/// `retTrib`: federal withholdings, informed by the client since they do not come from
+/// the items.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeRetTrib {
+    #[serde(rename = "vRetPIS")]
+    pub v_ret_pis: Option<Decimal>,
+    #[serde(rename = "vRetCOFINS")]
+    pub v_ret_cofins: Option<Decimal>,
+    #[serde(rename = "vRetCSLL")]
+    pub v_ret_csll: Option<Decimal>,
+    #[serde(rename = "vBCIRRF")]
+    pub v_bc_irrf: Option<Decimal>,
+    #[serde(rename = "vIRRF")]
+    pub v_irrf: Option<Decimal>,
+    #[serde(rename = "vBCRetPrev")]
+    pub v_bc_ret_prev: Option<Decimal>,
+    #[serde(rename = "vRetPrev")]
+    pub v_ret_prev: Option<Decimal>,
+}
+
+impl NFeIcmsTot {
+    /// Monetary fields in layout order, with their element names. The monophasic
+    /// quantities share the two decimal places of the amounts in this group.
+    pub fn amounts(&self) -> Vec<(&'static str, Option<Decimal>)> {
+        vec![
+            ("vBC", Some(self.v_bc)),
+            ("vICMS", Some(self.v_icms)),
+            ("vICMSDeson", Some(self.v_icms_deson)),
+            ("vFCP", Some(self.v_fcp)),
+            ("vBCST", Some(self.v_bc_st)),
+            ("vST", Some(self.v_st)),
+            ("vFCPST", Some(self.v_fcp_st)),
+            ("vFCPSTRet", Some(self.v_fcp_st_ret)),
+            ("qBCMono", self.q_bc_mono),
+            ("vICMSMono", self.v_icms_mono),
+            ("qBCMonoReten", self.q_bc_mono_reten),
+            ("vICMSMonoReten", self.v_icms_mono_reten),
+            ("qBCMonoRet", self.q_bc_mono_ret),
+            ("vICMSMonoRet", self.v_icms_mono_ret),
+            ("vProd", Some(self.v_prod)),
+            ("vFrete", Some(self.v_frete)),
+            ("vSeg", Some(self.v_seg)),
+            ("vDesc", Some(self.v_desc)),
+            ("vII", Some(self.v_ii)),
+            ("vIPI", Some(self.v_ipi)),
+            ("vIPIDevol", Some(self.v_ipi_devol)),
+            ("vPIS", Some(self.v_pis)),
+            ("vCOFINS", Some(self.v_cofins)),
+            ("vOutro", Some(self.v_outro)),
+            ("vNF", Some(self.v_nf)),
+            ("vTotTrib", self.v_tot_trib),
+        ]
+    }
+}
+
+impl NFeIssqnTot {
+    /// Monetary fields in layout order, with their element names.
+    pub fn amounts(&self) -> Vec<(&'static str, Option<Decimal>)> {
+        vec![
+            ("vServ", self.v_serv),
+            ("vBC", self.v_bc),
+            ("vISS", self.v_iss),
+            ("vPIS", self.v_pis),
+            ("vCOFINS", self.v_cofins),
+            ("vDeducao", self.v_deducao),
+            ("vOutro", self.v_outro),
+            ("vDescIncond", self.v_desc_incond),
+            ("vDescCond", self.v_desc_cond),
+            ("vISSRet", self.v_iss_ret),
+        ]
+    }
+}
+
+impl NFeRetTrib {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let amounts = [
+            ("retTrib.vRetPIS", self.v_ret_pis),
+            ("retTrib.vRetCOFINS", self.v_ret_cofins),
+            ("retTrib.vRetCSLL", self.v_ret_csll),
+            ("retTrib.vBCIRRF", self.v_bc_irrf),
+            ("retTrib.vIRRF", self.v_irrf),
+            ("retTrib.vBCRetPrev", self.v_bc_ret_prev),
+            ("retTrib.vRetPrev", self.v_ret_prev),
+        ];
+        for (field, amount) in amounts {
+            if amount.is_some_and(|v| v <= Decimal::ZERO) {
+                return Err(ValidationError::new(
+                    field,
+                    "must be positive when informed",
+                ));
+            }
+        }
+        if self.v_irrf.is_some() && self.v_bc_irrf.is_none() {
+            return Err(ValidationError::new(
+                "retTrib.vBCIRRF",
+                "is required when vIRRF is informed",
+            ));
+        }
+        if self.v_ret_prev.is_some() && self.v_bc_ret_prev.is_none() {
+            return Err(ValidationError::new(
+                "retTrib.vBCRetPrev",
+                "is required when vRetPrev is informed",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl UpdateNFeTotal {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(issqn_tot) = &self.issqn_tot {
+            if let Some(c_reg_trib) = &issqn_tot.c_reg_trib {
+                if !matches!(c_reg_trib.as_str(), "1" | "2" | "3" | "4" | "5" | "6") {
+                    return Err(ValidationError::new(
+                        "ISSQNtot.cRegTrib",
+                        "must be between 1 and 6",
+                    ));
+                }
+            }
+        }
+        if let Some(ret_trib) = &self.ret_trib {
+            ret_trib.validate()?;
+        }
+        Ok(())
+    }
+}
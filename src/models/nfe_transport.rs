@@ -0,0 +1,476 @@
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_address::{validate_document, UF_ABROAD, UF_ACRONYMS};
+use crate::models::validation::{validate_optional_text, validate_text};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// `modFrete` of a note without transport.
+pub const NO_FREIGHT: &str = "9";
+/// `reboque` groups of a note.
+pub const MAX_TRAILERS: usize = 5;
+/// `vol` groups of a note, and `lacres` of a volume.
+pub const MAX_VOLUMES: usize = 5000;
+
+/// `transp` group. Road vehicles (`veicTransp` and `reboque`), `vagao` and `balsa` are
+/// alternatives of the layout, so at most one of them is informed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeTransport {
+    #[serde(rename = "modFrete")]
+    pub mod_frete: String,
+    pub transporta: Option<NFeCarrier>,
+    #[serde(rename = "retTransp")]
+    pub ret_transp: Option<NFeRetTransp>,
+    #[serde(rename = "veicTransp")]
+    pub veic_transp: Option<NFeVehicle>,
+    #[serde(default)]
+    pub reboque: Vec<NFeVehicle>,
+    pub vagao: Option<String>,
+    pub balsa: Option<String>,
+    #[serde(default)]
+    pub vol: Vec<NFeVolume>,
+}
+
+/// `transporta`: the carrier, whose fields are all optional in the layout.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeCarrier {
+    #[serde(rename = "CNPJ")]
+    pub cnpj: Option<String>,
+    #[serde(rename = "CPF")]
+    pub cpf: Option<String>,
+    #[serde(rename = "xNome")]
+    pub x_nome: Option<String>,
+    #[serde(rename = "IE")]
+    pub ie: Option<String>,
+    #[serde(rename = "xEnder")]
+    pub x_ender: Option<String>,
+    #[serde(rename = "xMun")]
+    pub x_mun: Option<String>,
+    #[serde(rename = "UF")]
+    pub uf: Option<String>,
+}
+
+/// `retTransp`: ICMS withheld on the freight service.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeRetTransp {
+    #[serde(rename = "vServ")]
+    pub v_serv: Decimal,
+    #[serde(rename = "vBCRet")]
+    pub v_bc_ret: Decimal,
+    #[serde(rename = "pICMSRet")]
+    pub p_icms_ret: Decimal,
+    #[serde(rename = "vICMSRet")]
+    pub v_icms_ret: Decimal,
+    #[serde(rename = "CFOP")]
+    pub cfop: String,
+    #[serde(rename = "cMunFG")]
+    pub c_mun_fg: String,
+}
+
+/// `TVeiculo`, shared by `veicTransp` and `reboque`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeVehicle {
+    pub placa: String,
+    #[serde(rename = "UF")]
+    pub uf: Option<String>,
+    /// Registro Nacional de Transportador de Carga (ANTT).
+    #[serde(rename = "RNTC")]
+    pub rntc: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NFeVolume {
+    #[serde(rename = "qVol")]
+    pub q_vol: Option<String>,
+    pub esp: Option<String>,
+    pub marca: Option<String>,
+    #[serde(rename = "nVol")]
+    pub n_vol: Option<String>,
+    #[serde(rename = "pesoL")]
+    pub peso_l: Option<Decimal>,
+    #[serde(rename = "pesoB")]
+    pub peso_b: Option<Decimal>,
+    #[serde(default)]
+    pub lacres: Vec<NFeSeal>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct NFeSeal {
+    #[serde(rename = "nLacre")]
+    pub n_lacre: String,
+}
+
+impl Default for NFeTransport {
+    fn default() -> Self {
+        Self {
+            mod_frete: NO_FREIGHT.to_string(),
+            transporta: None,
+            ret_transp: None,
+            veic_transp: None,
+            reboque: Vec::new(),
+            vagao: None,
+            balsa: None,
+            vol: Vec::new(),
+        }
+    }
+}
+
+/// `TPlaca`: old plates (`AAA9999`), Mercosul plates (`AAA9A99`) and the shorter plates
+/// still accepted by the layout.
+fn is_plate(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let letters = bytes.iter().take_while(|b| b.is_ascii_uppercase()).count();
+    let all_digits_after = |from: usize| bytes[from..].iter().all(u8::is_ascii_digit);
+    match bytes.len() {
+        6 => letters == 2 && all_digits_after(2),
+        7 => bytes
+            .iter()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+impl NFeVehicle {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        if !is_plate(&self.placa) {
+            return Err(ValidationError::new(
+                &format!("{}.placa", group),
+                "must be a plate such as ABC1234 or ABC1D23",
+            ));
+        }
+        if let Some(uf) = &self.uf {
+            // A vehicle plated abroad cannot be informed in the group.
+            if !UF_ACRONYMS.contains(&uf.as_str()) {
+                return Err(ValidationError::new(
+                    &format!("{}.UF", group),
+                    "must be a Brazilian state",
+                ));
+            }
+        }
+        validate_optional_text(&format!("{}.RNTC", group), self.rntc.as_deref(), 1, 20)
+    }
+}
+
+impl NFeCarrier {
+    fn validate(&self) -> Result<(), ValidationError> {
+        if self.cnpj.is_some() || self.cpf.is_some() {
+            validate_document(
+                "transp.transporta",
+                self.cnpj.as_deref(),
+                self.cpf.as_deref(),
+                0,
+            )?;
+        }
+        validate_optional_text("transp.transporta.xNome", self.x_nome.as_deref(), 2, 60)?;
+        if let Some(ie) = &self.ie {
+            if ie != "ISENTO" && !is_digits(ie, 2, 14) {
+                return Err(ValidationError::new(
+                    "transp.transporta.IE",
+                    "must have 2 to 14 digits or be ISENTO",
+                ));
+            }
+        }
+        validate_optional_text("transp.transporta.xEnder", self.x_ender.as_deref(), 1, 60)?;
+        validate_optional_text("transp.transporta.xMun", self.x_mun.as_deref(), 1, 60)?;
+        if let Some(uf) = &self.uf {
+            if uf != UF_ABROAD && !UF_ACRONYMS.contains(&uf.as_str()) {
+                return Err(ValidationError::new(
+                    "transp.transporta.UF",
+                    "must be a state acronym or EX",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NFeRetTransp {
+    fn validate(&self) -> Result<(), ValidationError> {
+        let amounts = [
+            ("transp.retTransp.vServ", self.v_serv),
+            ("transp.retTransp.vBCRet", self.v_bc_ret),
+            ("transp.retTransp.pICMSRet", self.p_icms_ret),
+            ("transp.retTransp.vICMSRet", self.v_icms_ret),
+        ];
+        for (field, amount) in amounts {
+            if amount < Decimal::ZERO {
+                return Err(ValidationError::new(field, "must not be negative"));
+            }
+        }
+        if self.p_icms_ret > Decimal::ONE_HUNDRED {
+            return Err(ValidationError::new(
+                "transp.retTransp.pICMSRet",
+                "must not exceed 100",
+            ));
+        }
+        let expected = (self.v_bc_ret * self.p_icms_ret / Decimal::ONE_HUNDRED).round_dp(2);
+        if (self.v_icms_ret - expected).abs() > Decimal::new(1, 2) {
+            return Err(ValidationError::new(
+                "transp.retTransp.vICMSRet",
+                format!("must be vBCRet x pICMSRet ({})", expected),
+            ));
+        }
+        if !is_digits(&self.cfop, 4, 4)
+            || !matches!(&self.cfop[..1], "1" | "2" | "3" | "5" | "6" | "7")
+        {
+            return Err(ValidationError::new(
+                "transp.retTransp.CFOP",
+                "must be a 4-digit CFOP",
+            ));
+        }
+        if !is_digits(&self.c_mun_fg, 7, 7) {
+            return Err(ValidationError::new(
+                "transp.retTransp.cMunFG",
+                "must be a 7-digit IBGE code",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl NFeVolume {
+    fn validate(&self, group: &str) -> Result<(), ValidationError> {
+        if let Some(q_vol) = &self.q_vol {
+            if !is_digits(q_vol, 1, 15) {
+                return Err(ValidationError::new(
+                    &format!("{}.qVol", group),
+                    "must have 1 to 15 digits",
+                ));
+            }
+        }
+        validate_optional_text(&format!("{}.esp", group), self.esp.as_deref(), 1, 60)?;
+        validate_optional_text(&format!("{}.marca", group), self.marca.as_deref(), 1, 60)?;
+        validate_optional_text(&format!("{}.nVol", group), self.n_vol.as_deref(), 1, 60)?;
+        for (name, weight) in [("pesoL", self.peso_l), ("pesoB", self.peso_b)] {
+            if weight.is_some_and(|w| w < Decimal::ZERO) {
+                return Err(ValidationError::new(
+                    &format!("{}.{}", group, name),
+                    "must not be negative",
+                ));
+            }
+        }
+        if let (Some(peso_l), Some(peso_b)) = (self.peso_l, self.peso_b) {
+            if peso_l > peso_b {
+                return Err(ValidationError::new(
+                    &format!("{}.pesoL", group),
+                    "must not exceed pesoB",
+                ));
+            }
+        }
+        if self.lacres.len() > MAX_VOLUMES {
+            return Err(ValidationError::new(
+                &format!("{}.lacres", group),
+                format!("must have at most {} seals", MAX_VOLUMES),
+            ));
+        }
+        for seal in &self.lacres {
+            validate_text(&format!("{}.lacres.nLacre", group), &seal.n_lacre, 1, 60)?;
+        }
+        Ok(())
+    }
+}
+
+impl NFeTransport {
+    /// Whether a vehicle, wagon or ferry is informed.
+    pub fn has_conveyance(&self) -> bool {
+        self.veic_transp.is_some()
+            || !self.reboque.is_empty()
+            || self.vagao.is_some()
+            || self.balsa.is_some()
+    }
+
+    /// Checks the group against the model and destination of the note. Road vehicles
+    /// are informed only in operations inside the state (idDest 1), and the NFC-e takes
+    /// neither vehicles nor the ICMS withheld on freight.
+    pub fn validate(&self, mod_: &str, id_dest: &str) -> Result<(), ValidationError> {
+        if !matches!(self.mod_frete.as_str(), "0" | "1" | "2" | "3" | "4" | "9") {
+            return Err(ValidationError::new(
+                "transp.modFrete",
+                "must be 0, 1, 2, 3, 4 or 9",
+            ));
+        }
+        if self.mod_frete == NO_FREIGHT
+            && (self.transporta.is_some() || self.ret_transp.is_some() || self.has_conveyance())
+        {
+            return Err(ValidationError::new(
+                "transp.modFrete",
+                "9 (no transport) takes no carrier, retTransp or vehicle",
+            ));
+        }
+        if let Some(transporta) = &self.transporta {
+            transporta.validate()?;
+        }
+        if let Some(ret_transp) = &self.ret_transp {
+            if mod_ == "65" {
+                return Err(ValidationError::new(
+                    "transp.retTransp",
+                    "must not be informed for mod 65",
+                ));
+            }
+            ret_transp.validate()?;
+        }
+
+        let road = self.veic_transp.is_some() || !self.reboque.is_empty();
+        let alternatives = usize::from(road)
+            + usize::from(self.vagao.is_some())
+            + usize::from(self.balsa.is_some());
+        if alternatives > 1 {
+            return Err(ValidationError::new(
+                "transp",
+                "only one of veicTransp/reboque, vagao or balsa may be informed",
+            ));
+        }
+        if self.has_conveyance() && mod_ == "65" {
+            return Err(ValidationError::new(
+                "transp.veicTransp",
+                "vehicles are not informed for mod 65",
+            ));
+        }
+        if road && id_dest != "1" {
+            return Err(ValidationError::new(
+                "transp.veicTransp",
+                "veicTransp and reboque are only informed when idDest is 1",
+            ));
+        }
+        if let Some(vehicle) = &self.veic_transp {
+            vehicle.validate("transp.veicTransp")?;
+        }
+        if self.reboque.len() > MAX_TRAILERS {
+            return Err(ValidationError::new(
+                "transp.reboque",
+                format!("must have at most {} trailers", MAX_TRAILERS),
+            ));
+        }
+        for trailer in &self.reboque {
+            trailer.validate("transp.reboque")?;
+        }
+        validate_optional_text("transp.vagao", self.vagao.as_deref(), 1, 20)?;
+        validate_optional_text("transp.balsa", self.balsa.as_deref(), 1, 20)?;
+
+        if self.vol.len() > MAX_VOLUMES {
+            return Err(ValidationError::new(
+                "transp.vol",
+                format!("must have at most {} volumes", MAX_VOLUMES),
+            ));
+        }
+        for (i, volume) in self.vol.iter().enumerate() {
+            volume.validate(&format!("transp.vol[{}]", i + 1))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn truck() -> NFeTransport {
+        NFeTransport {
+            mod_frete: "0".to_string(),
+            transporta: Some(NFeCarrier {
+                cnpj: Some("11222333000181".to_string()),
+                x_nome: Some("Transportes Rapidos Ltda".to_string()),
+                ie: Some("ISENTO".to_string()),
+                uf: Some("SP".to_string()),
+                ..Default::default()
+            }),
+            ret_transp: None,
+            veic_transp: Some(NFeVehicle {
+                placa: "ABC1D23".to_string(),
+                uf: Some("SP".to_string()),
+                rntc: Some("12345678".to_string()),
+            }),
+            reboque: Vec::new(),
+            vagao: None,
+            balsa: None,
+            vol: vec![NFeVolume {
+                q_vol: Some("2".to_string()),
+                esp: Some("CAIXA".to_string()),
+                peso_l: Some(dec!(10.500)),
+                peso_b: Some(dec!(11.000)),
+                lacres: vec![NFeSeal {
+                    n_lacre: "L-001".to_string(),
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn allows_vehicles_only_inside_the_state() {
+        assert!(NFeTransport::default().validate("65", "1").is_ok());
+        assert!(truck().validate("55", "1").is_ok());
+        assert_eq!(
+            truck().validate("55", "2").unwrap_err().field,
+            "transp.veicTransp"
+        );
+        assert_eq!(
+            truck().validate("65", "1").unwrap_err().field,
+            "transp.veicTransp"
+        );
+
+        // A wagon is not a road vehicle, but it excludes one.
+        let mut rail = NFeTransport {
+            veic_transp: None,
+            vagao: Some("VG-1020".to_string()),
+            ..truck()
+        };
+        assert!(rail.validate("55", "2").is_ok());
+        rail.balsa = Some("BALSA 7".to_string());
+        assert_eq!(rail.validate("55", "2").unwrap_err().field, "transp");
+
+        let foreign = NFeTransport {
+            veic_transp: Some(NFeVehicle {
+                placa: "ABC1234".to_string(),
+                uf: Some("EX".to_string()),
+                rntc: None,
+            }),
+            ..truck()
+        };
+        assert_eq!(
+            foreign.validate("55", "1").unwrap_err().field,
+            "transp.veicTransp.UF"
+        );
+        let no_freight = NFeTransport {
+            mod_frete: NO_FREIGHT.to_string(),
+            ..truck()
+        };
+        assert_eq!(
+            no_freight.validate("55", "1").unwrap_err().field,
+            "transp.modFrete"
+        );
+    }
+
+    #[test]
+    fn checks_plates_volumes_and_withheld_icms() {
+        assert!(is_plate("ABC1234"));
+        assert!(is_plate("ABC1D23"));
+        assert!(is_plate("AB1234"));
+        assert!(!is_plate("abc1234"));
+        assert!(!is_plate("ABC-1234"));
+
+        let mut transport = truck();
+        transport.vol[0].peso_l = Some(dec!(12));
+        assert_eq!(
+            transport.validate("55", "1").unwrap_err().field,
+            "transp.vol[1].pesoL"
+        );
+
+        let mut transport = truck();
+        transport.ret_transp = Some(NFeRetTransp {
+            v_serv: dec!(200.00),
+            v_bc_ret: dec!(200.00),
+            p_icms_ret: dec!(12),
+            v_icms_ret: dec!(24.00),
+            cfop: "5352".to_string(),
+            c_mun_fg: "3550308".to_string(),
+        });
+        assert!(transport.validate("55", "1").is_ok());
+        transport.ret_transp.as_mut().unwrap().v_icms_ret = dec!(20.00);
+        assert_eq!(
+            transport.validate("55", "1").unwrap_err().field,
+            "transp.retTransp.vICMSRet"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use crate::errors::ValidationError;
+
+/// Checks the length of a free-text field, counted in characters after trimming.
+pub fn validate_text(
+    field: &str,
+    value: &str,
+    min: usize,
+    max: usize,
+) -> Result<(), ValidationError> {
+    let length = value.trim().chars().count();
+    if length < min || length > max {
+        return Err(ValidationError::new(
+            field,
+            format!("must have {} to {} characters, got {}", min, max, length),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_optional_text(
+    field: &str,
+    value: Option<&str>,
+    min: usize,
+    max: usize,
+) -> Result<(), ValidationError> {
+    match value {
+        Some(value) => validate_text(field, value, min, max),
+        None => Ok(()),
+    }
+}
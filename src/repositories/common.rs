@@ -0,0 +1,126 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::services::sefaz::soap::SoapClient;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use oracle::pool::Pool;
+use oracle::sql_type::{OracleType, ToSql, ToSqlNull};
+use oracle::{Connection, SqlValue};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+use tracing::error;
+use uuid::Uuid;
+
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Validates an internal key and formats it for Oracle HEXTORAW (no hyphens).
+pub fn to_oracle_uuid(internal_key: &str) -> Result<String, RepositoryError> {
+    let uuid =
+        Uuid::parse_str(internal_key).map_err(|e| RepositoryError::InvalidUuid(e.to_string()))?;
+    Ok(uuid.to_string().replace('-', ""))
+}
+
+/// Parses a timestamp selected with `TO_CHAR(..., 'YYYY-MM-DD HH24:MI:SS.FF3')`.
+pub fn parse_timestamp(value: &str) -> DateTime<Utc> {
+    NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT)
+        .map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+/// ORA-00001: a unique index or constraint refused the row.
+pub fn is_unique_violation(err: &oracle::Error) -> bool {
+    matches!(err, oracle::Error::OciError(db) if db.code() == 1)
+}
+
+/// Runs `work` on a connection of its own from the pool and commits everything it wrote
+/// as one transaction, or rolls it all back when any step fails.
+pub fn in_transaction<T>(
+    pool: &Pool,
+    work: impl FnOnce(&Connection) -> Result<T, RepositoryError>,
+) -> Result<T, RepositoryError> {
+    let conn = pool.get()?;
+    match work(&conn) {
+        Ok(value) => {
+            conn.commit()?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = conn.rollback() {
+                error!("Failed to roll back the transaction: {}", rollback_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Returns `NotFound` unless the parent `nfe_identifications` row exists.
+pub fn ensure_identification_exists(
+    conn: &Connection,
+    oracle_uuid: &str,
+) -> Result<(), RepositoryError> {
+    let count: u64 = conn.query_row_as(
+        "SELECT COUNT(*) FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+        &[&oracle_uuid],
+    )?;
+    if count == 0 {
+        return Err(RepositoryError::NotFound);
+    }
+    Ok(())
+}
+
+/// Refuses requests whose `tpAmb` differs from the environment configured for the state.
+pub fn check_environment(
+    sefaz: &SoapClient,
+    c_uf: &str,
+    tp_amb: &str,
+) -> Result<(), RepositoryError> {
+    let state = sefaz.config().state(c_uf)?;
+    if state.tp_amb != tp_amb {
+        return Err(ValidationError::new(
+            "tpAmb",
+            format!(
+                "SEFAZ of cUF {} is configured for tpAmb {}",
+                c_uf, state.tp_amb
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// A decimal bound to a NUMBER column from its exact text. The driver converts the text
+/// itself, so neither float rounding nor the session's NLS decimal separator applies.
+pub struct NumberBind(String);
+
+impl ToSql for NumberBind {
+    fn oratype(&self, _conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::Number(0, 0))
+    }
+
+    fn to_sql(&self, val: &mut SqlValue) -> oracle::Result<()> {
+        val.set(&self.0)
+    }
+}
+
+impl ToSqlNull for NumberBind {
+    fn oratype_for_null(_conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::Number(0, 0))
+    }
+}
+
+pub fn decimal_bind(value: &Decimal) -> NumberBind {
+    NumberBind(value.to_string())
+}
+
+pub fn optional_decimal_bind(value: &Option<Decimal>) -> Option<NumberBind> {
+    value.as_ref().map(decimal_bind)
+}
+
+/// Parses a NUMBER column fetched as text.
+pub fn parse_decimal(value: &str) -> Result<Decimal, RepositoryError> {
+    Decimal::from_str(value)
+        .or_else(|_| Decimal::from_scientific(value))
+        .map_err(|e| RepositoryError::InvalidData(format!("{}: {}", value, e)))
+}
+
+pub fn parse_optional_decimal(value: Option<String>) -> Result<Option<Decimal>, RepositoryError> {
+    value.as_deref().map(parse_decimal).transpose()
+}
@@ -1 +1,19 @@
+pub mod common;
+pub mod nfe_access_key_repository;
+pub mod nfe_additional_repository;
+pub mod nfe_authorization_repository;
+pub mod nfe_contingency_repository;
+pub mod nfe_distribution_repository;
+pub mod nfe_document_repository;
+pub mod nfe_event_repository;
 pub mod nfe_identification_repository;
+pub mod nfe_import_repository;
+pub mod nfe_inutilization_repository;
+pub mod nfe_item_repository;
+pub mod nfe_numbering_repository;
+pub mod nfe_participant_repository;
+pub mod nfe_payment_repository;
+pub mod nfe_reference_repository;
+pub mod nfe_status_repository;
+pub mod nfe_total_repository;
+pub mod nfe_transport_repository;
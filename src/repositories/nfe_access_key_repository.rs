@@ -0,0 +1,169 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_access_key::{aamm, AccessKey, AccessKeyParts};
+use crate::repositories::common::{is_unique_violation, parse_timestamp, to_oracle_uuid};
+use crate::repositories::nfe_numbering_repository::number_taken;
+use oracle::Connection;
+use std::sync::Arc;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+/// Builds the access key of an identification from `ide` and the emitter document.
+/// Returns `None` while the identification has no emitter.
+pub fn find_access_key(
+    conn: &Connection,
+    oracle_uuid: &str,
+) -> Result<Option<AccessKey>, RepositoryError> {
+    let sql = r#"
+        SELECT
+            i.CUF as c_uf,
+            TO_CHAR(i.DHEMI, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_emi,
+            NVL(e.CNPJ, e.CPF) as document,
+            i.MOD_ as mod_,
+            i.SERIE as serie,
+            i.NNF as n_nf,
+            i.TPEMIS as tp_emis,
+            i.CNF as c_nf
+        FROM nfe_identifications i
+        LEFT JOIN nfe_emitters e ON e.INTERNALKEY = i.INTERNALKEY
+        WHERE i.INTERNALKEY = HEXTORAW(:1)
+    "#;
+
+    let row = match conn.query_row(sql, &[&oracle_uuid]) {
+        Ok(row) => row,
+        Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+
+    let document: Option<String> = row.get("document")?;
+    let Some(document) = document else {
+        return Ok(None);
+    };
+
+    let c_uf: String = row.get("c_uf")?;
+    let dh_emi = parse_timestamp(&row.get::<_, String>("dh_emi")?);
+    let key = AccessKey::new(AccessKeyParts {
+        aamm: aamm(&c_uf, &dh_emi),
+        c_uf,
+        document,
+        mod_: row.get("mod_")?,
+        serie: row.get("serie")?,
+        n_nf: row.get("n_nf")?,
+        tp_emis: row.get("tp_emis")?,
+        c_nf: row.get("c_nf")?,
+    })?;
+    Ok(Some(key))
+}
+
+/// Finds the identification whose access key is `key`. Candidates are narrowed by the
+/// stored key fields and confirmed against the derived key, which also covers AAMM.
+pub fn find_by_access_key(
+    conn: &Connection,
+    key: &AccessKey,
+) -> Result<Option<String>, RepositoryError> {
+    let sql = r#"
+        SELECT RAWTOHEX(i.INTERNALKEY) as internal_key
+        FROM nfe_identifications i
+        JOIN nfe_emitters e ON e.INTERNALKEY = i.INTERNALKEY
+        WHERE i.CUF = :1
+          AND i.MOD_ = :2
+          AND TO_NUMBER(i.SERIE) = TO_NUMBER(:3)
+          AND TO_NUMBER(i.NNF) = TO_NUMBER(:4)
+          AND i.TPEMIS = :5
+          AND i.CNF = :6
+          AND LPAD(NVL(e.CNPJ, e.CPF), 14, '0') = :7
+    "#;
+
+    let parts = &key.parts;
+    let rows = conn.query(
+        sql,
+        &[
+            &parts.c_uf,
+            &parts.mod_,
+            &parts.serie,
+            &parts.n_nf,
+            &parts.tp_emis,
+            &parts.c_nf,
+            &parts.document,
+        ],
+    )?;
+    for row in rows {
+        let oracle_uuid: String = row?.get("internal_key")?;
+        if find_access_key(conn, &oracle_uuid)?.as_ref() == Some(key) {
+            let uuid = Uuid::parse_str(&oracle_uuid)
+                .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+            return Ok(Some(uuid.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Refuses an emitter whose document is not the one the note was numbered under: nNF
+/// belongs to the serie of that document, so changing it would leave a gap in one serie
+/// and take a number of another.
+pub fn check_emitter_document(
+    conn: &Connection,
+    oracle_uuid: &str,
+    document: Option<&str>,
+) -> Result<(), RepositoryError> {
+    let emit_doc: Option<String> = match conn.query_row_as(
+        "SELECT EMITDOC FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+        &[&oracle_uuid],
+    ) {
+        Ok(emit_doc) => emit_doc,
+        Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+    match (emit_doc, document) {
+        (Some(emit_doc), Some(document)) if emit_doc != document => {
+            Err(RepositoryError::Conflict(format!(
+                "the note was numbered for emitter {}; issue a new note for {}",
+                emit_doc, document
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Recomputes `cDV` after a change to any field of the key; it is cleared while the
+/// identification has no emitter. Notes created before EMITDOC was required take the
+/// document of their emitter, so the unique numbering index covers them too.
+pub fn refresh_check_digit(conn: &Connection, oracle_uuid: &str) -> Result<(), RepositoryError> {
+    let c_dv = find_access_key(conn, oracle_uuid)?.map(|key| key.c_dv);
+    let result = conn.execute(
+        r#"
+        UPDATE nfe_identifications i
+        SET CDV = :1,
+            EMITDOC = NVL(
+                EMITDOC,
+                (SELECT NVL(e.CNPJ, e.CPF) FROM nfe_emitters e WHERE e.INTERNALKEY = i.INTERNALKEY)
+            )
+        WHERE INTERNALKEY = HEXTORAW(:2)
+        "#,
+        &[&c_dv, &oracle_uuid],
+    );
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if is_unique_violation(&e) => Err(number_taken()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub struct NFeAccessKeyRepository {
+    conn: Arc<Connection>,
+}
+
+impl NFeAccessKeyRepository {
+    pub fn new(conn: Arc<Connection>) -> Self {
+        Self { conn }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find(&self, internal_key: &str) -> Result<AccessKey, RepositoryError> {
+        info!("Building access key for NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        find_access_key(&self.conn, &oracle_uuid)?.ok_or_else(|| {
+            ValidationError::new("emit", "an emitter is required to build the access key").into()
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_additional::{
+    validate_authorized_downloads, NFeAdditionalInfo, NFeAuthorizedDownload, NFeTechResponsible,
+};
+use crate::repositories::common::to_oracle_uuid;
+use crate::repositories::nfe_access_key_repository::find_access_key;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::xml::csrt::Csrt;
+use oracle::Connection;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+
+/// Columns of `nfe_additional_info`, one per group.
+pub const INFADIC: &str = "INFADIC";
+pub const INFRESPTEC: &str = "INFRESPTEC";
+pub const AUTXML: &str = "AUTXML";
+
+/// Replaces the group in `column`; `None` clears it.
+pub fn store_group<T: Serialize + ?Sized>(
+    conn: &Connection,
+    oracle_uuid: &str,
+    column: &str,
+    group: Option<&T>,
+) -> Result<(), RepositoryError> {
+    let json = group
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+    let sql = format!(
+        r#"
+        MERGE INTO nfe_additional_info t
+        USING (SELECT HEXTORAW(:1) AS INTERNALKEY FROM dual) s
+        ON (t.INTERNALKEY = s.INTERNALKEY)
+        WHEN MATCHED THEN UPDATE SET {0} = :2
+        WHEN NOT MATCHED THEN INSERT (INTERNALKEY, {0}) VALUES (s.INTERNALKEY, :3)
+        "#,
+        column
+    );
+    conn.execute(&sql, &[&oracle_uuid, &json, &json])?;
+    Ok(())
+}
+
+/// Persists `infAdic`, `infRespTec` and `autXML` of an identification. With a CSRT
+/// configured, the technical responsible is completed with `idCSRT` and `hashCSRT`.
+pub struct NFeAdditionalRepository {
+    conn: Arc<Connection>,
+    status: Arc<NFeStatusRepository>,
+    csrt: Option<Csrt>,
+}
+
+impl NFeAdditionalRepository {
+    pub fn new(conn: Arc<Connection>, status: Arc<NFeStatusRepository>) -> Self {
+        Self {
+            conn,
+            status,
+            csrt: None,
+        }
+    }
+
+    pub fn with_csrt(self, csrt: Option<Csrt>) -> Self {
+        Self { csrt, ..self }
+    }
+
+    /// The group stored in `column`; NotFound when the identification does not exist.
+    fn find_group<T: DeserializeOwned>(
+        &self,
+        oracle_uuid: &str,
+        column: &str,
+    ) -> Result<Option<T>, RepositoryError> {
+        let sql = format!(
+            r#"
+            SELECT a.{}
+            FROM nfe_identifications i
+            LEFT JOIN nfe_additional_info a ON a.INTERNALKEY = i.INTERNALKEY
+            WHERE i.INTERNALKEY = HEXTORAW(:1)
+            "#,
+            column
+        );
+        let json = match self
+            .conn
+            .query_row_as::<Option<String>>(&sql, &[&oracle_uuid])
+        {
+            Ok(json) => json,
+            Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        json.map(|json| {
+            serde_json::from_str(&json).map_err(|e| RepositoryError::InvalidData(e.to_string()))
+        })
+        .transpose()
+    }
+
+    async fn store_group<T: Serialize + ?Sized>(
+        &self,
+        internal_key: &str,
+        column: &str,
+        group: Option<&T>,
+    ) -> Result<(), RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        self.status
+            .edit(internal_key, |conn| {
+                store_group(conn, &oracle_uuid, column, group)
+            })
+            .await
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_additional_info(
+        &self,
+        internal_key: &str,
+    ) -> Result<NFeAdditionalInfo, RepositoryError> {
+        info!("Fetching additional information of NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        Ok(self.find_group(&oracle_uuid, INFADIC)?.unwrap_or_default())
+    }
+
+    #[instrument(skip(self, inf_adic), fields(internal_key = %internal_key))]
+    pub async fn update_additional_info(
+        &self,
+        internal_key: &str,
+        inf_adic: &NFeAdditionalInfo,
+    ) -> Result<NFeAdditionalInfo, RepositoryError> {
+        info!("Updating additional information of NFe identification");
+        debug!("Input data: {:?}", inf_adic);
+
+        inf_adic.validate()?;
+        self.find_additional_info(internal_key).await?;
+        let group = (!inf_adic.is_empty()).then_some(inf_adic);
+        self.store_group(internal_key, INFADIC, group).await?;
+
+        info!(
+            "Successfully updated additional information of {}",
+            internal_key
+        );
+        Ok(inf_adic.clone())
+    }
+
+    /// The technical responsible, with `idCSRT` and `hashCSRT` once the access key of
+    /// the note can be built.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_tech_responsible(
+        &self,
+        internal_key: &str,
+    ) -> Result<Option<NFeTechResponsible>, RepositoryError> {
+        info!("Fetching technical responsible of NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let mut responsible: Option<NFeTechResponsible> =
+            self.find_group(&oracle_uuid, INFRESPTEC)?;
+        if let (Some(responsible), Some(csrt)) = (responsible.as_mut(), &self.csrt) {
+            if let Some(key) = find_access_key(&self.conn, &oracle_uuid)? {
+                csrt.complete(responsible, &key.to_string());
+            }
+        }
+        Ok(responsible)
+    }
+
+    #[instrument(skip(self, responsible), fields(internal_key = %internal_key))]
+    pub async fn update_tech_responsible(
+        &self,
+        internal_key: &str,
+        responsible: Option<&NFeTechResponsible>,
+    ) -> Result<Option<NFeTechResponsible>, RepositoryError> {
+        info!("Updating technical responsible of NFe identification");
+        debug!("Input data: {:?}", responsible);
+
+        if let Some(responsible) = responsible {
+            responsible.validate()?;
+        }
+        self.find_group::<NFeTechResponsible>(&to_oracle_uuid(internal_key)?, INFRESPTEC)?;
+        self.store_group(internal_key, INFRESPTEC, responsible)
+            .await?;
+
+        info!(
+            "Successfully updated technical responsible of {}",
+            internal_key
+        );
+        self.find_tech_responsible(internal_key).await
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_authorized_downloads(
+        &self,
+        internal_key: &str,
+    ) -> Result<Vec<NFeAuthorizedDownload>, RepositoryError> {
+        info!("Fetching autXML of NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        Ok(self.find_group(&oracle_uuid, AUTXML)?.unwrap_or_default())
+    }
+
+    #[instrument(skip(self, authorized), fields(internal_key = %internal_key))]
+    pub async fn replace_authorized_downloads(
+        &self,
+        internal_key: &str,
+        authorized: &[NFeAuthorizedDownload],
+    ) -> Result<Vec<NFeAuthorizedDownload>, RepositoryError> {
+        info!("Replacing autXML of NFe identification");
+        debug!("Input data: {:?}", authorized);
+
+        validate_authorized_downloads(authorized)?;
+        self.find_authorized_downloads(internal_key).await?;
+        let group = (!authorized.is_empty()).then_some(authorized);
+        self.store_group(internal_key, AUTXML, group).await?;
+
+        info!("Successfully replaced autXML of {}", internal_key);
+        Ok(authorized.to_vec())
+    }
+}
@@ -0,0 +1,503 @@
+use crate::errors::{RepositoryError, SefazError, ValidationError};
+use crate::models::nfe_protocol::{NFeProtocol, DUPLICATE, PENDING};
+use crate::models::nfe_status::NFeStatus;
+use crate::repositories::common::{
+    check_environment, parse_timestamp, to_oracle_uuid, TIMESTAMP_FORMAT,
+};
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::sefaz::authorization::{self, BatchOutcome, ProtNFe};
+use crate::services::sefaz::config::authorizer;
+use crate::services::sefaz::consultation;
+use crate::services::sefaz::soap::SoapClient;
+use crate::services::signing_service::SigningService;
+use crate::services::xml::nfce_supplement::NFCeSupplement;
+use crate::services::xml::nfe_serializer;
+use crate::services::xml::schema_validator::{SchemaReport, SchemaValidator};
+use chrono::Utc;
+use oracle::{Connection, Row};
+use std::sync::Arc;
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+const PROTOCOL_COLUMNS: &str = r#"
+    RAWTOHEX(INTERNALKEY) as internal_key,
+    TPAMB as tp_amb,
+    VERAPLIC as ver_aplic,
+    CHNFE as ch_nfe,
+    TO_CHAR(DHRECBTO, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_recbto,
+    NPROT as n_prot,
+    DIGVAL as dig_val,
+    CSTAT as c_stat,
+    XMOTIVO as x_motivo,
+    NREC as n_rec,
+    TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at
+"#;
+
+fn map_protocol(row: &Row) -> Result<NFeProtocol, RepositoryError> {
+    let oracle_uuid: String = row.get("internal_key")?;
+    let internal_key = Uuid::parse_str(&oracle_uuid)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+        .to_string();
+    Ok(NFeProtocol {
+        internal_key,
+        tp_amb: row.get("tp_amb")?,
+        ver_aplic: row.get("ver_aplic")?,
+        ch_nfe: row.get("ch_nfe")?,
+        dh_recbto: row
+            .get::<_, Option<String>>("dh_recbto")?
+            .as_deref()
+            .map(parse_timestamp),
+        n_prot: row.get("n_prot")?,
+        dig_val: row.get("dig_val")?,
+        c_stat: row.get("c_stat")?,
+        x_motivo: row.get("x_motivo")?,
+        n_rec: row.get("n_rec")?,
+        created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+    })
+}
+
+/// Stores a `protNFe` with the signed NFe it answers; `n_rec` is kept while the batch
+/// is pending.
+pub fn insert_protocol(
+    conn: &Connection,
+    oracle_uuid: &str,
+    prot: &ProtNFe,
+    n_rec: Option<&str>,
+    signed: &str,
+) -> Result<(), RepositoryError> {
+    let sql = r#"
+        INSERT INTO nfe_protocols (
+            ID,
+            INTERNALKEY,
+            TPAMB,
+            VERAPLIC,
+            CHNFE,
+            DHRECBTO,
+            NPROT,
+            DIGVAL,
+            CSTAT,
+            XMOTIVO,
+            NREC,
+            NFEXML,
+            PROTXML
+        ) VALUES (
+            HEXTORAW(:1),
+            HEXTORAW(:2),
+            :3,
+            :4,
+            :5,
+            TO_TIMESTAMP(:6, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+            :7,
+            :8,
+            :9,
+            :10,
+            :11,
+            :12,
+            :13
+        )
+    "#;
+    let id = Uuid::new_v4().to_string().replace('-', "");
+    let ver_aplic = Some(prot.ver_aplic.as_str()).filter(|v| !v.is_empty());
+    let prot_xml = Some(prot.xml.as_str()).filter(|x| !x.is_empty());
+    conn.execute(
+        sql,
+        &[
+            &id,
+            &oracle_uuid,
+            &prot.tp_amb,
+            &ver_aplic,
+            &prot.ch_nfe,
+            &prot.dh_recbto.format(TIMESTAMP_FORMAT).to_string(),
+            &prot.n_prot,
+            &prot.dig_val,
+            &prot.c_stat,
+            &prot.x_motivo,
+            &n_rec,
+            &signed,
+            &prot_xml,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Moves notes through validation, signing and transmission to SEFAZ, and keeps every
+/// `protNFe` received for them.
+pub struct NFeAuthorizationRepository {
+    conn: Arc<Connection>,
+    documents: Arc<NFeDocumentRepository>,
+    status: Arc<NFeStatusRepository>,
+    validator: Arc<SchemaValidator>,
+    signing: Arc<SigningService>,
+    sefaz: Arc<SoapClient>,
+}
+
+impl NFeAuthorizationRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        documents: Arc<NFeDocumentRepository>,
+        status: Arc<NFeStatusRepository>,
+        validator: Arc<SchemaValidator>,
+        signing: Arc<SigningService>,
+        sefaz: Arc<SoapClient>,
+    ) -> Self {
+        Self {
+            conn,
+            documents,
+            status,
+            validator,
+            signing,
+            sefaz,
+        }
+    }
+
+    /// Latest protocol of the note.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find(&self, internal_key: &str) -> Result<NFeProtocol, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.latest(&oracle_uuid)?.ok_or(RepositoryError::NotFound)
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_all(&self, internal_key: &str) -> Result<Vec<NFeProtocol>, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let sql = format!(
+            "SELECT {} FROM nfe_protocols WHERE INTERNALKEY = HEXTORAW(:1) ORDER BY CREATEDAT",
+            PROTOCOL_COLUMNS
+        );
+        let rows = self.conn.query(&sql, &[&oracle_uuid])?;
+        rows.map(|row| map_protocol(&row?)).collect()
+    }
+
+    /// Checks the rules between the groups of the note and the generated XML against the
+    /// layout schema, and moves a draft or rejected note to validated when both pass.
+    /// The schema report is returned either way.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn validate(&self, internal_key: &str) -> Result<SchemaReport, RepositoryError> {
+        info!("Validating NFe");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let status = self.status.current(&oracle_uuid)?;
+        if !status.is_editable() {
+            return Err(RepositoryError::Conflict(format!(
+                "the note is {} and cannot be validated again",
+                status
+            )));
+        }
+
+        let document = self.documents.find(internal_key).await?;
+        document.validate_groups()?;
+        let xml = nfe_serializer::serialize(&document)?;
+        let report = self.validator.validate_unsigned(&xml)?;
+        if report.valid && matches!(status, NFeStatus::Draft | NFeStatus::Rejected) {
+            self.status
+                .transition(internal_key, NFeStatus::Validated, None, None)
+                .await?;
+        }
+        Ok(report)
+    }
+
+    /// Signs the note, validating it first when needed, and keeps the signed XML for the
+    /// transmission. A note that is already signed returns the stored XML.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn sign(&self, internal_key: &str) -> Result<String, RepositoryError> {
+        info!("Signing NFe");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let status = self.status.current(&oracle_uuid)?;
+        if status == NFeStatus::Signed {
+            if let Some(signed) = self.status.signed_xml(&oracle_uuid)? {
+                return Ok(signed);
+            }
+        } else if !status.is_editable() {
+            return Err(RepositoryError::Conflict(format!(
+                "the note is {} and cannot be signed",
+                status
+            )));
+        }
+
+        if status != NFeStatus::Validated && status != NFeStatus::Signed {
+            let report = self.validate(internal_key).await?;
+            if let Some(violation) = report.violations.first() {
+                return Err(ValidationError::new(
+                    &violation.xpath,
+                    format!(
+                        "the XML does not match the layout schema: {}",
+                        violation.message
+                    ),
+                )
+                .into());
+            }
+        }
+
+        let document = self.documents.find(internal_key).await?;
+        let signed = if document.identification.mod_ == "65" {
+            let (qr_code, url_chave) = self
+                .sefaz
+                .config()
+                .nfce_urls(&document.identification.c_uf)?;
+            self.signing.sign_nfce(&document, qr_code, url_chave)?
+        } else {
+            self.signing.sign_nfe(&document)?
+        };
+        self.status.store_signed_xml(&oracle_uuid, &signed)?;
+        if status != NFeStatus::Signed {
+            self.status
+                .transition(internal_key, NFeStatus::Signed, None, None)
+                .await?;
+        }
+        Ok(signed)
+    }
+
+    /// Signs and sends the note, or queries the receipt of a batch that was still being
+    /// processed, and stores the answer. Rejections are stored and returned as well.
+    /// Notes queued in contingency are sent with the XML signed when they were issued,
+    /// and notes in SVC contingency go to the virtual SEFAZ. The note is claimed as
+    /// transmitted before SEFAZ is called; when the answer is lost or SEFAZ reports a
+    /// duplicate, the protocol is recovered by consulting the key.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn transmit(
+        &self,
+        internal_key: &str,
+        sync: bool,
+    ) -> Result<NFeProtocol, RepositoryError> {
+        info!("Transmitting NFe to SEFAZ");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let status = self.status.current(&oracle_uuid)?;
+        if !status.is_editable() && !matches!(status, NFeStatus::Transmitted | NFeStatus::Queued) {
+            return Err(RepositoryError::Conflict(format!(
+                "the note is {} and cannot be transmitted",
+                status
+            )));
+        }
+
+        let document = self.documents.find(internal_key).await?;
+        let ide = &document.identification;
+        // The NFC-e is authorized synchronously only.
+        let sync = sync || ide.mod_ == "65";
+        let authorizer = authorizer(&ide.c_uf, &ide.mod_, &ide.tp_emis);
+        check_environment(&self.sefaz, &authorizer, &ide.tp_amb)?;
+        let ch_nfe = document.access_key()?.to_string();
+
+        let pending = match status {
+            NFeStatus::Transmitted => self
+                .latest(&oracle_uuid)?
+                .filter(|p| p.is_pending())
+                .and_then(|p| p.n_rec),
+            _ => None,
+        };
+        let (signed, outcome) = if let Some(n_rec) = pending {
+            info!("Querying pending batch {}", n_rec);
+            let signed = self.sent_xml(&oracle_uuid)?;
+            let outcome =
+                authorization::poll(&self.sefaz, &authorizer, &ide.tp_amb, &n_rec).await?;
+            (signed, outcome)
+        } else if status == NFeStatus::Transmitted {
+            // The answer of the last send was lost, so SEFAZ may have the note already.
+            let signed = self.status.signed_xml(&oracle_uuid)?.ok_or_else(|| {
+                RepositoryError::InvalidData("transmitted note without signed XML".to_string())
+            })?;
+            let outcome = match self.consult(&authorizer, &ide.tp_amb, &ch_nfe).await? {
+                Some(prot) => BatchOutcome::Processed(vec![prot]),
+                None => {
+                    info!("SEFAZ has no record of the note; sending it again");
+                    self.send(internal_key, &authorizer, &ide.tp_amb, sync, &signed, None)
+                        .await?
+                }
+            };
+            (signed, outcome)
+        } else {
+            let (signed, from) = if status == NFeStatus::Queued {
+                let signed = self.status.signed_xml(&oracle_uuid)?.ok_or_else(|| {
+                    RepositoryError::InvalidData("queued note without signed XML".to_string())
+                })?;
+                (signed, NFeStatus::Queued)
+            } else {
+                (self.sign(internal_key).await?, NFeStatus::Signed)
+            };
+            self.status
+                .claim(internal_key, from, NFeStatus::Transmitted)
+                .await?;
+            let outcome = self
+                .send(
+                    internal_key,
+                    &authorizer,
+                    &ide.tp_amb,
+                    sync,
+                    &signed,
+                    Some(from),
+                )
+                .await?;
+            (signed, outcome)
+        };
+
+        match outcome {
+            BatchOutcome::Processed(protocols) => {
+                let mut prot = protocols
+                    .into_iter()
+                    .find(|p| p.ch_nfe == ch_nfe)
+                    .ok_or_else(|| {
+                        SefazError::InvalidResponse(format!("no protNFe for {}", ch_nfe))
+                    })?;
+                info!("SEFAZ answered {} - {}", prot.c_stat, prot.x_motivo);
+                if DUPLICATE.contains(&prot.c_stat.as_str()) {
+                    if let Some(found) = self.consult(&authorizer, &ide.tp_amb, &ch_nfe).await? {
+                        info!("Recovered protocol {} - {}", found.c_stat, found.x_motivo);
+                        prot = found;
+                    }
+                }
+                insert_protocol(&self.conn, &oracle_uuid, &prot, None, &signed)?;
+            }
+            BatchOutcome::Pending { n_rec } => {
+                let prot = ProtNFe {
+                    tp_amb: ide.tp_amb.clone(),
+                    ver_aplic: String::new(),
+                    ch_nfe,
+                    dh_recbto: Utc::now(),
+                    n_prot: None,
+                    dig_val: None,
+                    c_stat: PENDING.to_string(),
+                    x_motivo: "Lote em processamento".to_string(),
+                    xml: String::new(),
+                };
+                insert_protocol(&self.conn, &oracle_uuid, &prot, Some(&n_rec), &signed)?;
+            }
+        }
+        let protocol = self
+            .latest(&oracle_uuid)?
+            .ok_or(RepositoryError::CreationFailed)?;
+        if !protocol.is_pending() {
+            self.status
+                .transition(
+                    internal_key,
+                    protocol.status(),
+                    Some(&protocol.c_stat),
+                    Some(&protocol.x_motivo),
+                )
+                .await?;
+        }
+        Ok(protocol)
+    }
+
+    /// Sends the claimed note in a batch of its own. A batch refused as a whole rejects
+    /// the note; a batch that never reached SEFAZ hands the note back to `release`, if
+    /// given, while any other failure leaves it transmitted to be consulted later.
+    async fn send(
+        &self,
+        internal_key: &str,
+        authorizer: &str,
+        tp_amb: &str,
+        sync: bool,
+        signed: &str,
+        release: Option<NFeStatus>,
+    ) -> Result<BatchOutcome, RepositoryError> {
+        let id_lote = Utc::now().timestamp_millis().to_string();
+        let result = authorization::authorize(
+            &self.sefaz,
+            authorizer,
+            tp_amb,
+            &id_lote,
+            sync,
+            &[signed.to_string()],
+        )
+        .await;
+        match result {
+            Ok(outcome) => Ok(outcome),
+            Err(SefazError::Rejected { c_stat, x_motivo }) => {
+                // SEFAZ received the batch but refused it as a whole.
+                self.status
+                    .transition(
+                        internal_key,
+                        NFeStatus::Rejected,
+                        Some(&c_stat),
+                        Some(&x_motivo),
+                    )
+                    .await?;
+                Err(SefazError::Rejected { c_stat, x_motivo }.into())
+            }
+            Err(SefazError::Unreachable(reason)) => {
+                if let Some(from) = release {
+                    self.status
+                        .claim(internal_key, NFeStatus::Transmitted, from)
+                        .await?;
+                }
+                Err(SefazError::Unreachable(reason).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// `protNFe` of the note at SEFAZ, or `None` when SEFAZ has no record of its key.
+    async fn consult(
+        &self,
+        authorizer: &str,
+        tp_amb: &str,
+        ch_nfe: &str,
+    ) -> Result<Option<ProtNFe>, RepositoryError> {
+        let ret = consultation::query(&self.sefaz, authorizer, tp_amb, ch_nfe).await?;
+        match ret.prot_nfe {
+            Some(prot) => Ok(Some(prot)),
+            None if ret.c_stat == consultation::NOT_FOUND => Ok(None),
+            None => Err(SefazError::Rejected {
+                c_stat: ret.c_stat,
+                x_motivo: ret.x_motivo,
+            }
+            .into()),
+        }
+    }
+
+    /// Protocol that authorized the note, if it was authorized through this service.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_authorization(
+        &self,
+        internal_key: &str,
+    ) -> Result<Option<NFeProtocol>, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let sql = format!(
+            "SELECT {} FROM nfe_protocols WHERE INTERNALKEY = HEXTORAW(:1) AND CSTAT IN ('100', '150') ORDER BY CREATEDAT DESC FETCH FIRST 1 ROWS ONLY",
+            PROTOCOL_COLUMNS
+        );
+        match self.conn.query_row(&sql, &[&oracle_uuid]) {
+            Ok(row) => Ok(Some(map_protocol(&row)?)),
+            Err(oracle::Error::NoDataFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// `infNFeSupl` of a signed NFC-e, whose QR code its DANFE prints.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_nfce_supplement(
+        &self,
+        internal_key: &str,
+    ) -> Result<NFCeSupplement, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let signed = self.status.signed_xml(&oracle_uuid)?.ok_or_else(|| {
+            RepositoryError::Conflict("the NFC-e has not been signed yet".to_string())
+        })?;
+        NFCeSupplement::parse(&signed).ok_or_else(|| {
+            RepositoryError::InvalidData("signed NFC-e without infNFeSupl".to_string())
+        })
+    }
+
+    fn latest(&self, oracle_uuid: &str) -> Result<Option<NFeProtocol>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_protocols WHERE INTERNALKEY = HEXTORAW(:1) ORDER BY CREATEDAT DESC FETCH FIRST 1 ROWS ONLY",
+            PROTOCOL_COLUMNS
+        );
+        match self.conn.query_row(&sql, &[&oracle_uuid]) {
+            Ok(row) => Ok(Some(map_protocol(&row)?)),
+            Err(oracle::Error::NoDataFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Signed NFe of the latest transmission.
+    fn sent_xml(&self, oracle_uuid: &str) -> Result<String, RepositoryError> {
+        let xml: Option<String> = self.conn.query_row_as(
+            "SELECT NFEXML FROM nfe_protocols WHERE INTERNALKEY = HEXTORAW(:1) ORDER BY CREATEDAT DESC FETCH FIRST 1 ROWS ONLY",
+            &[&oracle_uuid],
+        )?;
+        xml.ok_or_else(|| RepositoryError::InvalidData("pending batch without NFe".to_string()))
+    }
+}
@@ -0,0 +1,582 @@
+use crate::errors::{RepositoryError, SefazError, ValidationError};
+use crate::models::nfe_contingency::{
+    default_mode, is_svc, ActivateContingency, NFeContingency, NFeQueuedNote, NORMAL,
+};
+use crate::models::nfe_document::NFeDocument;
+use crate::models::nfe_identification::NFeIdentification;
+use crate::models::nfe_protocol::NFeProtocol;
+use crate::models::nfe_status::NFeStatus;
+use crate::repositories::common::{
+    is_unique_violation, parse_timestamp, to_oracle_uuid, TIMESTAMP_FORMAT,
+};
+use crate::repositories::nfe_authorization_repository::NFeAuthorizationRepository;
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::repositories::nfe_identification_repository::NFeIdentificationRepository;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::sefaz::config::authorizer;
+use crate::services::sefaz::soap::SoapClient;
+use crate::services::sefaz::status;
+use chrono::Utc;
+use oracle::{Connection, Row};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// `xJust` of the contingencies switched on after transmission failures.
+const AUTOMATIC_JUSTIFICATION: &str =
+    "Falha de comunicacao com o autorizador da SEFAZ em tentativas consecutivas";
+
+const CONTINGENCY_COLUMNS: &str = r#"
+    RAWTOHEX(ID) as id,
+    CUF as c_uf,
+    MOD_ as mod_,
+    TPEMIS as tp_emis,
+    TO_CHAR(DHCONT, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_cont,
+    XJUST as x_just,
+    AUTOMATIC as automatic,
+    TO_CHAR(DHEND, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_end
+"#;
+
+const QUEUE_COLUMNS: &str = r#"
+    RAWTOHEX(q.INTERNALKEY) as internal_key,
+    q.CUF as c_uf,
+    q.MOD_ as mod_,
+    q.TPEMIS as tp_emis,
+    TO_CHAR(q.QUEUEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as queued_at,
+    q.ATTEMPTS as attempts,
+    q.LASTERROR as last_error,
+    TO_CHAR(q.SENTAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as sent_at,
+    q.CSTAT as c_stat,
+    q.XMOTIVO as x_motivo
+"#;
+
+fn map_contingency(row: &Row) -> Result<NFeContingency, RepositoryError> {
+    let oracle_uuid: String = row.get("id")?;
+    let id = Uuid::parse_str(&oracle_uuid)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+        .to_string();
+    Ok(NFeContingency {
+        id,
+        c_uf: row.get("c_uf")?,
+        mod_: row.get("mod_")?,
+        tp_emis: row.get("tp_emis")?,
+        dh_cont: parse_timestamp(&row.get::<_, String>("dh_cont")?),
+        x_just: row.get("x_just")?,
+        automatic: row.get::<_, u32>("automatic")? == 1,
+        dh_end: row
+            .get::<_, Option<String>>("dh_end")?
+            .as_deref()
+            .map(parse_timestamp),
+    })
+}
+
+fn map_queued_note(row: &Row) -> Result<NFeQueuedNote, RepositoryError> {
+    let oracle_uuid: String = row.get("internal_key")?;
+    let internal_key = Uuid::parse_str(&oracle_uuid)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+        .to_string();
+    Ok(NFeQueuedNote {
+        internal_key,
+        c_uf: row.get("c_uf")?,
+        mod_: row.get("mod_")?,
+        tp_emis: row.get("tp_emis")?,
+        queued_at: parse_timestamp(&row.get::<_, String>("queued_at")?),
+        attempts: row.get("attempts")?,
+        last_error: row.get("last_error")?,
+        sent_at: row
+            .get::<_, Option<String>>("sent_at")?
+            .as_deref()
+            .map(parse_timestamp),
+        c_stat: row.get("c_stat")?,
+        x_motivo: row.get("x_motivo")?,
+    })
+}
+
+/// Outcome of a transmission request.
+pub enum Transmission {
+    /// SEFAZ, or the virtual SEFAZ in SVC contingency, answered with this protocol.
+    Sent(NFeProtocol),
+    /// Issued in offline contingency; sent once the contingency ends.
+    Queued(NFeQueuedNote),
+}
+
+/// Issues notes in contingency while the authorizer of their state is unavailable, and
+/// transmits the notes queued offline once it is back, in the order they were issued.
+pub struct NFeContingencyRepository {
+    conn: Arc<Connection>,
+    documents: Arc<NFeDocumentRepository>,
+    identifications: Arc<NFeIdentificationRepository>,
+    authorizations: Arc<NFeAuthorizationRepository>,
+    status: Arc<NFeStatusRepository>,
+    sefaz: Arc<SoapClient>,
+    /// Consecutive transport failures of each authorizer.
+    failures: Mutex<HashMap<String, u32>>,
+    /// Held while the queue is transmitted, so no note is sent twice.
+    draining: tokio::sync::Mutex<()>,
+}
+
+impl NFeContingencyRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        documents: Arc<NFeDocumentRepository>,
+        identifications: Arc<NFeIdentificationRepository>,
+        authorizations: Arc<NFeAuthorizationRepository>,
+        status: Arc<NFeStatusRepository>,
+        sefaz: Arc<SoapClient>,
+    ) -> Self {
+        Self {
+            conn,
+            documents,
+            identifications,
+            authorizations,
+            status,
+            sefaz,
+            failures: Mutex::new(HashMap::new()),
+            draining: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Every contingency period, newest first.
+    #[instrument(skip(self))]
+    pub async fn find_all(&self) -> Result<Vec<NFeContingency>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_contingencies ORDER BY DHCONT DESC",
+            CONTINGENCY_COLUMNS
+        );
+        let rows = self.conn.query(&sql, &[])?;
+        rows.map(|row| map_contingency(&row?)).collect()
+    }
+
+    /// Notes issued in offline contingency in the order they are transmitted, optionally
+    /// only those SEFAZ has not received yet.
+    #[instrument(skip(self))]
+    pub async fn find_queue(&self, pending: bool) -> Result<Vec<NFeQueuedNote>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_contingency_queue q {} ORDER BY q.QUEUEDAT",
+            QUEUE_COLUMNS,
+            if pending {
+                "WHERE q.SENTAT IS NULL"
+            } else {
+                ""
+            }
+        );
+        let rows = self.conn.query(&sql, &[])?;
+        rows.map(|row| map_queued_note(&row?)).collect()
+    }
+
+    /// Switches the notes of a state and model to contingency until it is deactivated.
+    #[instrument(skip(self, request), fields(c_uf = %request.c_uf, mod_ = %request.mod_))]
+    pub async fn activate(
+        &self,
+        request: &ActivateContingency,
+    ) -> Result<NFeContingency, RepositoryError> {
+        request.validate()?;
+        self.start(
+            &request.c_uf,
+            &request.mod_,
+            request.tp_emis(),
+            request.x_just.trim(),
+            false,
+        )
+    }
+
+    /// Ends the contingency of a state and model and transmits the queue. Returns the
+    /// queued notes that were attempted.
+    #[instrument(skip(self))]
+    pub async fn deactivate(
+        &self,
+        c_uf: &str,
+        mod_: &str,
+    ) -> Result<Vec<NFeQueuedNote>, RepositoryError> {
+        self.end(c_uf, mod_)?;
+        self.drain().await
+    }
+
+    /// Sends the note to SEFAZ, or issues it in contingency when one is active for its
+    /// state and model. After `contingency_after_failures` consecutive transport failures
+    /// of the authorizer of the state, a contingency in the default mode is switched on
+    /// and the note is issued in it.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn transmit(
+        &self,
+        internal_key: &str,
+        sync: bool,
+    ) -> Result<Transmission, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let status = self.status.current(&oracle_uuid)?;
+        if status == NFeStatus::Queued {
+            return Err(RepositoryError::Conflict(
+                "the note is queued and is transmitted when the contingency ends".to_string(),
+            ));
+        }
+
+        let document = self.documents.find(internal_key).await?;
+        let ide = &document.identification;
+        if status.is_editable() {
+            if let Some(contingency) = self.active(&ide.c_uf, &ide.mod_)? {
+                return self
+                    .issue_in_contingency(internal_key, &document, &contingency, sync)
+                    .await;
+            }
+        }
+
+        let key = authorizer(&ide.c_uf, &ide.mod_, &ide.tp_emis);
+        match self.authorizations.transmit(internal_key, sync).await {
+            Ok(protocol) => {
+                self.reset_failures(&key);
+                Ok(Transmission::Sent(protocol))
+            }
+            Err(RepositoryError::Sefaz(
+                e @ (SefazError::Unreachable(_) | SefazError::Transport(_)),
+            )) => {
+                let failures = self.count_failure(&key);
+                warn!(
+                    "Authorizer {} unreachable ({} consecutive failures): {}",
+                    key, failures, e
+                );
+                let threshold = self.sefaz.config().contingency_after_failures;
+                if failures < threshold || !status.is_editable() || key != ide.c_uf {
+                    return Err(e.into());
+                }
+                // A note that may have reached SEFAZ stays transmitted and is consulted
+                // on the next attempt; only one handed back unsent is issued again.
+                if !self.status.current(&oracle_uuid)?.is_editable() {
+                    return Err(e.into());
+                }
+                let contingency = match self.active(&ide.c_uf, &ide.mod_)? {
+                    Some(contingency) => contingency,
+                    None => self.start(
+                        &ide.c_uf,
+                        &ide.mod_,
+                        self.automatic_mode(&ide.c_uf, &ide.mod_),
+                        AUTOMATIC_JUSTIFICATION,
+                        true,
+                    )?,
+                };
+                self.issue_in_contingency(internal_key, &document, &contingency, sync)
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Transmits the queued notes whose state and model are no longer in contingency, in
+    /// the order they were queued. A transport failure leaves the remaining notes of the
+    /// state for the next attempt; any other failure is recorded on the note alone.
+    #[instrument(skip(self))]
+    pub async fn drain(&self) -> Result<Vec<NFeQueuedNote>, RepositoryError> {
+        let _guard = self.draining.lock().await;
+        let sql = format!(
+            r#"
+            SELECT {} FROM nfe_contingency_queue q
+            WHERE q.SENTAT IS NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM nfe_contingencies c
+                  WHERE c.CUF = q.CUF AND c.MOD_ = q.MOD_ AND c.DHEND IS NULL
+              )
+            ORDER BY q.QUEUEDAT
+            "#,
+            QUEUE_COLUMNS
+        );
+        let pending = self
+            .conn
+            .query(&sql, &[])?
+            .map(|row| map_queued_note(&row?))
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+        if !pending.is_empty() {
+            info!("Transmitting {} notes queued in contingency", pending.len());
+        }
+
+        let mut unreachable = HashSet::new();
+        let mut attempted = Vec::new();
+        for entry in pending {
+            let key = authorizer(&entry.c_uf, &entry.mod_, NORMAL);
+            if unreachable.contains(&key) {
+                continue;
+            }
+            let oracle_uuid = to_oracle_uuid(&entry.internal_key)?;
+            match self
+                .authorizations
+                .transmit(&entry.internal_key, true)
+                .await
+            {
+                Ok(protocol) => {
+                    self.reset_failures(&key);
+                    self.record_sent(&oracle_uuid, &protocol.c_stat, &protocol.x_motivo)?;
+                }
+                Err(RepositoryError::Sefaz(SefazError::Rejected { c_stat, x_motivo })) => {
+                    self.record_sent(&oracle_uuid, &c_stat, &x_motivo)?;
+                }
+                Err(e) => {
+                    warn!("Queued note {} not transmitted: {}", entry.internal_key, e);
+                    if matches!(
+                        e,
+                        RepositoryError::Sefaz(
+                            SefazError::Unreachable(_) | SefazError::Transport(_)
+                        )
+                    ) {
+                        unreachable.insert(key);
+                    }
+                    self.record_error(&oracle_uuid, &e.to_string())?;
+                }
+            }
+            attempted.push(self.find_entry(&oracle_uuid)?);
+        }
+        Ok(attempted)
+    }
+
+    /// Ends the automatic contingencies whose authorizer reports itself in operation
+    /// again, then transmits the queue. Meant to run periodically.
+    #[instrument(skip(self))]
+    pub async fn resume(&self) -> Result<Vec<NFeQueuedNote>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_contingencies WHERE DHEND IS NULL AND AUTOMATIC = 1",
+            CONTINGENCY_COLUMNS
+        );
+        let automatic = self
+            .conn
+            .query(&sql, &[])?
+            .map(|row| map_contingency(&row?))
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+        for contingency in automatic {
+            let key = authorizer(&contingency.c_uf, &contingency.mod_, NORMAL);
+            let Ok(state) = self.sefaz.config().state(&key) else {
+                continue;
+            };
+            let tp_amb = state.tp_amb.clone();
+            match status::query(&self.sefaz, &key, &contingency.c_uf, &tp_amb).await {
+                Ok(ret) if ret.is_in_operation() => {
+                    info!(
+                        "Authorizer of cUF {} is back in operation",
+                        contingency.c_uf
+                    );
+                    self.reset_failures(&key);
+                    self.end(&contingency.c_uf, &contingency.mod_)?;
+                }
+                Ok(ret) => info!(
+                    "Authorizer of cUF {} answered {} - {}",
+                    contingency.c_uf, ret.c_stat, ret.x_motivo
+                ),
+                Err(e) => warn!(
+                    "Authorizer of cUF {} still unavailable: {}",
+                    contingency.c_uf, e
+                ),
+            }
+        }
+        self.drain().await
+    }
+
+    fn active(&self, c_uf: &str, mod_: &str) -> Result<Option<NFeContingency>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_contingencies WHERE CUF = :1 AND MOD_ = :2 AND DHEND IS NULL",
+            CONTINGENCY_COLUMNS
+        );
+        match self.conn.query_row(&sql, &[&c_uf, &mod_]) {
+            Ok(row) => Ok(Some(map_contingency(&row)?)),
+            Err(oracle::Error::NoDataFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// SVC when the virtual SEFAZ of the state is configured, FS-DA otherwise.
+    fn automatic_mode(&self, c_uf: &str, mod_: &str) -> &'static str {
+        let mode = default_mode(c_uf, mod_);
+        if is_svc(mode)
+            && self
+                .sefaz
+                .config()
+                .state(&authorizer(c_uf, mod_, mode))
+                .is_err()
+        {
+            "5"
+        } else {
+            mode
+        }
+    }
+
+    fn start(
+        &self,
+        c_uf: &str,
+        mod_: &str,
+        tp_emis: &str,
+        x_just: &str,
+        automatic: bool,
+    ) -> Result<NFeContingency, RepositoryError> {
+        if is_svc(tp_emis) {
+            let key = authorizer(c_uf, mod_, tp_emis);
+            if self.sefaz.config().state(&key).is_err() {
+                return Err(ValidationError::new(
+                    "tpEmis",
+                    format!("no endpoints configured for {}", key),
+                )
+                .into());
+            }
+        }
+
+        let id = Uuid::new_v4().to_string().replace('-', "");
+        let result = self.conn.execute(
+            r#"
+            INSERT INTO nfe_contingencies (ID, CUF, MOD_, TPEMIS, DHCONT, XJUST, AUTOMATIC)
+            VALUES (
+                HEXTORAW(:1),
+                :2,
+                :3,
+                :4,
+                TO_TIMESTAMP(:5, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+                :6,
+                :7
+            )
+            "#,
+            &[
+                &id,
+                &c_uf,
+                &mod_,
+                &tp_emis,
+                &Utc::now().format(TIMESTAMP_FORMAT).to_string(),
+                &x_just,
+                &u32::from(automatic),
+            ],
+        );
+        match result {
+            Ok(_) => {}
+            Err(e) if is_unique_violation(&e) => {
+                return Err(RepositoryError::Conflict(format!(
+                    "a contingency is already active for cUF {} and mod {}",
+                    c_uf, mod_
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        info!(
+            "Contingency with tpEmis {} started for cUF {} and mod {}{}",
+            tp_emis,
+            c_uf,
+            mod_,
+            if automatic { " automatically" } else { "" }
+        );
+
+        let sql = format!(
+            "SELECT {} FROM nfe_contingencies WHERE ID = HEXTORAW(:1)",
+            CONTINGENCY_COLUMNS
+        );
+        map_contingency(&self.conn.query_row(&sql, &[&id])?)
+    }
+
+    fn end(&self, c_uf: &str, mod_: &str) -> Result<(), RepositoryError> {
+        let stmt = self.conn.execute(
+            "UPDATE nfe_contingencies SET DHEND = CURRENT_TIMESTAMP WHERE CUF = :1 AND MOD_ = :2 AND DHEND IS NULL",
+            &[&c_uf, &mod_],
+        )?;
+        if stmt.row_count()? == 0 {
+            return Err(RepositoryError::NotFound);
+        }
+        info!("Contingency ended for cUF {} and mod {}", c_uf, mod_);
+        Ok(())
+    }
+
+    /// Reissues the note with the mode, `dhCont` and `xJust` of the contingency. In SVC
+    /// the note is then sent to the virtual SEFAZ; otherwise it is signed and queued.
+    async fn issue_in_contingency(
+        &self,
+        internal_key: &str,
+        document: &NFeDocument,
+        contingency: &NFeContingency,
+        sync: bool,
+    ) -> Result<Transmission, RepositoryError> {
+        let ide = &document.identification;
+        if ide.tp_emis != contingency.tp_emis || ide.dh_cont != Some(contingency.dh_cont) {
+            info!(
+                "Issuing NFe in contingency with tpEmis {}",
+                contingency.tp_emis
+            );
+            let reissued = NFeIdentification {
+                tp_emis: contingency.tp_emis.clone(),
+                dh_cont: Some(contingency.dh_cont),
+                x_justificativa: Some(contingency.x_just.clone()),
+                ..ide.clone()
+            };
+            self.identifications.update(internal_key, &reissued).await?;
+        }
+        if is_svc(&contingency.tp_emis) {
+            return self
+                .authorizations
+                .transmit(internal_key, sync)
+                .await
+                .map(Transmission::Sent);
+        }
+
+        self.authorizations.sign(internal_key).await?;
+        self.status
+            .transition(internal_key, NFeStatus::Queued, None, None)
+            .await?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        // A note queued in an earlier contingency and rejected since starts over.
+        self.conn.execute(
+            r#"
+            MERGE INTO nfe_contingency_queue q
+            USING (
+                SELECT HEXTORAW(:1) AS INTERNALKEY, :2 AS CUF, :3 AS MOD_, :4 AS TPEMIS
+                FROM dual
+            ) s
+            ON (q.INTERNALKEY = s.INTERNALKEY)
+            WHEN MATCHED THEN UPDATE SET
+                CUF = s.CUF, MOD_ = s.MOD_, TPEMIS = s.TPEMIS, QUEUEDAT = CURRENT_TIMESTAMP,
+                ATTEMPTS = 0, LASTERROR = NULL, SENTAT = NULL, CSTAT = NULL, XMOTIVO = NULL
+            WHEN NOT MATCHED THEN INSERT (INTERNALKEY, CUF, MOD_, TPEMIS)
+                VALUES (s.INTERNALKEY, s.CUF, s.MOD_, s.TPEMIS)
+            "#,
+            &[&oracle_uuid, &ide.c_uf, &ide.mod_, &contingency.tp_emis],
+        )?;
+        info!("NFe queued for transmission after the contingency");
+        Ok(Transmission::Queued(self.find_entry(&oracle_uuid)?))
+    }
+
+    fn find_entry(&self, oracle_uuid: &str) -> Result<NFeQueuedNote, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_contingency_queue q WHERE q.INTERNALKEY = HEXTORAW(:1)",
+            QUEUE_COLUMNS
+        );
+        map_queued_note(&self.conn.query_row(&sql, &[&oracle_uuid])?)
+    }
+
+    fn record_sent(
+        &self,
+        oracle_uuid: &str,
+        c_stat: &str,
+        x_motivo: &str,
+    ) -> Result<(), RepositoryError> {
+        self.conn.execute(
+            r#"
+            UPDATE nfe_contingency_queue
+            SET ATTEMPTS = ATTEMPTS + 1, SENTAT = CURRENT_TIMESTAMP, CSTAT = :1, XMOTIVO = :2,
+                LASTERROR = NULL
+            WHERE INTERNALKEY = HEXTORAW(:3)
+            "#,
+            &[&c_stat, &x_motivo, &oracle_uuid],
+        )?;
+        Ok(())
+    }
+
+    fn record_error(&self, oracle_uuid: &str, error: &str) -> Result<(), RepositoryError> {
+        self.conn.execute(
+            "UPDATE nfe_contingency_queue SET ATTEMPTS = ATTEMPTS + 1, LASTERROR = SUBSTR(:1, 1, 1000) WHERE INTERNALKEY = HEXTORAW(:2)",
+            &[&error, &oracle_uuid],
+        )?;
+        Ok(())
+    }
+
+    fn count_failure(&self, key: &str) -> u32 {
+        let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        let count = failures.entry(key.to_string()).or_default();
+        *count += 1;
+        *count
+    }
+
+    fn reset_failures(&self, key: &str) {
+        self.failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+}
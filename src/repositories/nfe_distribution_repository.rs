@@ -0,0 +1,340 @@
+use crate::errors::{RepositoryError, SefazError};
+use crate::models::nfe_distribution::{validate_cnpj, DistributionReport, NFeDistributedDocument};
+use crate::models::nfe_import::ImportStatus;
+use crate::repositories::common::{
+    optional_decimal_bind, parse_optional_decimal, parse_timestamp, to_oracle_uuid,
+    TIMESTAMP_FORMAT,
+};
+use crate::repositories::nfe_import_repository::NFeImportRepository;
+use crate::services::sefaz::config::AN;
+use crate::services::sefaz::distribution::{self, DocZip, FOUND, NOTHING_FOUND, TOO_MANY_QUERIES};
+use crate::services::sefaz::soap::SoapClient;
+use chrono::{DateTime, Duration, Utc};
+use oracle::{Connection, Row};
+use std::sync::Arc;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// SEFAZ asks for an hour between queries once no new document is left, and blocks the
+/// CNPJ for an hour after too many queries.
+const WAIT_HOURS: i64 = 1;
+
+const DOCUMENT_COLUMNS: &str = r#"
+    CNPJ as cnpj,
+    NSU as nsu,
+    DOCSCHEMA as schema,
+    CHNFE as ch_nfe,
+    EMITDOC as emit_doc,
+    XNOME as x_nome,
+    TO_CHAR(DHEMI, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_emi,
+    TO_CHAR(VNF) as v_nf,
+    CSITNFE as c_sit_nfe,
+    NPROT as n_prot,
+    RAWTOHEX(INTERNALKEY) as internal_key,
+    IMPORTSTATUS as import_status,
+    REASON as reason,
+    TO_CHAR(RECEIVEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as received_at
+"#;
+
+fn map_document(row: &Row) -> Result<NFeDistributedDocument, RepositoryError> {
+    let internal_key = row
+        .get::<_, Option<String>>("internal_key")?
+        .map(|oracle_uuid| {
+            Uuid::parse_str(&oracle_uuid)
+                .map(|uuid| uuid.to_string())
+                .map_err(|e| RepositoryError::InvalidData(e.to_string()))
+        })
+        .transpose()?;
+    Ok(NFeDistributedDocument {
+        cnpj: row.get("cnpj")?,
+        nsu: row.get("nsu")?,
+        schema: row.get("schema")?,
+        ch_nfe: row.get("ch_nfe")?,
+        emit_doc: row.get("emit_doc")?,
+        x_nome: row.get("x_nome")?,
+        dh_emi: row
+            .get::<_, Option<String>>("dh_emi")?
+            .as_deref()
+            .map(parse_timestamp),
+        v_nf: parse_optional_decimal(row.get("v_nf")?)?,
+        c_sit_nfe: row.get("c_sit_nfe")?,
+        n_prot: row.get("n_prot")?,
+        internal_key,
+        import_status: row.get("import_status")?,
+        reason: row.get("reason")?,
+        received_at: parse_timestamp(&row.get::<_, String>("received_at")?),
+    })
+}
+
+/// Where the reading of a CNPJ stands.
+struct Position {
+    ult_nsu: u64,
+    max_nsu: u64,
+    next_query_at: Option<DateTime<Utc>>,
+}
+
+/// Pulls the documents the Ambiente Nacional holds for our CNPJs through
+/// NFeDistribuicaoDFe, from the last NSU read. Full notes are imported like uploaded
+/// XML; summaries and events are kept as received.
+pub struct NFeDistributionRepository {
+    conn: Arc<Connection>,
+    imports: Arc<NFeImportRepository>,
+    sefaz: Arc<SoapClient>,
+    /// Held while a CNPJ is read, so two runs never store the same NSU range.
+    syncing: tokio::sync::Mutex<()>,
+}
+
+impl NFeDistributionRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        imports: Arc<NFeImportRepository>,
+        sefaz: Arc<SoapClient>,
+    ) -> Self {
+        Self {
+            conn,
+            imports,
+            sefaz,
+            syncing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Documents distributed to the CNPJ, in NSU order.
+    #[instrument(skip(self))]
+    pub async fn find_documents(
+        &self,
+        cnpj: &str,
+    ) -> Result<Vec<NFeDistributedDocument>, RepositoryError> {
+        validate_cnpj(cnpj)?;
+        let sql = format!(
+            "SELECT {} FROM nfe_distribution_documents WHERE CNPJ = :1 ORDER BY NSU",
+            DOCUMENT_COLUMNS
+        );
+        let rows = self.conn.query(&sql, &[&cnpj])?;
+        rows.map(|row| map_document(&row?)).collect()
+    }
+
+    /// Reads every document of the CNPJ after the last NSU stored, 50 per query, until
+    /// SEFAZ has nothing new. The NSU is saved after each answer, so an interrupted run
+    /// resumes where it stopped. Refused with 409 while SEFAZ asks to wait.
+    #[instrument(skip(self))]
+    pub async fn sync(&self, cnpj: &str) -> Result<DistributionReport, RepositoryError> {
+        validate_cnpj(cnpj)?;
+        let _guard = self.syncing.lock().await;
+        let tp_amb = self.sefaz.config().state(AN)?.tp_amb.clone();
+        let position = self.position(cnpj)?;
+        if let Some(next_query_at) = position.next_query_at {
+            if next_query_at > Utc::now() {
+                return Err(RepositoryError::Conflict(format!(
+                    "SEFAZ accepts the next query of CNPJ {} at {}",
+                    cnpj,
+                    next_query_at.to_rfc3339()
+                )));
+            }
+        }
+
+        let mut report = DistributionReport {
+            cnpj: cnpj.to_string(),
+            ult_nsu: position.ult_nsu,
+            max_nsu: position.max_nsu,
+            received: 0,
+            imported: 0,
+            c_stat: String::new(),
+            x_motivo: String::new(),
+            next_query_at: None,
+        };
+        loop {
+            let ret = distribution::query(&self.sefaz, &tp_amb, cnpj, report.ult_nsu).await?;
+            report.c_stat = ret.c_stat.clone();
+            report.x_motivo = ret.x_motivo.clone();
+            match ret.c_stat.as_str() {
+                FOUND | NOTHING_FOUND => {}
+                TOO_MANY_QUERIES => {
+                    warn!("SEFAZ blocked the queries of CNPJ {} for an hour", cnpj);
+                    break;
+                }
+                _ => {
+                    return Err(SefazError::Rejected {
+                        c_stat: ret.c_stat,
+                        x_motivo: ret.x_motivo,
+                    }
+                    .into())
+                }
+            }
+
+            for document in &ret.documents {
+                if self.store(cnpj, document).await? == Some(ImportStatus::Inserted) {
+                    report.imported += 1;
+                }
+                report.received += 1;
+            }
+            report.ult_nsu = ret.ult_nsu;
+            report.max_nsu = ret.max_nsu;
+            if ret.is_caught_up() {
+                break;
+            }
+            self.save_position(cnpj, &report)?;
+        }
+        report.next_query_at = Some(Utc::now() + Duration::hours(WAIT_HOURS));
+        self.save_position(cnpj, &report)?;
+        info!(
+            "CNPJ {} read up to NSU {}: {} documents, {} notes imported",
+            cnpj, report.ult_nsu, report.received, report.imported
+        );
+        Ok(report)
+    }
+
+    fn position(&self, cnpj: &str) -> Result<Position, RepositoryError> {
+        let sql = r#"
+            SELECT ULTNSU, MAXNSU, TO_CHAR(NEXTQUERYAT, 'YYYY-MM-DD HH24:MI:SS.FF3')
+            FROM nfe_distribution_nsu WHERE CNPJ = :1
+        "#;
+        match self
+            .conn
+            .query_row_as::<(u64, u64, Option<String>)>(sql, &[&cnpj])
+        {
+            Ok((ult_nsu, max_nsu, next_query_at)) => Ok(Position {
+                ult_nsu,
+                max_nsu,
+                next_query_at: next_query_at.as_deref().map(parse_timestamp),
+            }),
+            Err(oracle::Error::NoDataFound) => Ok(Position {
+                ult_nsu: 0,
+                max_nsu: 0,
+                next_query_at: None,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_position(
+        &self,
+        cnpj: &str,
+        report: &DistributionReport,
+    ) -> Result<(), RepositoryError> {
+        let next_query_at = report
+            .next_query_at
+            .map(|at| at.format(TIMESTAMP_FORMAT).to_string());
+        self.conn.execute(
+            r#"
+            MERGE INTO nfe_distribution_nsu n
+            USING (
+                SELECT :1 AS CNPJ, :2 AS ULTNSU, :3 AS MAXNSU,
+                       TO_TIMESTAMP(:4, 'YYYY-MM-DD HH24:MI:SS.FF3') AS NEXTQUERYAT
+                FROM dual
+            ) s
+            ON (n.CNPJ = s.CNPJ)
+            WHEN MATCHED THEN UPDATE SET
+                ULTNSU = s.ULTNSU, MAXNSU = s.MAXNSU, NEXTQUERYAT = s.NEXTQUERYAT,
+                UPDATEDAT = CURRENT_TIMESTAMP
+            WHEN NOT MATCHED THEN INSERT (CNPJ, ULTNSU, MAXNSU, NEXTQUERYAT)
+                VALUES (s.CNPJ, s.ULTNSU, s.MAXNSU, s.NEXTQUERYAT)
+            "#,
+            &[&cnpj, &report.ult_nsu, &report.max_nsu, &next_query_at],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the document under its NSU. A `procNFe` is imported first and returns the
+    /// outcome of the import; a `resNFe` keeps its summary fields.
+    async fn store(
+        &self,
+        cnpj: &str,
+        document: &DocZip,
+    ) -> Result<Option<ImportStatus>, RepositoryError> {
+        let mut row = NFeDistributedDocument {
+            cnpj: cnpj.to_string(),
+            nsu: document.nsu,
+            schema: document.schema.clone(),
+            ch_nfe: document.ch_nfe(),
+            emit_doc: None,
+            x_nome: None,
+            dh_emi: None,
+            v_nf: None,
+            c_sit_nfe: None,
+            n_prot: None,
+            internal_key: None,
+            import_status: None,
+            reason: None,
+            received_at: Utc::now(),
+        };
+        let mut status = None;
+        match document.kind() {
+            "resNFe" => match distribution::parse_res_nfe(&document.xml) {
+                Ok(summary) => {
+                    row.emit_doc = Some(summary.emit_doc);
+                    row.x_nome = Some(summary.x_nome);
+                    row.dh_emi = Some(summary.dh_emi);
+                    row.v_nf = Some(summary.v_nf);
+                    row.c_sit_nfe = Some(summary.c_sit_nfe);
+                    row.n_prot = summary.n_prot;
+                }
+                Err(e) => row.reason = Some(e.to_string()),
+            },
+            "procNFe" => {
+                let name = format!("NSU {}", distribution::nsu(document.nsu));
+                let imported = self
+                    .imports
+                    .import_file(&name, document.xml.as_bytes())
+                    .await;
+                row.n_prot = imported.n_prot;
+                row.internal_key = imported.internal_key;
+                row.import_status = Some(imported.status.as_str().to_string());
+                row.reason = imported.reason;
+                status = Some(imported.status);
+            }
+            _ => {}
+        }
+
+        let internal_key = row
+            .internal_key
+            .as_deref()
+            .map(to_oracle_uuid)
+            .transpose()?;
+        let dh_emi = row
+            .dh_emi
+            .map(|dh_emi| dh_emi.format(TIMESTAMP_FORMAT).to_string());
+        // A run interrupted before its NSU was saved reads the same documents again.
+        self.conn.execute(
+            r#"
+            MERGE INTO nfe_distribution_documents d
+            USING (
+                SELECT :1 AS CNPJ, :2 AS NSU, :3 AS DOCSCHEMA, :4 AS CHNFE, :5 AS EMITDOC,
+                       :6 AS XNOME, TO_TIMESTAMP(:7, 'YYYY-MM-DD HH24:MI:SS.FF3') AS DHEMI,
+                       :8 AS VNF, :9 AS CSITNFE, :10 AS NPROT, HEXTORAW(:11) AS INTERNALKEY,
+                       :12 AS IMPORTSTATUS, SUBSTR(:13, 1, 1000) AS REASON, :14 AS XML
+                FROM dual
+            ) s
+            ON (d.CNPJ = s.CNPJ AND d.NSU = s.NSU)
+            WHEN MATCHED THEN UPDATE SET
+                DOCSCHEMA = s.DOCSCHEMA, CHNFE = s.CHNFE, EMITDOC = s.EMITDOC,
+                XNOME = s.XNOME, DHEMI = s.DHEMI, VNF = s.VNF, CSITNFE = s.CSITNFE,
+                NPROT = s.NPROT, INTERNALKEY = s.INTERNALKEY, IMPORTSTATUS = s.IMPORTSTATUS,
+                REASON = s.REASON, XML = s.XML, RECEIVEDAT = CURRENT_TIMESTAMP
+            WHEN NOT MATCHED THEN INSERT (
+                CNPJ, NSU, DOCSCHEMA, CHNFE, EMITDOC, XNOME, DHEMI, VNF, CSITNFE, NPROT,
+                INTERNALKEY, IMPORTSTATUS, REASON, XML
+            ) VALUES (
+                s.CNPJ, s.NSU, s.DOCSCHEMA, s.CHNFE, s.EMITDOC, s.XNOME, s.DHEMI, s.VNF,
+                s.CSITNFE, s.NPROT, s.INTERNALKEY, s.IMPORTSTATUS, s.REASON, s.XML
+            )
+            "#,
+            &[
+                &row.cnpj,
+                &row.nsu,
+                &row.schema,
+                &row.ch_nfe,
+                &row.emit_doc,
+                &row.x_nome,
+                &dh_emi,
+                &optional_decimal_bind(&row.v_nf),
+                &row.c_sit_nfe,
+                &row.n_prot,
+                &internal_key,
+                &row.import_status,
+                &row.reason,
+                &document.xml,
+            ],
+        )?;
+        Ok(status)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_document::NFeDocument;
+use crate::repositories::nfe_additional_repository::NFeAdditionalRepository;
+use crate::repositories::nfe_identification_repository::NFeIdentificationRepository;
+use crate::repositories::nfe_item_repository::NFeItemRepository;
+use crate::repositories::nfe_participant_repository::NFeParticipantRepository;
+use crate::repositories::nfe_payment_repository::NFePaymentRepository;
+use crate::repositories::nfe_reference_repository::NFeReferenceRepository;
+use crate::repositories::nfe_total_repository::NFeTotalRepository;
+use crate::repositories::nfe_transport_repository::NFeTransportRepository;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+/// Assembles a whole note from the repositories of each group.
+pub struct NFeDocumentRepository {
+    identifications: Arc<NFeIdentificationRepository>,
+    participants: Arc<NFeParticipantRepository>,
+    references: Arc<NFeReferenceRepository>,
+    items: Arc<NFeItemRepository>,
+    totals: Arc<NFeTotalRepository>,
+    transports: Arc<NFeTransportRepository>,
+    payments: Arc<NFePaymentRepository>,
+    additional: Arc<NFeAdditionalRepository>,
+}
+
+impl NFeDocumentRepository {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        identifications: Arc<NFeIdentificationRepository>,
+        participants: Arc<NFeParticipantRepository>,
+        references: Arc<NFeReferenceRepository>,
+        items: Arc<NFeItemRepository>,
+        totals: Arc<NFeTotalRepository>,
+        transports: Arc<NFeTransportRepository>,
+        payments: Arc<NFePaymentRepository>,
+        additional: Arc<NFeAdditionalRepository>,
+    ) -> Self {
+        Self {
+            identifications,
+            participants,
+            references,
+            items,
+            totals,
+            transports,
+            payments,
+            additional,
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find(&self, internal_key: &str) -> Result<NFeDocument, RepositoryError> {
+        info!("Assembling NFe document");
+
+        let identification = self
+            .identifications
+            .find_by_id(internal_key)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+        Ok(NFeDocument {
+            identification,
+            emitter: self.participants.find_emitter(internal_key).await?,
+            recipient: self.participants.find_recipient(internal_key).await?,
+            references: self.references.find_all(internal_key).await?,
+            items: self.items.find_all(internal_key).await?,
+            total: self.totals.find(internal_key).await?,
+            transport: self.transports.find(internal_key).await?,
+            billing: self.payments.find_billing(internal_key).await?,
+            payment: self.payments.find_payment(internal_key).await?,
+            authorized_downloads: self
+                .additional
+                .find_authorized_downloads(internal_key)
+                .await?,
+            additional_info: self.additional.find_additional_info(internal_key).await?,
+            tech_responsible: self.additional.find_tech_responsible(internal_key).await?,
+        })
+    }
+}
@@ -0,0 +1,400 @@
+use crate::errors::{RepositoryError, SefazError};
+use crate::models::nfe_access_key::AccessKey;
+use crate::models::nfe_contingency::NORMAL;
+use crate::models::nfe_document::NFeDocument;
+use crate::models::nfe_event::{
+    CancelNFe, CorrectNFe, NFeEvent, CANCELLATION, CONDITIONS_OF_USE, CORRECTION, MAX_CORRECTIONS,
+    REGISTERED,
+};
+use crate::models::nfe_identification::uf_offset;
+use crate::models::nfe_status::NFeStatus;
+use crate::repositories::common::{
+    check_environment, is_unique_violation, parse_timestamp, to_oracle_uuid, TIMESTAMP_FORMAT,
+};
+use crate::repositories::nfe_authorization_repository::NFeAuthorizationRepository;
+use crate::repositories::nfe_document_repository::NFeDocumentRepository;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::sefaz::config::authorizer;
+use crate::services::sefaz::event::{self, Event};
+use crate::services::sefaz::soap::SoapClient;
+use crate::services::signing_service::SigningService;
+use chrono::Utc;
+use oracle::pool::Pool;
+use oracle::{Connection, Row};
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+const EVENT_COLUMNS: &str = r#"
+    RAWTOHEX(INTERNALKEY) as internal_key,
+    TPEVENTO as tp_evento,
+    NSEQEVENTO as n_seq_evento,
+    CHNFE as ch_nfe,
+    TO_CHAR(DHEVENTO, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_evento,
+    DETAIL as detail,
+    CSTAT as c_stat,
+    XMOTIVO as x_motivo,
+    NPROT as n_prot,
+    TO_CHAR(DHREGEVENTO, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_reg_evento,
+    TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at
+"#;
+
+fn map_event(row: &Row) -> Result<NFeEvent, RepositoryError> {
+    let oracle_uuid: String = row.get("internal_key")?;
+    let internal_key = Uuid::parse_str(&oracle_uuid)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+        .to_string();
+    Ok(NFeEvent {
+        internal_key,
+        tp_evento: row.get("tp_evento")?,
+        n_seq_evento: row.get("n_seq_evento")?,
+        ch_nfe: row.get("ch_nfe")?,
+        dh_evento: parse_timestamp(&row.get::<_, String>("dh_evento")?),
+        detail: row.get("detail")?,
+        c_stat: row.get("c_stat")?,
+        x_motivo: row.get("x_motivo")?,
+        n_prot: row.get("n_prot")?,
+        dh_reg_evento: row
+            .get::<_, Option<String>>("dh_reg_evento")?
+            .as_deref()
+            .map(parse_timestamp),
+        created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+    })
+}
+
+/// Event of the note, authored by its emitter and dated now in the state's offset.
+fn new_event(
+    document: &NFeDocument,
+    tp_evento: &str,
+    n_seq_evento: u32,
+    desc_evento: &str,
+    details: Vec<(&'static str, String)>,
+) -> Result<Event, RepositoryError> {
+    let ide = &document.identification;
+    let emitter = document
+        .emitter
+        .as_ref()
+        .ok_or_else(|| RepositoryError::InvalidData("note without emitter".to_string()))?;
+    Ok(Event {
+        c_orgao: ide.c_uf.clone(),
+        tp_amb: ide.tp_amb.clone(),
+        author: emitter
+            .cnpj
+            .clone()
+            .or_else(|| emitter.cpf.clone())
+            .unwrap_or_default(),
+        ch_nfe: document.access_key()?.to_string(),
+        dh_evento: Utc::now().with_timezone(&uf_offset(&ide.c_uf)),
+        tp_evento: tp_evento.to_string(),
+        n_seq_evento,
+        desc_evento: desc_evento.to_string(),
+        details,
+    })
+}
+
+/// Sends events of authorized notes to SEFAZ and keeps each one with its answer, which
+/// makes up the event history of the note.
+pub struct NFeEventRepository {
+    conn: Arc<Connection>,
+    pool: Pool,
+    documents: Arc<NFeDocumentRepository>,
+    authorizations: Arc<NFeAuthorizationRepository>,
+    status: Arc<NFeStatusRepository>,
+    signing: Arc<SigningService>,
+    sefaz: Arc<SoapClient>,
+}
+
+impl NFeEventRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        pool: Pool,
+        documents: Arc<NFeDocumentRepository>,
+        authorizations: Arc<NFeAuthorizationRepository>,
+        status: Arc<NFeStatusRepository>,
+        signing: Arc<SigningService>,
+        sefaz: Arc<SoapClient>,
+    ) -> Self {
+        Self {
+            conn,
+            pool,
+            documents,
+            authorizations,
+            status,
+            signing,
+            sefaz,
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_all(&self, internal_key: &str) -> Result<Vec<NFeEvent>, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let sql = format!(
+            "SELECT {} FROM nfe_events WHERE INTERNALKEY = HEXTORAW(:1) ORDER BY CREATEDAT",
+            EVENT_COLUMNS
+        );
+        let rows = self.conn.query(&sql, &[&oracle_uuid])?;
+        rows.map(|row| map_event(&row?)).collect()
+    }
+
+    /// Registers the cancellation of an authorized note. The event is stored and returned
+    /// even when SEFAZ rejects it; only a registered one cancels the note.
+    #[instrument(skip(self, cancel), fields(internal_key = %internal_key))]
+    pub async fn cancel(
+        &self,
+        internal_key: &str,
+        cancel: &CancelNFe,
+    ) -> Result<NFeEvent, RepositoryError> {
+        info!("Cancelling NFe");
+
+        cancel.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let document = self
+            .authorized_document(internal_key, &oracle_uuid, "be cancelled")
+            .await?;
+        let n_prot = self
+            .authorizations
+            .find_authorization(internal_key)
+            .await?
+            .and_then(|p| p.n_prot)
+            .ok_or_else(|| {
+                RepositoryError::Conflict(
+                    "the authorization protocol of the note is not stored".to_string(),
+                )
+            })?;
+
+        let x_just = cancel.x_just.trim().to_string();
+        let stored = self
+            .send(&oracle_uuid, CANCELLATION, &x_just, |_| {
+                new_event(
+                    &document,
+                    CANCELLATION,
+                    1,
+                    "Cancelamento",
+                    vec![("nProt", n_prot), ("xJust", x_just.clone())],
+                )
+            })
+            .await?;
+        if stored.is_registered() {
+            self.status
+                .transition(
+                    internal_key,
+                    NFeStatus::Cancelled,
+                    Some(&stored.c_stat),
+                    Some(&stored.x_motivo),
+                )
+                .await?;
+        }
+        Ok(stored)
+    }
+
+    /// Registers a correction letter with the next `nSeqEvento` of the note. Each one
+    /// replaces the previous corrections, so the text must carry all of them.
+    #[instrument(skip(self, correction), fields(internal_key = %internal_key))]
+    pub async fn correct(
+        &self,
+        internal_key: &str,
+        correction: &CorrectNFe,
+    ) -> Result<NFeEvent, RepositoryError> {
+        info!("Registering correction letter");
+
+        correction.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let document = self
+            .authorized_document(internal_key, &oracle_uuid, "receive a correction letter")
+            .await?;
+
+        let x_correcao = correction.x_correcao.trim().to_string();
+        self.send(&oracle_uuid, CORRECTION, &x_correcao, |n_seq_evento| {
+            if n_seq_evento > MAX_CORRECTIONS {
+                return Err(RepositoryError::Conflict(format!(
+                    "the note already has {} correction letters",
+                    MAX_CORRECTIONS
+                )));
+            }
+            new_event(
+                &document,
+                CORRECTION,
+                n_seq_evento,
+                "Carta de Correcao",
+                vec![
+                    ("xCorrecao", x_correcao.clone()),
+                    ("xCondUso", CONDITIONS_OF_USE.to_string()),
+                ],
+            )
+        })
+        .await
+    }
+
+    /// Loads a note that can take events, which requires the authorization.
+    async fn authorized_document(
+        &self,
+        internal_key: &str,
+        oracle_uuid: &str,
+        action: &str,
+    ) -> Result<NFeDocument, RepositoryError> {
+        let status = self.status.current(oracle_uuid)?;
+        if status != NFeStatus::Authorized {
+            return Err(RepositoryError::Conflict(format!(
+                "only authorized notes can {}; the note is {}",
+                action, status
+            )));
+        }
+        let document = self.documents.find(internal_key).await?;
+        let ide = &document.identification;
+        check_environment(
+            &self.sefaz,
+            &authorizer(&ide.c_uf, &ide.mod_, NORMAL),
+            &ide.tp_amb,
+        )?;
+        Ok(document)
+    }
+
+    /// Highest `nSeqEvento` registered for the event type, 0 when there is none.
+    fn last_sequence(
+        conn: &Connection,
+        oracle_uuid: &str,
+        tp_evento: &str,
+    ) -> Result<u32, RepositoryError> {
+        let sql = format!(
+            "SELECT NVL(MAX(NSEQEVENTO), 0) FROM nfe_events WHERE INTERNALKEY = HEXTORAW(:1) AND TPEVENTO = :2 AND CSTAT IN ('{}')",
+            REGISTERED.join("', '")
+        );
+        Ok(conn.query_row_as::<u32>(&sql, &[&oracle_uuid, &tp_evento])?)
+    }
+
+    /// Builds the event for the next `nSeqEvento` of its type with `event`, then signs,
+    /// sends and stores it in one transaction that holds the lock of the note row, so
+    /// concurrent events of the same note never take the same sequence.
+    async fn send(
+        &self,
+        oracle_uuid: &str,
+        tp_evento: &str,
+        detail: &str,
+        event: impl FnOnce(u32) -> Result<Event, RepositoryError>,
+    ) -> Result<NFeEvent, RepositoryError> {
+        let conn = self.pool.get()?;
+        match self
+            .send_locked(&conn, oracle_uuid, tp_evento, detail, event)
+            .await
+        {
+            Ok(stored) => {
+                conn.commit()?;
+                Ok(stored)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = conn.rollback() {
+                    error!("Failed to roll back the transaction: {}", rollback_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn send_locked(
+        &self,
+        conn: &Connection,
+        oracle_uuid: &str,
+        tp_evento: &str,
+        detail: &str,
+        event: impl FnOnce(u32) -> Result<Event, RepositoryError>,
+    ) -> Result<NFeEvent, RepositoryError> {
+        // The status is read again under the lock: the note may have been cancelled since
+        // it was loaded.
+        let status = self.status.lock(conn, oracle_uuid)?;
+        if status != NFeStatus::Authorized {
+            return Err(RepositoryError::Conflict(format!(
+                "only authorized notes take events; the note is {}",
+                status
+            )));
+        }
+        let event = event(Self::last_sequence(conn, oracle_uuid, tp_evento)? + 1)?;
+
+        let signed = self.signing.sign(&event.to_xml(), &event.id())?;
+        let mod_ = AccessKey::parse(&event.ch_nfe)?.parts.mod_;
+        let id_lote = Utc::now().timestamp_millis().to_string();
+        let ret = event::send(
+            &self.sefaz,
+            &authorizer(&event.c_orgao, &mod_, NORMAL),
+            &id_lote,
+            std::slice::from_ref(&signed),
+        )
+        .await?
+        .into_iter()
+        .find(|ret| ret.ch_nfe.as_deref() == Some(event.ch_nfe.as_str()))
+        .ok_or_else(|| SefazError::InvalidResponse(format!("no retEvento for {}", event.ch_nfe)))?;
+        info!("SEFAZ answered {} - {}", ret.c_stat, ret.x_motivo);
+
+        let xml = if ret.is_registered() {
+            event::proc_evento(&signed, &ret.xml)
+        } else {
+            signed
+        };
+        let id = Uuid::new_v4().to_string().replace('-', "");
+        let inserted = conn.execute(
+            r#"
+            INSERT INTO nfe_events (
+                ID,
+                INTERNALKEY,
+                TPEVENTO,
+                NSEQEVENTO,
+                CHNFE,
+                DHEVENTO,
+                DETAIL,
+                CSTAT,
+                XMOTIVO,
+                NPROT,
+                DHREGEVENTO,
+                EVENTXML
+            ) VALUES (
+                HEXTORAW(:1),
+                HEXTORAW(:2),
+                :3,
+                :4,
+                :5,
+                TO_TIMESTAMP(:6, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+                :7,
+                :8,
+                :9,
+                :10,
+                TO_TIMESTAMP(:11, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+                :12
+            )
+            "#,
+            &[
+                &id,
+                &oracle_uuid,
+                &event.tp_evento,
+                &event.n_seq_evento,
+                &event.ch_nfe,
+                &event
+                    .dh_evento
+                    .with_timezone(&Utc)
+                    .format(TIMESTAMP_FORMAT)
+                    .to_string(),
+                &detail,
+                &ret.c_stat,
+                &ret.x_motivo,
+                &ret.n_prot,
+                &ret.dh_reg_evento
+                    .map(|dh| dh.format(TIMESTAMP_FORMAT).to_string()),
+                &xml,
+            ],
+        );
+        match inserted {
+            Ok(_) => {}
+            Err(e) if is_unique_violation(&e) => {
+                return Err(RepositoryError::Conflict(format!(
+                    "event {} sequence {} is already registered for the note",
+                    event.tp_evento, event.n_seq_evento
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let sql = format!(
+            "SELECT {} FROM nfe_events WHERE ID = HEXTORAW(:1)",
+            EVENT_COLUMNS
+        );
+        map_event(&conn.query_row(&sql, &[&id])?)
+    }
+}
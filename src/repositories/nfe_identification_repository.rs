@@ -1,9 +1,20 @@
-use crate::errors::RepositoryError;
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_access_key::validate_c_nf;
 use crate::models::nfe_identification::{CreateNFeIdentification, NFeIdentification};
+use crate::models::nfe_numbering::NFeSerie;
+use crate::models::nfe_reference::RETURN;
+use crate::models::nfe_status::NFeStatus;
+use crate::repositories::common::{in_transaction, is_unique_violation};
+use crate::repositories::nfe_access_key_repository::refresh_check_digit;
+use crate::repositories::nfe_item_repository::recompute_item_taxes;
+use crate::repositories::nfe_numbering_repository::{allocate_number, number_taken};
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
 use crate::services::cache_service::CacheService;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use oracle::pool::Pool;
 use oracle::Connection;
 use redis::aio::ConnectionManager;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, error, info, instrument};
@@ -17,18 +28,160 @@ pub struct NFeFilterParams {
     pub sort_order: Option<String>,
     pub filter: Option<String>,
     pub search: Option<String>,
+    pub status: Option<NFeStatus>,
+}
+
+/// Inserts a new note, allocating nNF from the serie of its emitter when it is omitted.
+/// Runs in the transaction that records the creation, so a rollback returns the number.
+pub fn insert_identification(
+    conn: &Connection,
+    oracle_uuid: &str,
+    identification: &CreateNFeIdentification,
+) -> Result<(), RepositoryError> {
+    let sql = r#"
+        INSERT INTO nfe_identifications (
+            INTERNALKEY,
+            CUF,
+            CNF,
+            NATOP,
+            MOD_,
+            SERIE,
+            NNF,
+            DHEMI,
+            DHSAIENT,
+            DHCONT,
+            TPNF,
+            IDDEST,
+            CMUNFG,
+            TPIMP,
+            TPEMIS,
+            TPAMB,
+            FINNFE,
+            INDFINAL,
+            INDPRES,
+            PROCEMI,
+            VERPROC,
+            EMITDOC,
+            X_JUSTIFICATIVA
+        ) VALUES (
+            HEXTORAW(:1),
+            :2,
+            :3,
+            :4,
+            :5,
+            :6,
+            :7,
+            TO_TIMESTAMP(:8, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+            TO_TIMESTAMP(:9, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+            TO_TIMESTAMP(:10, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+            :11,
+            :12,
+            :13,
+            :14,
+            :15,
+            :16,
+            :17,
+            :18,
+            :19,
+            :20,
+            :21,
+            :22,
+            :23
+        )
+    "#;
+
+    let dh_emi_str = identification
+        .dh_emi
+        .format("%Y-%m-%d %H:%M:%S%.3f")
+        .to_string();
+    let dh_sai_ent_str = identification
+        .dh_sai_ent
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+    let dh_cont_str = identification
+        .dh_cont
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+
+    let n_nf = match (&identification.n_nf, identification.emit_doc()) {
+        (Some(n_nf), _) => n_nf.clone(),
+        (None, Some(emit_doc)) => {
+            let serie = identification
+                .serie
+                .parse()
+                .map_err(|_| ValidationError::new("serie", "must have between 1 and 3 digits"))?;
+            let n_nf = allocate_number(
+                conn,
+                &NFeSerie {
+                    emit_doc: emit_doc.to_string(),
+                    mod_: identification.mod_.clone(),
+                    serie,
+                    tp_amb: identification.tp_amb.clone(),
+                },
+            )?
+            .to_string();
+            validate_c_nf(&identification.c_nf, &n_nf)?;
+            n_nf
+        }
+        (None, None) => {
+            return Err(ValidationError::new(
+                "emitCNPJ",
+                "is required (or emitCPF) to allocate nNF from the serie of the emitter",
+            )
+            .into())
+        }
+    };
+
+    let mut stmt = conn.statement(sql).build()?;
+    match stmt.execute(&[
+        &oracle_uuid,
+        &identification.c_uf,
+        &identification.c_nf,
+        &identification.nat_op,
+        &identification.mod_,
+        &identification.serie,
+        &n_nf,
+        &dh_emi_str,
+        &dh_sai_ent_str,
+        &dh_cont_str,
+        &identification.tp_nf,
+        &identification.id_dest,
+        &identification.c_mun_fg,
+        &identification.tp_imp,
+        &identification.tp_emis,
+        &identification.tp_amb,
+        &identification.fin_nfe,
+        &identification.ind_final,
+        &identification.ind_pres,
+        &identification.proc_emi,
+        &identification.ver_proc,
+        &identification.emit_doc(),
+        &identification.x_justificativa,
+    ]) {
+        Ok(_) => {}
+        Err(e) if is_unique_violation(&e) => return Err(number_taken()),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
 }
 
 pub struct NFeIdentificationRepository {
-    conn: Connection,
+    conn: Arc<Connection>,
+    pool: Pool,
     cache: Arc<CacheService>,
+    status: Arc<NFeStatusRepository>,
 }
 
 impl NFeIdentificationRepository {
-    pub fn new(conn: Connection, redis_manager: ConnectionManager) -> Self {
+    pub fn new(
+        conn: Arc<Connection>,
+        pool: Pool,
+        redis_manager: ConnectionManager,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
         Self {
             conn,
+            pool,
             cache: Arc::new(CacheService::new(redis_manager)),
+            status,
         }
     }
 
@@ -44,13 +197,14 @@ impl NFeIdentificationRepository {
 
         // Try to get from cache first
         let cache_key = format!(
-            "nfe:list:{}:{}:{}:{}:{}:{}",
+            "nfe:list:{}:{}:{}:{}:{}:{}:{}",
             params.page,
             params.page_size,
             params.sort_by.as_deref().unwrap_or(""),
             params.sort_order.as_deref().unwrap_or(""),
             params.filter.as_deref().unwrap_or(""),
-            params.search.as_deref().unwrap_or("")
+            params.search.as_deref().unwrap_or(""),
+            params.status.map(|s| s.as_str()).unwrap_or("")
         );
 
         if let Ok(Some(cached)) = self
@@ -89,6 +243,11 @@ impl NFeIdentificationRepository {
             bind_params.push(("search".to_string(), format!("%{}%", search)));
         }
 
+        if let Some(status) = &params.status {
+            where_clauses.push("STATUS = :status");
+            bind_params.push(("status".to_string(), status.as_str().to_string()));
+        }
+
         let where_clause = if !where_clauses.is_empty() {
             format!("WHERE {}", where_clauses.join(" AND "))
         } else {
@@ -141,6 +300,7 @@ impl NFeIdentificationRepository {
                         PROCEMI as proc_emi,
                         VERPROC as ver_proc,
                         X_JUSTIFICATIVA as x_justificativa,
+                        STATUS as status,
                         TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at,
                         TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as updated_at
                     FROM nfe_identifications
@@ -216,7 +376,7 @@ impl NFeIdentificationRepository {
                 c_mun_fg: row.get("c_mun_fg")?,
                 tp_imp: row.get("tp_imp")?,
                 tp_emis: row.get("tp_emis")?,
-                c_dv: row.get("c_dv")?,
+                c_dv: row.get::<_, Option<String>>("c_dv")?.unwrap_or_default(),
                 tp_amb: row.get("tp_amb")?,
                 fin_nfe: row.get("fin_nfe")?,
                 ind_final: row.get("ind_final")?,
@@ -224,6 +384,8 @@ impl NFeIdentificationRepository {
                 proc_emi: row.get("proc_emi")?,
                 ver_proc: row.get("ver_proc")?,
                 x_justificativa: row.get("x_justificativa")?,
+                status: NFeStatus::from_str(&row.get::<_, String>("status")?)
+                    .map_err(|e| RepositoryError::InvalidData(e.to_string()))?,
                 created_at,
                 updated_at,
             };
@@ -267,31 +429,33 @@ impl NFeIdentificationRepository {
         let oracle_uuid = uuid.to_string().replace('-', "");
 
         let sql = r#"
-            SELECT 
-                RAWTOHEX(INTERNALKEY),
-                CUF,
-                CNF,
-                NATOP,
-                MOD_,
-                SERIE,
-                NNF,
-                TO_CHAR(DHEMI, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TO_CHAR(DHSAIENT, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TO_CHAR(DHCONT, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TPNF,
-                IDDEST,
-                CMUNFG,
-                TPIMP,
-                TPEMIS,
-                CDV,
-                TPAMB,
-                FINNFE,
-                INDFINAL,
-                INDPRES,
-                PROCEMI,
-                VERPROC,
-                TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3')
+            SELECT
+                RAWTOHEX(INTERNALKEY) as internal_key,
+                CUF as c_uf,
+                CNF as c_nf,
+                NATOP as nat_op,
+                MOD_ as mod_,
+                SERIE as serie,
+                NNF as n_nf,
+                TO_CHAR(DHEMI, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_emi,
+                TO_CHAR(DHSAIENT, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_sai_ent,
+                TO_CHAR(DHCONT, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_cont,
+                TPNF as tp_nf,
+                IDDEST as id_dest,
+                CMUNFG as c_mun_fg,
+                TPIMP as tp_imp,
+                TPEMIS as tp_emis,
+                CDV as c_dv,
+                TPAMB as tp_amb,
+                FINNFE as fin_nfe,
+                INDFINAL as ind_final,
+                INDPRES as ind_pres,
+                PROCEMI as proc_emi,
+                VERPROC as ver_proc,
+                X_JUSTIFICATIVA as x_justificativa,
+                STATUS as status,
+                TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at,
+                TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as updated_at
             FROM nfe_identifications
             WHERE INTERNALKEY = HEXTORAW(:1)
         "#;
@@ -301,12 +465,12 @@ impl NFeIdentificationRepository {
 
         if let Some(row_result) = rows.next() {
             let row = row_result?;
-            let internal_key_hex: String = row.get(0)?;
-            let dh_emi_str: String = row.get(7)?;
-            let dh_sai_ent_str: Option<String> = row.get(8)?;
-            let dh_cont_str: Option<String> = row.get(9)?;
-            let created_at_str: String = row.get(20)?;
-            let updated_at_str: String = row.get(21)?;
+            let internal_key_hex: String = row.get("internal_key")?;
+            let dh_emi_str: String = row.get("dh_emi")?;
+            let dh_sai_ent_str: Option<String> = row.get("dh_sai_ent")?;
+            let dh_cont_str: Option<String> = row.get("dh_cont")?;
+            let created_at_str: String = row.get("created_at")?;
+            let updated_at_str: String = row.get("updated_at")?;
 
             let internal_key = Uuid::parse_str(&internal_key_hex)
                 .map_err(|e| RepositoryError::InvalidUuid(e.to_string()))?;
@@ -339,28 +503,30 @@ impl NFeIdentificationRepository {
 
             let identification = NFeIdentification {
                 internal_key: internal_key.to_string(),
-                c_uf: row.get(1)?,
-                c_nf: row.get(2)?,
-                nat_op: row.get(3)?,
-                mod_: row.get(4)?,
-                serie: row.get(5)?,
-                n_nf: row.get(6)?,
+                c_uf: row.get("c_uf")?,
+                c_nf: row.get("c_nf")?,
+                nat_op: row.get("nat_op")?,
+                mod_: row.get("mod_")?,
+                serie: row.get("serie")?,
+                n_nf: row.get("n_nf")?,
                 dh_emi,
                 dh_sai_ent,
                 dh_cont,
-                tp_nf: row.get(10)?,
-                id_dest: row.get(11)?,
-                c_mun_fg: row.get(12)?,
-                tp_imp: row.get(13)?,
-                tp_emis: row.get(14)?,
-                c_dv: row.get(15)?,
-                tp_amb: row.get(16)?,
-                fin_nfe: row.get(17)?,
-                ind_final: row.get(18)?,
-                ind_pres: row.get(19)?,
-                proc_emi: row.get(20)?,
-                ver_proc: row.get(21)?,
-                x_justificativa: row.get(22)?,
+                tp_nf: row.get("tp_nf")?,
+                id_dest: row.get("id_dest")?,
+                c_mun_fg: row.get("c_mun_fg")?,
+                tp_imp: row.get("tp_imp")?,
+                tp_emis: row.get("tp_emis")?,
+                c_dv: row.get::<_, Option<String>>("c_dv")?.unwrap_or_default(),
+                tp_amb: row.get("tp_amb")?,
+                fin_nfe: row.get("fin_nfe")?,
+                ind_final: row.get("ind_final")?,
+                ind_pres: row.get("ind_pres")?,
+                proc_emi: row.get("proc_emi")?,
+                ver_proc: row.get("ver_proc")?,
+                x_justificativa: row.get("x_justificativa")?,
+                status: NFeStatus::from_str(&row.get::<_, String>("status")?)
+                    .map_err(|e| RepositoryError::InvalidData(e.to_string()))?,
                 created_at,
                 updated_at,
             };
@@ -390,109 +556,27 @@ impl NFeIdentificationRepository {
         info!("Creating new NFe identification");
         debug!("Input data: {:?}", identification);
 
-        let sql = r#"
-            INSERT INTO nfe_identifications (
-                INTERNALKEY,
-                CUF,
-                CNF,
-                NATOP,
-                MOD_,
-                SERIE,
-                NNF,
-                DHEMI,
-                DHSAIENT,
-                DHCONT,
-                TPNF,
-                IDDEST,
-                CMUNFG,
-                TPIMP,
-                TPEMIS,
-                CDV,
-                TPAMB,
-                FINNFE,
-                INDFINAL,
-                INDPRES,
-                PROCEMI,
-                VERPROC
-            ) VALUES (
-                HEXTORAW(:1),
-                :2,
-                :3,
-                :4,
-                :5,
-                :6,
-                :7,
-                TO_TIMESTAMP(:8, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TO_TIMESTAMP(:9, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                TO_TIMESTAMP(:10, 'YYYY-MM-DD HH24:MI:SS.FF3'),
-                :11,
-                :12,
-                :13,
-                :14,
-                :15,
-                :16,
-                :17,
-                :18,
-                :19,
-                :20,
-                :21,
-                :22
-            )
-        "#;
+        identification.validate()?;
 
-        let mut stmt = self.conn.statement(sql).build()?;
         let internal_key = Uuid::new_v4();
 
         // Format UUID for Oracle HEXTORAW (remove hyphens)
         let oracle_uuid = internal_key.to_string().replace('-', "");
 
-        let dh_emi_str = identification
-            .dh_emi
-            .format("%Y-%m-%d %H:%M:%S%.3f")
-            .to_string();
-        let dh_sai_ent_str = identification
-            .dh_sai_ent
-            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
-        let dh_cont_str = identification
-            .dh_cont
-            .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
-
-        debug!(
-            "Executing SQL with parameters: internal_key={}, dh_emi={}, dh_sai_ent={:?}, dh_cont={:?}",
-            oracle_uuid, dh_emi_str, dh_sai_ent_str, dh_cont_str
-        );
-
-        let result = stmt.execute(&[
-            &oracle_uuid,
-            &identification.c_uf,
-            &identification.c_nf,
-            &identification.nat_op,
-            &identification.mod_,
-            &identification.serie,
-            &identification.n_nf,
-            &dh_emi_str,
-            &dh_sai_ent_str,
-            &dh_cont_str,
-            &identification.tp_nf,
-            &identification.id_dest,
-            &identification.c_mun_fg,
-            &identification.tp_imp,
-            &identification.tp_emis,
-            &identification.c_dv,
-            &identification.tp_amb,
-            &identification.fin_nfe,
-            &identification.ind_final,
-            &identification.ind_pres,
-            &identification.proc_emi,
-            &identification.ver_proc,
-        ]);
+        // The number, the note and its history commit together, so a failed insert
+        // hands the number back to the serie.
+        let result = in_transaction(&self.pool, |conn| {
+            insert_identification(conn, &oracle_uuid, identification)?;
+            self.status.record_created(conn, &oracle_uuid)
+        });
 
         match result {
-            Ok(_) => {
+            Ok(()) => {
                 info!(
                     "Successfully created NFe identification with ID {}",
                     internal_key
                 );
+                // cDV stays empty until the emitter completes the access key.
                 let created = self
                     .find_by_id(&internal_key.to_string())
                     .await?
@@ -507,7 +591,7 @@ impl NFeIdentificationRepository {
             }
             Err(e) => {
                 error!("Failed to create NFe identification: {}", e);
-                Err(RepositoryError::OracleError(e))
+                Err(e)
             }
         }
     }
@@ -521,6 +605,9 @@ impl NFeIdentificationRepository {
         info!("Updating NFe identification with ID {}", internal_key);
         debug!("Update data: {:?}", identification);
 
+        identification.validate()?;
+        self.status.ensure_editable(internal_key)?;
+
         // Parse the UUID to ensure it's valid
         let uuid = Uuid::parse_str(internal_key)
             .map_err(|e| RepositoryError::InvalidUuid(e.to_string()))?;
@@ -545,18 +632,16 @@ impl NFeIdentificationRepository {
                 CMUNFG = NVL(:12, CMUNFG),
                 TPIMP = NVL(:13, TPIMP),
                 TPEMIS = NVL(:14, TPEMIS),
-                CDV = NVL(:15, CDV),
-                TPAMB = NVL(:16, TPAMB),
-                FINNFE = NVL(:17, FINNFE),
-                INDFINAL = NVL(:18, INDFINAL),
-                INDPRES = NVL(:19, INDPRES),
-                PROCEMI = NVL(:20, PROCEMI),
-                VERPROC = NVL(:21, VERPROC)
+                TPAMB = NVL(:15, TPAMB),
+                FINNFE = NVL(:16, FINNFE),
+                INDFINAL = NVL(:17, INDFINAL),
+                INDPRES = NVL(:18, INDPRES),
+                PROCEMI = NVL(:19, PROCEMI),
+                VERPROC = NVL(:20, VERPROC),
+                X_JUSTIFICATIVA = NVL(:21, X_JUSTIFICATIVA)
             WHERE INTERNALKEY = HEXTORAW(:22)
         "#;
 
-        let mut stmt = self.conn.statement(sql).build()?;
-
         let dh_emi_str = identification
             .dh_emi
             .format("%Y-%m-%d %H:%M:%S%.3f")
@@ -573,55 +658,88 @@ impl NFeIdentificationRepository {
             dh_emi_str, dh_sai_ent_str, dh_cont_str, oracle_uuid
         );
 
-        let result = stmt.execute(&[
-            &identification.c_uf,
-            &identification.c_nf,
-            &identification.nat_op,
-            &identification.mod_,
-            &identification.serie,
-            &identification.n_nf,
-            &dh_emi_str,
-            &dh_sai_ent_str,
-            &dh_cont_str,
-            &identification.tp_nf,
-            &identification.id_dest,
-            &identification.c_mun_fg,
-            &identification.tp_imp,
-            &identification.tp_emis,
-            &identification.c_dv,
-            &identification.tp_amb,
-            &identification.fin_nfe,
-            &identification.ind_final,
-            &identification.ind_pres,
-            &identification.proc_emi,
-            &identification.ver_proc,
-            &oracle_uuid,
-        ]);
+        let result = self
+            .status
+            .edit(internal_key, |conn| {
+                // A return keeps referencing the documents it returns.
+                if identification.fin_nfe == RETURN {
+                    let references: u32 = conn.query_row_as(
+                        "SELECT COUNT(*) FROM nfe_references WHERE INTERNALKEY = HEXTORAW(:1)",
+                        &[&oracle_uuid],
+                    )?;
+                    if references == 0 {
+                        return Err(ValidationError::new(
+                            "finNFe",
+                            "a return (finNFe 4) must reference at least one document; add its NFref first",
+                        )
+                        .into());
+                    }
+                }
+                let mut stmt = conn.statement(sql).build()?;
+                match stmt.execute(&[
+                    &identification.c_uf,
+                    &identification.c_nf,
+                    &identification.nat_op,
+                    &identification.mod_,
+                    &identification.serie,
+                    &identification.n_nf,
+                    &dh_emi_str,
+                    &dh_sai_ent_str,
+                    &dh_cont_str,
+                    &identification.tp_nf,
+                    &identification.id_dest,
+                    &identification.c_mun_fg,
+                    &identification.tp_imp,
+                    &identification.tp_emis,
+                    &identification.tp_amb,
+                    &identification.fin_nfe,
+                    &identification.ind_final,
+                    &identification.ind_pres,
+                    &identification.proc_emi,
+                    &identification.ver_proc,
+                    &identification.x_justificativa,
+                    &oracle_uuid,
+                ]) {
+                    Ok(_) => {}
+                    Err(e) if is_unique_violation(&e) => return Err(number_taken()),
+                    Err(e) => return Err(e.into()),
+                }
+                // The stored item taxes depend on indFinal.
+                recompute_item_taxes(conn, &oracle_uuid)?;
+                // The client's cDV is ignored: it is derived from the key fields.
+                refresh_check_digit(conn, &oracle_uuid)
+            })
+            .await;
 
         match result {
-            Ok(_) => {
+            Ok(()) => {
                 info!(
                     "Successfully updated NFe identification with ID {}",
                     internal_key
                 );
-                let updated = self
-                    .find_by_id(internal_key)
-                    .await?
-                    .ok_or(RepositoryError::UpdateFailed)?;
 
-                // Invalidate caches
+                // Invalidate caches before reading back the updated row
                 if let Err(e) = self.cache.delete(&format!("nfe:{}", internal_key)).await {
                     error!("Failed to invalidate item cache: {}", e);
                 }
                 if let Err(e) = self.cache.delete("nfe:list:*").await {
                     error!("Failed to invalidate list cache: {}", e);
                 }
+                if let Err(e) = self
+                    .cache
+                    .delete(&format!("nfe:{}:items", internal_key))
+                    .await
+                {
+                    error!("Failed to invalidate items cache: {}", e);
+                }
 
-                Ok(updated)
+                self.find_by_id(internal_key)
+                    .await?
+                    .ok_or(RepositoryError::UpdateFailed)
             }
             Err(e) => {
                 error!("Failed to update NFe identification: {}", e);
-                Err(RepositoryError::OracleError(e))
+                Err(e)
             }
         }
     }
@@ -639,20 +757,28 @@ impl NFeIdentificationRepository {
 
         let sql = "DELETE FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)";
 
-        let mut stmt = self.conn.statement(sql).build()?;
-        let result = stmt.execute(&[&oracle_uuid]);
+        // The status is checked under the row lock, so a note cannot be signed or sent
+        // between the check and the delete.
+        let result = self.status.remove(internal_key, |conn| {
+            let mut stmt = conn.statement(sql).build()?;
+            stmt.execute(&[&oracle_uuid])?;
+            Ok(())
+        });
 
         match result {
-            Ok(_) => {
+            Ok(()) => {
                 info!(
                     "Successfully deleted NFe identification with ID {}",
                     internal_key
                 );
 
-                // Invalidate caches
+                // Invalidate caches, including those of the groups deleted with the note
                 if let Err(e) = self.cache.delete(&format!("nfe:{}", internal_key)).await {
                     error!("Failed to invalidate item cache: {}", e);
                 }
+                if let Err(e) = self.cache.delete(&format!("nfe:{}:*", internal_key)).await {
+                    error!("Failed to invalidate group caches: {}", e);
+                }
                 if let Err(e) = self.cache.delete("nfe:list:*").await {
                     error!("Failed to invalidate list cache: {}", e);
                 }
@@ -661,7 +787,7 @@ impl NFeIdentificationRepository {
             }
             Err(e) => {
                 error!("Failed to delete NFe identification: {}", e);
-                Err(RepositoryError::OracleError(e))
+                Err(e)
             }
         }
     }
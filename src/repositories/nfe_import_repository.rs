@@ -0,0 +1,316 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_access_key::{aamm, AccessKey, AccessKeyParts};
+use crate::models::nfe_additional::validate_authorized_downloads;
+use crate::models::nfe_import::{ImportReport, ImportStatus, ImportedFile};
+use crate::models::nfe_payment::NFePayment;
+use crate::models::nfe_protocol::AUTHORIZED;
+use crate::models::nfe_reference::validate_references;
+use crate::models::nfe_transport::NFeTransport;
+use crate::repositories::common::in_transaction;
+use crate::repositories::nfe_access_key_repository::find_by_access_key;
+use crate::repositories::nfe_additional_repository::{store_group, AUTXML, INFADIC, INFRESPTEC};
+use crate::repositories::nfe_authorization_repository::insert_protocol;
+use crate::repositories::nfe_identification_repository::insert_identification;
+use crate::repositories::nfe_item_repository::{insert_item, items_of};
+use crate::repositories::nfe_participant_repository::{insert_emitter, insert_recipient};
+use crate::repositories::nfe_payment_repository::{store_billing, store_payment};
+use crate::repositories::nfe_reference_repository::store_references;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::repositories::nfe_total_repository::store_totals;
+use crate::repositories::nfe_transport_repository::store_transport;
+use crate::services::cache_service::CacheService;
+use crate::services::tax::{compute_item_taxes, totals};
+use crate::services::xml::archive::{self, ImportFile};
+use crate::services::xml::nfe_parser::{self, ParsedNFe};
+use crate::services::xml::signature;
+use oracle::pool::Pool;
+use oracle::Connection;
+use redis::aio::ConnectionManager;
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+/// Loads NF-e XML files. Each file is validated with the rules of the API and stored in
+/// one transaction, so a file that fails leaves nothing behind. Drafts have their taxes
+/// derived again like notes created through the API; notes that come with their
+/// authorization are stored as received.
+pub struct NFeImportRepository {
+    conn: Arc<Connection>,
+    pool: Pool,
+    cache: Arc<CacheService>,
+    status: Arc<NFeStatusRepository>,
+}
+
+/// Reason shown in the report; validation errors are shown without the wrapper prefix.
+fn reason(error: &RepositoryError) -> String {
+    match error {
+        RepositoryError::Validation(e) => e.to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn imported(file: &str, status: ImportStatus) -> ImportedFile {
+    ImportedFile {
+        file: file.to_string(),
+        status,
+        access_key: None,
+        internal_key: None,
+        n_prot: None,
+        signer: None,
+        reason: None,
+    }
+}
+
+impl NFeImportRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        pool: Pool,
+        redis_manager: ConnectionManager,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
+        Self {
+            conn,
+            pool,
+            cache: Arc::new(CacheService::new(redis_manager)),
+            status,
+        }
+    }
+
+    /// Imports a single XML file or every XML file of a ZIP archive.
+    #[instrument(skip(self, upload), fields(file_name = %file_name, size = upload.len()))]
+    pub async fn import(
+        &self,
+        file_name: &str,
+        upload: &[u8],
+    ) -> Result<ImportReport, RepositoryError> {
+        info!("Importing NFe XML upload");
+
+        let mut report = ImportReport::default();
+        for ImportFile { name, content } in archive::unpack(file_name, upload)? {
+            let result = match content {
+                Ok(content) => self.import_file(&name, &content).await,
+                Err(e) => ImportedFile {
+                    reason: Some(e.to_string()),
+                    ..imported(&name, ImportStatus::Failed)
+                },
+            };
+            report.push(result);
+        }
+
+        info!(
+            "Import finished: {} inserted, {} skipped, {} failed",
+            report.inserted, report.skipped, report.failed
+        );
+        Ok(report)
+    }
+
+    /// Imports one XML document; failures are reported in the outcome.
+    pub async fn import_file(&self, name: &str, content: &[u8]) -> ImportedFile {
+        let xml = match std::str::from_utf8(content) {
+            Ok(xml) => xml.trim_start_matches('\u{feff}'),
+            Err(_) => {
+                return ImportedFile {
+                    reason: Some(
+                        ValidationError::new("xml", "file is not UTF-8 encoded").to_string(),
+                    ),
+                    ..imported(name, ImportStatus::Failed)
+                }
+            }
+        };
+        let parsed = match nfe_parser::parse(xml) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                return ImportedFile {
+                    reason: Some(e.to_string()),
+                    ..imported(name, ImportStatus::Failed)
+                }
+            }
+        };
+        let key = parsed.access_key.to_string();
+
+        // Unsigned files are accepted, but a signature that is present must hold.
+        let signer = match signature::verify(xml) {
+            Ok(verified) => verified.map(|v| v.subject),
+            Err(e) => {
+                return ImportedFile {
+                    access_key: Some(key),
+                    reason: Some(reason(&e.into())),
+                    ..imported(name, ImportStatus::Failed)
+                }
+            }
+        };
+
+        let outcome = match find_by_access_key(&self.conn, &parsed.access_key) {
+            Ok(Some(existing)) => ImportedFile {
+                internal_key: Some(existing),
+                reason: Some("a note with this access key already exists".to_string()),
+                ..imported(name, ImportStatus::Skipped)
+            },
+            Ok(None) => match self.insert(&parsed) {
+                Ok(internal_key) => {
+                    if let Err(e) = self.cache.delete("nfe:list:*").await {
+                        error!("Failed to invalidate list cache: {}", e);
+                    }
+                    ImportedFile {
+                        internal_key: Some(internal_key),
+                        ..imported(name, ImportStatus::Inserted)
+                    }
+                }
+                Err(e) => ImportedFile {
+                    reason: Some(reason(&e)),
+                    ..imported(name, ImportStatus::Failed)
+                },
+            },
+            Err(e) => ImportedFile {
+                reason: Some(reason(&e)),
+                ..imported(name, ImportStatus::Failed)
+            },
+        };
+        ImportedFile {
+            access_key: Some(key),
+            n_prot: parsed.protocol.as_ref().and_then(|p| p.n_prot.clone()),
+            signer,
+            ..outcome
+        }
+    }
+
+    /// Validates every group of the note and inserts them in one transaction.
+    fn insert(&self, parsed: &ParsedNFe) -> Result<String, RepositoryError> {
+        let ide = &parsed.identification;
+        let emitter = &parsed.emitter;
+        let derived = AccessKey::new(AccessKeyParts {
+            c_uf: ide.c_uf.clone(),
+            aamm: aamm(&ide.c_uf, &ide.dh_emi),
+            document: emitter
+                .cnpj
+                .clone()
+                .or_else(|| emitter.cpf.clone())
+                .unwrap_or_default(),
+            mod_: ide.mod_.clone(),
+            serie: ide.serie.clone(),
+            n_nf: ide.n_nf.clone().unwrap_or_default(),
+            tp_emis: ide.tp_emis.clone(),
+            c_nf: ide.c_nf.clone(),
+        })?;
+        if derived != parsed.access_key {
+            return Err(ValidationError::new(
+                "infNFe.Id",
+                format!(
+                    "does not match the key built from ide and emit ({})",
+                    derived
+                ),
+            )
+            .into());
+        }
+        if let Some(protocol) = &parsed.protocol {
+            if protocol.ch_nfe != parsed.access_key.key {
+                return Err(ValidationError::new(
+                    "protNFe.chNFe",
+                    format!("does not match the key of the note ({})", parsed.access_key),
+                )
+                .into());
+            }
+            if !AUTHORIZED.contains(&protocol.c_stat.as_str()) {
+                return Err(ValidationError::new(
+                    "protNFe.cStat",
+                    format!(
+                        "{} - {} is not an authorization",
+                        protocol.c_stat, protocol.x_motivo
+                    ),
+                )
+                .into());
+            }
+        }
+
+        ide.validate()?;
+        emitter.validate()?;
+        if let Some(recipient) = &parsed.recipient {
+            recipient.validate()?;
+        }
+        validate_references(&ide.fin_nfe, &parsed.references)?;
+        for item in &parsed.items {
+            item.validate()?;
+        }
+        parsed.total.validate()?;
+        if parsed.transport != NFeTransport::default() {
+            parsed.transport.validate(&ide.mod_, &ide.id_dest)?;
+        }
+        if !parsed.billing.is_empty() {
+            parsed.billing.validate(&ide.mod_)?;
+        }
+        validate_authorized_downloads(&parsed.authorized_downloads)?;
+        if !parsed.additional_info.is_empty() {
+            parsed.additional_info.validate()?;
+        }
+        if let Some(responsible) = &parsed.tech_responsible {
+            responsible.validate()?;
+        }
+
+        let internal_key = Uuid::new_v4().to_string();
+        let oracle_uuid = internal_key.replace('-', "");
+        in_transaction(&self.pool, |conn| {
+            insert_identification(conn, &oracle_uuid, ide)?;
+            self.status.record_created(conn, &oracle_uuid)?;
+            insert_emitter(conn, &oracle_uuid, emitter)?;
+            if let Some(recipient) = &parsed.recipient {
+                insert_recipient(conn, &oracle_uuid, recipient)?;
+            }
+            store_references(conn, &oracle_uuid, &parsed.references)?;
+
+            // Taxes accepted by SEFAZ are kept as received; a draft has them derived
+            // again and its declared totals checked against the result.
+            for item in &parsed.items {
+                let mut imposto = item.imposto.clone();
+                if parsed.protocol.is_none() {
+                    compute_item_taxes(&item.prod, &mut imposto, ide.ind_final == "1")?;
+                }
+                insert_item(conn, &oracle_uuid, item, &imposto)?;
+            }
+            let issqn = parsed.total.issqn_tot.as_ref();
+            let (icms_tot, issqn_tot) = totals::compute(
+                &items_of(conn, &internal_key, &oracle_uuid)?,
+                issqn.map_or(ide.dh_emi.date_naive(), |t| t.d_compet),
+                issqn.and_then(|t| t.c_reg_trib.clone()),
+            );
+            if parsed.protocol.is_none() {
+                totals::check_declared(&icms_tot, issqn_tot.as_ref(), &parsed.total)?;
+            }
+            store_totals(conn, &oracle_uuid, &parsed.total)?;
+
+            if parsed.transport != NFeTransport::default() {
+                store_transport(conn, &oracle_uuid, &parsed.transport)?;
+            }
+            if !parsed.billing.is_empty() {
+                store_billing(conn, &oracle_uuid, &parsed.billing)?;
+            }
+            if parsed.payment != NFePayment::default() {
+                parsed.payment.validate(icms_tot.v_nf)?;
+                store_payment(conn, &oracle_uuid, &parsed.payment)?;
+            }
+            if !parsed.authorized_downloads.is_empty() {
+                store_group(
+                    conn,
+                    &oracle_uuid,
+                    AUTXML,
+                    Some(&parsed.authorized_downloads[..]),
+                )?;
+            }
+            if !parsed.additional_info.is_empty() {
+                store_group(conn, &oracle_uuid, INFADIC, Some(&parsed.additional_info))?;
+            }
+            if let Some(responsible) = &parsed.tech_responsible {
+                store_group(conn, &oracle_uuid, INFRESPTEC, Some(responsible))?;
+            }
+
+            // An nfeProc carries the authorization, so the note cannot be changed anymore.
+            // Its protNFe is kept like one received from SEFAZ, for events and the DANFE.
+            if let Some(protocol) = &parsed.protocol {
+                let n_prot = protocol.n_prot.as_deref().unwrap_or_default();
+                self.status.mark_imported(conn, &oracle_uuid, n_prot)?;
+                insert_protocol(conn, &oracle_uuid, protocol, None, &parsed.nfe_xml)?;
+            }
+            Ok(())
+        })?;
+        Ok(internal_key)
+    }
+}
@@ -0,0 +1,330 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_contingency::NORMAL;
+use crate::models::nfe_inutilization::{CreateNFeInutilization, NFeInutilization, HOMOLOGATED};
+use crate::models::nfe_numbering::NFeSerie;
+use crate::repositories::common::{
+    check_environment, parse_timestamp, to_oracle_uuid, TIMESTAMP_FORMAT,
+};
+use crate::repositories::nfe_numbering_repository::lock_serie;
+use crate::services::sefaz::config::authorizer;
+use crate::services::sefaz::inutilization::{self, Inutilization};
+use crate::services::sefaz::soap::SoapClient;
+use crate::services::signing_service::SigningService;
+use oracle::pool::Pool;
+use oracle::{Connection, Row};
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+const INUTILIZATION_COLUMNS: &str = r#"
+    RAWTOHEX(ID) as id,
+    CUF as c_uf,
+    TPAMB as tp_amb,
+    ANO as ano,
+    CNPJ as cnpj,
+    CPF as cpf,
+    MOD_ as mod_,
+    SERIE as serie,
+    NNFINI as n_nf_ini,
+    NNFFIN as n_nf_fin,
+    XJUST as x_just,
+    CSTAT as c_stat,
+    XMOTIVO as x_motivo,
+    NPROT as n_prot,
+    TO_CHAR(DHRECBTO, 'YYYY-MM-DD HH24:MI:SS.FF3') as dh_recbto,
+    TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at
+"#;
+
+fn map_inutilization(row: &Row) -> Result<NFeInutilization, RepositoryError> {
+    let oracle_uuid: String = row.get("id")?;
+    let id = Uuid::parse_str(&oracle_uuid)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+        .to_string();
+    Ok(NFeInutilization {
+        id,
+        c_uf: row.get("c_uf")?,
+        tp_amb: row.get("tp_amb")?,
+        ano: row.get("ano")?,
+        cnpj: row.get("cnpj")?,
+        cpf: row.get("cpf")?,
+        mod_: row.get("mod_")?,
+        serie: row.get("serie")?,
+        n_nf_ini: row.get("n_nf_ini")?,
+        n_nf_fin: row.get("n_nf_fin")?,
+        x_just: row.get("x_just")?,
+        c_stat: row.get("c_stat")?,
+        x_motivo: row.get("x_motivo")?,
+        n_prot: row.get("n_prot")?,
+        dh_recbto: row
+            .get::<_, Option<String>>("dh_recbto")?
+            .as_deref()
+            .map(parse_timestamp),
+        created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+    })
+}
+
+/// Voids ranges of numbers that were skipped in a serie, and keeps every request with
+/// the answer of SEFAZ.
+pub struct NFeInutilizationRepository {
+    conn: Arc<Connection>,
+    pool: Pool,
+    signing: Arc<SigningService>,
+    sefaz: Arc<SoapClient>,
+}
+
+impl NFeInutilizationRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        pool: Pool,
+        signing: Arc<SigningService>,
+        sefaz: Arc<SoapClient>,
+    ) -> Self {
+        Self {
+            conn,
+            pool,
+            signing,
+            sefaz,
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn find_all(&self) -> Result<Vec<NFeInutilization>, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_inutilizations ORDER BY CREATEDAT DESC",
+            INUTILIZATION_COLUMNS
+        );
+        let rows = self.conn.query(&sql, &[])?;
+        rows.map(|row| map_inutilization(&row?)).collect()
+    }
+
+    #[instrument(skip(self), fields(id = %id))]
+    pub async fn find_by_id(&self, id: &str) -> Result<NFeInutilization, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(id)?;
+        self.find(&oracle_uuid)
+    }
+
+    /// Requests the inutilização of a range no stored note uses. The request is stored
+    /// and returned even when SEFAZ rejects it.
+    ///
+    /// The serie stays locked from the checks until the answer is stored, so no note can
+    /// be given a number of the range in between.
+    #[instrument(skip(self, request), fields(serie = %request.serie, n_nf_ini = request.n_nf_ini, n_nf_fin = request.n_nf_fin))]
+    pub async fn create(
+        &self,
+        request: &CreateNFeInutilization,
+    ) -> Result<NFeInutilization, RepositoryError> {
+        info!("Requesting inutilização");
+
+        request.validate()?;
+        let authorizer = authorizer(&request.c_uf, &request.mod_, NORMAL);
+        check_environment(&self.sefaz, &authorizer, &request.tp_amb)?;
+
+        let conn = self.pool.get()?;
+        match self.create_locked(&conn, request, &authorizer).await {
+            Ok(id) => {
+                conn.commit()?;
+                self.find(&id)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = conn.rollback() {
+                    error!("Failed to roll back the transaction: {}", rollback_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks, sends and stores the request in the transaction of `conn`, returning the
+    /// id of the stored row.
+    async fn create_locked(
+        &self,
+        conn: &Connection,
+        request: &CreateNFeInutilization,
+        authorizer: &str,
+    ) -> Result<String, RepositoryError> {
+        let emit_doc = request
+            .cnpj
+            .clone()
+            .or_else(|| request.cpf.clone())
+            .unwrap_or_default();
+        lock_serie(
+            conn,
+            &NFeSerie {
+                emit_doc: emit_doc.clone(),
+                mod_: request.mod_.clone(),
+                serie: request.serie_number(),
+                tp_amb: request.tp_amb.clone(),
+            },
+        )?;
+        check_unused(conn, request, &emit_doc)?;
+        check_not_voided(conn, request)?;
+
+        let inut = Inutilization {
+            c_uf: request.c_uf.clone(),
+            tp_amb: request.tp_amb.clone(),
+            ano: request.ano.clone(),
+            author: emit_doc,
+            mod_: request.mod_.clone(),
+            serie: request.serie_number(),
+            n_nf_ini: request.n_nf_ini,
+            n_nf_fin: request.n_nf_fin,
+            x_just: request.x_just.trim().to_string(),
+        };
+        let signed = self.signing.sign(&inut.to_xml(), &inut.id())?;
+        let ret = inutilization::send(&self.sefaz, authorizer, &signed).await?;
+        info!("SEFAZ answered {} - {}", ret.c_stat, ret.x_motivo);
+
+        let xml = if ret.c_stat == HOMOLOGATED {
+            inutilization::proc_inut(&signed, &ret.xml)
+        } else {
+            signed
+        };
+        let id = Uuid::new_v4().to_string().replace('-', "");
+        conn.execute(
+            r#"
+            INSERT INTO nfe_inutilizations (
+                ID,
+                CUF,
+                TPAMB,
+                ANO,
+                CNPJ,
+                CPF,
+                MOD_,
+                SERIE,
+                NNFINI,
+                NNFFIN,
+                XJUST,
+                CSTAT,
+                XMOTIVO,
+                NPROT,
+                DHRECBTO,
+                INUTXML
+            ) VALUES (
+                HEXTORAW(:1),
+                :2,
+                :3,
+                :4,
+                :5,
+                :6,
+                :7,
+                :8,
+                :9,
+                :10,
+                :11,
+                :12,
+                :13,
+                :14,
+                TO_TIMESTAMP(:15, 'YYYY-MM-DD HH24:MI:SS.FF3'),
+                :16
+            )
+            "#,
+            &[
+                &id,
+                &inut.c_uf,
+                &inut.tp_amb,
+                &inut.ano,
+                &request.cnpj,
+                &request.cpf,
+                &inut.mod_,
+                &inut.serie.to_string(),
+                &inut.n_nf_ini,
+                &inut.n_nf_fin,
+                &inut.x_just,
+                &ret.c_stat,
+                &ret.x_motivo,
+                &ret.n_prot,
+                &ret.dh_recbto
+                    .map(|dh| dh.format(TIMESTAMP_FORMAT).to_string()),
+                &xml,
+            ],
+        )?;
+        Ok(id)
+    }
+
+    fn find(&self, oracle_uuid: &str) -> Result<NFeInutilization, RepositoryError> {
+        let sql = format!(
+            "SELECT {} FROM nfe_inutilizations WHERE ID = HEXTORAW(:1)",
+            INUTILIZATION_COLUMNS
+        );
+        match self.conn.query_row(&sql, &[&oracle_uuid]) {
+            Ok(row) => map_inutilization(&row),
+            Err(oracle::Error::NoDataFound) => Err(RepositoryError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Refuses ranges with a number taken by a stored note of the same emitter, or by a
+/// note stored without an emitter document.
+fn check_unused(
+    conn: &Connection,
+    request: &CreateNFeInutilization,
+    emit_doc: &str,
+) -> Result<(), RepositoryError> {
+    let rows = conn.query(
+        r#"
+        SELECT NNF, RAWTOHEX(INTERNALKEY)
+        FROM nfe_identifications
+        WHERE MOD_ = :1
+          AND TO_NUMBER(SERIE) = :2
+          AND TPAMB = :3
+          AND TO_NUMBER(NNF) BETWEEN :4 AND :5
+          AND (EMITDOC IS NULL OR EMITDOC = :6)
+        ORDER BY TO_NUMBER(NNF)
+        FETCH FIRST 1 ROWS ONLY
+        "#,
+        &[
+            &request.mod_,
+            &request.serie_number(),
+            &request.tp_amb,
+            &request.n_nf_ini,
+            &request.n_nf_fin,
+            &emit_doc,
+        ],
+    )?;
+    if let Some(row) = rows.into_iter().next() {
+        let (n_nf, oracle_uuid) = row?.get_as::<(String, String)>()?;
+        let internal_key = Uuid::parse_str(&oracle_uuid)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+        return Err(RepositoryError::Conflict(format!(
+            "nNF {} of the range is used by the note {}",
+            n_nf, internal_key
+        )));
+    }
+    Ok(())
+}
+
+fn check_not_voided(
+    conn: &Connection,
+    request: &CreateNFeInutilization,
+) -> Result<(), RepositoryError> {
+    let overlapping = conn.query_row_as::<u32>(
+        r#"
+        SELECT COUNT(*)
+        FROM nfe_inutilizations
+        WHERE CSTAT = :1
+          AND MOD_ = :2
+          AND TO_NUMBER(SERIE) = :3
+          AND TPAMB = :4
+          AND NNFINI <= :5
+          AND NNFFIN >= :6
+          AND (CNPJ = :7 OR CPF = :8)
+        "#,
+        &[
+            &HOMOLOGATED,
+            &request.mod_,
+            &request.serie_number(),
+            &request.tp_amb,
+            &request.n_nf_fin,
+            &request.n_nf_ini,
+            &request.cnpj,
+            &request.cpf,
+        ],
+    )?;
+    if overlapping > 0 {
+        return Err(RepositoryError::Conflict(
+            "the range overlaps a range already voided".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,447 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_item::{
+    CreateNFeItem, NFeItem, NFeProduct, NFeReturnedIpi, NFeReturnedTax, ReorderNFeItems, MAX_ITEMS,
+};
+use crate::models::nfe_item_specific::NFeSpecificProduct;
+use crate::models::nfe_item_tax::NFeItemTaxes;
+use crate::repositories::common::{
+    decimal_bind, ensure_identification_exists, is_unique_violation, optional_decimal_bind,
+    parse_decimal, parse_optional_decimal, parse_timestamp, to_oracle_uuid,
+};
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::cache_service::CacheService;
+use crate::services::tax::compute_item_taxes;
+use oracle::{Connection, Row};
+use redis::aio::ConnectionManager;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, instrument};
+
+const SELECT_ITEMS: &str = r#"
+    SELECT
+        NITEM as n_item,
+        CPROD as c_prod,
+        CEAN as c_ean,
+        XPROD as x_prod,
+        NCM as ncm,
+        CEST as cest,
+        CFOP as cfop,
+        UCOM as u_com,
+        QCOM as q_com,
+        VUNCOM as v_un_com,
+        VPROD as v_prod,
+        CEANTRIB as c_ean_trib,
+        UTRIB as u_trib,
+        QTRIB as q_trib,
+        VUNTRIB as v_un_trib,
+        VFRETE as v_frete,
+        VSEG as v_seg,
+        VDESC as v_desc,
+        VOUTRO as v_outro,
+        INDTOT as ind_tot,
+        PRODSPECIFIC as specific,
+        IMPOSTO as imposto,
+        TO_CHAR(PDEVOL) as p_devol,
+        TO_CHAR(VIPIDEVOL) as v_ipi_devol,
+        INFADPROD as inf_ad_prod,
+        TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at,
+        TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as updated_at
+    FROM nfe_items
+    WHERE INTERNALKEY = HEXTORAW(:1)
+"#;
+
+/// Items of the note as seen by `conn`, including writes of its open transaction.
+pub fn items_of(
+    conn: &Connection,
+    internal_key: &str,
+    oracle_uuid: &str,
+) -> Result<Vec<NFeItem>, RepositoryError> {
+    let sql = format!("{} ORDER BY NITEM", SELECT_ITEMS);
+    let rows = conn.query(&sql, &[&oracle_uuid])?;
+    rows.map(|row| item_from_row(internal_key, &row?)).collect()
+}
+
+/// `indFinal` of the note as seen by `conn`; sales to final consumers add the IPI to the
+/// ICMS base of the items.
+fn ind_final_of(conn: &Connection, oracle_uuid: &str) -> Result<String, RepositoryError> {
+    match conn.query_row_as::<String>(
+        "SELECT INDFINAL FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+        &[&oracle_uuid],
+    ) {
+        Ok(ind_final) => Ok(ind_final),
+        Err(oracle::Error::NoDataFound) => Err(RepositoryError::NotFound),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Recomputes the taxes of the stored items from the identification as seen by `conn`,
+/// so a change of `indFinal` reaches the items added before it. The caller holds the
+/// lock of the note.
+pub fn recompute_item_taxes(conn: &Connection, oracle_uuid: &str) -> Result<(), RepositoryError> {
+    let ipi_in_icms_base = ind_final_of(conn, oracle_uuid)? == "1";
+    for item in items_of(conn, "", oracle_uuid)? {
+        let mut imposto = item.imposto.clone();
+        compute_item_taxes(&item.prod, &mut imposto, ipi_in_icms_base)?;
+        let before = serde_json::to_string(&item.imposto)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+        let after = serde_json::to_string(&imposto)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+        if before != after {
+            conn.execute(
+                "UPDATE nfe_items SET IMPOSTO = :1 \
+                 WHERE INTERNALKEY = HEXTORAW(:2) AND NITEM = :3",
+                &[&after, &oracle_uuid, &item.n_item],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends the item as the next `nItem` of the note with the given taxes. The caller
+/// holds the lock of the note, so the count is stable until the insert commits.
+pub fn insert_item(
+    conn: &Connection,
+    oracle_uuid: &str,
+    item: &CreateNFeItem,
+    imposto: &NFeItemTaxes,
+) -> Result<u32, RepositoryError> {
+    let imposto =
+        serde_json::to_string(imposto).map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+    let specific = (item.prod.specific != NFeSpecificProduct::default())
+        .then(|| serde_json::to_string(&item.prod.specific))
+        .transpose()
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+
+    let count: u32 = conn.query_row_as(
+        "SELECT COUNT(*) FROM nfe_items WHERE INTERNALKEY = HEXTORAW(:1)",
+        &[&oracle_uuid],
+    )?;
+    if count >= MAX_ITEMS {
+        return Err(ValidationError::new(
+            "det",
+            format!("a note cannot have more than {} items", MAX_ITEMS),
+        )
+        .into());
+    }
+    let n_item = count + 1;
+
+    let sql = r#"
+        INSERT INTO nfe_items (
+            INTERNALKEY, NITEM, CPROD, CEAN, XPROD, NCM, CEST, CFOP,
+            UCOM, QCOM, VUNCOM, VPROD, CEANTRIB, UTRIB, QTRIB, VUNTRIB,
+            VFRETE, VSEG, VDESC, VOUTRO, INDTOT, IMPOSTO, INFADPROD, PRODSPECIFIC,
+            PDEVOL, VIPIDEVOL
+        ) VALUES (
+            HEXTORAW(:1), :2, :3, :4, :5, :6, :7, :8,
+            :9, :10, :11, :12, :13, :14, :15, :16,
+            :17, :18, :19, :20, :21, :22, :23, :24,
+            :25, :26
+        )
+    "#;
+    let prod = &item.prod;
+    let mut stmt = conn.statement(sql).build()?;
+    match stmt.execute(&[
+        &oracle_uuid,
+        &n_item,
+        &prod.c_prod,
+        &prod.c_ean,
+        &prod.x_prod,
+        &prod.ncm,
+        &prod.cest,
+        &prod.cfop,
+        &prod.u_com,
+        &decimal_bind(&prod.q_com),
+        &decimal_bind(&prod.v_un_com),
+        &decimal_bind(&prod.v_prod),
+        &prod.c_ean_trib,
+        &prod.u_trib,
+        &decimal_bind(&prod.q_trib),
+        &decimal_bind(&prod.v_un_trib),
+        &optional_decimal_bind(&prod.v_frete),
+        &optional_decimal_bind(&prod.v_seg),
+        &optional_decimal_bind(&prod.v_desc),
+        &optional_decimal_bind(&prod.v_outro),
+        &prod.ind_tot,
+        &imposto,
+        &item.inf_ad_prod,
+        &specific,
+        &optional_decimal_bind(&item.imposto_devol.as_ref().map(|devol| devol.p_devol)),
+        &optional_decimal_bind(
+            &item
+                .imposto_devol
+                .as_ref()
+                .map(|devol| devol.ipi.v_ipi_devol),
+        ),
+    ]) {
+        Ok(_) => Ok(n_item),
+        Err(e) if is_unique_violation(&e) => Err(RepositoryError::Conflict(format!(
+            "item {} already exists",
+            n_item
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Persists the `det` line items of an identification, keyed by INTERNALKEY + nItem.
+pub struct NFeItemRepository {
+    conn: Arc<Connection>,
+    cache: Arc<CacheService>,
+    status: Arc<NFeStatusRepository>,
+}
+
+impl NFeItemRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        redis_manager: ConnectionManager,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
+        Self {
+            conn,
+            cache: Arc::new(CacheService::new(redis_manager)),
+            status,
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_all(&self, internal_key: &str) -> Result<Vec<NFeItem>, RepositoryError> {
+        info!("Fetching items for NFe identification");
+
+        let cache_key = format!("nfe:{}:items", internal_key);
+        if let Ok(Some(cached)) = self.cache.get::<Vec<NFeItem>>(&cache_key).await {
+            info!("Cache hit for items of {}", internal_key);
+            return Ok(cached);
+        }
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        ensure_identification_exists(&self.conn, &oracle_uuid)?;
+
+        let items = items_of(&self.conn, internal_key, &oracle_uuid)?;
+
+        if let Err(e) = self
+            .cache
+            .set(&cache_key, &items, Some(Duration::from_secs(300)))
+            .await
+        {
+            error!("Failed to cache items: {}", e);
+        }
+
+        Ok(items)
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_by_number(
+        &self,
+        internal_key: &str,
+        n_item: u32,
+    ) -> Result<Option<NFeItem>, RepositoryError> {
+        info!("Fetching item {} for NFe identification", n_item);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let sql = format!("{} AND NITEM = :2", SELECT_ITEMS);
+        let mut stmt = self.conn.statement(&sql).build()?;
+        let mut rows = stmt.query(&[&oracle_uuid, &n_item])?;
+
+        match rows.next() {
+            Some(row_result) => Ok(Some(item_from_row(internal_key, &row_result?)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self, item), fields(internal_key = %internal_key))]
+    pub async fn create(
+        &self,
+        internal_key: &str,
+        item: &CreateNFeItem,
+    ) -> Result<NFeItem, RepositoryError> {
+        info!("Adding item to NFe identification");
+        debug!("Input data: {:?}", item);
+
+        item.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+
+        // indFinal and nItem are read under the lock of the note, so the taxes match the
+        // identification being committed and concurrent adds get consecutive numbers.
+        let result = self
+            .status
+            .edit(internal_key, |conn| {
+                // Tax values are always derived from the item instead of trusting the client.
+                let mut imposto = item.imposto.clone();
+                let ind_final = ind_final_of(conn, &oracle_uuid)?;
+                compute_item_taxes(&item.prod, &mut imposto, ind_final == "1")?;
+                insert_item(conn, &oracle_uuid, item, &imposto)
+            })
+            .await;
+
+        match result {
+            Ok(n_item) => {
+                info!("Successfully added item {} to {}", n_item, internal_key);
+                self.invalidate(internal_key).await;
+                self.find_by_number(internal_key, n_item)
+                    .await?
+                    .ok_or(RepositoryError::CreationFailed)
+            }
+            Err(e) => {
+                error!("Failed to add item: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Renumbers the items so that `order[i]` becomes item `i + 1`.
+    #[instrument(skip(self, reorder), fields(internal_key = %internal_key))]
+    pub async fn reorder(
+        &self,
+        internal_key: &str,
+        reorder: &ReorderNFeItems,
+    ) -> Result<Vec<NFeItem>, RepositoryError> {
+        info!("Reordering items of NFe identification");
+        debug!("New order: {:?}", reorder.order);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        self.status
+            .edit(internal_key, |conn| {
+                let count: u32 = conn.query_row_as(
+                    "SELECT COUNT(*) FROM nfe_items WHERE INTERNALKEY = HEXTORAW(:1)",
+                    &[&oracle_uuid],
+                )?;
+                let mut sorted = reorder.order.clone();
+                sorted.sort_unstable();
+                if sorted.len() != count as usize || sorted.iter().zip(1..).any(|(n, i)| *n != i) {
+                    return Err(ValidationError::new(
+                        "order",
+                        format!("must be a permutation of the item numbers 1 to {}", count),
+                    )
+                    .into());
+                }
+
+                // A single UPDATE lets Oracle check the primary key only once the whole
+                // permutation has been applied.
+                let cases: String = reorder
+                    .order
+                    .iter()
+                    .zip(1..)
+                    .map(|(old, new)| format!(" WHEN {} THEN {}", old, new))
+                    .collect();
+                let sql = format!(
+                    "UPDATE nfe_items SET NITEM = CASE NITEM{} END \
+                     WHERE INTERNALKEY = HEXTORAW(:1)",
+                    cases
+                );
+                conn.execute(&sql, &[&oracle_uuid])?;
+                Ok(())
+            })
+            .await?;
+
+        self.invalidate(internal_key).await;
+        self.find_all(internal_key).await
+    }
+
+    /// Deletes an item and shifts the following ones down so numbering stays contiguous.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn delete(&self, internal_key: &str, n_item: u32) -> Result<(), RepositoryError> {
+        info!("Deleting item {} of NFe identification", n_item);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        let sql = r#"
+            BEGIN
+                DELETE FROM nfe_items
+                WHERE INTERNALKEY = HEXTORAW(:internal_key) AND NITEM = :n_item;
+                UPDATE nfe_items SET NITEM = NITEM - 1
+                WHERE INTERNALKEY = HEXTORAW(:internal_key) AND NITEM > :n_item;
+            END;
+        "#;
+        self.status
+            .edit(internal_key, |conn| {
+                let found: u32 = conn.query_row_as(
+                    "SELECT COUNT(*) FROM nfe_items \
+                     WHERE INTERNALKEY = HEXTORAW(:1) AND NITEM = :2",
+                    &[&oracle_uuid, &n_item],
+                )?;
+                if found == 0 {
+                    return Err(RepositoryError::NotFound);
+                }
+                let mut stmt = conn.statement(sql).build()?;
+                stmt.bind("internal_key", &oracle_uuid)?;
+                stmt.bind("n_item", &n_item)?;
+                stmt.execute(&[])?;
+                Ok(())
+            })
+            .await?;
+
+        info!("Successfully deleted item {} of {}", n_item, internal_key);
+        self.invalidate(internal_key).await;
+        Ok(())
+    }
+
+    async fn invalidate(&self, internal_key: &str) {
+        if let Err(e) = self
+            .cache
+            .delete(&format!("nfe:{}:items", internal_key))
+            .await
+        {
+            error!("Failed to invalidate items cache: {}", e);
+        }
+    }
+}
+
+fn item_from_row(internal_key: &str, row: &Row) -> Result<NFeItem, RepositoryError> {
+    let imposto_json: Option<String> = row.get("imposto")?;
+    let imposto = match imposto_json {
+        Some(json) => serde_json::from_str::<NFeItemTaxes>(&json)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?,
+        None => NFeItemTaxes::default(),
+    };
+    let specific = match row.get::<_, Option<String>>("specific")? {
+        Some(json) => serde_json::from_str::<NFeSpecificProduct>(&json)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?,
+        None => NFeSpecificProduct::default(),
+    };
+    let imposto_devol = match (
+        parse_optional_decimal(row.get("p_devol")?)?,
+        parse_optional_decimal(row.get("v_ipi_devol")?)?,
+    ) {
+        (Some(p_devol), Some(v_ipi_devol)) => Some(NFeReturnedTax {
+            p_devol,
+            ipi: NFeReturnedIpi { v_ipi_devol },
+        }),
+        _ => None,
+    };
+
+    Ok(NFeItem {
+        internal_key: internal_key.to_string(),
+        n_item: row.get("n_item")?,
+        prod: NFeProduct {
+            c_prod: row.get("c_prod")?,
+            // cEAN may be empty, which Oracle stores as NULL.
+            c_ean: row.get::<_, Option<String>>("c_ean")?.unwrap_or_default(),
+            x_prod: row.get("x_prod")?,
+            ncm: row.get("ncm")?,
+            cest: row.get("cest")?,
+            cfop: row.get("cfop")?,
+            u_com: row.get("u_com")?,
+            q_com: parse_decimal(&row.get::<_, String>("q_com")?)?,
+            v_un_com: parse_decimal(&row.get::<_, String>("v_un_com")?)?,
+            v_prod: parse_decimal(&row.get::<_, String>("v_prod")?)?,
+            c_ean_trib: row
+                .get::<_, Option<String>>("c_ean_trib")?
+                .unwrap_or_default(),
+            u_trib: row.get("u_trib")?,
+            q_trib: parse_decimal(&row.get::<_, String>("q_trib")?)?,
+            v_un_trib: parse_decimal(&row.get::<_, String>("v_un_trib")?)?,
+            v_frete: parse_optional_decimal(row.get("v_frete")?)?,
+            v_seg: parse_optional_decimal(row.get("v_seg")?)?,
+            v_desc: parse_optional_decimal(row.get("v_desc")?)?,
+            v_outro: parse_optional_decimal(row.get("v_outro")?)?,
+            ind_tot: row.get("ind_tot")?,
+            specific,
+        },
+        imposto,
+        imposto_devol,
+        inf_ad_prod: row.get("inf_ad_prod")?,
+        created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+        updated_at: parse_timestamp(&row.get::<_, String>("updated_at")?),
+    })
+}
@@ -0,0 +1,210 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_inutilization::{HOMOLOGATED, MAX_N_NF};
+use crate::models::nfe_numbering::{find_gaps, NFeGapFilter, NFeSerie, NFeSerieGaps};
+use crate::repositories::common::is_unique_violation;
+use oracle::Connection;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+/// Creates the numbering row of the serie when it does not exist yet.
+fn ensure_serie(conn: &Connection, serie: &NFeSerie) -> Result<(), RepositoryError> {
+    let created = conn.execute(
+        r#"
+        MERGE INTO nfe_numbering n
+        USING (SELECT :1 AS EMITDOC, :2 AS MOD_, :3 AS SERIE, :4 AS TPAMB FROM dual) s
+        ON (n.EMITDOC = s.EMITDOC AND n.MOD_ = s.MOD_ AND n.SERIE = s.SERIE AND n.TPAMB = s.TPAMB)
+        WHEN NOT MATCHED THEN
+            INSERT (EMITDOC, MOD_, SERIE, TPAMB) VALUES (s.EMITDOC, s.MOD_, s.SERIE, s.TPAMB)
+        "#,
+        &[&serie.emit_doc, &serie.mod_, &serie.serie, &serie.tp_amb],
+    );
+    match created {
+        // Another allocation created the row first.
+        Err(e) if is_unique_violation(&e) => Ok(()),
+        other => other.map(|_| ()).map_err(Into::into),
+    }
+}
+
+/// Takes the row lock of the serie in the transaction of `conn`, the same lock
+/// [`allocate_number`] takes, so no number of the serie is handed out until that
+/// transaction ends.
+pub fn lock_serie(conn: &Connection, serie: &NFeSerie) -> Result<(), RepositoryError> {
+    ensure_serie(conn, serie)?;
+    conn.query_row(
+        r#"
+        SELECT LASTNNF FROM nfe_numbering
+        WHERE EMITDOC = :1 AND MOD_ = :2 AND SERIE = :3 AND TPAMB = :4
+        FOR UPDATE
+        "#,
+        &[&serie.emit_doc, &serie.mod_, &serie.serie, &serie.tp_amb],
+    )?;
+    Ok(())
+}
+
+/// Hands out the next `nNF` of the serie: one past the last number allocated, used by a
+/// stored note or voided by a homologated inutilização, whichever is highest. Run it in
+/// the transaction that inserts the note: the row lock it takes on the serie is held
+/// until that transaction ends, and a rollback returns the number.
+pub fn allocate_number(conn: &Connection, serie: &NFeSerie) -> Result<u32, RepositoryError> {
+    ensure_serie(conn, serie)?;
+
+    let stmt = conn.execute(
+        r#"
+        UPDATE nfe_numbering n
+        SET LASTNNF = GREATEST(
+                n.LASTNNF,
+                (
+                    SELECT NVL(MAX(TO_NUMBER(i.NNF)), 0)
+                    FROM nfe_identifications i
+                    WHERE i.EMITDOC = n.EMITDOC
+                      AND i.MOD_ = n.MOD_
+                      AND TO_NUMBER(i.SERIE) = n.SERIE
+                      AND i.TPAMB = n.TPAMB
+                ),
+                (
+                    SELECT NVL(MAX(u.NNFFIN), 0)
+                    FROM nfe_inutilizations u
+                    WHERE u.CSTAT = :1
+                      AND NVL(u.CNPJ, u.CPF) = n.EMITDOC
+                      AND u.MOD_ = n.MOD_
+                      AND TO_NUMBER(u.SERIE) = n.SERIE
+                      AND u.TPAMB = n.TPAMB
+                )
+            ) + 1,
+            UPDATEDAT = CURRENT_TIMESTAMP
+        WHERE EMITDOC = :2 AND MOD_ = :3 AND SERIE = :4 AND TPAMB = :5
+        RETURNING LASTNNF INTO :6
+        "#,
+        &[
+            &HOMOLOGATED,
+            &serie.emit_doc,
+            &serie.mod_,
+            &serie.serie,
+            &serie.tp_amb,
+            &None::<u32>,
+        ],
+    )?;
+    let n_nf = stmt
+        .returned_values::<_, u32>(6)?
+        .into_iter()
+        .next()
+        .ok_or(RepositoryError::CreationFailed)?;
+    if n_nf > MAX_N_NF {
+        return Err(RepositoryError::Conflict(format!(
+            "serie {} has no numbers left",
+            serie.serie
+        )));
+    }
+    info!("Allocated nNF {} of serie {}", n_nf, serie.serie);
+    Ok(n_nf)
+}
+
+/// The error of a note whose number another note of the serie already has.
+pub fn number_taken() -> RepositoryError {
+    RepositoryError::Conflict(
+        "nNF is already used by another note of the emitter in this model, serie and tpAmb"
+            .to_string(),
+    )
+}
+
+/// Reports the numbers skipped in each serie, which must be voided by inutilização.
+pub struct NFeNumberingRepository {
+    conn: Arc<Connection>,
+}
+
+impl NFeNumberingRepository {
+    pub fn new(conn: Arc<Connection>) -> Self {
+        Self { conn }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn find_gaps(
+        &self,
+        filter: &NFeGapFilter,
+    ) -> Result<Vec<NFeSerieGaps>, RepositoryError> {
+        info!("Building numbering gap report");
+
+        type Numbers = (u32, Vec<u32>, Vec<(u32, u32)>);
+        let mut series: BTreeMap<NFeSerie, Numbers> = BTreeMap::new();
+
+        let allocated = self.conn.query_as::<(String, String, u32, String, u32)>(
+            r#"
+            SELECT EMITDOC, MOD_, SERIE, TPAMB, LASTNNF
+            FROM nfe_numbering
+            WHERE EMITDOC = NVL(:1, EMITDOC) AND MOD_ = NVL(:2, MOD_) AND TPAMB = NVL(:3, TPAMB)
+            "#,
+            &[&filter.emit_doc, &filter.mod_, &filter.tp_amb],
+        )?;
+        for row in allocated {
+            let (emit_doc, mod_, serie, tp_amb, last) = row?;
+            let entry = series
+                .entry(NFeSerie {
+                    emit_doc,
+                    mod_,
+                    serie,
+                    tp_amb,
+                })
+                .or_default();
+            entry.0 = entry.0.max(last);
+        }
+
+        let used = self.conn.query_as::<(String, String, u32, String, u32)>(
+            r#"
+            SELECT EMITDOC, MOD_, TO_NUMBER(SERIE), TPAMB, TO_NUMBER(NNF)
+            FROM nfe_identifications
+            WHERE EMITDOC IS NOT NULL
+              AND EMITDOC = NVL(:1, EMITDOC) AND MOD_ = NVL(:2, MOD_) AND TPAMB = NVL(:3, TPAMB)
+            "#,
+            &[&filter.emit_doc, &filter.mod_, &filter.tp_amb],
+        )?;
+        for row in used {
+            let (emit_doc, mod_, serie, tp_amb, n_nf) = row?;
+            let entry = series
+                .entry(NFeSerie {
+                    emit_doc,
+                    mod_,
+                    serie,
+                    tp_amb,
+                })
+                .or_default();
+            entry.0 = entry.0.max(n_nf);
+            entry.1.push(n_nf);
+        }
+
+        let voided = self
+            .conn
+            .query_as::<(String, String, u32, String, u32, u32)>(
+                r#"
+            SELECT NVL(CNPJ, CPF), MOD_, TO_NUMBER(SERIE), TPAMB, NNFINI, NNFFIN
+            FROM nfe_inutilizations
+            WHERE CSTAT = :1
+              AND NVL(CNPJ, CPF) = NVL(:2, NVL(CNPJ, CPF))
+              AND MOD_ = NVL(:3, MOD_)
+              AND TPAMB = NVL(:4, TPAMB)
+            "#,
+                &[&HOMOLOGATED, &filter.emit_doc, &filter.mod_, &filter.tp_amb],
+            )?;
+        for row in voided {
+            let (emit_doc, mod_, serie, tp_amb, ini, fin) = row?;
+            // Ranges of series without notes leave nothing to report.
+            if let Some(entry) = series.get_mut(&NFeSerie {
+                emit_doc,
+                mod_,
+                serie,
+                tp_amb,
+            }) {
+                entry.2.push((ini, fin));
+            }
+        }
+
+        Ok(series
+            .into_iter()
+            .map(|(serie, (last, used, voided))| NFeSerieGaps {
+                gaps: find_gaps(last, &used, &voided),
+                last_n_nf: last,
+                serie,
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,623 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_address::NFeAddress;
+use crate::models::nfe_emitter::{CreateNFeEmitter, NFeEmitter};
+use crate::models::nfe_recipient::{CreateNFeRecipient, NFeRecipient};
+use crate::repositories::common::{ensure_identification_exists, parse_timestamp, to_oracle_uuid};
+use crate::repositories::nfe_access_key_repository::{check_emitter_document, refresh_check_digit};
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::cache_service::CacheService;
+use oracle::{Connection, Row};
+use redis::aio::ConnectionManager;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, instrument};
+
+/// Stores the emitter of a note that has none, under the document the note was numbered
+/// for, and completes the access key.
+pub fn insert_emitter(
+    conn: &Connection,
+    oracle_uuid: &str,
+    emitter: &CreateNFeEmitter,
+) -> Result<(), RepositoryError> {
+    check_emitter_document(conn, oracle_uuid, emitter.document())?;
+    let sql = r#"
+        INSERT INTO nfe_emitters (
+            INTERNALKEY, CNPJ, CPF, XNOME, XFANT,
+            XLGR, NRO, XCPL, XBAIRRO, CMUN, XMUN, UF, CEP, CPAIS, XPAIS, FONE,
+            IE, IEST, IM, CNAE, CRT
+        ) VALUES (
+            HEXTORAW(:1), :2, :3, :4, :5,
+            :6, :7, :8, :9, :10, :11, :12, :13, :14, :15, :16,
+            :17, :18, :19, :20, :21
+        )
+    "#;
+    let address = &emitter.ender_emit;
+    let mut stmt = conn.statement(sql).build()?;
+    stmt.execute(&[
+        &oracle_uuid,
+        &emitter.cnpj,
+        &emitter.cpf,
+        &emitter.x_nome,
+        &emitter.x_fant,
+        &address.x_lgr,
+        &address.nro,
+        &address.x_cpl,
+        &address.x_bairro,
+        &address.c_mun,
+        &address.x_mun,
+        &address.uf,
+        &address.cep,
+        &address.c_pais,
+        &address.x_pais,
+        &address.fone,
+        &emitter.ie,
+        &emitter.iest,
+        &emitter.im,
+        &emitter.cnae,
+        &emitter.crt,
+    ])?;
+    refresh_check_digit(conn, oracle_uuid)
+}
+
+pub fn insert_recipient(
+    conn: &Connection,
+    oracle_uuid: &str,
+    recipient: &CreateNFeRecipient,
+) -> Result<(), RepositoryError> {
+    let sql = r#"
+        INSERT INTO nfe_recipients (
+            INTERNALKEY, CNPJ, CPF, IDESTRANGEIRO, XNOME,
+            XLGR, NRO, XCPL, XBAIRRO, CMUN, XMUN, UF, CEP, CPAIS, XPAIS, FONE,
+            INDIEDEST, IE, ISUF, IM, EMAIL
+        ) VALUES (
+            HEXTORAW(:1), :2, :3, :4, :5,
+            :6, :7, :8, :9, :10, :11, :12, :13, :14, :15, :16,
+            :17, :18, :19, :20, :21
+        )
+    "#;
+    let address = AddressBinds::from(recipient.ender_dest.as_ref());
+    let mut stmt = conn.statement(sql).build()?;
+    stmt.execute(&[
+        &oracle_uuid,
+        &recipient.cnpj,
+        &recipient.cpf,
+        &recipient.id_estrangeiro,
+        &recipient.x_nome,
+        &address.x_lgr,
+        &address.nro,
+        &address.x_cpl,
+        &address.x_bairro,
+        &address.c_mun,
+        &address.x_mun,
+        &address.uf,
+        &address.cep,
+        &address.c_pais,
+        &address.x_pais,
+        &address.fone,
+        &recipient.ind_ie_dest,
+        &recipient.ie,
+        &recipient.isuf,
+        &recipient.im,
+        &recipient.email,
+    ])?;
+    Ok(())
+}
+
+/// Persists the `emit` and `dest` groups, both keyed by the identification INTERNALKEY.
+pub struct NFeParticipantRepository {
+    conn: Arc<Connection>,
+    cache: Arc<CacheService>,
+    status: Arc<NFeStatusRepository>,
+}
+
+impl NFeParticipantRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        redis_manager: ConnectionManager,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
+        Self {
+            conn,
+            cache: Arc::new(CacheService::new(redis_manager)),
+            status,
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_emitter(
+        &self,
+        internal_key: &str,
+    ) -> Result<Option<NFeEmitter>, RepositoryError> {
+        info!("Fetching emitter for NFe identification");
+
+        let cache_key = format!("nfe:{}:emitter", internal_key);
+        if let Ok(Some(cached)) = self.cache.get::<NFeEmitter>(&cache_key).await {
+            info!("Cache hit for emitter of {}", internal_key);
+            return Ok(Some(cached));
+        }
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+
+        let sql = r#"
+            SELECT
+                RAWTOHEX(INTERNALKEY) as internal_key,
+                CNPJ as cnpj,
+                CPF as cpf,
+                XNOME as x_nome,
+                XFANT as x_fant,
+                XLGR as x_lgr,
+                NRO as nro,
+                XCPL as x_cpl,
+                XBAIRRO as x_bairro,
+                CMUN as c_mun,
+                XMUN as x_mun,
+                UF as uf,
+                CEP as cep,
+                CPAIS as c_pais,
+                XPAIS as x_pais,
+                FONE as fone,
+                IE as ie,
+                IEST as iest,
+                IM as im,
+                CNAE as cnae,
+                CRT as crt,
+                TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at,
+                TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as updated_at
+            FROM nfe_emitters
+            WHERE INTERNALKEY = HEXTORAW(:1)
+        "#;
+
+        let mut stmt = self.conn.statement(sql).build()?;
+        let mut rows = stmt.query(&[&oracle_uuid])?;
+
+        if let Some(row_result) = rows.next() {
+            let row = row_result?;
+            let emitter = NFeEmitter {
+                internal_key: internal_key.to_string(),
+                cnpj: row.get("cnpj")?,
+                cpf: row.get("cpf")?,
+                x_nome: row.get("x_nome")?,
+                x_fant: row.get("x_fant")?,
+                ender_emit: address_from_row(&row)?,
+                ie: row.get("ie")?,
+                iest: row.get("iest")?,
+                im: row.get("im")?,
+                cnae: row.get("cnae")?,
+                crt: row.get("crt")?,
+                created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+                updated_at: parse_timestamp(&row.get::<_, String>("updated_at")?),
+            };
+
+            if let Err(e) = self
+                .cache
+                .set(&cache_key, &emitter, Some(Duration::from_secs(300)))
+                .await
+            {
+                error!("Failed to cache emitter: {}", e);
+            }
+
+            Ok(Some(emitter))
+        } else {
+            info!("No emitter found for NFe identification {}", internal_key);
+            Ok(None)
+        }
+    }
+
+    #[instrument(skip(self, emitter), fields(internal_key = %internal_key))]
+    pub async fn create_emitter(
+        &self,
+        internal_key: &str,
+        emitter: &CreateNFeEmitter,
+    ) -> Result<NFeEmitter, RepositoryError> {
+        info!("Creating emitter for NFe identification");
+        debug!("Input data: {:?}", emitter);
+
+        emitter.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        ensure_identification_exists(&self.conn, &oracle_uuid)?;
+
+        if self.find_emitter(internal_key).await?.is_some() {
+            return Err(RepositoryError::Conflict(
+                "Identification already has an emitter".to_string(),
+            ));
+        }
+
+        let result = self
+            .status
+            .edit(internal_key, |conn| {
+                insert_emitter(conn, &oracle_uuid, emitter)
+            })
+            .await;
+
+        match result {
+            Ok(()) => {
+                info!("Successfully created emitter for {}", internal_key);
+                self.invalidate_identification(internal_key).await;
+                self.find_emitter(internal_key)
+                    .await?
+                    .ok_or(RepositoryError::CreationFailed)
+            }
+            Err(e) => {
+                error!("Failed to create emitter: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    #[instrument(skip(self, emitter), fields(internal_key = %internal_key))]
+    pub async fn update_emitter(
+        &self,
+        internal_key: &str,
+        emitter: &CreateNFeEmitter,
+    ) -> Result<NFeEmitter, RepositoryError> {
+        info!("Updating emitter for NFe identification");
+        debug!("Update data: {:?}", emitter);
+
+        emitter.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+
+        let sql = r#"
+            UPDATE nfe_emitters
+            SET
+                CNPJ = :1, CPF = :2, XNOME = :3, XFANT = :4,
+                XLGR = :5, NRO = :6, XCPL = :7, XBAIRRO = :8, CMUN = :9, XMUN = :10,
+                UF = :11, CEP = :12, CPAIS = :13, XPAIS = :14, FONE = :15,
+                IE = :16, IEST = :17, IM = :18, CNAE = :19, CRT = :20
+            WHERE INTERNALKEY = HEXTORAW(:21)
+        "#;
+
+        let address = &emitter.ender_emit;
+        self.status
+            .edit(internal_key, |conn| {
+                check_emitter_document(conn, &oracle_uuid, emitter.document())?;
+                let mut stmt = conn.statement(sql).build()?;
+                stmt.execute(&[
+                    &emitter.cnpj,
+                    &emitter.cpf,
+                    &emitter.x_nome,
+                    &emitter.x_fant,
+                    &address.x_lgr,
+                    &address.nro,
+                    &address.x_cpl,
+                    &address.x_bairro,
+                    &address.c_mun,
+                    &address.x_mun,
+                    &address.uf,
+                    &address.cep,
+                    &address.c_pais,
+                    &address.x_pais,
+                    &address.fone,
+                    &emitter.ie,
+                    &emitter.iest,
+                    &emitter.im,
+                    &emitter.cnae,
+                    &emitter.crt,
+                    &oracle_uuid,
+                ])?;
+                if stmt.row_count()? == 0 {
+                    return Err(RepositoryError::NotFound);
+                }
+                refresh_check_digit(conn, &oracle_uuid)
+            })
+            .await?;
+
+        self.invalidate(internal_key, "emitter").await;
+        self.invalidate_identification(internal_key).await;
+        self.find_emitter(internal_key)
+            .await?
+            .ok_or(RepositoryError::UpdateFailed)
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn delete_emitter(&self, internal_key: &str) -> Result<(), RepositoryError> {
+        info!("Deleting emitter for NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        self.status
+            .edit(internal_key, |conn| {
+                let mut stmt = conn
+                    .statement("DELETE FROM nfe_emitters WHERE INTERNALKEY = HEXTORAW(:1)")
+                    .build()?;
+                stmt.execute(&[&oracle_uuid])?;
+                if stmt.row_count()? == 0 {
+                    return Err(RepositoryError::NotFound);
+                }
+                refresh_check_digit(conn, &oracle_uuid)
+            })
+            .await?;
+
+        self.invalidate(internal_key, "emitter").await;
+        self.invalidate_identification(internal_key).await;
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_recipient(
+        &self,
+        internal_key: &str,
+    ) -> Result<Option<NFeRecipient>, RepositoryError> {
+        info!("Fetching recipient for NFe identification");
+
+        let cache_key = format!("nfe:{}:recipient", internal_key);
+        if let Ok(Some(cached)) = self.cache.get::<NFeRecipient>(&cache_key).await {
+            info!("Cache hit for recipient of {}", internal_key);
+            return Ok(Some(cached));
+        }
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+
+        let sql = r#"
+            SELECT
+                RAWTOHEX(INTERNALKEY) as internal_key,
+                CNPJ as cnpj,
+                CPF as cpf,
+                IDESTRANGEIRO as id_estrangeiro,
+                XNOME as x_nome,
+                XLGR as x_lgr,
+                NRO as nro,
+                XCPL as x_cpl,
+                XBAIRRO as x_bairro,
+                CMUN as c_mun,
+                XMUN as x_mun,
+                UF as uf,
+                CEP as cep,
+                CPAIS as c_pais,
+                XPAIS as x_pais,
+                FONE as fone,
+                INDIEDEST as ind_ie_dest,
+                IE as ie,
+                ISUF as isuf,
+                IM as im,
+                EMAIL as email,
+                TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at,
+                TO_CHAR(UPDATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as updated_at
+            FROM nfe_recipients
+            WHERE INTERNALKEY = HEXTORAW(:1)
+        "#;
+
+        let mut stmt = self.conn.statement(sql).build()?;
+        let mut rows = stmt.query(&[&oracle_uuid])?;
+
+        if let Some(row_result) = rows.next() {
+            let row = row_result?;
+            // The address block is optional for recipients; XLGR is mandatory inside it.
+            let x_lgr: Option<String> = row.get("x_lgr")?;
+            let ender_dest = match x_lgr {
+                Some(_) => Some(address_from_row(&row)?),
+                None => None,
+            };
+
+            let recipient = NFeRecipient {
+                internal_key: internal_key.to_string(),
+                cnpj: row.get("cnpj")?,
+                cpf: row.get("cpf")?,
+                id_estrangeiro: row.get("id_estrangeiro")?,
+                x_nome: row.get("x_nome")?,
+                ender_dest,
+                ind_ie_dest: row.get("ind_ie_dest")?,
+                ie: row.get("ie")?,
+                isuf: row.get("isuf")?,
+                im: row.get("im")?,
+                email: row.get("email")?,
+                created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+                updated_at: parse_timestamp(&row.get::<_, String>("updated_at")?),
+            };
+
+            if let Err(e) = self
+                .cache
+                .set(&cache_key, &recipient, Some(Duration::from_secs(300)))
+                .await
+            {
+                error!("Failed to cache recipient: {}", e);
+            }
+
+            Ok(Some(recipient))
+        } else {
+            info!("No recipient found for NFe identification {}", internal_key);
+            Ok(None)
+        }
+    }
+
+    #[instrument(skip(self, recipient), fields(internal_key = %internal_key))]
+    pub async fn create_recipient(
+        &self,
+        internal_key: &str,
+        recipient: &CreateNFeRecipient,
+    ) -> Result<NFeRecipient, RepositoryError> {
+        info!("Creating recipient for NFe identification");
+        debug!("Input data: {:?}", recipient);
+
+        recipient.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        ensure_identification_exists(&self.conn, &oracle_uuid)?;
+
+        if self.find_recipient(internal_key).await?.is_some() {
+            return Err(RepositoryError::Conflict(
+                "Identification already has a recipient".to_string(),
+            ));
+        }
+
+        let result = self
+            .status
+            .edit(internal_key, |conn| {
+                insert_recipient(conn, &oracle_uuid, recipient)
+            })
+            .await;
+
+        match result {
+            Ok(()) => {
+                info!("Successfully created recipient for {}", internal_key);
+                self.find_recipient(internal_key)
+                    .await?
+                    .ok_or(RepositoryError::CreationFailed)
+            }
+            Err(e) => {
+                error!("Failed to create recipient: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    #[instrument(skip(self, recipient), fields(internal_key = %internal_key))]
+    pub async fn update_recipient(
+        &self,
+        internal_key: &str,
+        recipient: &CreateNFeRecipient,
+    ) -> Result<NFeRecipient, RepositoryError> {
+        info!("Updating recipient for NFe identification");
+        debug!("Update data: {:?}", recipient);
+
+        recipient.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+
+        let sql = r#"
+            UPDATE nfe_recipients
+            SET
+                CNPJ = :1, CPF = :2, IDESTRANGEIRO = :3, XNOME = :4,
+                XLGR = :5, NRO = :6, XCPL = :7, XBAIRRO = :8, CMUN = :9, XMUN = :10,
+                UF = :11, CEP = :12, CPAIS = :13, XPAIS = :14, FONE = :15,
+                INDIEDEST = :16, IE = :17, ISUF = :18, IM = :19, EMAIL = :20
+            WHERE INTERNALKEY = HEXTORAW(:21)
+        "#;
+
+        let address = AddressBinds::from(recipient.ender_dest.as_ref());
+        self.status
+            .edit(internal_key, |conn| {
+                let mut stmt = conn.statement(sql).build()?;
+                stmt.execute(&[
+                    &recipient.cnpj,
+                    &recipient.cpf,
+                    &recipient.id_estrangeiro,
+                    &recipient.x_nome,
+                    &address.x_lgr,
+                    &address.nro,
+                    &address.x_cpl,
+                    &address.x_bairro,
+                    &address.c_mun,
+                    &address.x_mun,
+                    &address.uf,
+                    &address.cep,
+                    &address.c_pais,
+                    &address.x_pais,
+                    &address.fone,
+                    &recipient.ind_ie_dest,
+                    &recipient.ie,
+                    &recipient.isuf,
+                    &recipient.im,
+                    &recipient.email,
+                    &oracle_uuid,
+                ])?;
+                if stmt.row_count()? == 0 {
+                    return Err(RepositoryError::NotFound);
+                }
+                Ok(())
+            })
+            .await?;
+
+        self.invalidate(internal_key, "recipient").await;
+        self.find_recipient(internal_key)
+            .await?
+            .ok_or(RepositoryError::UpdateFailed)
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn delete_recipient(&self, internal_key: &str) -> Result<(), RepositoryError> {
+        info!("Deleting recipient for NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        self.status
+            .edit(internal_key, |conn| {
+                let mut stmt = conn
+                    .statement("DELETE FROM nfe_recipients WHERE INTERNALKEY = HEXTORAW(:1)")
+                    .build()?;
+                stmt.execute(&[&oracle_uuid])?;
+                if stmt.row_count()? == 0 {
+                    return Err(RepositoryError::NotFound);
+                }
+                Ok(())
+            })
+            .await?;
+
+        self.invalidate(internal_key, "recipient").await;
+        Ok(())
+    }
+
+    /// The emitter document is part of the access key, so `cDV` follows emitter changes.
+    async fn invalidate_identification(&self, internal_key: &str) {
+        if let Err(e) = self.cache.delete(&format!("nfe:{}", internal_key)).await {
+            error!("Failed to invalidate identification cache: {}", e);
+        }
+        if let Err(e) = self.cache.delete("nfe:list:*").await {
+            error!("Failed to invalidate list cache: {}", e);
+        }
+    }
+
+    async fn invalidate(&self, internal_key: &str, group: &str) {
+        if let Err(e) = self
+            .cache
+            .delete(&format!("nfe:{}:{}", internal_key, group))
+            .await
+        {
+            error!("Failed to invalidate {} cache: {}", group, e);
+        }
+    }
+}
+
+fn address_from_row(row: &Row) -> Result<NFeAddress, RepositoryError> {
+    Ok(NFeAddress {
+        x_lgr: row.get("x_lgr")?,
+        nro: row.get("nro")?,
+        x_cpl: row.get("x_cpl")?,
+        x_bairro: row.get("x_bairro")?,
+        c_mun: row.get("c_mun")?,
+        x_mun: row.get("x_mun")?,
+        uf: row.get("uf")?,
+        cep: row.get("cep")?,
+        c_pais: row.get("c_pais")?,
+        x_pais: row.get("x_pais")?,
+        fone: row.get("fone")?,
+    })
+}
+
+/// Bind values for an optional address; every column is NULL when it is absent.
+#[derive(Default)]
+struct AddressBinds {
+    x_lgr: Option<String>,
+    nro: Option<String>,
+    x_cpl: Option<String>,
+    x_bairro: Option<String>,
+    c_mun: Option<String>,
+    x_mun: Option<String>,
+    uf: Option<String>,
+    cep: Option<String>,
+    c_pais: Option<String>,
+    x_pais: Option<String>,
+    fone: Option<String>,
+}
+
+impl From<Option<&NFeAddress>> for AddressBinds {
+    fn from(address: Option<&NFeAddress>) -> Self {
+        match address {
+            Some(a) => Self {
+                x_lgr: Some(a.x_lgr.clone()),
+                nro: Some(a.nro.clone()),
+                x_cpl: a.x_cpl.clone(),
+                x_bairro: Some(a.x_bairro.clone()),
+                c_mun: Some(a.c_mun.clone()),
+                x_mun: Some(a.x_mun.clone()),
+                uf: Some(a.uf.clone()),
+                cep: a.cep.clone(),
+                c_pais: a.c_pais.clone(),
+                x_pais: a.x_pais.clone(),
+                fone: a.fone.clone(),
+            },
+            None => Self::default(),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_payment::{NFeBilling, NFePayment};
+use crate::repositories::common::{decimal_bind, optional_decimal_bind, to_oracle_uuid};
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::repositories::nfe_total_repository::NFeTotalRepository;
+use oracle::Connection;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+
+/// Replaces the `cobr` group; an empty one clears it.
+pub fn store_billing(
+    conn: &Connection,
+    oracle_uuid: &str,
+    billing: &NFeBilling,
+) -> Result<(), RepositoryError> {
+    let cobr = if billing.is_empty() {
+        None
+    } else {
+        Some(
+            serde_json::to_string(billing)
+                .map_err(|e| RepositoryError::InvalidData(e.to_string()))?,
+        )
+    };
+    conn.execute(
+        r#"
+        MERGE INTO nfe_payments t
+        USING (SELECT HEXTORAW(:1) AS INTERNALKEY FROM dual) s
+        ON (t.INTERNALKEY = s.INTERNALKEY)
+        WHEN MATCHED THEN UPDATE SET COBR = :2
+        WHEN NOT MATCHED THEN INSERT (INTERNALKEY, COBR) VALUES (s.INTERNALKEY, :3)
+        "#,
+        &[&oracle_uuid, &cobr, &cobr],
+    )?;
+    Ok(())
+}
+
+pub fn store_payment(
+    conn: &Connection,
+    oracle_uuid: &str,
+    payment: &NFePayment,
+) -> Result<(), RepositoryError> {
+    let pag =
+        serde_json::to_string(payment).map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+    conn.execute(
+        r#"
+        MERGE INTO nfe_payments t
+        USING (
+            SELECT HEXTORAW(:1) AS INTERNALKEY, :2 AS VPAG, :3 AS VTROCO FROM dual
+        ) s
+        ON (t.INTERNALKEY = s.INTERNALKEY)
+        WHEN MATCHED THEN UPDATE SET VPAG = s.VPAG, VTROCO = s.VTROCO, PAG = :4
+        WHEN NOT MATCHED THEN INSERT (INTERNALKEY, VPAG, VTROCO, PAG)
+            VALUES (s.INTERNALKEY, s.VPAG, s.VTROCO, :5)
+        "#,
+        &[
+            &oracle_uuid,
+            &decimal_bind(&payment.total()),
+            &optional_decimal_bind(&payment.v_troco),
+            &pag,
+            &pag,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Persists the `cobr` and `pag` groups of an identification. Notes without a stored
+/// `pag` are issued with tPag 90.
+pub struct NFePaymentRepository {
+    conn: Arc<Connection>,
+    totals: Arc<NFeTotalRepository>,
+    status: Arc<NFeStatusRepository>,
+}
+
+fn from_json<T: DeserializeOwned + Default>(json: Option<String>) -> Result<T, RepositoryError> {
+    match json {
+        Some(json) => {
+            serde_json::from_str(&json).map_err(|e| RepositoryError::InvalidData(e.to_string()))
+        }
+        None => Ok(T::default()),
+    }
+}
+
+impl NFePaymentRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        totals: Arc<NFeTotalRepository>,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
+        Self {
+            conn,
+            totals,
+            status,
+        }
+    }
+
+    /// Stored `COBR` and `PAG` columns; NotFound when the identification does not exist.
+    fn stored(
+        &self,
+        oracle_uuid: &str,
+    ) -> Result<(String, Option<String>, Option<String>), RepositoryError> {
+        let sql = r#"
+            SELECT i.MOD_, p.COBR, p.PAG
+            FROM nfe_identifications i
+            LEFT JOIN nfe_payments p ON p.INTERNALKEY = i.INTERNALKEY
+            WHERE i.INTERNALKEY = HEXTORAW(:1)
+        "#;
+        match self
+            .conn
+            .query_row_as::<(String, Option<String>, Option<String>)>(sql, &[&oracle_uuid])
+        {
+            Ok(row) => Ok(row),
+            Err(oracle::Error::NoDataFound) => Err(RepositoryError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_billing(&self, internal_key: &str) -> Result<NFeBilling, RepositoryError> {
+        info!("Fetching billing of NFe identification");
+
+        let (_, cobr, _) = self.stored(&to_oracle_uuid(internal_key)?)?;
+        from_json(cobr)
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_payment(&self, internal_key: &str) -> Result<NFePayment, RepositoryError> {
+        info!("Fetching payment of NFe identification");
+
+        let (_, _, pag) = self.stored(&to_oracle_uuid(internal_key)?)?;
+        from_json(pag)
+    }
+
+    /// Replaces the `cobr` group, checked against the model of the note.
+    #[instrument(skip(self, billing), fields(internal_key = %internal_key))]
+    pub async fn update_billing(
+        &self,
+        internal_key: &str,
+        billing: &NFeBilling,
+    ) -> Result<NFeBilling, RepositoryError> {
+        info!("Updating billing of NFe identification");
+        debug!("Input data: {:?}", billing);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let (mod_, _, _) = self.stored(&oracle_uuid)?;
+        billing.validate(&mod_)?;
+        self.status.ensure_editable(internal_key)?;
+
+        self.status
+            .edit(internal_key, |conn| {
+                store_billing(conn, &oracle_uuid, billing)
+            })
+            .await?;
+
+        info!("Successfully updated billing of {}", internal_key);
+        Ok(billing.clone())
+    }
+
+    /// Replaces the `pag` group, checked against the vNF derived from the items.
+    #[instrument(skip(self, payment), fields(internal_key = %internal_key))]
+    pub async fn update_payment(
+        &self,
+        internal_key: &str,
+        payment: &NFePayment,
+    ) -> Result<NFePayment, RepositoryError> {
+        info!("Updating payment of NFe identification");
+        debug!("Input data: {:?}", payment);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.stored(&oracle_uuid)?;
+        let total = self.totals.find(internal_key).await?;
+        payment.validate(total.icms_tot.v_nf)?;
+        self.status.ensure_editable(internal_key)?;
+
+        self.status
+            .edit(internal_key, |conn| {
+                store_payment(conn, &oracle_uuid, payment)
+            })
+            .await?;
+
+        info!("Successfully updated payment of {}", internal_key);
+        Ok(payment.clone())
+    }
+}
@@ -0,0 +1,143 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_reference::{validate_references, NFeReference, NFeReferrer};
+use crate::repositories::common::to_oracle_uuid;
+use crate::repositories::nfe_access_key_repository::find_access_key;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use oracle::Connection;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+use uuid::Uuid;
+
+/// Replaces every reference of the note. The old references are only replaced once
+/// every new one is stored.
+pub fn store_references(
+    conn: &Connection,
+    oracle_uuid: &str,
+    references: &[NFeReference],
+) -> Result<(), RepositoryError> {
+    conn.execute(
+        "DELETE FROM nfe_references WHERE INTERNALKEY = HEXTORAW(:1)",
+        &[&oracle_uuid],
+    )?;
+    for (n_ref, reference) in (1u32..).zip(references) {
+        let json = serde_json::to_string(reference)
+            .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+        conn.execute(
+            r#"
+            INSERT INTO nfe_references (INTERNALKEY, NREF, KIND, REFKEY, REFERENCE)
+            VALUES (HEXTORAW(:1), :2, :3, :4, :5)
+            "#,
+            &[
+                &oracle_uuid,
+                &n_ref,
+                &reference.kind().unwrap_or_default(),
+                &reference.access_key(),
+                &json,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Persists the `NFref` groups of an identification, one row per reference.
+pub struct NFeReferenceRepository {
+    conn: Arc<Connection>,
+    status: Arc<NFeStatusRepository>,
+}
+
+impl NFeReferenceRepository {
+    pub fn new(conn: Arc<Connection>, status: Arc<NFeStatusRepository>) -> Self {
+        Self { conn, status }
+    }
+
+    fn find_fin_nfe(&self, oracle_uuid: &str) -> Result<String, RepositoryError> {
+        match self.conn.query_row_as::<String>(
+            "SELECT FINNFE FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+            &[&oracle_uuid],
+        ) {
+            Ok(fin_nfe) => Ok(fin_nfe),
+            Err(oracle::Error::NoDataFound) => Err(RepositoryError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_all(&self, internal_key: &str) -> Result<Vec<NFeReference>, RepositoryError> {
+        info!("Fetching references of NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.find_fin_nfe(&oracle_uuid)?;
+        let rows = self.conn.query_as::<String>(
+            "SELECT REFERENCE FROM nfe_references WHERE INTERNALKEY = HEXTORAW(:1) ORDER BY NREF",
+            &[&oracle_uuid],
+        )?;
+        rows.map(|json| {
+            serde_json::from_str(&json?).map_err(|e| RepositoryError::InvalidData(e.to_string()))
+        })
+        .collect()
+    }
+
+    /// Replaces every reference of the note, checked against its `finNFe`.
+    #[instrument(skip(self, references), fields(internal_key = %internal_key))]
+    pub async fn replace(
+        &self,
+        internal_key: &str,
+        references: &[NFeReference],
+    ) -> Result<Vec<NFeReference>, RepositoryError> {
+        info!("Replacing references of NFe identification");
+        debug!("Input data: {:?}", references);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let fin_nfe = self.find_fin_nfe(&oracle_uuid)?;
+        validate_references(&fin_nfe, references)?;
+        self.status.ensure_editable(internal_key)?;
+
+        self.status
+            .edit(internal_key, |conn| {
+                store_references(conn, &oracle_uuid, references)
+            })
+            .await?;
+
+        info!(
+            "Successfully stored {} references of {}",
+            references.len(),
+            internal_key
+        );
+        Ok(references.to_vec())
+    }
+
+    /// Notes that reference the access key through refNFe, refNFeSig or refCTe.
+    #[instrument(skip(self))]
+    pub async fn find_referrers(&self, key: &str) -> Result<Vec<NFeReferrer>, RepositoryError> {
+        info!("Finding notes that reference access key");
+
+        if !is_digits(key, 44, 44) {
+            return Err(ValidationError::new("chNFe", "must have 44 digits").into());
+        }
+        let sql = r#"
+            SELECT RAWTOHEX(r.INTERNALKEY), i.FINNFE, r.KIND
+            FROM nfe_references r
+            JOIN nfe_identifications i ON i.INTERNALKEY = r.INTERNALKEY
+            WHERE r.REFKEY = :1
+            ORDER BY i.DHEMI, r.NREF
+        "#;
+        let rows = self
+            .conn
+            .query_as::<(String, String, String)>(sql, &[&key])?;
+        let mut referrers = Vec::new();
+        for row in rows {
+            let (oracle_uuid, fin_nfe, kind) = row?;
+            let internal_key = Uuid::parse_str(&oracle_uuid)
+                .map_err(|e| RepositoryError::InvalidData(e.to_string()))?
+                .to_string();
+            referrers.push(NFeReferrer {
+                access_key: find_access_key(&self.conn, &oracle_uuid)?.map(|key| key.to_string()),
+                internal_key,
+                fin_nfe,
+                kind,
+            });
+        }
+        Ok(referrers)
+    }
+}
@@ -0,0 +1,328 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_status::{NFeStatus, NFeStatusChange};
+use crate::repositories::common::{in_transaction, parse_timestamp, to_oracle_uuid};
+use crate::services::cache_service::CacheService;
+use oracle::pool::Pool;
+use oracle::Connection;
+use redis::aio::ConnectionManager;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+fn parse_status(value: &str) -> Result<NFeStatus, RepositoryError> {
+    NFeStatus::from_str(value).map_err(|e| RepositoryError::InvalidData(e.to_string()))
+}
+
+fn status_of(
+    conn: &Connection,
+    oracle_uuid: &str,
+    lock: bool,
+) -> Result<NFeStatus, RepositoryError> {
+    let sql = if lock {
+        "SELECT STATUS FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1) FOR UPDATE"
+    } else {
+        "SELECT STATUS FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)"
+    };
+    match conn.query_row_as::<String>(sql, &[&oracle_uuid]) {
+        Ok(status) => parse_status(&status),
+        Err(oracle::Error::NoDataFound) => Err(RepositoryError::NotFound),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Refuses changes to notes that SEFAZ has received.
+fn check_editable(status: NFeStatus) -> Result<(), RepositoryError> {
+    match status {
+        status if status.is_editable() => Ok(()),
+        NFeStatus::Authorized => Err(RepositoryError::Conflict(
+            "the note is authorized and cannot be changed; cancel it instead".to_string(),
+        )),
+        status => Err(RepositoryError::Conflict(format!(
+            "the note is {} and cannot be changed",
+            status
+        ))),
+    }
+}
+
+/// Moves the note from `from` to `to` and records it. The status in the WHERE clause
+/// keeps concurrent transitions from both applying.
+fn apply_transition(
+    conn: &Connection,
+    oracle_uuid: &str,
+    from: NFeStatus,
+    to: NFeStatus,
+    c_stat: Option<&str>,
+    x_motivo: Option<&str>,
+) -> Result<(), RepositoryError> {
+    let mut stmt = conn
+        .statement(
+            r#"
+            UPDATE nfe_identifications
+            SET STATUS = :1,
+                SIGNEDXML = CASE WHEN :2 = 'draft' THEN NULL ELSE SIGNEDXML END
+            WHERE INTERNALKEY = HEXTORAW(:3) AND STATUS = :4
+            "#,
+        )
+        .build()?;
+    stmt.execute(&[&to.as_str(), &to.as_str(), &oracle_uuid, &from.as_str()])?;
+    if stmt.row_count()? == 0 {
+        return Err(RepositoryError::Conflict(
+            "the status of the note changed concurrently".to_string(),
+        ));
+    }
+    insert_history(conn, oracle_uuid, Some(from), to, c_stat, x_motivo)
+}
+
+fn insert_history(
+    conn: &Connection,
+    oracle_uuid: &str,
+    from: Option<NFeStatus>,
+    to: NFeStatus,
+    c_stat: Option<&str>,
+    x_motivo: Option<&str>,
+) -> Result<(), RepositoryError> {
+    let id = Uuid::new_v4().to_string().replace('-', "");
+    conn.execute(
+        r#"
+        INSERT INTO nfe_status_history (ID, INTERNALKEY, FROMSTATUS, TOSTATUS, CSTAT, XMOTIVO)
+        VALUES (HEXTORAW(:1), HEXTORAW(:2), :3, :4, :5, :6)
+        "#,
+        &[
+            &id,
+            &oracle_uuid,
+            &from.map(|s| s.as_str()),
+            &to.as_str(),
+            &c_stat,
+            &x_motivo,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Keeps the lifecycle status of the notes and the history of its transitions.
+pub struct NFeStatusRepository {
+    conn: Arc<Connection>,
+    pool: Pool,
+    cache: Arc<CacheService>,
+}
+
+impl NFeStatusRepository {
+    pub fn new(conn: Arc<Connection>, pool: Pool, redis_manager: ConnectionManager) -> Self {
+        Self {
+            conn,
+            pool,
+            cache: Arc::new(CacheService::new(redis_manager)),
+        }
+    }
+
+    pub fn current(&self, oracle_uuid: &str) -> Result<NFeStatus, RepositoryError> {
+        status_of(&self.conn, oracle_uuid, false)
+    }
+
+    /// Opens the history of a note that was just inserted as draft, in the transaction
+    /// of the insert.
+    pub fn record_created(
+        &self,
+        conn: &Connection,
+        oracle_uuid: &str,
+    ) -> Result<(), RepositoryError> {
+        insert_history(conn, oracle_uuid, None, NFeStatus::Draft, None, None)
+    }
+
+    /// Moves the note to `to` if the lifecycle allows it, recording the SEFAZ answer
+    /// that caused the change. Returns the previous status.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn transition(
+        &self,
+        internal_key: &str,
+        to: NFeStatus,
+        c_stat: Option<&str>,
+        x_motivo: Option<&str>,
+    ) -> Result<NFeStatus, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let from = in_transaction(&self.pool, |conn| {
+            let from = status_of(conn, &oracle_uuid, true)?;
+            if !from.can_transition_to(to) {
+                return Err(RepositoryError::Conflict(format!(
+                    "the note is {} and cannot become {}",
+                    from, to
+                )));
+            }
+            apply_transition(conn, &oracle_uuid, from, to, c_stat, x_motivo)?;
+            Ok(from)
+        })?;
+        info!("NFe moved from {} to {}", from, to);
+
+        self.invalidate(internal_key).await;
+        Ok(from)
+    }
+
+    /// Moves the note from `from` to `to` under the lock of its row, refusing when
+    /// another request changed its status first. A transmission claims the note this way
+    /// before SEFAZ is called, so concurrent requests never send it twice.
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn claim(
+        &self,
+        internal_key: &str,
+        from: NFeStatus,
+        to: NFeStatus,
+    ) -> Result<(), RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        in_transaction(&self.pool, |conn| {
+            let current = status_of(conn, &oracle_uuid, true)?;
+            if current != from || !from.can_transition_to(to) {
+                return Err(RepositoryError::Conflict(format!(
+                    "the note is {} and cannot become {}",
+                    current, to
+                )));
+            }
+            apply_transition(conn, &oracle_uuid, from, to, None, None)
+        })?;
+        info!("NFe moved from {} to {}", from, to);
+
+        self.invalidate(internal_key).await;
+        Ok(())
+    }
+
+    /// Locks the note row in the transaction of `conn` and returns its status. Events
+    /// hold this lock while SEFAZ is called, so the sequence they take stays theirs until
+    /// they are stored.
+    pub fn lock(&self, conn: &Connection, oracle_uuid: &str) -> Result<NFeStatus, RepositoryError> {
+        status_of(conn, oracle_uuid, true)
+    }
+
+    /// Refuses changes to notes that SEFAZ has received. Only checks the status: the
+    /// change itself is written through `edit`.
+    pub fn ensure_editable(&self, internal_key: &str) -> Result<(), RepositoryError> {
+        check_editable(self.current(&to_oracle_uuid(internal_key)?)?)
+    }
+
+    /// Writes a change to the note with `work` in one transaction, holding the lock of
+    /// the note row so concurrent changes to the same note run one at a time. Once the
+    /// write succeeds, a validated, signed or rejected note goes back to draft in that
+    /// same transaction, dropping its signature; a failed write leaves the status alone.
+    pub async fn edit<T>(
+        &self,
+        internal_key: &str,
+        work: impl FnOnce(&Connection) -> Result<T, RepositoryError>,
+    ) -> Result<T, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let (value, from) = in_transaction(&self.pool, |conn| {
+            let from = status_of(conn, &oracle_uuid, true)?;
+            check_editable(from)?;
+            let value = work(conn)?;
+            if from != NFeStatus::Draft {
+                apply_transition(conn, &oracle_uuid, from, NFeStatus::Draft, None, None)?;
+            }
+            Ok((value, from))
+        })?;
+        if from != NFeStatus::Draft {
+            info!("NFe moved from {} to {}", from, NFeStatus::Draft);
+            self.invalidate(internal_key).await;
+        }
+        Ok(value)
+    }
+
+    /// Runs `work`, which removes the note, in one transaction under the lock of the note
+    /// row, once the note is known to be editable. Nothing is left to move back to draft.
+    pub fn remove<T>(
+        &self,
+        internal_key: &str,
+        work: impl FnOnce(&Connection) -> Result<T, RepositoryError>,
+    ) -> Result<T, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        in_transaction(&self.pool, |conn| {
+            check_editable(status_of(conn, &oracle_uuid, true)?)?;
+            work(conn)
+        })
+    }
+
+    /// Marks a draft imported from an `nfeProc` as authorized, in the transaction of the
+    /// import. This is the only change that skips the lifecycle, since the note was
+    /// authorized elsewhere.
+    pub fn mark_imported(
+        &self,
+        conn: &Connection,
+        oracle_uuid: &str,
+        n_prot: &str,
+    ) -> Result<(), RepositoryError> {
+        let from = status_of(conn, oracle_uuid, true)?;
+        if from != NFeStatus::Draft {
+            return Err(RepositoryError::Conflict(format!(
+                "the note is {} and cannot be marked as imported",
+                from
+            )));
+        }
+        apply_transition(
+            conn,
+            oracle_uuid,
+            from,
+            NFeStatus::Authorized,
+            Some("100"),
+            Some(&format!("Imported from nfeProc, nProt {}", n_prot)),
+        )
+    }
+
+    pub fn signed_xml(&self, oracle_uuid: &str) -> Result<Option<String>, RepositoryError> {
+        Ok(self.conn.query_row_as(
+            "SELECT SIGNEDXML FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+            &[&oracle_uuid],
+        )?)
+    }
+
+    pub fn store_signed_xml(&self, oracle_uuid: &str, xml: &str) -> Result<(), RepositoryError> {
+        self.conn.execute(
+            "UPDATE nfe_identifications SET SIGNEDXML = :1 WHERE INTERNALKEY = HEXTORAW(:2)",
+            &[&xml, &oracle_uuid],
+        )?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find_history(
+        &self,
+        internal_key: &str,
+    ) -> Result<Vec<NFeStatusChange>, RepositoryError> {
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.current(&oracle_uuid)?;
+
+        let rows = self.conn.query(
+            r#"
+            SELECT
+                FROMSTATUS as from_status,
+                TOSTATUS as to_status,
+                CSTAT as c_stat,
+                XMOTIVO as x_motivo,
+                TO_CHAR(CREATEDAT, 'YYYY-MM-DD HH24:MI:SS.FF3') as created_at
+            FROM nfe_status_history
+            WHERE INTERNALKEY = HEXTORAW(:1)
+            ORDER BY CREATEDAT, ROWID
+            "#,
+            &[&oracle_uuid],
+        )?;
+        let mut history = Vec::new();
+        for row in rows {
+            let row = row?;
+            let from: Option<String> = row.get("from_status")?;
+            history.push(NFeStatusChange {
+                internal_key: internal_key.to_string(),
+                from: from.as_deref().map(parse_status).transpose()?,
+                to: parse_status(&row.get::<_, String>("to_status")?)?,
+                c_stat: row.get("c_stat")?,
+                x_motivo: row.get("x_motivo")?,
+                created_at: parse_timestamp(&row.get::<_, String>("created_at")?),
+            });
+        }
+        Ok(history)
+    }
+
+    async fn invalidate(&self, internal_key: &str) {
+        if let Err(e) = self.cache.delete(&format!("nfe:{}", internal_key)).await {
+            error!("Failed to invalidate identification cache: {}", e);
+        }
+        if let Err(e) = self.cache.delete("nfe:list:*").await {
+            error!("Failed to invalidate list cache: {}", e);
+        }
+    }
+}
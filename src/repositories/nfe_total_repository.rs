@@ -0,0 +1,216 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_total::{NFeRetTrib, NFeTotal, UpdateNFeTotal};
+use crate::repositories::common::{optional_decimal_bind, parse_optional_decimal, to_oracle_uuid};
+use crate::repositories::nfe_item_repository::NFeItemRepository;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use crate::services::tax::totals;
+use chrono::NaiveDate;
+use oracle::{Connection, Row};
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Values of the `total` group that cannot be derived from the items.
+struct StoredTotals {
+    d_compet: Option<NaiveDate>,
+    c_reg_trib: Option<String>,
+    ret_trib: Option<NFeRetTrib>,
+    dh_emi: NaiveDate,
+}
+
+/// Stores `dCompet`, `cRegTrib` and `retTrib` of the declared totals; the rest of the
+/// group is derived from the items.
+pub fn store_totals(
+    conn: &Connection,
+    oracle_uuid: &str,
+    declared: &UpdateNFeTotal,
+) -> Result<(), RepositoryError> {
+    let sql = r#"
+        MERGE INTO nfe_totals t
+        USING (SELECT HEXTORAW(:internal_key) AS INTERNALKEY FROM DUAL) s
+        ON (t.INTERNALKEY = s.INTERNALKEY)
+        WHEN MATCHED THEN UPDATE SET
+            DCOMPET = TO_DATE(:d_compet, 'YYYY-MM-DD'),
+            CREGTRIB = :c_reg_trib,
+            VRETPIS = :v_ret_pis,
+            VRETCOFINS = :v_ret_cofins,
+            VRETCSLL = :v_ret_csll,
+            VBCIRRF = :v_bc_irrf,
+            VIRRF = :v_irrf,
+            VBCRETPREV = :v_bc_ret_prev,
+            VRETPREV = :v_ret_prev
+        WHEN NOT MATCHED THEN INSERT (
+            INTERNALKEY, DCOMPET, CREGTRIB, VRETPIS, VRETCOFINS, VRETCSLL,
+            VBCIRRF, VIRRF, VBCRETPREV, VRETPREV
+        ) VALUES (
+            s.INTERNALKEY, TO_DATE(:d_compet, 'YYYY-MM-DD'), :c_reg_trib, :v_ret_pis,
+            :v_ret_cofins, :v_ret_csll, :v_bc_irrf, :v_irrf, :v_bc_ret_prev, :v_ret_prev
+        )
+    "#;
+
+    let issqn_tot = declared.issqn_tot.as_ref();
+    let d_compet = issqn_tot.map(|t| t.d_compet.format(DATE_FORMAT).to_string());
+    let c_reg_trib = issqn_tot.and_then(|t| t.c_reg_trib.clone());
+    let ret_trib = declared.ret_trib.clone().unwrap_or_default();
+    let mut stmt = conn.statement(sql).build()?;
+    stmt.execute_named(&[
+        ("internal_key", &oracle_uuid),
+        ("d_compet", &d_compet),
+        ("c_reg_trib", &c_reg_trib),
+        ("v_ret_pis", &optional_decimal_bind(&ret_trib.v_ret_pis)),
+        (
+            "v_ret_cofins",
+            &optional_decimal_bind(&ret_trib.v_ret_cofins),
+        ),
+        ("v_ret_csll", &optional_decimal_bind(&ret_trib.v_ret_csll)),
+        ("v_bc_irrf", &optional_decimal_bind(&ret_trib.v_bc_irrf)),
+        ("v_irrf", &optional_decimal_bind(&ret_trib.v_irrf)),
+        (
+            "v_bc_ret_prev",
+            &optional_decimal_bind(&ret_trib.v_bc_ret_prev),
+        ),
+        ("v_ret_prev", &optional_decimal_bind(&ret_trib.v_ret_prev)),
+    ])?;
+    Ok(())
+}
+
+/// Builds the `total` group of an identification. ICMSTot and ISSQNtot are recomputed
+/// from the items on every read, so nothing derived is stored or cached.
+pub struct NFeTotalRepository {
+    conn: Arc<Connection>,
+    items: Arc<NFeItemRepository>,
+    status: Arc<NFeStatusRepository>,
+}
+
+impl NFeTotalRepository {
+    pub fn new(
+        conn: Arc<Connection>,
+        items: Arc<NFeItemRepository>,
+        status: Arc<NFeStatusRepository>,
+    ) -> Self {
+        Self {
+            conn,
+            items,
+            status,
+        }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find(&self, internal_key: &str) -> Result<NFeTotal, RepositoryError> {
+        info!("Deriving totals for NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let stored = self.find_stored(&oracle_uuid)?;
+        let items = self.items.find_all(internal_key).await?;
+
+        let (icms_tot, issqn_tot) = totals::compute(
+            &items,
+            stored.d_compet.unwrap_or(stored.dh_emi),
+            stored.c_reg_trib,
+        );
+        Ok(NFeTotal {
+            icms_tot,
+            issqn_tot,
+            ret_trib: stored.ret_trib,
+        })
+    }
+
+    /// Stores `dCompet`, `cRegTrib` and `retTrib`, rejecting declared ICMSTot/ISSQNtot
+    /// values that do not match the items.
+    #[instrument(skip(self, declared), fields(internal_key = %internal_key))]
+    pub async fn update(
+        &self,
+        internal_key: &str,
+        declared: &UpdateNFeTotal,
+    ) -> Result<NFeTotal, RepositoryError> {
+        info!("Updating totals for NFe identification");
+        debug!("Input data: {:?}", declared);
+
+        declared.validate()?;
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        self.status.ensure_editable(internal_key)?;
+        let stored = self.find_stored(&oracle_uuid)?;
+        let items = self.items.find_all(internal_key).await?;
+
+        let d_compet = declared.issqn_tot.as_ref().map(|t| t.d_compet);
+        let c_reg_trib = declared
+            .issqn_tot
+            .as_ref()
+            .and_then(|t| t.c_reg_trib.clone());
+        let (icms_tot, issqn_tot) = totals::compute(
+            &items,
+            d_compet.unwrap_or(stored.dh_emi),
+            c_reg_trib.clone(),
+        );
+        totals::check_declared(&icms_tot, issqn_tot.as_ref(), declared)?;
+
+        self.status
+            .edit(internal_key, |conn| {
+                store_totals(conn, &oracle_uuid, declared)
+            })
+            .await?;
+
+        info!("Successfully updated totals of {}", internal_key);
+        Ok(NFeTotal {
+            icms_tot,
+            issqn_tot,
+            ret_trib: declared.ret_trib.clone(),
+        })
+    }
+
+    fn find_stored(&self, oracle_uuid: &str) -> Result<StoredTotals, RepositoryError> {
+        let sql = r#"
+            SELECT
+                TO_CHAR(i.DHEMI, 'YYYY-MM-DD') as dh_emi,
+                TO_CHAR(t.DCOMPET, 'YYYY-MM-DD') as d_compet,
+                t.CREGTRIB as c_reg_trib,
+                t.VRETPIS as v_ret_pis,
+                t.VRETCOFINS as v_ret_cofins,
+                t.VRETCSLL as v_ret_csll,
+                t.VBCIRRF as v_bc_irrf,
+                t.VIRRF as v_irrf,
+                t.VBCRETPREV as v_bc_ret_prev,
+                t.VRETPREV as v_ret_prev
+            FROM nfe_identifications i
+            LEFT JOIN nfe_totals t ON t.INTERNALKEY = i.INTERNALKEY
+            WHERE i.INTERNALKEY = HEXTORAW(:1)
+        "#;
+
+        let row = match self.conn.query_row(sql, &[&oracle_uuid]) {
+            Ok(row) => row,
+            Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(StoredTotals {
+            d_compet: parse_optional_date(row.get("d_compet")?)?,
+            c_reg_trib: row.get("c_reg_trib")?,
+            ret_trib: ret_trib_from_row(&row)?,
+            dh_emi: parse_optional_date(row.get("dh_emi")?)?
+                .ok_or_else(|| RepositoryError::InvalidData("missing dhEmi".to_string()))?,
+        })
+    }
+}
+
+fn parse_optional_date(value: Option<String>) -> Result<Option<NaiveDate>, RepositoryError> {
+    value
+        .map(|v| {
+            NaiveDate::parse_from_str(&v, DATE_FORMAT)
+                .map_err(|e| RepositoryError::InvalidData(format!("{}: {}", v, e)))
+        })
+        .transpose()
+}
+
+fn ret_trib_from_row(row: &Row) -> Result<Option<NFeRetTrib>, RepositoryError> {
+    let ret_trib = NFeRetTrib {
+        v_ret_pis: parse_optional_decimal(row.get("v_ret_pis")?)?,
+        v_ret_cofins: parse_optional_decimal(row.get("v_ret_cofins")?)?,
+        v_ret_csll: parse_optional_decimal(row.get("v_ret_csll")?)?,
+        v_bc_irrf: parse_optional_decimal(row.get("v_bc_irrf")?)?,
+        v_irrf: parse_optional_decimal(row.get("v_irrf")?)?,
+        v_bc_ret_prev: parse_optional_decimal(row.get("v_bc_ret_prev")?)?,
+        v_ret_prev: parse_optional_decimal(row.get("v_ret_prev")?)?,
+    };
+    Ok((ret_trib != NFeRetTrib::default()).then_some(ret_trib))
+}
@@ -0,0 +1,114 @@
+use crate::errors::RepositoryError;
+use crate::models::nfe_transport::NFeTransport;
+use crate::repositories::common::to_oracle_uuid;
+use crate::repositories::nfe_status_repository::NFeStatusRepository;
+use oracle::Connection;
+use std::sync::Arc;
+use tracing::{debug, info, instrument};
+
+pub fn store_transport(
+    conn: &Connection,
+    oracle_uuid: &str,
+    transport: &NFeTransport,
+) -> Result<(), RepositoryError> {
+    let transp = serde_json::to_string(transport)
+        .map_err(|e| RepositoryError::InvalidData(e.to_string()))?;
+    let carrier_doc = transport
+        .transporta
+        .as_ref()
+        .and_then(|carrier| carrier.cnpj.clone().or_else(|| carrier.cpf.clone()));
+    conn.execute(
+        r#"
+        MERGE INTO nfe_transports t
+        USING (
+            SELECT HEXTORAW(:1) AS INTERNALKEY, :2 AS MODFRETE, :3 AS CARRIERDOC
+            FROM dual
+        ) s
+        ON (t.INTERNALKEY = s.INTERNALKEY)
+        WHEN MATCHED THEN UPDATE SET
+            MODFRETE = s.MODFRETE, CARRIERDOC = s.CARRIERDOC, TRANSP = :4
+        WHEN NOT MATCHED THEN INSERT (INTERNALKEY, MODFRETE, CARRIERDOC, TRANSP)
+            VALUES (s.INTERNALKEY, s.MODFRETE, s.CARRIERDOC, :5)
+        "#,
+        &[
+            &oracle_uuid,
+            &transport.mod_frete,
+            &carrier_doc,
+            &transp,
+            &transp,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Persists the `transp` group of an identification. Notes without a stored group are
+/// issued with modFrete 9.
+pub struct NFeTransportRepository {
+    conn: Arc<Connection>,
+    status: Arc<NFeStatusRepository>,
+}
+
+impl NFeTransportRepository {
+    pub fn new(conn: Arc<Connection>, status: Arc<NFeStatusRepository>) -> Self {
+        Self { conn, status }
+    }
+
+    #[instrument(skip(self), fields(internal_key = %internal_key))]
+    pub async fn find(&self, internal_key: &str) -> Result<NFeTransport, RepositoryError> {
+        info!("Fetching transport of NFe identification");
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let sql = r#"
+            SELECT t.TRANSP
+            FROM nfe_identifications i
+            LEFT JOIN nfe_transports t ON t.INTERNALKEY = i.INTERNALKEY
+            WHERE i.INTERNALKEY = HEXTORAW(:1)
+        "#;
+        let transp = match self
+            .conn
+            .query_row_as::<Option<String>>(sql, &[&oracle_uuid])
+        {
+            Ok(transp) => transp,
+            Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        match transp {
+            Some(json) => {
+                serde_json::from_str(&json).map_err(|e| RepositoryError::InvalidData(e.to_string()))
+            }
+            None => Ok(NFeTransport::default()),
+        }
+    }
+
+    /// Replaces the group, checked against the model and `idDest` of the note.
+    #[instrument(skip(self, transport), fields(internal_key = %internal_key))]
+    pub async fn update(
+        &self,
+        internal_key: &str,
+        transport: &NFeTransport,
+    ) -> Result<NFeTransport, RepositoryError> {
+        info!("Updating transport of NFe identification");
+        debug!("Input data: {:?}", transport);
+
+        let oracle_uuid = to_oracle_uuid(internal_key)?;
+        let (mod_, id_dest) = match self.conn.query_row_as::<(String, String)>(
+            "SELECT MOD_, IDDEST FROM nfe_identifications WHERE INTERNALKEY = HEXTORAW(:1)",
+            &[&oracle_uuid],
+        ) {
+            Ok(fields) => fields,
+            Err(oracle::Error::NoDataFound) => return Err(RepositoryError::NotFound),
+            Err(e) => return Err(e.into()),
+        };
+        transport.validate(&mod_, &id_dest)?;
+        self.status.ensure_editable(internal_key)?;
+
+        self.status
+            .edit(internal_key, |conn| {
+                store_transport(conn, &oracle_uuid, transport)
+            })
+            .await?;
+
+        info!("Successfully updated transport of {}", internal_key);
+        Ok(transport.clone())
+    }
+}
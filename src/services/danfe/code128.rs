@@ -0,0 +1,104 @@
+//! Code 128 barcodes in code set C, which packs two digits per symbol. The DANFE
+//! prints the 44-digit access key with it.
+
+use crate::errors::ValidationError;
+use crate::services::danfe::pdf::Page;
+
+/// Bar and space widths, in modules, of each symbol value; bars at the even positions.
+const PATTERNS: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+const START_C: usize = 105;
+const STOP: usize = 106;
+
+/// Symbol values of the digits in code set C, with start, checksum and stop.
+pub fn encode(digits: &str) -> Result<Vec<usize>, ValidationError> {
+    if digits.is_empty() || digits.len() % 2 == 1 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ValidationError::new(
+            "barcode",
+            "code set C needs an even number of digits",
+        ));
+    }
+    let mut symbols = vec![START_C];
+    symbols.extend(
+        digits
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| usize::from((pair[0] - b'0') * 10 + (pair[1] - b'0'))),
+    );
+    let checksum = symbols
+        .iter()
+        .enumerate()
+        .map(|(position, value)| position.max(1) * value)
+        .sum::<usize>()
+        % 103;
+    symbols.push(checksum);
+    symbols.push(STOP);
+    Ok(symbols)
+}
+
+/// Bar widths in modules, starting with a bar and alternating with spaces.
+pub fn modules(symbols: &[usize]) -> Vec<u8> {
+    symbols
+        .iter()
+        .flat_map(|&symbol| PATTERNS[symbol].bytes().map(|b| b - b'0'))
+        .collect()
+}
+
+/// Draws the barcode of the digits stretched across `width`, quiet zones included.
+pub fn draw(
+    page: &mut Page,
+    digits: &str,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) -> Result<(), ValidationError> {
+    let widths = modules(&encode(digits)?);
+    // Ten modules of quiet zone on each side.
+    let total = widths.iter().map(|&w| u32::from(w)).sum::<u32>() + 20;
+    let module = width / total as f32;
+    let mut cursor = x + 10.0 * module;
+    for (i, &w) in widths.iter().enumerate() {
+        let bar = f32::from(w) * module;
+        if i % 2 == 0 {
+            page.fill_rect(cursor, y, bar, height);
+        }
+        cursor += bar;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_digit_pairs_with_checksum() {
+        for (value, pattern) in PATTERNS.iter().enumerate() {
+            let modules: u32 = pattern.bytes().map(|b| u32::from(b - b'0')).sum();
+            assert_eq!(modules, if value == STOP { 13 } else { 11 }, "{}", value);
+        }
+        // (105 + 1*12 + 2*34) % 103 = 82
+        assert_eq!(encode("1234").unwrap(), vec![105, 12, 34, 82, 106]);
+        assert_eq!(
+            encode("35240312345678000195550010000001231876543219")
+                .unwrap()
+                .len(),
+            25
+        );
+        assert!(encode("123").is_err());
+        assert!(encode("12a4").is_err());
+    }
+}
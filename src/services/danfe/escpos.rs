@@ -0,0 +1,176 @@
+//! ESC/POS commands for 80 mm thermal printers, 48 columns in font A. Text is sent in
+//! code page 850, which has the Portuguese letters, and the QR code is drawn by the
+//! printer itself.
+
+use crate::services::danfe::nfce::Line;
+use crate::services::danfe::pdf::Align;
+
+pub const COLUMNS: usize = 48;
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+/// Code page 850 in `ESC t`.
+const PC850: u8 = 2;
+/// Size of a QR code module, in dots.
+const QR_MODULE: u8 = 5;
+
+/// Code page 850 byte of the character; characters outside it become `?`.
+fn encode_char(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        'Ç' => 0x80,
+        'ü' => 0x81,
+        'é' => 0x82,
+        'â' => 0x83,
+        'à' => 0x85,
+        'ç' => 0x87,
+        'ê' => 0x88,
+        'É' => 0x90,
+        'ô' => 0x93,
+        'ó' => 0xa2,
+        'á' => 0xa0,
+        'í' => 0xa1,
+        'ú' => 0xa3,
+        'ª' => 0xa6,
+        'º' => 0xa7,
+        'Á' => 0xb5,
+        'Â' => 0xb6,
+        'À' => 0xb7,
+        'ã' => 0xc6,
+        'Ã' => 0xc7,
+        'Ê' => 0xd2,
+        'Í' => 0xd6,
+        'Ó' => 0xe0,
+        'Ô' => 0xe2,
+        'õ' => 0xe4,
+        'Õ' => 0xe5,
+        'Ú' => 0xe9,
+        _ => b'?',
+    }
+}
+
+fn encode(text: &str) -> Vec<u8> {
+    text.chars().map(encode_char).collect()
+}
+
+/// Breaks the text into lines of at most `columns` characters, at spaces when possible.
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let used = line.chars().count();
+        if used > 0 && used + 1 + word.chars().count() <= columns {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if used > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut chars: Vec<char> = word.chars().collect();
+        while chars.len() > columns {
+            lines.push(chars.drain(..columns).collect());
+        }
+        line = chars.into_iter().collect();
+    }
+    lines.push(line);
+    lines
+}
+
+/// `GS ( k` function of the QR code symbol, with its parameters.
+fn qr_function(out: &mut Vec<u8>, function: u8, parameters: &[u8]) {
+    let length = parameters.len() + 2;
+    out.extend([
+        GS,
+        b'(',
+        b'k',
+        (length % 256) as u8,
+        (length / 256) as u8,
+        49,
+    ]);
+    out.push(function);
+    out.extend_from_slice(parameters);
+}
+
+fn qr_code(out: &mut Vec<u8>, data: &str) {
+    // Model 2, module size, error correction level M, store the data and print it.
+    qr_function(out, 65, &[50, 0]);
+    qr_function(out, 67, &[QR_MODULE]);
+    qr_function(out, 69, &[49]);
+    let mut store = vec![48];
+    store.extend_from_slice(data.as_bytes());
+    qr_function(out, 80, &store);
+    qr_function(out, 81, &[48]);
+}
+
+/// Prints the lines of the receipt and cuts the paper.
+pub fn render(lines: &[Line]) -> Vec<u8> {
+    let mut out = vec![ESC, b'@', ESC, b't', PC850];
+    let set = |out: &mut Vec<u8>, bold: bool, align: Align| {
+        let align = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        out.extend([ESC, b'E', u8::from(bold), ESC, b'a', align]);
+    };
+    for line in lines {
+        match line {
+            Line::Text { text, bold, align } => {
+                set(&mut out, *bold, *align);
+                for part in wrap(text, COLUMNS) {
+                    out.extend(encode(&part));
+                    out.push(b'\n');
+                }
+            }
+            Line::Pair { left, right, bold } => {
+                set(&mut out, *bold, Align::Left);
+                let room = COLUMNS.saturating_sub(right.chars().count() + 1);
+                let left: String = left.chars().take(room).collect();
+                let padding = COLUMNS.saturating_sub(left.chars().count() + right.chars().count());
+                out.extend(encode(&format!("{}{}{}", left, " ".repeat(padding), right)));
+                out.push(b'\n');
+            }
+            Line::Separator => {
+                set(&mut out, false, Align::Left);
+                out.extend("-".repeat(COLUMNS).bytes());
+                out.push(b'\n');
+            }
+            Line::QrCode(data) => {
+                set(&mut out, false, Align::Center);
+                qr_code(&mut out, data);
+                out.push(b'\n');
+            }
+        }
+    }
+    // Feed the receipt past the cutter and cut it, leaving a point uncut.
+    out.extend([GS, b'V', 66, 3]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_text_and_qr_code() {
+        assert_eq!(encode("Série nº"), b"S\x82rie n\xa7");
+        assert_eq!(wrap("um dois tres", 7), vec!["um dois", "tres"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+
+        let bytes = render(&[Line::Pair {
+            left: "Valor a Pagar R$".to_string(),
+            right: "100,00".to_string(),
+            bold: true,
+        }]);
+        let row = format!("Valor a Pagar R${}100,00\n", " ".repeat(COLUMNS - 22));
+        assert!(bytes.windows(row.len()).any(|w| w == row.as_bytes()));
+
+        let mut out = Vec::new();
+        qr_code(&mut out, "abc");
+        // Store: pL pH cover cn, fn, m and the three bytes of data.
+        assert!(out
+            .windows(9)
+            .any(|w| w == [GS, b'(', b'k', 6, 0, 49, 80, 48, b'a']));
+    }
+}
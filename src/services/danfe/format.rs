@@ -0,0 +1,163 @@
+//! Values as printed on the DANFE: Brazilian number format, masked documents and dates
+//! in the local time of the emitter's state.
+
+use crate::models::nfe_identification::uf_offset;
+use crate::services::xml::writer::format_decimal;
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+
+/// `1234.5` with two places as `1.234,50`.
+pub fn number(value: Decimal, scale: u32) -> String {
+    let plain = format_decimal(value, scale);
+    let (sign, plain) = match plain.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", plain.as_str()),
+    };
+    let (integer, fraction) = plain.split_once('.').unwrap_or((plain, ""));
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push('.');
+        }
+        grouped.push(digit);
+    }
+    if fraction.is_empty() {
+        format!("{}{}", sign, grouped)
+    } else {
+        format!("{}{},{}", sign, grouped, fraction)
+    }
+}
+
+pub fn money(value: Decimal) -> String {
+    number(value, 2)
+}
+
+pub fn optional_money(value: Option<Decimal>) -> String {
+    money(value.unwrap_or_default())
+}
+
+/// Applies a mask where `#` takes the next digit; values of another length are kept.
+fn mask(value: &str, pattern: &str) -> String {
+    if value.len() != pattern.chars().filter(|&c| c == '#').count() {
+        return value.to_string();
+    }
+    let mut digits = value.chars();
+    pattern
+        .chars()
+        .map(|c| match c {
+            '#' => digits.next().unwrap_or(' '),
+            other => other,
+        })
+        .collect()
+}
+
+pub fn cnpj(value: &str) -> String {
+    mask(value, "##.###.###/####-##")
+}
+
+pub fn cpf(value: &str) -> String {
+    mask(value, "###.###.###-##")
+}
+
+/// CNPJ or CPF, whichever is present.
+pub fn document(cnpj_value: Option<&str>, cpf_value: Option<&str>) -> String {
+    match (cnpj_value, cpf_value) {
+        (Some(value), _) => cnpj(value),
+        (None, Some(value)) => cpf(value),
+        (None, None) => String::new(),
+    }
+}
+
+pub fn cep(value: &str) -> String {
+    mask(value, "#####-###")
+}
+
+/// The access key in groups of four digits.
+pub fn access_key(key: &str) -> String {
+    key.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `123` as `000.000.123`.
+pub fn n_nf(value: &str) -> String {
+    mask(&format!("{:0>9}", value), "###.###.###")
+}
+
+/// `1` as `001`.
+pub fn serie(value: &str) -> String {
+    format!("{:0>3}", value)
+}
+
+pub fn date(c_uf: &str, value: &DateTime<Utc>) -> String {
+    value
+        .with_timezone(&uf_offset(c_uf))
+        .format("%d/%m/%Y")
+        .to_string()
+}
+
+pub fn time(c_uf: &str, value: &DateTime<Utc>) -> String {
+    value
+        .with_timezone(&uf_offset(c_uf))
+        .format("%H:%M:%S")
+        .to_string()
+}
+
+/// A date without time, such as the due date of an installment.
+pub fn day(value: NaiveDate) -> String {
+    value.format("%d/%m/%Y").to_string()
+}
+
+/// `tPag` as printed on the DANFE.
+pub fn payment_method(t_pag: &str) -> &'static str {
+    match t_pag {
+        "01" => "Dinheiro",
+        "02" => "Cheque",
+        "03" => "Cartão de Crédito",
+        "04" => "Cartão de Débito",
+        "05" => "Crédito Loja",
+        "10" => "Vale Alimentação",
+        "11" => "Vale Refeição",
+        "12" => "Vale Presente",
+        "13" => "Vale Combustível",
+        "15" => "Boleto Bancário",
+        "16" => "Depósito Bancário",
+        "17" => "Pagamento Instantâneo (PIX)",
+        "18" => "Transferência Bancária, Carteira Digital",
+        "19" => "Programa de Fidelidade, Cashback, Crédito Virtual",
+        "20" => "Pagamento Instantâneo (PIX) - Estático",
+        "21" => "Crédito em Loja",
+        "22" => "Pagamento Eletrônico não Informado",
+        "90" => "Sem Pagamento",
+        _ => "Outros",
+    }
+}
+
+pub fn date_time(c_uf: &str, value: &DateTime<Utc>) -> String {
+    format!("{} {}", date(c_uf, value), time(c_uf, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn formats_values_as_printed() {
+        assert_eq!(money(dec!(1234567.891)), "1.234.567,89");
+        assert_eq!(money(dec!(-0.5)), "-0,50");
+        assert_eq!(number(dec!(2), 4), "2,0000");
+        assert_eq!(cnpj("12345678000195"), "12.345.678/0001-95");
+        assert_eq!(cpf("12345678909"), "123.456.789-09");
+        assert_eq!(cep("01310100"), "01310-100");
+        assert_eq!(cep("0131"), "0131");
+        assert_eq!(n_nf("123"), "000.000.123");
+        assert_eq!(serie("1"), "001");
+        assert_eq!(access_key("35240312"), "3524 0312");
+        let dh = Utc.with_ymd_and_hms(2024, 3, 20, 2, 30, 0).unwrap();
+        assert_eq!(date_time("35", &dh), "19/03/2024 23:30:00");
+    }
+}
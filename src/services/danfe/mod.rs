@@ -0,0 +1,9 @@
+//! DANFE: the printed representation of a note, rendered to PDF in-process, and the
+//! DANFE NFC-e also to ESC/POS for thermal printers.
+
+pub mod code128;
+pub mod escpos;
+pub mod format;
+pub mod nfce;
+pub mod nfe;
+pub mod pdf;
@@ -0,0 +1,385 @@
+//! DANFE NFC-e: the receipt of the NFC-e (model 65) on 80 mm thermal paper. The receipt
+//! is built once as a list of lines and printed either to a PDF page as tall as the
+//! receipt or, through [`escpos`](crate::services::danfe::escpos), to the commands of
+//! the printer at the register.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_contingency::NORMAL;
+use crate::models::nfe_document::NFeDocument;
+use crate::models::nfe_protocol::NFeProtocol;
+use crate::services::danfe::format;
+use crate::services::danfe::pdf::{self, fit, text_width, wrap, Align, Font, Page, Style, MM};
+use crate::services::xml::nfce_supplement::NFCeSupplement;
+use qrcode::{Color, EcLevel, QrCode};
+use rust_decimal::Decimal;
+
+pub const PAPER_WIDTH: f32 = 80.0 * MM;
+const MARGIN: f32 = 4.0 * MM;
+const FONT_SIZE: f32 = 7.0;
+const LINE: f32 = 9.0;
+/// Side of the QR code; the layout asks for at least 25 mm.
+const QR_SIZE: f32 = 30.0 * MM;
+
+/// One line of the receipt, as wide as the paper.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    /// Text broken over as many lines as it needs.
+    Text {
+        text: String,
+        bold: bool,
+        align: Align,
+    },
+    /// A description on the left and its value on the right.
+    Pair {
+        left: String,
+        right: String,
+        bold: bool,
+    },
+    Separator,
+    QrCode(String),
+}
+
+impl Line {
+    fn left(text: impl Into<String>) -> Self {
+        Line::Text {
+            text: text.into(),
+            bold: false,
+            align: Align::Left,
+        }
+    }
+
+    fn center(text: impl Into<String>) -> Self {
+        Line::Text {
+            text: text.into(),
+            bold: false,
+            align: Align::Center,
+        }
+    }
+
+    fn title(text: impl Into<String>) -> Self {
+        Line::Text {
+            text: text.into(),
+            bold: true,
+            align: Align::Center,
+        }
+    }
+
+    fn pair(left: impl Into<String>, right: impl Into<String>) -> Self {
+        Line::Pair {
+            left: left.into(),
+            right: right.into(),
+            bold: false,
+        }
+    }
+}
+
+/// Lines of the DANFE NFC-e in the divisions of its layout: emitter, items, totals and
+/// payments, consultation by key, consumer, identification and QR code. `supplement` is the
+/// `infNFeSupl` of the signed note.
+pub fn receipt(
+    document: &NFeDocument,
+    authorization: Option<&NFeProtocol>,
+    supplement: &NFCeSupplement,
+) -> Result<Vec<Line>, ValidationError> {
+    let ide = &document.identification;
+    if ide.mod_ != "65" {
+        return Err(ValidationError::new(
+            "mod",
+            "the DANFE NFC-e is printed for model 65 only",
+        ));
+    }
+    let emitter = document.emitter()?;
+    let key = document.access_key()?.to_string();
+    let total = &document.total.icms_tot;
+    let mut lines = Vec::new();
+
+    // Emitter.
+    lines.push(Line::title(emitter.x_nome.as_str()));
+    lines.push(Line::center(format!(
+        "CNPJ: {} IE: {}",
+        format::document(emitter.cnpj.as_deref(), emitter.cpf.as_deref()),
+        emitter.ie
+    )));
+    let address = &emitter.ender_emit;
+    lines.push(Line::center(format!(
+        "{}, {}, {}, {} - {}",
+        address.x_lgr, address.nro, address.x_bairro, address.x_mun, address.uf
+    )));
+    lines.push(Line::title(
+        "Documento Auxiliar da Nota Fiscal de Consumidor Eletrônica",
+    ));
+
+    // Items.
+    lines.push(Line::Separator);
+    lines.push(Line::Pair {
+        left: "# CÓDIGO DESCRIÇÃO QTDE UN x VL UNIT".to_string(),
+        right: "VL TOTAL".to_string(),
+        bold: true,
+    });
+    for item in &document.items {
+        let prod = &item.prod;
+        lines.push(Line::left(format!(
+            "{:03} {} {}",
+            item.n_item, prod.c_prod, prod.x_prod
+        )));
+        lines.push(Line::pair(
+            format!(
+                "    {} {} x {}",
+                format::number(prod.q_com, 4),
+                prod.u_com,
+                format::money(prod.v_un_com)
+            ),
+            format::money(prod.v_prod),
+        ));
+    }
+
+    // Totals.
+    lines.push(Line::Separator);
+    lines.push(Line::pair(
+        "Qtde. total de itens",
+        document.items.len().to_string(),
+    ));
+    lines.push(Line::pair("Valor total R$", format::money(total.v_prod)));
+    if total.v_desc > Decimal::ZERO {
+        lines.push(Line::pair("Desconto R$", format::money(total.v_desc)));
+    }
+    let surcharge = total.v_frete + total.v_seg + total.v_outro;
+    if surcharge > Decimal::ZERO {
+        lines.push(Line::pair("Acréscimos R$", format::money(surcharge)));
+    }
+    lines.push(Line::Pair {
+        left: "Valor a Pagar R$".to_string(),
+        right: format::money(total.v_nf),
+        bold: true,
+    });
+    lines.push(Line::Pair {
+        left: "FORMA PAGAMENTO".to_string(),
+        right: "VALOR PAGO R$".to_string(),
+        bold: true,
+    });
+    for det in &document.payment.det_pag {
+        let method = match &det.x_pag {
+            Some(x_pag) => x_pag.as_str(),
+            None => format::payment_method(&det.t_pag),
+        };
+        lines.push(Line::pair(method, format::money(det.v_pag)));
+    }
+    if let Some(v_troco) = document.payment.v_troco {
+        lines.push(Line::pair("Troco R$", format::money(v_troco)));
+    }
+    if let Some(v_tot_trib) = total.v_tot_trib {
+        lines.push(Line::center(format!(
+            "Tributos Totais Incidentes (Lei Federal 12.741/2012): R$ {}",
+            format::money(v_tot_trib)
+        )));
+    }
+
+    // Consultation by access key.
+    lines.push(Line::Separator);
+    lines.push(Line::title("Consulte pela Chave de Acesso em"));
+    lines.push(Line::center(supplement.url_chave.as_str()));
+    lines.push(Line::center(format::access_key(&key)));
+
+    // Consumer.
+    lines.push(Line::Separator);
+    match &document.recipient {
+        None => lines.push(Line::title("CONSUMIDOR NÃO IDENTIFICADO")),
+        Some(recipient) => {
+            let id = match (&recipient.cnpj, &recipient.cpf, &recipient.id_estrangeiro) {
+                (None, None, Some(id)) => format!("Id. Estrangeiro: {}", id),
+                (Some(cnpj), _, _) => format!("CNPJ: {}", format::cnpj(cnpj)),
+                (None, cpf, _) => format!("CPF: {}", format::cpf(cpf.as_deref().unwrap_or(""))),
+            };
+            let name = recipient.x_nome.as_deref().unwrap_or("");
+            lines.push(Line::title(format!("CONSUMIDOR - {} {}", id, name).trim()));
+            if let Some(address) = &recipient.ender_dest {
+                lines.push(Line::center(format!(
+                    "{}, {}, {}, {} - {}",
+                    address.x_lgr, address.nro, address.x_bairro, address.x_mun, address.uf
+                )));
+            }
+        }
+    }
+
+    // Identification and authorization.
+    lines.push(Line::Separator);
+    lines.push(Line::title(format!(
+        "NFC-e nº {} Série {} {}",
+        format::n_nf(&ide.n_nf),
+        format::serie(&ide.serie),
+        format::date_time(&ide.c_uf, &ide.dh_emi)
+    )));
+    if ide.tp_amb == "2" {
+        lines.push(Line::title(
+            "EMITIDA EM AMBIENTE DE HOMOLOGAÇÃO - SEM VALOR FISCAL",
+        ));
+    }
+    match authorization {
+        Some(protocol) => {
+            lines.push(Line::center(format!(
+                "Protocolo de Autorização: {}",
+                protocol.n_prot.as_deref().unwrap_or("")
+            )));
+            if let Some(dh_recbto) = protocol.dh_recbto {
+                lines.push(Line::center(format!(
+                    "Data de Autorização: {}",
+                    format::date_time(&ide.c_uf, &dh_recbto)
+                )));
+            }
+        }
+        None if ide.tp_emis != NORMAL => {
+            lines.push(Line::title("EMITIDA EM CONTINGÊNCIA"));
+            lines.push(Line::center("Pendente de autorização"));
+        }
+        None => lines.push(Line::title("NFC-e NÃO AUTORIZADA - SEM VALOR FISCAL")),
+    }
+    lines.push(Line::QrCode(supplement.qr_code.clone()));
+    // Message of interest of the taxpayer, printed below the QR code.
+    if let Some(inf_cpl) = &document.additional_info.inf_cpl {
+        lines.push(Line::Separator);
+        lines.push(Line::center(inf_cpl.as_str()));
+    }
+    Ok(lines)
+}
+
+/// Dark modules of the QR code, row by row, and the number of modules per side.
+fn qr_modules(data: &str) -> Result<(usize, Vec<bool>), ValidationError> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::M)
+        .map_err(|e| ValidationError::new("qrCode", format!("cannot be encoded: {}", e)))?;
+    let modules = code
+        .to_colors()
+        .into_iter()
+        .map(|c| c == Color::Dark)
+        .collect();
+    Ok((code.width(), modules))
+}
+
+fn style(bold: bool, align: Align) -> Style {
+    let font = if bold { Font::Bold } else { Font::Regular };
+    Style::new(font, FONT_SIZE, align)
+}
+
+/// Height the line takes on the PDF page.
+fn height(line: &Line, width: f32) -> f32 {
+    match line {
+        Line::Text { text, bold, .. } => {
+            LINE * wrap(text, style(*bold, Align::Left).font, FONT_SIZE, width).len() as f32
+        }
+        Line::Pair { .. } | Line::Separator => LINE,
+        Line::QrCode(_) => QR_SIZE + 2.0 * LINE,
+    }
+}
+
+/// Draws the line with its top at `y` and returns its height.
+fn draw(page: &mut Page, line: &Line, x: f32, y: f32, width: f32) -> Result<f32, ValidationError> {
+    match line {
+        Line::Text { text, bold, align } => {
+            let style = style(*bold, *align);
+            for (i, part) in wrap(text, style.font, FONT_SIZE, width).iter().enumerate() {
+                page.text_in(x, y + LINE * (i as f32 + 1.0) - 2.0, width, style, part);
+            }
+        }
+        Line::Pair { left, right, bold } => {
+            let right_style = style(*bold, Align::Right);
+            let right_width = text_width(right, right_style.font, FONT_SIZE);
+            let left_width = width - right_width - 4.0;
+            let left = fit(left, right_style.font, FONT_SIZE, left_width);
+            page.text_in(
+                x,
+                y + LINE - 2.0,
+                left_width,
+                style(*bold, Align::Left),
+                &left,
+            );
+            page.text_in(x, y + LINE - 2.0, width, right_style, right);
+        }
+        Line::Separator => page.dashed_line(x, y + LINE / 2.0, x + width, y + LINE / 2.0),
+        Line::QrCode(data) => {
+            let (side, modules) = qr_modules(data)?;
+            let module = QR_SIZE / side as f32;
+            let left = x + (width - QR_SIZE) / 2.0;
+            let top = y + LINE;
+            for (i, _) in modules.iter().enumerate().filter(|(_, &dark)| dark) {
+                let (row, column) = (i / side, i % side);
+                page.fill_rect(
+                    left + column as f32 * module,
+                    top + row as f32 * module,
+                    module,
+                    module,
+                );
+            }
+        }
+    }
+    Ok(height(line, width))
+}
+
+/// Renders the receipt to a single page 80 mm wide.
+pub fn render_pdf(lines: &[Line], key: &str) -> Result<Vec<u8>, ValidationError> {
+    let width = PAPER_WIDTH - 2.0 * MARGIN;
+    let content: f32 = lines.iter().map(|line| height(line, width)).sum();
+    let mut page = Page::new(PAPER_WIDTH, content + 2.0 * MARGIN);
+    let mut y = MARGIN;
+    for line in lines {
+        y += draw(&mut page, line, MARGIN, y, width)?;
+    }
+    Ok(pdf::finish(vec![page], &format!("DANFE NFC-e {}", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::danfe::escpos;
+
+    fn nfce() -> NFeDocument {
+        let mut document = NFeDocument::sample();
+        let ide = &mut document.identification;
+        ide.mod_ = "65".to_string();
+        ide.tp_imp = "4".to_string();
+        ide.ind_final = "1".to_string();
+        document.recipient = None;
+        document
+    }
+
+    #[test]
+    fn prints_the_receipt_to_pdf_and_escpos() {
+        let document = nfce();
+        let key = document.access_key().unwrap().to_string();
+        let supplement = NFCeSupplement {
+            qr_code: format!(
+                "https://www.homologacao.nfce.fazenda.sp.gov.br/qrcode?p={}|2|1|1|{}",
+                key, "24344623072E39C7CBC49C0E4F199D8CC5C3FB36"
+            ),
+            url_chave: "https://www.homologacao.nfce.fazenda.sp.gov.br/consulta".to_string(),
+        };
+        let lines = receipt(&document, None, &supplement).unwrap();
+        assert!(lines.contains(&Line::title("CONSUMIDOR NÃO IDENTIFICADO")));
+        assert!(lines.contains(&Line::pair("Sem Pagamento", "0,00")));
+        assert!(lines.contains(&Line::title("NFC-e NÃO AUTORIZADA - SEM VALOR FISCAL")));
+        assert_eq!(
+            lines.last(),
+            Some(&Line::QrCode(supplement.qr_code.clone()))
+        );
+
+        let pdf = render_pdf(&lines, &key).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("226.77"), "the page is 80 mm wide");
+
+        let bytes = escpos::render(&lines);
+        assert!(bytes.starts_with(&[0x1b, b'@']));
+        let data = supplement.qr_code.as_bytes();
+        assert!(bytes.windows(data.len()).any(|w| w == data));
+
+        let mut document = document;
+        document.additional_info.inf_cpl = Some("Volte sempre!".to_string());
+        let lines = receipt(&document, None, &supplement).unwrap();
+        assert_eq!(lines.last(), Some(&Line::center("Volte sempre!")));
+
+        assert_eq!(
+            receipt(&NFeDocument::sample(), None, &supplement)
+                .unwrap_err()
+                .field,
+            "mod"
+        );
+    }
+}
@@ -0,0 +1,1372 @@
+//! DANFE of the NF-e (model 55) on A4, portrait or landscape as `tpImp`. The first page
+//! carries the receipt stub, the identification of the note, the recipient, the invoice
+//! and its installments, the tax totals and the transport above the item table, with the ISSQN and the additional
+//! data at its foot; the following pages repeat the identification and carry on with
+//! the items.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_contingency::{is_svc, NORMAL};
+use crate::models::nfe_document::NFeDocument;
+use crate::models::nfe_item::NFeItem;
+use crate::models::nfe_protocol::NFeProtocol;
+use crate::models::nfe_transport::NFeVolume;
+use crate::services::danfe::pdf::{self, wrap, Align, Font, Page, Style, A4_HEIGHT, A4_WIDTH};
+use crate::services::danfe::{code128, format};
+use rust_decimal::Decimal;
+use std::ops::Range;
+
+const MARGIN: f32 = 14.0;
+/// Height of a labelled field.
+const ROW: f32 = 20.0;
+/// Height of the caption above each block.
+const TITLE: f32 = 9.0;
+/// Receipt stub across the top in portrait, plus the space of the cut line.
+const STUB: f32 = 46.0;
+const STUB_SPACE: f32 = 56.0;
+/// Receipt stub down the left side in landscape, plus the space of the cut line.
+const STUB_WIDTH: f32 = 44.0;
+const STUB_WIDTH_SPACE: f32 = 54.0;
+
+const HEADER: f32 = 92.0;
+const IDENTIFICATION: f32 = HEADER + 2.0 * ROW;
+const RECIPIENT: f32 = TITLE + 3.0 * ROW;
+const TAXES: f32 = TITLE + 2.0 * ROW;
+const TRANSPORT: f32 = TITLE + 3.0 * ROW;
+const ITEM_HEADER: f32 = 16.0;
+const ITEMS_HEAD: f32 = TITLE + ITEM_HEADER;
+const ISSQN: f32 = TITLE + ROW;
+/// Width of an installment in the invoice block, and the rows of installments printed;
+/// the others go to the complementary information.
+const INSTALLMENT: f32 = 94.0;
+const INSTALLMENT_ROWS: usize = 3;
+const ADDITIONAL: f32 = TITLE + 72.0;
+
+const ITEM_FONT: f32 = 6.0;
+const ITEM_LINE: f32 = 7.0;
+const ITEM_PADDING: f32 = 3.0;
+
+/// Caption of a field.
+const LABEL: Style = Style::new(Font::Regular, 5.0, Align::Left);
+
+const WATERMARK: &str = "SEM VALOR FISCAL";
+
+/// Columns of the item table other than the description, which takes the rest of the
+/// width.
+const COLUMNS: [(&str, f32, Align); 13] = [
+    ("CÓDIGO PRODUTO", 48.0, Align::Left),
+    ("NCM/SH", 36.0, Align::Center),
+    ("O/CST", 22.0, Align::Center),
+    ("CFOP", 22.0, Align::Center),
+    ("UN", 18.0, Align::Center),
+    ("QUANT", 36.0, Align::Right),
+    ("VALOR UNIT", 40.0, Align::Right),
+    ("VALOR TOTAL", 40.0, Align::Right),
+    ("B.CÁLC ICMS", 38.0, Align::Right),
+    ("VALOR ICMS", 34.0, Align::Right),
+    ("VALOR IPI", 30.0, Align::Right),
+    ("ALÍQ. ICMS", 22.0, Align::Right),
+    ("ALÍQ. IPI", 22.0, Align::Right),
+];
+const DESCRIPTION: &str = "DESCRIÇÃO DO PRODUTO / SERVIÇO";
+/// Position of the description among the columns.
+const DESCRIPTION_COLUMN: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+impl Orientation {
+    fn from_tp_imp(tp_imp: &str) -> Result<Self, ValidationError> {
+        match tp_imp {
+            "1" => Ok(Orientation::Portrait),
+            "2" => Ok(Orientation::Landscape),
+            other => Err(ValidationError::new(
+                "tpImp",
+                format!(
+                    "tpImp {} has no A4 DANFE; use 1 (portrait) or 2 (landscape)",
+                    other
+                ),
+            )),
+        }
+    }
+
+    fn size(self) -> (f32, f32) {
+        match self {
+            Orientation::Portrait => (A4_WIDTH, A4_HEIGHT),
+            Orientation::Landscape => (A4_HEIGHT, A4_WIDTH),
+        }
+    }
+
+    /// Left edge and width of the blocks.
+    fn columns(self) -> (f32, f32) {
+        let (width, _) = self.size();
+        match self {
+            Orientation::Portrait => (MARGIN, width - 2.0 * MARGIN),
+            Orientation::Landscape => (
+                MARGIN + STUB_WIDTH_SPACE,
+                width - 2.0 * MARGIN - STUB_WIDTH_SPACE,
+            ),
+        }
+    }
+
+    /// Top of the identification block on the first page, below the stub in portrait.
+    fn first_top(self) -> f32 {
+        match self {
+            Orientation::Portrait => MARGIN + STUB_SPACE,
+            Orientation::Landscape => MARGIN,
+        }
+    }
+}
+
+/// Renders the DANFE of the note. `authorization` is the protocol that authorized it;
+/// without one the DANFE is marked as having no fiscal value unless the note was issued
+/// in offline contingency, whose DANFE is printed before the authorization.
+pub fn render(
+    document: &NFeDocument,
+    authorization: Option<&NFeProtocol>,
+) -> Result<Vec<u8>, ValidationError> {
+    let ide = &document.identification;
+    if ide.mod_ != "55" {
+        return Err(ValidationError::new(
+            "mod",
+            "the A4 DANFE is printed for model 55 only",
+        ));
+    }
+    let orientation = Orientation::from_tp_imp(&ide.tp_imp)?;
+    let key = document.access_key()?.to_string();
+    let danfe = Danfe {
+        document,
+        authorization,
+        key: &key,
+        orientation,
+    };
+
+    let description_width = danfe.description_width();
+    let rows: Vec<Vec<String>> = document
+        .items
+        .iter()
+        .map(|item| description_lines(item, description_width))
+        .collect();
+    let heights: Vec<f32> = rows.iter().map(|lines| row_height(lines.len())).collect();
+    let pages = paginate(&heights, danfe.first_capacity(), danfe.rest_capacity());
+
+    let offline = ide.tp_emis != NORMAL && !is_svc(&ide.tp_emis);
+    let watermark = ide.tp_amb == "2" || (authorization.is_none() && !offline);
+    let (width, height) = orientation.size();
+    let count = pages.len();
+    let mut rendered = Vec::with_capacity(count);
+    for (index, range) in pages.into_iter().enumerate() {
+        let mut page = Page::new(width, height);
+        if watermark {
+            page.watermark(WATERMARK);
+        }
+        danfe.draw_page(
+            &mut page,
+            index,
+            count,
+            &document.items[range.clone()],
+            &rows[range],
+        )?;
+        rendered.push(page);
+    }
+    Ok(pdf::finish(rendered, &format!("DANFE {}", key)))
+}
+
+/// Splits rows of the given heights into pages: the first holds `first_capacity`
+/// points of rows, the others `rest_capacity`. A row taller than a page gets one alone.
+pub fn paginate(heights: &[f32], first_capacity: f32, rest_capacity: f32) -> Vec<Range<usize>> {
+    let mut pages = Vec::new();
+    let mut start = 0;
+    let mut used = 0.0;
+    let mut capacity = first_capacity;
+    for (i, height) in heights.iter().enumerate() {
+        if used + height > capacity && i > start {
+            pages.push(start..i);
+            start = i;
+            used = 0.0;
+            capacity = rest_capacity;
+        }
+        used += height;
+    }
+    pages.push(start..heights.len());
+    pages
+}
+
+/// Description of the item with its additional information, broken into lines.
+fn description_lines(item: &NFeItem, width: f32) -> Vec<String> {
+    let mut text = item.prod.x_prod.clone();
+    if let Some(inf_ad_prod) = &item.inf_ad_prod {
+        text.push('\n');
+        text.push_str(inf_ad_prod);
+    }
+    wrap(&text, Font::Regular, ITEM_FONT, width - 4.0)
+}
+
+fn row_height(lines: usize) -> f32 {
+    lines.max(1) as f32 * ITEM_LINE + ITEM_PADDING
+}
+
+/// Values of the item in the order of the columns, without the description.
+fn item_cells(item: &NFeItem) -> Vec<String> {
+    let prod = &item.prod;
+    let icms = item.imposto.icms.as_ref();
+    let ipi = item.imposto.ipi.as_ref();
+    let cst = icms
+        .map(|icms| {
+            format!(
+                "{}{}",
+                icms.orig,
+                icms.cst.as_deref().or(icms.csosn.as_deref()).unwrap_or("")
+            )
+        })
+        .unwrap_or_default();
+    vec![
+        prod.c_prod.clone(),
+        prod.ncm.clone(),
+        cst,
+        prod.cfop.clone(),
+        prod.u_com.clone(),
+        format::number(prod.q_com, 4),
+        format::number(prod.v_un_com, 4),
+        format::money(prod.v_prod),
+        format::optional_money(icms.and_then(|icms| icms.v_bc)),
+        format::optional_money(icms.and_then(|icms| icms.v_icms)),
+        format::optional_money(ipi.and_then(|ipi| ipi.v_ipi)),
+        format::optional_money(icms.and_then(|icms| icms.p_icms)),
+        format::optional_money(ipi.and_then(|ipi| ipi.p_ipi)),
+    ]
+}
+
+/// Draws a labelled field: the caption in small print at the top, the value below.
+fn field(page: &mut Page, x: f32, y: f32, width: f32, label: &str, value: &str, align: Align) {
+    page.rect(x, y, width, ROW);
+    page.text_in(x + 2.0, y + 6.0, width - 4.0, LABEL, label);
+    page.text_in(
+        x + 2.0,
+        y + ROW - 4.5,
+        width - 4.0,
+        Style::new(Font::Regular, 8.0, align),
+        value,
+    );
+}
+
+/// Draws a row of fields sharing the width in the given proportions.
+fn fields(page: &mut Page, x: f32, y: f32, width: f32, row: &[(&str, String, f32, Align)]) {
+    let mut cursor = x;
+    for (i, (label, value, share, align)) in row.iter().enumerate() {
+        let w = if i + 1 == row.len() {
+            x + width - cursor
+        } else {
+            width * share
+        };
+        field(page, cursor, y, w, label, value, *align);
+        cursor += w;
+    }
+}
+
+/// `modFrete` as printed in the transport block.
+fn freight(mod_frete: &str) -> &'static str {
+    match mod_frete {
+        "0" => "0 - Por conta do Remetente (CIF)",
+        "1" => "1 - Por conta do Destinatário (FOB)",
+        "2" => "2 - Por conta de Terceiros",
+        "3" => "3 - Próprio por conta do Remetente",
+        "4" => "4 - Próprio por conta do Destinatário",
+        _ => "9 - Sem Ocorrência de Transporte",
+    }
+}
+
+fn title(page: &mut Page, x: f32, y: f32, text: &str) {
+    page.text(x, y + TITLE - 2.5, Font::Bold, 6.0, text);
+}
+
+struct Danfe<'a> {
+    document: &'a NFeDocument,
+    authorization: Option<&'a NFeProtocol>,
+    key: &'a str,
+    orientation: Orientation,
+}
+
+impl Danfe<'_> {
+    fn page_height(&self) -> f32 {
+        self.orientation.size().1
+    }
+
+    fn description_width(&self) -> f32 {
+        let (_, width) = self.orientation.columns();
+        width - COLUMNS.iter().map(|(_, w, _)| w).sum::<f32>()
+    }
+
+    fn footer_height(&self) -> f32 {
+        let issqn = if self.document.total.issqn_tot.is_some() {
+            ISSQN
+        } else {
+            0.0
+        };
+        issqn + ADDITIONAL
+    }
+
+    fn installments_per_row(&self) -> usize {
+        let (_, width) = self.orientation.columns();
+        (width / INSTALLMENT) as usize
+    }
+
+    /// Installments printed in the invoice block.
+    fn printed_installments(&self) -> usize {
+        let dup = self.document.billing.dup.len();
+        dup.min(INSTALLMENT_ROWS * self.installments_per_row())
+    }
+
+    /// The invoice block, left out when the note has no `cobr`.
+    fn billing_height(&self) -> f32 {
+        let billing = &self.document.billing;
+        if billing.is_empty() {
+            return 0.0;
+        }
+        let per_row = self.installments_per_row();
+        let rows =
+            usize::from(billing.fat.is_some()) + self.printed_installments().div_ceil(per_row);
+        TITLE + ROW * rows as f32
+    }
+
+    /// Points available to item rows on the first page.
+    fn first_capacity(&self) -> f32 {
+        let top = self.orientation.first_top()
+            + IDENTIFICATION
+            + RECIPIENT
+            + self.billing_height()
+            + TAXES
+            + TRANSPORT
+            + ITEMS_HEAD;
+        self.page_height() - MARGIN - self.footer_height() - top
+    }
+
+    /// Points available to item rows on the following pages.
+    fn rest_capacity(&self) -> f32 {
+        self.page_height() - MARGIN - (MARGIN + IDENTIFICATION + ITEMS_HEAD)
+    }
+
+    fn draw_page(
+        &self,
+        page: &mut Page,
+        index: usize,
+        count: usize,
+        items: &[NFeItem],
+        rows: &[Vec<String>],
+    ) -> Result<(), ValidationError> {
+        let (x, width) = self.orientation.columns();
+        let first = index == 0;
+        let mut y = if first {
+            self.orientation.first_top()
+        } else {
+            MARGIN
+        };
+        if first {
+            self.stub(page);
+        }
+        self.identification(page, x, y, width, index, count)?;
+        y += IDENTIFICATION;
+        if first {
+            self.recipient(page, x, y, width);
+            y += RECIPIENT;
+            self.billing(page, x, y, width);
+            y += self.billing_height();
+            self.taxes(page, x, y, width);
+            y += TAXES;
+            self.transport(page, x, y, width);
+            y += TRANSPORT;
+        }
+        let bottom = if first {
+            self.page_height() - MARGIN - self.footer_height()
+        } else {
+            self.page_height() - MARGIN
+        };
+        self.items(page, x, y, width, bottom, items, rows);
+        if first {
+            let mut y = bottom;
+            if self.document.total.issqn_tot.is_some() {
+                self.issqn(page, x, y, width);
+                y += ISSQN;
+            }
+            self.additional(page, x, y, width);
+        }
+        Ok(())
+    }
+
+    /// Receipt stub, signed by the recipient and kept by the emitter.
+    fn stub(&self, page: &mut Page) {
+        let document = self.document;
+        let ide = &document.identification;
+        let emitter = document.emitter.as_ref().map_or("", |e| e.x_nome.as_str());
+        let recipient = document
+            .recipient
+            .as_ref()
+            .and_then(|r| r.x_nome.as_deref())
+            .unwrap_or("");
+        let receipt = format!(
+            "RECEBEMOS DE {} OS PRODUTOS E/OU SERVIÇOS CONSTANTES DA NOTA FISCAL ELETRÔNICA INDICADA AO LADO. EMISSÃO: {} VALOR TOTAL: R$ {} DESTINATÁRIO: {}",
+            emitter,
+            format::date(&ide.c_uf, &ide.dh_emi),
+            format::money(document.total.icms_tot.v_nf),
+            recipient
+        );
+        let number = format!("Nº {}", format::n_nf(&ide.n_nf));
+        let serie = format!("SÉRIE {}", format::serie(&ide.serie));
+
+        match self.orientation {
+            Orientation::Portrait => {
+                let (x, width) = self.orientation.columns();
+                let y = MARGIN;
+                let main = width * 0.8;
+                let half = STUB / 2.0;
+                page.rect(x, y, main, half);
+                for (i, line) in wrap(&receipt, Font::Regular, 6.0, main - 4.0)
+                    .iter()
+                    .take(3)
+                    .enumerate()
+                {
+                    page.text(x + 2.0, y + 7.0 + 7.0 * i as f32, Font::Regular, 6.0, line);
+                }
+                page.rect(x, y + half, main * 0.25, half);
+                page.text_in(
+                    x + 2.0,
+                    y + half + 6.0,
+                    main * 0.25 - 4.0,
+                    LABEL,
+                    "DATA DE RECEBIMENTO",
+                );
+                page.rect(x + main * 0.25, y + half, main * 0.75, half);
+                page.text_in(
+                    x + main * 0.25 + 2.0,
+                    y + half + 6.0,
+                    main * 0.75 - 4.0,
+                    LABEL,
+                    "IDENTIFICAÇÃO E ASSINATURA DO RECEBEDOR",
+                );
+                let side = width - main;
+                page.rect(x + main, y, side, STUB);
+                page.text_in(
+                    x + main,
+                    y + 14.0,
+                    side,
+                    Style::new(Font::Bold, 10.0, Align::Center),
+                    "NF-e",
+                );
+                page.text_in(
+                    x + main,
+                    y + 27.0,
+                    side,
+                    Style::new(Font::Bold, 8.0, Align::Center),
+                    &number,
+                );
+                page.text_in(
+                    x + main,
+                    y + 37.0,
+                    side,
+                    Style::new(Font::Bold, 8.0, Align::Center),
+                    &serie,
+                );
+                let cut = y + (STUB + STUB_SPACE) / 2.0;
+                page.dashed_line(x, cut, x + width, cut);
+            }
+            Orientation::Landscape => {
+                let x = MARGIN;
+                let top = MARGIN;
+                let height = self.page_height() - 2.0 * MARGIN;
+                let bottom = top + height;
+                let half = STUB_WIDTH / 2.0;
+                let side = height * 0.2;
+                let main = height - side;
+                // The portrait stub turned a quarter to the left: its rows become columns
+                // read upwards and its right-hand box goes to the top.
+                page.rect(x, top, STUB_WIDTH, side);
+                page.vertical_text(x + 14.0, bottom - main - 8.0, Font::Bold, 10.0, "NF-e");
+                page.vertical_text(x + 25.0, bottom - main - 8.0, Font::Bold, 8.0, &number);
+                page.vertical_text(x + 35.0, bottom - main - 8.0, Font::Bold, 8.0, &serie);
+                page.rect(x, top + side, half, main);
+                for (i, line) in wrap(&receipt, Font::Regular, 6.0, main - 4.0)
+                    .iter()
+                    .take(3)
+                    .enumerate()
+                {
+                    page.vertical_text(
+                        x + 7.0 + 7.0 * i as f32,
+                        bottom - 2.0,
+                        Font::Regular,
+                        6.0,
+                        line,
+                    );
+                }
+                let date = main * 0.25;
+                page.rect(x + half, bottom - date, half, date);
+                page.vertical_text(
+                    x + half + 6.0,
+                    bottom - 2.0,
+                    Font::Regular,
+                    5.0,
+                    "DATA DE RECEBIMENTO",
+                );
+                page.rect(x + half, top + side, half, main - date);
+                page.vertical_text(
+                    x + half + 6.0,
+                    bottom - date - 2.0,
+                    Font::Regular,
+                    5.0,
+                    "IDENTIFICAÇÃO E ASSINATURA DO RECEBEDOR",
+                );
+                let cut = x + (STUB_WIDTH + STUB_WIDTH_SPACE) / 2.0;
+                page.dashed_line(cut, top, cut, bottom);
+            }
+        }
+    }
+
+    /// Emitter, DANFE and access key boxes, then the nature of the operation, the
+    /// authorization protocol and the registrations of the emitter.
+    fn identification(
+        &self,
+        page: &mut Page,
+        x: f32,
+        y: f32,
+        width: f32,
+        index: usize,
+        count: usize,
+    ) -> Result<(), ValidationError> {
+        let document = self.document;
+        let ide = &document.identification;
+        let emitter = document.emitter()?;
+
+        // Emitter.
+        let emitter_width = width * 0.4;
+        page.rect(x, y, emitter_width, HEADER);
+        page.text(
+            x + 2.0,
+            y + 6.0,
+            Font::Regular,
+            5.0,
+            "IDENTIFICAÇÃO DO EMITENTE",
+        );
+        let mut line_y = y + 20.0;
+        for line in wrap(&emitter.x_nome, Font::Bold, 9.0, emitter_width - 8.0)
+            .iter()
+            .take(2)
+        {
+            page.text_in(
+                x + 4.0,
+                line_y,
+                emitter_width - 8.0,
+                Style::new(Font::Bold, 9.0, Align::Center),
+                line,
+            );
+            line_y += 11.0;
+        }
+        let address = &emitter.ender_emit;
+        let mut street = format!("{}, {}", address.x_lgr, address.nro);
+        if let Some(x_cpl) = &address.x_cpl {
+            street.push_str(" - ");
+            street.push_str(x_cpl);
+        }
+        let mut lines = vec![street];
+        lines.push(match &address.cep {
+            Some(cep) => format!("{} - CEP {}", address.x_bairro, format::cep(cep)),
+            None => address.x_bairro.clone(),
+        });
+        lines.push(format!("{} - {}", address.x_mun, address.uf));
+        if let Some(fone) = &address.fone {
+            lines.push(format!("Fone: {}", fone));
+        }
+        line_y += 4.0;
+        for line in &lines {
+            page.text_in(
+                x + 4.0,
+                line_y,
+                emitter_width - 8.0,
+                Style::new(Font::Regular, 7.0, Align::Center),
+                line,
+            );
+            line_y += 9.0;
+        }
+
+        // DANFE.
+        let danfe_x = x + emitter_width;
+        let danfe_width = width * 0.17;
+        page.rect(danfe_x, y, danfe_width, HEADER);
+        let center = |page: &mut Page, y: f32, font: Font, size: f32, text: &str| {
+            page.text_in(
+                danfe_x + 2.0,
+                y,
+                danfe_width - 4.0,
+                Style::new(font, size, Align::Center),
+                text,
+            );
+        };
+        center(page, y + 14.0, Font::Bold, 12.0, "DANFE");
+        center(page, y + 23.0, Font::Regular, 6.5, "Documento Auxiliar da");
+        center(page, y + 30.0, Font::Regular, 6.5, "Nota Fiscal Eletrônica");
+        page.text(danfe_x + 8.0, y + 41.0, Font::Regular, 7.0, "0 - ENTRADA");
+        page.text(danfe_x + 8.0, y + 50.0, Font::Regular, 7.0, "1 - SAÍDA");
+        let box_x = danfe_x + danfe_width - 22.0;
+        page.rect(box_x, y + 36.0, 13.0, 15.0);
+        page.text_in(
+            box_x,
+            y + 47.5,
+            13.0,
+            Style::new(Font::Bold, 10.0, Align::Center),
+            &ide.tp_nf,
+        );
+        center(
+            page,
+            y + 64.0,
+            Font::Bold,
+            8.0,
+            &format!("Nº {}", format::n_nf(&ide.n_nf)),
+        );
+        center(
+            page,
+            y + 74.0,
+            Font::Bold,
+            8.0,
+            &format!("SÉRIE {}", format::serie(&ide.serie)),
+        );
+        center(
+            page,
+            y + 85.0,
+            Font::Regular,
+            7.0,
+            &format!("FOLHA {}/{}", index + 1, count),
+        );
+
+        // Access key.
+        let key_x = danfe_x + danfe_width;
+        let key_width = width - emitter_width - danfe_width;
+        page.rect(key_x, y, key_width, HEADER);
+        code128::draw(page, self.key, key_x + 4.0, y + 4.0, key_width - 8.0, 34.0)?;
+        page.line(key_x, y + 42.0, key_x + key_width, y + 42.0);
+        page.text(key_x + 2.0, y + 48.0, Font::Regular, 5.0, "CHAVE DE ACESSO");
+        page.text_in(
+            key_x + 2.0,
+            y + 58.0,
+            key_width - 4.0,
+            Style::new(Font::Bold, 8.0, Align::Center),
+            &format::access_key(self.key),
+        );
+        page.line(key_x, y + 62.0, key_x + key_width, y + 62.0);
+        page.text_in(
+            key_x + 2.0,
+            y + 73.0,
+            key_width - 4.0,
+            Style::new(Font::Regular, 7.0, Align::Center),
+            "Consulta de autenticidade no portal nacional da NF-e",
+        );
+        page.text_in(
+            key_x + 2.0,
+            y + 82.0,
+            key_width - 4.0,
+            Style::new(Font::Regular, 7.0, Align::Center),
+            "www.nfe.fazenda.gov.br/portal ou no site da Sefaz Autorizadora",
+        );
+
+        let protocol = match self.authorization {
+            Some(protocol) => {
+                let received = protocol
+                    .dh_recbto
+                    .map(|dh| format::date_time(&ide.c_uf, &dh))
+                    .unwrap_or_default();
+                format!(
+                    "{} - {}",
+                    protocol.n_prot.as_deref().unwrap_or(""),
+                    received
+                )
+            }
+            None => String::new(),
+        };
+        let y = y + HEADER;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "NATUREZA DA OPERAÇÃO",
+                    ide.nat_op.clone(),
+                    0.57,
+                    Align::Left,
+                ),
+                (
+                    "PROTOCOLO DE AUTORIZAÇÃO DE USO",
+                    protocol,
+                    0.43,
+                    Align::Center,
+                ),
+            ],
+        );
+        let y = y + ROW;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "INSCRIÇÃO ESTADUAL",
+                    emitter.ie.clone(),
+                    1.0 / 3.0,
+                    Align::Left,
+                ),
+                (
+                    "INSCRIÇÃO ESTADUAL DO SUBST. TRIBUT.",
+                    emitter.iest.clone().unwrap_or_default(),
+                    1.0 / 3.0,
+                    Align::Left,
+                ),
+                (
+                    "CNPJ / CPF",
+                    format::document(emitter.cnpj.as_deref(), emitter.cpf.as_deref()),
+                    1.0 / 3.0,
+                    Align::Left,
+                ),
+            ],
+        );
+        Ok(())
+    }
+
+    fn recipient(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        let ide = &self.document.identification;
+        let recipient = self.document.recipient.as_ref();
+        let address = recipient.and_then(|r| r.ender_dest.as_ref());
+        let text = |value: Option<&String>| value.cloned().unwrap_or_default();
+        let document = recipient
+            .map(|r| match (&r.cnpj, &r.cpf, &r.id_estrangeiro) {
+                (None, None, Some(id)) => id.clone(),
+                (cnpj, cpf, _) => format::document(cnpj.as_deref(), cpf.as_deref()),
+            })
+            .unwrap_or_default();
+        let street = address
+            .map(|a| match &a.x_cpl {
+                Some(x_cpl) => format!("{}, {} - {}", a.x_lgr, a.nro, x_cpl),
+                None => format!("{}, {}", a.x_lgr, a.nro),
+            })
+            .unwrap_or_default();
+        let (exit_date, exit_time) = ide
+            .dh_sai_ent
+            .map(|dh| (format::date(&ide.c_uf, &dh), format::time(&ide.c_uf, &dh)))
+            .unwrap_or_default();
+
+        title(page, x, y, "DESTINATÁRIO / REMETENTE");
+        let y = y + TITLE;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "NOME / RAZÃO SOCIAL",
+                    text(recipient.and_then(|r| r.x_nome.as_ref())),
+                    0.55,
+                    Align::Left,
+                ),
+                ("CNPJ / CPF", document, 0.25, Align::Center),
+                (
+                    "DATA DA EMISSÃO",
+                    format::date(&ide.c_uf, &ide.dh_emi),
+                    0.2,
+                    Align::Center,
+                ),
+            ],
+        );
+        let y = y + ROW;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                ("ENDEREÇO", street, 0.45, Align::Left),
+                (
+                    "BAIRRO / DISTRITO",
+                    text(address.map(|a| &a.x_bairro)),
+                    0.25,
+                    Align::Left,
+                ),
+                (
+                    "CEP",
+                    address
+                        .and_then(|a| a.cep.as_deref())
+                        .map(format::cep)
+                        .unwrap_or_default(),
+                    0.1,
+                    Align::Center,
+                ),
+                ("DATA DA SAÍDA/ENTRADA", exit_date, 0.2, Align::Center),
+            ],
+        );
+        let y = y + ROW;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "MUNICÍPIO",
+                    text(address.map(|a| &a.x_mun)),
+                    0.35,
+                    Align::Left,
+                ),
+                (
+                    "FONE / FAX",
+                    text(address.and_then(|a| a.fone.as_ref())),
+                    0.2,
+                    Align::Left,
+                ),
+                ("UF", text(address.map(|a| &a.uf)), 0.05, Align::Center),
+                (
+                    "INSCRIÇÃO ESTADUAL",
+                    text(recipient.and_then(|r| r.ie.as_ref())),
+                    0.2,
+                    Align::Left,
+                ),
+                ("HORA DA SAÍDA/ENTRADA", exit_time, 0.2, Align::Center),
+            ],
+        );
+    }
+
+    fn billing(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        let billing = &self.document.billing;
+        if billing.is_empty() {
+            return;
+        }
+        title(page, x, y, "FATURA / DUPLICATAS");
+        let mut y = y + TITLE;
+        if let Some(fat) = &billing.fat {
+            fields(
+                page,
+                x,
+                y,
+                width,
+                &[
+                    (
+                        "NÚMERO DA FATURA",
+                        fat.n_fat.clone().unwrap_or_default(),
+                        0.25,
+                        Align::Left,
+                    ),
+                    (
+                        "VALOR ORIGINAL",
+                        format::optional_money(fat.v_orig),
+                        0.25,
+                        Align::Right,
+                    ),
+                    (
+                        "VALOR DO DESCONTO",
+                        format::optional_money(fat.v_desc),
+                        0.25,
+                        Align::Right,
+                    ),
+                    (
+                        "VALOR LÍQUIDO",
+                        format::optional_money(fat.v_liq),
+                        0.25,
+                        Align::Right,
+                    ),
+                ],
+            );
+            y += ROW;
+        }
+        let per_row = self.installments_per_row();
+        let cell = width / per_row as f32;
+        let printed = &billing.dup[..self.printed_installments()];
+        for (row, installments) in printed.chunks(per_row).enumerate() {
+            for (column, dup) in installments.iter().enumerate() {
+                let label = format!(
+                    "DUP. {} VENC. {}",
+                    dup.n_dup.as_deref().unwrap_or(""),
+                    dup.d_venc.map(format::day).unwrap_or_default()
+                );
+                field(
+                    page,
+                    x + cell * column as f32,
+                    y + ROW * row as f32,
+                    cell,
+                    &label,
+                    &format::money(dup.v_dup),
+                    Align::Right,
+                );
+            }
+        }
+    }
+
+    fn taxes(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        let tot = &self.document.total.icms_tot;
+        let share = 1.0 / 7.0;
+        let money = |label, value| (label, format::money(value), share, Align::Right);
+        title(page, x, y, "CÁLCULO DO IMPOSTO");
+        let y = y + TITLE;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                money("BASE DE CÁLC. DO ICMS", tot.v_bc),
+                money("VALOR DO ICMS", tot.v_icms),
+                money("BASE DE CÁLC. ICMS S.T.", tot.v_bc_st),
+                money("VALOR DO ICMS SUBST.", tot.v_st),
+                money("V. IMP. IMPORTAÇÃO", tot.v_ii),
+                money("VALOR DO PIS", tot.v_pis),
+                money("V. TOTAL PRODUTOS", tot.v_prod),
+            ],
+        );
+        let y = y + ROW;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                money("VALOR DO FRETE", tot.v_frete),
+                money("VALOR DO SEGURO", tot.v_seg),
+                money("DESCONTO", tot.v_desc),
+                money("OUTRAS DESPESAS", tot.v_outro),
+                money("VALOR TOTAL IPI", tot.v_ipi),
+                money("VALOR DA COFINS", tot.v_cofins),
+                money("V. TOTAL DA NOTA", tot.v_nf),
+            ],
+        );
+    }
+
+    /// Transport block. Volumes are printed together: quantities and weights are
+    /// summed, and the species, brand and numbering shown when all volumes share them.
+    fn transport(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        let transport = &self.document.transport;
+        let carrier = transport.transporta.as_ref();
+        let vehicle = transport.veic_transp.as_ref();
+        let text = |value: Option<&String>| value.cloned().unwrap_or_default();
+        let volumes = &transport.vol;
+        let shared = |value: fn(&NFeVolume) -> Option<&String>| {
+            let first = volumes.first().and_then(value);
+            if volumes.iter().all(|volume| value(volume) == first) {
+                text(first)
+            } else {
+                String::new()
+            }
+        };
+        let quantity: u64 = volumes
+            .iter()
+            .filter_map(|volume| volume.q_vol.as_deref()?.parse::<u64>().ok())
+            .sum();
+        let weight = |value: fn(&NFeVolume) -> Option<Decimal>| {
+            let weights: Vec<Decimal> = volumes.iter().filter_map(value).collect();
+            if weights.is_empty() {
+                String::new()
+            } else {
+                format::number(weights.iter().sum(), 3)
+            }
+        };
+
+        title(page, x, y, "TRANSPORTADOR / VOLUMES TRANSPORTADOS");
+        let y = y + TITLE;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "NOME / RAZÃO SOCIAL",
+                    text(carrier.and_then(|c| c.x_nome.as_ref())),
+                    0.3,
+                    Align::Left,
+                ),
+                (
+                    "FRETE POR CONTA",
+                    freight(&transport.mod_frete).to_string(),
+                    0.2,
+                    Align::Left,
+                ),
+                (
+                    "CÓDIGO ANTT",
+                    text(vehicle.and_then(|v| v.rntc.as_ref())),
+                    0.1,
+                    Align::Left,
+                ),
+                (
+                    "PLACA DO VEÍCULO",
+                    text(vehicle.map(|v| &v.placa)),
+                    0.1,
+                    Align::Left,
+                ),
+                (
+                    "UF",
+                    text(vehicle.and_then(|v| v.uf.as_ref())),
+                    0.05,
+                    Align::Center,
+                ),
+                (
+                    "CNPJ / CPF",
+                    carrier
+                        .map(|c| format::document(c.cnpj.as_deref(), c.cpf.as_deref()))
+                        .unwrap_or_default(),
+                    0.25,
+                    Align::Center,
+                ),
+            ],
+        );
+        let y = y + ROW;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "ENDEREÇO",
+                    text(carrier.and_then(|c| c.x_ender.as_ref())),
+                    0.45,
+                    Align::Left,
+                ),
+                (
+                    "MUNICÍPIO",
+                    text(carrier.and_then(|c| c.x_mun.as_ref())),
+                    0.3,
+                    Align::Left,
+                ),
+                (
+                    "UF",
+                    text(carrier.and_then(|c| c.uf.as_ref())),
+                    0.05,
+                    Align::Center,
+                ),
+                (
+                    "INSCRIÇÃO ESTADUAL",
+                    text(carrier.and_then(|c| c.ie.as_ref())),
+                    0.2,
+                    Align::Left,
+                ),
+            ],
+        );
+        let y = y + ROW;
+        let share = 1.0 / 6.0;
+        fields(
+            page,
+            x,
+            y,
+            width,
+            &[
+                (
+                    "QUANTIDADE",
+                    if quantity > 0 {
+                        quantity.to_string()
+                    } else {
+                        String::new()
+                    },
+                    share,
+                    Align::Right,
+                ),
+                ("ESPÉCIE", shared(|v| v.esp.as_ref()), share, Align::Left),
+                ("MARCA", shared(|v| v.marca.as_ref()), share, Align::Left),
+                (
+                    "NUMERAÇÃO",
+                    shared(|v| v.n_vol.as_ref()),
+                    share,
+                    Align::Left,
+                ),
+                ("PESO BRUTO", weight(|v| v.peso_b), share, Align::Right),
+                ("PESO LÍQUIDO", weight(|v| v.peso_l), share, Align::Right),
+            ],
+        );
+    }
+
+    /// Item table from `y` down to `bottom`.
+    #[allow(clippy::too_many_arguments)]
+    fn items(
+        &self,
+        page: &mut Page,
+        x: f32,
+        y: f32,
+        width: f32,
+        bottom: f32,
+        items: &[NFeItem],
+        rows: &[Vec<String>],
+    ) {
+        title(page, x, y, "DADOS DOS PRODUTOS / SERVIÇOS");
+        let y = y + TITLE;
+
+        let mut columns: Vec<(&str, f32, Align)> = COLUMNS.to_vec();
+        columns.insert(
+            DESCRIPTION_COLUMN,
+            (DESCRIPTION, self.description_width(), Align::Left),
+        );
+
+        let mut cursor = x;
+        for (label, w, _) in &columns {
+            page.rect(cursor, y, *w, ITEM_HEADER);
+            let lines = wrap(label, Font::Bold, 5.0, w - 2.0);
+            let first = y + if lines.len() > 1 { 7.0 } else { 10.0 };
+            for (i, line) in lines.iter().take(2).enumerate() {
+                page.text_in(
+                    cursor + 1.0,
+                    first + 6.0 * i as f32,
+                    w - 2.0,
+                    Style::new(Font::Bold, 5.0, Align::Center),
+                    line,
+                );
+            }
+            page.rect(cursor, y + ITEM_HEADER, *w, bottom - y - ITEM_HEADER);
+            cursor += w;
+        }
+        debug_assert!((cursor - x - width).abs() < 0.01);
+
+        let mut row_y = y + ITEM_HEADER;
+        for (item, lines) in items.iter().zip(rows) {
+            let baseline = row_y + ITEM_LINE;
+            let mut cells = item_cells(item).into_iter();
+            let mut cursor = x;
+            for (i, (_, w, align)) in columns.iter().enumerate() {
+                if i == DESCRIPTION_COLUMN {
+                    for (n, line) in lines.iter().enumerate() {
+                        page.text(
+                            cursor + 2.0,
+                            baseline + ITEM_LINE * n as f32,
+                            Font::Regular,
+                            ITEM_FONT,
+                            line,
+                        );
+                    }
+                } else if let Some(value) = cells.next() {
+                    page.text_in(
+                        cursor + 1.5,
+                        baseline,
+                        w - 3.0,
+                        Style::new(Font::Regular, ITEM_FONT, *align),
+                        &value,
+                    );
+                }
+                cursor += w;
+            }
+            row_y += row_height(lines.len());
+        }
+    }
+
+    fn issqn(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        let Some(tot) = &self.document.total.issqn_tot else {
+            return;
+        };
+        let im = self
+            .document
+            .emitter
+            .as_ref()
+            .and_then(|e| e.im.clone())
+            .unwrap_or_default();
+        title(page, x, y, "CÁLCULO DO ISSQN");
+        fields(
+            page,
+            x,
+            y + TITLE,
+            width,
+            &[
+                ("INSCRIÇÃO MUNICIPAL", im, 0.25, Align::Left),
+                (
+                    "VALOR TOTAL DOS SERVIÇOS",
+                    format::optional_money(tot.v_serv),
+                    0.25,
+                    Align::Right,
+                ),
+                (
+                    "BASE DE CÁLCULO DO ISSQN",
+                    format::optional_money(tot.v_bc),
+                    0.25,
+                    Align::Right,
+                ),
+                (
+                    "VALOR DO ISSQN",
+                    format::optional_money(tot.v_iss),
+                    0.25,
+                    Align::Right,
+                ),
+            ],
+        );
+    }
+
+    /// Complementary information printed on the DANFE.
+    fn complementary(&self) -> Vec<String> {
+        let ide = &self.document.identification;
+        let mut lines = Vec::new();
+        if ide.tp_amb == "2" {
+            lines.push("NF-E EMITIDA EM AMBIENTE DE HOMOLOGAÇÃO - SEM VALOR FISCAL".to_string());
+        }
+        if ide.tp_emis != NORMAL {
+            let since = ide
+                .dh_cont
+                .map(|dh| format::date_time(&ide.c_uf, &dh))
+                .unwrap_or_default();
+            lines.push(format!(
+                "DANFE EMITIDO EM CONTINGÊNCIA (tpEmis {}) DESDE {}. JUSTIFICATIVA: {}",
+                ide.tp_emis,
+                since,
+                ide.x_justificativa.as_deref().unwrap_or("")
+            ));
+        }
+        let inf_adic = &self.document.additional_info;
+        if let Some(inf_ad_fisco) = &inf_adic.inf_ad_fisco {
+            lines.push(format!("Inf. Contribuinte Fisco: {}", inf_ad_fisco));
+        }
+        lines.extend(inf_adic.inf_cpl.clone());
+        lines.extend(
+            inf_adic
+                .obs_cont
+                .iter()
+                .map(|obs| format!("{}: {}", obs.x_campo, obs.x_texto)),
+        );
+        let remaining = &self.document.billing.dup[self.printed_installments()..];
+        if !remaining.is_empty() {
+            let installments: Vec<String> = remaining
+                .iter()
+                .map(|dup| {
+                    format!(
+                        "{} {} R$ {}",
+                        dup.n_dup.as_deref().unwrap_or(""),
+                        dup.d_venc.map(format::day).unwrap_or_default(),
+                        format::money(dup.v_dup)
+                    )
+                    .trim()
+                    .to_string()
+                })
+                .collect();
+            lines.push(format!("DEMAIS DUPLICATAS: {}", installments.join("; ")));
+        }
+        if let Some(v_tot_trib) = self.document.total.icms_tot.v_tot_trib {
+            lines.push(format!(
+                "Valor aproximado dos tributos: R$ {} (Lei 12.741/2012)",
+                format::money(v_tot_trib)
+            ));
+        }
+        lines
+    }
+
+    fn additional(&self, page: &mut Page, x: f32, y: f32, width: f32) {
+        title(page, x, y, "DADOS ADICIONAIS");
+        let y = y + TITLE;
+        let height = ADDITIONAL - TITLE;
+        let info_width = width * 0.7;
+        page.rect(x, y, info_width, height);
+        page.text(
+            x + 2.0,
+            y + 6.0,
+            Font::Regular,
+            5.0,
+            "INFORMAÇÕES COMPLEMENTARES",
+        );
+        let lines = wrap(
+            &self.complementary().join("\n"),
+            Font::Regular,
+            6.0,
+            info_width - 4.0,
+        );
+        let fits = ((height - 10.0) / ITEM_LINE) as usize;
+        for (i, line) in lines.iter().take(fits).enumerate() {
+            let line = if i + 1 == fits && lines.len() > fits {
+                pdf::fit(
+                    &format!("{} ...", line),
+                    Font::Regular,
+                    6.0,
+                    info_width - 4.0,
+                )
+            } else {
+                line.clone()
+            };
+            page.text(
+                x + 2.0,
+                y + 14.0 + ITEM_LINE * i as f32,
+                Font::Regular,
+                6.0,
+                &line,
+            );
+        }
+        page.rect(x + info_width, y, width - info_width, height);
+        page.text(
+            x + info_width + 2.0,
+            y + 6.0,
+            Font::Regular,
+            5.0,
+            "RESERVADO AO FISCO",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_payment::NFeInstallment;
+    use chrono::{Days, NaiveDate};
+
+    #[test]
+    fn splits_items_into_pages() {
+        assert_eq!(paginate(&[], 100.0, 200.0), vec![0..0]);
+        assert_eq!(paginate(&[40.0, 40.0], 100.0, 200.0), vec![0..2]);
+        assert_eq!(
+            paginate(&[40.0, 40.0, 40.0, 90.0, 90.0, 90.0], 100.0, 200.0),
+            vec![0..2, 2..4, 4..6]
+        );
+        // A row that fits nowhere still gets a page.
+        assert_eq!(paginate(&[300.0, 10.0], 100.0, 200.0), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn renders_every_page_of_the_danfe() {
+        let mut document = NFeDocument::sample();
+        let item = document.items[0].clone();
+        document.items = (1..=120)
+            .map(|n_item| {
+                let mut item = item.clone();
+                item.n_item = n_item;
+                item.inf_ad_prod =
+                    (n_item % 3 == 0).then(|| "Lote 42, validade 12/2025".to_string());
+                item
+            })
+            .collect();
+        document.billing.dup = (1..=30)
+            .map(|n| NFeInstallment {
+                n_dup: Some(format!("{:03}", n)),
+                d_venc: NaiveDate::from_ymd_opt(2024, 4, 20).map(|d| d + Days::new(30 * n)),
+                v_dup: Decimal::new(333, 2),
+            })
+            .collect();
+
+        let pdf = render(&document, None).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+        let text = String::from_utf8_lossy(&pdf);
+        let portrait = Danfe {
+            document: &document,
+            authorization: None,
+            key: "",
+            orientation: Orientation::Portrait,
+        };
+        let heights: Vec<f32> = document
+            .items
+            .iter()
+            .map(|item| row_height(description_lines(item, portrait.description_width()).len()))
+            .collect();
+        let expected = paginate(
+            &heights,
+            portrait.first_capacity(),
+            portrait.rest_capacity(),
+        )
+        .len();
+        assert!(expected > 1);
+        assert_eq!(portrait.printed_installments(), 18);
+        assert!(portrait
+            .complementary()
+            .iter()
+            .any(|line| line.starts_with("DEMAIS DUPLICATAS: 019 11/11/2025 R$ 3,33; 020")));
+        assert!(text.contains(&format!("/Count {}", expected)));
+
+        document.identification.tp_imp = "2".to_string();
+        assert!(render(&document, None).unwrap().starts_with(b"%PDF"));
+        document.identification.tp_imp = "4".to_string();
+        assert_eq!(render(&document, None).unwrap_err().field, "tpImp");
+    }
+}
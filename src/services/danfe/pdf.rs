@@ -0,0 +1,344 @@
+//! Minimal drawing surface over `pdf-writer`: boxes, lines and Helvetica text placed
+//! from the top-left corner of the page, in points. Text is written in WinAnsiEncoding,
+//! which covers Portuguese, and measured with the standard Helvetica metrics so it can
+//! be aligned, wrapped and cut to fit a box.
+
+use pdf_writer::{Content, Name, Pdf, Rect, Ref, Str, TextStr};
+
+/// Points per millimetre.
+pub const MM: f32 = 72.0 / 25.4;
+
+/// A4 portrait, in points.
+pub const A4_WIDTH: f32 = 595.28;
+pub const A4_HEIGHT: f32 = 841.89;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"F1"),
+            Font::Bold => Name(b"F2"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Widths of `!`..=`~` in Helvetica, per 1000 units of the font size.
+const HELVETICA: [u16; 94] = [
+    278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611,
+    778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667,
+    611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222,
+    833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+/// Widths of `!`..=`~` in Helvetica-Bold.
+const HELVETICA_BOLD: [u16; 94] = [
+    333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667, 611,
+    778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667,
+    611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278,
+    889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// Letter whose width an accented Latin-1 letter shares.
+fn base_letter(c: char) -> char {
+    match c {
+        'À'..='Å' => 'A',
+        'Ç' => 'C',
+        'È'..='Ë' => 'E',
+        'Ì'..='Ï' => 'I',
+        'Ñ' => 'N',
+        'Ò'..='Ö' | 'Ø' => 'O',
+        'Ù'..='Ü' => 'U',
+        'Ý' => 'Y',
+        'à'..='å' => 'a',
+        'ç' => 'c',
+        'è'..='ë' => 'e',
+        'ì'..='ï' => 'i',
+        'ñ' => 'n',
+        'ò'..='ö' | 'ø' => 'o',
+        'ù'..='ü' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ª' | 'º' | '°' => 'o',
+        _ => c,
+    }
+}
+
+fn char_width(c: char, font: Font) -> f32 {
+    let widths = match font {
+        Font::Regular => &HELVETICA,
+        Font::Bold => &HELVETICA_BOLD,
+    };
+    let width = match base_letter(c) {
+        ' ' => 278,
+        c @ '!'..='~' => widths[c as usize - '!' as usize],
+        _ => 556,
+    };
+    f32::from(width) / 1000.0
+}
+
+pub fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    text.chars().map(|c| char_width(c, font)).sum::<f32>() * size
+}
+
+/// The text cut to `width`, ending in `...` when it does not fit.
+pub fn fit(text: &str, font: Font, size: f32, width: f32) -> String {
+    if text_width(text, font, size) <= width {
+        return text.to_string();
+    }
+    let budget = width - text_width("...", font, size);
+    let mut used = 0.0;
+    let mut fitted = String::new();
+    for c in text.chars() {
+        used += char_width(c, font) * size;
+        if used > budget {
+            break;
+        }
+        fitted.push(c);
+    }
+    fitted.truncate(fitted.trim_end().len());
+    fitted.push_str("...");
+    fitted
+}
+
+/// Breaks the text into lines no wider than `width`, at spaces when possible. Line
+/// breaks in the text are kept.
+pub fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if text_width(&candidate, font, size) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // A word wider than the line is split wherever it overflows.
+            let mut rest = word.to_string();
+            while text_width(&rest, font, size) > width {
+                let mut used = 0.0;
+                let split = rest
+                    .char_indices()
+                    .find(|(_, c)| {
+                        used += char_width(*c, font) * size;
+                        used > width
+                    })
+                    .map(|(i, _)| i.max(rest.chars().next().map_or(1, char::len_utf8)))
+                    .unwrap_or(rest.len());
+                lines.push(rest[..split].to_string());
+                rest = rest[split..].to_string();
+            }
+            line = rest;
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// WinAnsiEncoding bytes of the text; characters outside it become `?`.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '\u{2013}' | '\u{2014}' => b'-',
+            '\u{2018}' | '\u{2019}' => b'\'',
+            '\u{201c}' | '\u{201d}' => b'"',
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Font, size and alignment of a piece of text placed in a box.
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    pub font: Font,
+    pub size: f32,
+    pub align: Align,
+}
+
+impl Style {
+    pub const fn new(font: Font, size: f32, align: Align) -> Self {
+        Self { font, size, align }
+    }
+}
+
+/// One page being drawn.
+pub struct Page {
+    pub width: f32,
+    pub height: f32,
+    content: Content,
+}
+
+impl Page {
+    pub fn new(width: f32, height: f32) -> Self {
+        let mut content = Content::new();
+        content.set_line_width(0.5);
+        Self {
+            width,
+            height,
+            content,
+        }
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.content
+            .rect(x, self.height - y - height, width, height)
+            .stroke();
+    }
+
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.content
+            .rect(x, self.height - y - height, width, height)
+            .fill_nonzero();
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.content
+            .move_to(x1, self.height - y1)
+            .line_to(x2, self.height - y2)
+            .stroke();
+    }
+
+    pub fn dashed_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.content.save_state().set_dash_pattern([3.0, 2.0], 0.0);
+        self.line(x1, y1, x2, y2);
+        self.content.restore_state();
+    }
+
+    /// Writes the text with its baseline at `y`.
+    pub fn text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        self.content
+            .begin_text()
+            .set_font(font.resource(), size)
+            .next_line(x, self.height - y)
+            .show(Str(&encode(text)))
+            .end_text();
+    }
+
+    /// Writes the text aligned within `[x, x + width]`, cut to fit.
+    pub fn text_in(&mut self, x: f32, y: f32, width: f32, style: Style, text: &str) {
+        let text = fit(text, style.font, style.size, width);
+        let offset = match style.align {
+            Align::Left => 0.0,
+            Align::Center => (width - text_width(&text, style.font, style.size)) / 2.0,
+            Align::Right => width - text_width(&text, style.font, style.size),
+        };
+        self.text(x + offset, y, style.font, style.size, &text);
+    }
+
+    /// Writes the text turned 90 degrees counterclockwise, reading upwards from `(x, y)`.
+    pub fn vertical_text(&mut self, x: f32, y: f32, font: Font, size: f32, text: &str) {
+        self.content
+            .begin_text()
+            .set_font(font.resource(), size)
+            .set_text_matrix([0.0, 1.0, -1.0, 0.0, x, self.height - y])
+            .show(Str(&encode(text)))
+            .end_text();
+    }
+
+    /// Large light-gray text across the page, drawn before the content so it stays
+    /// behind it.
+    pub fn watermark(&mut self, text: &str) {
+        let size = 40.0;
+        let width = text_width(text, Font::Bold, size);
+        let (cos, sin) = (
+            std::f32::consts::FRAC_1_SQRT_2,
+            std::f32::consts::FRAC_1_SQRT_2,
+        );
+        let x = (self.width - width * cos) / 2.0;
+        let y = (self.height - width * sin) / 2.0;
+        self.content
+            .save_state()
+            .set_fill_gray(0.85)
+            .begin_text()
+            .set_font(Font::Bold.resource(), size)
+            .set_text_matrix([cos, sin, -sin, cos, x, y])
+            .show(Str(&encode(text)))
+            .end_text()
+            .restore_state();
+    }
+}
+
+/// Assembles the pages into a PDF document.
+pub fn finish(pages: Vec<Page>, title: &str) -> Vec<u8> {
+    let mut pdf = Pdf::new();
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let page_ids: Vec<Ref> = (0..pages.len() as i32)
+        .map(|i| Ref::new(6 + 2 * i))
+        .collect();
+
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    pdf.type1_font(regular_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id).title(TextStr(title));
+
+    for (page, page_id) in pages.into_iter().zip(page_ids) {
+        let content_id = Ref::new(page_id.get() + 1);
+        {
+            let mut writer = pdf.page(page_id);
+            writer
+                .media_box(Rect::new(0.0, 0.0, page.width, page.height))
+                .parent(tree_id)
+                .contents(content_id);
+            writer
+                .resources()
+                .fonts()
+                .pair(Font::Regular.resource(), regular_id)
+                .pair(Font::Bold.resource(), bold_id);
+        }
+        pdf.stream(content_id, &page.content.finish());
+    }
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_and_breaks_text() {
+        assert_eq!(text_width("A", Font::Regular, 10.0), 6.67);
+        assert_eq!(
+            text_width("Ação", Font::Regular, 10.0),
+            text_width("Acao", Font::Regular, 10.0)
+        );
+        assert_eq!(fit("CAIXA", Font::Regular, 10.0, 100.0), "CAIXA");
+        let cut = fit("CAIXA DE PAPELAO", Font::Regular, 10.0, 40.0);
+        assert!(cut.ends_with("...") && text_width(&cut, Font::Regular, 10.0) <= 40.0);
+
+        let lines = wrap("Parafuso sextavado M8\nLote 42", Font::Regular, 10.0, 60.0);
+        assert_eq!(lines, vec!["Parafuso", "sextavado", "M8", "Lote 42"]);
+        let long = wrap("78912345678901234567", Font::Regular, 10.0, 30.0);
+        assert!(long.len() > 1 && long.concat() == "78912345678901234567");
+        assert_eq!(encode("Nº ação"), b"N\xba a\xe7\xe3o");
+    }
+}
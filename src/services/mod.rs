@@ -1 +1,6 @@
 pub mod cache_service;
+pub mod danfe;
+pub mod sefaz;
+pub mod signing_service;
+pub mod tax;
+pub mod xml;
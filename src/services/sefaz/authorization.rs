@@ -0,0 +1,353 @@
+//! NFeAutorizacao4 and NFeRetAutorizacao4: sends `enviNFe` batches and reads the
+//! `protNFe` of each note, polling `consReciNFe` for asynchronous batches.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::soap::{self, child_text, parse_timestamp, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use chrono::{DateTime, Utc};
+use roxmltree::{Document, Node};
+use std::time::Duration;
+use tracing::{info, instrument, warn};
+
+/// Batch received, to be queried by receipt.
+pub const BATCH_RECEIVED: &str = "103";
+/// Batch processed; every note has its `protNFe`.
+pub const BATCH_PROCESSED: &str = "104";
+/// Batch still being processed.
+pub const BATCH_IN_PROGRESS: &str = "105";
+
+/// `protNFe` of one note.
+#[derive(Debug, Clone)]
+pub struct ProtNFe {
+    pub tp_amb: String,
+    pub ver_aplic: String,
+    pub ch_nfe: String,
+    pub dh_recbto: DateTime<Utc>,
+    pub n_prot: Option<String>,
+    pub dig_val: Option<String>,
+    pub c_stat: String,
+    pub x_motivo: String,
+    /// The element as received, to be joined with the signed NFe in `nfeProc`.
+    pub xml: String,
+}
+
+#[derive(Debug)]
+pub enum BatchOutcome {
+    Processed(Vec<ProtNFe>),
+    /// Still in progress after the last poll; query again with the receipt.
+    Pending {
+        n_rec: String,
+    },
+}
+
+/// Status part of `retEnviNFe` and `retConsReciNFe`.
+struct BatchReply {
+    c_stat: String,
+    x_motivo: String,
+    n_rec: Option<String>,
+    protocols: Vec<ProtNFe>,
+}
+
+/// `enviNFe` with already signed notes.
+pub fn envi_nfe(id_lote: &str, sync: bool, signed: &[String]) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "enviNFe",
+        &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+    );
+    w.text("idLote", id_lote);
+    w.text("indSinc", if sync { "1" } else { "0" });
+    let mut xml = w.into_string();
+    for nfe in signed {
+        xml.push_str(nfe);
+    }
+    xml.push_str("</enviNFe>");
+    xml
+}
+
+pub fn cons_reci_nfe(tp_amb: &str, n_rec: &str) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "consReciNFe",
+        &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+    );
+    w.text("tpAmb", tp_amb);
+    w.text("nRec", n_rec);
+    w.end("consReciNFe");
+    w.into_string()
+}
+
+/// Reads the `protNFe` of one note, keeping the element as received.
+pub fn parse_prot_nfe(xml: &str, prot: Node) -> Result<ProtNFe, SefazError> {
+    let inf = prot
+        .children()
+        .find(|n| n.has_tag_name((NFE_NAMESPACE, "infProt")))
+        .ok_or_else(|| SefazError::InvalidResponse("protNFe without infProt".to_string()))?;
+    Ok(ProtNFe {
+        tp_amb: required(inf, "tpAmb")?,
+        ver_aplic: required(inf, "verAplic")?,
+        ch_nfe: required(inf, "chNFe")?,
+        dh_recbto: parse_timestamp(&required(inf, "dhRecbto")?)?,
+        n_prot: child_text(inf, "nProt"),
+        dig_val: child_text(inf, "digVal"),
+        c_stat: required(inf, "cStat")?,
+        x_motivo: required(inf, "xMotivo")?,
+        xml: soap::standalone(xml, prot),
+    })
+}
+
+fn parse_reply(response: &str) -> Result<BatchReply, SefazError> {
+    let document = Document::parse(response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    let protocols = reply
+        .children()
+        .filter(|n| n.has_tag_name((NFE_NAMESPACE, "protNFe")))
+        .map(|n| parse_prot_nfe(response, n))
+        .collect::<Result<Vec<_>, _>>()?;
+    let n_rec = child_text(reply, "nRec").or_else(|| {
+        reply
+            .children()
+            .find(|n| n.has_tag_name((NFE_NAMESPACE, "infRec")))
+            .and_then(|n| child_text(n, "nRec"))
+    });
+    Ok(BatchReply {
+        c_stat: required(reply, "cStat")?,
+        x_motivo: required(reply, "xMotivo")?,
+        n_rec,
+        protocols,
+    })
+}
+
+fn rejected(reply: BatchReply) -> SefazError {
+    SefazError::Rejected {
+        c_stat: reply.c_stat,
+        x_motivo: reply.x_motivo,
+    }
+}
+
+/// Sends a batch of signed notes of one state. Synchronous batches are answered with
+/// the protocols; otherwise, or when the state only processes asynchronously, the
+/// receipt is polled.
+#[instrument(skip(client, signed), fields(notes = signed.len()))]
+pub async fn authorize(
+    client: &SoapClient,
+    c_uf: &str,
+    tp_amb: &str,
+    id_lote: &str,
+    sync: bool,
+    signed: &[String],
+) -> Result<BatchOutcome, SefazError> {
+    info!("Sending NFe batch to SEFAZ");
+
+    let response = client
+        .call(
+            c_uf,
+            Service::NFeAutorizacao4,
+            &envi_nfe(id_lote, sync, signed),
+        )
+        .await?;
+    let reply = parse_reply(&response)?;
+    match reply.c_stat.as_str() {
+        BATCH_PROCESSED => Ok(BatchOutcome::Processed(reply.protocols)),
+        BATCH_RECEIVED => {
+            let n_rec = reply.n_rec.ok_or_else(|| {
+                SefazError::InvalidResponse("batch received without nRec".to_string())
+            })?;
+            poll(client, c_uf, tp_amb, &n_rec).await
+        }
+        _ => Err(rejected(reply)),
+    }
+}
+
+/// Queries the receipt of an asynchronous batch until it is processed or the configured
+/// number of polls runs out.
+#[instrument(skip(client))]
+pub async fn poll(
+    client: &SoapClient,
+    c_uf: &str,
+    tp_amb: &str,
+    n_rec: &str,
+) -> Result<BatchOutcome, SefazError> {
+    let config = client.config();
+    for attempt in 1..=config.max_polls {
+        tokio::time::sleep(Duration::from_millis(config.poll_interval_ms)).await;
+        let response = client
+            .call(
+                c_uf,
+                Service::NFeRetAutorizacao4,
+                &cons_reci_nfe(tp_amb, n_rec),
+            )
+            .await?;
+        let reply = parse_reply(&response)?;
+        match reply.c_stat.as_str() {
+            BATCH_PROCESSED => return Ok(BatchOutcome::Processed(reply.protocols)),
+            BATCH_IN_PROGRESS => info!("Batch {} in progress (attempt {})", n_rec, attempt),
+            _ => return Err(rejected(reply)),
+        }
+    }
+    warn!(
+        "Batch {} still in progress after {} polls",
+        n_rec, config.max_polls
+    );
+    Ok(BatchOutcome::Pending {
+        n_rec: n_rec.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::signature::tests::test_signer;
+
+    fn signed_sample() -> (String, String) {
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signed = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+        (signed, key)
+    }
+
+    async fn mock_client(max_polls: u32) -> (SoapClient, MockSefaz) {
+        let mock = MockSefaz::start("1").await.unwrap();
+        let config = SefazConfig {
+            poll_interval_ms: 10,
+            max_polls,
+            ..SefazConfig::single_server(mock.url(), "1")
+        };
+        (SoapClient::new(config, None).unwrap(), mock)
+    }
+
+    #[test]
+    fn builds_batch_messages() {
+        assert_eq!(
+            envi_nfe("1", true, &["<NFe/>".to_string()]),
+            "<enviNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"><idLote>1</idLote><indSinc>1</indSinc><NFe/></enviNFe>"
+        );
+        assert_eq!(
+            cons_reci_nfe("2", "351000000000001"),
+            "<consReciNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"><tpAmb>2</tpAmb><nRec>351000000000001</nRec></consReciNFe>"
+        );
+    }
+
+    #[test]
+    fn parses_prefixed_replies() {
+        let response = concat!(
+            "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\"><soap:Body>",
+            "<nfeResultMsg xmlns=\"http://www.portalfiscal.inf.br/nfe/wsdl/NFeAutorizacao4\" ",
+            "xmlns:ns2=\"http://www.portalfiscal.inf.br/nfe\">",
+            "<ns2:retEnviNFe versao=\"4.00\"><ns2:tpAmb>2</ns2:tpAmb>",
+            "<ns2:cStat>104</ns2:cStat><ns2:xMotivo>Lote processado</ns2:xMotivo>",
+            "<ns2:protNFe versao=\"4.00\"><ns2:infProt><ns2:tpAmb>2</ns2:tpAmb>",
+            "<ns2:verAplic>SP_NFE_PL009_V4</ns2:verAplic>",
+            "<ns2:chNFe>35240312345678000195550010000000011000000013</ns2:chNFe>",
+            "<ns2:dhRecbto>2024-03-01T10:00:00-03:00</ns2:dhRecbto>",
+            "<ns2:nProt>135240000000001</ns2:nProt><ns2:cStat>100</ns2:cStat>",
+            "<ns2:xMotivo>Autorizado o uso da NF-e</ns2:xMotivo></ns2:infProt></ns2:protNFe>",
+            "</ns2:retEnviNFe></nfeResultMsg></soap:Body></soap:Envelope>"
+        );
+        let reply = parse_reply(response).unwrap();
+        assert_eq!(reply.c_stat, "104");
+        let prot = &reply.protocols[0];
+        assert_eq!(prot.c_stat, "100");
+        assert_eq!(prot.n_prot.as_deref(), Some("135240000000001"));
+        assert!(prot.xml.starts_with(
+            "<ns2:protNFe xmlns:ns2=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\">"
+        ));
+        assert!(!prot.xml.contains("soap"));
+
+        let stored = Document::parse(&prot.xml).unwrap();
+        let stored = stored.root_element();
+        assert!(stored.has_tag_name((NFE_NAMESPACE, "protNFe")));
+        assert_eq!(parse_prot_nfe(&prot.xml, stored).unwrap().c_stat, "100");
+    }
+
+    #[actix_web::test]
+    async fn authorizes_synchronous_batches() {
+        let (client, mock) = mock_client(3).await;
+        let (signed, key) = signed_sample();
+
+        let outcome = authorize(&client, "35", "1", "1", true, std::slice::from_ref(&signed))
+            .await
+            .unwrap();
+        let BatchOutcome::Processed(protocols) = outcome else {
+            panic!("expected a processed batch");
+        };
+        assert_eq!(protocols.len(), 1);
+        let prot = &protocols[0];
+        assert_eq!(prot.c_stat, "100");
+        assert_eq!(prot.ch_nfe, key);
+        assert_eq!(prot.n_prot.as_deref().map(str::len), Some(15));
+        let digest = signed
+            .split("<DigestValue>")
+            .nth(1)
+            .and_then(|s| s.split('<').next())
+            .unwrap();
+        assert_eq!(prot.dig_val.as_deref(), Some(digest));
+        assert!(prot
+            .xml
+            .starts_with("<protNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\""));
+
+        // The same key again is a duplicate.
+        let BatchOutcome::Processed(protocols) =
+            authorize(&client, "35", "1", "2", true, &[signed])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        assert_eq!(protocols[0].c_stat, "204");
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn polls_asynchronous_batches() {
+        let (client, mock) = mock_client(3).await;
+        let (signed, _) = signed_sample();
+        let BatchOutcome::Processed(protocols) =
+            authorize(&client, "35", "1", "1", false, &[signed])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        assert_eq!(protocols[0].c_stat, "100");
+        mock.stop().await;
+
+        let (client, mock) = mock_client(1).await;
+        let (signed, _) = signed_sample();
+        let outcome = authorize(&client, "35", "1", "1", false, &[signed])
+            .await
+            .unwrap();
+        assert!(matches!(outcome, BatchOutcome::Pending { .. }));
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn reports_rejections() {
+        let (client, mock) = mock_client(1).await;
+        let (signed, _) = signed_sample();
+        let tampered = signed.replace("<vNF>100.00</vNF>", "<vNF>10.00</vNF>");
+        let BatchOutcome::Processed(protocols) =
+            authorize(&client, "35", "1", "1", true, &[tampered])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        assert_eq!(protocols[0].c_stat, "297");
+
+        let empty = authorize(&client, "35", "1", "1", true, &[]).await;
+        assert!(matches!(empty, Err(SefazError::Rejected { ref c_stat, .. }) if c_stat == "225"));
+
+        let unknown = poll(&client, "35", "1", "350000000000000").await;
+        assert!(matches!(unknown, Err(SefazError::Rejected { ref c_stat, .. }) if c_stat == "106"));
+        mock.stop().await;
+    }
+}
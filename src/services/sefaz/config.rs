@@ -0,0 +1,249 @@
+use crate::errors::SefazError;
+use crate::models::nfe_access_key::UF_CODES;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Web services of the NF-e, named as in their WSDLs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Service {
+    NFeAutorizacao4,
+    NFeRetAutorizacao4,
+    NFeConsultaProtocolo4,
+    NFeRecepcaoEvento4,
+    NFeInutilizacao4,
+    NFeStatusServico4,
+    NFeDistribuicaoDFe,
+}
+
+impl Service {
+    pub const ALL: [Service; 7] = [
+        Service::NFeAutorizacao4,
+        Service::NFeRetAutorizacao4,
+        Service::NFeConsultaProtocolo4,
+        Service::NFeRecepcaoEvento4,
+        Service::NFeInutilizacao4,
+        Service::NFeStatusServico4,
+        Service::NFeDistribuicaoDFe,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Service::NFeAutorizacao4 => "NFeAutorizacao4",
+            Service::NFeRetAutorizacao4 => "NFeRetAutorizacao4",
+            Service::NFeConsultaProtocolo4 => "NFeConsultaProtocolo4",
+            Service::NFeRecepcaoEvento4 => "NFeRecepcaoEvento4",
+            Service::NFeInutilizacao4 => "NFeInutilizacao4",
+            Service::NFeStatusServico4 => "NFeStatusServico4",
+            Service::NFeDistribuicaoDFe => "NFeDistribuicaoDFe",
+        }
+    }
+
+    /// WSDL operation, which completes the SOAP action.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Service::NFeAutorizacao4 => "nfeAutorizacaoLote",
+            Service::NFeRetAutorizacao4 => "nfeRetAutorizacaoLote",
+            Service::NFeConsultaProtocolo4 => "nfeConsultaNF",
+            Service::NFeRecepcaoEvento4 => "nfeRecepcaoEvento",
+            Service::NFeInutilizacao4 => "nfeInutilizacaoNF",
+            Service::NFeStatusServico4 => "nfeStatusServicoNF",
+            Service::NFeDistribuicaoDFe => "nfeDistDFeInteresse",
+        }
+    }
+
+    /// Namespace of `nfeDadosMsg` and `nfeResultMsg`.
+    pub fn namespace(&self) -> String {
+        format!("http://www.portalfiscal.inf.br/nfe/wsdl/{}", self.name())
+    }
+
+    pub fn from_name(name: &str) -> Option<Service> {
+        Service::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+/// Authorizer of the SEFAZ Virtual de Contingência Ambiente Nacional (tpEmis 6).
+pub const SVC_AN: &str = "SVC-AN";
+/// Authorizer of the SEFAZ Virtual de Contingência Rio Grande do Sul (tpEmis 7).
+pub const SVC_RS: &str = "SVC-RS";
+
+/// Key of the endpoints of the Ambiente Nacional, which distributes the documents of
+/// every state.
+pub const AN: &str = "AN";
+
+/// Prefix of the key of the NFC-e endpoints of a state (`NFCe-35`), which most states
+/// run apart from the NF-e ones.
+pub const NFCE_PREFIX: &str = "NFCe-";
+
+/// Key of the endpoints that authorize a note: the virtual contingency SEFAZ for
+/// tpEmis 6 and 7, the NFC-e endpoints of the state for model 65 and the state itself
+/// otherwise.
+pub fn authorizer(c_uf: &str, mod_: &str, tp_emis: &str) -> String {
+    match tp_emis {
+        "6" => SVC_AN.to_string(),
+        "7" => SVC_RS.to_string(),
+        _ if mod_ == "65" => format!("{}{}", NFCE_PREFIX, c_uf),
+        _ => c_uf.to_string(),
+    }
+}
+
+/// Environment and endpoints used for the notes of one state (cUF).
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateConfig {
+    /// 1 = production, 2 = homologation. Notes with another `tpAmb` are not sent.
+    #[serde(rename = "tpAmb")]
+    pub tp_amb: String,
+    pub services: HashMap<Service, String>,
+    /// Consultation pages of the NFC-e, printed in the QR code (`qrCode`) and as text
+    /// (`urlChave`). Only the `NFCe-` entries have them.
+    #[serde(rename = "qrCode")]
+    pub qr_code: Option<String>,
+    #[serde(rename = "urlChave")]
+    pub url_chave: Option<String>,
+}
+
+/// SEFAZ endpoints per state, read from a JSON file keyed by cUF.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SefazConfig {
+    /// Wait between receipt queries of an asynchronous batch.
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+    #[serde(default = "default_max_polls")]
+    pub max_polls: u32,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Consecutive transport failures of an authorizer that switch its notes to
+    /// contingency.
+    #[serde(default = "default_contingency_after_failures")]
+    pub contingency_after_failures: u32,
+    /// Wait between status queries of authorizers in automatic contingency.
+    #[serde(default = "default_contingency_check_secs")]
+    pub contingency_check_secs: u64,
+    /// Wait between NFeDistribuicaoDFe runs. SEFAZ refuses queries made within an hour
+    /// of one that found no new document.
+    #[serde(default = "default_distribution_interval_secs")]
+    pub distribution_interval_secs: u64,
+    /// PEM bundle with the ICP-Brasil chain, trusted on top of the system roots.
+    pub ca_file: Option<PathBuf>,
+    /// Keyed by cUF, plus `NFCe-` and the cUF for the NFC-e, `SVC-AN` and `SVC-RS` for
+    /// the contingency authorizers and `AN` for the Ambiente Nacional.
+    #[serde(default)]
+    pub states: HashMap<String, StateConfig>,
+}
+
+fn default_poll_interval_ms() -> u64 {
+    2000
+}
+
+fn default_max_polls() -> u32 {
+    5
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_contingency_after_failures() -> u32 {
+    3
+}
+
+fn default_contingency_check_secs() -> u64 {
+    300
+}
+
+fn default_distribution_interval_secs() -> u64 {
+    3600
+}
+
+impl Default for SefazConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: default_poll_interval_ms(),
+            max_polls: default_max_polls(),
+            timeout_secs: default_timeout_secs(),
+            contingency_after_failures: default_contingency_after_failures(),
+            contingency_check_secs: default_contingency_check_secs(),
+            distribution_interval_secs: default_distribution_interval_secs(),
+            ca_file: None,
+            states: HashMap::new(),
+        }
+    }
+}
+
+impl SefazConfig {
+    pub fn load(path: &Path) -> Result<Self, SefazError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            SefazError::NotConfigured(format!("cannot read {}: {}", path.display(), e))
+        })?;
+        serde_json::from_str(&content)
+            .map_err(|e| SefazError::NotConfigured(format!("invalid {}: {}", path.display(), e)))
+    }
+
+    /// Every state and service pointing to one server, as used with the mock SEFAZ.
+    pub fn single_server(base_url: &str, tp_amb: &str) -> Self {
+        let services: HashMap<Service, String> = Service::ALL
+            .into_iter()
+            .map(|service| (service, format!("{}/ws/{}", base_url, service.name())))
+            .collect();
+        let state = StateConfig {
+            tp_amb: tp_amb.to_string(),
+            services,
+            qr_code: None,
+            url_chave: None,
+        };
+        let nfce = StateConfig {
+            qr_code: Some(format!("{}/nfce/qrcode", base_url)),
+            url_chave: Some(format!("{}/nfce/consulta", base_url)),
+            ..state.clone()
+        };
+        let states = UF_CODES
+            .iter()
+            .map(|c_uf| (c_uf.to_string(), state.clone()))
+            .chain(
+                UF_CODES
+                    .iter()
+                    .map(|c_uf| (format!("{}{}", NFCE_PREFIX, c_uf), nfce.clone())),
+            )
+            .chain([SVC_AN, SVC_RS, AN].map(|key| (key.to_string(), state.clone())))
+            .collect();
+        Self {
+            states,
+            ..Self::default()
+        }
+    }
+
+    pub fn state(&self, c_uf: &str) -> Result<&StateConfig, SefazError> {
+        self.states.get(c_uf).ok_or_else(|| {
+            SefazError::NotConfigured(format!("no SEFAZ endpoints configured for cUF {}", c_uf))
+        })
+    }
+
+    /// `qrCode` and `urlChave` URLs of the NFC-e of the state.
+    pub fn nfce_urls(&self, c_uf: &str) -> Result<(&str, &str), SefazError> {
+        let key = format!("{}{}", NFCE_PREFIX, c_uf);
+        let state = self.state(&key)?;
+        match (&state.qr_code, &state.url_chave) {
+            (Some(qr_code), Some(url_chave)) => Ok((qr_code, url_chave)),
+            _ => Err(SefazError::NotConfigured(format!(
+                "no qrCode and urlChave configured for {}",
+                key
+            ))),
+        }
+    }
+
+    pub fn endpoint(&self, c_uf: &str, service: Service) -> Result<&str, SefazError> {
+        self.state(c_uf)?
+            .services
+            .get(&service)
+            .map(String::as_str)
+            .ok_or_else(|| {
+                SefazError::NotConfigured(format!(
+                    "no {} endpoint configured for cUF {}",
+                    service.name(),
+                    c_uf
+                ))
+            })
+    }
+}
@@ -0,0 +1,117 @@
+//! NFeConsultaProtocolo4: asks the authorizer for the situation of a note by its key,
+//! which recovers the `protNFe` of a note whose answer was lost or that SEFAZ reports
+//! as a duplicate.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::authorization::{parse_prot_nfe, ProtNFe};
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::soap::{self, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use roxmltree::Document;
+use tracing::{info, instrument};
+
+/// The key is not in the base of the authorizer.
+pub const NOT_FOUND: &str = "217";
+
+/// Status part of `retConsSitNFe`, with the `protNFe` of the note when SEFAZ has
+/// processed it.
+#[derive(Debug, Clone)]
+pub struct RetConsSitNFe {
+    pub c_stat: String,
+    pub x_motivo: String,
+    pub prot_nfe: Option<ProtNFe>,
+}
+
+pub fn cons_sit_nfe(tp_amb: &str, ch_nfe: &str) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "consSitNFe",
+        &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+    );
+    w.text("tpAmb", tp_amb);
+    w.text("xServ", "CONSULTAR");
+    w.text("chNFe", ch_nfe);
+    w.end("consSitNFe");
+    w.into_string()
+}
+
+fn parse_reply(response: &str) -> Result<RetConsSitNFe, SefazError> {
+    let document = Document::parse(response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    let prot_nfe = reply
+        .children()
+        .find(|n| n.has_tag_name((NFE_NAMESPACE, "protNFe")))
+        .map(|n| parse_prot_nfe(response, n))
+        .transpose()?;
+    Ok(RetConsSitNFe {
+        c_stat: required(reply, "cStat")?,
+        x_motivo: required(reply, "xMotivo")?,
+        prot_nfe,
+    })
+}
+
+/// Queries the situation of the note `ch_nfe` at `authorizer`.
+#[instrument(skip(client))]
+pub async fn query(
+    client: &SoapClient,
+    authorizer: &str,
+    tp_amb: &str,
+    ch_nfe: &str,
+) -> Result<RetConsSitNFe, SefazError> {
+    info!("Querying the situation of the NFe at SEFAZ");
+
+    let response = client
+        .call(
+            authorizer,
+            Service::NFeConsultaProtocolo4,
+            &cons_sit_nfe(tp_amb, ch_nfe),
+        )
+        .await?;
+    parse_reply(&response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::sefaz::authorization::{authorize, BatchOutcome};
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::signature::tests::test_signer;
+
+    #[actix_web::test]
+    async fn recovers_the_protocol_of_a_note() {
+        assert_eq!(
+            cons_sit_nfe("2", "35240312345678000195550010000000011000000013"),
+            "<consSitNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"><tpAmb>2</tpAmb><xServ>CONSULTAR</xServ><chNFe>35240312345678000195550010000000011000000013</chNFe></consSitNFe>"
+        );
+
+        let mock = MockSefaz::start("1").await.unwrap();
+        let client = SoapClient::new(SefazConfig::single_server(mock.url(), "1"), None).unwrap();
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signed = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+
+        let unknown = query(&client, "35", "1", &key).await.unwrap();
+        assert_eq!(unknown.c_stat, NOT_FOUND);
+        assert!(unknown.prot_nfe.is_none());
+
+        let BatchOutcome::Processed(protocols) =
+            authorize(&client, "35", "1", "1", true, &[signed])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        let found = query(&client, "35", "1", &key).await.unwrap();
+        assert_eq!(found.c_stat, "100");
+        let prot = found.prot_nfe.unwrap();
+        assert_eq!(prot.ch_nfe, key);
+        assert_eq!(prot.n_prot, protocols[0].n_prot);
+        mock.stop().await;
+    }
+}
@@ -0,0 +1,270 @@
+//! NFeDistribuicaoDFe: the documents of the Ambiente Nacional that involve a CNPJ, read
+//! in NSU order. Each comes as a `docZip`, gzip compressed and base64 encoded: the
+//! summary of a note issued to the CNPJ (`resNFe`), the authorized note itself
+//! (`procNFe`) or an event of one of them.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::config::{Service, AN};
+use crate::services::sefaz::soap::{self, child_text, parse_timestamp, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::NFE_NAMESPACE;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use roxmltree::Document;
+use rust_decimal::Decimal;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use tracing::{info, instrument};
+
+pub const DIST_VERSION: &str = "1.01";
+/// Documents found after the NSU.
+pub const FOUND: &str = "138";
+/// No document after the NSU.
+pub const NOTHING_FOUND: &str = "137";
+/// Queries too frequent; SEFAZ refuses the CNPJ for an hour.
+pub const TOO_MANY_QUERIES: &str = "656";
+
+/// NSU as written in the layout, in 15 digits.
+pub fn nsu(value: u64) -> String {
+    format!("{:015}", value)
+}
+
+fn parse_nsu(value: &str) -> Result<u64, SefazError> {
+    value
+        .parse()
+        .map_err(|_| SefazError::InvalidResponse(format!("invalid NSU {}", value)))
+}
+
+/// One document of `loteDistDFeInt`, decompressed.
+#[derive(Debug, Clone)]
+pub struct DocZip {
+    pub nsu: u64,
+    /// Schema of the document, e.g. `resNFe_v1.01.xsd`.
+    pub schema: String,
+    pub xml: String,
+}
+
+impl DocZip {
+    /// Layout of the document without its version: `resNFe`, `procNFe`, `resEvento` or
+    /// `procEventoNFe`.
+    pub fn kind(&self) -> &str {
+        self.schema.split('_').next().unwrap_or(&self.schema)
+    }
+
+    /// Access key of the note the document refers to.
+    pub fn ch_nfe(&self) -> Option<String> {
+        let document = Document::parse(&self.xml).ok()?;
+        let ch_nfe = document
+            .descendants()
+            .find(|n| n.has_tag_name((NFE_NAMESPACE, "chNFe")))
+            .and_then(|n| n.text())
+            .map(|text| text.trim().to_string());
+        ch_nfe
+    }
+}
+
+/// `retDistDFeInt`.
+#[derive(Debug, Clone)]
+pub struct RetDistDFeInt {
+    pub c_stat: String,
+    pub x_motivo: String,
+    /// Last NSU of this answer, from which the next query starts.
+    pub ult_nsu: u64,
+    /// Highest NSU of the CNPJ so far.
+    pub max_nsu: u64,
+    pub documents: Vec<DocZip>,
+}
+
+impl RetDistDFeInt {
+    /// Whether every document of the CNPJ has been read.
+    pub fn is_caught_up(&self) -> bool {
+        self.c_stat != FOUND || self.ult_nsu >= self.max_nsu
+    }
+}
+
+/// `resNFe`: the summary of a note issued to the CNPJ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResNFe {
+    pub ch_nfe: String,
+    /// CNPJ or CPF of the emitter.
+    pub emit_doc: String,
+    pub x_nome: String,
+    pub ie: Option<String>,
+    pub dh_emi: DateTime<Utc>,
+    pub tp_nf: String,
+    pub v_nf: Decimal,
+    pub dig_val: Option<String>,
+    pub dh_recbto: DateTime<Utc>,
+    pub n_prot: Option<String>,
+    /// 1 = authorized, 2 = denied, 3 = cancelled.
+    pub c_sit_nfe: String,
+}
+
+pub fn dist_dfe_int(tp_amb: &str, cnpj: &str, ult_nsu: u64) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "distDFeInt",
+        &[("xmlns", NFE_NAMESPACE), ("versao", DIST_VERSION)],
+    );
+    w.text("tpAmb", tp_amb);
+    w.text("CNPJ", cnpj);
+    w.start("distNSU");
+    w.text("ultNSU", &nsu(ult_nsu));
+    w.end("distNSU");
+    w.end("distDFeInt");
+    w.into_string()
+}
+
+/// Content of a `docZip`.
+pub fn unzip(content: &str) -> Result<String, SefazError> {
+    let compressed = STANDARD
+        .decode(content.trim())
+        .map_err(|e| SefazError::InvalidResponse(format!("docZip is not base64: {}", e)))?;
+    let mut xml = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut xml)
+        .map_err(|e| SefazError::InvalidResponse(format!("docZip is not gzip: {}", e)))?;
+    Ok(xml)
+}
+
+/// A document as `docZip` carries it.
+pub fn zip(xml: &str) -> String {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(xml.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map(|compressed| STANDARD.encode(compressed))
+        .expect("compressing in memory does not fail")
+}
+
+pub fn parse_res_nfe(xml: &str) -> Result<ResNFe, SefazError> {
+    let document = Document::parse(xml)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed resNFe: {}", e)))?;
+    let res = document.root_element();
+    let v_nf = required(res, "vNF")?;
+    Ok(ResNFe {
+        ch_nfe: required(res, "chNFe")?,
+        emit_doc: child_text(res, "CNPJ")
+            .or_else(|| child_text(res, "CPF"))
+            .ok_or_else(|| SefazError::InvalidResponse("resNFe without CNPJ".to_string()))?,
+        x_nome: required(res, "xNome")?,
+        ie: child_text(res, "IE"),
+        dh_emi: parse_timestamp(&required(res, "dhEmi")?)?,
+        tp_nf: required(res, "tpNF")?,
+        v_nf: Decimal::from_str(&v_nf)
+            .map_err(|_| SefazError::InvalidResponse(format!("invalid vNF {}", v_nf)))?,
+        dig_val: child_text(res, "digVal"),
+        dh_recbto: parse_timestamp(&required(res, "dhRecbto")?)?,
+        n_prot: child_text(res, "nProt"),
+        c_sit_nfe: required(res, "cSitNFe")?,
+    })
+}
+
+/// Asks the Ambiente Nacional for the documents of the CNPJ after `ult_nsu`, at most
+/// 50 per answer.
+#[instrument(skip(client))]
+pub async fn query(
+    client: &SoapClient,
+    tp_amb: &str,
+    cnpj: &str,
+    ult_nsu: u64,
+) -> Result<RetDistDFeInt, SefazError> {
+    info!("Querying NFeDistribuicaoDFe");
+
+    let response = client
+        .call(
+            AN,
+            Service::NFeDistribuicaoDFe,
+            &dist_dfe_int(tp_amb, cnpj, ult_nsu),
+        )
+        .await?;
+    let document = Document::parse(&response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    let documents = reply
+        .children()
+        .filter(|n| n.has_tag_name((NFE_NAMESPACE, "loteDistDFeInt")))
+        .flat_map(|lote| lote.children())
+        .filter(|n| n.has_tag_name((NFE_NAMESPACE, "docZip")))
+        .map(|doc| {
+            Ok(DocZip {
+                nsu: parse_nsu(doc.attribute("NSU").unwrap_or_default())?,
+                schema: doc.attribute("schema").unwrap_or_default().to_string(),
+                xml: unzip(doc.text().unwrap_or_default())?,
+            })
+        })
+        .collect::<Result<Vec<_>, SefazError>>()?;
+    Ok(RetDistDFeInt {
+        c_stat: required(reply, "cStat")?,
+        x_motivo: required(reply, "xMotivo")?,
+        ult_nsu: parse_nsu(&required(reply, "ultNSU")?)?,
+        max_nsu: parse_nsu(&required(reply, "maxNSU")?)?,
+        documents,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::sefaz::authorization::{self, BatchOutcome};
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::signature::tests::test_signer;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn builds_the_query_and_reads_doc_zip() {
+        assert_eq!(
+            dist_dfe_int("2", "98765432000198", 42),
+            "<distDFeInt xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"1.01\"><tpAmb>2</tpAmb><CNPJ>98765432000198</CNPJ><distNSU><ultNSU>000000000000042</ultNSU></distNSU></distDFeInt>"
+        );
+        let xml = "<resNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\"/>";
+        assert_eq!(unzip(&zip(xml)).unwrap(), xml);
+        assert!(unzip("bm90IGd6aXA=").is_err());
+    }
+
+    #[actix_web::test]
+    async fn distributes_notes_issued_to_the_cnpj() {
+        let mock = MockSefaz::start("1").await.unwrap();
+        let client = SoapClient::new(SefazConfig::single_server(mock.url(), "1"), None).unwrap();
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signed = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+        let outcome = authorization::authorize(&client, "35", "1", "1", true, &[signed])
+            .await
+            .unwrap();
+        assert!(matches!(outcome, BatchOutcome::Processed(_)));
+
+        let ret = query(&client, "1", "98765432000198", 0).await.unwrap();
+        assert_eq!(ret.c_stat, FOUND, "{}", ret.x_motivo);
+        assert!(ret.is_caught_up());
+        let kinds: Vec<&str> = ret.documents.iter().map(DocZip::kind).collect();
+        assert_eq!(kinds, vec!["resNFe", "procNFe"]);
+        assert!(ret
+            .documents
+            .iter()
+            .all(|doc| doc.ch_nfe() == Some(key.clone())));
+        let summary = parse_res_nfe(&ret.documents[0].xml).unwrap();
+        assert_eq!(summary.emit_doc, "12345678000195");
+        assert_eq!(summary.v_nf, dec!(100.00));
+        assert_eq!(summary.c_sit_nfe, "1");
+        assert!(ret.documents[1].xml.starts_with("<nfeProc"));
+
+        // Nothing after the last NSU, and nothing for another CNPJ.
+        let again = query(&client, "1", "98765432000198", ret.ult_nsu)
+            .await
+            .unwrap();
+        assert_eq!(again.c_stat, NOTHING_FOUND);
+        assert_eq!(again.ult_nsu, ret.ult_nsu);
+        let other = query(&client, "1", "11111111000191", 0).await.unwrap();
+        assert_eq!(other.c_stat, NOTHING_FOUND);
+        mock.stop().await;
+    }
+}
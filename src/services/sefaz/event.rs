@@ -0,0 +1,342 @@
+//! NFeRecepcaoEvento4: registers events of an authorized note, such as its cancellation,
+//! and joins each signed `evento` with its `retEvento` into `procEventoNFe`.
+
+use crate::errors::SefazError;
+use crate::models::nfe_event::REGISTERED;
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::soap::{self, child_text, parse_timestamp, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::NFE_NAMESPACE;
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use roxmltree::{Document, Node};
+use tracing::{info, instrument};
+
+/// Version of `envEvento`, `evento` and the `detEvento` layouts.
+pub const EVENT_VERSION: &str = "1.00";
+/// Batch of events processed; each event has its `retEvento`.
+pub const BATCH_PROCESSED: &str = "128";
+
+/// `evento` of one note, before it is signed.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub c_orgao: String,
+    pub tp_amb: String,
+    /// CNPJ, or CPF when it has 11 digits.
+    pub author: String,
+    pub ch_nfe: String,
+    pub dh_evento: DateTime<FixedOffset>,
+    pub tp_evento: String,
+    pub n_seq_evento: u32,
+    pub desc_evento: String,
+    /// Elements of `detEvento` after `descEvento`, in layout order.
+    pub details: Vec<(&'static str, String)>,
+}
+
+impl Event {
+    /// `Id` of `infEvento`, the reference of the signature.
+    pub fn id(&self) -> String {
+        format!(
+            "ID{}{}{:02}",
+            self.tp_evento, self.ch_nfe, self.n_seq_evento
+        )
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut w = XmlWriter::new();
+        w.start_with(
+            "evento",
+            &[("xmlns", NFE_NAMESPACE), ("versao", EVENT_VERSION)],
+        );
+        w.start_with("infEvento", &[("Id", &self.id())]);
+        w.text("cOrgao", &self.c_orgao);
+        w.text("tpAmb", &self.tp_amb);
+        let author = if self.author.len() == 11 {
+            "CPF"
+        } else {
+            "CNPJ"
+        };
+        w.text(author, &self.author);
+        w.text("chNFe", &self.ch_nfe);
+        w.text(
+            "dhEvento",
+            &self.dh_evento.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+        w.text("tpEvento", &self.tp_evento);
+        w.text("nSeqEvento", &self.n_seq_evento.to_string());
+        w.text("verEvento", EVENT_VERSION);
+        w.start_with("detEvento", &[("versao", EVENT_VERSION)]);
+        w.text("descEvento", &self.desc_evento);
+        for (tag, value) in &self.details {
+            w.text(tag, value);
+        }
+        w.end("detEvento");
+        w.end("infEvento");
+        w.end("evento");
+        w.into_string()
+    }
+}
+
+/// `infEvento` of a `retEvento`.
+#[derive(Debug, Clone)]
+pub struct RetEvento {
+    pub c_stat: String,
+    pub x_motivo: String,
+    pub ch_nfe: Option<String>,
+    pub dh_reg_evento: Option<DateTime<Utc>>,
+    pub n_prot: Option<String>,
+    /// The element as received, to be joined with the signed event.
+    pub xml: String,
+}
+
+impl RetEvento {
+    pub fn is_registered(&self) -> bool {
+        REGISTERED.contains(&self.c_stat.as_str())
+    }
+}
+
+/// `envEvento` with already signed events.
+pub fn env_evento(id_lote: &str, signed: &[String]) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "envEvento",
+        &[("xmlns", NFE_NAMESPACE), ("versao", EVENT_VERSION)],
+    );
+    w.text("idLote", id_lote);
+    let mut xml = w.into_string();
+    for evento in signed {
+        xml.push_str(evento);
+    }
+    xml.push_str("</envEvento>");
+    xml
+}
+
+/// `procEventoNFe`, the registered event as it must be kept and shared.
+pub fn proc_evento(signed: &str, ret_evento: &str) -> String {
+    format!(
+        "<procEventoNFe xmlns=\"{}\" versao=\"{}\">{}{}</procEventoNFe>",
+        NFE_NAMESPACE,
+        EVENT_VERSION,
+        signed.replacen(&format!(" xmlns=\"{}\"", NFE_NAMESPACE), "", 1),
+        ret_evento.replacen(&format!(" xmlns=\"{}\"", NFE_NAMESPACE), "", 1)
+    )
+}
+
+fn parse_ret_evento(xml: &str, ret: Node) -> Result<RetEvento, SefazError> {
+    let inf = ret
+        .children()
+        .find(|n| n.has_tag_name((NFE_NAMESPACE, "infEvento")))
+        .ok_or_else(|| SefazError::InvalidResponse("retEvento without infEvento".to_string()))?;
+    Ok(RetEvento {
+        c_stat: required(inf, "cStat")?,
+        x_motivo: required(inf, "xMotivo")?,
+        ch_nfe: child_text(inf, "chNFe"),
+        dh_reg_evento: child_text(inf, "dhRegEvento")
+            .as_deref()
+            .map(parse_timestamp)
+            .transpose()?,
+        n_prot: child_text(inf, "nProt"),
+        xml: soap::standalone(xml, ret),
+    })
+}
+
+/// Sends a batch of signed events to the service of the state and returns the
+/// `retEvento` of each one.
+#[instrument(skip(client, signed), fields(events = signed.len()))]
+pub async fn send(
+    client: &SoapClient,
+    c_uf: &str,
+    id_lote: &str,
+    signed: &[String],
+) -> Result<Vec<RetEvento>, SefazError> {
+    info!("Sending event batch to SEFAZ");
+
+    let response = client
+        .call(
+            c_uf,
+            Service::NFeRecepcaoEvento4,
+            &env_evento(id_lote, signed),
+        )
+        .await?;
+    let document = Document::parse(&response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    let c_stat = required(reply, "cStat")?;
+    if c_stat != BATCH_PROCESSED {
+        return Err(SefazError::Rejected {
+            c_stat,
+            x_motivo: required(reply, "xMotivo")?,
+        });
+    }
+    reply
+        .children()
+        .filter(|n| n.has_tag_name((NFE_NAMESPACE, "retEvento")))
+        .map(|n| parse_ret_evento(&response, n))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::models::nfe_event::{CONDITIONS_OF_USE, CORRECTION};
+    use crate::services::sefaz::authorization::{self, BatchOutcome};
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::signature::{self, tests::test_signer};
+
+    fn cancellation(ch_nfe: &str, n_prot: &str) -> Event {
+        Event {
+            c_orgao: "35".to_string(),
+            tp_amb: "1".to_string(),
+            author: "12345678000195".to_string(),
+            ch_nfe: ch_nfe.to_string(),
+            dh_evento: DateTime::parse_from_rfc3339("2024-03-27T10:00:00-03:00").unwrap(),
+            tp_evento: "110111".to_string(),
+            n_seq_evento: 1,
+            desc_evento: "Cancelamento".to_string(),
+            details: vec![
+                ("nProt", n_prot.to_string()),
+                ("xJust", "Pedido cancelado pelo cliente".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn builds_signed_events() {
+        let event = cancellation("35240312345678000195550010000000011000000013", "1");
+        assert_eq!(
+            event.id(),
+            "ID1101113524031234567800019555001000000001100000001301"
+        );
+        let xml = event.to_xml();
+        assert!(xml.contains("<CNPJ>12345678000195</CNPJ><chNFe>"));
+        assert!(xml.contains("<dhEvento>2024-03-27T10:00:00-03:00</dhEvento>"));
+        assert!(xml.contains(
+            "<detEvento versao=\"1.00\"><descEvento>Cancelamento</descEvento><nProt>1</nProt>"
+        ));
+
+        let signed = test_signer().sign(&xml, &event.id()).unwrap();
+        assert!(signature::verify(&signed).unwrap().is_some());
+        let proc = proc_evento(
+            &signed,
+            "<retEvento xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"1.00\"/>",
+        );
+        assert!(proc.starts_with("<procEventoNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"1.00\"><evento versao=\"1.00\">"));
+        assert!(proc.ends_with("</evento><retEvento versao=\"1.00\"/></procEventoNFe>"));
+        // The signature still holds once the namespace comes from procEventoNFe.
+        assert!(signature::verify(&proc).unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn registers_cancellations() {
+        let mock = MockSefaz::start("1").await.unwrap();
+        let client = SoapClient::new(SefazConfig::single_server(mock.url(), "1"), None).unwrap();
+
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let nfe = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+        let cancel = |n_prot: &str| {
+            let event = cancellation(&key, n_prot);
+            test_signer().sign(&event.to_xml(), &event.id()).unwrap()
+        };
+
+        // Unknown to SEFAZ until it is authorized.
+        let rets = send(&client, "35", "1", &[cancel("135240000000001")])
+            .await
+            .unwrap();
+        assert_eq!(rets[0].c_stat, "217");
+
+        let BatchOutcome::Processed(protocols) =
+            authorization::authorize(&client, "35", "1", "1", true, &[nfe])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        let n_prot = protocols[0].n_prot.clone().unwrap();
+
+        let rets = send(&client, "35", "2", &[cancel("135240000000001")])
+            .await
+            .unwrap();
+        assert_eq!(rets[0].c_stat, "222");
+
+        let rets = send(&client, "35", "3", &[cancel(&n_prot)]).await.unwrap();
+        let ret = &rets[0];
+        assert!(ret.is_registered(), "{:?}", ret);
+        assert_eq!(ret.ch_nfe.as_deref(), Some(key.as_str()));
+        assert!(ret.n_prot.is_some() && ret.dh_reg_evento.is_some());
+
+        let rets = send(&client, "35", "4", &[cancel(&n_prot)]).await.unwrap();
+        assert_eq!(rets[0].c_stat, "573");
+
+        let empty = send(&client, "35", "5", &[]).await;
+        assert!(matches!(empty, Err(SefazError::Rejected { ref c_stat, .. }) if c_stat == "225"));
+        mock.stop().await;
+    }
+
+    #[actix_web::test]
+    async fn registers_correction_letters_in_sequence() {
+        let mock = MockSefaz::start("1").await.unwrap();
+        let client = SoapClient::new(SefazConfig::single_server(mock.url(), "1"), None).unwrap();
+
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let nfe = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+        let BatchOutcome::Processed(protocols) =
+            authorization::authorize(&client, "35", "1", "1", true, &[nfe])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        let n_prot = protocols[0].n_prot.clone().unwrap();
+
+        let correct = |n_seq_evento: u32| {
+            let event = Event {
+                tp_evento: CORRECTION.to_string(),
+                n_seq_evento,
+                desc_evento: "Carta de Correcao".to_string(),
+                details: vec![
+                    ("xCorrecao", "Endereco de entrega: Rua B, 20".to_string()),
+                    ("xCondUso", CONDITIONS_OF_USE.to_string()),
+                ],
+                ..cancellation(&key, &n_prot)
+            };
+            assert!(event.id().ends_with(&format!("{:02}", n_seq_evento)));
+            test_signer().sign(&event.to_xml(), &event.id()).unwrap()
+        };
+        let c_stat = |rets: Vec<RetEvento>| rets[0].c_stat.clone();
+
+        assert_eq!(
+            c_stat(send(&client, "35", "1", &[correct(1)]).await.unwrap()),
+            "135"
+        );
+        assert_eq!(
+            c_stat(send(&client, "35", "2", &[correct(1)]).await.unwrap()),
+            "573"
+        );
+        assert_eq!(
+            c_stat(send(&client, "35", "3", &[correct(2)]).await.unwrap()),
+            "135"
+        );
+        assert_eq!(
+            c_stat(send(&client, "35", "4", &[correct(21)]).await.unwrap()),
+            "594"
+        );
+
+        let event = cancellation(&key, &n_prot);
+        let cancel = test_signer().sign(&event.to_xml(), &event.id()).unwrap();
+        assert_eq!(
+            c_stat(send(&client, "35", "5", &[cancel]).await.unwrap()),
+            "135"
+        );
+        assert_eq!(
+            c_stat(send(&client, "35", "6", &[correct(3)]).await.unwrap()),
+            "580"
+        );
+        mock.stop().await;
+    }
+}
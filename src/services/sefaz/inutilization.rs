@@ -0,0 +1,187 @@
+//! NFeInutilizacao4: voids a range of numbers skipped in a serie and joins the signed
+//! `inutNFe` with its `retInutNFe` into `ProcInutNFe`.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::soap::{self, child_text, parse_timestamp, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use chrono::{DateTime, Utc};
+use roxmltree::Document;
+use tracing::{info, instrument};
+
+/// `inutNFe` of one range, before it is signed.
+#[derive(Debug, Clone)]
+pub struct Inutilization {
+    pub c_uf: String,
+    pub tp_amb: String,
+    pub ano: String,
+    /// CNPJ, or CPF when it has 11 digits.
+    pub author: String,
+    pub mod_: String,
+    /// Serie without leading zeros.
+    pub serie: u32,
+    pub n_nf_ini: u32,
+    pub n_nf_fin: u32,
+    pub x_just: String,
+}
+
+impl Inutilization {
+    /// `Id` of `infInut`, the reference of the signature:
+    /// ID cUF(2) ano(2) CNPJ/CPF(14) mod(2) serie(3) nNFIni(9) nNFFin(9).
+    pub fn id(&self) -> String {
+        format!(
+            "ID{}{}{:0>14}{}{:03}{:09}{:09}",
+            self.c_uf, self.ano, self.author, self.mod_, self.serie, self.n_nf_ini, self.n_nf_fin
+        )
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut w = XmlWriter::new();
+        w.start_with(
+            "inutNFe",
+            &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+        );
+        w.start_with("infInut", &[("Id", &self.id())]);
+        w.text("tpAmb", &self.tp_amb);
+        w.text("xServ", "INUTILIZAR");
+        w.text("cUF", &self.c_uf);
+        w.text("ano", &self.ano);
+        let author = if self.author.len() == 11 {
+            "CPF"
+        } else {
+            "CNPJ"
+        };
+        w.text(author, &self.author);
+        w.text("mod", &self.mod_);
+        w.text("serie", &self.serie.to_string());
+        w.text("nNFIni", &self.n_nf_ini.to_string());
+        w.text("nNFFin", &self.n_nf_fin.to_string());
+        w.text("xJust", &self.x_just);
+        w.end("infInut");
+        w.end("inutNFe");
+        w.into_string()
+    }
+}
+
+/// `infInut` of a `retInutNFe`.
+#[derive(Debug, Clone)]
+pub struct RetInutNFe {
+    pub c_stat: String,
+    pub x_motivo: String,
+    pub dh_recbto: Option<DateTime<Utc>>,
+    pub n_prot: Option<String>,
+    /// The element as received, to be joined with the signed request.
+    pub xml: String,
+}
+
+/// `ProcInutNFe`, the homologated inutilização as it must be kept.
+pub fn proc_inut(signed: &str, ret_inut: &str) -> String {
+    format!(
+        "<ProcInutNFe xmlns=\"{}\" versao=\"{}\">{}{}</ProcInutNFe>",
+        NFE_NAMESPACE,
+        NFE_VERSION,
+        signed.replacen(&format!(" xmlns=\"{}\"", NFE_NAMESPACE), "", 1),
+        ret_inut.replacen(&format!(" xmlns=\"{}\"", NFE_NAMESPACE), "", 1)
+    )
+}
+
+/// Sends a signed `inutNFe` to the service of the state and returns its answer,
+/// homologated or not.
+#[instrument(skip(client, signed))]
+pub async fn send(client: &SoapClient, c_uf: &str, signed: &str) -> Result<RetInutNFe, SefazError> {
+    info!("Sending inutNFe to SEFAZ");
+
+    let response = client.call(c_uf, Service::NFeInutilizacao4, signed).await?;
+    let document = Document::parse(&response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    let inf = reply
+        .children()
+        .find(|n| n.has_tag_name((NFE_NAMESPACE, "infInut")))
+        .ok_or_else(|| SefazError::InvalidResponse("retInutNFe without infInut".to_string()))?;
+    Ok(RetInutNFe {
+        c_stat: required(inf, "cStat")?,
+        x_motivo: required(inf, "xMotivo")?,
+        dh_recbto: child_text(inf, "dhRecbto")
+            .as_deref()
+            .map(parse_timestamp)
+            .transpose()?,
+        n_prot: child_text(inf, "nProt"),
+        xml: soap::standalone(&response, reply),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::models::nfe_inutilization::HOMOLOGATED;
+    use crate::services::sefaz::authorization::{self, BatchOutcome};
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::signature::{self, tests::test_signer};
+
+    fn range(n_nf_ini: u32, n_nf_fin: u32) -> Inutilization {
+        Inutilization {
+            c_uf: "35".to_string(),
+            tp_amb: "1".to_string(),
+            ano: "24".to_string(),
+            author: "12345678000195".to_string(),
+            mod_: "55".to_string(),
+            serie: 1,
+            n_nf_ini,
+            n_nf_fin,
+            x_just: "Numeracao pulada por falha no sistema".to_string(),
+        }
+    }
+
+    #[test]
+    fn builds_signed_requests() {
+        let inut = range(10, 12);
+        assert_eq!(inut.id(), "ID35241234567800019555001000000010000000012");
+        let xml = inut.to_xml();
+        assert!(xml.contains("<xServ>INUTILIZAR</xServ><cUF>35</cUF><ano>24</ano><CNPJ>"));
+        assert!(xml.contains("<serie>1</serie><nNFIni>10</nNFIni><nNFFin>12</nNFFin>"));
+
+        let signed = test_signer().sign(&xml, &inut.id()).unwrap();
+        let proc = proc_inut(
+            &signed,
+            "<retInutNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"/>",
+        );
+        assert!(proc.starts_with("<ProcInutNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"><inutNFe versao=\"4.00\">"));
+        assert!(signature::verify(&proc).unwrap().is_some());
+    }
+
+    #[actix_web::test]
+    async fn voids_unused_ranges() {
+        let mock = MockSefaz::start("1").await.unwrap();
+        let client = SoapClient::new(SefazConfig::single_server(mock.url(), "1"), None).unwrap();
+        let inutilize = |inut: Inutilization| {
+            let signed = test_signer().sign(&inut.to_xml(), &inut.id()).unwrap();
+            let client = &client;
+            async move { send(client, "35", &signed).await.unwrap() }
+        };
+
+        let ret = inutilize(range(10, 12)).await;
+        assert_eq!(ret.c_stat, HOMOLOGATED, "{:?}", ret);
+        assert!(ret.n_prot.is_some() && ret.dh_recbto.is_some());
+        assert_eq!(inutilize(range(12, 20)).await.c_stat, "256");
+
+        // The sample note is number 123 of serie 1, issued in 2024.
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap().to_string();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let nfe = test_signer().sign(&xml, &format!("NFe{}", key)).unwrap();
+        let BatchOutcome::Processed(_) =
+            authorization::authorize(&client, "35", "1", "1", true, &[nfe])
+                .await
+                .unwrap()
+        else {
+            panic!("expected a processed batch");
+        };
+        assert_eq!(inutilize(range(100, 130)).await.c_stat, "241");
+        mock.stop().await;
+    }
+}
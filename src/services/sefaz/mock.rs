@@ -0,0 +1,716 @@
+//! In-process stand-in for the SEFAZ authorization and event services, so the
+//! transmission flow can run offline. It checks each note's signature, environment and
+//! duplicity, answers asynchronous batches on the second receipt query, reports the
+//! situation of the notes it received, registers
+//! cancellations and correction letters of the notes it authorized, voids number
+//! ranges none of them uses, distributes those notes to the CNPJ of their recipient and
+//! reports itself in operation.
+
+use crate::models::nfe_access_key::AccessKey;
+use crate::models::nfe_event::{CANCELLATION, CONDITIONS_OF_USE, CORRECTION, MAX_CORRECTIONS};
+use crate::models::nfe_identification::uf_offset;
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::distribution::{self, DIST_VERSION};
+use crate::services::sefaz::event::{self, EVENT_VERSION};
+use crate::services::sefaz::soap::{self, SOAP_NAMESPACE};
+use crate::services::xml::signature::{self, XMLDSIG_NAMESPACE};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use chrono::{SecondsFormat, Utc};
+use roxmltree::{Document, Node};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::Mutex;
+use tracing::info;
+
+const VER_APLIC: &str = "MOCK-4.00";
+/// Largest batch accepted by NFeAutorizacao4.
+const MAX_BATCH: usize = 50;
+/// Largest batch accepted by NFeRecepcaoEvento4.
+const MAX_EVENTS: usize = 20;
+/// Most documents in one answer of NFeDistribuicaoDFe.
+const MAX_DOC_ZIPS: usize = 50;
+
+/// ano, CNPJ/CPF, mod and serie of a numbering.
+type SerieKey = (String, String, String, u32);
+
+struct Receipt {
+    c_uf: String,
+    polls: u32,
+    protocols: Vec<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    tp_amb: String,
+    sequence: u64,
+    /// nProt of each authorized key.
+    authorized: HashMap<String, String>,
+    /// `protNFe` of each authorized key, answered to consultations.
+    protocols: HashMap<String, String>,
+    cancelled: HashSet<String>,
+    /// Last correction letter registered for each key.
+    corrections: HashMap<String, u32>,
+    receipts: HashMap<String, Receipt>,
+    /// Voided ranges of each serie.
+    voided: HashMap<SerieKey, Vec<(u32, u32)>>,
+    /// CNPJ, schema and XML of the documents to distribute; the NSU of each is its
+    /// position plus one.
+    distributed: Vec<(String, String, String)>,
+}
+
+pub struct MockSefaz {
+    url: String,
+    handle: ServerHandle,
+}
+
+impl MockSefaz {
+    /// Starts the server on a free local port, answering as the environment `tp_amb`.
+    pub async fn start(tp_amb: &str) -> io::Result<Self> {
+        let state = web::Data::new(Mutex::new(MockState {
+            tp_amb: tp_amb.to_string(),
+            ..MockState::default()
+        }));
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .route("/ws/{service}", web::post().to(handle))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))?;
+        let url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+        info!("Mock SEFAZ listening on {}", url);
+        Ok(Self { url, handle })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(true).await;
+    }
+}
+
+fn respond(service: Service, reply: String) -> HttpResponse {
+    let result = match service {
+        Service::NFeDistribuicaoDFe => format!(
+            "<{0}Response xmlns=\"{1}\"><{0}Result>{2}</{0}Result></{0}Response>",
+            service.operation(),
+            service.namespace(),
+            reply
+        ),
+        _ => format!(
+            "<nfeResultMsg xmlns=\"{}\">{}</nfeResultMsg>",
+            service.namespace(),
+            reply
+        ),
+    };
+    let body = format!(
+        "<soap12:Envelope xmlns:soap12=\"{}\"><soap12:Body>{}</soap12:Body></soap12:Envelope>",
+        SOAP_NAMESPACE, result
+    );
+    HttpResponse::Ok()
+        .content_type("application/soap+xml; charset=utf-8")
+        .body(body)
+}
+
+fn fault(reason: &str) -> HttpResponse {
+    let body = format!(
+        "<soap12:Envelope xmlns:soap12=\"{0}\"><soap12:Body><soap12:Fault><soap12:Code><soap12:Value>soap12:Sender</soap12:Value></soap12:Code><soap12:Reason><soap12:Text xml:lang=\"pt-BR\">{1}</soap12:Text></soap12:Reason></soap12:Fault></soap12:Body></soap12:Envelope>",
+        SOAP_NAMESPACE, reason
+    );
+    HttpResponse::InternalServerError()
+        .content_type("application/soap+xml; charset=utf-8")
+        .body(body)
+}
+
+async fn handle(
+    state: web::Data<Mutex<MockState>>,
+    service: web::Path<String>,
+    body: String,
+) -> HttpResponse {
+    let Some(service) = Service::from_name(&service) else {
+        return fault("unknown service");
+    };
+    let document = match Document::parse(&body) {
+        Ok(document) => document,
+        Err(e) => return fault(&format!("malformed XML: {}", e)),
+    };
+    let Some(message) = document
+        .descendants()
+        .find(|n| n.tag_name().name() == "nfeDadosMsg")
+        .and_then(|n| n.first_element_child())
+    else {
+        return fault("missing nfeDadosMsg");
+    };
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    let reply = match service {
+        Service::NFeAutorizacao4 => state.authorize(&body, message),
+        Service::NFeRetAutorizacao4 => state.receipt(message),
+        Service::NFeConsultaProtocolo4 => state.situation(message),
+        Service::NFeRecepcaoEvento4 => state.events(&body, message),
+        Service::NFeInutilizacao4 => state.inutilize(&body, message),
+        Service::NFeStatusServico4 => state.status(message),
+        Service::NFeDistribuicaoDFe => state.distribute(message),
+    };
+    respond(service, reply)
+}
+
+fn nfe_text(node: Node, path: &[&str]) -> String {
+    let mut current = Some(node);
+    for name in path {
+        current = current.and_then(|n| {
+            n.children()
+                .find(|c| c.has_tag_name((NFE_NAMESPACE, *name)))
+        });
+    }
+    current
+        .and_then(|n| n.text())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn dh_recbto(c_uf: &str) -> String {
+    Utc::now()
+        .with_timezone(&uf_offset(c_uf))
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn reply_header(w: &mut XmlWriter, tag: &str, tp_amb: &str) {
+    w.start_with(tag, &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)]);
+    w.text("tpAmb", tp_amb);
+    w.text("verAplic", VER_APLIC);
+}
+
+/// `resNFe` of a note this mock authorized.
+fn res_nfe(nfe: Node, ch_nfe: &str, n_prot: &str, dig_val: Option<&str>) -> String {
+    let field = |path: &[&str]| {
+        let mut full = vec!["infNFe"];
+        full.extend_from_slice(path);
+        nfe_text(nfe, &full)
+    };
+    let c_uf = field(&["ide", "cUF"]);
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "resNFe",
+        &[("xmlns", NFE_NAMESPACE), ("versao", DIST_VERSION)],
+    );
+    w.text("chNFe", ch_nfe);
+    let cnpj = field(&["emit", "CNPJ"]);
+    if cnpj.is_empty() {
+        w.text("CPF", &field(&["emit", "CPF"]));
+    } else {
+        w.text("CNPJ", &cnpj);
+    }
+    w.text("xNome", &field(&["emit", "xNome"]));
+    w.text("IE", &field(&["emit", "IE"]));
+    w.text("dhEmi", &field(&["ide", "dhEmi"]));
+    w.text("tpNF", &field(&["ide", "tpNF"]));
+    w.text("vNF", &field(&["total", "ICMSTot", "vNF"]));
+    w.opt_text("digVal", dig_val);
+    w.text("dhRecbto", &dh_recbto(&c_uf));
+    w.text("nProt", n_prot);
+    w.text("cSitNFe", "1");
+    w.end("resNFe");
+    w.into_string()
+}
+
+impl MockState {
+    fn next(&mut self) -> u64 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    fn authorize(&mut self, xml: &str, envi: Node) -> String {
+        let notes: Vec<Node> = envi
+            .children()
+            .filter(|n| n.has_tag_name((NFE_NAMESPACE, "NFe")))
+            .collect();
+        let sync = nfe_text(envi, &["indSinc"]) == "1";
+        let c_uf = notes
+            .first()
+            .map(|n| nfe_text(*n, &["infNFe", "ide", "cUF"]))
+            .unwrap_or_else(|| "35".to_string());
+
+        let mut w = XmlWriter::new();
+        let tp_amb = self.tp_amb.clone();
+        reply_header(&mut w, "retEnviNFe", &tp_amb);
+        let status = if notes.is_empty() || notes.len() > MAX_BATCH {
+            Some(("225", "Rejeição: Falha no Schema XML do lote de NFe"))
+        } else if sync && notes.len() > 1 {
+            Some((
+                "452",
+                "Rejeição: Solicitada resposta síncrona para Lote com mais de uma NF-e",
+            ))
+        } else {
+            None
+        };
+        if let Some((c_stat, x_motivo)) = status {
+            w.text("cStat", c_stat);
+            w.text("xMotivo", x_motivo);
+            w.text("cUF", &c_uf);
+            w.text("dhRecbto", &dh_recbto(&c_uf));
+            w.end("retEnviNFe");
+            return w.into_string();
+        }
+
+        let protocols: Vec<String> = notes
+            .iter()
+            .map(|nfe| self.protocol(&soap::standalone(xml, *nfe), *nfe))
+            .collect();
+        if sync {
+            w.text("cStat", "104");
+            w.text("xMotivo", "Lote processado");
+            w.text("cUF", &c_uf);
+            w.text("dhRecbto", &dh_recbto(&c_uf));
+            let mut xml = w.into_string();
+            xml.push_str(&protocols.concat());
+            xml.push_str("</retEnviNFe>");
+            return xml;
+        }
+
+        let n_rec = format!("{}{:013}", c_uf, self.next());
+        w.text("cStat", "103");
+        w.text("xMotivo", "Lote recebido com sucesso");
+        w.text("cUF", &c_uf);
+        w.text("dhRecbto", &dh_recbto(&c_uf));
+        w.start("infRec");
+        w.text("nRec", &n_rec);
+        w.text("tMed", "1");
+        w.end("infRec");
+        w.end("retEnviNFe");
+        self.receipts.insert(
+            n_rec,
+            Receipt {
+                c_uf,
+                polls: 0,
+                protocols,
+            },
+        );
+        w.into_string()
+    }
+
+    /// `protNFe` for one note of the batch.
+    fn protocol(&mut self, standalone: &str, nfe: Node) -> String {
+        let id = nfe
+            .children()
+            .find(|n| n.has_tag_name((NFE_NAMESPACE, "infNFe")))
+            .and_then(|n| n.attribute("Id"))
+            .unwrap_or_default();
+        let ch_nfe = id.trim_start_matches("NFe").to_string();
+        let c_uf = nfe_text(nfe, &["infNFe", "ide", "cUF"]);
+        let dig_val = nfe
+            .descendants()
+            .find(|n| n.has_tag_name((XMLDSIG_NAMESPACE, "DigestValue")))
+            .and_then(|n| n.text());
+
+        let (c_stat, x_motivo) = match signature::verify(standalone) {
+            Ok(Some(_)) if nfe_text(nfe, &["infNFe", "ide", "tpAmb"]) != self.tp_amb => (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            ),
+            Ok(Some(_)) if self.authorized.contains_key(&ch_nfe) => {
+                ("204", "Rejeição: Duplicidade de NF-e")
+            }
+            Ok(Some(_)) => ("100", "Autorizado o uso da NF-e"),
+            Ok(None) | Err(_) => ("297", "Rejeição: Assinatura difere do calculado"),
+        };
+
+        let mut w = XmlWriter::new();
+        w.start_with("protNFe", &[("versao", NFE_VERSION)]);
+        w.start("infProt");
+        w.text("tpAmb", &self.tp_amb);
+        w.text("verAplic", VER_APLIC);
+        w.text("chNFe", &ch_nfe);
+        w.text("dhRecbto", &dh_recbto(&c_uf));
+        let n_prot = (c_stat == "100")
+            .then(|| format!("1{}{}{:010}", c_uf, Utc::now().format("%y"), self.next()));
+        if let Some(n_prot) = &n_prot {
+            w.text("nProt", n_prot);
+            w.opt_text("digVal", dig_val);
+            self.authorized.insert(ch_nfe.clone(), n_prot.clone());
+        }
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.end("infProt");
+        w.end("protNFe");
+        let protocol = w.into_string();
+        if n_prot.is_some() {
+            self.protocols.insert(ch_nfe.clone(), protocol.clone());
+        }
+
+        let recipient = nfe_text(nfe, &["infNFe", "dest", "CNPJ"]);
+        if let (Some(n_prot), false) = (n_prot, recipient.is_empty()) {
+            let summary = res_nfe(nfe, &ch_nfe, &n_prot, dig_val);
+            let proc = format!(
+                "<nfeProc xmlns=\"{}\" versao=\"{}\">{}{}</nfeProc>",
+                NFE_NAMESPACE, NFE_VERSION, standalone, protocol
+            );
+            self.distributed
+                .push((recipient.clone(), "resNFe_v1.01.xsd".to_string(), summary));
+            self.distributed
+                .push((recipient, "procNFe_v4.00.xsd".to_string(), proc));
+        }
+        protocol
+    }
+
+    fn receipt(&mut self, cons: Node) -> String {
+        let n_rec = nfe_text(cons, &["nRec"]);
+        let mut w = XmlWriter::new();
+        let tp_amb = self.tp_amb.clone();
+        reply_header(&mut w, "retConsReciNFe", &tp_amb);
+        w.text("nRec", &n_rec);
+
+        let Some(receipt) = self.receipts.get_mut(&n_rec) else {
+            w.text("cStat", "106");
+            w.text("xMotivo", "Lote não localizado");
+            w.text("cUF", "35");
+            w.text("dhRecbto", &dh_recbto("35"));
+            w.end("retConsReciNFe");
+            return w.into_string();
+        };
+        receipt.polls += 1;
+        if receipt.polls == 1 {
+            w.text("cStat", "105");
+            w.text("xMotivo", "Lote em processamento");
+            w.text("cUF", &receipt.c_uf);
+            w.text("dhRecbto", &dh_recbto(&receipt.c_uf));
+            w.end("retConsReciNFe");
+            return w.into_string();
+        }
+        w.text("cStat", "104");
+        w.text("xMotivo", "Lote processado");
+        w.text("cUF", &receipt.c_uf);
+        w.text("dhRecbto", &dh_recbto(&receipt.c_uf));
+        let mut xml = w.into_string();
+        xml.push_str(&receipt.protocols.concat());
+        xml.push_str("</retConsReciNFe>");
+        xml
+    }
+
+    /// `retConsSitNFe` with the `protNFe` of an authorized note.
+    fn situation(&self, cons: Node) -> String {
+        let ch_nfe = nfe_text(cons, &["chNFe"]);
+        let c_uf = ch_nfe.get(..2).unwrap_or("35").to_string();
+        let protocol = self.protocols.get(&ch_nfe);
+        let (c_stat, x_motivo) = match protocol {
+            _ if nfe_text(cons, &["tpAmb"]) != self.tp_amb => (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            ),
+            Some(_) if self.cancelled.contains(&ch_nfe) => {
+                ("101", "Cancelamento de NF-e homologado")
+            }
+            Some(_) => ("100", "Autorizado o uso da NF-e"),
+            None => ("217", "Rejeição: NF-e não consta na base de dados da SEFAZ"),
+        };
+        let mut w = XmlWriter::new();
+        reply_header(&mut w, "retConsSitNFe", &self.tp_amb);
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.text("cUF", &c_uf);
+        w.text("dhRecbto", &dh_recbto(&c_uf));
+        w.text("chNFe", &ch_nfe);
+        let mut xml = w.into_string();
+        if c_stat != "252" {
+            xml.push_str(protocol.map(String::as_str).unwrap_or_default());
+        }
+        xml.push_str("</retConsSitNFe>");
+        xml
+    }
+
+    fn events(&mut self, xml: &str, env: Node) -> String {
+        let events: Vec<Node> = env
+            .children()
+            .filter(|n| n.has_tag_name((NFE_NAMESPACE, "evento")))
+            .collect();
+        let c_orgao = events
+            .first()
+            .map(|n| nfe_text(*n, &["infEvento", "cOrgao"]))
+            .unwrap_or_else(|| "35".to_string());
+
+        let mut w = XmlWriter::new();
+        w.start_with(
+            "retEnvEvento",
+            &[("xmlns", NFE_NAMESPACE), ("versao", EVENT_VERSION)],
+        );
+        w.text("idLote", &nfe_text(env, &["idLote"]));
+        w.text("tpAmb", &self.tp_amb);
+        w.text("verAplic", VER_APLIC);
+        w.text("cOrgao", &c_orgao);
+        if events.is_empty() || events.len() > MAX_EVENTS {
+            w.text("cStat", "225");
+            w.text(
+                "xMotivo",
+                "Rejeição: Falha no Schema XML do lote de eventos",
+            );
+            w.end("retEnvEvento");
+            return w.into_string();
+        }
+        w.text("cStat", event::BATCH_PROCESSED);
+        w.text("xMotivo", "Lote de evento processado");
+        let mut reply = w.into_string();
+        for evento in events {
+            reply.push_str(&self.event(&soap::standalone(xml, evento), evento));
+        }
+        reply.push_str("</retEnvEvento>");
+        reply
+    }
+
+    /// `retEvento` for one event of the batch.
+    fn event(&mut self, standalone: &str, evento: Node) -> String {
+        let field = |name: &str| nfe_text(evento, &["infEvento", name]);
+        let ch_nfe = field("chNFe");
+        let tp_evento = field("tpEvento");
+        let c_orgao = field("cOrgao");
+
+        let (c_stat, x_motivo) = match signature::verify(standalone) {
+            Ok(Some(_)) if field("tpAmb") != self.tp_amb => (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            ),
+            Ok(Some(_)) if !self.authorized.contains_key(&ch_nfe) => {
+                ("217", "Rejeição: NF-e não consta na base de dados da SEFAZ")
+            }
+            Ok(Some(_)) => self.register(&ch_nfe, evento),
+            Ok(None) | Err(_) => ("297", "Rejeição: Assinatura difere do calculado"),
+        };
+
+        let mut w = XmlWriter::new();
+        w.start_with("retEvento", &[("versao", EVENT_VERSION)]);
+        w.start("infEvento");
+        w.text("tpAmb", &self.tp_amb);
+        w.text("verAplic", VER_APLIC);
+        w.text("cOrgao", &c_orgao);
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.text("chNFe", &ch_nfe);
+        w.text("tpEvento", &tp_evento);
+        if c_stat == "135" {
+            let x_evento = if tp_evento == CANCELLATION {
+                "Cancelamento registrado"
+            } else {
+                "Carta de Correção registrada"
+            };
+            w.text("xEvento", x_evento);
+        }
+        w.text("nSeqEvento", &field("nSeqEvento"));
+        w.text("dhRegEvento", &dh_recbto(&c_orgao));
+        if c_stat == "135" {
+            let n_prot = format!("1{}{}{:010}", c_orgao, Utc::now().format("%y"), self.next());
+            w.text("nProt", &n_prot);
+        }
+        w.end("infEvento");
+        w.end("retEvento");
+        w.into_string()
+    }
+
+    /// Applies a signed event of a note this mock authorized.
+    fn register(&mut self, ch_nfe: &str, evento: Node) -> (&'static str, &'static str) {
+        let detail = |name: &str| nfe_text(evento, &["infEvento", "detEvento", name]);
+        let n_seq_evento: u32 = nfe_text(evento, &["infEvento", "nSeqEvento"])
+            .parse()
+            .unwrap_or_default();
+        match nfe_text(evento, &["infEvento", "tpEvento"]).as_str() {
+            CANCELLATION if self.cancelled.contains(ch_nfe) => {
+                ("573", "Rejeição: Duplicidade de Evento")
+            }
+            CANCELLATION if self.authorized.get(ch_nfe) != Some(&detail("nProt")) => (
+                "222",
+                "Rejeição: Protocolo de Autorização de Uso difere do cadastrado",
+            ),
+            CANCELLATION => {
+                self.cancelled.insert(ch_nfe.to_string());
+                ("135", "Evento registrado e vinculado a NF-e")
+            }
+            CORRECTION if self.cancelled.contains(ch_nfe) => {
+                ("580", "Rejeição: O evento exige uma NF-e autorizada")
+            }
+            CORRECTION if n_seq_evento > MAX_CORRECTIONS => (
+                "594",
+                "Rejeição: O número de sequência do evento informado é maior que o permitido",
+            ),
+            CORRECTION if detail("xCondUso") != CONDITIONS_OF_USE => {
+                ("225", "Rejeição: Falha no Schema XML do evento")
+            }
+            CORRECTION => {
+                let last = self.corrections.entry(ch_nfe.to_string()).or_default();
+                if n_seq_evento <= *last {
+                    return ("573", "Rejeição: Duplicidade de Evento");
+                }
+                *last = n_seq_evento;
+                ("135", "Evento registrado e vinculado a NF-e")
+            }
+            _ => ("491", "Rejeição: O tpEvento informado inválido"),
+        }
+    }
+
+    /// `retConsStatServ`: always in operation for its own environment.
+    fn status(&self, cons: Node) -> String {
+        let c_uf = nfe_text(cons, &["cUF"]);
+        let (c_stat, x_motivo) = if nfe_text(cons, &["tpAmb"]) == self.tp_amb {
+            ("107", "Servico em Operacao")
+        } else {
+            (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            )
+        };
+        let mut w = XmlWriter::new();
+        reply_header(&mut w, "retConsStatServ", &self.tp_amb);
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.text("cUF", &c_uf);
+        w.text("dhRecbto", &dh_recbto(&c_uf));
+        w.text("tMed", "1");
+        w.end("retConsStatServ");
+        w.into_string()
+    }
+
+    /// `retDistDFeInt` with the documents of the CNPJ after `ultNSU`.
+    fn distribute(&self, dist: Node) -> String {
+        let cnpj = nfe_text(dist, &["CNPJ"]);
+        let ult_nsu: u64 = nfe_text(dist, &["distNSU", "ultNSU"])
+            .parse()
+            .unwrap_or_default();
+        let nsus: Vec<u64> = (1..=self.distributed.len() as u64)
+            .filter(|nsu| self.distributed[*nsu as usize - 1].0 == cnpj)
+            .collect();
+        let max_nsu = nsus.last().copied().unwrap_or(ult_nsu).max(ult_nsu);
+        let batch: Vec<u64> = nsus
+            .into_iter()
+            .filter(|nsu| *nsu > ult_nsu)
+            .take(MAX_DOC_ZIPS)
+            .collect();
+
+        let mut w = XmlWriter::new();
+        w.start_with(
+            "retDistDFeInt",
+            &[("xmlns", NFE_NAMESPACE), ("versao", DIST_VERSION)],
+        );
+        w.text("tpAmb", &self.tp_amb);
+        w.text("verAplic", VER_APLIC);
+        let (c_stat, x_motivo) = if nfe_text(dist, &["tpAmb"]) != self.tp_amb {
+            (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            )
+        } else if batch.is_empty() {
+            (distribution::NOTHING_FOUND, "Nenhum documento localizado")
+        } else {
+            (distribution::FOUND, "Documento(s) localizado(s)")
+        };
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.text("dhResp", &dh_recbto("35"));
+        let last = batch.last().copied().unwrap_or(max_nsu);
+        w.text("ultNSU", &distribution::nsu(last));
+        w.text("maxNSU", &distribution::nsu(max_nsu));
+        if c_stat == distribution::FOUND {
+            w.start("loteDistDFeInt");
+            for nsu in batch {
+                let (_, schema, xml) = &self.distributed[nsu as usize - 1];
+                w.text_with(
+                    "docZip",
+                    &[("NSU", &distribution::nsu(nsu)), ("schema", schema)],
+                    &distribution::zip(xml),
+                );
+            }
+            w.end("loteDistDFeInt");
+        }
+        w.end("retDistDFeInt");
+        w.into_string()
+    }
+
+    /// `retInutNFe` for a signed `inutNFe`.
+    fn inutilize(&mut self, xml: &str, inut: Node) -> String {
+        let field = |name: &str| nfe_text(inut, &["infInut", name]);
+        let c_uf = field("cUF");
+        let author = Some(field("CNPJ"))
+            .filter(|cnpj| !cnpj.is_empty())
+            .unwrap_or_else(|| field("CPF"));
+        let serie: u32 = field("serie").parse().unwrap_or_default();
+        let n_nf_ini: u32 = field("nNFIni").parse().unwrap_or_default();
+        let n_nf_fin: u32 = field("nNFFin").parse().unwrap_or_default();
+        let serie_key = (
+            field("ano"),
+            format!("{:0>14}", author),
+            field("mod"),
+            serie,
+        );
+
+        let used = self.authorized.keys().any(|key| {
+            AccessKey::parse(key).is_ok_and(|key| {
+                let parts = key.parts;
+                (
+                    parts.aamm[..2].to_string(),
+                    parts.document,
+                    parts.mod_,
+                    parts.serie.parse().unwrap_or_default(),
+                ) == serie_key
+                    && parts
+                        .n_nf
+                        .parse::<u32>()
+                        .is_ok_and(|n_nf| (n_nf_ini..=n_nf_fin).contains(&n_nf))
+            })
+        });
+        let voided = self.voided.get(&serie_key).is_some_and(|ranges| {
+            ranges
+                .iter()
+                .any(|(ini, fin)| n_nf_ini <= *fin && *ini <= n_nf_fin)
+        });
+        let (c_stat, x_motivo) = match signature::verify(&soap::standalone(xml, inut)) {
+            Ok(Some(_)) if field("tpAmb") != self.tp_amb => (
+                "252",
+                "Rejeição: Ambiente informado diverge do Ambiente de recebimento",
+            ),
+            Ok(Some(_)) if voided => (
+                "256",
+                "Rejeição: Uma NF-e da faixa já está inutilizada na Base de dados da SEFAZ",
+            ),
+            Ok(Some(_)) if used => ("241", "Rejeição: Um número da faixa já foi utilizado"),
+            Ok(Some(_)) => {
+                self.voided
+                    .entry(serie_key)
+                    .or_default()
+                    .push((n_nf_ini, n_nf_fin));
+                ("102", "Inutilização de número homologado")
+            }
+            Ok(None) | Err(_) => ("297", "Rejeição: Assinatura difere do calculado"),
+        };
+
+        let mut w = XmlWriter::new();
+        w.start_with(
+            "retInutNFe",
+            &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+        );
+        w.start("infInut");
+        w.text("tpAmb", &self.tp_amb);
+        w.text("verAplic", VER_APLIC);
+        w.text("cStat", c_stat);
+        w.text("xMotivo", x_motivo);
+        w.text("cUF", &c_uf);
+        if c_stat == "102" {
+            w.text("ano", &field("ano"));
+            w.text(if author.len() == 11 { "CPF" } else { "CNPJ" }, &author);
+            w.text("mod", &field("mod"));
+            w.text("serie", &field("serie"));
+            w.text("nNFIni", &field("nNFIni"));
+            w.text("nNFFin", &field("nNFFin"));
+        }
+        w.text("dhRecbto", &dh_recbto(&c_uf));
+        if c_stat == "102" {
+            let n_prot = format!("1{}{}{:010}", c_uf, Utc::now().format("%y"), self.next());
+            w.text("nProt", &n_prot);
+        }
+        w.end("infInut");
+        w.end("retInutNFe");
+        w.into_string()
+    }
+}
@@ -0,0 +1,9 @@
+pub mod authorization;
+pub mod config;
+pub mod consultation;
+pub mod distribution;
+pub mod event;
+pub mod inutilization;
+pub mod mock;
+pub mod soap;
+pub mod status;
@@ -0,0 +1,215 @@
+//! SOAP 1.2 transport of the SEFAZ web services. The message goes inside `nfeDadosMsg`
+//! and the answer comes back inside `nfeResultMsg`, both in the WSDL namespace; the
+//! distribution service wraps them in its operation and answers in
+//! `nfeDistDFeInteresseResult`.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::config::{SefazConfig, Service};
+use crate::services::xml::NFE_NAMESPACE;
+use chrono::{DateTime, Utc};
+use reqwest::{Certificate, Client, Identity};
+use roxmltree::{Document, Node};
+use std::fs;
+use std::time::Duration;
+use tracing::{debug, instrument};
+
+pub const SOAP_NAMESPACE: &str = "http://www.w3.org/2003/05/soap-envelope";
+
+pub fn envelope(service: Service, message: &str) -> String {
+    // NFeDistribuicaoDFe keeps the operation element of its WSDL around the message.
+    let body = match service {
+        Service::NFeDistribuicaoDFe => format!(
+            "<{0} xmlns=\"{1}\"><nfeDadosMsg>{2}</nfeDadosMsg></{0}>",
+            service.operation(),
+            service.namespace(),
+            message
+        ),
+        _ => format!(
+            "<nfeDadosMsg xmlns=\"{}\">{}</nfeDadosMsg>",
+            service.namespace(),
+            message
+        ),
+    };
+    format!(
+        "<soap12:Envelope xmlns:soap12=\"{}\"><soap12:Body>{}</soap12:Body></soap12:Envelope>",
+        SOAP_NAMESPACE, body
+    )
+}
+
+pub fn content_type(service: Service) -> String {
+    format!(
+        "application/soap+xml; charset=utf-8; action=\"{}/{}\"",
+        service.namespace(),
+        service.operation()
+    )
+}
+
+/// Text of `node` as a standalone document. Namespaces that the element or its
+/// descendants inherit from an ancestor, such as the NF-e namespace or the `ns2`
+/// prefix some SEFAZ servers use, are declared on its start tag, so `protNFe` can be
+/// stored on its own.
+pub fn standalone(xml: &str, node: Node) -> String {
+    let text = &xml[node.range()];
+    let start_tag = &text[..text.find('>').unwrap_or(text.len())];
+    let name_end = start_tag
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(start_tag.len());
+    let elements: Vec<Node> = node.descendants().filter(|n| n.is_element()).collect();
+    let mut declarations = String::new();
+    for namespace in node.namespaces() {
+        let (declared, used) = match namespace.name() {
+            Some(prefix) => (
+                start_tag.contains(&format!("xmlns:{}=", prefix)),
+                elements.iter().any(|n| {
+                    qualified_name(xml, *n).starts_with(&format!("{}:", prefix))
+                        || n.attributes()
+                            .any(|a| a.namespace() == Some(namespace.uri()))
+                }),
+            ),
+            None => (
+                start_tag.contains("xmlns="),
+                elements.iter().any(|n| {
+                    n.tag_name().namespace() == Some(namespace.uri())
+                        && !qualified_name(xml, *n).contains(':')
+                }),
+            ),
+        };
+        if used && !declared {
+            match namespace.name() {
+                Some(prefix) => {
+                    declarations.push_str(&format!(" xmlns:{}=\"{}\"", prefix, namespace.uri()))
+                }
+                None => declarations.push_str(&format!(" xmlns=\"{}\"", namespace.uri())),
+            }
+        }
+    }
+    format!("{}{}{}", &text[..name_end], declarations, &text[name_end..])
+}
+
+/// Name of an element as written in its start tag, with its prefix.
+fn qualified_name<'a>(xml: &'a str, node: Node) -> &'a str {
+    let tag = &xml[node.range().start + 1..];
+    &tag[..tag
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(tag.len())]
+}
+
+/// Trimmed text of the child `name` of `node`, in the NF-e namespace.
+pub fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|n| n.has_tag_name((NFE_NAMESPACE, name)))
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+}
+
+pub fn required(node: Node, name: &str) -> Result<String, SefazError> {
+    child_text(node, name).ok_or_else(|| {
+        SefazError::InvalidResponse(format!("{} without {}", node.tag_name().name(), name))
+    })
+}
+
+/// Parses a SEFAZ timestamp (`dhRecbto`), which carries the local offset.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, SefazError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| SefazError::InvalidResponse(format!("invalid timestamp {}: {}", value, e)))
+}
+
+/// First element of `nfeResultMsg` (or `nfeDistDFeInteresseResult`), e.g. `retEnviNFe`.
+pub fn result<'a, 'input>(document: &'a Document<'input>) -> Result<Node<'a, 'input>, SefazError> {
+    if let Some(fault) = document
+        .descendants()
+        .find(|n| n.has_tag_name((SOAP_NAMESPACE, "Fault")))
+    {
+        let reason = fault
+            .descendants()
+            .find(|n| n.has_tag_name((SOAP_NAMESPACE, "Text")))
+            .and_then(|n| n.text())
+            .unwrap_or("SOAP fault");
+        return Err(SefazError::Fault(reason.trim().to_string()));
+    }
+    document
+        .descendants()
+        .find(|n| {
+            matches!(
+                n.tag_name().name(),
+                "nfeResultMsg" | "nfeDistDFeInteresseResult"
+            )
+        })
+        .and_then(|n| n.first_element_child())
+        .ok_or_else(|| SefazError::InvalidResponse("missing nfeResultMsg".to_string()))
+}
+
+/// Posts SOAP envelopes to the endpoint configured for each state, authenticating with
+/// the A1 certificate as SEFAZ requires mutual TLS.
+pub struct SoapClient {
+    config: SefazConfig,
+    http: Client,
+}
+
+impl SoapClient {
+    pub fn new(config: SefazConfig, identity: Option<(&[u8], &str)>) -> Result<Self, SefazError> {
+        let mut builder = Client::builder().timeout(Duration::from_secs(config.timeout_secs));
+        if let Some((pkcs12, password)) = identity {
+            let identity = Identity::from_pkcs12_der(pkcs12, password)
+                .map_err(|e| SefazError::NotConfigured(format!("client certificate: {}", e)))?;
+            builder = builder.identity(identity);
+        }
+        if let Some(ca_file) = &config.ca_file {
+            let pem = fs::read(ca_file).map_err(|e| {
+                SefazError::NotConfigured(format!("cannot read {}: {}", ca_file.display(), e))
+            })?;
+            let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| {
+                SefazError::NotConfigured(format!("invalid {}: {}", ca_file.display(), e))
+            })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        let http = builder
+            .build()
+            .map_err(|e| SefazError::NotConfigured(format!("HTTP client: {}", e)))?;
+        Ok(Self { config, http })
+    }
+
+    pub fn config(&self) -> &SefazConfig {
+        &self.config
+    }
+
+    /// Sends `message` to the service of the state and returns the response envelope.
+    #[instrument(skip(self, message), fields(service = service.name()))]
+    pub async fn call(
+        &self,
+        c_uf: &str,
+        service: Service,
+        message: &str,
+    ) -> Result<String, SefazError> {
+        let url = self.config.endpoint(c_uf, service)?;
+        debug!("Posting to {}", url);
+
+        let response = self
+            .http
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, content_type(service))
+            .body(envelope(service, message))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_connect() {
+                    SefazError::Unreachable(e.to_string())
+                } else {
+                    SefazError::Transport(e.to_string())
+                }
+            })?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| SefazError::Transport(e.to_string()))?;
+        // Faults come with status 500 and are reported from the body.
+        if !status.is_success() && !body.contains("Fault") {
+            return Err(SefazError::Fault(format!("HTTP {}", status)));
+        }
+        Ok(body)
+    }
+}
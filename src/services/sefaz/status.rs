@@ -0,0 +1,94 @@
+//! NFeStatusServico4: asks an authorizer whether it is in operation, which is how the
+//! end of a contingency is detected.
+
+use crate::errors::SefazError;
+use crate::services::sefaz::config::Service;
+use crate::services::sefaz::soap::{self, required, SoapClient};
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use roxmltree::Document;
+use tracing::{info, instrument};
+
+/// Service in operation.
+pub const IN_OPERATION: &str = "107";
+
+/// Status part of `retConsStatServ`.
+#[derive(Debug, Clone)]
+pub struct RetConsStatServ {
+    pub c_stat: String,
+    pub x_motivo: String,
+}
+
+impl RetConsStatServ {
+    pub fn is_in_operation(&self) -> bool {
+        self.c_stat == IN_OPERATION
+    }
+}
+
+pub fn cons_stat_serv(c_uf: &str, tp_amb: &str) -> String {
+    let mut w = XmlWriter::new();
+    w.start_with(
+        "consStatServ",
+        &[("xmlns", NFE_NAMESPACE), ("versao", NFE_VERSION)],
+    );
+    w.text("tpAmb", tp_amb);
+    w.text("cUF", c_uf);
+    w.text("xServ", "STATUS");
+    w.end("consStatServ");
+    w.into_string()
+}
+
+/// Queries the status of the service that authorizes the notes of `c_uf`, which is the
+/// state itself or, in SVC contingency, `authorizer`.
+#[instrument(skip(client))]
+pub async fn query(
+    client: &SoapClient,
+    authorizer: &str,
+    c_uf: &str,
+    tp_amb: &str,
+) -> Result<RetConsStatServ, SefazError> {
+    info!("Querying SEFAZ service status");
+
+    let response = client
+        .call(
+            authorizer,
+            Service::NFeStatusServico4,
+            &cons_stat_serv(c_uf, tp_amb),
+        )
+        .await?;
+    let document = Document::parse(&response)
+        .map_err(|e| SefazError::InvalidResponse(format!("malformed XML: {}", e)))?;
+    let reply = soap::result(&document)?;
+    Ok(RetConsStatServ {
+        c_stat: required(reply, "cStat")?,
+        x_motivo: required(reply, "xMotivo")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::sefaz::config::SefazConfig;
+    use crate::services::sefaz::mock::MockSefaz;
+
+    #[actix_web::test]
+    async fn reports_the_service_status() {
+        assert_eq!(
+            cons_stat_serv("35", "2"),
+            "<consStatServ xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\"><tpAmb>2</tpAmb><cUF>35</cUF><xServ>STATUS</xServ></consStatServ>"
+        );
+
+        let mock = MockSefaz::start("2").await.unwrap();
+        let url = mock.url().to_string();
+        let client = SoapClient::new(SefazConfig::single_server(&url, "2"), None).unwrap();
+        let ret = query(&client, "35", "35", "2").await.unwrap();
+        assert!(ret.is_in_operation(), "{:?}", ret);
+        assert_eq!(query(&client, "35", "35", "1").await.unwrap().c_stat, "252");
+        mock.stop().await;
+
+        // Nothing listens on the port once the mock is stopped.
+        let Err(SefazError::Unreachable(_)) = query(&client, "35", "35", "2").await else {
+            panic!("expected a connection error");
+        };
+    }
+}
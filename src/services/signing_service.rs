@@ -0,0 +1,65 @@
+use crate::errors::{RepositoryError, ValidationError};
+use crate::models::nfe_document::NFeDocument;
+use crate::services::xml::nfce_supplement::{self, Csc};
+use crate::services::xml::nfe_serializer;
+use crate::services::xml::signature::Signer;
+use tracing::info;
+
+/// Signs the documents sent to SEFAZ with the emitter's A1 certificate, and completes
+/// the NFC-e with the QR code hashed with the emitter's CSC. Signing is unavailable when
+/// no certificate is configured, and the NFC-e when no CSC is.
+pub struct SigningService {
+    signer: Option<Signer>,
+    csc: Option<Csc>,
+}
+
+impl SigningService {
+    pub fn new(signer: Option<Signer>) -> Self {
+        Self { signer, csc: None }
+    }
+
+    pub fn with_csc(self, csc: Option<Csc>) -> Self {
+        Self { csc, ..self }
+    }
+
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self, RepositoryError> {
+        let signer = Signer::from_pkcs12(der, password)?;
+        info!("Loaded A1 certificate {}", signer.subject());
+        Ok(Self::new(Some(signer)))
+    }
+
+    fn signer(&self) -> Result<&Signer, RepositoryError> {
+        self.signer.as_ref().ok_or_else(|| {
+            ValidationError::new("certificate", "no A1 certificate is configured").into()
+        })
+    }
+
+    /// Signs the element with the given `Id` of an already serialized document.
+    pub fn sign(&self, xml: &str, reference: &str) -> Result<String, RepositoryError> {
+        Ok(self.signer()?.sign(xml, reference)?)
+    }
+
+    /// Serializes the note and signs its `infNFe`.
+    pub fn sign_nfe(&self, document: &NFeDocument) -> Result<String, RepositoryError> {
+        let xml = nfe_serializer::serialize(document)?;
+        let reference = format!("NFe{}", document.access_key()?);
+        self.sign(&xml, &reference)
+    }
+
+    /// Signs an NFC-e and adds its `infNFeSupl`, pointing to the consultation pages of
+    /// the state.
+    pub fn sign_nfce(
+        &self,
+        document: &NFeDocument,
+        qr_code_url: &str,
+        url_chave: &str,
+    ) -> Result<String, RepositoryError> {
+        let csc = self
+            .csc
+            .as_ref()
+            .ok_or_else(|| ValidationError::new("CSC", "no CSC is configured to issue NFC-e"))?;
+        let signed = self.sign_nfe(document)?;
+        let supplement = nfce_supplement::build(document, &signed, csc, qr_code_url, url_chave)?;
+        Ok(supplement.attach(&signed)?)
+    }
+}
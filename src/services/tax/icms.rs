@@ -0,0 +1,1235 @@
+//! ICMS calculation for the CST (regime normal) and CSOSN (Simples Nacional) groups of
+//! `leiauteNFe_v4.00.xsd`.
+//!
+//! Every intermediate value is rounded before it is reused, because SEFAZ recomputes the
+//! tax from the rounded values that appear in the XML.
+//!
+//! The monophasic fuel groups (CST 02, 15, 53 and 61) are assessed per unit: the taxed
+//! quantity (`qBCMono`..., the item `qTrib` when not informed) times the ad rem rate.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_item::NFeProduct;
+use crate::models::nfe_item_tax::NFeIcms;
+use crate::services::tax::{percent_of, reduce, round_value};
+use rust_decimal::Decimal;
+
+/// XSD subgroup of `ICMS` an item is reported under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmsGroup {
+    Icms00,
+    Icms02,
+    Icms10,
+    Icms15,
+    Icms20,
+    Icms30,
+    Icms40,
+    Icms51,
+    Icms53,
+    Icms60,
+    Icms61,
+    Icms70,
+    Icms90,
+    IcmsPart,
+    IcmsSt,
+    Sn101,
+    Sn102,
+    Sn201,
+    Sn202,
+    Sn500,
+    Sn900,
+}
+
+impl IcmsGroup {
+    /// Picks the subgroup for a CST or CSOSN.
+    ///
+    /// `partilha` selects ICMSPart for CST 10/90 and `repasse` selects ICMSST for CST 41/60.
+    pub fn resolve(
+        cst: Option<&str>,
+        csosn: Option<&str>,
+        partilha: bool,
+        repasse: bool,
+    ) -> Result<Self, ValidationError> {
+        match (cst, csosn) {
+            (Some(cst), None) => match cst {
+                "00" => Ok(Self::Icms00),
+                "02" => Ok(Self::Icms02),
+                "10" if partilha => Ok(Self::IcmsPart),
+                "10" => Ok(Self::Icms10),
+                "15" => Ok(Self::Icms15),
+                "20" => Ok(Self::Icms20),
+                "30" => Ok(Self::Icms30),
+                "41" | "60" if repasse => Ok(Self::IcmsSt),
+                "40" | "41" | "50" => Ok(Self::Icms40),
+                "51" => Ok(Self::Icms51),
+                "53" => Ok(Self::Icms53),
+                "60" => Ok(Self::Icms60),
+                "61" => Ok(Self::Icms61),
+                "70" => Ok(Self::Icms70),
+                "90" if partilha => Ok(Self::IcmsPart),
+                "90" => Ok(Self::Icms90),
+                _ => Err(ValidationError::new("ICMS.CST", "unsupported CST")),
+            },
+            (None, Some(csosn)) => match csosn {
+                "101" => Ok(Self::Sn101),
+                "102" | "103" | "300" | "400" => Ok(Self::Sn102),
+                "201" => Ok(Self::Sn201),
+                "202" | "203" => Ok(Self::Sn202),
+                "500" => Ok(Self::Sn500),
+                "900" => Ok(Self::Sn900),
+                _ => Err(ValidationError::new("ICMS.CSOSN", "unsupported CSOSN")),
+            },
+            _ => Err(ValidationError::new(
+                "ICMS",
+                "exactly one of CST or CSOSN must be informed",
+            )),
+        }
+    }
+
+    /// Subgroup of a stored item, using the same rules as the calculation.
+    pub fn of(icms: &NFeIcms) -> Result<Self, ValidationError> {
+        Self::resolve(
+            icms.cst.as_deref(),
+            icms.csosn.as_deref(),
+            icms.p_bc_op.is_some() || icms.uf_st.is_some(),
+            icms.v_bc_st_dest.is_some(),
+        )
+    }
+
+    /// Element name of the subgroup inside `ICMS`.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Icms00 => "ICMS00",
+            Self::Icms02 => "ICMS02",
+            Self::Icms10 => "ICMS10",
+            Self::Icms15 => "ICMS15",
+            Self::Icms20 => "ICMS20",
+            Self::Icms30 => "ICMS30",
+            Self::Icms40 => "ICMS40",
+            Self::Icms51 => "ICMS51",
+            Self::Icms53 => "ICMS53",
+            Self::Icms60 => "ICMS60",
+            Self::Icms61 => "ICMS61",
+            Self::Icms70 => "ICMS70",
+            Self::Icms90 => "ICMS90",
+            Self::IcmsPart => "ICMSPart",
+            Self::IcmsSt => "ICMSST",
+            Self::Sn101 => "ICMSSN101",
+            Self::Sn102 => "ICMSSN102",
+            Self::Sn201 => "ICMSSN201",
+            Self::Sn202 => "ICMSSN202",
+            Self::Sn500 => "ICMSSN500",
+            Self::Sn900 => "ICMSSN900",
+        }
+    }
+}
+
+/// Item values and rates needed to compute ICMS. Percentages are given as in the XML (18.00 = 18%).
+#[derive(Debug, Clone, Default)]
+pub struct IcmsInput {
+    pub cst: Option<String>,
+    pub csosn: Option<String>,
+    pub v_prod: Decimal,
+    pub v_frete: Decimal,
+    pub v_seg: Decimal,
+    pub v_outro: Decimal,
+    pub v_desc: Decimal,
+    /// Item IPI; always part of the ST base and part of the own base when `ipi_in_bc` is set
+    /// (sales to final consumers).
+    pub v_ipi: Decimal,
+    pub ipi_in_bc: bool,
+    pub p_icms: Option<Decimal>,
+    pub p_red_bc: Option<Decimal>,
+    pub p_fcp: Option<Decimal>,
+    pub p_dif: Option<Decimal>,
+    pub mod_bc_st: Option<String>,
+    pub p_mva_st: Option<Decimal>,
+    pub p_red_bc_st: Option<Decimal>,
+    pub p_icms_st: Option<Decimal>,
+    pub p_fcp_st: Option<Decimal>,
+    pub mot_des_icms: Option<String>,
+    pub p_bc_op: Option<Decimal>,
+    pub uf_st: Option<String>,
+    pub v_bc_st_ret: Option<Decimal>,
+    pub p_st: Option<Decimal>,
+    pub v_icms_substituto: Option<Decimal>,
+    pub v_bc_st_dest: Option<Decimal>,
+    pub p_cred_sn: Option<Decimal>,
+    /// Taxable quantity of the item, used by the monophasic groups when their own
+    /// quantity is not informed.
+    pub q_trib: Decimal,
+    pub q_bc_mono: Option<Decimal>,
+    pub ad_rem_icms: Option<Decimal>,
+    pub q_bc_mono_reten: Option<Decimal>,
+    pub ad_rem_icms_reten: Option<Decimal>,
+    pub p_red_ad_rem: Option<Decimal>,
+    pub mot_red_ad_rem: Option<String>,
+    pub q_bc_mono_ret: Option<Decimal>,
+    pub ad_rem_icms_ret: Option<Decimal>,
+}
+
+/// Computed values; `None` means the field does not apply to the group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcmsValues {
+    pub group: IcmsGroup,
+    pub v_bc: Option<Decimal>,
+    pub v_icms_op: Option<Decimal>,
+    pub v_icms_dif: Option<Decimal>,
+    pub v_icms: Option<Decimal>,
+    pub v_bc_fcp: Option<Decimal>,
+    pub v_fcp: Option<Decimal>,
+    pub v_bc_st: Option<Decimal>,
+    pub v_icms_st: Option<Decimal>,
+    pub v_bc_fcp_st: Option<Decimal>,
+    pub v_fcp_st: Option<Decimal>,
+    pub v_icms_deson: Option<Decimal>,
+    pub v_icms_st_ret: Option<Decimal>,
+    pub v_icms_st_dest: Option<Decimal>,
+    pub v_cred_icms_sn: Option<Decimal>,
+    pub q_bc_mono: Option<Decimal>,
+    pub v_icms_mono_op: Option<Decimal>,
+    pub v_icms_mono_dif: Option<Decimal>,
+    pub v_icms_mono: Option<Decimal>,
+    pub q_bc_mono_reten: Option<Decimal>,
+    pub v_icms_mono_reten: Option<Decimal>,
+    pub q_bc_mono_ret: Option<Decimal>,
+    pub v_icms_mono_ret: Option<Decimal>,
+}
+
+impl IcmsValues {
+    fn empty(group: IcmsGroup) -> Self {
+        Self {
+            group,
+            v_bc: None,
+            v_icms_op: None,
+            v_icms_dif: None,
+            v_icms: None,
+            v_bc_fcp: None,
+            v_fcp: None,
+            v_bc_st: None,
+            v_icms_st: None,
+            v_bc_fcp_st: None,
+            v_fcp_st: None,
+            v_icms_deson: None,
+            v_icms_st_ret: None,
+            v_icms_st_dest: None,
+            v_cred_icms_sn: None,
+            q_bc_mono: None,
+            v_icms_mono_op: None,
+            v_icms_mono_dif: None,
+            v_icms_mono: None,
+            q_bc_mono_reten: None,
+            v_icms_mono_reten: None,
+            q_bc_mono_ret: None,
+            v_icms_mono_ret: None,
+        }
+    }
+}
+
+impl IcmsInput {
+    fn group(&self) -> Result<IcmsGroup, ValidationError> {
+        IcmsGroup::resolve(
+            self.cst.as_deref(),
+            self.csosn.as_deref(),
+            self.p_bc_op.is_some() || self.uf_st.is_some(),
+            self.v_bc_st_dest.is_some(),
+        )
+    }
+
+    /// vProd + vFrete + vSeg + vOutro - vDesc, plus IPI when it integrates the base.
+    fn operation_base(&self) -> Decimal {
+        let base = self.v_prod + self.v_frete + self.v_seg + self.v_outro - self.v_desc;
+        if self.ipi_in_bc {
+            base + self.v_ipi
+        } else {
+            base
+        }
+    }
+
+    fn st_base(&self) -> Decimal {
+        self.v_prod + self.v_frete + self.v_seg + self.v_outro - self.v_desc + self.v_ipi
+    }
+}
+
+impl IcmsInput {
+    /// Builds the input from a stored item; the ICMS fields act as rates and switches.
+    pub fn from_item(prod: &NFeProduct, icms: &NFeIcms, v_ipi: Decimal, ipi_in_bc: bool) -> Self {
+        Self {
+            cst: icms.cst.clone(),
+            csosn: icms.csosn.clone(),
+            v_prod: prod.v_prod,
+            v_frete: prod.v_frete.unwrap_or_default(),
+            v_seg: prod.v_seg.unwrap_or_default(),
+            v_outro: prod.v_outro.unwrap_or_default(),
+            v_desc: prod.v_desc.unwrap_or_default(),
+            v_ipi,
+            ipi_in_bc,
+            p_icms: icms.p_icms,
+            p_red_bc: icms.p_red_bc,
+            p_fcp: icms.p_fcp,
+            p_dif: icms.p_dif,
+            mod_bc_st: icms.mod_bc_st.clone(),
+            p_mva_st: icms.p_mva_st,
+            p_red_bc_st: icms.p_red_bc_st,
+            p_icms_st: icms.p_icms_st,
+            p_fcp_st: icms.p_fcp_st,
+            mot_des_icms: icms.mot_des_icms.clone(),
+            p_bc_op: icms.p_bc_op,
+            uf_st: icms.uf_st.clone(),
+            v_bc_st_ret: icms.v_bc_st_ret,
+            p_st: icms.p_st,
+            v_icms_substituto: icms.v_icms_substituto,
+            v_bc_st_dest: icms.v_bc_st_dest,
+            p_cred_sn: icms.p_cred_sn,
+            q_trib: prod.q_trib,
+            q_bc_mono: icms.q_bc_mono,
+            ad_rem_icms: icms.ad_rem_icms,
+            q_bc_mono_reten: icms.q_bc_mono_reten,
+            ad_rem_icms_reten: icms.ad_rem_icms_reten,
+            p_red_ad_rem: icms.p_red_ad_rem,
+            mot_red_ad_rem: icms.mot_red_ad_rem.clone(),
+            q_bc_mono_ret: icms.q_bc_mono_ret,
+            ad_rem_icms_ret: icms.ad_rem_icms_ret,
+        }
+    }
+}
+
+impl IcmsValues {
+    /// Overwrites the computed fields of a stored ICMS group.
+    pub fn apply_to(&self, icms: &mut NFeIcms) {
+        icms.v_bc = self.v_bc;
+        icms.v_icms_op = self.v_icms_op;
+        icms.v_icms_dif = self.v_icms_dif;
+        icms.v_icms = self.v_icms;
+        icms.v_bc_fcp = self.v_bc_fcp;
+        icms.v_fcp = self.v_fcp;
+        icms.v_bc_st = self.v_bc_st;
+        icms.v_icms_st = self.v_icms_st;
+        icms.v_bc_fcp_st = self.v_bc_fcp_st;
+        icms.v_fcp_st = self.v_fcp_st;
+        icms.v_icms_deson = self.v_icms_deson;
+        icms.v_icms_st_ret = self.v_icms_st_ret;
+        icms.v_icms_st_dest = self.v_icms_st_dest;
+        icms.v_cred_icms_sn = self.v_cred_icms_sn;
+        icms.q_bc_mono = self.q_bc_mono;
+        icms.v_icms_mono_op = self.v_icms_mono_op;
+        icms.v_icms_mono_dif = self.v_icms_mono_dif;
+        icms.v_icms_mono = self.v_icms_mono;
+        icms.q_bc_mono_reten = self.q_bc_mono_reten;
+        icms.v_icms_mono_reten = self.v_icms_mono_reten;
+        icms.q_bc_mono_ret = self.q_bc_mono_ret;
+        icms.v_icms_mono_ret = self.v_icms_mono_ret;
+    }
+}
+
+fn required(
+    value: Option<Decimal>,
+    field: &str,
+    group: IcmsGroup,
+) -> Result<Decimal, ValidationError> {
+    value.ok_or_else(|| {
+        ValidationError::new(
+            &format!("ICMS.{}", field),
+            format!("is required for {}", group.tag()),
+        )
+    })
+}
+
+/// Own operation: returns (vBC, vICMS) after the optional base reduction.
+fn own_icms(
+    input: &IcmsInput,
+    base: Decimal,
+    group: IcmsGroup,
+) -> Result<(Decimal, Decimal), ValidationError> {
+    let p_icms = required(input.p_icms, "pICMS", group)?;
+    let v_bc = reduce(base, input.p_red_bc);
+    Ok((v_bc, percent_of(v_bc, p_icms)))
+}
+
+/// Fills vBCFCP/vFCP on the own base.
+fn own_fcp(input: &IcmsInput, v_bc: Decimal, values: &mut IcmsValues) {
+    if let Some(p_fcp) = input.p_fcp {
+        values.v_bc_fcp = Some(v_bc);
+        values.v_fcp = Some(percent_of(v_bc, p_fcp));
+    }
+}
+
+/// Tax substitution: fills vBCST, vICMSST, vBCFCPST and vFCPST, deducting the own ICMS and FCP.
+fn substitution(
+    input: &IcmsInput,
+    own_icms: Decimal,
+    own_fcp: Decimal,
+    values: &mut IcmsValues,
+) -> Result<(), ValidationError> {
+    let group = values.group;
+    let p_icms_st = required(input.p_icms_st, "pICMSST", group)?;
+
+    // modBCST 6 uses the operation value itself; the other modes add the MVA when informed.
+    let st_base = match (input.mod_bc_st.as_deref(), input.p_mva_st) {
+        (Some("6"), _) | (_, None) => input.st_base(),
+        (_, Some(p_mva_st)) => {
+            input.st_base() * (Decimal::ONE_HUNDRED + p_mva_st) / Decimal::ONE_HUNDRED
+        }
+    };
+    let v_bc_st = reduce(st_base, input.p_red_bc_st);
+
+    values.v_bc_st = Some(v_bc_st);
+    values.v_icms_st = Some((percent_of(v_bc_st, p_icms_st) - own_icms).max(Decimal::ZERO));
+    if let Some(p_fcp_st) = input.p_fcp_st {
+        values.v_bc_fcp_st = Some(v_bc_st);
+        values.v_fcp_st = Some((percent_of(v_bc_st, p_fcp_st) - own_fcp).max(Decimal::ZERO));
+    }
+    Ok(())
+}
+
+/// ICMS that would be due without the relief, minus what is actually charged.
+fn relief(input: &IcmsInput, base: Decimal, charged: Decimal) -> Option<Decimal> {
+    match (&input.mot_des_icms, input.p_icms) {
+        (Some(_), Some(p_icms)) => {
+            Some((percent_of(round_value(base), p_icms) - charged).max(Decimal::ZERO))
+        }
+        _ => None,
+    }
+}
+
+/// ICMS previously retained by substitution (CST 60, ICMSST, CSOSN 500).
+fn retained(input: &IcmsInput, values: &mut IcmsValues) {
+    if let (Some(v_bc_st_ret), Some(p_st)) = (input.v_bc_st_ret, input.p_st) {
+        let substituto = input.v_icms_substituto.unwrap_or(Decimal::ZERO);
+        values.v_icms_st_ret =
+            Some((percent_of(v_bc_st_ret, p_st) - substituto).max(Decimal::ZERO));
+    }
+}
+
+/// Own ICMS used only as a deduction from the ST value (CST 30, CSOSN 201/202).
+fn reference_icms(input: &IcmsInput, base: Decimal) -> Decimal {
+    input
+        .p_icms
+        .map(|p_icms| percent_of(round_value(base), p_icms))
+        .unwrap_or(Decimal::ZERO)
+}
+
+/// Monophasic ICMS: returns (quantity, quantity x ad rem rate); the quantity defaults
+/// to `qTrib`.
+fn monophasic(
+    input: &IcmsInput,
+    quantity: Option<Decimal>,
+    ad_rem: Option<Decimal>,
+    field: &str,
+    group: IcmsGroup,
+) -> Result<(Decimal, Decimal), ValidationError> {
+    let ad_rem = required(ad_rem, field, group)?;
+    let quantity = quantity.unwrap_or(input.q_trib);
+    Ok((quantity, round_value(quantity * ad_rem)))
+}
+
+pub fn calculate(input: &IcmsInput) -> Result<IcmsValues, ValidationError> {
+    let group = input.group()?;
+    let base = input.operation_base();
+    let mut values = IcmsValues::empty(group);
+
+    match group {
+        IcmsGroup::Icms00 => {
+            let (v_bc, v_icms) = own_icms(input, base, group)?;
+            values.v_bc = Some(v_bc);
+            values.v_icms = Some(v_icms);
+            if let Some(p_fcp) = input.p_fcp {
+                values.v_fcp = Some(percent_of(v_bc, p_fcp));
+            }
+        }
+        IcmsGroup::Icms02 => {
+            let (q_bc_mono, v_icms_mono) = monophasic(
+                input,
+                input.q_bc_mono,
+                input.ad_rem_icms,
+                "adRemICMS",
+                group,
+            )?;
+            values.q_bc_mono = Some(q_bc_mono);
+            values.v_icms_mono = Some(v_icms_mono);
+        }
+        IcmsGroup::Icms15 => {
+            let (q_bc_mono, v_icms_mono) = monophasic(
+                input,
+                input.q_bc_mono,
+                input.ad_rem_icms,
+                "adRemICMS",
+                group,
+            )?;
+            let (q_bc_mono_reten, v_icms_mono_reten) = monophasic(
+                input,
+                input.q_bc_mono_reten,
+                input.ad_rem_icms_reten,
+                "adRemICMSReten",
+                group,
+            )?;
+            // The reduction of the ad rem rate (pRedAdRem) applies to the own operation only.
+            if input.p_red_ad_rem.is_some() {
+                match input.mot_red_ad_rem.as_deref() {
+                    Some("1") | Some("9") => {}
+                    _ => {
+                        return Err(ValidationError::new(
+                            "ICMS.motRedAdRem",
+                            "must be 1 or 9 when pRedAdRem is informed",
+                        ))
+                    }
+                }
+            }
+            values.q_bc_mono = Some(q_bc_mono);
+            values.v_icms_mono = Some(reduce(v_icms_mono, input.p_red_ad_rem));
+            values.q_bc_mono_reten = Some(q_bc_mono_reten);
+            values.v_icms_mono_reten = Some(v_icms_mono_reten);
+        }
+        IcmsGroup::Icms53 => {
+            // Every field of ICMS53 is optional: without the ad rem rate the deferral is
+            // reported by the CST alone.
+            if input.ad_rem_icms.is_some() {
+                let (q_bc_mono, v_icms_mono_op) = monophasic(
+                    input,
+                    input.q_bc_mono,
+                    input.ad_rem_icms,
+                    "adRemICMS",
+                    group,
+                )?;
+                let v_icms_mono_dif = input
+                    .p_dif
+                    .map(|p_dif| percent_of(v_icms_mono_op, p_dif))
+                    .unwrap_or(Decimal::ZERO);
+                values.q_bc_mono = Some(q_bc_mono);
+                values.v_icms_mono_op = Some(v_icms_mono_op);
+                values.v_icms_mono_dif = Some(v_icms_mono_dif);
+                values.v_icms_mono = Some(v_icms_mono_op - v_icms_mono_dif);
+            }
+        }
+        IcmsGroup::Icms61 => {
+            let (q_bc_mono_ret, v_icms_mono_ret) = monophasic(
+                input,
+                input.q_bc_mono_ret,
+                input.ad_rem_icms_ret,
+                "adRemICMSRet",
+                group,
+            )?;
+            values.q_bc_mono_ret = Some(q_bc_mono_ret);
+            values.v_icms_mono_ret = Some(v_icms_mono_ret);
+        }
+        IcmsGroup::Icms10 | IcmsGroup::Icms70 => {
+            if group == IcmsGroup::Icms70 {
+                required(input.p_red_bc, "pRedBC", group)?;
+            }
+            let (v_bc, v_icms) = own_icms(input, base, group)?;
+            values.v_bc = Some(v_bc);
+            values.v_icms = Some(v_icms);
+            own_fcp(input, v_bc, &mut values);
+            substitution(input, v_icms, values.v_fcp.unwrap_or_default(), &mut values)?;
+            if group == IcmsGroup::Icms70 {
+                values.v_icms_deson = relief(input, base, v_icms);
+            }
+        }
+        IcmsGroup::Icms20 => {
+            required(input.p_red_bc, "pRedBC", group)?;
+            let (v_bc, v_icms) = own_icms(input, base, group)?;
+            values.v_bc = Some(v_bc);
+            values.v_icms = Some(v_icms);
+            own_fcp(input, v_bc, &mut values);
+            values.v_icms_deson = relief(input, base, v_icms);
+        }
+        IcmsGroup::Icms30 => {
+            substitution(
+                input,
+                reference_icms(input, base),
+                Decimal::ZERO,
+                &mut values,
+            )?;
+            values.v_icms_deson = relief(input, base, Decimal::ZERO);
+        }
+        IcmsGroup::Icms40 => {
+            values.v_icms_deson = relief(input, base, Decimal::ZERO);
+        }
+        IcmsGroup::Icms51 => {
+            let (v_bc, v_icms_op) = own_icms(input, base, group)?;
+            let v_icms_dif = input
+                .p_dif
+                .map(|p_dif| percent_of(v_icms_op, p_dif))
+                .unwrap_or(Decimal::ZERO);
+            values.v_bc = Some(v_bc);
+            values.v_icms_op = Some(v_icms_op);
+            values.v_icms_dif = Some(v_icms_dif);
+            values.v_icms = Some(v_icms_op - v_icms_dif);
+            own_fcp(input, v_bc, &mut values);
+        }
+        IcmsGroup::Icms60 | IcmsGroup::Sn500 => retained(input, &mut values),
+        IcmsGroup::Icms90 => {
+            let mut v_icms = Decimal::ZERO;
+            if input.p_icms.is_some() {
+                let (v_bc, own) = own_icms(input, base, group)?;
+                values.v_bc = Some(v_bc);
+                values.v_icms = Some(own);
+                own_fcp(input, v_bc, &mut values);
+                v_icms = own;
+            }
+            if input.p_icms_st.is_some() {
+                substitution(input, v_icms, values.v_fcp.unwrap_or_default(), &mut values)?;
+            }
+            values.v_icms_deson = relief(input, base, v_icms);
+        }
+        IcmsGroup::IcmsPart => {
+            if input.uf_st.is_none() {
+                return Err(ValidationError::new(
+                    "ICMS.UFST",
+                    "is required for ICMSPart",
+                ));
+            }
+            let p_bc_op = input.p_bc_op.unwrap_or(Decimal::ONE_HUNDRED);
+            let own_base = base * p_bc_op / Decimal::ONE_HUNDRED;
+            let (v_bc, v_icms) = own_icms(input, own_base, group)?;
+            values.v_bc = Some(v_bc);
+            values.v_icms = Some(v_icms);
+            substitution(input, v_icms, Decimal::ZERO, &mut values)?;
+        }
+        IcmsGroup::IcmsSt => {
+            retained(input, &mut values);
+            let v_bc_st_dest = required(input.v_bc_st_dest, "vBCSTDest", group)?;
+            let p_icms_st = required(input.p_icms_st, "pICMSST", group)?;
+            values.v_icms_st_dest = Some(percent_of(v_bc_st_dest, p_icms_st));
+        }
+        IcmsGroup::Sn101 => {
+            let p_cred_sn = required(input.p_cred_sn, "pCredSN", group)?;
+            values.v_cred_icms_sn = Some(percent_of(round_value(base), p_cred_sn));
+        }
+        IcmsGroup::Sn102 => {}
+        IcmsGroup::Sn201 | IcmsGroup::Sn202 => {
+            substitution(
+                input,
+                reference_icms(input, base),
+                Decimal::ZERO,
+                &mut values,
+            )?;
+            if group == IcmsGroup::Sn201 {
+                let p_cred_sn = required(input.p_cred_sn, "pCredSN", group)?;
+                values.v_cred_icms_sn = Some(percent_of(round_value(base), p_cred_sn));
+            }
+        }
+        IcmsGroup::Sn900 => {
+            let mut v_icms = Decimal::ZERO;
+            if input.p_icms.is_some() {
+                let (v_bc, own) = own_icms(input, base, group)?;
+                values.v_bc = Some(v_bc);
+                values.v_icms = Some(own);
+                v_icms = own;
+            }
+            if input.p_icms_st.is_some() {
+                substitution(input, v_icms, Decimal::ZERO, &mut values)?;
+            }
+            if let Some(p_cred_sn) = input.p_cred_sn {
+                values.v_cred_icms_sn = Some(percent_of(round_value(base), p_cred_sn));
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn cst(code: &str) -> IcmsInput {
+        IcmsInput {
+            cst: Some(code.to_string()),
+            v_prod: dec!(1000.00),
+            ..Default::default()
+        }
+    }
+
+    fn csosn(code: &str) -> IcmsInput {
+        IcmsInput {
+            csosn: Some(code.to_string()),
+            v_prod: dec!(1000.00),
+            ..Default::default()
+        }
+    }
+
+    struct Case {
+        name: &'static str,
+        input: IcmsInput,
+        group: IcmsGroup,
+        // (vBC, vICMS, vBCST, vICMSST, vFCP, vFCPST, vICMSDeson)
+        expected: [Option<Decimal>; 7],
+    }
+
+    fn cases() -> Vec<Case> {
+        vec![
+            Case {
+                name: "CST 00 with FCP",
+                input: IcmsInput {
+                    p_icms: Some(dec!(18)),
+                    p_fcp: Some(dec!(2)),
+                    ..cst("00")
+                },
+                group: IcmsGroup::Icms00,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(180.00)),
+                    None,
+                    None,
+                    Some(dec!(20.00)),
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CST 00 with freight, insurance and discount",
+                input: IcmsInput {
+                    v_frete: dec!(50.00),
+                    v_seg: dec!(10.00),
+                    v_desc: dec!(60.00),
+                    p_icms: Some(dec!(17.5)),
+                    ..cst("00")
+                },
+                group: IcmsGroup::Icms00,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(175.00)),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CST 10 with IPI in the ST base",
+                input: IcmsInput {
+                    v_ipi: dec!(50.00),
+                    p_icms: Some(dec!(12)),
+                    p_fcp: Some(dec!(2)),
+                    mod_bc_st: Some("4".to_string()),
+                    p_mva_st: Some(dec!(40)),
+                    p_icms_st: Some(dec!(18)),
+                    p_fcp_st: Some(dec!(2)),
+                    ..cst("10")
+                },
+                group: IcmsGroup::Icms10,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(120.00)),
+                    Some(dec!(1470.00)),
+                    Some(dec!(144.60)),
+                    Some(dec!(20.00)),
+                    Some(dec!(9.40)),
+                    None,
+                ],
+            },
+            Case {
+                name: "CST 20 with relief",
+                input: IcmsInput {
+                    p_red_bc: Some(dec!(33.33)),
+                    p_icms: Some(dec!(18)),
+                    mot_des_icms: Some("9".to_string()),
+                    ..cst("20")
+                },
+                group: IcmsGroup::Icms20,
+                expected: [
+                    Some(dec!(666.70)),
+                    Some(dec!(120.01)),
+                    None,
+                    None,
+                    None,
+                    None,
+                    Some(dec!(59.99)),
+                ],
+            },
+            Case {
+                name: "CST 30 exempt with ST",
+                input: IcmsInput {
+                    p_icms: Some(dec!(12)),
+                    p_mva_st: Some(dec!(40)),
+                    p_icms_st: Some(dec!(18)),
+                    mot_des_icms: Some("7".to_string()),
+                    ..cst("30")
+                },
+                group: IcmsGroup::Icms30,
+                expected: [
+                    None,
+                    None,
+                    Some(dec!(1400.00)),
+                    Some(dec!(132.00)),
+                    None,
+                    None,
+                    Some(dec!(120.00)),
+                ],
+            },
+            Case {
+                name: "CST 40 exempt with relief",
+                input: IcmsInput {
+                    p_icms: Some(dec!(18)),
+                    mot_des_icms: Some("1".to_string()),
+                    ..cst("40")
+                },
+                group: IcmsGroup::Icms40,
+                expected: [None, None, None, None, None, None, Some(dec!(180.00))],
+            },
+            Case {
+                name: "CST 41 not taxed",
+                input: cst("41"),
+                group: IcmsGroup::Icms40,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CST 50 suspended",
+                input: cst("50"),
+                group: IcmsGroup::Icms40,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CST 51 with deferral",
+                input: IcmsInput {
+                    p_icms: Some(dec!(18)),
+                    p_dif: Some(dec!(40)),
+                    ..cst("51")
+                },
+                group: IcmsGroup::Icms51,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(108.00)),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CST 60 previously retained",
+                input: IcmsInput {
+                    v_bc_st_ret: Some(dec!(1200.00)),
+                    p_st: Some(dec!(18)),
+                    v_icms_substituto: Some(dec!(120.00)),
+                    ..cst("60")
+                },
+                group: IcmsGroup::Icms60,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CST 70 with reduced bases",
+                input: IcmsInput {
+                    p_red_bc: Some(dec!(10)),
+                    p_icms: Some(dec!(18)),
+                    p_mva_st: Some(dec!(30)),
+                    p_red_bc_st: Some(dec!(10)),
+                    p_icms_st: Some(dec!(18)),
+                    ..cst("70")
+                },
+                group: IcmsGroup::Icms70,
+                expected: [
+                    Some(dec!(900.00)),
+                    Some(dec!(162.00)),
+                    Some(dec!(1170.00)),
+                    Some(dec!(48.60)),
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CST 90 own operation only",
+                input: IcmsInput {
+                    p_icms: Some(dec!(7)),
+                    ..cst("90")
+                },
+                group: IcmsGroup::Icms90,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(70.00)),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "ICMSPart for CST 10",
+                input: IcmsInput {
+                    p_icms: Some(dec!(12)),
+                    mod_bc_st: Some("6".to_string()),
+                    p_mva_st: Some(dec!(40)),
+                    p_icms_st: Some(dec!(18)),
+                    p_bc_op: Some(dec!(100)),
+                    uf_st: Some("SP".to_string()),
+                    ..cst("10")
+                },
+                group: IcmsGroup::IcmsPart,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(120.00)),
+                    Some(dec!(1000.00)),
+                    Some(dec!(60.00)),
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "ICMSST repasse for CST 41",
+                input: IcmsInput {
+                    v_bc_st_ret: Some(dec!(1000.00)),
+                    p_st: Some(dec!(18)),
+                    v_icms_substituto: Some(dec!(100.00)),
+                    v_bc_st_dest: Some(dec!(1000.00)),
+                    p_icms_st: Some(dec!(12)),
+                    ..cst("41")
+                },
+                group: IcmsGroup::IcmsSt,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CSOSN 101 with credit",
+                input: IcmsInput {
+                    p_cred_sn: Some(dec!(2.56)),
+                    ..csosn("101")
+                },
+                group: IcmsGroup::Sn101,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CSOSN 400 not taxed",
+                input: csosn("400"),
+                group: IcmsGroup::Sn102,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CSOSN 201 with credit and ST",
+                input: IcmsInput {
+                    p_cred_sn: Some(dec!(1.25)),
+                    p_icms: Some(dec!(12)),
+                    p_mva_st: Some(dec!(50)),
+                    p_icms_st: Some(dec!(18)),
+                    ..csosn("201")
+                },
+                group: IcmsGroup::Sn201,
+                expected: [
+                    None,
+                    None,
+                    Some(dec!(1500.00)),
+                    Some(dec!(150.00)),
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CSOSN 203 ST without credit",
+                input: IcmsInput {
+                    p_icms: Some(dec!(7)),
+                    p_mva_st: Some(dec!(50)),
+                    p_icms_st: Some(dec!(17)),
+                    ..csosn("203")
+                },
+                group: IcmsGroup::Sn202,
+                expected: [
+                    None,
+                    None,
+                    Some(dec!(1500.00)),
+                    Some(dec!(185.00)),
+                    None,
+                    None,
+                    None,
+                ],
+            },
+            Case {
+                name: "CSOSN 500 previously retained",
+                input: IcmsInput {
+                    v_bc_st_ret: Some(dec!(800.00)),
+                    p_st: Some(dec!(17)),
+                    v_icms_substituto: Some(dec!(56.00)),
+                    ..csosn("500")
+                },
+                group: IcmsGroup::Sn500,
+                expected: [None, None, None, None, None, None, None],
+            },
+            Case {
+                name: "CSOSN 900 with own ICMS",
+                input: IcmsInput {
+                    p_icms: Some(dec!(18)),
+                    p_cred_sn: Some(dec!(2)),
+                    ..csosn("900")
+                },
+                group: IcmsGroup::Sn900,
+                expected: [
+                    Some(dec!(1000.00)),
+                    Some(dec!(180.00)),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn computes_every_group() {
+        for case in cases() {
+            let values = calculate(&case.input)
+                .unwrap_or_else(|e| panic!("{}: unexpected error {}", case.name, e));
+            assert_eq!(values.group, case.group, "{}", case.name);
+            let actual = [
+                values.v_bc,
+                values.v_icms,
+                values.v_bc_st,
+                values.v_icms_st,
+                values.v_fcp,
+                values.v_fcp_st,
+                values.v_icms_deson,
+            ];
+            assert_eq!(actual, case.expected, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn computes_group_specific_values() {
+        let deferred = calculate(&IcmsInput {
+            p_icms: Some(dec!(18)),
+            p_dif: Some(dec!(40)),
+            ..cst("51")
+        })
+        .unwrap();
+        assert_eq!(deferred.v_icms_op, Some(dec!(180.00)));
+        assert_eq!(deferred.v_icms_dif, Some(dec!(72.00)));
+
+        let retained = calculate(&IcmsInput {
+            v_bc_st_ret: Some(dec!(1200.00)),
+            p_st: Some(dec!(18)),
+            v_icms_substituto: Some(dec!(120.00)),
+            ..cst("60")
+        })
+        .unwrap();
+        assert_eq!(retained.v_icms_st_ret, Some(dec!(96.00)));
+
+        let repasse = calculate(&IcmsInput {
+            v_bc_st_ret: Some(dec!(1000.00)),
+            p_st: Some(dec!(18)),
+            v_icms_substituto: Some(dec!(100.00)),
+            v_bc_st_dest: Some(dec!(1000.00)),
+            p_icms_st: Some(dec!(12)),
+            ..cst("60")
+        })
+        .unwrap();
+        assert_eq!(repasse.group, IcmsGroup::IcmsSt);
+        assert_eq!(repasse.v_icms_st_ret, Some(dec!(80.00)));
+        assert_eq!(repasse.v_icms_st_dest, Some(dec!(120.00)));
+
+        let credit = calculate(&IcmsInput {
+            p_cred_sn: Some(dec!(2.56)),
+            ..csosn("101")
+        })
+        .unwrap();
+        assert_eq!(credit.v_cred_icms_sn, Some(dec!(25.60)));
+    }
+
+    fn fuel(code: &str) -> IcmsInput {
+        IcmsInput {
+            q_trib: dec!(1500.0000),
+            ..cst(code)
+        }
+    }
+
+    #[test]
+    fn computes_monophasic_fuel_groups() {
+        // (name, input, group, (qBCMono, vICMSMonoOp, vICMSMonoDif, vICMSMono),
+        //  (qBCMonoReten, vICMSMonoReten), (qBCMonoRet, vICMSMonoRet))
+        #[allow(clippy::type_complexity)]
+        let cases: Vec<(
+            &str,
+            IcmsInput,
+            IcmsGroup,
+            [Option<Decimal>; 4],
+            [Option<Decimal>; 2],
+            [Option<Decimal>; 2],
+        )> = vec![
+            (
+                "CST 02 on the taxable quantity",
+                IcmsInput {
+                    ad_rem_icms: Some(dec!(1.2200)),
+                    ..fuel("02")
+                },
+                IcmsGroup::Icms02,
+                [Some(dec!(1500.0000)), None, None, Some(dec!(1830.00))],
+                [None, None],
+                [None, None],
+            ),
+            (
+                "CST 02 with an informed quantity",
+                IcmsInput {
+                    q_bc_mono: Some(dec!(1000.5000)),
+                    ad_rem_icms: Some(dec!(0.9456)),
+                    ..fuel("02")
+                },
+                IcmsGroup::Icms02,
+                [Some(dec!(1000.5000)), None, None, Some(dec!(946.07))],
+                [None, None],
+                [None, None],
+            ),
+            (
+                "CST 15 with retention and a reduced rate",
+                IcmsInput {
+                    ad_rem_icms: Some(dec!(1.2200)),
+                    q_bc_mono_reten: Some(dec!(500.0000)),
+                    ad_rem_icms_reten: Some(dec!(1.1000)),
+                    p_red_ad_rem: Some(dec!(10)),
+                    mot_red_ad_rem: Some("1".to_string()),
+                    ..fuel("15")
+                },
+                IcmsGroup::Icms15,
+                [Some(dec!(1500.0000)), None, None, Some(dec!(1647.00))],
+                [Some(dec!(500.0000)), Some(dec!(550.00))],
+                [None, None],
+            ),
+            (
+                "CST 53 with deferral",
+                IcmsInput {
+                    ad_rem_icms: Some(dec!(1.2200)),
+                    p_dif: Some(dec!(25)),
+                    ..fuel("53")
+                },
+                IcmsGroup::Icms53,
+                [
+                    Some(dec!(1500.0000)),
+                    Some(dec!(1830.00)),
+                    Some(dec!(457.50)),
+                    Some(dec!(1372.50)),
+                ],
+                [None, None],
+                [None, None],
+            ),
+            (
+                "CST 53 without values",
+                fuel("53"),
+                IcmsGroup::Icms53,
+                [None, None, None, None],
+                [None, None],
+                [None, None],
+            ),
+            (
+                "CST 61 previously charged",
+                IcmsInput {
+                    ad_rem_icms_ret: Some(dec!(1.2200)),
+                    ..fuel("61")
+                },
+                IcmsGroup::Icms61,
+                [None, None, None, None],
+                [None, None],
+                [Some(dec!(1500.0000)), Some(dec!(1830.00))],
+            ),
+        ];
+        for (name, input, group, mono, reten, ret) in cases {
+            let values =
+                calculate(&input).unwrap_or_else(|e| panic!("{}: unexpected error {}", name, e));
+            assert_eq!(values.group, group, "{}", name);
+            assert_eq!(
+                [
+                    values.q_bc_mono,
+                    values.v_icms_mono_op,
+                    values.v_icms_mono_dif,
+                    values.v_icms_mono,
+                ],
+                mono,
+                "{}",
+                name
+            );
+            assert_eq!(
+                [values.q_bc_mono_reten, values.v_icms_mono_reten],
+                reten,
+                "{}",
+                name
+            );
+            assert_eq!(
+                [values.q_bc_mono_ret, values.v_icms_mono_ret],
+                ret,
+                "{}",
+                name
+            );
+            assert_eq!(values.v_icms, None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rounds_ties_to_even() {
+        let cases = [
+            (dec!(1.00), dec!(0.5), dec!(0.00)),
+            (dec!(3.00), dec!(0.5), dec!(0.02)),
+            (dec!(10.05), dec!(17.5), dec!(1.76)),
+        ];
+        for (v_prod, p_icms, expected) in cases {
+            let values = calculate(&IcmsInput {
+                v_prod,
+                p_icms: Some(p_icms),
+                ..cst("00")
+            })
+            .unwrap();
+            assert_eq!(values.v_icms, Some(expected), "{} x {}%", v_prod, p_icms);
+        }
+    }
+
+    #[test]
+    fn includes_ipi_in_base_for_final_consumer() {
+        let values = calculate(&IcmsInput {
+            v_ipi: dec!(100.00),
+            ipi_in_bc: true,
+            p_icms: Some(dec!(18)),
+            ..cst("00")
+        })
+        .unwrap();
+        assert_eq!(values.v_bc, Some(dec!(1100.00)));
+        assert_eq!(values.v_icms, Some(dec!(198.00)));
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let cases = [
+            (cst("00"), "ICMS.pICMS"),
+            (cst("20"), "ICMS.pRedBC"),
+            (
+                IcmsInput {
+                    p_icms: Some(dec!(12)),
+                    ..cst("10")
+                },
+                "ICMS.pICMSST",
+            ),
+            (fuel("02"), "ICMS.adRemICMS"),
+            (
+                IcmsInput {
+                    ad_rem_icms: Some(dec!(1.22)),
+                    ..fuel("15")
+                },
+                "ICMS.adRemICMSReten",
+            ),
+            (
+                IcmsInput {
+                    ad_rem_icms: Some(dec!(1.22)),
+                    ad_rem_icms_reten: Some(dec!(1.10)),
+                    p_red_ad_rem: Some(dec!(10)),
+                    ..fuel("15")
+                },
+                "ICMS.motRedAdRem",
+            ),
+            (fuel("61"), "ICMS.adRemICMSRet"),
+            (cst("99"), "ICMS.CST"),
+            (csosn("999"), "ICMS.CSOSN"),
+            (csosn("101"), "ICMS.pCredSN"),
+            (
+                IcmsInput {
+                    csosn: Some("102".to_string()),
+                    ..cst("00")
+                },
+                "ICMS",
+            ),
+        ];
+        for (input, field) in cases {
+            let err = calculate(&input).unwrap_err();
+            assert_eq!(err.field, field);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! IPI calculation for the `IPITrib` and `IPINT` groups of `TIpi`.
+
+use crate::errors::ValidationError;
+use crate::services::tax::Assessment;
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpiGroup {
+    Trib,
+    Nt,
+}
+
+impl IpiGroup {
+    pub fn resolve(cst: &str) -> Result<Self, ValidationError> {
+        match cst {
+            "00" | "49" | "50" | "99" => Ok(Self::Trib),
+            "01" | "02" | "03" | "04" | "05" | "51" | "52" | "53" | "54" | "55" => Ok(Self::Nt),
+            _ => Err(ValidationError::new("IPI.CST", "unsupported CST")),
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::Trib => "IPITrib",
+            Self::Nt => "IPINT",
+        }
+    }
+}
+
+/// `v_bc` is the item base used when the rate is ad valorem; `q_unid` the quantity used
+/// when it is per unit.
+#[derive(Debug, Clone, Default)]
+pub struct IpiInput {
+    pub cst: String,
+    pub v_bc: Decimal,
+    pub p_ipi: Option<Decimal>,
+    pub q_unid: Decimal,
+    pub v_unid: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpiValues {
+    pub group: IpiGroup,
+    pub assessment: Option<Assessment>,
+    pub v_ipi: Option<Decimal>,
+}
+
+pub fn calculate(input: &IpiInput) -> Result<IpiValues, ValidationError> {
+    let group = IpiGroup::resolve(&input.cst)?;
+    if group == IpiGroup::Nt {
+        return Ok(IpiValues {
+            group,
+            assessment: None,
+            v_ipi: None,
+        });
+    }
+
+    let assessment = Assessment::choose(
+        "IPI",
+        input.v_bc,
+        input.p_ipi,
+        input.q_unid,
+        input.v_unid,
+        ("pIPI", "vUnid"),
+    )?;
+    Ok(IpiValues {
+        group,
+        v_ipi: Some(assessment.amount()),
+        assessment: Some(assessment),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn computes_ipi() {
+        let cases = [
+            ("00", Some(dec!(10)), None, Some(dec!(100.00))),
+            ("50", Some(dec!(3.25)), None, Some(dec!(32.50))),
+            ("99", None, Some(dec!(0.1234)), Some(dec!(1.23))),
+            ("53", None, None, None),
+        ];
+        for (cst, p_ipi, v_unid, expected) in cases {
+            let values = calculate(&IpiInput {
+                cst: cst.to_string(),
+                v_bc: dec!(1000.00),
+                p_ipi,
+                q_unid: dec!(10),
+                v_unid,
+            })
+            .unwrap();
+            assert_eq!(values.v_ipi, expected, "CST {}", cst);
+        }
+    }
+
+    #[test]
+    fn requires_a_single_rate() {
+        let both = IpiInput {
+            cst: "00".to_string(),
+            p_ipi: Some(dec!(10)),
+            v_unid: Some(dec!(1)),
+            ..Default::default()
+        };
+        assert_eq!(calculate(&both).unwrap_err().field, "IPI");
+        let unknown = IpiInput {
+            cst: "10".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(calculate(&unknown).unwrap_err().field, "IPI.CST");
+    }
+}
@@ -0,0 +1,107 @@
+//! ISSQN calculation. The layout only defines an ad valorem rate (`vAliq`) for this tax.
+
+use crate::errors::ValidationError;
+use crate::services::tax::percent_of;
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Default)]
+pub struct IssqnInput {
+    /// Service value before deductions.
+    pub v_serv: Decimal,
+    pub v_deducao: Decimal,
+    pub v_desc_incond: Decimal,
+    pub v_aliq: Decimal,
+    pub ind_iss: String,
+    pub ind_incentivo: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssqnValues {
+    pub v_bc: Decimal,
+    pub v_issqn: Decimal,
+}
+
+pub fn calculate(input: &IssqnInput) -> Result<IssqnValues, ValidationError> {
+    if !matches!(
+        input.ind_iss.as_str(),
+        "1" | "2" | "3" | "4" | "5" | "6" | "7"
+    ) {
+        return Err(ValidationError::new(
+            "ISSQN.indISS",
+            "must be between 1 and 7",
+        ));
+    }
+    if !matches!(input.ind_incentivo.as_str(), "1" | "2") {
+        return Err(ValidationError::new("ISSQN.indIncentivo", "must be 1 or 2"));
+    }
+    if input.v_aliq < Decimal::ZERO || input.v_aliq > Decimal::ONE_HUNDRED {
+        return Err(ValidationError::new(
+            "ISSQN.vAliq",
+            "must be a percentage between 0 and 100",
+        ));
+    }
+
+    let v_bc = (input.v_serv - input.v_deducao - input.v_desc_incond).max(Decimal::ZERO);
+    Ok(IssqnValues {
+        v_bc,
+        v_issqn: percent_of(v_bc, input.v_aliq),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn computes_issqn() {
+        let cases = [
+            (
+                dec!(1000.00),
+                dec!(0),
+                dec!(0),
+                dec!(5),
+                dec!(1000.00),
+                dec!(50.00),
+            ),
+            (
+                dec!(1000.00),
+                dec!(200.00),
+                dec!(0),
+                dec!(2.5),
+                dec!(800.00),
+                dec!(20.00),
+            ),
+            (
+                dec!(333.33),
+                dec!(0),
+                dec!(33.33),
+                dec!(3.01),
+                dec!(300.00),
+                dec!(9.03),
+            ),
+        ];
+        for (v_serv, v_deducao, v_desc_incond, v_aliq, v_bc, v_issqn) in cases {
+            let values = calculate(&IssqnInput {
+                v_serv,
+                v_deducao,
+                v_desc_incond,
+                v_aliq,
+                ind_iss: "1".to_string(),
+                ind_incentivo: "2".to_string(),
+            })
+            .unwrap();
+            assert_eq!(values, IssqnValues { v_bc, v_issqn });
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_indicators() {
+        let input = IssqnInput {
+            ind_iss: "8".to_string(),
+            ind_incentivo: "2".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(calculate(&input).unwrap_err().field, "ISSQN.indISS");
+    }
+}
@@ -0,0 +1,232 @@
+pub mod icms;
+pub mod ipi;
+pub mod issqn;
+pub mod pis_cofins;
+pub mod totals;
+
+use crate::errors::ValidationError;
+use crate::models::nfe_item::NFeProduct;
+use crate::models::nfe_item_tax::NFeItemTaxes;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// Rounds a monetary value to 2 decimal places following ABNT NBR 5891 (ties to even),
+/// the rule adopted by the NF-e taxpayer manual.
+pub fn round_value(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(2, RoundingStrategy::MidpointNearestEven)
+}
+
+/// `value x rate / 100`, rounded to 2 decimal places.
+pub fn percent_of(value: Decimal, rate: Decimal) -> Decimal {
+    round_value(value * rate / Decimal::ONE_HUNDRED)
+}
+
+/// Applies a base reduction percentage (`pRedBC`, `pRedBCST`) to a value, rounded.
+pub fn reduce(value: Decimal, reduction: Option<Decimal>) -> Decimal {
+    match reduction {
+        Some(p) => round_value(value * (Decimal::ONE_HUNDRED - p) / Decimal::ONE_HUNDRED),
+        None => round_value(value),
+    }
+}
+
+/// How IPI, PIS and COFINS are assessed: a percentage over a value base, or an amount per
+/// unit (`qBCProd` x `vAliqProd`, `qUnid` x `vUnid`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Assessment {
+    AdValorem {
+        v_bc: Decimal,
+        rate: Decimal,
+    },
+    PerUnit {
+        quantity: Decimal,
+        unit_value: Decimal,
+    },
+}
+
+impl Assessment {
+    /// Picks the mode from whichever rate was informed; exactly one must be present.
+    pub fn choose(
+        tax: &str,
+        v_bc: Decimal,
+        rate: Option<Decimal>,
+        quantity: Decimal,
+        unit_value: Option<Decimal>,
+        fields: (&str, &str),
+    ) -> Result<Self, ValidationError> {
+        match (rate, unit_value) {
+            (Some(rate), None) => Ok(Self::AdValorem {
+                v_bc: round_value(v_bc),
+                rate,
+            }),
+            (None, Some(unit_value)) => Ok(Self::PerUnit {
+                quantity,
+                unit_value,
+            }),
+            _ => Err(ValidationError::new(
+                tax,
+                format!(
+                    "exactly one of {} or {} must be informed",
+                    fields.0, fields.1
+                ),
+            )),
+        }
+    }
+
+    pub fn amount(&self) -> Decimal {
+        match self {
+            Self::AdValorem { v_bc, rate } => percent_of(*v_bc, *rate),
+            Self::PerUnit {
+                quantity,
+                unit_value,
+            } => round_value(quantity * unit_value),
+        }
+    }
+
+    /// Splits an optional assessment into the (vBC, rate, quantity, unit value) fields.
+    pub fn fields(
+        assessment: &Option<Self>,
+    ) -> (
+        Option<Decimal>,
+        Option<Decimal>,
+        Option<Decimal>,
+        Option<Decimal>,
+    ) {
+        match assessment {
+            Some(Self::AdValorem { v_bc, rate }) => (Some(*v_bc), Some(*rate), None, None),
+            Some(Self::PerUnit {
+                quantity,
+                unit_value,
+            }) => (None, None, Some(*quantity), Some(*unit_value)),
+            None => (None, None, None, None),
+        }
+    }
+}
+
+/// Recomputes every tax value of an item from its product values and rates, replacing the
+/// values sent by the client. Informed bases (`vBC`, `qBCProd`) are kept because they may
+/// legitimately differ from the item value; missing ones default to the item.
+///
+/// `ipi_in_icms_base` is set for sales to final consumers, where IPI integrates the ICMS base.
+pub fn compute_item_taxes(
+    prod: &NFeProduct,
+    taxes: &mut NFeItemTaxes,
+    ipi_in_icms_base: bool,
+) -> Result<(), ValidationError> {
+    let item_base = prod.v_prod
+        + prod.v_frete.unwrap_or_default()
+        + prod.v_seg.unwrap_or_default()
+        + prod.v_outro.unwrap_or_default()
+        - prod.v_desc.unwrap_or_default();
+
+    let mut v_ipi = Decimal::ZERO;
+    if let Some(ipi) = taxes.ipi.as_mut() {
+        let values = ipi::calculate(&ipi::IpiInput {
+            cst: ipi.cst.clone(),
+            v_bc: ipi.v_bc.unwrap_or(item_base),
+            p_ipi: ipi.p_ipi,
+            q_unid: ipi.q_unid.unwrap_or(prod.q_trib),
+            v_unid: ipi.v_unid,
+        })?;
+        (ipi.v_bc, ipi.p_ipi, ipi.q_unid, ipi.v_unid) = Assessment::fields(&values.assessment);
+        ipi.v_ipi = values.v_ipi;
+        v_ipi = values.v_ipi.unwrap_or_default();
+    }
+
+    if let Some(icms) = taxes.icms.as_mut() {
+        let values = icms::calculate(&icms::IcmsInput::from_item(
+            prod,
+            icms,
+            v_ipi,
+            ipi_in_icms_base,
+        ))?;
+        values.apply_to(icms);
+    }
+
+    if let Some(pis) = taxes.pis.as_mut() {
+        let values = pis_cofins::calculate(
+            "PIS",
+            &pis.cst,
+            &pis_cofins::ContributionInput {
+                v_bc: pis.v_bc.unwrap_or(item_base),
+                p_aliq: pis.p_pis,
+                q_bc_prod: pis.q_bc_prod.unwrap_or(prod.q_trib),
+                v_aliq_prod: pis.v_aliq_prod,
+            },
+        )?;
+        (pis.v_bc, pis.p_pis, pis.q_bc_prod, pis.v_aliq_prod) =
+            Assessment::fields(&values.assessment);
+        pis.v_pis = values.value;
+    }
+
+    if let Some(pis_st) = taxes.pis_st.as_mut() {
+        let values = pis_cofins::calculate_st(
+            "PIS",
+            &pis_cofins::ContributionInput {
+                v_bc: pis_st.v_bc.unwrap_or(item_base),
+                p_aliq: pis_st.p_pis,
+                q_bc_prod: pis_st.q_bc_prod.unwrap_or(prod.q_trib),
+                v_aliq_prod: pis_st.v_aliq_prod,
+            },
+        )?;
+        (
+            pis_st.v_bc,
+            pis_st.p_pis,
+            pis_st.q_bc_prod,
+            pis_st.v_aliq_prod,
+        ) = Assessment::fields(&values.assessment);
+        pis_st.v_pis = values.value;
+    }
+
+    if let Some(cofins) = taxes.cofins.as_mut() {
+        let values = pis_cofins::calculate(
+            "COFINS",
+            &cofins.cst,
+            &pis_cofins::ContributionInput {
+                v_bc: cofins.v_bc.unwrap_or(item_base),
+                p_aliq: cofins.p_cofins,
+                q_bc_prod: cofins.q_bc_prod.unwrap_or(prod.q_trib),
+                v_aliq_prod: cofins.v_aliq_prod,
+            },
+        )?;
+        (
+            cofins.v_bc,
+            cofins.p_cofins,
+            cofins.q_bc_prod,
+            cofins.v_aliq_prod,
+        ) = Assessment::fields(&values.assessment);
+        cofins.v_cofins = values.value;
+    }
+
+    if let Some(cofins_st) = taxes.cofins_st.as_mut() {
+        let values = pis_cofins::calculate_st(
+            "COFINS",
+            &pis_cofins::ContributionInput {
+                v_bc: cofins_st.v_bc.unwrap_or(item_base),
+                p_aliq: cofins_st.p_cofins,
+                q_bc_prod: cofins_st.q_bc_prod.unwrap_or(prod.q_trib),
+                v_aliq_prod: cofins_st.v_aliq_prod,
+            },
+        )?;
+        (
+            cofins_st.v_bc,
+            cofins_st.p_cofins,
+            cofins_st.q_bc_prod,
+            cofins_st.v_aliq_prod,
+        ) = Assessment::fields(&values.assessment);
+        cofins_st.v_cofins = values.value;
+    }
+
+    if let Some(issqn) = taxes.issqn.as_mut() {
+        let values = issqn::calculate(&issqn::IssqnInput {
+            v_serv: prod.v_prod,
+            v_deducao: issqn.v_deducao.unwrap_or_default(),
+            v_desc_incond: issqn.v_desc_incond.unwrap_or_default(),
+            v_aliq: issqn.v_aliq,
+            ind_iss: issqn.ind_iss.clone(),
+            ind_incentivo: issqn.ind_incentivo.clone(),
+        })?;
+        issqn.v_bc = Some(values.v_bc);
+        issqn.v_issqn = Some(values.v_issqn);
+    }
+
+    Ok(())
+}
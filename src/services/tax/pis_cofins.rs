@@ -0,0 +1,209 @@
+//! PIS and COFINS share the same groups (Aliq, Qtde, NT, Outr and ST); only the element
+//! names differ, so both are computed here with the tax name as a parameter.
+
+use crate::errors::ValidationError;
+use crate::services::tax::Assessment;
+use rust_decimal::Decimal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContributionGroup {
+    Aliq,
+    Qtde,
+    Nt,
+    Outr,
+}
+
+impl ContributionGroup {
+    pub fn resolve(tax: &str, cst: &str) -> Result<Self, ValidationError> {
+        match cst {
+            "01" | "02" => Ok(Self::Aliq),
+            "03" => Ok(Self::Qtde),
+            "04" | "05" | "06" | "07" | "08" | "09" => Ok(Self::Nt),
+            "49" | "50" | "51" | "52" | "53" | "54" | "55" | "56" | "60" | "61" | "62" | "63"
+            | "64" | "65" | "66" | "67" | "70" | "71" | "72" | "73" | "74" | "75" | "98" | "99" => {
+                Ok(Self::Outr)
+            }
+            _ => Err(ValidationError::new(
+                &format!("{}.CST", tax),
+                "unsupported CST",
+            )),
+        }
+    }
+
+    /// Element name of the subgroup, e.g. `PISAliq` or `COFINSNT`.
+    pub fn tag(&self, tax: &str) -> String {
+        let suffix = match self {
+            Self::Aliq => "Aliq",
+            Self::Qtde => "Qtde",
+            Self::Nt => "NT",
+            Self::Outr => "Outr",
+        };
+        format!("{}{}", tax, suffix)
+    }
+}
+
+/// `v_bc` is the item base for ad valorem rates; `q_bc_prod` the quantity for per-unit rates.
+#[derive(Debug, Clone, Default)]
+pub struct ContributionInput {
+    pub v_bc: Decimal,
+    pub p_aliq: Option<Decimal>,
+    pub q_bc_prod: Decimal,
+    pub v_aliq_prod: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContributionValues {
+    pub group: Option<ContributionGroup>,
+    pub assessment: Option<Assessment>,
+    pub value: Option<Decimal>,
+}
+
+fn assess(
+    tax: &str,
+    input: &ContributionInput,
+    allow_ad_valorem: bool,
+    allow_per_unit: bool,
+) -> Result<Assessment, ValidationError> {
+    let p_aliq = if allow_ad_valorem { input.p_aliq } else { None };
+    let v_aliq_prod = if allow_per_unit {
+        input.v_aliq_prod
+    } else {
+        None
+    };
+    let rate_field = format!("p{}", tax);
+    Assessment::choose(
+        tax,
+        input.v_bc,
+        p_aliq,
+        input.q_bc_prod,
+        v_aliq_prod,
+        (&rate_field, "vAliqProd"),
+    )
+}
+
+/// `tax` is `PIS` or `COFINS`.
+pub fn calculate(
+    tax: &str,
+    cst: &str,
+    input: &ContributionInput,
+) -> Result<ContributionValues, ValidationError> {
+    let group = ContributionGroup::resolve(tax, cst)?;
+    let assessment = match group {
+        ContributionGroup::Aliq => Some(assess(tax, input, true, false)?),
+        ContributionGroup::Qtde => Some(assess(tax, input, false, true)?),
+        ContributionGroup::Nt => None,
+        ContributionGroup::Outr => Some(assess(tax, input, true, true)?),
+    };
+    Ok(ContributionValues {
+        group: Some(group),
+        value: assessment.as_ref().map(Assessment::amount),
+        assessment,
+    })
+}
+
+/// `PISST` / `COFINSST`, which carry no CST.
+pub fn calculate_st(
+    tax: &str,
+    input: &ContributionInput,
+) -> Result<ContributionValues, ValidationError> {
+    let assessment = assess(&format!("{}ST", tax), input, true, true)?;
+    Ok(ContributionValues {
+        group: None,
+        value: Some(assessment.amount()),
+        assessment: Some(assessment),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn input(p_aliq: Option<Decimal>, v_aliq_prod: Option<Decimal>) -> ContributionInput {
+        ContributionInput {
+            v_bc: dec!(1000.00),
+            p_aliq,
+            q_bc_prod: dec!(250.0000),
+            v_aliq_prod,
+        }
+    }
+
+    #[test]
+    fn computes_every_group() {
+        let cases = [
+            (
+                "PIS",
+                "01",
+                input(Some(dec!(1.65)), None),
+                Some(dec!(16.50)),
+                ContributionGroup::Aliq,
+            ),
+            (
+                "COFINS",
+                "01",
+                input(Some(dec!(7.6)), None),
+                Some(dec!(76.00)),
+                ContributionGroup::Aliq,
+            ),
+            (
+                "PIS",
+                "02",
+                input(Some(dec!(0.65)), None),
+                Some(dec!(6.50)),
+                ContributionGroup::Aliq,
+            ),
+            (
+                "PIS",
+                "03",
+                input(None, Some(dec!(0.0331))),
+                Some(dec!(8.28)),
+                ContributionGroup::Qtde,
+            ),
+            (
+                "COFINS",
+                "03",
+                input(None, Some(dec!(0.1526))),
+                Some(dec!(38.15)),
+                ContributionGroup::Qtde,
+            ),
+            ("PIS", "06", input(None, None), None, ContributionGroup::Nt),
+            (
+                "COFINS",
+                "49",
+                input(Some(dec!(3)), None),
+                Some(dec!(30.00)),
+                ContributionGroup::Outr,
+            ),
+            (
+                "PIS",
+                "99",
+                input(None, Some(dec!(0.0125))),
+                Some(dec!(3.12)),
+                ContributionGroup::Outr,
+            ),
+        ];
+        for (tax, cst, input, expected, group) in cases {
+            let values = calculate(tax, cst, &input).unwrap();
+            assert_eq!(values.value, expected, "{} CST {}", tax, cst);
+            assert_eq!(values.group, Some(group), "{} CST {}", tax, cst);
+        }
+    }
+
+    #[test]
+    fn computes_substitution() {
+        let ad_valorem = calculate_st("PIS", &input(Some(dec!(1.65)), None)).unwrap();
+        assert_eq!(ad_valorem.value, Some(dec!(16.50)));
+        let per_unit = calculate_st("COFINS", &input(None, Some(dec!(0.2)))).unwrap();
+        assert_eq!(per_unit.value, Some(dec!(50.00)));
+    }
+
+    #[test]
+    fn rejects_rates_outside_the_group() {
+        let err = calculate("PIS", "01", &input(None, Some(dec!(0.5)))).unwrap_err();
+        assert_eq!(err.field, "PIS");
+        let err = calculate("COFINS", "03", &input(Some(dec!(7.6)), None)).unwrap_err();
+        assert_eq!(err.field, "COFINS");
+        let err = calculate("PIS", "10", &input(None, None)).unwrap_err();
+        assert_eq!(err.field, "PIS.CST");
+    }
+}
@@ -0,0 +1,454 @@
+//! `total` group derived from the line items.
+//!
+//! Items carrying ISSQN are services: their value goes to `ISSQNtot.vServ` instead of
+//! `ICMSTot.vProd`, and their PIS/COFINS to `ISSQNtot`. `vNF` follows the taxpayer manual:
+//!
+//! vNF = vProd - vDesc - vICMSDeson + vST + vFCPST + vFrete + vSeg + vOutro + vII + vIPI
+//!       + vIPIDevol + vServ + vPISST + vCOFINSST
+//!
+//! where vICMSDeson only counts when `indDeduzDeson` = 1 and PISST/COFINSST only when
+//! `indSomaPISST` / `indSomaCOFINSST` = 1.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_item::NFeItem;
+use crate::models::nfe_total::{NFeIcmsTot, NFeIssqnTot, UpdateNFeTotal};
+use crate::services::tax::round_value;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+/// Largest difference accepted between a declared total and the derived one, absorbing
+/// per-item rounding on the client side.
+pub const TOTAL_TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+fn sum<F>(items: &[&NFeItem], value: F) -> Decimal
+where
+    F: Fn(&NFeItem) -> Option<Decimal>,
+{
+    round_value(items.iter().filter_map(|item| value(item)).sum())
+}
+
+/// Sum of a field that is omitted from ICMSTot when no item carries it.
+fn optional_sum<F>(items: &[&NFeItem], value: F) -> Option<Decimal>
+where
+    F: Fn(&NFeItem) -> Option<Decimal>,
+{
+    items
+        .iter()
+        .any(|item| value(item).is_some())
+        .then(|| sum(items, value))
+}
+
+/// `TDec_1302Opc` does not accept zero, so empty ISSQNtot amounts are omitted.
+fn non_zero(value: Decimal) -> Option<Decimal> {
+    (!value.is_zero()).then_some(value)
+}
+
+fn counts_in_total(item: &NFeItem) -> bool {
+    item.prod.ind_tot == "1"
+}
+
+/// Derives ICMSTot and, when any item is a service, ISSQNtot from the items.
+pub fn compute(
+    items: &[NFeItem],
+    d_compet: NaiveDate,
+    c_reg_trib: Option<String>,
+) -> (NFeIcmsTot, Option<NFeIssqnTot>) {
+    let all: Vec<&NFeItem> = items.iter().collect();
+    let (services, goods): (Vec<&NFeItem>, Vec<&NFeItem>) =
+        all.iter().partition(|item| item.imposto.issqn.is_some());
+
+    let icms = |item: &NFeItem| item.imposto.icms.clone();
+    let deducted_deson = sum(&goods, |item| {
+        icms(item)
+            .filter(|icms| icms.ind_deduz_deson.as_deref() == Some("1"))
+            .and_then(|icms| icms.v_icms_deson)
+    });
+    let summed_pis_st = sum(&all, |item| {
+        item.imposto
+            .pis_st
+            .as_ref()
+            .filter(|st| st.ind_soma_pis_st.as_deref() == Some("1"))
+            .and_then(|st| st.v_pis)
+    });
+    let summed_cofins_st = sum(&all, |item| {
+        item.imposto
+            .cofins_st
+            .as_ref()
+            .filter(|st| st.ind_soma_cofins_st.as_deref() == Some("1"))
+            .and_then(|st| st.v_cofins)
+    });
+    let v_serv = sum(&services, |item| {
+        counts_in_total(item).then_some(item.prod.v_prod)
+    });
+
+    let mut icms_tot = NFeIcmsTot {
+        v_bc: sum(&goods, |item| icms(item).and_then(|icms| icms.v_bc)),
+        v_icms: sum(&goods, |item| icms(item).and_then(|icms| icms.v_icms)),
+        v_icms_deson: sum(&goods, |item| icms(item).and_then(|icms| icms.v_icms_deson)),
+        v_fcp: sum(&goods, |item| icms(item).and_then(|icms| icms.v_fcp)),
+        v_bc_st: sum(&goods, |item| icms(item).and_then(|icms| icms.v_bc_st)),
+        v_st: sum(&goods, |item| icms(item).and_then(|icms| icms.v_icms_st)),
+        v_fcp_st: sum(&goods, |item| icms(item).and_then(|icms| icms.v_fcp_st)),
+        v_fcp_st_ret: sum(&goods, |item| icms(item).and_then(|icms| icms.v_fcp_st_ret)),
+        q_bc_mono: optional_sum(&goods, |item| icms(item).and_then(|icms| icms.q_bc_mono)),
+        v_icms_mono: optional_sum(&goods, |item| icms(item).and_then(|icms| icms.v_icms_mono)),
+        q_bc_mono_reten: optional_sum(&goods, |item| {
+            icms(item).and_then(|icms| icms.q_bc_mono_reten)
+        }),
+        v_icms_mono_reten: optional_sum(&goods, |item| {
+            icms(item).and_then(|icms| icms.v_icms_mono_reten)
+        }),
+        q_bc_mono_ret: optional_sum(&goods, |item| {
+            icms(item).and_then(|icms| icms.q_bc_mono_ret)
+        }),
+        v_icms_mono_ret: optional_sum(&goods, |item| {
+            icms(item).and_then(|icms| icms.v_icms_mono_ret)
+        }),
+        v_prod: sum(&goods, |item| {
+            counts_in_total(item).then_some(item.prod.v_prod)
+        }),
+        v_frete: sum(&all, |item| item.prod.v_frete),
+        v_seg: sum(&all, |item| item.prod.v_seg),
+        v_desc: sum(&all, |item| item.prod.v_desc),
+        v_ii: sum(&all, |item| item.imposto.ii.as_ref().map(|ii| ii.v_ii)),
+        v_ipi: sum(&all, |item| {
+            item.imposto.ipi.as_ref().and_then(|ipi| ipi.v_ipi)
+        }),
+        v_ipi_devol: sum(&all, |item| {
+            item.imposto_devol
+                .as_ref()
+                .map(|devol| devol.ipi.v_ipi_devol)
+        }),
+        v_pis: sum(&goods, |item| {
+            item.imposto.pis.as_ref().and_then(|pis| pis.v_pis)
+        }),
+        v_cofins: sum(&goods, |item| {
+            item.imposto
+                .cofins
+                .as_ref()
+                .and_then(|cofins| cofins.v_cofins)
+        }),
+        v_outro: sum(&all, |item| item.prod.v_outro),
+        v_nf: Decimal::ZERO,
+        v_tot_trib: optional_sum(&all, |item| item.imposto.v_tot_trib),
+    };
+    icms_tot.v_nf = icms_tot.v_prod - icms_tot.v_desc - deducted_deson
+        + icms_tot.v_st
+        + icms_tot.v_fcp_st
+        + icms_tot.v_frete
+        + icms_tot.v_seg
+        + icms_tot.v_outro
+        + icms_tot.v_ii
+        + icms_tot.v_ipi
+        + icms_tot.v_ipi_devol
+        + v_serv
+        + summed_pis_st
+        + summed_cofins_st;
+
+    if services.is_empty() {
+        return (icms_tot, None);
+    }
+
+    let issqn = |item: &NFeItem| item.imposto.issqn.clone();
+    let issqn_tot = NFeIssqnTot {
+        v_serv: non_zero(v_serv),
+        v_bc: non_zero(sum(&services, |item| issqn(item).and_then(|i| i.v_bc))),
+        v_iss: non_zero(sum(&services, |item| issqn(item).and_then(|i| i.v_issqn))),
+        v_pis: non_zero(sum(&services, |item| {
+            item.imposto.pis.as_ref().and_then(|pis| pis.v_pis)
+        })),
+        v_cofins: non_zero(sum(&services, |item| {
+            item.imposto
+                .cofins
+                .as_ref()
+                .and_then(|cofins| cofins.v_cofins)
+        })),
+        d_compet,
+        v_deducao: non_zero(sum(&services, |item| issqn(item).and_then(|i| i.v_deducao))),
+        v_outro: non_zero(sum(&services, |item| issqn(item).and_then(|i| i.v_outro))),
+        v_desc_incond: non_zero(sum(&services, |item| {
+            issqn(item).and_then(|i| i.v_desc_incond)
+        })),
+        v_desc_cond: non_zero(sum(&services, |item| {
+            issqn(item).and_then(|i| i.v_desc_cond)
+        })),
+        v_iss_ret: non_zero(sum(&services, |item| issqn(item).and_then(|i| i.v_iss_ret))),
+        c_reg_trib,
+    };
+    (icms_tot, Some(issqn_tot))
+}
+
+fn compare(
+    group: &str,
+    derived: Vec<(&'static str, Option<Decimal>)>,
+    declared: Vec<(&'static str, Option<Decimal>)>,
+) -> Result<(), ValidationError> {
+    for ((field, expected), (_, informed)) in derived.into_iter().zip(declared) {
+        let expected = expected.unwrap_or_default();
+        let informed = informed.unwrap_or_default();
+        if (expected - informed).abs() > TOTAL_TOLERANCE {
+            return Err(ValidationError::new(
+                &format!("{}.{}", group, field),
+                format!(
+                    "informed {} differs from the sum of the items ({})",
+                    informed, expected
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the totals declared by the client against the derived ones, naming the first
+/// field that differs by more than [`TOTAL_TOLERANCE`].
+pub fn check_declared(
+    icms_tot: &NFeIcmsTot,
+    issqn_tot: Option<&NFeIssqnTot>,
+    declared: &UpdateNFeTotal,
+) -> Result<(), ValidationError> {
+    if let Some(declared_icms) = &declared.icms_tot {
+        compare("ICMSTot", icms_tot.amounts(), declared_icms.amounts())?;
+    }
+    match (issqn_tot, &declared.issqn_tot) {
+        (Some(derived), Some(declared)) => {
+            compare("ISSQNtot", derived.amounts(), declared.amounts())
+        }
+        (None, Some(_)) => Err(ValidationError::new(
+            "ISSQNtot",
+            "no item is subject to ISSQN",
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_item::NFeProduct;
+    use crate::models::nfe_item_tax::{NFeIcms, NFeIpi, NFeIssqn, NFeItemTaxes, NFePis};
+    use chrono::Utc;
+    use rust_decimal_macros::dec;
+
+    fn item(v_prod: Decimal, imposto: NFeItemTaxes) -> NFeItem {
+        NFeItem {
+            internal_key: String::new(),
+            n_item: 1,
+            prod: NFeProduct {
+                c_prod: "1".to_string(),
+                c_ean: "SEM GTIN".to_string(),
+                x_prod: "Item".to_string(),
+                ncm: "61091000".to_string(),
+                cest: None,
+                cfop: "5102".to_string(),
+                u_com: "UN".to_string(),
+                q_com: dec!(1),
+                v_un_com: v_prod,
+                v_prod,
+                c_ean_trib: "SEM GTIN".to_string(),
+                u_trib: "UN".to_string(),
+                q_trib: dec!(1),
+                v_un_trib: v_prod,
+                v_frete: None,
+                v_seg: None,
+                v_desc: None,
+                v_outro: None,
+                ind_tot: "1".to_string(),
+                specific: Default::default(),
+            },
+            imposto,
+            imposto_devol: None,
+            inf_ad_prod: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn d_compet() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 20).unwrap()
+    }
+
+    fn sample_items() -> Vec<NFeItem> {
+        let mut goods = item(
+            dec!(1000.00),
+            NFeItemTaxes {
+                icms: Some(NFeIcms {
+                    cst: Some("10".to_string()),
+                    v_bc: Some(dec!(1000.00)),
+                    v_icms: Some(dec!(180.00)),
+                    v_bc_st: Some(dec!(1400.00)),
+                    v_icms_st: Some(dec!(72.00)),
+                    v_icms_deson: Some(dec!(10.00)),
+                    ind_deduz_deson: Some("1".to_string()),
+                    ..Default::default()
+                }),
+                ipi: Some(NFeIpi {
+                    cst: "50".to_string(),
+                    v_ipi: Some(dec!(100.00)),
+                    ..Default::default()
+                }),
+                pis: Some(NFePis {
+                    cst: "01".to_string(),
+                    v_pis: Some(dec!(16.50)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        goods.prod.v_frete = Some(dec!(50.00));
+        goods.prod.v_desc = Some(dec!(20.00));
+        let mut gift = item(dec!(30.00), NFeItemTaxes::default());
+        gift.prod.ind_tot = "0".to_string();
+        let service = item(
+            dec!(200.00),
+            NFeItemTaxes {
+                issqn: Some(NFeIssqn {
+                    v_bc: Some(dec!(200.00)),
+                    v_aliq: dec!(5),
+                    v_issqn: Some(dec!(10.00)),
+                    ..Default::default()
+                }),
+                pis: Some(NFePis {
+                    cst: "01".to_string(),
+                    v_pis: Some(dec!(3.30)),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        vec![goods, gift, service]
+    }
+
+    #[test]
+    fn derives_totals_from_items() {
+        let (icms_tot, issqn_tot) = compute(&sample_items(), d_compet(), None);
+        assert_eq!(icms_tot.v_prod, dec!(1000.00));
+        assert_eq!(icms_tot.v_bc, dec!(1000.00));
+        assert_eq!(icms_tot.v_icms, dec!(180.00));
+        assert_eq!(icms_tot.v_st, dec!(72.00));
+        assert_eq!(icms_tot.v_pis, dec!(16.50));
+        // 1000 - 20 - 10 + 72 + 50 + 100 + 200
+        assert_eq!(icms_tot.v_nf, dec!(1392.00));
+        assert_eq!(icms_tot.v_tot_trib, None);
+
+        let issqn_tot = issqn_tot.unwrap();
+        assert_eq!(issqn_tot.v_serv, Some(dec!(200.00)));
+        assert_eq!(issqn_tot.v_iss, Some(dec!(10.00)));
+        assert_eq!(issqn_tot.v_pis, Some(dec!(3.30)));
+        assert_eq!(issqn_tot.v_cofins, None);
+    }
+
+    #[test]
+    fn adds_the_returned_ipi_to_the_note_value() {
+        use crate::models::nfe_item::{NFeReturnedIpi, NFeReturnedTax};
+
+        let mut items = sample_items();
+        for (item, v_ipi_devol) in items.iter_mut().zip([dec!(7.25), dec!(0.75)]) {
+            item.imposto_devol = Some(NFeReturnedTax {
+                p_devol: dec!(100.00),
+                ipi: NFeReturnedIpi { v_ipi_devol },
+            });
+        }
+        let (icms_tot, _) = compute(&items, d_compet(), None);
+        assert_eq!(icms_tot.v_ipi_devol, dec!(8.00));
+        assert_eq!(icms_tot.v_nf, dec!(1400.00));
+    }
+
+    #[test]
+    fn sums_monophasic_fuel_totals() {
+        let fuel = |cst: &str, icms: NFeIcms| {
+            item(
+                dec!(500.00),
+                NFeItemTaxes {
+                    icms: Some(NFeIcms {
+                        cst: Some(cst.to_string()),
+                        ..icms
+                    }),
+                    ..Default::default()
+                },
+            )
+        };
+        let items = vec![
+            fuel(
+                "02",
+                NFeIcms {
+                    q_bc_mono: Some(dec!(100.0000)),
+                    v_icms_mono: Some(dec!(122.00)),
+                    ..Default::default()
+                },
+            ),
+            fuel(
+                "53",
+                NFeIcms {
+                    q_bc_mono: Some(dec!(50.0000)),
+                    v_icms_mono: Some(dec!(45.75)),
+                    ..Default::default()
+                },
+            ),
+            fuel(
+                "61",
+                NFeIcms {
+                    q_bc_mono_ret: Some(dec!(80.0000)),
+                    v_icms_mono_ret: Some(dec!(97.60)),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let (icms_tot, _) = compute(&items, d_compet(), None);
+        assert_eq!(icms_tot.q_bc_mono, Some(dec!(150.00)));
+        assert_eq!(icms_tot.v_icms_mono, Some(dec!(167.75)));
+        assert_eq!(icms_tot.q_bc_mono_reten, None);
+        assert_eq!(icms_tot.v_icms_mono_reten, None);
+        assert_eq!(icms_tot.q_bc_mono_ret, Some(dec!(80.00)));
+        assert_eq!(icms_tot.v_icms_mono_ret, Some(dec!(97.60)));
+        // The monophasic ICMS is part of the price and does not add to vNF.
+        assert_eq!(icms_tot.v_nf, dec!(1500.00));
+
+        let (icms_tot, _) = compute(&sample_items(), d_compet(), None);
+        assert_eq!(icms_tot.q_bc_mono, None);
+    }
+
+    #[test]
+    fn omits_issqn_totals_without_services() {
+        let items = vec![item(dec!(10.00), NFeItemTaxes::default())];
+        let (icms_tot, issqn_tot) = compute(&items, d_compet(), None);
+        assert_eq!(icms_tot.v_nf, dec!(10.00));
+        assert!(issqn_tot.is_none());
+    }
+
+    #[test]
+    fn accepts_declared_totals_within_tolerance() {
+        let (icms_tot, issqn_tot) = compute(&sample_items(), d_compet(), None);
+        let mut declared_icms = icms_tot.clone();
+        declared_icms.v_nf += dec!(0.01);
+        let declared = UpdateNFeTotal {
+            icms_tot: Some(declared_icms),
+            issqn_tot: issqn_tot.clone(),
+            ret_trib: None,
+        };
+        assert!(check_declared(&icms_tot, issqn_tot.as_ref(), &declared).is_ok());
+    }
+
+    #[test]
+    fn names_the_mismatched_field() {
+        let (icms_tot, issqn_tot) = compute(&sample_items(), d_compet(), None);
+        let mut declared_icms = icms_tot.clone();
+        declared_icms.v_nf = dec!(1400.00);
+        let declared = UpdateNFeTotal {
+            icms_tot: Some(declared_icms),
+            issqn_tot: None,
+            ret_trib: None,
+        };
+        let err = check_declared(&icms_tot, issqn_tot.as_ref(), &declared).unwrap_err();
+        assert_eq!(err.field, "ICMSTot.vNF");
+
+        let mut declared_issqn = issqn_tot.clone().unwrap();
+        declared_issqn.v_iss = Some(dec!(12.00));
+        let declared = UpdateNFeTotal {
+            icms_tot: None,
+            issqn_tot: Some(declared_issqn),
+            ret_trib: None,
+        };
+        let err = check_declared(&icms_tot, issqn_tot.as_ref(), &declared).unwrap_err();
+        assert_eq!(err.field, "ISSQNtot.vISS");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::errors::ValidationError;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// Local file header signature that starts every ZIP archive.
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Upper bound for the uncompressed size of an archive member, against ZIP bombs.
+const MAX_ENTRY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A file received for import: the upload itself, or one member of a ZIP upload.
+#[derive(Debug)]
+pub struct ImportFile {
+    pub name: String,
+    pub content: Result<Vec<u8>, ValidationError>,
+}
+
+/// Splits an upload into XML files. Members that cannot be read are returned with their
+/// error so they still show up in the report; directories are ignored.
+pub fn unpack(name: &str, upload: &[u8]) -> Result<Vec<ImportFile>, ValidationError> {
+    if !upload.starts_with(ZIP_SIGNATURE) {
+        return Ok(vec![ImportFile {
+            name: name.to_string(),
+            content: Ok(upload.to_vec()),
+        }]);
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(upload))
+        .map_err(|e| ValidationError::new("file", format!("invalid ZIP archive: {}", e)))?;
+    let mut files = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                files.push(ImportFile {
+                    name: format!("{}#{}", name, index),
+                    content: Err(ValidationError::new("file", e.to_string())),
+                });
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let entry_name = entry.name().to_string();
+        let content = if !entry_name.to_lowercase().ends_with(".xml") {
+            Err(ValidationError::new("file", "not an XML file"))
+        } else if entry.size() > MAX_ENTRY_SIZE {
+            Err(ValidationError::new(
+                "file",
+                format!("larger than {} bytes", MAX_ENTRY_SIZE),
+            ))
+        } else {
+            let mut content = Vec::with_capacity(entry.size() as usize);
+            entry
+                .by_ref()
+                .take(MAX_ENTRY_SIZE)
+                .read_to_end(&mut content)
+                .map(|_| content)
+                .map_err(|e| ValidationError::new("file", e.to_string()))
+        };
+        files.push(ImportFile {
+            name: entry_name,
+            content,
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn unpacks_zip_members() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("notas/", SimpleFileOptions::default())
+            .unwrap();
+        writer
+            .start_file("notas/a.xml", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"<NFe/>").unwrap();
+        writer
+            .start_file("leiame.txt", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"texto").unwrap();
+        let zip = writer.finish().unwrap().into_inner();
+
+        let files = unpack("lote.zip", &zip).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].name, "notas/a.xml");
+        assert_eq!(files[0].content.as_deref().unwrap(), b"<NFe/>");
+        assert!(files[1].content.is_err());
+    }
+
+    #[test]
+    fn passes_plain_xml_through() {
+        let files = unpack("nota.xml", b"<NFe/>").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "nota.xml");
+    }
+}
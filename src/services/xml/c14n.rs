@@ -0,0 +1,128 @@
+//! Canonical XML 1.0 (inclusive, without comments) of a document subtree, as required by
+//! the `CanonicalizationMethod` and `Transform` of the fiscal signatures.
+
+use roxmltree::Node;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Canonicalizes `node` and its descendants, skipping any descendant for which `skip`
+/// returns true (the enveloped-signature transform removes the `Signature` this way).
+pub fn canonicalize(node: Node, skip: &dyn Fn(Node) -> bool) -> String {
+    let mut out = String::new();
+    write_element(node, None, skip, &mut out);
+    out
+}
+
+/// In-scope namespace declarations as (prefix, uri), sorted with the default first.
+fn namespaces<'a>(node: Node<'a, '_>) -> Vec<(&'a str, &'a str)> {
+    let mut namespaces: Vec<(&str, &str)> = node
+        .namespaces()
+        .filter(|ns| ns.uri() != XML_NAMESPACE)
+        .map(|ns| (ns.name().unwrap_or_default(), ns.uri()))
+        .collect();
+    namespaces.sort();
+    namespaces.dedup_by(|a, b| a.0 == b.0);
+    namespaces
+}
+
+fn qualified(node: Node, namespace: Option<&str>, local: &str) -> String {
+    match namespace.and_then(|ns| node.lookup_prefix(ns)) {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, local),
+        _ => local.to_string(),
+    }
+}
+
+fn write_element(node: Node, parent: Option<Node>, skip: &dyn Fn(Node) -> bool, out: &mut String) {
+    out.push('<');
+    out.push_str(&qualified(
+        node,
+        node.tag_name().namespace(),
+        node.tag_name().name(),
+    ));
+
+    // The apex renders every namespace in scope; descendants only what changed.
+    let inherited = parent.map(namespaces).unwrap_or_default();
+    for (prefix, uri) in namespaces(node) {
+        if inherited.contains(&(prefix, uri)) {
+            continue;
+        }
+        if prefix.is_empty() {
+            out.push_str(&format!(" xmlns=\"{}\"", escape_attribute(uri)));
+        } else {
+            out.push_str(&format!(" xmlns:{}=\"{}\"", prefix, escape_attribute(uri)));
+        }
+    }
+
+    let mut attributes: Vec<_> = node.attributes().collect();
+    attributes.sort_by_key(|a| (a.namespace().unwrap_or_default(), a.name()));
+    for attribute in attributes {
+        out.push_str(&format!(
+            " {}=\"{}\"",
+            qualified(node, attribute.namespace(), attribute.name()),
+            escape_attribute(attribute.value())
+        ));
+    }
+    out.push('>');
+
+    for child in node.children() {
+        if child.is_element() {
+            if !skip(child) {
+                write_element(child, Some(node), skip, out);
+            }
+        } else if child.is_text() {
+            out.push_str(&escape_text(child.text().unwrap_or_default()));
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&qualified(
+        node,
+        node.tag_name().namespace(),
+        node.tag_name().name(),
+    ));
+    out.push('>');
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\r', "&#xD;")
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\t', "&#x9;")
+        .replace('\n', "&#xA;")
+        .replace('\r', "&#xD;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roxmltree::Document;
+
+    #[test]
+    fn canonicalizes_subtree() {
+        let xml = "<?xml version=\"1.0\"?>\n<NFe xmlns=\"http://www.portalfiscal.inf.br/nfe\"><infNFe versao='4.00' Id=\"NFe1\"><!-- c --><a/><b x=\"1 &amp; 2\">Tom &amp; &#39;Jerry&#39;</b></infNFe></NFe>";
+        let document = Document::parse(xml).unwrap();
+        let inf_nfe = document.root_element().first_element_child().unwrap();
+        assert_eq!(
+            canonicalize(inf_nfe, &|_| false),
+            "<infNFe xmlns=\"http://www.portalfiscal.inf.br/nfe\" Id=\"NFe1\" versao=\"4.00\"><a></a><b x=\"1 &amp; 2\">Tom &amp; 'Jerry'</b></infNFe>"
+        );
+    }
+
+    #[test]
+    fn skips_enveloped_nodes() {
+        let document = Document::parse("<a><b/><Signature/></a>").unwrap();
+        assert_eq!(
+            canonicalize(document.root_element(), &|n| n.has_tag_name("Signature")),
+            "<a><b></b></a>"
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Código de Segurança do Responsável Técnico: the token the state issues to the company
+//! responsible for the issuing software. The note carries its id and `hashCSRT`, the
+//! SHA-1 of the token followed by the access key, in base64.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_additional::NFeTechResponsible;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use openssl::sha::sha1;
+use std::fmt;
+
+#[derive(Clone)]
+pub struct Csrt {
+    pub id: String,
+    pub token: String,
+}
+
+impl fmt::Debug for Csrt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Csrt").field("id", &self.id).finish()
+    }
+}
+
+impl Csrt {
+    pub fn new(id: &str, token: &str) -> Result<Self, ValidationError> {
+        if !is_digits(id, 2, 2) {
+            return Err(ValidationError::new("idCSRT", "must have 2 digits"));
+        }
+        if !(16..=36).contains(&token.len()) {
+            return Err(ValidationError::new(
+                "CSRT",
+                "must have between 16 and 36 characters",
+            ));
+        }
+        Ok(Self {
+            id: id.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    /// `hashCSRT` of the note with the given access key.
+    pub fn hash(&self, key: &str) -> String {
+        STANDARD.encode(sha1(format!("{}{}", self.token, key).as_bytes()))
+    }
+
+    /// Fills `idCSRT` and `hashCSRT` of the group for the note with the given key.
+    pub fn complete(&self, responsible: &mut NFeTechResponsible, key: &str) {
+        responsible.id_csrt = Some(self.id.clone());
+        responsible.hash_csrt = Some(self.hash(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_the_token_with_the_key() {
+        let csrt = Csrt::new("01", "G8063VRTNDMO886SFNK5LDUDEI24XJ22YIPO").unwrap();
+        assert_eq!(
+            csrt.hash("41190806117473000150550010000463191912607532"),
+            "szSdvSBoR8Rh8FJA6GDbjldUI24="
+        );
+        assert!(!format!("{:?}", csrt).contains("G8063"));
+        assert_eq!(Csrt::new("1", &csrt.token).unwrap_err().field, "idCSRT");
+    }
+}
@@ -0,0 +1,15 @@
+pub mod archive;
+pub mod c14n;
+pub mod csrt;
+pub mod nfce_supplement;
+pub mod nfe_parser;
+pub mod nfe_serializer;
+pub mod schema;
+pub mod schema_validator;
+pub mod signature;
+pub mod writer;
+
+/// Namespace of the NF-e layouts.
+pub const NFE_NAMESPACE: &str = "http://www.portalfiscal.inf.br/nfe";
+/// Layout version written in `versao`.
+pub const NFE_VERSION: &str = "4.00";
@@ -0,0 +1,268 @@
+//! `infNFeSupl` of the NFC-e: the QR code (version 2) that takes the consumer to the
+//! note on the consultation page of the state, and the address of the page that looks
+//! notes up by access key (`urlChave`).
+
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::is_digits;
+use crate::models::nfe_document::NFeDocument;
+use crate::services::xml::writer::{format_decimal, XmlWriter};
+use crate::services::xml::NFE_NAMESPACE;
+use openssl::sha::sha1;
+use roxmltree::Document;
+use serde::Serialize;
+use std::fmt;
+
+/// Version of the QR code parameters.
+pub const QR_CODE_VERSION: &str = "2";
+
+const XMLDSIG_NAMESPACE: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+/// Código de Segurança do Contribuinte: the token the state issues to the emitter of
+/// NFC-e. It never appears in the note; the QR code carries only its id and a hash.
+#[derive(Clone)]
+pub struct Csc {
+    pub id: String,
+    pub token: String,
+}
+
+impl fmt::Debug for Csc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Csc").field("id", &self.id).finish()
+    }
+}
+
+impl Csc {
+    pub fn new(id: &str, token: &str) -> Result<Self, ValidationError> {
+        if !is_digits(id, 1, 6) {
+            return Err(ValidationError::new("cIdToken", "must have up to 6 digits"));
+        }
+        if !(16..=36).contains(&token.len()) {
+            return Err(ValidationError::new(
+                "CSC",
+                "must have between 16 and 36 characters",
+            ));
+        }
+        Ok(Self {
+            id: id.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    /// `cIdToken` as written in the QR code, without leading zeros.
+    fn id_token(&self) -> &str {
+        match self.id.trim_start_matches('0') {
+            "" => "0",
+            id => id,
+        }
+    }
+
+    /// `cHashQRCode`: SHA-1 of the parameters followed by the token, in hexadecimal.
+    fn hash(&self, params: &str) -> String {
+        hex(&sha1(format!("{}{}", params, self.token).as_bytes())).to_uppercase()
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `infNFeSupl` as stored in the note.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NFCeSupplement {
+    #[serde(rename = "qrCode")]
+    pub qr_code: String,
+    #[serde(rename = "urlChave")]
+    pub url_chave: String,
+}
+
+/// QR code of a note authorized online: `chave|2|tpAmb|cIdToken|cHashQRCode`.
+pub fn online_qr_code(url: &str, key: &str, tp_amb: &str, csc: &Csc) -> String {
+    let params = format!("{}|{}|{}|{}", key, QR_CODE_VERSION, tp_amb, csc.id_token());
+    format!("{}?p={}|{}", url, params, csc.hash(&params))
+}
+
+/// QR code of a note issued offline (tpEmis 9), which also carries the day of issue,
+/// `vNF` and the digest of the signature so the printed note can be checked before it
+/// reaches SEFAZ: `chave|2|tpAmb|dia|vNF|digVal|cIdToken|cHashQRCode`.
+pub fn offline_qr_code(
+    url: &str,
+    key: &str,
+    tp_amb: &str,
+    day: &str,
+    v_nf: &str,
+    dig_val: &str,
+    csc: &Csc,
+) -> String {
+    let params = format!(
+        "{}|{}|{}|{}|{}|{}|{}",
+        key,
+        QR_CODE_VERSION,
+        tp_amb,
+        day,
+        v_nf,
+        hex(dig_val.as_bytes()),
+        csc.id_token()
+    );
+    format!("{}?p={}|{}", url, params, csc.hash(&params))
+}
+
+/// Builds the supplement of a signed NFC-e from the consultation URLs of its state.
+pub fn build(
+    document: &NFeDocument,
+    signed: &str,
+    csc: &Csc,
+    qr_code_url: &str,
+    url_chave: &str,
+) -> Result<NFCeSupplement, ValidationError> {
+    let ide = &document.identification;
+    let key = document.access_key()?.to_string();
+    let qr_code = if ide.tp_emis == "9" {
+        let dig_val = digest_value(signed)?;
+        offline_qr_code(
+            qr_code_url,
+            &key,
+            &ide.tp_amb,
+            &ide.local_time(&ide.dh_emi).format("%d").to_string(),
+            &format_decimal(document.total.icms_tot.v_nf, 2),
+            &dig_val,
+            csc,
+        )
+    } else {
+        online_qr_code(qr_code_url, &key, &ide.tp_amb, csc)
+    };
+    Ok(NFCeSupplement {
+        qr_code,
+        url_chave: url_chave.to_string(),
+    })
+}
+
+/// `DigestValue` of the signature of `infNFe`.
+fn digest_value(signed: &str) -> Result<String, ValidationError> {
+    let document = Document::parse(signed)
+        .map_err(|e| ValidationError::new("NFe", format!("malformed XML: {}", e)))?;
+    document
+        .descendants()
+        .find(|n| n.has_tag_name((XMLDSIG_NAMESPACE, "DigestValue")))
+        .and_then(|n| n.text())
+        .map(|text| text.trim().to_string())
+        .ok_or_else(|| ValidationError::new("Signature", "the note is not signed"))
+}
+
+impl NFCeSupplement {
+    pub fn to_xml(&self) -> String {
+        let mut w = XmlWriter::new();
+        w.start("infNFeSupl");
+        w.text("qrCode", &self.qr_code);
+        w.text("urlChave", &self.url_chave);
+        w.end("infNFeSupl");
+        w.into_string()
+    }
+
+    /// Places the supplement between `infNFe` and its signature, as TNFe orders them.
+    pub fn attach(&self, signed: &str) -> Result<String, ValidationError> {
+        const END: &str = "</infNFe>";
+        let end = signed
+            .find(END)
+            .ok_or_else(|| ValidationError::new("NFe", "no infNFe element"))?
+            + END.len();
+        Ok(format!(
+            "{}{}{}",
+            &signed[..end],
+            self.to_xml(),
+            &signed[end..]
+        ))
+    }
+
+    /// Reads the supplement of a stored NFC-e.
+    pub fn parse(xml: &str) -> Option<Self> {
+        let document = Document::parse(xml).ok()?;
+        let supplement = document
+            .descendants()
+            .find(|n| n.has_tag_name((NFE_NAMESPACE, "infNFeSupl")))?;
+        let text = |name: &str| {
+            supplement
+                .children()
+                .find(|n| n.has_tag_name((NFE_NAMESPACE, name)))
+                .and_then(|n| n.text())
+                .map(|text| text.trim().to_string())
+        };
+        Some(Self {
+            qr_code: text("qrCode")?,
+            url_chave: text("urlChave")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::xml::nfe_serializer;
+    use crate::services::xml::schema_validator::SchemaValidator;
+    use crate::services::xml::signature::tests::test_signer;
+    use std::path::PathBuf;
+
+    const KEY: &str = "35240312345678000195650010000001231876543210";
+
+    fn csc() -> Csc {
+        Csc::new("000001", "0123456789ABCDEF0123456789ABCDEF").unwrap()
+    }
+
+    #[test]
+    fn hashes_the_parameters_with_the_csc() {
+        let url = "https://www.homologacao.nfce.fazenda.sp.gov.br/qrcode";
+        assert_eq!(
+            online_qr_code(url, KEY, "2", &csc()),
+            format!(
+                "{}?p={}|2|2|1|{}",
+                url, KEY, "24344623072E39C7CBC49C0E4F199D8CC5C3FB36"
+            )
+        );
+        let offline = offline_qr_code(url, KEY, "2", "05", "100.00", "AAAA", &csc());
+        assert!(
+            offline.contains("|2|2|05|100.00|41414141|1|"),
+            "{}",
+            offline
+        );
+        assert!(Csc::new("1234567", "0123456789ABCDEF").is_err());
+        assert!(Csc::new("1", "short").is_err());
+    }
+
+    #[test]
+    fn attaches_a_schema_valid_supplement() {
+        let mut document = NFeDocument::sample();
+        document.identification.mod_ = "65".to_string();
+        document.identification.tp_imp = "4".to_string();
+        document.identification.ind_final = "1".to_string();
+        let id = format!("NFe{}", document.access_key().unwrap());
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signed = test_signer().sign(&xml, &id).unwrap();
+        let validator = SchemaValidator::load(&PathBuf::from(env!("CARGO_MANIFEST_DIR"))).unwrap();
+
+        let qr_url = "https://www.homologacao.nfce.fazenda.sp.gov.br/qrcode";
+        let url_chave = "https://www.homologacao.nfce.fazenda.sp.gov.br/consulta";
+        let supplement = build(&document, &signed, &csc(), qr_url, url_chave).unwrap();
+        let with_supplement = supplement.attach(&signed).unwrap();
+        assert!(with_supplement.contains("</infNFe><infNFeSupl><qrCode>"));
+        let report = validator.validate(&with_supplement).unwrap();
+        assert!(report.valid, "{:?}", report.violations);
+        assert_eq!(NFCeSupplement::parse(&with_supplement), Some(supplement));
+
+        // Offline, the QR code carries the digest of the signature.
+        document.identification.tp_emis = "9".to_string();
+        document.identification.dh_cont = Some(document.identification.dh_emi);
+        document.identification.x_justificativa =
+            Some("Falha de comunicacao com a SEFAZ".to_string());
+        let id = format!("NFe{}", document.access_key().unwrap());
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signed = test_signer().sign(&xml, &id).unwrap();
+        let supplement = build(&document, &signed, &csc(), qr_url, url_chave).unwrap();
+        let digest = hex(digest_value(&signed).unwrap().as_bytes());
+        assert!(supplement
+            .qr_code
+            .contains(&format!("|2|1|20|100.00|{}|1|", digest)));
+        let report = validator
+            .validate(&supplement.attach(&signed).unwrap())
+            .unwrap();
+        assert!(report.valid, "{:?}", report.violations);
+    }
+}
@@ -0,0 +1,601 @@
+//! Reads `TNFe` and `TNfeProc` documents back into the create models.
+//!
+//! Each group is converted into a JSON object keyed by element name and deserialized
+//! through the serde renames of the models, which already match the layout tags.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_access_key::AccessKey;
+use crate::models::nfe_additional::{NFeAdditionalInfo, NFeAuthorizedDownload, NFeTechResponsible};
+use crate::models::nfe_emitter::CreateNFeEmitter;
+use crate::models::nfe_identification::CreateNFeIdentification;
+use crate::models::nfe_item::CreateNFeItem;
+use crate::models::nfe_payment::{NFeBilling, NFePayment};
+use crate::models::nfe_recipient::CreateNFeRecipient;
+use crate::models::nfe_reference::NFeReference;
+use crate::models::nfe_total::UpdateNFeTotal;
+use crate::models::nfe_transport::NFeTransport;
+use crate::services::sefaz::authorization::{parse_prot_nfe, ProtNFe};
+use crate::services::sefaz::soap;
+use crate::services::xml::NFE_NAMESPACE;
+use roxmltree::{Document, Node};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+/// Tax groups whose XSD choice subgroup (ICMS00, PISAliq, IPITrib...) is stored flat.
+const CHOICE_GROUPS: [&str; 4] = ["ICMS", "IPI", "PIS", "COFINS"];
+
+/// A note read from XML, with its declared totals kept for the consistency check.
+#[derive(Debug)]
+pub struct ParsedNFe {
+    /// Key taken from the `Id` attribute of `infNFe`.
+    pub access_key: AccessKey,
+    pub identification: CreateNFeIdentification,
+    pub emitter: CreateNFeEmitter,
+    pub recipient: Option<CreateNFeRecipient>,
+    /// `NFref` groups of `ide`.
+    pub references: Vec<NFeReference>,
+    pub items: Vec<CreateNFeItem>,
+    pub total: UpdateNFeTotal,
+    pub transport: NFeTransport,
+    pub billing: NFeBilling,
+    pub payment: NFePayment,
+    pub authorized_downloads: Vec<NFeAuthorizedDownload>,
+    pub additional_info: NFeAdditionalInfo,
+    /// `infRespTec` without `idCSRT` and `hashCSRT`, which are derived again on load.
+    pub tech_responsible: Option<NFeTechResponsible>,
+    /// `protNFe` when the file is an `nfeProc`.
+    pub protocol: Option<ProtNFe>,
+    /// The `NFe` element as received, kept with the protocol as the note was authorized.
+    pub nfe_xml: String,
+}
+
+/// Parses an `NFe` or `nfeProc` document.
+pub fn parse(xml: &str) -> Result<ParsedNFe, ValidationError> {
+    let document = Document::parse(xml)
+        .map_err(|e| ValidationError::new("xml", format!("malformed XML: {}", e)))?;
+    let root = document.root_element();
+    let (nfe, prot) = match root.tag_name().name() {
+        "NFe" => (root, None),
+        "nfeProc" => (child(root, "NFe")?, element(root, "protNFe")),
+        other => {
+            return Err(ValidationError::new(
+                "xml",
+                format!("expected NFe or nfeProc, found {}", other),
+            ))
+        }
+    };
+    if nfe.tag_name().namespace() != Some(NFE_NAMESPACE) {
+        return Err(ValidationError::new(
+            "xml",
+            format!("NFe must be in the {} namespace", NFE_NAMESPACE),
+        ));
+    }
+
+    let inf_nfe = child(nfe, "infNFe")?;
+    let id = inf_nfe.attribute("Id").unwrap_or_default();
+    let access_key = AccessKey::parse(id.strip_prefix("NFe").unwrap_or(id))
+        .map_err(|e| ValidationError::new("infNFe.Id", e.message))?;
+
+    let ide_node = child(inf_nfe, "ide")?;
+    let mut ide = to_object(ide_node);
+    ide.remove("NFref");
+    let references: Vec<NFeReference> = serde_json::from_value(list(ide_node, "NFref"))
+        .map_err(|e| ValidationError::new("NFref", e.to_string()))?;
+    // `mod` is a reserved word in Rust, so the model keeps the field as `mod_`.
+    if let Some(value) = ide.remove("mod") {
+        ide.insert("mod_".to_string(), value);
+    }
+
+    let items = inf_nfe
+        .children()
+        .filter(|n| n.has_tag_name("det"))
+        .map(|det| {
+            let mut item = to_object(det);
+            if let (Some(Value::Object(prod)), Some(node)) =
+                (item.get_mut("prod"), element(det, "prod"))
+            {
+                read_specific_lists(prod, node);
+            }
+            if let Some(Value::Object(imposto)) = item.get_mut("imposto") {
+                flatten_choices(imposto);
+            }
+            from_object(
+                &format!("det[{}]", det.attribute("nItem").unwrap_or("?")),
+                item,
+            )
+        })
+        .collect::<Result<Vec<CreateNFeItem>, _>>()?;
+
+    let emitter: CreateNFeEmitter = from_object("emit", to_object(child(inf_nfe, "emit")?))?;
+    // The number is checked against the serie of the emitter as soon as ide is stored.
+    let identification = CreateNFeIdentification {
+        emit_cnpj: emitter.cnpj.clone(),
+        emit_cpf: emitter.cpf.clone(),
+        ..from_object("ide", ide)?
+    };
+
+    Ok(ParsedNFe {
+        access_key,
+        identification,
+        emitter,
+        recipient: element(inf_nfe, "dest")
+            .map(|dest| from_object("dest", to_object(dest)))
+            .transpose()?,
+        references,
+        items,
+        total: from_object("total", to_object(child(inf_nfe, "total")?))?,
+        transport: element(inf_nfe, "transp")
+            .map(parse_transport)
+            .transpose()?
+            .unwrap_or_default(),
+        billing: element(inf_nfe, "cobr")
+            .map(|cobr| {
+                let mut object = to_object(cobr);
+                object.insert("dup".to_string(), list(cobr, "dup"));
+                from_object("cobr", object)
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        payment: element(inf_nfe, "pag")
+            .map(|pag| {
+                let mut object = to_object(pag);
+                object.insert("detPag".to_string(), list(pag, "detPag"));
+                from_object("pag", object)
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        authorized_downloads: serde_json::from_value(list(inf_nfe, "autXML"))
+            .map_err(|e| ValidationError::new("autXML", e.to_string()))?,
+        additional_info: element(inf_nfe, "infAdic")
+            .map(parse_additional_info)
+            .transpose()?
+            .unwrap_or_default(),
+        tech_responsible: element(inf_nfe, "infRespTec")
+            .map(|resp| from_object("infRespTec", to_object(resp)))
+            .transpose()?,
+        protocol: prot
+            .map(|prot| parse_prot_nfe(xml, prot))
+            .transpose()
+            .map_err(|e| ValidationError::new("protNFe", e.to_string()))?,
+        nfe_xml: soap::standalone(xml, nfe),
+    })
+}
+
+/// `infNFe` of an `NFe` or `nfeProc` document: the content [`parse`] reads, and so the
+/// element its signature must cover.
+pub fn inf_nfe<'a, 'input>(root: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+    let nfe = match root.tag_name().name() {
+        "NFe" => root,
+        "nfeProc" => element(root, "NFe")?,
+        _ => return None,
+    };
+    element(nfe, "infNFe")
+}
+
+fn element<'a, 'input>(parent: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    parent.children().find(|n| n.has_tag_name(name))
+}
+
+fn child<'a, 'input>(
+    parent: Node<'a, 'input>,
+    name: &str,
+) -> Result<Node<'a, 'input>, ValidationError> {
+    element(parent, name).ok_or_else(|| {
+        ValidationError::new(name, format!("missing from {}", parent.tag_name().name()))
+    })
+}
+
+/// Element children become object members; leaves become their trimmed text.
+/// Repeated elements keep the last occurrence, so lists are read explicitly.
+fn to_object(node: Node) -> Map<String, Value> {
+    node.children()
+        .filter(Node::is_element)
+        .map(|n| {
+            let value = if n.children().any(|c| c.is_element()) {
+                Value::Object(to_object(n))
+            } else {
+                Value::String(n.text().unwrap_or_default().trim().to_string())
+            };
+            (n.tag_name().name().to_string(), value)
+        })
+        .collect()
+}
+
+/// Every `name` child of the parent, as an array of objects.
+fn list(parent: Node, name: &str) -> Value {
+    Value::Array(
+        parent
+            .children()
+            .filter(|n| n.has_tag_name(name))
+            .map(|n| Value::Object(to_object(n)))
+            .collect(),
+    )
+}
+
+/// `transp`, whose `reboque`, `vol` and the `lacres` of each volume repeat.
+fn parse_transport(transp: Node) -> Result<NFeTransport, ValidationError> {
+    let mut object = to_object(transp);
+    object.insert("reboque".to_string(), list(transp, "reboque"));
+    let volumes = transp
+        .children()
+        .filter(|n| n.has_tag_name("vol"))
+        .map(|vol| {
+            let mut volume = to_object(vol);
+            volume.insert("lacres".to_string(), list(vol, "lacres"));
+            Value::Object(volume)
+        })
+        .collect();
+    object.insert("vol".to_string(), Value::Array(volumes));
+    from_object("transp", object)
+}
+
+/// `infAdic`, whose `obsCont` and `obsFisco` carry their name in the `xCampo` attribute.
+fn parse_additional_info(inf_adic: Node) -> Result<NFeAdditionalInfo, ValidationError> {
+    let observations = |name: &str| {
+        let observations = inf_adic
+            .children()
+            .filter(|n| n.has_tag_name(name))
+            .map(|obs| {
+                let mut observation = to_object(obs);
+                let x_campo = obs.attribute("xCampo").unwrap_or_default();
+                observation.insert("xCampo".to_string(), Value::String(x_campo.to_string()));
+                Value::Object(observation)
+            })
+            .collect();
+        Value::Array(observations)
+    };
+    let mut object = to_object(inf_adic);
+    object.insert("obsCont".to_string(), observations("obsCont"));
+    object.insert("obsFisco".to_string(), observations("obsFisco"));
+    object.insert("procRef".to_string(), list(inf_adic, "procRef"));
+    from_object("infAdic", object)
+}
+
+/// `rastro`, `arma` and the `origComb` of a fuel, which repeat inside `prod`.
+fn read_specific_lists(prod: &mut Map<String, Value>, node: Node) {
+    prod.insert("rastro".to_string(), list(node, "rastro"));
+    prod.insert("arma".to_string(), list(node, "arma"));
+    if let (Some(Value::Object(comb)), Some(comb_node)) =
+        (prod.get_mut("comb"), element(node, "comb"))
+    {
+        comb.insert("origComb".to_string(), list(comb_node, "origComb"));
+    }
+}
+
+/// Merges the subgroup of each choice group into the group itself.
+fn flatten_choices(imposto: &mut Map<String, Value>) {
+    for group in CHOICE_GROUPS {
+        if let Some(Value::Object(fields)) = imposto.get_mut(group) {
+            let subgroups: Vec<String> = fields
+                .iter()
+                .filter(|(_, v)| v.is_object())
+                .map(|(k, _)| k.clone())
+                .collect();
+            for name in subgroups {
+                if let Some(Value::Object(inner)) = fields.remove(&name) {
+                    fields.extend(inner);
+                }
+            }
+        }
+    }
+}
+
+fn from_object<T: DeserializeOwned>(
+    group: &str,
+    object: Map<String, Value>,
+) -> Result<T, ValidationError> {
+    serde_json::from_value(Value::Object(object))
+        .map_err(|e| ValidationError::new(group, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::xml::nfe_serializer;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn reads_back_serialized_note() {
+        let document = NFeDocument::sample();
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let parsed = parse(&xml).unwrap();
+
+        assert_eq!(parsed.access_key, document.access_key().unwrap());
+        assert_eq!(parsed.identification.mod_, "55");
+        assert_eq!(parsed.identification.dh_emi, document.identification.dh_emi);
+        assert_eq!(parsed.emitter.cnpj.as_deref(), Some("12345678000195"));
+        assert_eq!(parsed.items.len(), 1);
+        let imposto = &parsed.items[0].imposto;
+        assert_eq!(imposto.icms.as_ref().unwrap().p_icms, Some(dec!(18.0000)));
+        assert_eq!(imposto.pis.as_ref().unwrap().cst, "01");
+        assert_eq!(parsed.total.icms_tot.as_ref().unwrap().v_nf, dec!(100.00));
+        assert!(parsed.protocol.is_none());
+        assert_eq!(parsed.transport, NFeTransport::default());
+        assert_eq!(parsed.payment, NFePayment::default());
+    }
+
+    #[test]
+    fn reads_repeated_transport_groups() {
+        use crate::models::nfe_transport::{NFeSeal, NFeVehicle, NFeVolume};
+
+        let mut document = NFeDocument::sample();
+        let trailer = |placa: &str| NFeVehicle {
+            placa: placa.to_string(),
+            uf: Some("SP".to_string()),
+            rntc: None,
+        };
+        let volume = |n_vol: &str, seals: &[&str]| NFeVolume {
+            n_vol: Some(n_vol.to_string()),
+            peso_b: Some(dec!(5.250)),
+            lacres: seals
+                .iter()
+                .map(|n_lacre| NFeSeal {
+                    n_lacre: n_lacre.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        document.transport = NFeTransport {
+            mod_frete: "1".to_string(),
+            veic_transp: Some(trailer("ABC1234")),
+            reboque: vec![trailer("DEF5678"), trailer("GHI9J01")],
+            vol: vec![volume("1", &["L1", "L2"]), volume("2", &[])],
+            ..Default::default()
+        };
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        assert_eq!(parse(&xml).unwrap().transport, document.transport);
+    }
+
+    #[test]
+    fn reads_the_references_of_a_return() {
+        use crate::models::nfe_item::{NFeReturnedIpi, NFeReturnedTax};
+        use crate::models::nfe_reference::{NFeRefECF, NFeReference};
+
+        let mut document = NFeDocument::sample();
+        document.identification.fin_nfe = "4".to_string();
+        assert_eq!(
+            nfe_serializer::serialize(&document).unwrap_err().field,
+            "NFref"
+        );
+
+        let original = NFeDocument::sample().access_key().unwrap().to_string();
+        document.references = vec![
+            NFeReference {
+                ref_nfe: Some(original),
+                ..Default::default()
+            },
+            NFeReference {
+                ref_ecf: Some(NFeRefECF {
+                    mod_: "2D".to_string(),
+                    n_ecf: "001".to_string(),
+                    n_coo: "004512".to_string(),
+                }),
+                ..Default::default()
+            },
+        ];
+        document.items[0].imposto_devol = Some(NFeReturnedTax {
+            p_devol: dec!(50.00),
+            ipi: NFeReturnedIpi {
+                v_ipi_devol: dec!(2.50),
+            },
+        });
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        assert!(xml.contains("</verProc><NFref><refNFe>"));
+        assert!(xml.contains(
+            "</imposto><impostoDevol><pDevol>50.00</pDevol><IPI><vIPIDevol>2.50</vIPIDevol></IPI></impostoDevol>"
+        ));
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(parsed.references, document.references);
+        assert_eq!(
+            parsed.items[0].imposto_devol,
+            document.items[0].imposto_devol
+        );
+        assert_eq!(parsed.identification.fin_nfe, "4");
+    }
+
+    #[test]
+    fn reads_installments_and_payments() {
+        use crate::models::nfe_payment::{NFeCard, NFeInstallment, NFeInvoice, NFePaymentDetail};
+        use chrono::NaiveDate;
+
+        let mut document = NFeDocument::sample();
+        let dup = |n_dup: &str, day: u32| NFeInstallment {
+            n_dup: Some(n_dup.to_string()),
+            d_venc: NaiveDate::from_ymd_opt(2024, 4, day),
+            v_dup: dec!(50.00),
+        };
+        document.billing = NFeBilling {
+            fat: Some(NFeInvoice {
+                n_fat: Some("123".to_string()),
+                v_orig: Some(dec!(100.00)),
+                v_desc: None,
+                v_liq: Some(dec!(100.00)),
+            }),
+            dup: vec![dup("001", 20), dup("002", 30)],
+        };
+        let detail = NFePayment::default().det_pag[0].clone();
+        document.payment = NFePayment {
+            det_pag: vec![
+                NFePaymentDetail {
+                    ind_pag: Some("1".to_string()),
+                    t_pag: "03".to_string(),
+                    v_pag: dec!(80.00),
+                    card: Some(NFeCard {
+                        tp_integra: "2".to_string(),
+                        cnpj: None,
+                        t_band: Some("02".to_string()),
+                        c_aut: Some("998877".to_string()),
+                    }),
+                    ..detail.clone()
+                },
+                NFePaymentDetail {
+                    t_pag: "01".to_string(),
+                    v_pag: dec!(25.00),
+                    ..detail
+                },
+            ],
+            v_troco: Some(dec!(5.00)),
+        };
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        assert!(
+            xml.contains("<dup><nDup>001</nDup><dVenc>2024-04-20</dVenc><vDup>50.00</vDup></dup>")
+        );
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(parsed.billing, document.billing);
+        assert_eq!(parsed.payment, document.payment);
+    }
+
+    #[test]
+    fn reads_additional_info_and_technical_responsible() {
+        use crate::models::nfe_additional::{NFeObservation, NFeProcessReference};
+        use crate::services::xml::csrt::Csrt;
+
+        let mut document = NFeDocument::sample();
+        document.authorized_downloads = vec![NFeAuthorizedDownload {
+            cnpj: None,
+            cpf: Some("12345678909".to_string()),
+        }];
+        document.additional_info = NFeAdditionalInfo {
+            inf_cpl: Some("Pedido 4521".to_string()),
+            obs_cont: vec![NFeObservation {
+                x_campo: "Vendedor".to_string(),
+                x_texto: "Maria".to_string(),
+            }],
+            proc_ref: vec![NFeProcessReference {
+                n_proc: "2024/0001".to_string(),
+                ind_proc: "0".to_string(),
+                tp_ato: None,
+            }],
+            ..Default::default()
+        };
+        let mut responsible = NFeTechResponsible {
+            cnpj: "11222333000181".to_string(),
+            x_contato: "Suporte".to_string(),
+            email: "suporte@example.com".to_string(),
+            fone: "1133334444".to_string(),
+            id_csrt: None,
+            hash_csrt: None,
+        };
+        let key = document.access_key().unwrap().to_string();
+        Csrt::new("01", "G8063VRTNDMO886SFNK5LDUDEI24XJ22YIPO")
+            .unwrap()
+            .complete(&mut responsible, &key);
+        document.tech_responsible = Some(responsible.clone());
+
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        assert!(xml.contains("</dest><autXML><CPF>12345678909</CPF></autXML><det"));
+        assert!(xml.contains("<obsCont xCampo=\"Vendedor\"><xTexto>Maria</xTexto></obsCont>"));
+        assert!(xml.contains("<idCSRT>01</idCSRT><hashCSRT>"));
+        assert!(xml.ends_with("</infRespTec></infNFe></NFe>"));
+
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(parsed.authorized_downloads, document.authorized_downloads);
+        assert_eq!(parsed.additional_info, document.additional_info);
+        // The CSRT fields are derived again when the imported note is loaded.
+        responsible.id_csrt = None;
+        responsible.hash_csrt = None;
+        assert_eq!(parsed.tech_responsible, Some(responsible));
+    }
+
+    #[test]
+    fn reads_batches_and_fuel_groups() {
+        use crate::models::nfe_item_specific::{
+            NFeBatch, NFeFuel, NFeFuelOrigin, NFeMedicine, NFeSpecificProduct,
+        };
+        use chrono::NaiveDate;
+
+        let mut document = NFeDocument::sample();
+        document.items[0].prod.specific = NFeSpecificProduct {
+            rastro: vec![NFeBatch {
+                n_lote: "L2403".to_string(),
+                q_lote: dec!(2.000),
+                d_fab: NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(),
+                d_val: NaiveDate::from_ymd_opt(2026, 1, 10).unwrap(),
+                c_agreg: None,
+            }],
+            med: Some(NFeMedicine {
+                c_prod_anvisa: "1234567890123".to_string(),
+                x_motivo_isencao: None,
+                v_pmc: dec!(45.90),
+            }),
+            ..Default::default()
+        };
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        assert!(xml.contains("<indTot>1</indTot><rastro><nLote>L2403</nLote><qLote>2.000</qLote>"));
+        assert!(xml.contains("</rastro><med><cProdANVISA>1234567890123</cProdANVISA>"));
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(
+            parsed.items[0].prod.specific,
+            document.items[0].prod.specific
+        );
+
+        document.items[0].prod.specific = NFeSpecificProduct {
+            comb: Some(NFeFuel {
+                c_prod_anp: "320102001".to_string(),
+                desc_anp: "GASOLINA C COMUM".to_string(),
+                p_glp: None,
+                p_gnn: None,
+                p_gni: None,
+                v_part: None,
+                codif: None,
+                q_temp: None,
+                uf_cons: "SP".to_string(),
+                cide: None,
+                encerrante: None,
+                p_bio: None,
+                orig_comb: vec![
+                    NFeFuelOrigin {
+                        ind_import: "0".to_string(),
+                        c_uf_orig: "35".to_string(),
+                        p_orig: dec!(60.0000),
+                    },
+                    NFeFuelOrigin {
+                        ind_import: "1".to_string(),
+                        c_uf_orig: "41".to_string(),
+                        p_orig: dec!(40.0000),
+                    },
+                ],
+            }),
+            ..Default::default()
+        };
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let parsed = parse(&xml).unwrap();
+        assert_eq!(
+            parsed.items[0].prod.specific,
+            document.items[0].prod.specific
+        );
+    }
+
+    #[test]
+    fn reads_nfe_proc() {
+        let nfe = nfe_serializer::serialize(&NFeDocument::sample()).unwrap();
+        let xml = format!(
+            concat!(
+                "<nfeProc xmlns=\"{0}\" versao=\"4.00\">{1}<protNFe versao=\"4.00\"><infProt>",
+                "<tpAmb>2</tpAmb><verAplic>SP_NFE_PL009_V4</verAplic>",
+                "<chNFe>35240312345678000195550010000000011000000013</chNFe>",
+                "<dhRecbto>2024-03-01T10:00:00-03:00</dhRecbto><nProt>135240000000001</nProt>",
+                "<cStat>100</cStat><xMotivo>Autorizado o uso da NF-e</xMotivo>",
+                "</infProt></protNFe></nfeProc>"
+            ),
+            NFE_NAMESPACE, nfe
+        );
+        let parsed = parse(&xml).unwrap();
+        let protocol = parsed.protocol.unwrap();
+        assert_eq!(protocol.n_prot.as_deref(), Some("135240000000001"));
+        assert_eq!(protocol.c_stat, "100");
+        assert!(protocol.xml.starts_with(&format!(
+            "<protNFe xmlns=\"{}\" versao=\"4.00\">",
+            NFE_NAMESPACE
+        )));
+        assert_eq!(parsed.nfe_xml, nfe);
+    }
+
+    #[test]
+    fn rejects_other_documents() {
+        assert_eq!(parse("<NFe>").unwrap_err().field, "xml");
+        assert_eq!(parse("<CTe/>").unwrap_err().field, "xml");
+        assert_eq!(parse("<NFe/>").unwrap_err().field, "xml");
+    }
+}
@@ -0,0 +1,1064 @@
+//! `TNFe` serialization following the element order of `leiauteNFe_v4.00.xsd`.
+
+use crate::errors::ValidationError;
+use crate::models::nfe_additional::{
+    validate_authorized_downloads, NFeAdditionalInfo, NFeAuthorizedDownload, NFeTechResponsible,
+};
+use crate::models::nfe_address::NFeAddress;
+use crate::models::nfe_document::NFeDocument;
+use crate::models::nfe_emitter::NFeEmitter;
+use crate::models::nfe_identification::NFeIdentification;
+use crate::models::nfe_item::NFeItem;
+use crate::models::nfe_item_specific::{NFeFuel, NFeSpecificProduct, NFeVehicleProduct};
+use crate::models::nfe_item_tax::{NFeIcms, NFeItemTaxes};
+use crate::models::nfe_payment::{NFeBilling, NFePayment};
+use crate::models::nfe_recipient::NFeRecipient;
+use crate::models::nfe_reference::{validate_references, NFeReference};
+use crate::models::nfe_total::NFeTotal;
+use crate::models::nfe_transport::{NFeTransport, NFeVehicle};
+use crate::services::tax::icms::IcmsGroup;
+use crate::services::tax::ipi::IpiGroup;
+use crate::services::tax::pis_cofins::ContributionGroup;
+use crate::services::xml::writer::XmlWriter;
+use crate::services::xml::{NFE_NAMESPACE, NFE_VERSION};
+use chrono::{DateTime, Utc};
+
+/// `TDec_1302`: monetary values.
+const MONEY: u32 = 2;
+/// `TDec_0302a04`: percentages.
+const RATE: u32 = 4;
+/// `TDec_0302Max100`: the returned share of `pDevol`.
+const SHARE: u32 = 2;
+/// `TDec_1104v` / `TDec_1204v`: quantities and per-unit rates.
+const QUANTITY: u32 = 4;
+/// `TDec_1110v`: unit prices.
+const UNIT_VALUE: u32 = 10;
+/// `TDec_1203`: weights.
+const WEIGHT: u32 = 3;
+/// `TDec_0803v` / `TDec_1203`: batch quantities and pump totalizer readings.
+const BATCH: u32 = 3;
+
+/// Prefix of a standalone document; omitted when the note is embedded in a batch.
+pub const XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+/// Recipient name SEFAZ requires in the homologation environment (tpAmb 2).
+const HOMOLOGATION_NAME: &str = "NF-E EMITIDA EM AMBIENTE DE HOMOLOGACAO - SEM VALOR FISCAL";
+/// Description SEFAZ requires in the first item of an NFC-e in homologation, which may
+/// have no recipient to carry [`HOMOLOGATION_NAME`].
+const HOMOLOGATION_PRODUCT: &str =
+    "NOTA FISCAL EMITIDA EM AMBIENTE DE HOMOLOGACAO - SEM VALOR FISCAL";
+
+/// Serializes the `NFe` element, without the XML declaration.
+pub fn serialize(document: &NFeDocument) -> Result<String, ValidationError> {
+    let key = document.access_key()?;
+    let emitter = document.emitter()?;
+    if document.items.is_empty() {
+        return Err(ValidationError::new("det", "the note has no items"));
+    }
+    validate_recipient(document)?;
+    let ide = &document.identification;
+    validate_references(&ide.fin_nfe, &document.references)?;
+    document.transport.validate(&ide.mod_, &ide.id_dest)?;
+    document.billing.validate(&ide.mod_)?;
+    document.payment.validate(document.total.icms_tot.v_nf)?;
+    validate_authorized_downloads(&document.authorized_downloads)?;
+    document.additional_info.validate()?;
+    if let Some(responsible) = &document.tech_responsible {
+        responsible.validate()?;
+    }
+
+    let mut w = XmlWriter::new();
+    w.start_with("NFe", &[("xmlns", NFE_NAMESPACE)]);
+    w.start_with(
+        "infNFe",
+        &[("Id", &format!("NFe{}", key)), ("versao", NFE_VERSION)],
+    );
+    write_ide(&mut w, ide, &key.c_dv, &document.references);
+    write_emit(&mut w, emitter);
+    if let Some(recipient) = &document.recipient {
+        write_dest(&mut w, recipient, &ide.tp_amb);
+    }
+    for person in &document.authorized_downloads {
+        write_aut_xml(&mut w, person);
+    }
+    for item in &document.items {
+        write_det(&mut w, item, ide)?;
+    }
+    write_total(&mut w, &document.total);
+    write_transp(&mut w, &document.transport);
+    write_cobr(&mut w, &document.billing);
+    write_pag(&mut w, &document.payment);
+    write_inf_adic(&mut w, &document.additional_info);
+    if let Some(responsible) = &document.tech_responsible {
+        write_inf_resp_tec(&mut w, responsible);
+    }
+    w.end("infNFe");
+    w.end("NFe");
+    Ok(w.into_string())
+}
+
+/// The NF-e always identifies its recipient. The NFC-e may omit the consumer, and when
+/// it does identify them, they are not a taxpayer of ICMS.
+fn validate_recipient(document: &NFeDocument) -> Result<(), ValidationError> {
+    match (&document.recipient, document.identification.mod_.as_str()) {
+        (None, "55") => Err(ValidationError::new("dest", "is required for mod 55")),
+        (Some(recipient), "65") if recipient.ind_ie_dest != "9" || recipient.ie.is_some() => Err(
+            ValidationError::new("indIEDest", "must be 9, without IE, for mod 65"),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// `TDateTimeUTC`: AAAA-MM-DDThh:mm:ssTZD in the local time of the issuing state.
+fn date_time(ide: &NFeIdentification, value: &DateTime<Utc>) -> String {
+    ide.local_time(value)
+        .format("%Y-%m-%dT%H:%M:%S%:z")
+        .to_string()
+}
+
+/// `TSerie` and `TNF` do not accept leading zeros.
+fn number(value: &str) -> String {
+    let trimmed = value.trim_start_matches('0');
+    if trimmed.is_empty() {
+        "0".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn write_ide(w: &mut XmlWriter, ide: &NFeIdentification, c_dv: &str, references: &[NFeReference]) {
+    w.start("ide");
+    w.text("cUF", &ide.c_uf);
+    w.text("cNF", &ide.c_nf);
+    w.text("natOp", &ide.nat_op);
+    w.text("mod", &ide.mod_);
+    w.text("serie", &number(&ide.serie));
+    w.text("nNF", &number(&ide.n_nf));
+    w.text("dhEmi", &date_time(ide, &ide.dh_emi));
+    if let Some(dh_sai_ent) = &ide.dh_sai_ent {
+        w.text("dhSaiEnt", &date_time(ide, dh_sai_ent));
+    }
+    w.text("tpNF", &ide.tp_nf);
+    w.text("idDest", &ide.id_dest);
+    w.text("cMunFG", &ide.c_mun_fg);
+    w.text("tpImp", &ide.tp_imp);
+    w.text("tpEmis", &ide.tp_emis);
+    w.text("cDV", c_dv);
+    w.text("tpAmb", &ide.tp_amb);
+    w.text("finNFe", &ide.fin_nfe);
+    w.text("indFinal", &ide.ind_final);
+    w.text("indPres", &ide.ind_pres);
+    w.text("procEmi", &ide.proc_emi);
+    w.text("verProc", &ide.ver_proc);
+    if let Some(dh_cont) = &ide.dh_cont {
+        w.text("dhCont", &date_time(ide, dh_cont));
+        w.opt_text("xJust", ide.x_justificativa.as_deref());
+    }
+    for reference in references {
+        write_reference(w, reference);
+    }
+    w.end("ide");
+}
+
+fn write_reference(w: &mut XmlWriter, reference: &NFeReference) {
+    w.start("NFref");
+    w.opt_text("refNFe", reference.ref_nfe.as_deref());
+    w.opt_text("refNFeSig", reference.ref_nfe_sig.as_deref());
+    if let Some(ref_nf) = &reference.ref_nf {
+        w.start("refNF");
+        w.text("cUF", &ref_nf.c_uf);
+        w.text("AAMM", &ref_nf.aamm);
+        w.text("CNPJ", &ref_nf.cnpj);
+        w.text("mod", &ref_nf.mod_);
+        w.text("serie", &number(&ref_nf.serie));
+        w.text("nNF", &number(&ref_nf.n_nf));
+        w.end("refNF");
+    }
+    if let Some(ref_nfp) = &reference.ref_nfp {
+        w.start("refNFP");
+        w.text("cUF", &ref_nfp.c_uf);
+        w.text("AAMM", &ref_nfp.aamm);
+        w.opt_text("CNPJ", ref_nfp.cnpj.as_deref());
+        w.opt_text("CPF", ref_nfp.cpf.as_deref());
+        w.text("IE", &ref_nfp.ie);
+        w.text("mod", &ref_nfp.mod_);
+        w.text("serie", &number(&ref_nfp.serie));
+        w.text("nNF", &number(&ref_nfp.n_nf));
+        w.end("refNFP");
+    }
+    w.opt_text("refCTe", reference.ref_cte.as_deref());
+    if let Some(ref_ecf) = &reference.ref_ecf {
+        w.start("refECF");
+        w.text("mod", &ref_ecf.mod_);
+        w.text("nECF", &ref_ecf.n_ecf);
+        w.text("nCOO", &ref_ecf.n_coo);
+        w.end("refECF");
+    }
+    w.end("NFref");
+}
+
+fn write_address(w: &mut XmlWriter, tag: &str, address: &NFeAddress) {
+    w.start(tag);
+    w.text("xLgr", &address.x_lgr);
+    w.text("nro", &address.nro);
+    w.opt_text("xCpl", address.x_cpl.as_deref());
+    w.text("xBairro", &address.x_bairro);
+    w.text("cMun", &address.c_mun);
+    w.text("xMun", &address.x_mun);
+    w.text("UF", &address.uf);
+    w.opt_text("CEP", address.cep.as_deref());
+    w.opt_text("cPais", address.c_pais.as_deref());
+    w.opt_text("xPais", address.x_pais.as_deref());
+    w.opt_text("fone", address.fone.as_deref());
+    w.end(tag);
+}
+
+fn write_emit(w: &mut XmlWriter, emitter: &NFeEmitter) {
+    w.start("emit");
+    w.opt_text("CNPJ", emitter.cnpj.as_deref());
+    w.opt_text("CPF", emitter.cpf.as_deref());
+    w.text("xNome", &emitter.x_nome);
+    w.opt_text("xFant", emitter.x_fant.as_deref());
+    write_address(w, "enderEmit", &emitter.ender_emit);
+    w.text("IE", &emitter.ie);
+    w.opt_text("IEST", emitter.iest.as_deref());
+    if emitter.im.is_some() {
+        w.opt_text("IM", emitter.im.as_deref());
+        w.opt_text("CNAE", emitter.cnae.as_deref());
+    }
+    w.text("CRT", &emitter.crt);
+    w.end("emit");
+}
+
+fn write_dest(w: &mut XmlWriter, recipient: &NFeRecipient, tp_amb: &str) {
+    w.start("dest");
+    w.opt_text("CNPJ", recipient.cnpj.as_deref());
+    w.opt_text("CPF", recipient.cpf.as_deref());
+    w.opt_text("idEstrangeiro", recipient.id_estrangeiro.as_deref());
+    if tp_amb == "2" {
+        w.text("xNome", HOMOLOGATION_NAME);
+    } else {
+        w.opt_text("xNome", recipient.x_nome.as_deref());
+    }
+    if let Some(address) = &recipient.ender_dest {
+        write_address(w, "enderDest", address);
+    }
+    w.text("indIEDest", &recipient.ind_ie_dest);
+    w.opt_text("IE", recipient.ie.as_deref());
+    w.opt_text("ISUF", recipient.isuf.as_deref());
+    w.opt_text("IM", recipient.im.as_deref());
+    w.opt_text("email", recipient.email.as_deref());
+    w.end("dest");
+}
+
+fn write_det(
+    w: &mut XmlWriter,
+    item: &NFeItem,
+    ide: &NFeIdentification,
+) -> Result<(), ValidationError> {
+    let prod = &item.prod;
+    w.start_with("det", &[("nItem", &item.n_item.to_string())]);
+    w.start("prod");
+    w.text("cProd", &prod.c_prod);
+    w.text("cEAN", &prod.c_ean);
+    if ide.mod_ == "65" && ide.tp_amb == "2" && item.n_item == 1 {
+        w.text("xProd", HOMOLOGATION_PRODUCT);
+    } else {
+        w.text("xProd", &prod.x_prod);
+    }
+    w.text("NCM", &prod.ncm);
+    w.opt_text("CEST", prod.cest.as_deref());
+    w.text("CFOP", &prod.cfop);
+    w.text("uCom", &prod.u_com);
+    w.decimal("qCom", prod.q_com, QUANTITY);
+    w.decimal("vUnCom", prod.v_un_com, UNIT_VALUE);
+    w.decimal("vProd", prod.v_prod, MONEY);
+    w.text("cEANTrib", &prod.c_ean_trib);
+    w.text("uTrib", &prod.u_trib);
+    w.decimal("qTrib", prod.q_trib, QUANTITY);
+    w.decimal("vUnTrib", prod.v_un_trib, UNIT_VALUE);
+    w.opt_decimal("vFrete", prod.v_frete, MONEY);
+    w.opt_decimal("vSeg", prod.v_seg, MONEY);
+    w.opt_decimal("vDesc", prod.v_desc, MONEY);
+    w.opt_decimal("vOutro", prod.v_outro, MONEY);
+    w.text("indTot", &prod.ind_tot);
+    write_specific(w, &prod.specific);
+    w.end("prod");
+    write_imposto(w, &item.imposto)?;
+    if let Some(devol) = &item.imposto_devol {
+        w.start("impostoDevol");
+        w.decimal("pDevol", devol.p_devol, SHARE);
+        w.start("IPI");
+        w.decimal("vIPIDevol", devol.ipi.v_ipi_devol, MONEY);
+        w.end("IPI");
+        w.end("impostoDevol");
+    }
+    w.opt_text("infAdProd", item.inf_ad_prod.as_deref());
+    w.end("det");
+    Ok(())
+}
+
+/// `rastro` followed by the informed group of the specific-product choice.
+fn write_specific(w: &mut XmlWriter, specific: &NFeSpecificProduct) {
+    for batch in &specific.rastro {
+        w.start("rastro");
+        w.text("nLote", &batch.n_lote);
+        w.decimal("qLote", batch.q_lote, BATCH);
+        w.text("dFab", &batch.d_fab.format("%Y-%m-%d").to_string());
+        w.text("dVal", &batch.d_val.format("%Y-%m-%d").to_string());
+        w.opt_text("cAgreg", batch.c_agreg.as_deref());
+        w.end("rastro");
+    }
+    if let Some(veic_prod) = &specific.veic_prod {
+        write_veic_prod(w, veic_prod);
+    }
+    if let Some(med) = &specific.med {
+        w.start("med");
+        w.text("cProdANVISA", &med.c_prod_anvisa);
+        w.opt_text("xMotivoIsencao", med.x_motivo_isencao.as_deref());
+        w.decimal("vPMC", med.v_pmc, MONEY);
+        w.end("med");
+    }
+    for arma in &specific.arma {
+        w.start("arma");
+        w.text("tpArma", &arma.tp_arma);
+        w.text("nSerie", &arma.n_serie);
+        w.text("nCano", &arma.n_cano);
+        w.text("descr", &arma.descr);
+        w.end("arma");
+    }
+    if let Some(comb) = &specific.comb {
+        write_comb(w, comb);
+    }
+    w.opt_text("nRECOPI", specific.n_recopi.as_deref());
+}
+
+fn write_veic_prod(w: &mut XmlWriter, veic_prod: &NFeVehicleProduct) {
+    w.start("veicProd");
+    for (tag, value) in [
+        ("tpOp", &veic_prod.tp_op),
+        ("chassi", &veic_prod.chassi),
+        ("cCor", &veic_prod.c_cor),
+        ("xCor", &veic_prod.x_cor),
+        ("pot", &veic_prod.pot),
+        ("cilin", &veic_prod.cilin),
+        ("pesoL", &veic_prod.peso_l),
+        ("pesoB", &veic_prod.peso_b),
+        ("nSerie", &veic_prod.n_serie),
+        ("tpComb", &veic_prod.tp_comb),
+        ("nMotor", &veic_prod.n_motor),
+        ("CMT", &veic_prod.cmt),
+        ("dist", &veic_prod.dist),
+        ("anoMod", &veic_prod.ano_mod),
+        ("anoFab", &veic_prod.ano_fab),
+        ("tpPint", &veic_prod.tp_pint),
+        ("tpVeic", &veic_prod.tp_veic),
+        ("espVeic", &veic_prod.esp_veic),
+        ("VIN", &veic_prod.vin),
+        ("condVeic", &veic_prod.cond_veic),
+        ("cMod", &veic_prod.c_mod),
+        ("cCorDENATRAN", &veic_prod.c_cor_denatran),
+        ("lota", &veic_prod.lota),
+        ("tpRest", &veic_prod.tp_rest),
+    ] {
+        w.text(tag, value);
+    }
+    w.end("veicProd");
+}
+
+fn write_comb(w: &mut XmlWriter, comb: &NFeFuel) {
+    w.start("comb");
+    w.text("cProdANP", &comb.c_prod_anp);
+    w.text("descANP", &comb.desc_anp);
+    w.opt_decimal("pGLP", comb.p_glp, RATE);
+    w.opt_decimal("pGNn", comb.p_gnn, RATE);
+    w.opt_decimal("pGNi", comb.p_gni, RATE);
+    w.opt_decimal("vPart", comb.v_part, MONEY);
+    w.opt_text("CODIF", comb.codif.as_deref());
+    w.opt_decimal("qTemp", comb.q_temp, QUANTITY);
+    w.text("UFCons", &comb.uf_cons);
+    if let Some(cide) = &comb.cide {
+        w.start("CIDE");
+        w.decimal("qBCProd", cide.q_bc_prod, QUANTITY);
+        w.decimal("vAliqProd", cide.v_aliq_prod, QUANTITY);
+        w.decimal("vCIDE", cide.v_cide, MONEY);
+        w.end("CIDE");
+    }
+    if let Some(encerrante) = &comb.encerrante {
+        w.start("encerrante");
+        w.text("nBico", &encerrante.n_bico);
+        w.opt_text("nBomba", encerrante.n_bomba.as_deref());
+        w.text("nTanque", &encerrante.n_tanque);
+        w.decimal("vEncIni", encerrante.v_enc_ini, BATCH);
+        w.decimal("vEncFin", encerrante.v_enc_fin, BATCH);
+        w.end("encerrante");
+    }
+    w.opt_decimal("pBio", comb.p_bio, RATE);
+    for origin in &comb.orig_comb {
+        w.start("origComb");
+        w.text("indImport", &origin.ind_import);
+        w.text("cUFOrig", &origin.c_uf_orig);
+        w.decimal("pOrig", origin.p_orig, RATE);
+        w.end("origComb");
+    }
+    w.end("comb");
+}
+
+fn write_imposto(w: &mut XmlWriter, taxes: &NFeItemTaxes) -> Result<(), ValidationError> {
+    w.start("imposto");
+    w.opt_decimal("vTotTrib", taxes.v_tot_trib, MONEY);
+    if let Some(icms) = &taxes.icms {
+        write_icms(w, icms)?;
+    }
+    if let Some(ipi) = &taxes.ipi {
+        let group = IpiGroup::resolve(&ipi.cst)?;
+        w.start("IPI");
+        w.opt_text("CNPJProd", ipi.cnpj_prod.as_deref());
+        w.opt_text("cSelo", ipi.c_selo.as_deref());
+        w.opt_text("qSelo", ipi.q_selo.as_deref());
+        w.text("cEnq", &ipi.c_enq);
+        w.start(group.tag());
+        w.text("CST", &ipi.cst);
+        if group == IpiGroup::Trib {
+            w.opt_decimal("vBC", ipi.v_bc, MONEY);
+            w.opt_decimal("pIPI", ipi.p_ipi, RATE);
+            w.opt_decimal("qUnid", ipi.q_unid, QUANTITY);
+            w.opt_decimal("vUnid", ipi.v_unid, QUANTITY);
+            w.opt_decimal("vIPI", ipi.v_ipi, MONEY);
+        }
+        w.end(group.tag());
+        w.end("IPI");
+    }
+    if let Some(ii) = &taxes.ii {
+        w.start("II");
+        w.decimal("vBC", ii.v_bc, MONEY);
+        w.decimal("vDespAdu", ii.v_desp_adu, MONEY);
+        w.decimal("vII", ii.v_ii, MONEY);
+        w.decimal("vIOF", ii.v_iof, MONEY);
+        w.end("II");
+    }
+    if let Some(issqn) = &taxes.issqn {
+        w.start("ISSQN");
+        w.opt_decimal("vBC", issqn.v_bc, MONEY);
+        w.decimal("vAliq", issqn.v_aliq, RATE);
+        w.opt_decimal("vISSQN", issqn.v_issqn, MONEY);
+        w.text("cMunFG", &issqn.c_mun_fg);
+        w.text("cListServ", &issqn.c_list_serv);
+        w.opt_decimal("vDeducao", issqn.v_deducao, MONEY);
+        w.opt_decimal("vOutro", issqn.v_outro, MONEY);
+        w.opt_decimal("vDescIncond", issqn.v_desc_incond, MONEY);
+        w.opt_decimal("vDescCond", issqn.v_desc_cond, MONEY);
+        w.opt_decimal("vISSRet", issqn.v_iss_ret, MONEY);
+        w.text("indISS", &issqn.ind_iss);
+        w.opt_text("cServico", issqn.c_servico.as_deref());
+        w.opt_text("cMun", issqn.c_mun.as_deref());
+        w.opt_text("cPais", issqn.c_pais.as_deref());
+        w.opt_text("nProcesso", issqn.n_processo.as_deref());
+        w.text("indIncentivo", &issqn.ind_incentivo);
+        w.end("ISSQN");
+    }
+    if let Some(pis) = &taxes.pis {
+        let group = ContributionGroup::resolve("PIS", &pis.cst)?;
+        w.start("PIS");
+        w.start(&group.tag("PIS"));
+        w.text("CST", &pis.cst);
+        if group != ContributionGroup::Nt {
+            w.opt_decimal("vBC", pis.v_bc, MONEY);
+            w.opt_decimal("pPIS", pis.p_pis, RATE);
+            w.opt_decimal("qBCProd", pis.q_bc_prod, QUANTITY);
+            w.opt_decimal("vAliqProd", pis.v_aliq_prod, QUANTITY);
+            w.opt_decimal("vPIS", pis.v_pis, MONEY);
+        }
+        w.end(&group.tag("PIS"));
+        w.end("PIS");
+    }
+    if let Some(pis_st) = &taxes.pis_st {
+        w.start("PISST");
+        w.opt_decimal("vBC", pis_st.v_bc, MONEY);
+        w.opt_decimal("pPIS", pis_st.p_pis, RATE);
+        w.opt_decimal("qBCProd", pis_st.q_bc_prod, QUANTITY);
+        w.opt_decimal("vAliqProd", pis_st.v_aliq_prod, QUANTITY);
+        w.opt_decimal("vPIS", pis_st.v_pis, MONEY);
+        w.opt_text("indSomaPISST", pis_st.ind_soma_pis_st.as_deref());
+        w.end("PISST");
+    }
+    if let Some(cofins) = &taxes.cofins {
+        let group = ContributionGroup::resolve("COFINS", &cofins.cst)?;
+        w.start("COFINS");
+        w.start(&group.tag("COFINS"));
+        w.text("CST", &cofins.cst);
+        if group != ContributionGroup::Nt {
+            w.opt_decimal("vBC", cofins.v_bc, MONEY);
+            w.opt_decimal("pCOFINS", cofins.p_cofins, RATE);
+            w.opt_decimal("qBCProd", cofins.q_bc_prod, QUANTITY);
+            w.opt_decimal("vAliqProd", cofins.v_aliq_prod, QUANTITY);
+            w.opt_decimal("vCOFINS", cofins.v_cofins, MONEY);
+        }
+        w.end(&group.tag("COFINS"));
+        w.end("COFINS");
+    }
+    if let Some(cofins_st) = &taxes.cofins_st {
+        w.start("COFINSST");
+        w.opt_decimal("vBC", cofins_st.v_bc, MONEY);
+        w.opt_decimal("pCOFINS", cofins_st.p_cofins, RATE);
+        w.opt_decimal("qBCProd", cofins_st.q_bc_prod, QUANTITY);
+        w.opt_decimal("vAliqProd", cofins_st.v_aliq_prod, QUANTITY);
+        w.opt_decimal("vCOFINS", cofins_st.v_cofins, MONEY);
+        w.opt_text("indSomaCOFINSST", cofins_st.ind_soma_cofins_st.as_deref());
+        w.end("COFINSST");
+    }
+    w.end("imposto");
+    Ok(())
+}
+
+/// Elements of each ICMS subgroup in layout order, limited to the fields that are stored.
+fn icms_elements(group: IcmsGroup) -> &'static [&'static str] {
+    const ST: [&str; 9] = [
+        "modBCST", "pMVAST", "pRedBCST", "vBCST", "pICMSST", "vICMSST", "vBCFCPST", "pFCPST",
+        "vFCPST",
+    ];
+    match group {
+        IcmsGroup::Icms00 => &[
+            "orig", "CST", "modBC", "vBC", "pICMS", "vICMS", "pFCP", "vFCP",
+        ],
+        IcmsGroup::Icms02 => &["orig", "CST", "qBCMono", "adRemICMS", "vICMSMono"],
+        IcmsGroup::Icms15 => &[
+            "orig",
+            "CST",
+            "qBCMono",
+            "adRemICMS",
+            "vICMSMono",
+            "qBCMonoReten",
+            "adRemICMSReten",
+            "vICMSMonoReten",
+            "pRedAdRem",
+            "motRedAdRem",
+        ],
+        IcmsGroup::Icms53 => &[
+            "orig",
+            "CST",
+            "qBCMono",
+            "adRemICMS",
+            "vICMSMonoOp",
+            "pDif",
+            "vICMSMonoDif",
+            "vICMSMono",
+            "qBCMonoDif",
+            "adRemICMSDif",
+        ],
+        IcmsGroup::Icms61 => &["orig", "CST", "qBCMonoRet", "adRemICMSRet", "vICMSMonoRet"],
+        IcmsGroup::Icms10 => &[
+            "orig", "CST", "modBC", "vBC", "pICMS", "vICMS", "vBCFCP", "pFCP", "vFCP", ST[0],
+            ST[1], ST[2], ST[3], ST[4], ST[5], ST[6], ST[7], ST[8],
+        ],
+        IcmsGroup::Icms20 => &[
+            "orig",
+            "CST",
+            "modBC",
+            "pRedBC",
+            "vBC",
+            "pICMS",
+            "vICMS",
+            "vBCFCP",
+            "pFCP",
+            "vFCP",
+            "vICMSDeson",
+            "motDesICMS",
+            "indDeduzDeson",
+        ],
+        IcmsGroup::Icms30 => &[
+            "orig",
+            "CST",
+            ST[0],
+            ST[1],
+            ST[2],
+            ST[3],
+            ST[4],
+            ST[5],
+            ST[6],
+            ST[7],
+            ST[8],
+            "vICMSDeson",
+            "motDesICMS",
+            "indDeduzDeson",
+        ],
+        IcmsGroup::Icms40 => &["orig", "CST", "vICMSDeson", "motDesICMS", "indDeduzDeson"],
+        IcmsGroup::Icms51 => &[
+            "orig", "CST", "modBC", "pRedBC", "vBC", "pICMS", "vICMSOp", "pDif", "vICMSDif",
+            "vICMS", "vBCFCP", "pFCP", "vFCP",
+        ],
+        IcmsGroup::Icms60 | IcmsGroup::Sn500 => &[
+            "orig",
+            "CST",
+            "CSOSN",
+            "vBCSTRet",
+            "pST",
+            "vICMSSubstituto",
+            "vICMSSTRet",
+            "vBCFCPSTRet",
+            "pFCPSTRet",
+            "vFCPSTRet",
+        ],
+        IcmsGroup::Icms70 => &[
+            "orig",
+            "CST",
+            "modBC",
+            "pRedBC",
+            "vBC",
+            "pICMS",
+            "vICMS",
+            "vBCFCP",
+            "pFCP",
+            "vFCP",
+            ST[0],
+            ST[1],
+            ST[2],
+            ST[3],
+            ST[4],
+            ST[5],
+            ST[6],
+            ST[7],
+            ST[8],
+            "vICMSDeson",
+            "motDesICMS",
+            "indDeduzDeson",
+        ],
+        IcmsGroup::Icms90 => &[
+            "orig",
+            "CST",
+            "modBC",
+            "vBC",
+            "pRedBC",
+            "pICMS",
+            "vICMS",
+            "vBCFCP",
+            "pFCP",
+            "vFCP",
+            ST[0],
+            ST[1],
+            ST[2],
+            ST[3],
+            ST[4],
+            ST[5],
+            ST[6],
+            ST[7],
+            ST[8],
+            "vICMSDeson",
+            "motDesICMS",
+            "indDeduzDeson",
+        ],
+        IcmsGroup::IcmsPart => &[
+            "orig", "CST", "modBC", "vBC", "pRedBC", "pICMS", "vICMS", ST[0], ST[1], ST[2], ST[3],
+            ST[4], ST[5], ST[6], ST[7], ST[8], "pBCOp", "UFST",
+        ],
+        IcmsGroup::IcmsSt => &[
+            "orig",
+            "CST",
+            "vBCSTRet",
+            "pST",
+            "vICMSSubstituto",
+            "vICMSSTRet",
+            "vBCFCPSTRet",
+            "pFCPSTRet",
+            "vFCPSTRet",
+            "vBCSTDest",
+            "vICMSSTDest",
+        ],
+        IcmsGroup::Sn101 => &["orig", "CSOSN", "pCredSN", "vCredICMSSN"],
+        IcmsGroup::Sn102 => &["orig", "CSOSN"],
+        IcmsGroup::Sn201 => &[
+            "orig",
+            "CSOSN",
+            ST[0],
+            ST[1],
+            ST[2],
+            ST[3],
+            ST[4],
+            ST[5],
+            ST[6],
+            ST[7],
+            ST[8],
+            "pCredSN",
+            "vCredICMSSN",
+        ],
+        IcmsGroup::Sn202 => &[
+            "orig", "CSOSN", ST[0], ST[1], ST[2], ST[3], ST[4], ST[5], ST[6], ST[7], ST[8],
+        ],
+        IcmsGroup::Sn900 => &[
+            "orig",
+            "CSOSN",
+            "modBC",
+            "vBC",
+            "pRedBC",
+            "pICMS",
+            "vICMS",
+            ST[0],
+            ST[1],
+            ST[2],
+            ST[3],
+            ST[4],
+            ST[5],
+            ST[6],
+            ST[7],
+            ST[8],
+            "pCredSN",
+            "vCredICMSSN",
+        ],
+    }
+}
+
+fn write_icms(w: &mut XmlWriter, icms: &NFeIcms) -> Result<(), ValidationError> {
+    let group = IcmsGroup::of(icms)?;
+    w.start("ICMS");
+    w.start(group.tag());
+    for element in icms_elements(group) {
+        let (text, amount, rate) = match *element {
+            "orig" => (Some(icms.orig.as_str()), None, None),
+            "CST" => (icms.cst.as_deref(), None, None),
+            "CSOSN" => (icms.csosn.as_deref(), None, None),
+            "modBC" => (icms.mod_bc.as_deref(), None, None),
+            "modBCST" => (icms.mod_bc_st.as_deref(), None, None),
+            "motDesICMS" => (icms.mot_des_icms.as_deref(), None, None),
+            "indDeduzDeson" => (icms.ind_deduz_deson.as_deref(), None, None),
+            "UFST" => (icms.uf_st.as_deref(), None, None),
+            "motRedAdRem" => (icms.mot_red_ad_rem.as_deref(), None, None),
+            "vBC" => (None, icms.v_bc, None),
+            "vICMSOp" => (None, icms.v_icms_op, None),
+            "vICMSDif" => (None, icms.v_icms_dif, None),
+            "vICMS" => (None, icms.v_icms, None),
+            "vBCFCP" => (None, icms.v_bc_fcp, None),
+            "vFCP" => (None, icms.v_fcp, None),
+            "vBCST" => (None, icms.v_bc_st, None),
+            "vICMSST" => (None, icms.v_icms_st, None),
+            "vBCFCPST" => (None, icms.v_bc_fcp_st, None),
+            "vFCPST" => (None, icms.v_fcp_st, None),
+            "vICMSDeson" => (None, icms.v_icms_deson, None),
+            "vBCSTRet" => (None, icms.v_bc_st_ret, None),
+            "vICMSSubstituto" => (None, icms.v_icms_substituto, None),
+            "vICMSSTRet" => (None, icms.v_icms_st_ret, None),
+            "vBCFCPSTRet" => (None, icms.v_bc_fcp_st_ret, None),
+            "vFCPSTRet" => (None, icms.v_fcp_st_ret, None),
+            "vBCSTDest" => (None, icms.v_bc_st_dest, None),
+            "vICMSSTDest" => (None, icms.v_icms_st_dest, None),
+            "vCredICMSSN" => (None, icms.v_cred_icms_sn, None),
+            "vICMSMonoOp" => (None, icms.v_icms_mono_op, None),
+            "vICMSMonoDif" => (None, icms.v_icms_mono_dif, None),
+            "vICMSMono" => (None, icms.v_icms_mono, None),
+            "vICMSMonoReten" => (None, icms.v_icms_mono_reten, None),
+            "vICMSMonoRet" => (None, icms.v_icms_mono_ret, None),
+            "pRedBC" => (None, None, icms.p_red_bc),
+            "pICMS" => (None, None, icms.p_icms),
+            "pDif" => (None, None, icms.p_dif),
+            "pFCP" => (None, None, icms.p_fcp),
+            "pMVAST" => (None, None, icms.p_mva_st),
+            "pRedBCST" => (None, None, icms.p_red_bc_st),
+            "pICMSST" => (None, None, icms.p_icms_st),
+            "pFCPST" => (None, None, icms.p_fcp_st),
+            "pST" => (None, None, icms.p_st),
+            "pFCPSTRet" => (None, None, icms.p_fcp_st_ret),
+            "pBCOp" => (None, None, icms.p_bc_op),
+            "pCredSN" => (None, None, icms.p_cred_sn),
+            "adRemICMS" => (None, None, icms.ad_rem_icms),
+            "adRemICMSReten" => (None, None, icms.ad_rem_icms_reten),
+            "adRemICMSDif" => (None, None, icms.ad_rem_icms_dif),
+            "adRemICMSRet" => (None, None, icms.ad_rem_icms_ret),
+            "pRedAdRem" => (None, None, icms.p_red_ad_rem),
+            _ => (None, None, None),
+        };
+        let quantity = match *element {
+            "qBCMono" => icms.q_bc_mono,
+            "qBCMonoReten" => icms.q_bc_mono_reten,
+            "qBCMonoDif" => icms.q_bc_mono_dif,
+            "qBCMonoRet" => icms.q_bc_mono_ret,
+            _ => None,
+        };
+        w.opt_text(element, text);
+        w.opt_decimal(element, amount, MONEY);
+        w.opt_decimal(element, rate, RATE);
+        w.opt_decimal(element, quantity, QUANTITY);
+    }
+    w.end(group.tag());
+    w.end("ICMS");
+    Ok(())
+}
+
+fn write_total(w: &mut XmlWriter, total: &NFeTotal) {
+    w.start("total");
+    w.start("ICMSTot");
+    for (element, amount) in total.icms_tot.amounts() {
+        w.opt_decimal(element, amount, MONEY);
+    }
+    w.end("ICMSTot");
+    if let Some(issqn_tot) = &total.issqn_tot {
+        w.start("ISSQNtot");
+        let amounts = issqn_tot.amounts();
+        // dCompet sits between vCOFINS and vDeducao.
+        let (before, after) = amounts.split_at(5);
+        for (element, amount) in before {
+            w.opt_decimal(element, *amount, MONEY);
+        }
+        w.text(
+            "dCompet",
+            &issqn_tot.d_compet.format("%Y-%m-%d").to_string(),
+        );
+        for (element, amount) in after {
+            w.opt_decimal(element, *amount, MONEY);
+        }
+        w.opt_text("cRegTrib", issqn_tot.c_reg_trib.as_deref());
+        w.end("ISSQNtot");
+    }
+    if let Some(ret_trib) = &total.ret_trib {
+        w.start("retTrib");
+        w.opt_decimal("vRetPIS", ret_trib.v_ret_pis, MONEY);
+        w.opt_decimal("vRetCOFINS", ret_trib.v_ret_cofins, MONEY);
+        w.opt_decimal("vRetCSLL", ret_trib.v_ret_csll, MONEY);
+        w.opt_decimal("vBCIRRF", ret_trib.v_bc_irrf, MONEY);
+        w.opt_decimal("vIRRF", ret_trib.v_irrf, MONEY);
+        w.opt_decimal("vBCRetPrev", ret_trib.v_bc_ret_prev, MONEY);
+        w.opt_decimal("vRetPrev", ret_trib.v_ret_prev, MONEY);
+        w.end("retTrib");
+    }
+    w.end("total");
+}
+
+fn write_vehicle(w: &mut XmlWriter, tag: &str, vehicle: &NFeVehicle) {
+    w.start(tag);
+    w.text("placa", &vehicle.placa);
+    w.opt_text("UF", vehicle.uf.as_deref());
+    w.opt_text("RNTC", vehicle.rntc.as_deref());
+    w.end(tag);
+}
+
+fn write_transp(w: &mut XmlWriter, transport: &NFeTransport) {
+    w.start("transp");
+    w.text("modFrete", &transport.mod_frete);
+    if let Some(carrier) = &transport.transporta {
+        w.start("transporta");
+        w.opt_text("CNPJ", carrier.cnpj.as_deref());
+        w.opt_text("CPF", carrier.cpf.as_deref());
+        w.opt_text("xNome", carrier.x_nome.as_deref());
+        w.opt_text("IE", carrier.ie.as_deref());
+        w.opt_text("xEnder", carrier.x_ender.as_deref());
+        w.opt_text("xMun", carrier.x_mun.as_deref());
+        w.opt_text("UF", carrier.uf.as_deref());
+        w.end("transporta");
+    }
+    if let Some(ret_transp) = &transport.ret_transp {
+        w.start("retTransp");
+        w.decimal("vServ", ret_transp.v_serv, MONEY);
+        w.decimal("vBCRet", ret_transp.v_bc_ret, MONEY);
+        w.decimal("pICMSRet", ret_transp.p_icms_ret, RATE);
+        w.decimal("vICMSRet", ret_transp.v_icms_ret, MONEY);
+        w.text("CFOP", &ret_transp.cfop);
+        w.text("cMunFG", &ret_transp.c_mun_fg);
+        w.end("retTransp");
+    }
+    if let Some(vehicle) = &transport.veic_transp {
+        write_vehicle(w, "veicTransp", vehicle);
+    }
+    for trailer in &transport.reboque {
+        write_vehicle(w, "reboque", trailer);
+    }
+    w.opt_text("vagao", transport.vagao.as_deref());
+    w.opt_text("balsa", transport.balsa.as_deref());
+    for volume in &transport.vol {
+        w.start("vol");
+        w.opt_text("qVol", volume.q_vol.as_deref());
+        w.opt_text("esp", volume.esp.as_deref());
+        w.opt_text("marca", volume.marca.as_deref());
+        w.opt_text("nVol", volume.n_vol.as_deref());
+        w.opt_decimal("pesoL", volume.peso_l, WEIGHT);
+        w.opt_decimal("pesoB", volume.peso_b, WEIGHT);
+        for seal in &volume.lacres {
+            w.start("lacres");
+            w.text("nLacre", &seal.n_lacre);
+            w.end("lacres");
+        }
+        w.end("vol");
+    }
+    w.end("transp");
+}
+
+fn write_cobr(w: &mut XmlWriter, billing: &NFeBilling) {
+    if billing.is_empty() {
+        return;
+    }
+    w.start("cobr");
+    if let Some(fat) = &billing.fat {
+        w.start("fat");
+        w.opt_text("nFat", fat.n_fat.as_deref());
+        w.opt_decimal("vOrig", fat.v_orig, MONEY);
+        w.opt_decimal("vDesc", fat.v_desc, MONEY);
+        w.opt_decimal("vLiq", fat.v_liq, MONEY);
+        w.end("fat");
+    }
+    for dup in &billing.dup {
+        w.start("dup");
+        w.opt_text("nDup", dup.n_dup.as_deref());
+        if let Some(d_venc) = dup.d_venc {
+            w.text("dVenc", &d_venc.format("%Y-%m-%d").to_string());
+        }
+        w.decimal("vDup", dup.v_dup, MONEY);
+        w.end("dup");
+    }
+    w.end("cobr");
+}
+
+fn write_pag(w: &mut XmlWriter, payment: &NFePayment) {
+    w.start("pag");
+    for det in &payment.det_pag {
+        w.start("detPag");
+        w.opt_text("indPag", det.ind_pag.as_deref());
+        w.text("tPag", &det.t_pag);
+        w.opt_text("xPag", det.x_pag.as_deref());
+        w.decimal("vPag", det.v_pag, MONEY);
+        if let Some(d_pag) = det.d_pag {
+            w.text("dPag", &d_pag.format("%Y-%m-%d").to_string());
+        }
+        w.opt_text("CNPJPag", det.cnpj_pag.as_deref());
+        w.opt_text("UFPag", det.uf_pag.as_deref());
+        if let Some(card) = &det.card {
+            w.start("card");
+            w.text("tpIntegra", &card.tp_integra);
+            w.opt_text("CNPJ", card.cnpj.as_deref());
+            w.opt_text("tBand", card.t_band.as_deref());
+            w.opt_text("cAut", card.c_aut.as_deref());
+            w.end("card");
+        }
+        w.end("detPag");
+    }
+    w.opt_decimal("vTroco", payment.v_troco, MONEY);
+    w.end("pag");
+}
+
+fn write_aut_xml(w: &mut XmlWriter, person: &NFeAuthorizedDownload) {
+    w.start("autXML");
+    w.opt_text("CNPJ", person.cnpj.as_deref());
+    w.opt_text("CPF", person.cpf.as_deref());
+    w.end("autXML");
+}
+
+fn write_inf_adic(w: &mut XmlWriter, inf_adic: &NFeAdditionalInfo) {
+    if inf_adic.is_empty() {
+        return;
+    }
+    w.start("infAdic");
+    w.opt_text("infAdFisco", inf_adic.inf_ad_fisco.as_deref());
+    w.opt_text("infCpl", inf_adic.inf_cpl.as_deref());
+    for (tag, observations) in [
+        ("obsCont", &inf_adic.obs_cont),
+        ("obsFisco", &inf_adic.obs_fisco),
+    ] {
+        for observation in observations {
+            w.start_with(tag, &[("xCampo", &observation.x_campo)]);
+            w.text("xTexto", &observation.x_texto);
+            w.end(tag);
+        }
+    }
+    for proc_ref in &inf_adic.proc_ref {
+        w.start("procRef");
+        w.text("nProc", &proc_ref.n_proc);
+        w.text("indProc", &proc_ref.ind_proc);
+        w.opt_text("tpAto", proc_ref.tp_ato.as_deref());
+        w.end("procRef");
+    }
+    w.end("infAdic");
+}
+
+fn write_inf_resp_tec(w: &mut XmlWriter, responsible: &NFeTechResponsible) {
+    w.start("infRespTec");
+    w.text("CNPJ", &responsible.cnpj);
+    w.text("xContato", &responsible.x_contato);
+    w.text("email", &responsible.email);
+    w.text("fone", &responsible.fone);
+    w.opt_text("idCSRT", responsible.id_csrt.as_deref());
+    w.opt_text("hashCSRT", responsible.hash_csrt.as_deref());
+    w.end("infRespTec");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(xml: &str, fragment: &str) -> usize {
+        xml.find(fragment)
+            .unwrap_or_else(|| panic!("{} not found in {}", fragment, xml))
+    }
+
+    #[test]
+    fn writes_root_and_ide() {
+        let document = NFeDocument::sample();
+        let key = document.access_key().unwrap();
+        let xml = serialize(&document).unwrap();
+
+        assert!(xml.starts_with(&format!(
+            "<NFe xmlns=\"http://www.portalfiscal.inf.br/nfe\"><infNFe Id=\"NFe{}\" versao=\"4.00\"><ide>",
+            key
+        )));
+        assert!(
+            xml.contains("<serie>1</serie><nNF>123</nNF><dhEmi>2024-03-20T10:30:00-03:00</dhEmi>")
+        );
+        assert!(xml.contains(&format!("<cDV>{}</cDV>", key.c_dv)));
+        assert!(xml.ends_with("</infNFe></NFe>"));
+    }
+
+    #[test]
+    fn follows_layout_order_and_scales() {
+        let xml = serialize(&NFeDocument::sample()).unwrap();
+
+        let order = [
+            "<ide>",
+            "<emit>",
+            "<dest>",
+            "<det nItem=\"1\">",
+            "<total>",
+            "<transp>",
+            "<pag>",
+        ];
+        for pair in order.windows(2) {
+            assert!(position(&xml, pair[0]) < position(&xml, pair[1]));
+        }
+        assert!(
+            xml.contains("<qCom>2.0000</qCom><vUnCom>50.0000000000</vUnCom><vProd>100.00</vProd>")
+        );
+        assert!(xml.contains(
+            "<ICMS><ICMS00><orig>0</orig><CST>00</CST><modBC>3</modBC><vBC>100.00</vBC><pICMS>18.0000</pICMS><vICMS>18.00</vICMS></ICMS00></ICMS>"
+        ));
+        assert!(xml.contains("<PIS><PISAliq><CST>01</CST><vBC>100.00</vBC><pPIS>1.6500</pPIS><vPIS>1.65</vPIS></PISAliq></PIS>"));
+        assert!(xml.contains("<xNome>Cliente &amp; Filhos</xNome>"));
+        assert!(xml.contains("<vNF>100.00</vNF>"));
+    }
+
+    #[test]
+    fn replaces_recipient_name_in_homologation() {
+        let mut document = NFeDocument::sample();
+        document.identification.tp_amb = "2".to_string();
+        let xml = serialize(&document).unwrap();
+        assert!(xml.contains(&format!("<xNome>{}</xNome>", HOMOLOGATION_NAME)));
+    }
+
+    #[test]
+    fn identifies_the_recipient_of_each_model() {
+        let mut document = NFeDocument::sample();
+        document.recipient = None;
+        assert_eq!(serialize(&document).unwrap_err().field, "dest");
+
+        document.identification.mod_ = "65".to_string();
+        document.identification.tp_amb = "2".to_string();
+        let xml = serialize(&document).unwrap();
+        assert!(!xml.contains("<dest>"));
+        assert!(xml.contains(&format!("<xProd>{}</xProd>", HOMOLOGATION_PRODUCT)));
+
+        let mut recipient = NFeDocument::sample().recipient.unwrap();
+        recipient.ind_ie_dest = "1".to_string();
+        document.recipient = Some(recipient);
+        assert_eq!(serialize(&document).unwrap_err().field, "indIEDest");
+    }
+
+    #[test]
+    fn requires_emitter() {
+        let mut document = NFeDocument::sample();
+        document.emitter = None;
+        assert_eq!(serialize(&document).unwrap_err().field, "emit");
+    }
+}
@@ -0,0 +1,413 @@
+//! Compiled form of the bundled XSDs.
+//!
+//! Only the constructs the NF-e layouts use are supported: global and local elements,
+//! `sequence`/`choice` with occurrence bounds, attributes and simple types restricted by
+//! enumeration, pattern, length and whiteSpace facets. Identity constraints and
+//! annotations are ignored. Schemas referenced through `include`/`import` that are not
+//! on disk (tiposBasico and xmldsig are not bundled) are recorded as missing, and the
+//! types they define are accepted without lexical checks.
+
+use crate::errors::SchemaError;
+use regex::Regex;
+use roxmltree::{Document, Node};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+pub const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema";
+
+/// `{namespace}local` name of a schema component or instance element.
+pub fn clark(namespace: &str, local: &str) -> String {
+    format!("{{{}}}{}", namespace, local)
+}
+
+/// Local part of a Clark name.
+pub fn local_name(name: &str) -> &str {
+    name.rsplit('}').next().unwrap_or(name)
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeRef {
+    /// Named type, resolved against the complex and simple types when validating.
+    Named(String),
+    /// Type of a global element, for `ref` particles.
+    Element(String),
+    Complex(Box<ComplexType>),
+    Simple(Box<SimpleType>),
+    /// No type given: any content is accepted.
+    Any,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElementDecl {
+    pub name: String,
+    pub ty: TypeRef,
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+    Element(ElementDecl),
+    Sequence(Vec<Particle>),
+    Choice(Vec<Particle>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub term: Term,
+    pub min: u32,
+    /// `None` for `maxOccurs="unbounded"`.
+    pub max: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeDecl {
+    pub name: String,
+    pub required: bool,
+    pub ty: TypeRef,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ComplexType {
+    pub particle: Option<Particle>,
+    pub attributes: Vec<AttributeDecl>,
+}
+
+/// One restriction step; `base` is checked as well, so facets accumulate along the chain.
+#[derive(Debug, Clone)]
+pub struct SimpleType {
+    pub base: String,
+    pub enumeration: Vec<String>,
+    /// Alternatives of this step; XSD patterns match the whole value.
+    pub patterns: Vec<Regex>,
+    pub length: Option<usize>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub collapse: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Schema {
+    pub target_namespace: String,
+    pub elements: HashMap<String, ElementDecl>,
+    pub complex_types: HashMap<String, ComplexType>,
+    pub simple_types: HashMap<String, SimpleType>,
+    /// `include`/`import` locations that are not on disk.
+    pub missing: Vec<String>,
+}
+
+impl Schema {
+    /// Loads `entry` from `dir` together with every schema it includes or imports.
+    pub fn load(dir: &Path, entry: &str) -> Result<Self, SchemaError> {
+        let mut schema = Schema::default();
+        let mut loaded = HashSet::new();
+        schema.load_file(dir, entry, &mut loaded)?;
+        Ok(schema)
+    }
+
+    /// Named types referenced by the loaded schemas but defined in none of them.
+    pub fn unresolved_types(&self) -> Vec<String> {
+        let mut names = HashSet::new();
+        let mut visit = |ty: &TypeRef| {
+            if let TypeRef::Named(name) = ty {
+                if !self.is_builtin(name)
+                    && !self.complex_types.contains_key(name)
+                    && !self.simple_types.contains_key(name)
+                {
+                    names.insert(name.clone());
+                }
+            }
+        };
+        for element in self.elements.values() {
+            walk_type(&element.ty, &mut visit);
+        }
+        for complex in self.complex_types.values() {
+            walk_complex(complex, &mut visit);
+        }
+        for simple in self.simple_types.values() {
+            visit(&TypeRef::Named(simple.base.clone()));
+        }
+        let mut names: Vec<String> = names
+            .into_iter()
+            .map(|n| local_name(&n).to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn is_builtin(&self, name: &str) -> bool {
+        name.starts_with(&format!("{{{}}}", XSD_NAMESPACE))
+    }
+
+    fn load_file(
+        &mut self,
+        dir: &Path,
+        file: &str,
+        loaded: &mut HashSet<String>,
+    ) -> Result<(), SchemaError> {
+        if !loaded.insert(file.to_string()) {
+            return Ok(());
+        }
+        let path = dir.join(file);
+        if !path.exists() {
+            self.missing.push(file.to_string());
+            return Ok(());
+        }
+        let text = fs::read_to_string(&path).map_err(|e| SchemaError::new(file, e.to_string()))?;
+        let document = Document::parse(&text).map_err(|e| SchemaError::new(file, e.to_string()))?;
+        let root = document.root_element();
+        let target = root.attribute("targetNamespace").unwrap_or_default();
+        if self.target_namespace.is_empty() {
+            self.target_namespace = target.to_string();
+        }
+        let reader = Reader { file, target };
+
+        for node in root.children().filter(is_xsd) {
+            match node.tag_name().name() {
+                "include" | "import" => {
+                    if let Some(location) = node.attribute("schemaLocation") {
+                        self.load_file(dir, location, loaded)?;
+                    }
+                }
+                "element" => {
+                    let decl = reader.element(node)?;
+                    self.elements.insert(decl.name.clone(), decl);
+                }
+                "complexType" => {
+                    let name = reader.component_name(node)?;
+                    self.complex_types.insert(name, reader.complex_type(node)?);
+                }
+                "simpleType" => {
+                    let name = reader.component_name(node)?;
+                    self.simple_types.insert(name, reader.simple_type(node)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn walk_type(ty: &TypeRef, visit: &mut impl FnMut(&TypeRef)) {
+    match ty {
+        TypeRef::Complex(complex) => walk_complex(complex, visit),
+        TypeRef::Simple(simple) => visit(&TypeRef::Named(simple.base.clone())),
+        other => visit(other),
+    }
+}
+
+fn walk_complex(complex: &ComplexType, visit: &mut impl FnMut(&TypeRef)) {
+    for attribute in &complex.attributes {
+        walk_type(&attribute.ty, visit);
+    }
+    if let Some(particle) = &complex.particle {
+        walk_particle(particle, visit);
+    }
+}
+
+fn walk_particle(particle: &Particle, visit: &mut impl FnMut(&TypeRef)) {
+    match &particle.term {
+        Term::Element(decl) => walk_type(&decl.ty, visit),
+        Term::Sequence(items) | Term::Choice(items) => {
+            for item in items {
+                walk_particle(item, visit);
+            }
+        }
+    }
+}
+
+fn is_xsd(node: &Node) -> bool {
+    node.is_element() && node.tag_name().namespace() == Some(XSD_NAMESPACE)
+}
+
+/// Reads the components of one schema document.
+struct Reader<'a> {
+    file: &'a str,
+    target: &'a str,
+}
+
+impl Reader<'_> {
+    fn error(&self, node: Node, message: impl Into<String>) -> SchemaError {
+        SchemaError::new(
+            self.file,
+            format!("{} (at byte {})", message.into(), node.range().start),
+        )
+    }
+
+    fn component_name(&self, node: Node) -> Result<String, SchemaError> {
+        node.attribute("name")
+            .map(|name| clark(self.target, name))
+            .ok_or_else(|| self.error(node, "global component without a name"))
+    }
+
+    /// Resolves a QName attribute value through the namespaces in scope.
+    fn qname(&self, node: Node, value: &str) -> Result<String, SchemaError> {
+        let (prefix, local) = match value.split_once(':') {
+            Some((prefix, local)) => (Some(prefix), local),
+            None => (None, value),
+        };
+        let namespace = node
+            .lookup_namespace_uri(prefix)
+            .ok_or_else(|| self.error(node, format!("unknown prefix in {}", value)))?;
+        Ok(clark(namespace, local))
+    }
+
+    fn occurs(&self, node: Node) -> Result<(u32, Option<u32>), SchemaError> {
+        let min = match node.attribute("minOccurs") {
+            Some(value) => value
+                .parse()
+                .map_err(|_| self.error(node, "invalid minOccurs"))?,
+            None => 1,
+        };
+        let max = match node.attribute("maxOccurs") {
+            Some("unbounded") => None,
+            Some(value) => Some(
+                value
+                    .parse()
+                    .map_err(|_| self.error(node, "invalid maxOccurs"))?,
+            ),
+            None => Some(1),
+        };
+        Ok((min, max))
+    }
+
+    fn element(&self, node: Node) -> Result<ElementDecl, SchemaError> {
+        if let Some(reference) = node.attribute("ref") {
+            let name = self.qname(node, reference)?;
+            return Ok(ElementDecl {
+                ty: TypeRef::Element(name.clone()),
+                name,
+            });
+        }
+        let name = node
+            .attribute("name")
+            .ok_or_else(|| self.error(node, "element without name or ref"))?;
+        Ok(ElementDecl {
+            // elementFormDefault="qualified" in every NF-e schema.
+            name: clark(self.target, name),
+            ty: self.type_of(node)?,
+        })
+    }
+
+    fn type_of(&self, node: Node) -> Result<TypeRef, SchemaError> {
+        if let Some(ty) = node.attribute("type") {
+            return Ok(TypeRef::Named(self.qname(node, ty)?));
+        }
+        for child in node.children().filter(is_xsd) {
+            match child.tag_name().name() {
+                "complexType" => return Ok(TypeRef::Complex(Box::new(self.complex_type(child)?))),
+                "simpleType" => return Ok(TypeRef::Simple(Box::new(self.simple_type(child)?))),
+                _ => {}
+            }
+        }
+        Ok(TypeRef::Any)
+    }
+
+    fn complex_type(&self, node: Node) -> Result<ComplexType, SchemaError> {
+        let mut complex = ComplexType::default();
+        for child in node.children().filter(is_xsd) {
+            match child.tag_name().name() {
+                "sequence" | "choice" => complex.particle = Some(self.particle(child)?),
+                "attribute" => complex.attributes.push(AttributeDecl {
+                    name: child
+                        .attribute("name")
+                        .ok_or_else(|| self.error(child, "attribute without a name"))?
+                        .to_string(),
+                    required: child.attribute("use") == Some("required"),
+                    ty: self.type_of(child)?,
+                }),
+                "annotation" => {}
+                other => {
+                    return Err(
+                        self.error(child, format!("unsupported complexType content {}", other))
+                    )
+                }
+            }
+        }
+        Ok(complex)
+    }
+
+    fn particle(&self, node: Node) -> Result<Particle, SchemaError> {
+        let (min, max) = self.occurs(node)?;
+        let term = match node.tag_name().name() {
+            "element" => Term::Element(self.element(node)?),
+            "sequence" | "choice" => {
+                let items = node
+                    .children()
+                    .filter(is_xsd)
+                    .filter(|c| matches!(c.tag_name().name(), "element" | "sequence" | "choice"))
+                    .map(|c| self.particle(c))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if node.tag_name().name() == "sequence" {
+                    Term::Sequence(items)
+                } else {
+                    Term::Choice(items)
+                }
+            }
+            other => return Err(self.error(node, format!("unsupported particle {}", other))),
+        };
+        Ok(Particle { term, min, max })
+    }
+
+    fn simple_type(&self, node: Node) -> Result<SimpleType, SchemaError> {
+        let restriction = node
+            .children()
+            .filter(is_xsd)
+            .find(|c| c.has_tag_name((XSD_NAMESPACE, "restriction")))
+            .ok_or_else(|| self.error(node, "only restriction simple types are supported"))?;
+        let base = restriction
+            .attribute("base")
+            .ok_or_else(|| self.error(restriction, "restriction without base"))?;
+
+        let mut simple = SimpleType {
+            base: self.qname(restriction, base)?,
+            enumeration: Vec::new(),
+            patterns: Vec::new(),
+            length: None,
+            min_length: None,
+            max_length: None,
+            collapse: false,
+        };
+        for facet in restriction.children().filter(is_xsd) {
+            let value = facet.attribute("value").unwrap_or_default();
+            let size = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| self.error(facet, format!("invalid length {}", value)))
+            };
+            match facet.tag_name().name() {
+                "enumeration" => simple.enumeration.push(value.to_string()),
+                "pattern" => simple.patterns.push(
+                    Regex::new(&format!("^(?:{})$", value))
+                        .map_err(|e| self.error(facet, format!("invalid pattern: {}", e)))?,
+                ),
+                "length" => simple.length = Some(size()?),
+                "minLength" => simple.min_length = Some(size()?),
+                "maxLength" => simple.max_length = Some(size()?),
+                "whiteSpace" => simple.collapse = value == "collapse",
+                _ => {}
+            }
+        }
+        Ok(simple)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn loads_bundled_layout() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let schema = Schema::load(&dir, "nfe_v4.00.xsd").unwrap();
+        let nfe = clark(&schema.target_namespace, "NFe");
+        assert!(schema.elements.contains_key(&nfe));
+        assert!(schema
+            .complex_types
+            .contains_key(&clark(&schema.target_namespace, "TNFe")));
+        assert!(schema
+            .missing
+            .contains(&"tiposBasico_v4.00.xsd".to_string()));
+        assert!(schema.unresolved_types().contains(&"TDec_1302".to_string()));
+    }
+}
@@ -0,0 +1,491 @@
+//! Offline validation of NF-e documents against the bundled schemas.
+
+use crate::errors::{SchemaError, ValidationError};
+use crate::services::xml::schema::{
+    clark, local_name, ComplexType, Particle, Schema, SimpleType, Term, TypeRef,
+};
+use crate::services::xml::NFE_NAMESPACE;
+use chrono::{DateTime, NaiveDate};
+use roxmltree::{Document, Node};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Entry schema of the layout; it includes `leiauteNFe_v4.00.xsd`.
+pub const NFE_SCHEMA: &str = "nfe_v4.00.xsd";
+
+const XMLDSIG_NAMESPACE: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaViolation {
+    pub xpath: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaReport {
+    pub valid: bool,
+    pub violations: Vec<SchemaViolation>,
+    /// Types from schemas that are not bundled, accepted without lexical checks.
+    pub unchecked_types: Vec<String>,
+}
+
+/// Validates documents against `nfe_v4.00.xsd`.
+#[derive(Debug)]
+pub struct SchemaValidator {
+    schema: Schema,
+    unchecked_types: Vec<String>,
+}
+
+/// State of one validation run.
+struct Run<'s> {
+    schema: &'s Schema,
+    /// Accepts a note without `ds:Signature`, for notes that are not signed yet.
+    unsigned: bool,
+    violations: Vec<SchemaViolation>,
+}
+
+impl SchemaValidator {
+    pub fn load(dir: &Path) -> Result<Self, SchemaError> {
+        let schema = Schema::load(dir, NFE_SCHEMA)?;
+        let unchecked_types = schema.unresolved_types();
+        Ok(Self {
+            schema,
+            unchecked_types,
+        })
+    }
+
+    /// Validates a signed `NFe` document. An `nfeProc` is accepted too, but only its
+    /// `NFe` is checked because the procNFe schema is not bundled.
+    pub fn validate(&self, xml: &str) -> Result<SchemaReport, ValidationError> {
+        self.run(xml, false)
+    }
+
+    /// Same as [`validate`](Self::validate), without requiring the signature.
+    pub fn validate_unsigned(&self, xml: &str) -> Result<SchemaReport, ValidationError> {
+        self.run(xml, true)
+    }
+
+    fn run(&self, xml: &str, unsigned: bool) -> Result<SchemaReport, ValidationError> {
+        let document = Document::parse(xml)
+            .map_err(|e| ValidationError::new("xml", format!("malformed XML: {}", e)))?;
+        let mut root = document.root_element();
+        let mut xpath = String::new();
+        if root.has_tag_name((NFE_NAMESPACE, "nfeProc")) {
+            xpath.push_str("/nfeProc");
+            root = root
+                .children()
+                .find(|n| n.has_tag_name((NFE_NAMESPACE, "NFe")))
+                .ok_or_else(|| ValidationError::new("nfeProc", "missing NFe"))?;
+        }
+
+        let mut run = Run {
+            schema: &self.schema,
+            unsigned,
+            violations: Vec::new(),
+        };
+        let name = element_name(root);
+        xpath.push_str(&format!("/{}", root.tag_name().name()));
+        match self.schema.elements.get(&name) {
+            Some(decl) => run.element(root, &decl.ty, &xpath),
+            None => run.violation(
+                &xpath,
+                format!(
+                    "no schema declares {} in namespace {}",
+                    root.tag_name().name(),
+                    root.tag_name().namespace().unwrap_or("(none)")
+                ),
+            ),
+        }
+
+        Ok(SchemaReport {
+            valid: run.violations.is_empty(),
+            violations: run.violations,
+            unchecked_types: self.unchecked_types.clone(),
+        })
+    }
+}
+
+fn element_name(node: Node) -> String {
+    clark(
+        node.tag_name().namespace().unwrap_or_default(),
+        node.tag_name().name(),
+    )
+}
+
+/// XPath step of a child, indexed when it has siblings with the same name.
+fn step(node: Node) -> String {
+    let name = node.tag_name().name();
+    let same = |n: &Node| n.is_element() && n.tag_name() == node.tag_name();
+    let siblings = node
+        .parent()
+        .map_or(1, |p| p.children().filter(same).count());
+    if siblings > 1 {
+        let index = node.prev_siblings().filter(same).count();
+        format!("{}[{}]", name, index)
+    } else {
+        name.to_string()
+    }
+}
+
+impl Run<'_> {
+    fn violation(&mut self, xpath: &str, message: impl Into<String>) {
+        self.violations.push(SchemaViolation {
+            xpath: xpath.to_string(),
+            message: message.into(),
+        });
+    }
+
+    fn element(&mut self, node: Node, ty: &TypeRef, xpath: &str) {
+        match ty {
+            TypeRef::Any => {}
+            TypeRef::Complex(complex) => self.complex(node, complex, xpath),
+            TypeRef::Simple(simple) => {
+                self.simple_content(node, |run, value| run.simple(simple, value), xpath)
+            }
+            TypeRef::Element(name) => {
+                if let Some(decl) = self.schema.elements.get(name) {
+                    self.element(node, &decl.ty, xpath);
+                }
+            }
+            TypeRef::Named(name) => {
+                if let Some(complex) = self.schema.complex_types.get(name) {
+                    self.complex(node, complex, xpath);
+                } else {
+                    let name = name.clone();
+                    self.simple_content(node, |run, value| run.named(&name, value), xpath);
+                }
+            }
+        }
+    }
+
+    fn simple_content(
+        &mut self,
+        node: Node,
+        check: impl FnOnce(&Self, &str) -> Vec<String>,
+        xpath: &str,
+    ) {
+        if let Some(child) = node.children().find(Node::is_element) {
+            self.violation(
+                xpath,
+                format!(
+                    "element {} is not allowed in simple content",
+                    child.tag_name().name()
+                ),
+            );
+            return;
+        }
+        let value: String = node
+            .children()
+            .filter(Node::is_text)
+            .filter_map(|n| n.text())
+            .collect();
+        for message in check(self, &value) {
+            self.violation(xpath, message);
+        }
+    }
+
+    /// Messages for a value of a named type; types that are not loaded are accepted.
+    fn named(&self, name: &str, value: &str) -> Vec<String> {
+        if let Some(simple) = self.schema.simple_types.get(name) {
+            return self.simple(simple, value);
+        }
+        if self.schema.is_builtin(name) {
+            return builtin(local_name(name), value).into_iter().collect();
+        }
+        Vec::new()
+    }
+
+    fn simple(&self, simple: &SimpleType, value: &str) -> Vec<String> {
+        let collapsed;
+        let value = if simple.collapse {
+            collapsed = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            collapsed.as_str()
+        } else {
+            value
+        };
+
+        let mut messages = self.named(&simple.base, value);
+        let chars = value.chars().count();
+        if !simple.enumeration.is_empty() && !simple.enumeration.iter().any(|e| e == value) {
+            messages.push(format!(
+                "value '{}' is not one of {}",
+                value,
+                simple.enumeration.join(", ")
+            ));
+        }
+        if !simple.patterns.is_empty() && !simple.patterns.iter().any(|p| p.is_match(value)) {
+            let pattern = simple.patterns[0].as_str();
+            messages.push(format!(
+                "value '{}' does not match the pattern {}",
+                value,
+                &pattern[4..pattern.len() - 2]
+            ));
+        }
+        if let Some(length) = simple.length.filter(|l| *l != chars) {
+            messages.push(format!("length must be {}, found {}", length, chars));
+        }
+        if let Some(min) = simple.min_length.filter(|m| chars < *m) {
+            messages.push(format!("length must be at least {}, found {}", min, chars));
+        }
+        if let Some(max) = simple.max_length.filter(|m| chars > *m) {
+            messages.push(format!("length must be at most {}, found {}", max, chars));
+        }
+        messages
+    }
+
+    fn complex(&mut self, node: Node, complex: &ComplexType, xpath: &str) {
+        for attribute in &complex.attributes {
+            match node.attribute(attribute.name.as_str()) {
+                Some(value) => {
+                    let messages = match &attribute.ty {
+                        TypeRef::Simple(simple) => self.simple(simple, value),
+                        TypeRef::Named(name) => self.named(name, value),
+                        _ => Vec::new(),
+                    };
+                    for message in messages {
+                        self.violation(&format!("{}/@{}", xpath, attribute.name), message);
+                    }
+                }
+                None if attribute.required => self.violation(
+                    xpath,
+                    format!("missing required attribute {}", attribute.name),
+                ),
+                None => {}
+            }
+        }
+        let declared: HashSet<&str> = complex.attributes.iter().map(|a| a.name.as_str()).collect();
+        for attribute in node.attributes() {
+            if attribute.namespace().is_none() && !declared.contains(attribute.name()) {
+                self.violation(
+                    xpath,
+                    format!("attribute {} is not allowed", attribute.name()),
+                );
+            }
+        }
+
+        if node
+            .children()
+            .any(|n| n.is_text() && !n.text().unwrap_or_default().trim().is_empty())
+        {
+            self.violation(xpath, "text is not allowed in element-only content");
+        }
+        let children: Vec<Node> = node.children().filter(Node::is_element).collect();
+        let mut position = 0;
+        if let Some(particle) = &complex.particle {
+            self.particle(particle, &children, &mut position, xpath);
+        }
+        for child in &children[position..] {
+            self.violation(
+                &format!("{}/{}", xpath, step(*child)),
+                format!("unexpected element {}", child.tag_name().name()),
+            );
+        }
+    }
+
+    fn min_occurs(&self, particle: &Particle) -> u32 {
+        match &particle.term {
+            Term::Element(decl)
+                if self.unsigned && decl.name == clark(XMLDSIG_NAMESPACE, "Signature") =>
+            {
+                0
+            }
+            _ => particle.min,
+        }
+    }
+
+    fn nullable(&self, particle: &Particle) -> bool {
+        self.min_occurs(particle) == 0
+            || match &particle.term {
+                Term::Element(_) => false,
+                Term::Sequence(items) => items.iter().all(|p| self.nullable(p)),
+                Term::Choice(items) => items.iter().any(|p| self.nullable(p)),
+            }
+    }
+
+    /// Element names that can start the term.
+    fn first<'p>(&self, term: &'p Term, names: &mut Vec<&'p str>) {
+        match term {
+            Term::Element(decl) => names.push(&decl.name),
+            Term::Sequence(items) => {
+                for item in items {
+                    self.first(&item.term, names);
+                    if !self.nullable(item) {
+                        break;
+                    }
+                }
+            }
+            Term::Choice(items) => {
+                for item in items {
+                    self.first(&item.term, names);
+                }
+            }
+        }
+    }
+
+    fn starts(&self, term: &Term, node: Option<&Node>) -> bool {
+        let Some(node) = node else {
+            return false;
+        };
+        let mut names = Vec::new();
+        self.first(term, &mut names);
+        let name = element_name(*node);
+        names.contains(&name.as_str())
+    }
+
+    fn expected(&self, term: &Term) -> String {
+        let mut names = Vec::new();
+        self.first(term, &mut names);
+        let names: Vec<&str> = names.into_iter().map(local_name).collect();
+        match names.as_slice() {
+            [single] => single.to_string(),
+            many => format!("one of {}", many.join(", ")),
+        }
+    }
+
+    /// Greedy matching; the layouts satisfy Unique Particle Attribution, so the first
+    /// element names decide every branch.
+    fn particle(
+        &mut self,
+        particle: &Particle,
+        children: &[Node],
+        position: &mut usize,
+        xpath: &str,
+    ) {
+        let min = self.min_occurs(particle);
+        let mut count = 0;
+        while !matches!(particle.max, Some(max) if count >= max) {
+            let before = *position;
+            if self.starts(&particle.term, children.get(*position)) {
+                self.term(&particle.term, children, position, xpath);
+            } else if count < min && !self.nullable(particle) {
+                self.term(&particle.term, children, position, xpath);
+                if *position == before {
+                    break;
+                }
+            } else {
+                break;
+            }
+            count += 1;
+            if *position == before {
+                break;
+            }
+        }
+    }
+
+    fn term(&mut self, term: &Term, children: &[Node], position: &mut usize, xpath: &str) {
+        match term {
+            Term::Element(decl) => match children.get(*position) {
+                Some(child) if element_name(*child) == decl.name => {
+                    *position += 1;
+                    let child_xpath = format!("{}/{}", xpath, step(*child));
+                    self.element(*child, &decl.ty, &child_xpath);
+                }
+                found => self.missing(term, found, xpath),
+            },
+            Term::Sequence(items) => {
+                for item in items {
+                    self.particle(item, children, position, xpath);
+                }
+            }
+            Term::Choice(items) => {
+                let child = children.get(*position);
+                match items.iter().find(|item| self.starts(&item.term, child)) {
+                    Some(item) => self.particle(item, children, position, xpath),
+                    None if items.iter().any(|item| self.nullable(item)) => {}
+                    None => self.missing(term, child, xpath),
+                }
+            }
+        }
+    }
+
+    fn missing(&mut self, term: &Term, found: Option<&Node>, xpath: &str) {
+        let message = match found {
+            Some(node) => format!(
+                "expected {}, found {}",
+                self.expected(term),
+                node.tag_name().name()
+            ),
+            None => format!("missing {}", self.expected(term)),
+        };
+        self.violation(xpath, message);
+    }
+}
+
+/// Lexical checks for the built-in types the layouts use directly.
+fn builtin(name: &str, value: &str) -> Option<String> {
+    let valid = match name {
+        "decimal" => {
+            let digits = value.trim_start_matches(['+', '-']);
+            !digits.is_empty()
+                && digits != "."
+                && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && digits.matches('.').count() <= 1
+        }
+        "date" => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+        "dateTime" => DateTime::parse_from_rfc3339(value).is_ok(),
+        "ID" => value
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_'),
+        "base64Binary" => value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=') || c.is_whitespace()
+        }),
+        _ => true,
+    };
+    (!valid).then(|| format!("value '{}' is not a valid xs:{}", value, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::xml::nfe_serializer;
+    use std::path::PathBuf;
+
+    fn validator() -> SchemaValidator {
+        SchemaValidator::load(&PathBuf::from(env!("CARGO_MANIFEST_DIR"))).unwrap()
+    }
+
+    #[test]
+    fn accepts_serialized_note() {
+        let xml = nfe_serializer::serialize(&NFeDocument::sample()).unwrap();
+        let report = validator().validate_unsigned(&xml).unwrap();
+        assert_eq!(report.violations, vec![]);
+        assert!(report.valid);
+    }
+
+    #[test]
+    fn requires_signature_unless_unsigned() {
+        let xml = nfe_serializer::serialize(&NFeDocument::sample()).unwrap();
+        let report = validator().validate(&xml).unwrap();
+        assert_eq!(
+            report.violations,
+            vec![SchemaViolation {
+                xpath: "/NFe".to_string(),
+                message: "missing Signature".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn reports_every_violation_with_xpath() {
+        let xml = nfe_serializer::serialize(&NFeDocument::sample())
+            .unwrap()
+            .replace("<tpNF>1</tpNF>", "<tpNF>7</tpNF>")
+            .replace("<natOp>Venda de mercadoria</natOp>", "")
+            .replace("<indTot>1</indTot>", "<indTot>1</indTot><extra>1</extra>");
+        let report = validator().validate_unsigned(&xml).unwrap();
+        let xpaths: Vec<&str> = report.violations.iter().map(|v| v.xpath.as_str()).collect();
+        assert_eq!(
+            xpaths,
+            vec![
+                "/NFe/infNFe/ide",
+                "/NFe/infNFe/ide/tpNF",
+                "/NFe/infNFe/det/prod/extra",
+            ]
+        );
+        assert_eq!(report.violations[0].message, "expected natOp, found mod");
+        assert!(!report.valid);
+    }
+}
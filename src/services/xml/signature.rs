@@ -0,0 +1,543 @@
+//! Enveloped XMLDSig signatures (RSA-SHA1 over inclusive C14N) as required by the
+//! NF-e manual, with A1 certificates stored as PKCS#12.
+
+use crate::errors::SignatureError;
+use crate::services::xml::c14n::canonicalize;
+use crate::services::xml::nfe_parser;
+use crate::services::xml::writer::XmlWriter;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use openssl::asn1::Asn1Time;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::{Signer as RsaSigner, Verifier};
+use openssl::x509::X509;
+use roxmltree::{Document, Node};
+use serde::Serialize;
+
+pub const XMLDSIG_NAMESPACE: &str = "http://www.w3.org/2000/09/xmldsig#";
+const C14N: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315";
+const RSA_SHA1: &str = "http://www.w3.org/2000/09/xmldsig#rsa-sha1";
+const SHA1: &str = "http://www.w3.org/2000/09/xmldsig#sha1";
+const ENVELOPED: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+/// Signs with the private key and certificate of an A1 certificate.
+pub struct Signer {
+    key: PKey<Private>,
+    certificate: X509,
+}
+
+/// A signature that verified, with the certificate that produced it.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifiedSignature {
+    /// `Id` of the signed element.
+    pub reference: String,
+    pub subject: String,
+}
+
+fn malformed(e: roxmltree::Error) -> SignatureError {
+    SignatureError::Invalid(format!("malformed XML: {}", e))
+}
+
+fn is_signature(node: Node) -> bool {
+    node.has_tag_name((XMLDSIG_NAMESPACE, "Signature"))
+}
+
+/// The only element whose `Id` is `id`. A repeated `Id` is refused: the copy that gets
+/// verified could be another than the one that is read.
+fn find_by_id<'a, 'input>(
+    document: &'a Document<'input>,
+    id: &str,
+) -> Result<Node<'a, 'input>, SignatureError> {
+    let mut found = document
+        .descendants()
+        .filter(|n| n.is_element() && n.attribute("Id") == Some(id));
+    match (found.next(), found.count()) {
+        (Some(target), 0) => Ok(target),
+        (Some(_), more) => Err(SignatureError::Invalid(format!(
+            "Id {} is repeated on {} elements",
+            id,
+            more + 1
+        ))),
+        (None, _) => Err(SignatureError::Invalid(format!(
+            "no element with Id {}",
+            id
+        ))),
+    }
+}
+
+fn digest(node: Node) -> Result<String, SignatureError> {
+    let canonical = canonicalize(node, &is_signature);
+    Ok(STANDARD.encode(hash(MessageDigest::sha1(), canonical.as_bytes())?))
+}
+
+/// Common name of a certificate subject, e.g. `EMPRESA LTDA:12345678000195`.
+fn common_name(certificate: &X509) -> String {
+    certificate
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .map(|entry| String::from_utf8_lossy(entry.data().as_slice()).into_owned())
+        .unwrap_or_default()
+}
+
+/// DER encoding of OID 2.16.76.1.3.3, the otherName of the subjectAltName in which
+/// ICP-Brasil e-CNPJ certificates carry the CNPJ of their holder.
+const CNPJ_OID: &[u8] = &[0x06, 0x05, 0x60, 0x4C, 0x01, 0x03, 0x03];
+
+/// Splits a DER value into its tag, its content and the bytes that follow it.
+fn der_value(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let size = (first & 0x7F) as usize;
+        if size == 0 || size > 4 || rest.len() < size {
+            return None;
+        }
+        let len = rest[..size]
+            .iter()
+            .fold(0usize, |len, &b| (len << 8) | b as usize);
+        (len, &rest[size..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// CNPJ of the holder of an e-CNPJ certificate, read from its otherName 2.16.76.1.3.3:
+/// `[0] EXPLICIT` wrapping a string whose first 14 positions are the CNPJ.
+fn certificate_cnpj(certificate: &X509) -> Option<String> {
+    let der = certificate.to_der().ok()?;
+    let start = der.windows(CNPJ_OID.len()).position(|w| w == CNPJ_OID)? + CNPJ_OID.len();
+    let (tag, explicit, _) = der_value(&der[start..])?;
+    if tag != 0xA0 {
+        return None;
+    }
+    let (_, value, _) = der_value(explicit)?;
+    let cnpj = value.get(..14)?;
+    cnpj.iter()
+        .all(u8::is_ascii_digit)
+        .then(|| String::from_utf8_lossy(cnpj).into_owned())
+}
+
+/// CNPJ of the author of the signed element: `emit/CNPJ` of an NF-e, the `CNPJ` of an
+/// event or inutilização request. `None` for elements signed by SEFAZ (protocols,
+/// responses) or by an emitter identified by CPF.
+fn author_cnpj(target: Node) -> Option<String> {
+    let cnpj = |node: Node| {
+        node.children()
+            .find(|n| n.has_tag_name("CNPJ"))
+            .and_then(|n| n.text())
+            .map(|text| text.trim().to_string())
+    };
+    let parent = target.parent_element()?;
+    match (target.tag_name().name(), parent.tag_name().name()) {
+        ("infNFe", _) => target
+            .children()
+            .find(|n| n.has_tag_name("emit"))
+            .and_then(cnpj),
+        ("infEvento", "evento") | ("infInut", "inutNFe") => cnpj(target),
+        _ => None,
+    }
+}
+
+fn signed_info(reference: &str, digest_value: &str) -> String {
+    let mut w = XmlWriter::new();
+    w.start("SignedInfo");
+    w.start_with("CanonicalizationMethod", &[("Algorithm", C14N)]);
+    w.end("CanonicalizationMethod");
+    w.start_with("SignatureMethod", &[("Algorithm", RSA_SHA1)]);
+    w.end("SignatureMethod");
+    w.start_with("Reference", &[("URI", &format!("#{}", reference))]);
+    w.start("Transforms");
+    w.start_with("Transform", &[("Algorithm", ENVELOPED)]);
+    w.end("Transform");
+    w.start_with("Transform", &[("Algorithm", C14N)]);
+    w.end("Transform");
+    w.end("Transforms");
+    w.start_with("DigestMethod", &[("Algorithm", SHA1)]);
+    w.end("DigestMethod");
+    w.text("DigestValue", digest_value);
+    w.end("Reference");
+    w.end("SignedInfo");
+    w.into_string()
+}
+
+impl Signer {
+    pub fn from_pkcs12(der: &[u8], password: &str) -> Result<Self, SignatureError> {
+        let parsed = Pkcs12::from_der(der)?.parse2(password).map_err(|e| {
+            SignatureError::Certificate(format!("cannot open PKCS#12 (wrong password?): {}", e))
+        })?;
+        match (parsed.pkey, parsed.cert) {
+            (Some(key), Some(certificate)) => Ok(Self { key, certificate }),
+            _ => Err(SignatureError::Certificate(
+                "PKCS#12 must contain a private key and its certificate".to_string(),
+            )),
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        common_name(&self.certificate)
+    }
+
+    /// Signs the element whose `Id` is `reference` and appends the `Signature` as the
+    /// last child of its parent, as TNFe, TEvento and TInutNFe expect.
+    pub fn sign(&self, xml: &str, reference: &str) -> Result<String, SignatureError> {
+        let now = Asn1Time::days_from_now(0)?;
+        if self.certificate.not_after() < now || self.certificate.not_before() > now {
+            return Err(SignatureError::Certificate(format!(
+                "certificate is not valid now (valid from {} to {})",
+                self.certificate.not_before(),
+                self.certificate.not_after()
+            )));
+        }
+
+        let document = Document::parse(xml).map_err(malformed)?;
+        let target = find_by_id(&document, reference)?;
+        let parent = target
+            .parent_element()
+            .ok_or_else(|| SignatureError::Invalid("cannot sign the root element".to_string()))?;
+
+        let signed_info = signed_info(reference, &digest(target)?);
+        // SignedInfo is canonicalized in the context of Signature, whose only namespace
+        // in scope is the xmldsig default one.
+        let envelope = format!(
+            "<Signature xmlns=\"{}\">{}</Signature>",
+            XMLDSIG_NAMESPACE, signed_info
+        );
+        let envelope = Document::parse(&envelope).map_err(malformed)?;
+        let canonical = canonicalize(child(envelope.root_element(), "SignedInfo")?, &|_| false);
+
+        let mut signer = RsaSigner::new(MessageDigest::sha1(), &self.key)?;
+        signer.update(canonical.as_bytes())?;
+        let signature_value = STANDARD.encode(signer.sign_to_vec()?);
+
+        let mut w = XmlWriter::new();
+        w.start_with("Signature", &[("xmlns", XMLDSIG_NAMESPACE)]);
+        let mut signature = w.into_string();
+        signature.push_str(&signed_info);
+        let mut w = XmlWriter::new();
+        w.text("SignatureValue", &signature_value);
+        w.start("KeyInfo");
+        w.start("X509Data");
+        w.text(
+            "X509Certificate",
+            &STANDARD.encode(self.certificate.to_der()?),
+        );
+        w.end("X509Data");
+        w.end("KeyInfo");
+        w.end("Signature");
+        signature.push_str(&w.into_string());
+
+        let end = parent.range().end;
+        let closing = xml[..end]
+            .rfind("</")
+            .ok_or_else(|| SignatureError::Invalid("parent element has no end tag".to_string()))?;
+        Ok(format!(
+            "{}{}{}",
+            &xml[..closing],
+            signature,
+            &xml[closing..]
+        ))
+    }
+}
+
+/// Verifies every signature of the document. Returns the first one, normally the
+/// emitter's, or `None` when the document is not signed.
+///
+/// Each signature must be enveloped, a sibling of the element it references. In an
+/// `NFe` or `nfeProc`, one of them must cover the `infNFe` the parser reads, so a signed
+/// copy cannot vouch for other content placed where the parser looks.
+///
+/// Besides the digest and the signature value, the CNPJ of the certificate must be the
+/// one of the author of the signed element. The certificate chain is not validated: a
+/// self-issued certificate carrying the right CNPJ is accepted, so a verified signature
+/// proves integrity, not that ICP-Brasil issued the certificate.
+pub fn verify(xml: &str) -> Result<Option<VerifiedSignature>, SignatureError> {
+    let document = Document::parse(xml).map_err(malformed)?;
+    let mut verified = Vec::new();
+    for signature in document.descendants().filter(|n| is_signature(*n)) {
+        verified.push(verify_signature(&document, signature)?);
+    }
+    if let (Some(inf_nfe), false) = (
+        nfe_parser::inf_nfe(document.root_element()),
+        verified.is_empty(),
+    ) {
+        let covered = verified
+            .iter()
+            .position(|(target, _)| *target == inf_nfe)
+            .ok_or_else(|| {
+                SignatureError::Invalid("no signature covers the infNFe of the note".to_string())
+            })?;
+        verified.swap(0, covered);
+    }
+    Ok(verified.into_iter().next().map(|(_, signature)| signature))
+}
+
+fn child<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &str,
+) -> Result<Node<'a, 'input>, SignatureError> {
+    node.children()
+        .find(|n| n.has_tag_name((XMLDSIG_NAMESPACE, name)))
+        .ok_or_else(|| SignatureError::Invalid(format!("missing {}", name)))
+}
+
+fn base64_text(node: Node) -> Result<Vec<u8>, SignatureError> {
+    let text: String = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    STANDARD.decode(text).map_err(|e| {
+        SignatureError::Invalid(format!("{} is not base64: {}", node.tag_name().name(), e))
+    })
+}
+
+/// Verifies one signature and returns it with the element it covers.
+fn verify_signature<'a, 'input>(
+    document: &'a Document<'input>,
+    signature: Node<'a, 'input>,
+) -> Result<(Node<'a, 'input>, VerifiedSignature), SignatureError> {
+    let signed_info = child(signature, "SignedInfo")?;
+    let reference = child(signed_info, "Reference")?;
+    let uri = reference.attribute("URI").unwrap_or_default();
+    let id = uri.strip_prefix('#').unwrap_or(uri);
+    let target = find_by_id(document, id)?;
+    if target.parent_element() != signature.parent_element() {
+        return Err(SignatureError::Invalid(format!(
+            "the signature of {} is not enveloped in the parent of the signed element",
+            id
+        )));
+    }
+
+    let declared = child(reference, "DigestValue")?
+        .text()
+        .unwrap_or_default()
+        .trim();
+    if digest(target)? != declared {
+        return Err(SignatureError::Invalid(format!(
+            "digest of {} does not match; the document was changed after signing",
+            id
+        )));
+    }
+
+    let certificate = X509::from_der(&base64_text(child(
+        child(child(signature, "KeyInfo")?, "X509Data")?,
+        "X509Certificate",
+    )?)?)
+    .map_err(|e| SignatureError::Invalid(format!("invalid X509Certificate: {}", e)))?;
+    let key = certificate.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha1(), &key)?;
+    verifier.update(canonicalize(signed_info, &|_| false).as_bytes())?;
+    if !verifier.verify(&base64_text(child(signature, "SignatureValue")?)?)? {
+        return Err(SignatureError::Invalid(format!(
+            "SignatureValue of {} does not match the certificate",
+            id
+        )));
+    }
+
+    if let Some(author) = author_cnpj(target) {
+        match certificate_cnpj(&certificate) {
+            Some(cnpj) if cnpj == author => {}
+            Some(cnpj) => {
+                return Err(SignatureError::Invalid(format!(
+                    "{} is signed by CNPJ {} but its author is {}",
+                    id, cnpj, author
+                )))
+            }
+            None => {
+                return Err(SignatureError::Invalid(format!(
+                    "the certificate that signed {} does not carry a CNPJ (OID 2.16.76.1.3.3)",
+                    id
+                )))
+            }
+        }
+    }
+
+    Ok((
+        target,
+        VerifiedSignature {
+            reference: id.to_string(),
+            subject: common_name(&certificate),
+        },
+    ))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::models::nfe_document::NFeDocument;
+    use crate::services::xml::schema_validator::SchemaValidator;
+    use crate::services::xml::{nfe_parser, nfe_serializer};
+    use openssl::asn1::Asn1Object;
+    use openssl::bn::BigNum;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::SubjectAlternativeName;
+    use openssl::x509::X509NameBuilder;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+
+    const PASSWORD: &str = "senha";
+
+    /// Self-signed A1 certificate, as PKCS#12, generated once per test run.
+    pub fn test_pkcs12() -> &'static [u8] {
+        static PKCS12: OnceLock<Vec<u8>> = OnceLock::new();
+        PKCS12.get_or_init(|| certificate_pkcs12("12345678000195"))
+    }
+
+    /// Self-signed e-CNPJ certificate of `cnpj`, as PKCS#12.
+    fn certificate_pkcs12(cnpj: &str) -> Vec<u8> {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", &format!("LOJA EXEMPLO LTDA:{}", cnpj))
+            .unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        let mut holder = vec![0x04, cnpj.len() as u8];
+        holder.extend_from_slice(cnpj.as_bytes());
+        let san = SubjectAlternativeName::new()
+            .other_name2(Asn1Object::from_str("2.16.76.1.3.3").unwrap(), &holder)
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let certificate = builder.build();
+        Pkcs12::builder()
+            .name("a1")
+            .pkey(&key)
+            .cert(&certificate)
+            .build2(PASSWORD)
+            .unwrap()
+            .to_der()
+            .unwrap()
+    }
+
+    pub fn test_signer() -> Signer {
+        Signer::from_pkcs12(test_pkcs12(), PASSWORD).unwrap()
+    }
+
+    fn signed_sample() -> (String, String) {
+        let document = NFeDocument::sample();
+        let id = format!("NFe{}", document.access_key().unwrap());
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        (test_signer().sign(&xml, &id).unwrap(), id)
+    }
+
+    #[test]
+    fn signs_and_verifies() {
+        let (signed, id) = signed_sample();
+        assert!(signed.ends_with("</X509Certificate></X509Data></KeyInfo></Signature></NFe>"));
+        assert!(signed.contains(&format!("<Reference URI=\"#{}\">", id)));
+
+        let verified = verify(&signed).unwrap().unwrap();
+        assert_eq!(verified.reference, id);
+        assert_eq!(verified.subject, "LOJA EXEMPLO LTDA:12345678000195");
+
+        let validator = SchemaValidator::load(&PathBuf::from(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert!(validator.validate(&signed).unwrap().valid);
+        assert!(nfe_parser::parse(&signed).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_documents() {
+        let (signed, _) = signed_sample();
+        let tampered = signed.replace("<vNF>100.00</vNF>", "<vNF>10.00</vNF>");
+        assert!(matches!(verify(&tampered), Err(SignatureError::Invalid(_))));
+
+        let value_start = signed.find("<SignatureValue>").unwrap() + "<SignatureValue>".len();
+        let mut forged = signed.clone();
+        let replacement = if &signed[value_start..value_start + 1] == "A" {
+            "B"
+        } else {
+            "A"
+        };
+        forged.replace_range(value_start..value_start + 1, replacement);
+        assert!(matches!(verify(&forged), Err(SignatureError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_wrapped_signatures() {
+        let (signed, id) = signed_sample();
+        let start = signed.find("<infNFe").unwrap();
+        let end = signed.find("</infNFe>").unwrap() + "</infNFe>".len();
+        let original = &signed[start..end];
+        let tampered = original.replace("<vNF>100.00</vNF>", "<vNF>10.00</vNF>");
+        assert_ne!(tampered, original);
+
+        // A tampered copy with the same Id ahead of the signed one.
+        let repeated = signed.replace(original, &format!("{}{}", tampered, original));
+        match verify(&repeated) {
+            Err(SignatureError::Invalid(message)) => {
+                assert!(message.contains("repeated"), "{}", message)
+            }
+            other => panic!("expected a repeated Id, got {:?}", other),
+        }
+
+        // A tampered infNFe where the parser reads, with the signed one moved behind it.
+        let other_id = format!("{}0", &id[..id.len() - 1]);
+        let wrapped = signed.replace(
+            original,
+            &format!("{}{}", tampered.replace(&id, &other_id), original),
+        );
+        match verify(&wrapped) {
+            Err(SignatureError::Invalid(message)) => {
+                assert!(message.contains("infNFe"), "{}", message)
+            }
+            other => panic!("expected an uncovered infNFe, got {:?}", other),
+        }
+
+        // The signed infNFe moved out of NFe, next to a signature placed beside it.
+        let outside = format!(
+            "<nfeProc xmlns=\"http://www.portalfiscal.inf.br/nfe\" versao=\"4.00\">{}<Wrapper>{}</Wrapper></nfeProc>",
+            signed.replace(original, &tampered),
+            original
+        );
+        assert!(matches!(verify(&outside), Err(SignatureError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_certificates_of_another_cnpj() {
+        let document = NFeDocument::sample();
+        let id = format!("NFe{}", document.access_key().unwrap());
+        let xml = nfe_serializer::serialize(&document).unwrap();
+        let signer = Signer::from_pkcs12(&certificate_pkcs12("98765432000198"), PASSWORD).unwrap();
+        let signed = signer.sign(&xml, &id).unwrap();
+        match verify(&signed) {
+            Err(SignatureError::Invalid(message)) => {
+                assert!(message.contains("98765432000198"), "{}", message)
+            }
+            other => panic!("expected a CNPJ mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsigned_documents_have_no_signature() {
+        let xml = nfe_serializer::serialize(&NFeDocument::sample()).unwrap();
+        assert!(verify(&xml).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        assert!(matches!(
+            Signer::from_pkcs12(test_pkcs12(), "errada"),
+            Err(SignatureError::Certificate(_))
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// Minimal XML writer for the fiscal layouts: no pretty-printing, since whitespace between
+/// elements is not allowed, and text is trimmed because the layouts reject leading or
+/// trailing spaces.
+#[derive(Debug, Default)]
+pub struct XmlWriter {
+    buffer: String,
+}
+
+/// Escapes the characters that cannot appear literally in text or attribute values.
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Formats a decimal with a fixed number of decimal places (ties to even, as in the taxes).
+pub fn format_decimal(value: Decimal, scale: u32) -> String {
+    let mut rounded = value.round_dp_with_strategy(scale, RoundingStrategy::MidpointNearestEven);
+    rounded.rescale(scale);
+    rounded.to_string()
+}
+
+impl XmlWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&mut self, tag: &str) {
+        self.start_with(tag, &[]);
+    }
+
+    pub fn start_with(&mut self, tag: &str, attributes: &[(&str, &str)]) {
+        self.buffer.push('<');
+        self.buffer.push_str(tag);
+        for (name, value) in attributes {
+            self.buffer
+                .push_str(&format!(" {}=\"{}\"", name, escape(value.trim())));
+        }
+        self.buffer.push('>');
+    }
+
+    pub fn end(&mut self, tag: &str) {
+        self.buffer.push_str(&format!("</{}>", tag));
+    }
+
+    pub fn text(&mut self, tag: &str, value: &str) {
+        self.buffer
+            .push_str(&format!("<{0}>{1}</{0}>", tag, escape(value.trim())));
+    }
+
+    pub fn text_with(&mut self, tag: &str, attributes: &[(&str, &str)], value: &str) {
+        self.start_with(tag, attributes);
+        self.buffer.push_str(&escape(value.trim()));
+        self.end(tag);
+    }
+
+    pub fn opt_text(&mut self, tag: &str, value: Option<&str>) {
+        if let Some(value) = value {
+            self.text(tag, value);
+        }
+    }
+
+    pub fn decimal(&mut self, tag: &str, value: Decimal, scale: u32) {
+        self.text(tag, &format_decimal(value, scale));
+    }
+
+    pub fn opt_decimal(&mut self, tag: &str, value: Option<Decimal>, scale: u32) {
+        if let Some(value) = value {
+            self.decimal(tag, value, scale);
+        }
+    }
+
+    pub fn into_string(self) -> String {
+        self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn writes_escaped_and_trimmed_elements() {
+        let mut writer = XmlWriter::new();
+        writer.start_with("a", &[("Id", "x&y")]);
+        writer.text("b", "  Tom & Jerry <ltda> ");
+        writer.opt_text("c", None);
+        writer.decimal("d", dec!(1.005), 2);
+        writer.decimal("e", dec!(3), 4);
+        writer.end("a");
+        assert_eq!(
+            writer.into_string(),
+            "<a Id=\"x&amp;y\"><b>Tom &amp; Jerry &lt;ltda&gt;</b><d>1.00</d><e>3.0000</e></a>"
+        );
+    }
+}
@@ -21,6 +21,7 @@ export interface NFeIdentification {
     mod: string;
     serie: string;
     cNF: string;
+    // Derived by the backend from the access key; ignored when sent.
     cDV: string;
     tpAmb: string;
     finNFe: string;
@@ -13,6 +13,7 @@ export interface NFeIdentification {
     cMunFG: string;
     tpImp: string;
     tpEmis: string;
+    // Derived by the backend from the access key; ignored when sent.
     cDV: string;
     tpAmb: string;
     finNFe: string;
@@ -22,6 +23,7 @@ export interface NFeIdentification {
     procEmi: string;
     verProc: string;
     dhCont?: string;
+    xJust?: string;
     created_at: string;
     updated_at: string;
-} 
\ No newline at end of file
+}